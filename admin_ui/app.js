@@ -0,0 +1,132 @@
+// Polls /admin/rooms for occupancy and throughput, and follows /admin/events for the event log
+"use strict";
+
+const POLL_INTERVAL_MS = 2000;
+// The routes are served under the --base-path the dashboard itself is served under
+const BASE_PATH =
+  new URL(document.currentScript.src).pathname.replace(/\/admin\/ui\/app\.js$/, "");
+const MAX_EVENTS = 200;
+
+let token = sessionStorage.getItem("admin-token") || "";
+let previousRooms = new Map(); // Room ID -> { routed_messages, at }
+let pollTimer = null;
+let eventSocket = null;
+
+const roomsBody = document.getElementById("rooms");
+const eventList = document.getElementById("events");
+const statusLabel = document.getElementById("status");
+
+function adminFetch(path, method) {
+  const headers = { Authorization: "Bearer " + token };
+  return fetch(BASE_PATH + path, { method: method || "GET", headers: headers })
+    .then((response) => {
+      if (!response.ok) {
+        throw new Error(response.status + " " + response.statusText);
+      }
+      return response.json();
+    });
+}
+
+function button(label, onClick) {
+  const result = document.createElement("button");
+  result.textContent = label;
+  result.addEventListener("click", onClick);
+  return result;
+}
+
+function renderRooms(rooms) {
+  const now = performance.now();
+  const nextRooms = new Map();
+  roomsBody.replaceChildren();
+
+  for (const room of rooms) {
+    const previous = previousRooms.get(room.room_id);
+    const rate = previous
+      ? ((room.routed_messages - previous.routed_messages) * 1000) / (now - previous.at)
+      : 0;
+    nextRooms.set(room.room_id, { routed_messages: room.routed_messages, at: now });
+
+    const row = roomsBody.insertRow();
+    row.className = room.is_paused ? "paused" : "";
+    row.insertCell().textContent = room.room_id + (room.is_paused ? " (paused)" : "");
+    row.insertCell().textContent = room.client_party_ids.length;
+    row.insertCell().textContent = rate.toFixed(1);
+
+    const partyCell = row.insertCell();
+    for (const partyId of room.client_party_ids) {
+      const kickButton = button(partyId + " ✕", () =>
+        runCommand("/admin/rooms/" + room.room_id + "/clients/" + partyId + "/kick"));
+      kickButton.className = "party";
+      kickButton.title = "Kick party " + partyId;
+      partyCell.appendChild(kickButton);
+    }
+
+    const commandCell = row.insertCell();
+    commandCell.appendChild(button(room.is_paused ? "Resume" : "Pause", () =>
+      runCommand("/admin/rooms/" + room.room_id + (room.is_paused ? "/resume" : "/pause"))));
+    commandCell.appendChild(button("Drain", () => {
+      if (confirm("Disconnect every client of room " + room.room_id + "?")) {
+        runCommand("/admin/rooms/" + room.room_id + "/drain");
+      }
+    }));
+  }
+
+  previousRooms = nextRooms;
+}
+
+function pollRooms() {
+  adminFetch("/admin/rooms")
+    .then(renderRooms)
+    .catch((error) => { statusLabel.textContent = "Rooms: " + error.message; });
+}
+
+function runCommand(path) {
+  adminFetch(path, "POST")
+    .then(pollRooms)
+    .catch((error) => alert(path + ": " + error.message));
+}
+
+function appendEvent(record) {
+  const item = document.createElement("li");
+  const { timestamp_millis: timestamp, event, ...fields } = record;
+  item.textContent = new Date(timestamp).toLocaleTimeString() + " " + event + " "
+    + JSON.stringify(fields);
+  eventList.prepend(item);
+
+  while (eventList.childElementCount > MAX_EVENTS) {
+    eventList.lastElementChild.remove();
+  }
+}
+
+function followEvents() {
+  const scheme = location.protocol === "https:" ? "wss://" : "ws://";
+  eventSocket = new WebSocket(
+    scheme + location.host + BASE_PATH + "/admin/events?token=" + encodeURIComponent(token));
+  eventSocket.onopen = () => { statusLabel.textContent = "Connected"; };
+  eventSocket.onclose = () => { statusLabel.textContent = "Disconnected"; };
+  eventSocket.onmessage = (message) => appendEvent(JSON.parse(message.data));
+}
+
+function connect() {
+  if (eventSocket) {
+    eventSocket.close();
+  }
+  clearInterval(pollTimer);
+  previousRooms = new Map();
+
+  followEvents();
+  pollRooms();
+  pollTimer = setInterval(pollRooms, POLL_INTERVAL_MS);
+}
+
+document.getElementById("token").value = token;
+document.getElementById("token-form").addEventListener("submit", (submit) => {
+  submit.preventDefault();
+  token = document.getElementById("token").value;
+  sessionStorage.setItem("admin-token", token);
+  connect();
+});
+
+if (token) {
+  connect();
+}
@@ -0,0 +1,112 @@
+//! Serialization hot paths of the wire protocol, run with `cargo bench --bench proto`.
+
+#[path = "../src/proto/mod.rs"]
+#[allow(dead_code, unused_imports)]
+mod proto;
+
+pub(crate) use anyhow::{anyhow as anyerror, Error as AnyError, Result as AnyResult};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use proto::{
+    MessageCode, MessagePriority, MessageStream, PartyId, PayloadKind, ALL_CLIENT_ID,
+    ALL_SERVER_ID_WITH_ECHO, OFFSET_SERVER_ID,
+};
+
+const PAYLOAD_LENGTHS: [usize; 4] = [0, 64, 1024, MessageStream::MAX_PAYLOAD_LENGTH];
+
+fn message_stream(payload_length: usize, has_header_options: bool) -> MessageStream {
+    let payload = vec![0xA5; payload_length];
+    let mut result = MessageStream::new(
+        MessageCode::Normal,
+        7,
+        PartyId::Server(0),
+        PartyId::Client(42),
+        PayloadKind::Data,
+        Some(&payload),
+    );
+
+    if has_header_options {
+        result.header_options.interest_key = Some(0xBEEF);
+        result.header_options.priority = Some(MessagePriority::Bulk);
+    }
+
+    result
+}
+
+fn bench_into_raw(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("message_stream_into_raw");
+
+    for payload_length in PAYLOAD_LENGTHS.iter().copied() {
+        group.throughput(Throughput::Bytes(payload_length as u64));
+
+        for has_header_options in [false, true].iter().copied() {
+            let message = message_stream(payload_length, has_header_options);
+            let parameter = format!("{}/options={}", payload_length, has_header_options);
+
+            group.bench_with_input(
+                BenchmarkId::from_parameter(parameter),
+                &message,
+                |b, message| b.iter(|| black_box(message.clone()).into_raw()),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_from_raw(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("message_stream_from_raw");
+
+    for payload_length in PAYLOAD_LENGTHS.iter().copied() {
+        group.throughput(Throughput::Bytes(payload_length as u64));
+
+        for has_header_options in [false, true].iter().copied() {
+            let message_raw = message_stream(payload_length, has_header_options).into_raw();
+            let parameter = format!("{}/options={}", payload_length, has_header_options);
+
+            group.bench_with_input(
+                BenchmarkId::from_parameter(parameter),
+                &message_raw,
+                |b, message_raw| b.iter(|| MessageStream::from_raw(black_box(message_raw))),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_party_id(criterion: &mut Criterion) {
+    // Plain IDs on both sides of the server offset plus every sentinel
+    let party_id_values = [
+        0,
+        ALL_CLIENT_ID - 1,
+        ALL_CLIENT_ID,
+        OFFSET_SERVER_ID,
+        OFFSET_SERVER_ID + 1,
+        ALL_SERVER_ID_WITH_ECHO,
+    ];
+    let mut group = criterion.benchmark_group("party_id");
+
+    group.bench_function("from_u32", |b| {
+        b.iter(|| {
+            for party_id_value in party_id_values.iter() {
+                black_box(PartyId::from_u32(black_box(*party_id_value)));
+            }
+        })
+    });
+    group.bench_function("get_repr", |b| {
+        let party_ids: Vec<PartyId> =
+            party_id_values.iter().map(|value| PartyId::from_u32(*value)).collect();
+
+        b.iter(|| {
+            for party_id in party_ids.iter() {
+                black_box(black_box(party_id).get_repr());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_into_raw, bench_from_raw, bench_party_id);
+criterion_main!(benches);
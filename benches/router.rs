@@ -0,0 +1,159 @@
+//! Router fan-out and party ID lookup against sink endpoints, run with `cargo bench --bench router`.
+//! A measured iteration ends once the router handled the frame and queued every delivery, the
+//! sinks drain their mailboxes while the next iteration runs.
+
+#[path = "../src/admin_events.rs"]
+#[allow(dead_code, unused_imports)]
+mod admin_events;
+#[path = "../src/audit_log.rs"]
+#[allow(dead_code, unused_imports)]
+mod audit_log;
+#[path = "../src/connection_journal.rs"]
+#[allow(dead_code, unused_imports)]
+mod connection_journal;
+#[path = "../src/cron.rs"]
+#[allow(dead_code, unused_imports)]
+mod cron;
+#[path = "../src/metrics.rs"]
+#[allow(dead_code, unused_imports)]
+mod metrics;
+#[path = "../src/proto/mod.rs"]
+#[allow(dead_code, unused_imports)]
+mod proto;
+#[path = "../src/structured_log.rs"]
+#[allow(dead_code, unused_imports)]
+mod structured_log;
+#[path = "../src/telemetry.rs"]
+#[allow(dead_code, unused_imports)]
+mod telemetry;
+#[path = "../src/trusted_proxies.rs"]
+#[allow(dead_code, unused_imports)]
+mod trusted_proxies;
+#[path = "../src/utils.rs"]
+#[allow(dead_code, unused_imports)]
+mod utils;
+#[path = "../src/ws_handlers/mod.rs"]
+#[allow(dead_code, unused_imports)]
+mod ws_handlers;
+
+pub(crate) use anyhow::{anyhow as anyerror, Error as AnyError, Result as AnyResult};
+
+use actix::{Actor as ActixActor, Addr as ActorAddress, Context, Handler as MessageHandler};
+use actix_rt::SystemRunner;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use proto::{MessageCode, MessageStream, PartyId, PayloadKind};
+use std::sync::Arc;
+use uuid::Uuid;
+use ws_handlers::{GameRoomRouterActor, GameRoomRouterConfig, InterActorMessage, RoomClient};
+
+const ROOM_ID: u32 = 1;
+const CLIENT_COUNTS: [u32; 4] = [1, 16, 256, 1024];
+
+// Endpoint dropping whatever the router delivers
+struct SinkEndpoint;
+
+impl ActixActor for SinkEndpoint {
+    type Context = Context<Self>;
+}
+
+impl MessageHandler<InterActorMessage> for SinkEndpoint {
+    type Result = ();
+
+    fn handle(&mut self, _: InterActorMessage, _: &mut Self::Context) {}
+}
+
+fn data_message(origin_id: PartyId, destination_id: PartyId) -> MessageStream {
+    MessageStream::new(
+        MessageCode::Normal,
+        ROOM_ID,
+        origin_id,
+        destination_id,
+        PayloadKind::Data,
+        Some(&[0xA5; 64]),
+    )
+}
+
+// Router with a joined sink server announcing ROOM_ID and client_count sink clients in it
+fn start_router(system: &mut SystemRunner, client_count: u32) -> ActorAddress<GameRoomRouterActor> {
+    system.block_on(async move {
+        let router = GameRoomRouterActor::new(GameRoomRouterConfig::default(), None).start();
+        let server = SinkEndpoint.start().recipient();
+        let room_announcement = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&ROOM_ID.to_le_bytes()),
+        );
+
+        router.send(InterActorMessage::ServerConnect(PartyId::Server(0), server)).await.unwrap();
+        router
+            .send(InterActorMessage::NewMessage(PartyId::Server(0), room_announcement, None))
+            .await
+            .unwrap();
+
+        for client_party_id in 0..client_count {
+            let client = SinkEndpoint.start().recipient();
+            router
+                .send(InterActorMessage::ClientConnect(
+                    ROOM_ID,
+                    PartyId::Client(client_party_id),
+                    RoomClient::new(Uuid::new_v4(), client, Arc::from([])),
+                ))
+                .await
+                .unwrap();
+        }
+
+        router
+    })
+}
+
+fn bench_routing(
+    criterion: &mut Criterion,
+    group_name: &str,
+    destination_id: impl Fn(u32) -> PartyId,
+    deliveries: impl Fn(u32) -> u64,
+) {
+    let mut system = actix_rt::System::new("router-bench");
+    let mut group = criterion.benchmark_group(group_name);
+
+    for client_count in CLIENT_COUNTS.iter().copied() {
+        let router = start_router(&mut system, client_count);
+        let message = data_message(PartyId::Server(0), destination_id(client_count));
+        group.throughput(Throughput::Elements(deliveries(client_count)));
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(client_count),
+            &message,
+            |b, message| {
+                b.iter(|| {
+                    system.block_on(router.send(InterActorMessage::NewMessage(
+                        PartyId::Server(0),
+                        message.clone(),
+                        None,
+                    )))
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_fan_out(criterion: &mut Criterion) {
+    bench_routing(criterion, "router_fan_out", |_| PartyId::AllClients, u64::from);
+}
+
+// A single client picked out of the room by its party ID, the last one joined
+fn bench_party_id_lookup(criterion: &mut Criterion) {
+    bench_routing(
+        criterion,
+        "router_party_id_lookup",
+        |client_count| PartyId::Client(client_count - 1),
+        |_| 1,
+    );
+}
+
+criterion_group!(benches, bench_fan_out, bench_party_id_lookup);
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+// Generates the gRPC service of the `grpc` feature, nothing to do without it
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    tonic_build::configure().build_client(false).compile(&["proto/game_room.proto"], &["proto"])?;
+
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use anyhow::{anyhow as anyerror, Result as AnyResult};
+use game_room_client::proto::{MessageCode, MessageStream, PartyId, PayloadKind};
+use game_room_client::{connect, ConnectOptions, GameRoomClient};
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+use tokio::time::delay_for;
+use uuid::Uuid;
+
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// Game Room CLI - Connect to a router and exchange hand crafted frames
+///
+/// Commands, one per line:
+///   send to=<party> [kind=data] [room=<id>] [from=<party>] [special] [priority=<priority>]
+///        [interest=<key>] [ack=<sequence>] [correlation=<id>] [sequence=<number>]
+///        [ttl=<milliseconds>] [hex=<bytes> | text=<rest of the line>]
+///   sleep <milliseconds>
+///   quit
+///
+/// Parties are server[:id], client:id, all-clients[-echo], all-servers[-echo] or a raw u32.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "game-room-cli", verbatim_doc_comment)]
+struct GameRoomCliOptions {
+    /// Router base URL
+    #[structopt(short, long, default_value = "ws://127.0.0.1:7575")]
+    url: String,
+    /// Client UUID/GUID, the server UUID when connecting as the server
+    #[structopt(short, long, default_value = "00000000-0000-0000-0000-000000000000")]
+    client_id: Uuid,
+    /// Join this room as a client, connect as the server when omitted
+    #[structopt(short, long)]
+    room_id: Option<u32>,
+    /// Party ID assigned by the router, used as the default origin of sent frames
+    #[structopt(short, long)]
+    party_id: Option<PartyId>,
+    /// Run the commands of this file instead of reading the standard input
+    #[structopt(short, long)]
+    script: Option<PathBuf>,
+    /// Keep printing received frames this long after the script ends
+    #[structopt(long, default_value = "1000")]
+    linger_ms: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum CliCommand {
+    Send(MessageStream),
+    Sleep(Duration),
+    Quit,
+    Nothing,
+}
+
+fn parse_command(line: &str, room_id: u32, origin_id: PartyId) -> AnyResult<CliCommand> {
+    let line = line.trim();
+    let mut line_split = line.splitn(2, char::is_whitespace);
+
+    match (line_split.next(), line_split.next().map(str::trim)) {
+        (None, _) | (Some(""), _) => Ok(CliCommand::Nothing),
+        (Some(comment), _) if comment.starts_with('#') => Ok(CliCommand::Nothing),
+        (Some("quit"), _) => Ok(CliCommand::Quit),
+        (Some("sleep"), Some(milliseconds)) => {
+            Ok(CliCommand::Sleep(Duration::from_millis(milliseconds.parse()?)))
+        }
+        (Some("send"), Some(arguments)) => parse_send(arguments, room_id, origin_id),
+        _ => Err(anyerror!("Unknown command \"{}\"", line)),
+    }
+}
+
+fn parse_send(mut arguments: &str, room_id: u32, origin_id: PartyId) -> AnyResult<CliCommand> {
+    let mut message_stream = MessageStream::new(
+        MessageCode::Normal,
+        room_id,
+        origin_id,
+        PartyId::Server(0),
+        PayloadKind::Data,
+        None,
+    );
+    let mut has_destination = false;
+
+    while !arguments.is_empty() {
+        // text= swallows the rest of the line so it can contain spaces
+        if let Some(text) = arguments.strip_prefix("text=") {
+            message_stream.payload = text.as_bytes().to_vec();
+            break;
+        }
+
+        let mut arguments_split = arguments.splitn(2, char::is_whitespace);
+        let argument = arguments_split.next().unwrap_or_default();
+        arguments = arguments_split.next().unwrap_or_default().trim_start();
+        let mut argument_split = argument.splitn(2, '=');
+
+        match (argument_split.next(), argument_split.next()) {
+            (Some("special"), None) => message_stream.message_code = MessageCode::Special,
+            (Some("to"), Some(destination_id)) => {
+                message_stream.destination_id = destination_id.parse()?;
+                has_destination = true;
+            }
+            (Some("from"), Some(origin_id)) => message_stream.origin_id = origin_id.parse()?,
+            (Some("kind"), Some(payload_kind)) => {
+                message_stream.payload_kind = payload_kind.parse()?
+            }
+            (Some("room"), Some(room_id)) => message_stream.room_id = room_id.parse()?,
+            (Some("priority"), Some(priority)) => {
+                message_stream.header_options.priority = Some(priority.parse()?)
+            }
+            (Some("interest"), Some(interest_key)) => {
+                message_stream.header_options.interest_key = Some(interest_key.parse()?)
+            }
+            (Some("ack"), Some(ack_sequence)) => {
+                message_stream.header_options.ack_sequence = Some(ack_sequence.parse()?)
+            }
+            (Some("correlation"), Some(correlation_id)) => {
+                message_stream.header_options.correlation_id = Some(correlation_id.parse()?)
+            }
+            (Some("sequence"), Some(sequence)) => {
+                message_stream.header_options.sequence = Some(sequence.parse()?)
+            }
+            (Some("ttl"), Some(ttl_millis)) => {
+                message_stream.header_options.ttl_millis = Some(ttl_millis.parse()?)
+            }
+            (Some("hex"), Some(hex)) => message_stream.payload = parse_hex(hex)?,
+            _ => return Err(anyerror!("Unknown send argument \"{}\"", argument)),
+        }
+    }
+
+    if !has_destination {
+        return Err(anyerror!("send needs a to=<party> argument"));
+    }
+
+    Ok(CliCommand::Send(message_stream))
+}
+
+fn parse_hex(source: &str) -> AnyResult<Vec<u8>> {
+    let source = source.trim_start_matches("0x");
+
+    if !source.len().is_multiple_of(2) {
+        return Err(anyerror!("Odd number of hex digits in {}", source));
+    }
+
+    (0..source.len()).step_by(2).map(|i| Ok(u8::from_str_radix(&source[i..i + 2], 16)?)).collect()
+}
+
+fn format_message(message_stream: &MessageStream) -> String {
+    let mut result = format!(
+        "<< {:?} room {} | {:?} -> {:?} | {:?}",
+        message_stream.message_code,
+        message_stream.room_id,
+        message_stream.origin_id,
+        message_stream.destination_id,
+        message_stream.payload_kind,
+    );
+
+    if !message_stream.header_options.is_empty() {
+        result.push_str(&format!(" | {:?}", message_stream.header_options));
+    }
+
+    result.push_str(&format!(" | {} bytes", message_stream.payload.len()));
+
+    for chunk in message_stream.payload.chunks(HEX_DUMP_WIDTH) {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+            .collect();
+        result.push_str(&format!("\n   {:<width$}  {}", hex.join(" "), ascii, width = 47));
+    }
+
+    result
+}
+
+// Returns false once the session should end
+async fn run_command(client: &mut GameRoomClient, command: CliCommand) -> AnyResult<bool> {
+    match command {
+        CliCommand::Send(message_stream) => client.send(message_stream)?,
+        CliCommand::Sleep(duration) => print_received_for(client, duration).await,
+        CliCommand::Quit => return Ok(false),
+        CliCommand::Nothing => (),
+    }
+
+    Ok(true)
+}
+
+async fn print_received_for(client: &mut GameRoomClient, duration: Duration) {
+    let deadline = delay_for(duration);
+    tokio::pin!(deadline);
+
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return,
+            message_stream = client.receive() => match message_stream {
+                Some(message_stream) => println!("{}", format_message(&message_stream)),
+                None => return,
+            },
+        }
+    }
+}
+
+#[actix_rt::main]
+async fn main() -> AnyResult<()> {
+    env_logger::init();
+    let options = GameRoomCliOptions::from_args();
+    let (connect_options, room_id, default_origin_id) = match options.room_id {
+        Some(room_id) => (
+            ConnectOptions::client(&options.url, options.client_id, room_id),
+            room_id,
+            options.party_id.unwrap_or(PartyId::Client(0)),
+        ),
+        None => (
+            ConnectOptions::server(&options.url, options.client_id),
+            0,
+            options.party_id.unwrap_or(PartyId::Server(0)),
+        ),
+    };
+    let mut client = connect(connect_options).await?;
+
+    if let Some(script) = options.script {
+        for line in std::fs::read_to_string(script)?.lines() {
+            let command = parse_command(line, room_id, default_origin_id)?;
+
+            if !run_command(&mut client, command).await? {
+                return Ok(());
+            }
+        }
+
+        print_received_for(&mut client, Duration::from_millis(options.linger_ms)).await;
+
+        return Ok(());
+    }
+
+    let mut stdin_lines = BufReader::new(stdin()).lines();
+
+    loop {
+        tokio::select! {
+            line = stdin_lines.next_line() => {
+                let line = match line? {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+
+                match parse_command(&line, room_id, default_origin_id) {
+                    Ok(command) => {
+                        if !run_command(&mut client, command).await? {
+                            return Ok(());
+                        }
+                    }
+                    Err(error) => eprintln!("!! {}", error),
+                }
+            }
+            message_stream = client.receive() => match message_stream {
+                Some(message_stream) => println!("{}", format_message(&message_stream)),
+                None => return Err(anyerror!("Connection to the router is closed")),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command_is_as_expected() {
+        let command = parse_command(
+            "send to=client:3 kind=command special priority=critical ack=9 text=hello world",
+            1,
+            PartyId::Server(0),
+        )
+        .unwrap();
+        let mut expected_message_stream = MessageStream::new(
+            MessageCode::Special,
+            1,
+            PartyId::Server(0),
+            PartyId::Client(3),
+            PayloadKind::Command,
+            Some(b"hello world"),
+        );
+        expected_message_stream.header_options.priority =
+            Some(game_room_client::proto::MessagePriority::Critical);
+        expected_message_stream.header_options.ack_sequence = Some(9);
+
+        assert_eq!(command, CliCommand::Send(expected_message_stream));
+        assert_eq!(
+            parse_command("sleep 250", 0, PartyId::Server(0)).unwrap(),
+            CliCommand::Sleep(Duration::from_millis(250))
+        );
+        assert_eq!(parse_command("# comment", 0, PartyId::Server(0)).unwrap(), CliCommand::Nothing);
+        assert!(parse_command("send kind=data", 0, PartyId::Server(0)).is_err());
+    }
+
+    #[test]
+    fn test_format_message_is_as_expected() {
+        let message_stream = MessageStream::new(
+            MessageCode::Normal,
+            2,
+            PartyId::Client(1),
+            PartyId::AllClients,
+            PayloadKind::Data,
+            Some(&parse_hex("0x68690a").unwrap()),
+        );
+
+        assert_eq!(
+            format_message(&message_stream),
+            format!(
+                "<< Normal room 2 | Client(1) -> AllClients | Data | 3 bytes\n   {:<47}  hi.",
+                "68 69 0a"
+            )
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Chunked transfers of large blobs, e.g. replays or map data, as `Blob` frames the router keeps
+//! on the Bulk lane. The sender runs at most its window ahead of the length the receiver
+//! acknowledged, the `--blob-window` of the router by default:
+//!
+//! 1. The sender sends the frames of `BlobSender::next_frames`
+//! 2. The receiver hands every chunk to `BlobReceiver::receive` and sends back the ack it returns
+//! 3. The sender hands the acks to `BlobSender::acknowledge` and sends the next frames
+//!
+//! After a reconnect the receiver acks again what it holds and `BlobSender::resume` sends the
+//! rest from there.
+
+use crate::proto::{BlobFrame, BlobKind, MessageCode, MessageStream, PartyId, PayloadKind};
+use crate::{anyerror, AnyResult};
+
+const CHUNK_LENGTH: usize = 16 * 1024;
+const WINDOW_LENGTH: usize = 1024 * 1024;
+
+impl BlobFrame {
+    pub fn into_raw(self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(BlobFrame::LENGTH_BLOB_HEADER + self.data.len());
+        result.push(self.kind.into());
+        result.extend_from_slice(&self.transfer_id.to_le_bytes());
+        result.extend_from_slice(&self.total_length.to_le_bytes());
+        result.extend_from_slice(&self.offset.to_le_bytes());
+        result.extend_from_slice(&self.data);
+
+        result
+    }
+
+    fn from_message(message: &MessageStream, transfer_id: u32) -> AnyResult<Self> {
+        if message.payload_kind != PayloadKind::Blob {
+            return Err(anyerror!("Expected a Blob frame, got {:?}", message.payload_kind));
+        }
+
+        let blob_frame = Self::from_raw(&message.payload)?;
+
+        if blob_frame.transfer_id != transfer_id {
+            return Err(anyerror!("Blob frame of transfer {}", blob_frame.transfer_id));
+        }
+
+        Ok(blob_frame)
+    }
+}
+
+fn blob_message(
+    room_id: u32,
+    origin_id: PartyId,
+    destination_id: PartyId,
+    blob_frame: BlobFrame,
+) -> MessageStream {
+    MessageStream::new(
+        MessageCode::Normal,
+        room_id,
+        origin_id,
+        destination_id,
+        PayloadKind::Blob,
+        Some(&blob_frame.into_raw()),
+    )
+}
+
+/// Sending side of a transfer, numbered by a transfer ID unique to its sender
+#[derive(Debug)]
+pub struct BlobSender {
+    room_id: u32,
+    origin_id: PartyId,
+    destination_id: PartyId,
+    transfer_id: u32,
+    blob: Vec<u8>,
+    chunk_length: usize,
+    window_length: usize,
+    acknowledged_length: usize,
+    sent_length: usize,
+}
+
+impl BlobSender {
+    /// Blobs are at most `u32::MAX` bytes long
+    pub fn new(
+        room_id: u32,
+        origin_id: PartyId,
+        destination_id: PartyId,
+        transfer_id: u32,
+        blob: Vec<u8>,
+    ) -> AnyResult<Self> {
+        if blob.len() > u32::MAX as usize {
+            return Err(anyerror!("Blob of {} bytes is too large", blob.len()));
+        }
+
+        Ok(Self {
+            room_id,
+            origin_id,
+            destination_id,
+            transfer_id,
+            blob,
+            chunk_length: CHUNK_LENGTH,
+            window_length: WINDOW_LENGTH,
+            acknowledged_length: 0,
+            sent_length: 0,
+        })
+    }
+
+    pub fn with_chunk_length(mut self, chunk_length: usize) -> Self {
+        self.chunk_length = chunk_length.max(1);
+        self
+    }
+
+    /// Keep it at most the `--blob-window` of the router, chunks past it are refused
+    pub fn with_window_length(mut self, window_length: usize) -> Self {
+        self.window_length = window_length;
+        self
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.acknowledged_length == self.blob.len()
+    }
+
+    /// Chunks the window has room for, none until the next ack once it is full
+    pub fn next_frames(&mut self) -> Vec<MessageStream> {
+        let window_end = (self.acknowledged_length + self.window_length).min(self.blob.len());
+        let mut result = Vec::new();
+
+        while self.sent_length < window_end {
+            let chunk_end = (self.sent_length + self.chunk_length).min(window_end);
+            let blob_frame = BlobFrame {
+                kind: BlobKind::Chunk,
+                transfer_id: self.transfer_id,
+                total_length: self.blob.len() as u32,
+                offset: self.sent_length as u32,
+                data: self.blob[self.sent_length..chunk_end].to_vec(),
+            };
+            result.push(blob_message(
+                self.room_id,
+                self.origin_id,
+                self.destination_id,
+                blob_frame,
+            ));
+            self.sent_length = chunk_end;
+        }
+
+        result
+    }
+
+    pub fn acknowledge(&mut self, ack: &MessageStream) -> AnyResult<()> {
+        let blob_frame = BlobFrame::from_message(ack, self.transfer_id)?;
+
+        if blob_frame.kind != BlobKind::Ack {
+            return Err(anyerror!("Expected a blob ack, got a {:?}", blob_frame.kind));
+        }
+
+        let acknowledged_length = (blob_frame.offset as usize).min(self.blob.len());
+        self.acknowledged_length = self.acknowledged_length.max(acknowledged_length);
+        self.sent_length = self.sent_length.max(self.acknowledged_length);
+
+        Ok(())
+    }
+
+    /// Sends again from the last acknowledged length, e.g. after a reconnect or a refused chunk
+    pub fn resume(&mut self) -> Vec<MessageStream> {
+        self.sent_length = self.acknowledged_length;
+        self.next_frames()
+    }
+}
+
+/// Receiving side of a transfer, keeping the bytes received in order from the start
+#[derive(Debug)]
+pub struct BlobReceiver {
+    transfer_id: u32,
+    total_length: Option<u32>,
+    blob: Vec<u8>,
+}
+
+impl BlobReceiver {
+    pub fn new(transfer_id: u32) -> Self {
+        Self { transfer_id, total_length: None, blob: Vec::new() }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total_length.is_some_and(|total_length| self.blob.len() == total_length as usize)
+    }
+
+    /// Appends the chunk when it follows the bytes received so far and returns the ack for its
+    /// origin. Chunks received already or out of order only get the current length acknowledged
+    pub fn receive(&mut self, chunk: &MessageStream) -> AnyResult<MessageStream> {
+        let blob_frame = BlobFrame::from_message(chunk, self.transfer_id)?;
+
+        if blob_frame.kind != BlobKind::Chunk {
+            return Err(anyerror!("Expected a blob chunk, got a {:?}", blob_frame.kind));
+        }
+
+        if blob_frame.offset as usize == self.blob.len() {
+            self.blob.extend_from_slice(&blob_frame.data);
+        }
+
+        self.total_length = Some(blob_frame.total_length);
+
+        Ok(self.ack(chunk.room_id, chunk.destination_id, chunk.origin_id))
+    }
+
+    /// Acknowledges the length received so far, sent again after a reconnect to resume from it
+    pub fn ack(&self, room_id: u32, origin_id: PartyId, destination_id: PartyId) -> MessageStream {
+        let blob_frame = BlobFrame {
+            kind: BlobKind::Ack,
+            transfer_id: self.transfer_id,
+            total_length: self.total_length.unwrap_or_default(),
+            offset: self.blob.len() as u32,
+            data: Vec::new(),
+        };
+
+        blob_message(room_id, origin_id, destination_id, blob_frame)
+    }
+
+    /// The whole blob once complete
+    pub fn into_blob(self) -> Option<Vec<u8>> {
+        self.is_complete().then_some(self.blob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_transfer_is_as_expected() {
+        let blob: Vec<u8> = (0..=255).collect();
+        let mut sender =
+            BlobSender::new(3, PartyId::Server(0), PartyId::Client(1), 9, blob.clone())
+                .unwrap()
+                .with_chunk_length(40)
+                .with_window_length(100);
+        let mut receiver = BlobReceiver::new(9);
+
+        // The window holds two chunks and a half, then waits for an ack
+        let frames = sender.next_frames();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].payload.len(), BlobFrame::LENGTH_BLOB_HEADER + 20);
+        assert!(sender.next_frames().is_empty());
+
+        // The second chunk is lost, the third one is only acknowledged
+        let ack = receiver.receive(&frames[0]).unwrap();
+        assert_eq!(ack.destination_id, PartyId::Server(0));
+        receiver.receive(&frames[2]).unwrap();
+        sender.acknowledge(&ack).unwrap();
+
+        let mut frames = sender.resume();
+        assert_eq!(BlobFrame::from_raw(&frames[0].payload).unwrap().offset, 40);
+
+        while !frames.is_empty() {
+            for frame in frames {
+                sender.acknowledge(&receiver.receive(&frame).unwrap()).unwrap();
+            }
+            frames = sender.next_frames();
+        }
+
+        assert!(sender.is_complete());
+        assert_eq!(receiver.into_blob().unwrap(), blob);
+        // Acks are no chunks, and chunks of other transfers are refused
+        let mut other_sender =
+            BlobSender::new(3, PartyId::Server(0), PartyId::Client(1), 8, vec![0x01]).unwrap();
+
+        assert!(BlobReceiver::new(9).receive(&ack).is_err());
+        assert!(BlobReceiver::new(9).receive(&other_sender.next_frames()[0]).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::proto::{CompressionCodec, MessageBatch, MessageStream, PartyId, PayloadKind};
+use crate::reconnect::{ReconnectPolicy, ReconnectState};
+use crate::{anyerror, AnyResult};
+use actix_codec::Framed;
+use awc::ws::{Codec, Frame, Message as WsMessage};
+use awc::{BoxedSocket, Client};
+use bytes::Bytes;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{SinkExt, StreamExt};
+use log::warn;
+use std::time::Duration;
+use tokio::time::{delay_for, interval};
+use uuid::Uuid;
+
+type WsFramed = Framed<BoxedSocket, Codec>;
+
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    pub base_url: String, // e.g. ws://127.0.0.1:8080
+    pub client_id: Uuid,
+    pub room_id: Option<u32>, // None connects as the server
+    pub accepted_codecs: Vec<CompressionCodec>,
+    pub heartbeat_interval: Duration,
+    pub app_heartbeat: bool, // Heartbeat frames both ways instead of pings, for proxies stripping them
+    pub reconnect_policy: ReconnectPolicy,
+}
+
+impl ConnectOptions {
+    pub fn server(base_url: &str, client_id: Uuid) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client_id,
+            room_id: None,
+            accepted_codecs: Vec::new(),
+            heartbeat_interval: Duration::from_millis(500),
+            app_heartbeat: false,
+            reconnect_policy: Default::default(),
+        }
+    }
+
+    pub fn client(base_url: &str, client_id: Uuid, room_id: u32) -> Self {
+        Self { room_id: Some(room_id), ..Self::server(base_url, client_id) }
+    }
+
+    pub fn url(&self) -> String {
+        let mut url = match self.room_id {
+            None => format!("{}/server?client_id={}", self.base_url, self.client_id),
+            Some(room_id) => {
+                format!("{}/client?client_id={}&room_id={}", self.base_url, self.client_id, room_id)
+            }
+        };
+
+        if !self.accepted_codecs.is_empty() {
+            let codec_names: Vec<&str> = self
+                .accepted_codecs
+                .iter()
+                .map(|codec| match codec {
+                    CompressionCodec::Lz4 => "lz4",
+                    CompressionCodec::Zstd => "zstd",
+                })
+                .collect();
+            url.push_str(&format!("&compression={}", codec_names.join(",")));
+        }
+
+        if self.app_heartbeat {
+            url.push_str("&app_heartbeat=true");
+        }
+
+        url
+    }
+}
+
+/// Handle to a router connection driven by a background task on the current actix system.
+/// Messages sent while reconnecting are dropped, the router assigns a new party ID on rejoin.
+#[derive(Debug)]
+pub struct GameRoomClient {
+    outbound_sender: UnboundedSender<MessageStream>,
+    inbound_receiver: UnboundedReceiver<MessageStream>,
+}
+
+impl GameRoomClient {
+    pub fn send(&self, message_stream: MessageStream) -> AnyResult<()> {
+        self.outbound_sender
+            .unbounded_send(message_stream)
+            .map_err(|_| anyerror!("Connection to the router is closed"))
+    }
+
+    // None once the connection is gone for good
+    pub async fn receive(&mut self) -> Option<MessageStream> {
+        self.inbound_receiver.next().await
+    }
+}
+
+enum ConnectionExit {
+    Dropped,
+    Disconnected,
+}
+
+// The first connection must succeed, later ones are retried following the reconnect policy
+pub async fn connect(options: ConnectOptions) -> AnyResult<GameRoomClient> {
+    let framed = open(&options.url()).await?;
+    let (outbound_sender, outbound_receiver) = unbounded();
+    let (inbound_sender, inbound_receiver) = unbounded();
+
+    actix_rt::spawn(run_connection(options, framed, outbound_receiver, inbound_sender));
+
+    Ok(GameRoomClient { outbound_sender, inbound_receiver })
+}
+
+async fn open(url: &str) -> AnyResult<WsFramed> {
+    let (_, framed) =
+        Client::new().ws(url).connect().await.map_err(|error| anyerror!("{}", error))?;
+
+    Ok(framed)
+}
+
+async fn run_connection(
+    options: ConnectOptions,
+    mut framed: WsFramed,
+    mut outbound_receiver: UnboundedReceiver<MessageStream>,
+    inbound_sender: UnboundedSender<MessageStream>,
+) {
+    let url = options.url();
+    let mut reconnect_state = ReconnectState::new(options.reconnect_policy.clone());
+    let heartbeat_frame = Some(options.room_id.unwrap_or(0))
+        .filter(|_| options.app_heartbeat)
+        .map(|room_id| MessageStream::new_heartbeat(room_id, PartyId::Server(0)));
+
+    loop {
+        let connection_exit = drive_connection(
+            &mut framed,
+            &mut outbound_receiver,
+            &inbound_sender,
+            options.heartbeat_interval,
+            heartbeat_frame.as_ref(),
+        )
+        .await;
+
+        if let ConnectionExit::Dropped = connection_exit {
+            return;
+        }
+
+        loop {
+            let retry_delay = match reconnect_state.on_failure() {
+                Some(retry_delay) => retry_delay,
+                None => {
+                    warn!("Giving up reconnecting to {}", url);
+                    return;
+                }
+            };
+
+            warn!("Connection to {} lost, retrying in {:#?}", url, retry_delay);
+            delay_for(retry_delay).await;
+
+            if let Ok(new_framed) = open(&url).await {
+                framed = new_framed;
+                reconnect_state.on_connected();
+                break;
+            }
+        }
+    }
+}
+
+async fn drive_connection(
+    framed: &mut WsFramed,
+    outbound_receiver: &mut UnboundedReceiver<MessageStream>,
+    inbound_sender: &UnboundedSender<MessageStream>,
+    heartbeat_interval: Duration,
+    heartbeat_frame: Option<&MessageStream>, // Pings when None
+) -> ConnectionExit {
+    let mut heartbeat = interval(heartbeat_interval);
+
+    loop {
+        let send_result = tokio::select! {
+            frame = framed.next() => match frame {
+                Some(Ok(Frame::Binary(binary_payload))) => {
+                    if forward_inbound(&binary_payload, inbound_sender).is_err() {
+                        return ConnectionExit::Dropped;
+                    }
+
+                    Ok(())
+                }
+                Some(Ok(Frame::Ping(ping_payload))) => {
+                    framed.send(WsMessage::Pong(ping_payload)).await
+                }
+                Some(Ok(Frame::Close(_))) | Some(Err(_)) | None => {
+                    return ConnectionExit::Disconnected;
+                }
+                Some(Ok(_)) => Ok(()),
+            },
+            message_stream = outbound_receiver.next() => match message_stream {
+                Some(message_stream) => {
+                    framed.send(WsMessage::Binary(message_stream.into_raw().into())).await
+                }
+                None => {
+                    let _ = framed.send(WsMessage::Close(None)).await;
+                    return ConnectionExit::Dropped;
+                }
+            },
+            _ = heartbeat.tick() => match heartbeat_frame {
+                Some(heartbeat_frame) => {
+                    framed.send(WsMessage::Binary(heartbeat_frame.clone().into_raw().into())).await
+                }
+                None => framed.send(WsMessage::Ping(Bytes::new())).await,
+            },
+        };
+
+        if send_result.is_err() {
+            return ConnectionExit::Disconnected;
+        }
+    }
+}
+
+// Batches from the router are handed over frame by frame
+fn forward_inbound(
+    binary_payload: &[u8],
+    inbound_sender: &UnboundedSender<MessageStream>,
+) -> AnyResult<()> {
+    let mut message_stream = match MessageStream::from_raw(binary_payload) {
+        Ok(message_stream) => message_stream,
+        Err(error) => {
+            warn!("Ignoring undecodable frame: {}", error);
+            return Ok(());
+        }
+    };
+
+    // The router heartbeats only keep the connection alive
+    if message_stream.is_heartbeat() {
+        return Ok(());
+    }
+
+    if message_stream.payload_kind != PayloadKind::Batch {
+        return Ok(inbound_sender.unbounded_send(message_stream)?);
+    }
+
+    message_stream.decompress()?;
+
+    for batched_message in MessageBatch::unpack(&message_stream)? {
+        inbound_sender.unbounded_send(batched_message)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_options_url_is_as_expected() {
+        let client_id = Uuid::nil();
+        let mut connect_options = ConnectOptions::client("ws://127.0.0.1:8080/", client_id, 3);
+        connect_options.accepted_codecs = vec![CompressionCodec::Lz4, CompressionCodec::Zstd];
+        connect_options.app_heartbeat = true;
+
+        assert_eq!(
+            connect_options.url(),
+            format!(
+                "ws://127.0.0.1:8080/client?client_id={}&room_id=3&compression=lz4,zstd&app_heartbeat=true",
+                client_id
+            )
+        );
+        assert_eq!(
+            ConnectOptions::server("ws://127.0.0.1:8080", client_id).url(),
+            format!("ws://127.0.0.1:8080/server?client_id={}", client_id)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! End-to-end payload encryption between the server and the clients of a room, over routers not
+//! trusted with the payloads. The server keeps a symmetric key per room, numbered by its epoch,
+//! and hands it to every client through the router sealed by an X25519 key exchange:
+//!
+//! 1. The client sends the server a `KeyExchange` frame whose payload is `KeyExchange::request`
+//! 2. The server answers with a `KeyExchange` frame whose payload is `KeyExchange::seal_room_key`
+//! 3. The client opens the answer with `KeyExchange::open_room_key`
+//!
+//! Both sides then encrypt their payloads with `RoomKey::encrypt`, which sets the Key Epoch header
+//! option so that the router routes them without ever reading them.
+
+use crate::proto::MessageStream;
+use crate::{anyerror, AnyResult};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const LENGTH_PUBLIC_KEY: usize = 32;
+const LENGTH_NONCE: usize = 12;
+const LENGTH_SEALED_ROOM_KEY: usize = 4 + 32 + 16; // Epoch, key and tag
+
+// Sealing keys are derived from the X25519 shared secret with HKDF-SHA256 and this context
+const SEALING_KEY_INFO: &[u8] = b"game-room room key sealing";
+
+/// Symmetric key of a room, a new epoch replaces it
+#[derive(Clone)]
+pub struct RoomKey {
+    epoch: u32,
+    key: Key,
+}
+
+impl Debug for RoomKey {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.debug_struct("RoomKey").field("epoch", &self.epoch).finish_non_exhaustive()
+    }
+}
+
+impl RoomKey {
+    pub fn generate(epoch: u32) -> Self {
+        Self { epoch, key: ChaCha20Poly1305::generate_key(&mut OsRng) }
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Encrypts the payload in place and sets the Key Epoch header option, the room ID and the
+    /// payload kind are authenticated along with it. Compress the payload beforehand if needed,
+    /// the router never decompresses an encrypted one
+    pub fn encrypt(&self, message: &mut MessageStream) -> AnyResult<()> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let associated_data = Self::associated_data(message);
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, Payload { msg: &message.payload, aad: &associated_data })
+            .map_err(|_| anyerror!("Failed to encrypt the payload"))?;
+
+        message.payload = nonce.to_vec();
+        message.payload.extend_from_slice(&ciphertext);
+        message.header_options.key_epoch = Some(self.epoch);
+
+        Ok(())
+    }
+
+    /// Decrypts the payload in place and clears the Key Epoch header option
+    pub fn decrypt(&self, message: &mut MessageStream) -> AnyResult<()> {
+        match message.header_options.key_epoch {
+            Some(key_epoch) if key_epoch == self.epoch => (),
+            Some(key_epoch) => {
+                return Err(anyerror!("Payload is encrypted with the key of epoch {}", key_epoch))
+            }
+            None => return Err(anyerror!("Payload is not encrypted")),
+        }
+
+        if message.payload.len() < LENGTH_NONCE {
+            return Err(anyerror!("Encrypted payload is shorter than its nonce"));
+        }
+
+        let associated_data = Self::associated_data(message);
+        let (nonce, ciphertext) = message.payload.split_at(LENGTH_NONCE);
+        let plaintext = ChaCha20Poly1305::new(&self.key)
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &associated_data })
+            .map_err(|_| anyerror!("Encrypted payload is tampered with"))?;
+
+        message.payload = plaintext;
+        message.header_options.key_epoch = None;
+
+        Ok(())
+    }
+
+    fn associated_data(message: &MessageStream) -> Vec<u8> {
+        let mut result = message.room_id.to_le_bytes().to_vec();
+        result.push(message.payload_kind.into());
+
+        result
+    }
+}
+
+/// One side of the key exchange, a server may answer every client with the same one
+pub struct KeyExchange {
+    secret: StaticSecret,
+    public_key: PublicKey,
+}
+
+impl Debug for KeyExchange {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.debug_struct("KeyExchange").field("public_key", &self.public_key).finish()
+    }
+}
+
+impl Default for KeyExchange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyExchange {
+    pub fn new() -> Self {
+        let secret = StaticSecret::random();
+        let public_key = PublicKey::from(&secret);
+
+        Self { secret, public_key }
+    }
+
+    /// Payload of the `KeyExchange` frame of a client asking the server for the room key
+    pub fn request(&self) -> Vec<u8> {
+        self.public_key.as_bytes().to_vec()
+    }
+
+    /// Payload of the `KeyExchange` frame answering the request of a client: the public key of
+    /// the server, a nonce, then the epoch and the room key sealed for that client only
+    pub fn seal_room_key(&self, request: &[u8], room_key: &RoomKey) -> AnyResult<Vec<u8>> {
+        let sealing_cipher = self.sealing_cipher(request)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut room_key_raw = room_key.epoch.to_le_bytes().to_vec();
+        room_key_raw.extend_from_slice(&room_key.key);
+
+        let sealed_room_key = sealing_cipher
+            .encrypt(&nonce, room_key_raw.as_slice())
+            .map_err(|_| anyerror!("Failed to seal the room key"))?;
+        let mut result = self.request();
+        result.extend_from_slice(&nonce);
+        result.extend_from_slice(&sealed_room_key);
+
+        Ok(result)
+    }
+
+    /// Room key of the answer of the server to the request of this side
+    pub fn open_room_key(&self, reply: &[u8]) -> AnyResult<RoomKey> {
+        if reply.len() != LENGTH_PUBLIC_KEY + LENGTH_NONCE + LENGTH_SEALED_ROOM_KEY {
+            return Err(anyerror!("Invalid sealed room key length {}", reply.len()));
+        }
+
+        let (server_public_key, sealed) = reply.split_at(LENGTH_PUBLIC_KEY);
+        let (nonce, sealed_room_key) = sealed.split_at(LENGTH_NONCE);
+        let room_key_raw = self
+            .sealing_cipher(server_public_key)?
+            .decrypt(Nonce::from_slice(nonce), sealed_room_key)
+            .map_err(|_| anyerror!("Sealed room key is tampered with"))?;
+        let mut epoch_bytes = [0u8; 4];
+        epoch_bytes.copy_from_slice(&room_key_raw[..4]);
+
+        Ok(RoomKey {
+            epoch: u32::from_le_bytes(epoch_bytes),
+            key: *Key::from_slice(&room_key_raw[4..]),
+        })
+    }
+
+    // Low order public keys are refused, their shared secret is known to anyone
+    fn sealing_cipher(&self, peer_public_key: &[u8]) -> AnyResult<ChaCha20Poly1305> {
+        let peer_public_key = <[u8; LENGTH_PUBLIC_KEY]>::try_from(peer_public_key)
+            .map_err(|_| anyerror!("Expected a {} bytes public key", LENGTH_PUBLIC_KEY))?;
+        let shared_secret = self.secret.diffie_hellman(&PublicKey::from(peer_public_key));
+
+        if !shared_secret.was_contributory() {
+            return Err(anyerror!("Refusing a low order public key"));
+        }
+
+        let mut sealing_key = Key::default();
+        Hkdf::<Sha256>::new(None, shared_secret.as_bytes())
+            .expand(SEALING_KEY_INFO, &mut sealing_key)
+            .map_err(|_| anyerror!("Failed to derive the sealing key"))?;
+
+        Ok(ChaCha20Poly1305::new(&sealing_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PartyId, PayloadKind};
+
+    #[test]
+    fn test_key_exchange_is_as_expected() {
+        let server_exchange = KeyExchange::new();
+        let client_exchange = KeyExchange::new();
+        let room_key = RoomKey::generate(7);
+
+        let reply = server_exchange.seal_room_key(&client_exchange.request(), &room_key).unwrap();
+        let opened_room_key = client_exchange.open_room_key(&reply).unwrap();
+
+        assert_eq!(opened_room_key.epoch(), 7);
+        assert_eq!(opened_room_key.key, room_key.key);
+
+        // Only the client that asked can open it
+        assert!(KeyExchange::new().open_room_key(&reply).is_err());
+        assert!(server_exchange.seal_room_key(&[0u8; LENGTH_PUBLIC_KEY], &room_key).is_err());
+        assert!(!format!("{:?}", room_key).contains("key:"));
+    }
+
+    #[test]
+    fn test_room_key_is_as_expected() {
+        let room_key = RoomKey::generate(1);
+        let plain = MessageStream::new(
+            MessageCode::Normal,
+            3,
+            PartyId::Server(0),
+            PartyId::AllClients,
+            PayloadKind::Data,
+            Some(b"secret move"),
+        );
+        let mut message = plain.clone();
+        room_key.encrypt(&mut message).unwrap();
+
+        assert_eq!(message.header_options.key_epoch, Some(1));
+        assert_ne!(message.payload, plain.payload);
+
+        let mut decrypted = message.clone();
+        room_key.decrypt(&mut decrypted).unwrap();
+
+        assert_eq!(decrypted, plain);
+
+        // Another room, a tampered payload or another epoch do not decrypt
+        let mut moved = message.clone();
+        moved.room_id = 4;
+        let mut tampered = message.clone();
+        tampered.payload[LENGTH_NONCE] ^= 0x01;
+
+        assert!(room_key.decrypt(&mut moved).is_err());
+        assert!(room_key.decrypt(&mut tampered).is_err());
+        assert!(RoomKey::generate(2).decrypt(&mut message).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Reference client for the game room router. It shares the `proto` module with the router so
+//! framing and constants never drift, answers the router heartbeat, and reconnects on its own.
+//!
+//! ```no_run
+//! use game_room_client::proto::{MessageCode, MessageStream, PartyId, PayloadKind};
+//! use game_room_client::{connect, ConnectOptions};
+//!
+//! #[actix_rt::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let client_id = "00000000-0000-0000-0000-000000000000".parse()?;
+//!     let mut client = connect(ConnectOptions::client("ws://127.0.0.1:8080", client_id, 0)).await?;
+//!
+//!     client.send(MessageStream::new(
+//!         MessageCode::Normal,
+//!         0,
+//!         PartyId::Client(0),
+//!         PartyId::AllClients,
+//!         PayloadKind::Data,
+//!         Some(b"hello"),
+//!     ))?;
+//!
+//!     while let Some(message_stream) = client.receive().await {
+//!         println!("{:?}", message_stream);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+mod blob;
+mod connection;
+mod e2e;
+#[path = "../../src/proto/mod.rs"]
+pub mod proto;
+mod reconnect;
+mod rpc;
+mod stream;
+
+pub(crate) use anyhow::{anyhow as anyerror, Error as AnyError, Result as AnyResult};
+
+pub use blob::{BlobReceiver, BlobSender};
+pub use connection::{connect, ConnectOptions, GameRoomClient};
+pub use e2e::{KeyExchange, RoomKey};
+pub use reconnect::{ReconnectPolicy, ReconnectState};
+pub use stream::{StreamReceiver, StreamSender};
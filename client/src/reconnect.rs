@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+/// Exponential backoff applied after the connection to the router is lost.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub max_attempts: Option<u32>, // None retries forever
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Transport agnostic reconnection bookkeeping, the caller owns the timers and the socket.
+#[derive(Clone, Debug)]
+pub struct ReconnectState {
+    policy: ReconnectPolicy,
+    failed_attempts: u32,
+}
+
+impl ReconnectState {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self { policy, failed_attempts: 0 }
+    }
+
+    pub fn on_connected(&mut self) {
+        self.failed_attempts = 0;
+    }
+
+    // Delay before the next attempt, None once the policy gives up
+    pub fn on_failure(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.failed_attempts >= max_attempts {
+                return None;
+            }
+        }
+
+        let backoff_factor = 1u32 << self.failed_attempts.min(16);
+        self.failed_attempts += 1;
+
+        Some(self.policy.initial_delay.saturating_mul(backoff_factor).min(self.policy.max_delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_state_backoff_is_as_expected() {
+        let mut reconnect_state = ReconnectState::new(ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            max_attempts: Some(3),
+        });
+
+        assert_eq!(reconnect_state.on_failure(), Some(Duration::from_millis(100)));
+        assert_eq!(reconnect_state.on_failure(), Some(Duration::from_millis(200)));
+        assert_eq!(reconnect_state.on_failure(), Some(Duration::from_millis(300)));
+        assert_eq!(reconnect_state.on_failure(), None);
+
+        reconnect_state.on_connected();
+
+        assert_eq!(reconnect_state.on_failure(), Some(Duration::from_millis(100)));
+    }
+}
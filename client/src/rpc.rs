@@ -0,0 +1,61 @@
+use crate::proto::{MessageCode, MessageStream, PartyId, PayloadKind};
+
+// Only callers build RPC frames, the router routes them like any other
+impl MessageStream {
+    // RPC call matched to its response by the correlation ID, chosen by the caller
+    pub fn new_request(
+        room_id: u32,
+        origin_id: PartyId,
+        destination_id: PartyId,
+        correlation_id: u32,
+        payload: &[u8],
+    ) -> Self {
+        let mut result = Self::new(
+            MessageCode::Normal,
+            room_id,
+            origin_id,
+            destination_id,
+            PayloadKind::Request,
+            Some(payload),
+        );
+        result.header_options.correlation_id = Some(correlation_id);
+
+        result
+    }
+
+    // Response of the responder to this request, back to its origin with the same correlation ID
+    pub fn new_response(&self, responder_id: PartyId, payload: &[u8]) -> Self {
+        let mut result = Self::new(
+            MessageCode::Normal,
+            self.room_id,
+            responder_id,
+            self.origin_id,
+            PayloadKind::Response,
+            Some(payload),
+        );
+        result.header_options.correlation_id = self.header_options.correlation_id;
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_stream_request_response_is_as_expected() {
+        let request =
+            MessageStream::new_request(3, PartyId::Server(0), PartyId::Client(4), 77, b"bag");
+        let response = request.new_response(PartyId::Client(4), b"sword");
+
+        assert_eq!(request.payload_kind, PayloadKind::Request);
+        assert_eq!(
+            (response.room_id, response.origin_id, response.destination_id),
+            (3, PartyId::Client(4), PartyId::Server(0))
+        );
+        assert_eq!(response.payload_kind, PayloadKind::Response);
+        assert_eq!(response.header_options.correlation_id, Some(77));
+        assert_eq!(MessageStream::from_raw(&response.clone().into_raw()).unwrap(), response);
+    }
+}
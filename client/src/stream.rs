@@ -0,0 +1,251 @@
+//! Open-ended byte streams, e.g. voice or telemetry, as `Stream` frames multiplexed with the
+//! other frames of a room. Streams to a single party are flow controlled by credits, the router
+//! refusing the chunks past what the destination granted:
+//!
+//! 1. The sender sends the Open frame of `StreamSender::open`
+//! 2. The receiver hands every frame to `StreamReceiver::receive` and sends back the credits it
+//!    returns, its window on the Open frame then more as the chunks come
+//! 3. The sender hands the credits to `StreamSender::grant`, `StreamSender::write` keeping the
+//!    bytes past the credit until then
+//!
+//! Either side ends the stream with a Close frame.
+
+use crate::proto::{MessageCode, MessageStream, PartyId, PayloadKind, StreamFrame, StreamKind};
+use crate::{anyerror, AnyResult};
+
+const CHUNK_LENGTH: usize = 1200;
+const WINDOW_LENGTH: u32 = 64 * 1024;
+
+impl StreamFrame {
+    pub fn into_raw(self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(StreamFrame::LENGTH_STREAM_HEADER + self.data.len());
+        result.push(self.kind.into());
+        result.extend_from_slice(&self.stream_id.to_le_bytes());
+        result.extend_from_slice(&self.data);
+
+        result
+    }
+
+    fn from_message(message: &MessageStream, stream_id: u32) -> AnyResult<Self> {
+        if message.payload_kind != PayloadKind::Stream {
+            return Err(anyerror!("Expected a Stream frame, got {:?}", message.payload_kind));
+        }
+
+        let stream_frame = Self::from_raw(&message.payload)?;
+
+        if stream_frame.stream_id != stream_id {
+            return Err(anyerror!("Stream frame of stream {}", stream_frame.stream_id));
+        }
+
+        Ok(stream_frame)
+    }
+}
+
+fn stream_message(
+    room_id: u32,
+    origin_id: PartyId,
+    destination_id: PartyId,
+    kind: StreamKind,
+    stream_id: u32,
+    data: Vec<u8>,
+) -> MessageStream {
+    MessageStream::new(
+        MessageCode::Normal,
+        room_id,
+        origin_id,
+        destination_id,
+        PayloadKind::Stream,
+        Some(&StreamFrame { kind, stream_id, data }.into_raw()),
+    )
+}
+
+/// Sending side of a stream, numbered by a stream ID unique to its sender
+#[derive(Debug)]
+pub struct StreamSender {
+    room_id: u32,
+    origin_id: PartyId,
+    destination_id: PartyId,
+    stream_id: u32,
+    chunk_length: usize,
+    credit: u64,
+    pending: Vec<u8>,
+}
+
+impl StreamSender {
+    pub fn new(room_id: u32, origin_id: PartyId, destination_id: PartyId, stream_id: u32) -> Self {
+        Self {
+            room_id,
+            origin_id,
+            destination_id,
+            stream_id,
+            chunk_length: CHUNK_LENGTH,
+            credit: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn with_chunk_length(mut self, chunk_length: usize) -> Self {
+        self.chunk_length = chunk_length.max(1);
+        self
+    }
+
+    /// Bytes written and waiting for credit
+    pub fn pending_length(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn message(&self, kind: StreamKind, data: Vec<u8>) -> MessageStream {
+        stream_message(
+            self.room_id,
+            self.origin_id,
+            self.destination_id,
+            kind,
+            self.stream_id,
+            data,
+        )
+    }
+
+    pub fn open(&self) -> MessageStream {
+        self.message(StreamKind::Open, Vec::new())
+    }
+
+    /// Chunks the credit has room for, the rest of the bytes being sent on the next grants
+    pub fn write(&mut self, bytes: &[u8]) -> Vec<MessageStream> {
+        self.pending.extend_from_slice(bytes);
+        self.next_frames()
+    }
+
+    pub fn grant(&mut self, credit: &MessageStream) -> AnyResult<Vec<MessageStream>> {
+        let stream_frame = StreamFrame::from_message(credit, self.stream_id)?;
+
+        if stream_frame.kind != StreamKind::Credit {
+            return Err(anyerror!("Expected a stream credit, got a {:?}", stream_frame.kind));
+        }
+
+        self.credit += stream_frame.credit() as u64;
+
+        Ok(self.next_frames())
+    }
+
+    pub fn close(&self) -> MessageStream {
+        self.message(StreamKind::Close, Vec::new())
+    }
+
+    fn next_frames(&mut self) -> Vec<MessageStream> {
+        let mut result = Vec::new();
+
+        while !self.pending.is_empty() && self.credit > 0 {
+            let chunk_length = self.chunk_length.min(self.pending.len()).min(self.credit as usize);
+            let data: Vec<u8> = self.pending.drain(..chunk_length).collect();
+            self.credit -= chunk_length as u64;
+            result.push(self.message(StreamKind::Chunk, data));
+        }
+
+        result
+    }
+}
+
+/// Receiving side of a stream, granting its window again as the chunks are received
+#[derive(Debug)]
+pub struct StreamReceiver {
+    stream_id: u32,
+    window_length: u32,
+    granted_length: u64, // Granted and not received yet
+    is_closed: bool,
+}
+
+impl StreamReceiver {
+    pub fn new(stream_id: u32) -> Self {
+        Self { stream_id, window_length: WINDOW_LENGTH, granted_length: 0, is_closed: false }
+    }
+
+    pub fn with_window_length(mut self, window_length: u32) -> Self {
+        self.window_length = window_length.max(1);
+        self
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    /// Bytes of the frame along with the credit to send back to its origin, granted once half of
+    /// the window was received
+    pub fn receive(
+        &mut self,
+        message: &MessageStream,
+    ) -> AnyResult<(Vec<u8>, Option<MessageStream>)> {
+        let stream_frame = StreamFrame::from_message(message, self.stream_id)?;
+
+        match stream_frame.kind {
+            StreamKind::Open => self.is_closed = false,
+            StreamKind::Chunk => {
+                self.granted_length =
+                    self.granted_length.saturating_sub(stream_frame.data.len() as u64)
+            }
+            StreamKind::Close => {
+                self.is_closed = true;
+                return Ok((Vec::new(), None));
+            }
+            StreamKind::Credit => {
+                return Err(anyerror!("Expected a stream chunk, got a {:?}", stream_frame.kind))
+            }
+        }
+
+        let credit =
+            (self.window_length as u64 - self.granted_length.min(self.window_length as u64)) as u32;
+        let credit = (credit as u64 * 2 >= self.window_length as u64).then(|| {
+            self.granted_length += credit as u64;
+            stream_message(
+                message.room_id,
+                message.destination_id,
+                message.origin_id,
+                StreamKind::Credit,
+                self.stream_id,
+                credit.to_le_bytes().to_vec(),
+            )
+        });
+
+        Ok((stream_frame.data, credit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_is_as_expected() {
+        let mut sender =
+            StreamSender::new(3, PartyId::Client(1), PartyId::Server(0), 5).with_chunk_length(40);
+        let mut receiver = StreamReceiver::new(5).with_window_length(100);
+
+        // Nothing is sent before the receiver grants its window on the Open frame
+        assert!(sender.write(&[0xAA; 250]).is_empty());
+        let (data, credit) = receiver.receive(&sender.open()).unwrap();
+        let credit = credit.unwrap();
+        assert!(data.is_empty());
+        assert_eq!(credit.destination_id, PartyId::Client(1));
+
+        let frames = sender.grant(&credit).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].payload.len(), StreamFrame::LENGTH_STREAM_HEADER + 20);
+        assert_eq!(sender.pending_length(), 150);
+
+        // More credit once half of the window is received
+        let (data, credit) = receiver.receive(&frames[0]).unwrap();
+        assert_eq!(data, vec![0xAA; 40]);
+        assert!(credit.is_none());
+        let (_, credit) = receiver.receive(&frames[1]).unwrap();
+        let credit = credit.unwrap();
+        assert_eq!(StreamFrame::from_raw(&credit.payload).unwrap().credit(), 80);
+        assert_eq!(sender.grant(&credit).unwrap().len(), 2);
+        assert_eq!(sender.pending_length(), 70);
+
+        receiver.receive(&sender.close()).unwrap();
+        assert!(receiver.is_closed());
+        // Credits are no chunks, and frames of other streams are refused
+        assert!(StreamReceiver::new(5).receive(&credit).is_err());
+        assert!(StreamReceiver::new(6).receive(&sender.open()).is_err());
+        assert!(sender.grant(&frames[0]).is_err());
+    }
+}
@@ -0,0 +1,10 @@
+#![no_main]
+
+use game_room_fuzz::proto::ControlCommand;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|payload: &[u8]| {
+    if let Ok(control_command) = ControlCommand::from_payload(payload) {
+        assert_eq!(u8::from(control_command.code()), payload[0]);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use game_room_fuzz::proto::JsonEnvelope;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    if let Ok(message_stream) = JsonEnvelope::from_text(source) {
+        let _ = JsonEnvelope::to_text(message_stream);
+    }
+});
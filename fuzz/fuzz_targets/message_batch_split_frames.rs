@@ -0,0 +1,10 @@
+#![no_main]
+
+use game_room_fuzz::proto::{MessageBatch, TimeSync, TrafficRecord};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &[u8]| {
+    let _ = MessageBatch::split_frames(source);
+    let _ = TimeSync::from_request(source);
+    let _ = TrafficRecord::from_raw(source);
+});
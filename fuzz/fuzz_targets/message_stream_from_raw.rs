@@ -0,0 +1,19 @@
+#![no_main]
+
+use game_room_fuzz::proto::{HeaderOptions, MessageStream};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &[u8]| {
+    let _ = HeaderOptions::from_raw(source);
+
+    if let Ok(message_stream) = MessageStream::from_raw(source) {
+        // Whatever was accepted must re-encode into a frame that decodes to the same message
+        let raw_length = message_stream.raw_length();
+        let raw_message = message_stream.clone().into_raw();
+        assert_eq!(raw_message.len(), raw_length);
+        assert_eq!(MessageStream::from_raw(&raw_message).unwrap(), message_stream);
+
+        let mut message_stream = message_stream;
+        let _ = message_stream.decompress();
+    }
+});
@@ -0,0 +1,7 @@
+//! Exposes the router `proto` module to the fuzz targets, run them with
+//! `cargo +nightly fuzz run <target>` from the repository root.
+
+#[path = "../../src/proto/mod.rs"]
+pub mod proto;
+
+pub(crate) use anyhow::{anyhow as anyerror, Error as AnyError, Result as AnyResult};
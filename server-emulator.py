@@ -22,13 +22,20 @@ room_list_packet = [
     0x00,  # 15
     0x00,  # 16
     0x1F,  # 17
-    0x05,  # 18
+    0x0C,  # 18
     0x00,  # 19
-    0x01,  # 20
+    0x00,  # 20
     0x00,  # 21
     0x00,  # 22
-    0x01,  # 23
-    0x03,  # 24
+    0x00,  # 23
+    0x01,  # 24
+    0x00,  # 25
+    0x00,  # 26
+    0x00,  # 27
+    0x03,  # 28
+    0x00,  # 29
+    0x00,  # 30
+    0x00,  # 31
 ]
 room_wave_packet = [
     0xEF,  # 0
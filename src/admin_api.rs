@@ -0,0 +1,390 @@
+//! Admin endpoints, all off unless `--admin-token` is set: the `/admin/events` stream, the room
+//! listing with the kick, drain and link simulation commands, the connection history, the config
+//! reload, the log filters and the dashboard embedded from `admin_ui/`.
+
+use crate::audit_log::{AuditAction, AUDIT_LOG};
+use crate::config_reload;
+use crate::connection_journal::{HistoryQuery, CONNECTION_JOURNAL};
+use crate::utils::{current_log_filters, reload_log_filters};
+use crate::ws_handlers::{
+    ws_start, AdminActor, AdminCommand, LinkConditions, ListRooms, SetRoomPaused, SimulateLink,
+};
+use crate::HttpSharedState;
+use actix_web::web::{
+    block, get, post, put, resource, Data as SharedData, Json, Path as RequestPath, Payload,
+    Query as RequestQuery, ServiceConfig,
+};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use log::info;
+use rust_embed::RustEmbed;
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+
+#[derive(RustEmbed)]
+#[folder = "admin_ui/"]
+struct AdminUiAssets;
+
+#[derive(Deserialize)]
+struct AdminQueryParams {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LogLevelBody {
+    log_filters: String, // In the RUST_LOG syntax, e.g. info,game_room::ws_handlers=debug
+}
+
+pub(crate) fn configure(config: &mut ServiceConfig) {
+    config
+        .service(resource("/admin/events").route(get().to(ws_admin_events_upgrade)))
+        .service(resource("/admin/history").route(get().to(get_history)))
+        .service(resource("/admin/reload").route(post().to(reload_config)))
+        .service(resource("/admin/log-level").route(put().to(set_log_level)))
+        .service(resource("/admin/rooms").route(get().to(get_rooms)))
+        .service(resource("/admin/rooms/{room_id}/drain").route(post().to(drain_room)))
+        .service(resource("/admin/rooms/{room_id}/pause").route(post().to(pause_room)))
+        .service(resource("/admin/rooms/{room_id}/resume").route(post().to(resume_room)))
+        .service(
+            resource("/admin/rooms/{room_id}/clients/{party_id}/kick")
+                .route(post().to(kick_client)),
+        )
+        .service(
+            resource("/admin/rooms/{room_id}/clients/{party_id}/link")
+                .route(post().to(simulate_link)),
+        )
+        .service(resource("/admin/ui").route(get().to(get_admin_ui)))
+        .service(resource("/admin/ui/{file_name}").route(get().to(get_admin_ui)));
+}
+
+// The token is shared by every admin, their IP tells them apart in the audit log
+fn admin_principal(request: &HttpRequest) -> String {
+    let client_ip = request
+        .app_data::<SharedData<HttpSharedState>>()
+        .and_then(|shared_state| shared_state.client_ip(request));
+
+    match client_ip {
+        Some(client_ip) => format!("admin@{}", client_ip),
+        None => "admin".into(),
+    }
+}
+
+// Browsers cannot set headers on a WebSocket handshake, hence the query parameter fallback, only
+// taken for WebSocket upgrades. Failures are audited once a token is set
+fn is_admin_authorized(
+    admin_token: Option<&str>,
+    request: &HttpRequest,
+    query_token: Option<&str>,
+) -> bool {
+    let admin_token = match admin_token {
+        Some(admin_token) => admin_token,
+        None => return false,
+    };
+    let bearer_token = request
+        .headers()
+        .get("Authorization")
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|header_value| header_value.strip_prefix("Bearer "));
+    let is_upgrade = request
+        .headers()
+        .get("Upgrade")
+        .and_then(|header_value| header_value.to_str().ok())
+        .is_some_and(|header_value| header_value.eq_ignore_ascii_case("websocket"));
+    let query_token = query_token.filter(|_| is_upgrade);
+
+    // Compared in constant time, the timing would otherwise tell how much of the token matched
+    let is_authorized = bearer_token
+        .or(query_token)
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(admin_token.as_bytes())));
+
+    if !is_authorized {
+        AUDIT_LOG.record(
+            &admin_principal(request),
+            AuditAction::AuthFailure {
+                endpoint: request.path().into(),
+                reason: "Invalid admin token".into(),
+            },
+        );
+    }
+
+    is_authorized
+}
+
+async fn ws_admin_events_upgrade(
+    query_params: RequestQuery<AdminQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+    stream: Payload,
+) -> impl Responder {
+    if !is_admin_authorized(
+        shared_state.admin_token.as_deref(),
+        &request,
+        query_params.token.as_deref(),
+    ) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!").await;
+    }
+
+    match ws_start(AdminActor::new(), &request, stream, shared_state.permessage_deflate) {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok((_, response)) => {
+            info!("Admin just joined the event stream...");
+
+            response.await
+        }
+    }
+}
+
+async fn get_rooms(
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!").await;
+    }
+
+    match shared_state.router_address.send(ListRooms).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok(rooms) => HttpResponse::Ok().json(rooms).await,
+    }
+}
+
+// Not found unless started with --journal
+async fn get_history(
+    query: RequestQuery<HistoryQuery>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!").await;
+    }
+
+    if !CONNECTION_JOURNAL.is_open() {
+        return HttpResponse::NotFound().body("Connection journal is off!").await;
+    }
+
+    let query = query.into_inner();
+
+    match block(move || CONNECTION_JOURNAL.history(query)).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok(events) => HttpResponse::Ok().json(events).await,
+    }
+}
+
+// Same as SIGHUP, a file failing to load is a 500 and leaves every setting as it was
+async fn reload_config(
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!");
+    }
+
+    info!("Admin requested a config reload");
+
+    match config_reload::reload(&shared_state) {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+        Ok(()) => {
+            AUDIT_LOG.record(&admin_principal(&request), AuditAction::ConfigReloaded);
+
+            HttpResponse::Ok().finish()
+        }
+    }
+}
+
+// Invalid filters are a 400 and leave the filters as they were, until the next config reload
+async fn set_log_level(
+    body: Json<LogLevelBody>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!");
+    }
+
+    let log_filters = body.into_inner().log_filters;
+    info!("Admin requested the log filters {}", log_filters);
+
+    match reload_log_filters(&log_filters) {
+        Err(error) => HttpResponse::BadRequest().body(error.to_string()),
+        Ok(()) => {
+            AUDIT_LOG
+                .record(&admin_principal(&request), AuditAction::LogLevelChanged { log_filters });
+
+            HttpResponse::Ok().json(json!({ "log_filters": current_log_filters() }))
+        }
+    }
+}
+
+async fn drain_room(
+    path_params: RequestPath<u32>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    run_admin_command(AdminCommand::Drain(path_params.into_inner()), shared_state, request).await
+}
+
+async fn pause_room(
+    path_params: RequestPath<u32>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    set_room_paused(SetRoomPaused(path_params.into_inner(), true), shared_state, request).await
+}
+
+async fn resume_room(
+    path_params: RequestPath<u32>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    set_room_paused(SetRoomPaused(path_params.into_inner(), false), shared_state, request).await
+}
+
+async fn set_room_paused(
+    command: SetRoomPaused,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!");
+    }
+
+    info!("Admin requested {:?}", command);
+    let SetRoomPaused(room_id, is_paused) = command;
+
+    match shared_state.router_address.send(command).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+        Ok(released_messages) => {
+            AUDIT_LOG.record(
+                &admin_principal(&request),
+                if is_paused {
+                    AuditAction::RoomPaused { room_id }
+                } else {
+                    AuditAction::RoomResumed { room_id, released_messages }
+                },
+            );
+
+            HttpResponse::Ok().json(json!({ "released_messages": released_messages }))
+        }
+    }
+}
+
+async fn kick_client(
+    path_params: RequestPath<(u32, u32)>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    let (room_id, client_party_id) = path_params.into_inner();
+
+    run_admin_command(AdminCommand::Kick(room_id, client_party_id), shared_state, request).await
+}
+
+// Zeros lift the simulation
+async fn simulate_link(
+    path_params: RequestPath<(u32, u32)>,
+    conditions: Json<LinkConditions>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!").await;
+    }
+
+    let (room_id, client_party_id) = path_params.into_inner();
+    let conditions = conditions.into_inner();
+    info!("Admin requested {:?} for client {} of room {}", conditions, client_party_id, room_id);
+
+    match shared_state.router_address.send(SimulateLink(room_id, client_party_id, conditions)).await
+    {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok(false) => HttpResponse::NotFound().body("No such client!").await,
+        Ok(true) => {
+            AUDIT_LOG.record(
+                &admin_principal(&request),
+                AuditAction::LinkSimulated {
+                    room_id,
+                    party_id: client_party_id,
+                    bytes_per_second: conditions.bytes_per_second,
+                    jitter_millis: conditions.jitter_millis,
+                },
+            );
+
+            HttpResponse::Ok().json(conditions).await
+        }
+    }
+}
+
+async fn run_admin_command(
+    command: AdminCommand,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> HttpResponse {
+    if !is_admin_authorized(shared_state.admin_token.as_deref(), &request, None) {
+        return HttpResponse::Unauthorized().body("Invalid admin token!");
+    }
+
+    info!("Admin requested {:?}", command);
+    let is_kick = matches!(command, AdminCommand::Kick(_, _));
+    let audit_action = move |disconnected_clients| match command {
+        AdminCommand::Kick(room_id, client_party_id) => AuditAction::Kick {
+            room_id: Some(room_id),
+            party_id: client_party_id,
+            reason: "admin".into(),
+        },
+        AdminCommand::Drain(room_id) => AuditAction::Drain { room_id, disconnected_clients },
+    };
+
+    match shared_state.router_address.send(command).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+        Ok(0) if is_kick => HttpResponse::NotFound().body("No such client!"),
+        Ok(disconnected) => {
+            AUDIT_LOG.record(&admin_principal(&request), audit_action(disconnected));
+
+            HttpResponse::Ok().json(json!({ "disconnected": disconnected }))
+        }
+    }
+}
+
+// The assets are public, the dashboard asks for the token before calling anything
+async fn get_admin_ui(
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    let file_name = request.match_info().get("file_name").unwrap_or("index.html");
+
+    match (shared_state.admin_token.as_ref(), AdminUiAssets::get(file_name)) {
+        (Some(_), Some(asset)) => {
+            let body = match file_name {
+                // Its links are absolute, they go under the base path
+                "index.html" => String::from_utf8_lossy(&asset.data)
+                    .replace("\"/admin/ui/", &format!("\"{}/admin/ui/", shared_state.base_path))
+                    .into_bytes(),
+                _ => asset.data.into_owned(),
+            };
+
+            HttpResponse::Ok().content_type(asset.metadata.mimetype()).body(body).await
+        }
+        _ => HttpResponse::NotFound().body("Nothing to look here...").await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_admin_authorized_is_as_expected() {
+        let bearer = TestRequest::get().header("Authorization", "Bearer secret").to_http_request();
+        let upgrade = TestRequest::get().header("Upgrade", "websocket").to_http_request();
+        let plain = TestRequest::get().to_http_request();
+
+        assert!(is_admin_authorized(Some("secret"), &bearer, None));
+        assert!(!is_admin_authorized(Some("secrets"), &bearer, None));
+        assert!(!is_admin_authorized(None, &bearer, None));
+        assert!(is_admin_authorized(Some("secret"), &upgrade, Some("secret")));
+        assert!(!is_admin_authorized(Some("secret"), &upgrade, Some("secreT")));
+
+        // Query tokens are only taken on a WebSocket upgrade
+        assert!(!is_admin_authorized(Some("secret"), &plain, Some("secret")));
+    }
+}
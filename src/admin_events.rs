@@ -0,0 +1,143 @@
+//! Live feed of the notable router events for the `/admin/events` WebSocket. Events are published
+//! from any actor to the process wide `ADMIN_EVENTS` bus, which forwards them to every connected
+//! admin as one JSON object per text message. Nothing is kept when no admin is listening.
+
+use actix::{Message, Recipient};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub(crate) static ADMIN_EVENTS: AdminEvents = AdminEvents::new();
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum AdminEvent {
+    Connected {
+        room_id: Option<u32>,
+        party_id: u32,
+        client_id: Option<Uuid>,
+    },
+    Disconnected {
+        room_id: Option<u32>,
+        party_id: u32,
+        client_id: Option<Uuid>,
+    },
+    Kicked {
+        room_id: Option<u32>,
+        party_id: u32,
+        reason: String,
+    },
+    RoutingError {
+        room_id: u32,
+        party_id: u32,
+        reason: String,
+    },
+    RoomPaused {
+        room_id: u32,
+    },
+    RoomResumed {
+        room_id: u32,
+        released_messages: usize,
+    },
+    RoomClosed {
+        room_id: u32,
+        disconnected_clients: usize,
+    },
+    RoomExpired {
+        room_id: u32,
+    },
+    RoomSwitched {
+        client_id: Uuid,
+        from_room_id: u32,
+        from_party_id: u32,
+        room_id: u32,
+        party_id: u32,
+    },
+}
+
+/// One event as sent to the admins, stamped when it was published
+#[derive(Clone, Debug, Eq, Message, PartialEq, Serialize)]
+#[rtype(result = "()")]
+pub(crate) struct AdminEventRecord {
+    pub(crate) timestamp_millis: u64,
+    #[serde(flatten)]
+    pub(crate) event: AdminEvent,
+}
+
+#[derive(Debug)]
+pub(crate) struct AdminEvents {
+    next_subscriber_id: AtomicU64,
+    subscribers: Mutex<Vec<(u64, Recipient<AdminEventRecord>)>>,
+}
+
+impl AdminEvents {
+    pub(crate) const fn new() -> Self {
+        Self { next_subscriber_id: AtomicU64::new(0), subscribers: Mutex::new(Vec::new()) }
+    }
+
+    // Returns the ID to unsubscribe with
+    pub(crate) fn subscribe(&self, recipient: Recipient<AdminEventRecord>) -> u64 {
+        let subscriber_id = self.next_subscriber_id.fetch_add(1, Ordering::Relaxed);
+
+        if let Ok(mut write_guard) = self.subscribers.lock() {
+            write_guard.push((subscriber_id, recipient));
+        }
+
+        subscriber_id
+    }
+
+    pub(crate) fn unsubscribe(&self, subscriber_id: u64) {
+        if let Ok(mut write_guard) = self.subscribers.lock() {
+            write_guard.retain(|(id, _)| *id != subscriber_id);
+        }
+    }
+
+    // Subscribers whose mailbox is closed are dropped on the way
+    pub(crate) fn publish(&self, event: AdminEvent) {
+        if let Ok(mut write_guard) = self.subscribers.lock() {
+            if write_guard.is_empty() {
+                return;
+            }
+
+            let record = AdminEventRecord { timestamp_millis: unix_millis(), event };
+            write_guard.retain(|(_, recipient)| recipient.do_send(record.clone()).is_ok());
+        }
+    }
+}
+
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_event_record_to_json_is_as_expected() {
+        let record = AdminEventRecord {
+            timestamp_millis: 1_000,
+            event: AdminEvent::Kicked {
+                room_id: Some(3),
+                party_id: 7,
+                reason: "inactivity".into(),
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_value(&record).unwrap(),
+            serde_json::json!({
+                "timestamp_millis": 1_000,
+                "event": "kicked",
+                "room_id": 3,
+                "party_id": 7,
+                "reason": "inactivity",
+            })
+        );
+    }
+}
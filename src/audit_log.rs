@@ -0,0 +1,199 @@
+//! Append-only audit log of the administrative and control actions, written with `--audit-log`.
+//! Each action is one JSON object per line carrying who asked for it, so operators can rebuild
+//! who did what during an incident. Once the file would outgrow `--audit-log-max-bytes` it is
+//! renamed to `<path>.1`, the previous rotations shifting up to `<path>.5`.
+
+use crate::admin_events::unix_millis;
+use crate::proto::PartyId;
+use log::warn;
+use serde::Serialize;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{Result as IOResult, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub(crate) static AUDIT_LOG: AuditLog = AuditLog::new();
+
+const KEPT_ROTATIONS: u32 = 5;
+
+// Principal of the actions the router takes on its own, such as inactivity kicks
+pub(crate) const ROUTER_PRINCIPAL: &str = "router";
+
+// Principal of the actions a connected party asked for, such as a server closing a room
+pub(crate) fn party_principal(party_id: PartyId) -> String {
+    match party_id {
+        PartyId::Client(client_party_id) => format!("client {}", client_party_id),
+        PartyId::Server(server_id) => format!("server {}", server_id),
+        _ => format!("party {}", party_id.get_repr()),
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum AuditAction {
+    Kick { room_id: Option<u32>, party_id: u32, reason: String },
+    Drain { room_id: u32, disconnected_clients: usize },
+    RoomPaused { room_id: u32 },
+    RoomResumed { room_id: u32, released_messages: usize },
+    RoomClosed { room_id: u32, disconnected_clients: usize },
+    LinkSimulated { room_id: u32, party_id: u32, bytes_per_second: u64, jitter_millis: u64 },
+    AuthFailure { endpoint: String, reason: String },
+    ConfigReloaded,
+    LogLevelChanged { log_filters: String },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct AuditRecord {
+    pub(crate) timestamp_millis: u64,
+    pub(crate) principal: String, // e.g. admin@10.0.0.7, server 0 or router
+    #[serde(flatten)]
+    pub(crate) action: AuditAction,
+}
+
+#[derive(Debug)]
+struct AuditLogFile {
+    path: PathBuf,
+    file: File,
+    length: u64,
+    max_bytes: Option<u64>,
+}
+
+impl AuditLogFile {
+    fn open(path: &Path, max_bytes: Option<u64>) -> IOResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let length = file.metadata()?.len();
+
+        Ok(Self { path: path.into(), file, length, max_bytes })
+    }
+
+    fn rotated_path(&self, rotation: u32) -> PathBuf {
+        let mut rotated_path = self.path.clone().into_os_string();
+        rotated_path.push(format!(".{}", rotation));
+
+        rotated_path.into()
+    }
+
+    // The oldest rotation is overwritten, a missing one is skipped
+    fn rotate(&mut self) -> IOResult<()> {
+        for rotation in (1..KEPT_ROTATIONS).rev() {
+            let rotated_path = self.rotated_path(rotation);
+
+            if rotated_path.exists() {
+                rename(rotated_path, self.rotated_path(rotation + 1))?;
+            }
+        }
+
+        rename(&self.path, self.rotated_path(1))?;
+        *self = Self::open(&self.path, self.max_bytes)?;
+
+        Ok(())
+    }
+
+    fn append(&mut self, line: &[u8]) -> IOResult<()> {
+        let is_full = self.max_bytes.is_some_and(|max_bytes| {
+            self.length > 0 && self.length + line.len() as u64 > max_bytes
+        });
+
+        if is_full {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line)?;
+        self.length += line.len() as u64;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    log_file: Mutex<Option<AuditLogFile>>, // Actions are not kept until opened
+}
+
+impl AuditLog {
+    pub(crate) const fn new() -> Self {
+        Self { log_file: Mutex::new(None) }
+    }
+
+    // Appends to the file if it already exists, a max_bytes of None never rotates it
+    pub(crate) fn open(&self, path: &Path, max_bytes: Option<u64>) -> IOResult<()> {
+        let log_file = AuditLogFile::open(path, max_bytes)?;
+
+        if let Ok(mut write_guard) = self.log_file.lock() {
+            *write_guard = Some(log_file);
+        }
+
+        Ok(())
+    }
+
+    // Written right away, a failed write is logged and the action carries on
+    pub(crate) fn record(&self, principal: &str, action: AuditAction) {
+        if let Ok(mut write_guard) = self.log_file.lock() {
+            let log_file = match write_guard.as_mut() {
+                Some(log_file) => log_file,
+                None => return,
+            };
+            let record = AuditRecord {
+                timestamp_millis: unix_millis(),
+                principal: principal.into(),
+                action,
+            };
+            let mut line = serde_json::to_vec(&record).unwrap_or_default();
+            line.push(b'\n');
+
+            if let Err(error) = log_file.append(&line) {
+                warn!("Failed to write the audit log: {}", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{read_to_string, remove_dir_all};
+
+    #[test]
+    fn test_audit_log_is_as_expected() {
+        let log_dir =
+            std::env::temp_dir().join(format!("game-room-audit-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&log_dir).unwrap();
+        let log_path = log_dir.join("audit.jsonl");
+        let audit_log = AuditLog::new();
+
+        // Nothing is kept until opened
+        audit_log.record(ROUTER_PRINCIPAL, AuditAction::RoomPaused { room_id: 1 });
+        audit_log.open(&log_path, Some(150)).unwrap();
+        audit_log.record(
+            "admin@10.0.0.7",
+            AuditAction::Kick { room_id: Some(3), party_id: 7, reason: "admin".into() },
+        );
+
+        let line = read_to_string(&log_path).unwrap();
+        let record: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+
+        assert!(record["timestamp_millis"].as_u64().unwrap() > 0);
+        assert_eq!(record["principal"], "admin@10.0.0.7");
+        assert_eq!(record["action"], "kick");
+        assert_eq!(record["room_id"], 3);
+        assert_eq!(record["party_id"], 7);
+        assert_eq!(record["reason"], "admin");
+
+        // The second line does not fit, the first one is rotated away
+        audit_log
+            .record("server 0", AuditAction::RoomClosed { room_id: 3, disconnected_clients: 2 });
+
+        assert_eq!(read_to_string(log_dir.join("audit.jsonl.1")).unwrap(), line);
+        assert!(read_to_string(&log_path).unwrap().contains(r#""action":"room_closed""#));
+
+        audit_log.record(
+            "10.0.0.8",
+            AuditAction::AuthFailure { endpoint: "/admin/rooms".into(), reason: "token".into() },
+        );
+
+        assert!(read_to_string(log_dir.join("audit.jsonl.2")).unwrap().contains("kick"));
+        assert!(read_to_string(log_dir.join("audit.jsonl.1")).unwrap().contains("room_closed"));
+
+        remove_dir_all(log_dir).unwrap();
+    }
+}
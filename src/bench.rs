@@ -0,0 +1,296 @@
+use crate::proto::{MessageBatch, MessageCode, MessageStream, PartyId, PayloadKind, TimeSync};
+use crate::{anyerror, AnyResult};
+use actix::clock::{delay_until, interval_at, Duration, Instant};
+use actix_codec::Framed;
+use awc::ws::{Codec as WsCodec, Frame, Message as WsMessage};
+use awc::{BoxedSocket, Client};
+use futures::future::join_all;
+use futures::{SinkExt, StreamExt};
+use log::warn;
+use std::cell::RefCell;
+use std::rc::Rc;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+const WARMUP_DURATION: Duration = Duration::from_millis(500);
+const GRACE_DURATION: Duration = Duration::from_secs(1);
+const LENGTH_BENCH_PAYLOAD: usize = 8; // u64 send timestamp in microseconds
+
+/// Drive broadcast traffic from synthetic clients against a running instance
+#[derive(StructOpt, Debug)]
+pub(crate) struct BenchOptions {
+    /// Base URL of the instance under test
+    #[structopt(short, long, default_value = "ws://127.0.0.1:7575")]
+    pub(crate) url: String,
+    /// Number of synthetic clients, spread evenly over the rooms
+    #[structopt(short, long, default_value = "100")]
+    pub(crate) clients: u32,
+    /// Number of rooms, starting from room 0
+    #[structopt(long, default_value = "1")]
+    pub(crate) rooms: u32,
+    /// Broadcasts sent per client per second
+    #[structopt(long, default_value = "30")]
+    pub(crate) rate: u32,
+    /// Sending duration in seconds
+    #[structopt(short, long, default_value = "10")]
+    pub(crate) duration: u64,
+    /// Also join as the server with this UUID/GUID and open the rooms, when none is connected
+    #[structopt(short, long)]
+    pub(crate) server_uuid: Option<Uuid>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct BenchStats {
+    failed_connections: u32,
+    sent_per_room: Vec<u64>,
+    clients_per_room: Vec<u64>,
+    received: u64,
+    latencies_us: Vec<u64>,
+}
+
+impl BenchStats {
+    fn percentile(sorted_latencies_us: &[u64], percentile: f64) -> u64 {
+        if sorted_latencies_us.is_empty() {
+            return 0;
+        }
+
+        let rank = (percentile / 100.0 * (sorted_latencies_us.len() - 1) as f64).round();
+
+        sorted_latencies_us[rank as usize]
+    }
+
+    // Broadcasts ask for the echo, every client of the room should receive every message of the room
+    fn expected(&self) -> u64 {
+        self.sent_per_room
+            .iter()
+            .zip(self.clients_per_room.iter())
+            .map(|(sent, clients)| sent * clients)
+            .sum()
+    }
+
+    fn report(&mut self, duration: Duration) -> String {
+        self.latencies_us.sort_unstable();
+        let sent: u64 = self.sent_per_room.iter().sum();
+        let expected = self.expected();
+        let connected: u64 = self.clients_per_room.iter().sum();
+        let to_ms = |latency_us: u64| latency_us as f64 / 1000.0;
+
+        format!(
+            "clients: {} connected, {} failed\n\
+             sent: {} ({:.1} msg/s)\n\
+             delivered: {} ({:.1} msg/s), expected {}, dropped {}\n\
+             latency: p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+            connected,
+            self.failed_connections,
+            sent,
+            sent as f64 / duration.as_secs_f64(),
+            self.received,
+            self.received as f64 / duration.as_secs_f64(),
+            expected,
+            expected.saturating_sub(self.received),
+            to_ms(Self::percentile(&self.latencies_us, 50.0)),
+            to_ms(Self::percentile(&self.latencies_us, 90.0)),
+            to_ms(Self::percentile(&self.latencies_us, 99.0)),
+            to_ms(self.latencies_us.last().copied().unwrap_or_default()),
+        )
+    }
+}
+
+pub(crate) async fn run(options: BenchOptions) -> AnyResult<String> {
+    if options.rooms == 0 || options.rate == 0 {
+        return Err(anyerror!("Rooms and rate should be at least 1"));
+    }
+
+    let base_url = options.url.trim_end_matches('/');
+    let stats = Rc::new(RefCell::new(BenchStats {
+        sent_per_room: vec![0; options.rooms as usize],
+        clients_per_room: vec![0; options.rooms as usize],
+        ..Default::default()
+    }));
+    let duration = Duration::from_secs(options.duration);
+
+    if let Some(server_uuid) = options.server_uuid {
+        let server_url = format!("{}/server?client_id={}", base_url, server_uuid);
+        let mut server_framed = Client::new()
+            .ws(server_url)
+            .connect()
+            .await
+            .map_err(|error| anyerror!("Server connection failed: {}", error))?
+            .1;
+        let room_list: Vec<u8> =
+            (0..options.rooms).flat_map(|room_id| room_id.to_le_bytes()).collect();
+        let announce = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&room_list),
+        );
+        server_framed.send(WsMessage::Binary(announce.into_raw().into())).await?;
+
+        // Keeps the server connection alive, everything it receives is discarded
+        actix::spawn(async move {
+            while let Some(Ok(frame)) = server_framed.next().await {
+                if let Frame::Ping(ping_payload) = frame {
+                    let _ = server_framed.send(WsMessage::Pong(ping_payload)).await;
+                }
+            }
+        });
+        delay_until(Instant::now() + WARMUP_DURATION).await;
+    }
+
+    let client_connections = (0..options.clients).map(|i| {
+        let room_id = i % options.rooms;
+        let client_url =
+            format!("{}/client?client_id={}&room_id={}", base_url, Uuid::new_v4(), room_id);
+
+        async move { (room_id, Client::new().ws(client_url).connect().await) }
+    });
+    let start_at = Instant::now() + WARMUP_DURATION;
+    let mut client_drivers = Vec::new();
+
+    for (room_id, connect_result) in join_all(client_connections).await {
+        match connect_result {
+            Ok((_, framed)) => {
+                stats.borrow_mut().clients_per_room[room_id as usize] += 1;
+                client_drivers.push(drive_client(
+                    framed,
+                    room_id,
+                    options.rate,
+                    start_at,
+                    duration,
+                    stats.clone(),
+                ));
+            }
+            Err(error) => {
+                warn!("Synthetic client failed to join room {}: {}", room_id, error);
+                stats.borrow_mut().failed_connections += 1;
+            }
+        }
+    }
+
+    join_all(client_drivers).await;
+
+    let report = stats.borrow_mut().report(duration);
+
+    Ok(report)
+}
+
+async fn drive_client(
+    mut framed: Framed<BoxedSocket, WsCodec>,
+    room_id: u32,
+    rate: u32,
+    start_at: Instant,
+    duration: Duration,
+    stats: Rc<RefCell<BenchStats>>,
+) {
+    // The TimeSync reply is addressed to us, it tells the party ID assigned by the router
+    let time_sync = MessageStream::new(
+        MessageCode::Special,
+        room_id,
+        PartyId::Client(0),
+        PartyId::Server(0),
+        PayloadKind::TimeSync,
+        Some(&TimeSync::now().to_le_bytes()),
+    );
+    let mut party_id = None;
+
+    if framed.send(WsMessage::Binary(time_sync.into_raw().into())).await.is_err() {
+        return;
+    }
+
+    let mut send_interval = interval_at(start_at, Duration::from_secs_f64(1.0 / rate as f64));
+    let stop_sending_at = start_at + duration;
+    let stop_receiving = delay_until(stop_sending_at + GRACE_DURATION);
+    tokio::pin!(stop_receiving);
+
+    loop {
+        tokio::select! {
+            _ = &mut stop_receiving => break,
+            instant = send_interval.tick(), if party_id.is_some() && Instant::now() < stop_sending_at => {
+                let broadcast = MessageStream::new(
+                    MessageCode::Normal,
+                    room_id,
+                    party_id.unwrap_or(PartyId::Client(0)),
+                    PartyId::AllClientsWithEcho,
+                    PayloadKind::Data,
+                    Some(&TimeSync::now().to_le_bytes()),
+                );
+
+                if instant < stop_sending_at
+                    && framed.send(WsMessage::Binary(broadcast.into_raw().into())).await.is_ok()
+                {
+                    stats.borrow_mut().sent_per_room[room_id as usize] += 1;
+                }
+            }
+            frame = framed.next() => match frame {
+                Some(Ok(Frame::Binary(binary_payload))) => {
+                    if let Ok(message_stream) = MessageStream::from_raw(&binary_payload) {
+                        record_received(message_stream, &mut party_id, &stats);
+                    }
+                }
+                Some(Ok(Frame::Ping(ping_payload))) => {
+                    let _ = framed.send(WsMessage::Pong(ping_payload)).await;
+                }
+                Some(Ok(_)) => (),
+                _ => break,
+            },
+        }
+    }
+
+    let _ = framed.send(WsMessage::Close(None)).await;
+}
+
+fn record_received(
+    mut message_stream: MessageStream,
+    party_id: &mut Option<PartyId>,
+    stats: &Rc<RefCell<BenchStats>>,
+) {
+    if message_stream.payload_kind == PayloadKind::Batch && message_stream.decompress().is_err() {
+        return;
+    }
+
+    match message_stream.payload_kind {
+        PayloadKind::TimeSync => *party_id = Some(message_stream.destination_id),
+        PayloadKind::Batch => {
+            for batched_message in MessageBatch::unpack(&message_stream).unwrap_or_default() {
+                record_received(batched_message, party_id, stats);
+            }
+        }
+        PayloadKind::Data if message_stream.payload.len() == LENGTH_BENCH_PAYLOAD => {
+            let mut u64_bytes = [0u8; LENGTH_BENCH_PAYLOAD];
+            u64_bytes.copy_from_slice(&message_stream.payload);
+            let latency_us = TimeSync::now().saturating_sub(u64::from_le_bytes(u64_bytes));
+            let mut stats = stats.borrow_mut();
+            stats.received += 1;
+            stats.latencies_us.push(latency_us);
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_stats_report_is_as_expected() {
+        let mut stats = BenchStats {
+            failed_connections: 1,
+            sent_per_room: vec![10, 5],
+            clients_per_room: vec![2, 1],
+            received: 20,
+            latencies_us: (1..=100).rev().map(|i| i * 1000).collect(),
+        };
+
+        assert_eq!(stats.expected(), 25);
+        assert_eq!(
+            stats.report(Duration::from_secs(5)),
+            "clients: 3 connected, 1 failed\n\
+             sent: 15 (3.0 msg/s)\n\
+             delivered: 20 (4.0 msg/s), expected 25, dropped 5\n\
+             latency: p50 51.00 ms, p90 90.00 ms, p99 99.00 ms, max 100.00 ms"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Static cluster mode for deployments without a shared store. Every instance is started with the
+//! same peer list and places the rooms on a consistent hash ring of the peer URLs, so they all
+//! agree on the owner of a room without talking to each other. Clients joining a room owned by
+//! another peer are proxied to it, see `ws_handlers::PeerProxyActor`.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Points per peer on the ring, enough to spread the rooms evenly over a handful of peers
+const VIRTUAL_NODES: u32 = 64;
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+// Set on the upgrade requests a peer proxies, they are always served locally
+pub(crate) const HEADER_PROXIED_BY: &str = "X-Game-Room-Proxied-By";
+
+#[derive(Clone, Debug)]
+pub(crate) struct PeerRing {
+    node_url: String,
+    ring: BTreeMap<u64, String>, // Ring position -> Peer URL
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct RoomOwner {
+    pub(crate) room_id: u32,
+    pub(crate) owner_url: String,
+    pub(crate) is_local: bool,
+}
+
+impl PeerRing {
+    // The node URL joins the ring even when the peer list leaves it out
+    pub(crate) fn new(node_url: &str, peer_urls: &[String]) -> Self {
+        let node_url = Self::normalize(node_url);
+        let mut ring = BTreeMap::new();
+
+        for peer_url in
+            peer_urls.iter().map(|peer_url| Self::normalize(peer_url)).chain(Some(node_url.clone()))
+        {
+            for virtual_node in 0..VIRTUAL_NODES {
+                let position = Self::hash(format!("{}#{}", peer_url, virtual_node).as_bytes());
+                ring.insert(position, peer_url.clone());
+            }
+        }
+
+        Self { node_url, ring }
+    }
+
+    fn normalize(peer_url: &str) -> String {
+        peer_url.trim().trim_end_matches('/').to_lowercase()
+    }
+
+    // FNV-1a, stable across builds and platforms unlike the std hasher
+    fn hash(source: &[u8]) -> u64 {
+        source
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+    }
+
+    // First peer clockwise from the room position
+    pub(crate) fn owner_url(&self, room_id: u32) -> &str {
+        let position = Self::hash(&room_id.to_le_bytes());
+
+        self.ring
+            .range(position..)
+            .chain(self.ring.iter())
+            .map(|(_, peer_url)| peer_url.as_str())
+            .next()
+            .unwrap_or(&self.node_url)
+    }
+
+    // Every peer of the ring but this node
+    pub(crate) fn peer_urls(&self) -> Vec<String> {
+        let peer_urls: BTreeSet<&String> =
+            self.ring.values().filter(|peer_url| **peer_url != self.node_url).collect();
+
+        peer_urls.into_iter().cloned().collect()
+    }
+
+    pub(crate) fn is_peer(&self, peer_url: &str) -> bool {
+        let peer_url = Self::normalize(peer_url);
+
+        peer_url != self.node_url && self.ring.values().any(|ring_url| *ring_url == peer_url)
+    }
+
+    pub(crate) fn node_url(&self) -> &str {
+        &self.node_url
+    }
+
+    pub(crate) fn room_owner(&self, room_id: u32) -> RoomOwner {
+        let owner_url = self.owner_url(room_id);
+
+        RoomOwner { room_id, owner_url: owner_url.into(), is_local: owner_url == self.node_url }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_ring_is_as_expected() {
+        let peer_urls = vec!["ws://10.0.0.1:7575".to_string(), "ws://10.0.0.2:7575/".to_string()];
+        let first_ring = PeerRing::new("ws://10.0.0.1:7575", &peer_urls);
+        let second_ring = PeerRing::new("WS://10.0.0.2:7575", &peer_urls[..1]);
+
+        // Every peer agrees on the owner, only the owner sees the room as local
+        for room_id in 0..256 {
+            let first_owner = first_ring.room_owner(room_id);
+            let second_owner = second_ring.room_owner(room_id);
+
+            assert_eq!(first_owner.owner_url, second_owner.owner_url);
+            assert_ne!(first_owner.is_local, second_owner.is_local);
+        }
+
+        let local_count =
+            (0..1024).filter(|room_id| first_ring.room_owner(*room_id).is_local).count();
+
+        assert!((256..768).contains(&local_count));
+
+        // A lone node owns everything
+        let lone_ring = PeerRing::new("ws://10.0.0.1:7575", &[]);
+
+        assert!((0..256).all(|room_id| lone_ring.room_owner(room_id).is_local));
+    }
+}
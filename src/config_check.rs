@@ -0,0 +1,62 @@
+use crate::config_reload::ReloadableConfig;
+use crate::connection_policy::ConnectionPolicy;
+use crate::{anyerror, AnyResult, GameRoomOptions};
+use serde::Serialize;
+use tracing_subscriber::EnvFilter;
+
+// What the server would run with, the command line overridden by the --config file
+#[derive(Debug, Serialize)]
+struct EffectiveConfig {
+    config: ReloadableConfig,
+    connection_policy: ConnectionPolicy,
+}
+
+// Nothing is bound nor opened, only the options and the files they name are checked
+pub(crate) fn run(options: &GameRoomOptions) -> AnyResult<String> {
+    options.validate()?;
+
+    let config_reloader = options.config_reloader();
+    let config =
+        config_reloader.load().map_err(|error| anyerror!("Invalid --config: {}", error))?;
+    let connection_policy = config_reloader
+        .load_connection_policy()
+        .map_err(|error| anyerror!("Invalid --connection-policy: {}", error))?;
+
+    if let Some(log_filters) = config.log_filters.as_deref() {
+        EnvFilter::try_new(log_filters)
+            .map_err(|error| anyerror!("Invalid log_filters {}: {}", log_filters, error))?;
+    }
+
+    Ok(serde_json::to_string_pretty(&EffectiveConfig { config, connection_policy })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_config_check_is_as_expected() {
+        let config_path =
+            std::env::temp_dir().join(format!("game-room-config-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&config_path, r#"{"max_room_clients": 16, "log_filters": "info"}"#).unwrap();
+        let config_option = config_path.to_str().unwrap();
+
+        let options = GameRoomOptions::from_iter(&["check", "--config", config_option]);
+        let effective_config: serde_json::Value =
+            serde_json::from_str(&run(&options).unwrap()).unwrap();
+
+        assert_eq!(effective_config["config"]["max_room_clients"], 16);
+        assert_eq!(effective_config["config"]["max_connections"], 0);
+        assert_eq!(effective_config["config"]["log_filters"], "info");
+        assert_eq!(effective_config["connection_policy"]["banned_ips"], serde_json::json!([]));
+
+        std::fs::write(&config_path, r#"{"log_filters": "info,="}"#).unwrap();
+        assert!(run(&options).is_err());
+        std::fs::write(&config_path, r#"{"max_rooms": 1}"#).unwrap();
+        assert!(run(&options).is_err());
+        assert!(run(&GameRoomOptions::from_iter(&["check", "--peers", "ws://10.0.0.2"])).is_err());
+
+        std::fs::remove_file(config_path).unwrap();
+    }
+}
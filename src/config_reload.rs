@@ -0,0 +1,270 @@
+//! Settings reloaded without a restart, on SIGHUP or `POST /admin/reload`. The JSON file of
+//! `--config` overrides the matching command line options, at startup and on every reload:
+//!
+//! ```json
+//! {
+//!   "handshake_rate": 50.0,
+//!   "handshake_rate_per_ip": 2.0,
+//!   "max_connections": 10000,
+//!   "max_connections_per_ip": 8,
+//!   "max_room_clients": 16,
+//!   "log_filters": "info,game_room::ws_handlers=debug",
+//!   "rooms": {
+//!     "3": {"max_clients": 4, "client_timeout_millis": 10000, "tick_rate": 20},
+//!     "7": {"allowed_payload_kinds": ["Data", "Command"]}
+//!   },
+//!   "room_templates": {
+//!     "ranked-2v2": {"config": {"max_clients": 4}, "metadata": {"mode": "ranked"}},
+//!     "lobby-64": {"config": {"max_clients": 64, "tick_rate": 10}},
+//!     "friday-cup": {"schedule": {"open": "0 18 * * 5", "close": "0 22 * * 5"}}
+//!   },
+//!   "provisioned_rooms": {"100": "ranked-2v2", "200": "lobby-64", "300": "friday-cup"}
+//! }
+//! ```
+//!
+//! Settings left out of the file come back to their command line value, `RUST_LOG` for the log
+//! filters. The settings of the rooms are replaced as a whole, those their server set with the
+//! ConfigureRoom control command still apply over them. A provisioned room takes the settings of
+//! its template under those of its `rooms` entry. The provisioned rooms themselves are published
+//! at startup only, a reload changing their settings but not which rooms exist. The
+//! `--connection-policy` file is read again too, its banned IPs closing the connected clients
+//! they match. The router and the client connections get the new settings through a
+//! `ConfigUpdate`. The WebTransport certificate and key are read again as well, for the sessions
+//! opened after the reload. A file failing to load leaves every setting as it was.
+
+use crate::connection_policy::ConnectionPolicy;
+use crate::utils::reload_log_filters;
+#[cfg(feature = "webtransport")]
+use crate::webtransport_listener::WebTransportCertificate;
+use crate::ws_handlers::{ConfigUpdate, ProvisionedRoom, RoomConfig, RoomTemplate};
+use crate::{anyerror, AnyResult, HttpSharedState};
+use actix_web::web::Data as SharedData;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::read;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReloadableConfig {
+    // Zeros disable, as on the command line
+    pub(crate) handshake_rate: Option<f64>,
+    pub(crate) handshake_rate_per_ip: Option<f64>,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) max_connections_per_ip: Option<usize>,
+    pub(crate) max_room_clients: Option<usize>,
+    pub(crate) log_filters: Option<String>, // In the RUST_LOG syntax
+    pub(crate) rooms: Option<BTreeMap<u32, RoomConfig>>, // Room ID -> Settings over the router ones
+    pub(crate) room_templates: Option<BTreeMap<String, RoomTemplate>>, // Name -> Template
+    pub(crate) provisioned_rooms: Option<BTreeMap<u32, String>>, // Room ID -> Template name
+}
+
+impl ReloadableConfig {
+    pub(crate) fn load(path: &Path) -> AnyResult<Self> {
+        Ok(serde_json::from_slice(&read(path)?)?)
+    }
+
+    // Settings left out are taken from the fallback
+    fn or(self, fallback: &Self) -> Self {
+        Self {
+            handshake_rate: self.handshake_rate.or(fallback.handshake_rate),
+            handshake_rate_per_ip: self.handshake_rate_per_ip.or(fallback.handshake_rate_per_ip),
+            max_connections: self.max_connections.or(fallback.max_connections),
+            max_connections_per_ip: self.max_connections_per_ip.or(fallback.max_connections_per_ip),
+            max_room_clients: self.max_room_clients.or(fallback.max_room_clients),
+            log_filters: self.log_filters.or_else(|| fallback.log_filters.clone()),
+            rooms: self.rooms.or_else(|| fallback.rooms.clone()),
+            room_templates: self.room_templates.or_else(|| fallback.room_templates.clone()),
+            provisioned_rooms: self
+                .provisioned_rooms
+                .or_else(|| fallback.provisioned_rooms.clone()),
+        }
+    }
+
+    pub(crate) fn handshake_rates(&self) -> (Option<f64>, Option<f64>) {
+        let is_enabled = |rate: &f64| *rate > 0.0;
+
+        (self.handshake_rate.filter(is_enabled), self.handshake_rate_per_ip.filter(is_enabled))
+    }
+
+    pub(crate) fn connection_limits(&self) -> (Option<usize>, Option<usize>) {
+        let is_enabled = |limit: &usize| *limit > 0;
+
+        (self.max_connections.filter(is_enabled), self.max_connections_per_ip.filter(is_enabled))
+    }
+
+    pub(crate) fn max_room_clients(&self) -> Option<usize> {
+        self.max_room_clients.filter(|max_room_clients| *max_room_clients > 0)
+    }
+
+    // The settings of the rooms entries over those of the templates
+    pub(crate) fn room_configs(&self) -> BTreeMap<u32, RoomConfig> {
+        let mut room_configs: BTreeMap<u32, RoomConfig> = self
+            .provisioned_rooms
+            .iter()
+            .flatten()
+            .filter_map(|(room_id, template_name)| {
+                let template = self.room_templates.as_ref()?.get(template_name)?;
+                Some((*room_id, template.config.clone()))
+            })
+            .collect();
+
+        for (room_id, room_config) in self.rooms.iter().flatten() {
+            let template_config = room_configs.remove(room_id).unwrap_or_default();
+            room_configs.insert(*room_id, room_config.clone().or(&template_config));
+        }
+
+        room_configs
+    }
+
+    pub(crate) fn provisioned_rooms(&self) -> AnyResult<BTreeMap<u32, ProvisionedRoom>> {
+        ProvisionedRoom::provision(
+            &self.room_templates.clone().unwrap_or_default(),
+            &self.provisioned_rooms.clone().unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ConfigReloader {
+    config_path: Option<PathBuf>,
+    connection_policy_path: Option<PathBuf>,
+    command_line: ReloadableConfig, // What the file overrides
+}
+
+impl ConfigReloader {
+    pub(crate) fn new(
+        config_path: Option<PathBuf>,
+        connection_policy_path: Option<PathBuf>,
+        command_line: ReloadableConfig,
+    ) -> Self {
+        Self { config_path, connection_policy_path, command_line }
+    }
+
+    // The settings in effect, the command line overridden by the file when set. A file
+    // provisioning rooms from unknown templates fails to load
+    pub(crate) fn load(&self) -> AnyResult<ReloadableConfig> {
+        let config = match self.config_path.as_deref() {
+            Some(config_path) => ReloadableConfig::load(config_path)?.or(&self.command_line),
+            None => self.command_line.clone(),
+        };
+        config.provisioned_rooms()?;
+
+        Ok(config)
+    }
+
+    pub(crate) fn load_connection_policy(&self) -> AnyResult<ConnectionPolicy> {
+        match self.connection_policy_path.as_deref() {
+            Some(policy_path) => ConnectionPolicy::load(policy_path),
+            None => Ok(ConnectionPolicy::default()),
+        }
+    }
+}
+
+// Both files, and the WebTransport certificate, are loaded before anything is applied
+pub(crate) fn reload(shared_state: &HttpSharedState) -> AnyResult<()> {
+    let config = shared_state.config_reloader.load()?;
+    let connection_policy = shared_state.config_reloader.load_connection_policy()?;
+    #[cfg(feature = "webtransport")]
+    let webtransport_server_config = shared_state
+        .webtransport_certificate
+        .as_ref()
+        .map(WebTransportCertificate::load)
+        .transpose()?;
+
+    if let Some(log_filters) = config.log_filters.as_deref() {
+        reload_log_filters(log_filters)?;
+    }
+
+    let (handshake_rate, handshake_rate_per_ip) = config.handshake_rates();
+    shared_state.handshake_limiter.set_rates(handshake_rate, handshake_rate_per_ip);
+    let (max_connections, max_connections_per_ip) = config.connection_limits();
+    shared_state.connection_limits.set_limits(max_connections, max_connections_per_ip);
+    let config_update = ConfigUpdate {
+        max_room_clients: config.max_room_clients(),
+        banned_ips: connection_policy.banned_ips().into(),
+        room_configs: config.room_configs().into(),
+    };
+    *shared_state
+        .connection_policy
+        .write()
+        .map_err(|_| anyerror!("Memory poisoning detected!"))? = connection_policy;
+    shared_state.router_address.do_send(config_update);
+
+    #[cfg(feature = "webtransport")]
+    if let (Some(webtransport_certificate), Some(server_config)) =
+        (shared_state.webtransport_certificate.as_ref(), webtransport_server_config)
+    {
+        webtransport_certificate.apply(server_config);
+    }
+
+    info!("Config reloaded: {:?}", config);
+
+    Ok(())
+}
+
+// A failed reload is logged and the server carries on with the previous settings
+pub(crate) fn reload_on_hangup(shared_state: SharedData<HttpSharedState>) {
+    #[cfg(unix)]
+    actix::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(error) => return warn!("Failed to watch SIGHUP: {}", error),
+        };
+
+        while hangup.recv().await.is_some() {
+            if let Err(error) = reload(&shared_state) {
+                warn!("Failed to reload the config: {}", error);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reloadable_config_is_as_expected() {
+        let command_line = ReloadableConfig {
+            handshake_rate: Some(0.0),
+            handshake_rate_per_ip: Some(5.0),
+            max_connections: Some(100),
+            max_connections_per_ip: Some(0),
+            max_room_clients: Some(0),
+            log_filters: Some("warn".into()),
+            rooms: None,
+            room_templates: None,
+            provisioned_rooms: None,
+        };
+        let config: ReloadableConfig = serde_json::from_str(
+            r#"{
+                "handshake_rate": 50.0,
+                "max_connections_per_ip": 8,
+                "log_filters": "info",
+                "rooms": {"3": {"max_clients": 4}, "5": {"tick_rate": 20}},
+                "room_templates": {"duel": {"config": {"max_clients": 2, "tick_rate": 30}}},
+                "provisioned_rooms": {"5": "duel", "6": "duel"}
+            }"#,
+        )
+        .unwrap();
+        let config = config.or(&command_line);
+
+        assert_eq!(config.handshake_rates(), (Some(50.0), Some(5.0)));
+        assert_eq!(config.connection_limits(), (Some(100), Some(8)));
+        assert_eq!(config.max_room_clients(), None);
+        assert_eq!(config.log_filters.as_deref(), Some("info"));
+        assert_eq!(config.room_configs()[&3].max_clients, Some(4));
+        assert_eq!(config.room_configs()[&5].max_clients, Some(2));
+        assert_eq!(config.room_configs()[&5].tick_rate, Some(20));
+        assert_eq!(config.room_configs()[&6].tick_rate, Some(30));
+        assert_eq!(config.provisioned_rooms().unwrap()[&6].template, "duel");
+
+        // Left out of the file, the command line applies again
+        assert_eq!(ReloadableConfig::default().or(&command_line), command_line);
+        assert!(serde_json::from_str::<ReloadableConfig>(r#"{"rate": 1.0}"#).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! Journal of the client connections kept in an embedded SQLite database with `--journal`, for
+//! post-mortems going further back than the live metrics. Each connection is one `connected` row
+//! once started and one `disconnected` row once stopped, carrying how long it lasted, the bytes it
+//! received and sent and why it was closed. Rows are written by a thread of their own so no actor
+//! waits on the disk, `/admin/history` reads them back over another connection.
+
+use crate::AnyResult;
+use log::warn;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::io::{Error as IOError, Result as IOResult};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::OnceLock;
+use std::thread;
+use uuid::Uuid;
+
+pub(crate) static CONNECTION_JOURNAL: ConnectionJournal = ConnectionJournal::new();
+
+const MAX_PENDING_EVENTS: usize = 8192; // Events are dropped while the writer lags this far behind
+const MAX_HISTORY_EVENTS: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConnectionEventKind {
+    Connected,
+    Disconnected,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct ConnectionEvent {
+    pub(crate) timestamp_millis: u64,
+    pub(crate) event: ConnectionEventKind,
+    pub(crate) room_id: u32, // The room the client connected to
+    pub(crate) party_id: u32,
+    pub(crate) client_id: Uuid,
+    pub(crate) duration_millis: Option<u64>, // Set once disconnected, as are the next ones
+    pub(crate) bytes_received: Option<u64>,
+    pub(crate) bytes_sent: Option<u64>,
+    pub(crate) close_reason: Option<String>,
+}
+
+// Both are optional, the oldest events come first
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub(crate) struct HistoryQuery {
+    pub(crate) room_id: Option<u32>,
+    pub(crate) since: Option<u64>, // UNIX millis, inclusive
+}
+
+#[derive(Debug)]
+struct JournalWriter {
+    path: PathBuf,
+    sender: SyncSender<ConnectionEvent>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ConnectionJournal {
+    writer: OnceLock<JournalWriter>, // Nothing is journaled until opened
+}
+
+impl ConnectionJournal {
+    pub(crate) const fn new() -> Self {
+        Self { writer: OnceLock::new() }
+    }
+
+    // Creates the database if needed and spawns the writer thread
+    pub(crate) fn open(&self, path: &Path) -> IOResult<()> {
+        let connection = open_database(path).map_err(IOError::other)?;
+        let (sender, receiver) = sync_channel(MAX_PENDING_EVENTS);
+
+        thread::Builder::new()
+            .name("connection-journal".into())
+            .spawn(move || write_events(connection, receiver))?;
+
+        let _ = self.writer.set(JournalWriter { path: path.into(), sender });
+
+        Ok(())
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.writer.get().is_some()
+    }
+
+    pub(crate) fn record(&self, event: ConnectionEvent) {
+        if let Some(writer) = self.writer.get() {
+            let _ = writer.sender.try_send(event);
+        }
+    }
+
+    // Blocking, run it off the actix threads
+    pub(crate) fn history(&self, query: HistoryQuery) -> AnyResult<Vec<ConnectionEvent>> {
+        match self.writer.get() {
+            Some(writer) => Ok(query_history(&open_database(&writer.path)?, query)?),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn open_database(path: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    // Readers are not held up by the writer
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS connection_events (
+            timestamp_millis INTEGER NOT NULL,
+            event TEXT NOT NULL,
+            room_id INTEGER NOT NULL,
+            party_id INTEGER NOT NULL,
+            client_id TEXT NOT NULL,
+            duration_millis INTEGER,
+            bytes_received INTEGER,
+            bytes_sent INTEGER,
+            close_reason TEXT
+        );
+        CREATE INDEX IF NOT EXISTS connection_events_room
+            ON connection_events (room_id, timestamp_millis);
+        CREATE INDEX IF NOT EXISTS connection_events_time ON connection_events (timestamp_millis);",
+    )?;
+
+    Ok(connection)
+}
+
+fn write_events(connection: Connection, receiver: Receiver<ConnectionEvent>) {
+    for event in receiver {
+        if let Err(error) = insert_event(&connection, &event) {
+            warn!("Failed to journal a connection event: {}", error);
+        }
+    }
+}
+
+fn insert_event(connection: &Connection, event: &ConnectionEvent) -> rusqlite::Result<()> {
+    let event_kind = match event.event {
+        ConnectionEventKind::Connected => "connected",
+        ConnectionEventKind::Disconnected => "disconnected",
+    };
+    connection.execute(
+        "INSERT INTO connection_events VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            event.timestamp_millis as i64,
+            event_kind,
+            event.room_id,
+            event.party_id,
+            event.client_id.to_string(),
+            event.duration_millis.map(|duration_millis| duration_millis as i64),
+            event.bytes_received.map(|bytes_received| bytes_received as i64),
+            event.bytes_sent.map(|bytes_sent| bytes_sent as i64),
+            event.close_reason,
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn query_history(
+    connection: &Connection,
+    query: HistoryQuery,
+) -> rusqlite::Result<Vec<ConnectionEvent>> {
+    let mut statement = connection.prepare(
+        "SELECT * FROM connection_events
+        WHERE (?1 IS NULL OR room_id = ?1) AND timestamp_millis >= ?2
+        ORDER BY timestamp_millis, rowid LIMIT ?3",
+    )?;
+    let since = query.since.unwrap_or(0) as i64;
+    let rows =
+        statement.query_map(params![query.room_id, since, MAX_HISTORY_EVENTS], read_event)?;
+
+    rows.collect()
+}
+
+fn read_event(row: &Row<'_>) -> rusqlite::Result<ConnectionEvent> {
+    let event_kind: String = row.get(1)?;
+    let client_id: String = row.get(4)?;
+    let as_u64 = |value: Option<i64>| value.map(|value| value as u64);
+
+    Ok(ConnectionEvent {
+        timestamp_millis: row.get::<_, i64>(0)? as u64,
+        event: match event_kind.as_str() {
+            "connected" => ConnectionEventKind::Connected,
+            _ => ConnectionEventKind::Disconnected,
+        },
+        room_id: row.get(2)?,
+        party_id: row.get(3)?,
+        client_id: client_id.parse().unwrap_or_default(),
+        duration_millis: as_u64(row.get(5)?),
+        bytes_received: as_u64(row.get(6)?),
+        bytes_sent: as_u64(row.get(7)?),
+        close_reason: row.get(8)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection_event(timestamp_millis: u64, room_id: u32) -> ConnectionEvent {
+        ConnectionEvent {
+            timestamp_millis,
+            event: ConnectionEventKind::Connected,
+            room_id,
+            party_id: 2,
+            client_id: Uuid::new_v4(),
+            duration_millis: None,
+            bytes_received: None,
+            bytes_sent: None,
+            close_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_connection_journal_history_is_as_expected() {
+        let connection = open_database(Path::new(":memory:")).unwrap();
+        let disconnected = ConnectionEvent {
+            event: ConnectionEventKind::Disconnected,
+            duration_millis: Some(5_000),
+            bytes_received: Some(120),
+            bytes_sent: Some(4_096),
+            close_reason: Some("Room closed".into()),
+            ..connection_event(3_000, 1)
+        };
+        let events = vec![connection_event(1_000, 1), connection_event(2_000, 2), disconnected];
+
+        for event in events.iter() {
+            insert_event(&connection, event).unwrap();
+        }
+
+        assert_eq!(query_history(&connection, HistoryQuery::default()).unwrap(), events);
+        assert_eq!(
+            query_history(&connection, HistoryQuery { room_id: Some(1), since: None }).unwrap(),
+            vec![events[0].clone(), events[2].clone()]
+        );
+        assert_eq!(
+            query_history(&connection, HistoryQuery { room_id: Some(1), since: Some(2_000) })
+                .unwrap(),
+            vec![events[2].clone()]
+        );
+        assert_eq!(
+            serde_json::to_value(&events[2]).unwrap()["close_reason"],
+            serde_json::json!("Room closed")
+        );
+    }
+}
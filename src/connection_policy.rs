@@ -0,0 +1,163 @@
+//! Policy on the client connections, loaded from the JSON file of `--connection-policy` and checked
+//! before any upgrade or poll session completes, ahead of the connection limits:
+//!
+//! ```json
+//! {
+//!   "browser_origins": ["https://play.example.com", "https://*.example.com"],
+//!   "blocked_user_agents": ["*bot*", "python-requests/*"],
+//!   "banned_ips": ["203.0.113.7", "198.51.100.0/24"]
+//! }
+//! ```
+//!
+//! Browser clients, those sending an `Origin` header or a `Mozilla/` user agent, must come from one
+//! of the origins when any is listed. Clients whose `User-Agent` matches a blocked pattern are
+//! refused. Patterns are case insensitive, `*` matching any run of characters. Clients from a
+//! banned IP or network are refused on every transport, the file being read again on reload.
+
+use crate::trusted_proxies::IpNetwork;
+use crate::AnyResult;
+use serde::{Deserialize, Serialize};
+use std::fs::read;
+use std::net::IpAddr;
+use std::path::Path;
+
+const BROWSER_USER_AGENT: &str = "mozilla/";
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConnectionPolicy {
+    #[serde(default)]
+    browser_origins: Vec<String>, // Browsers come from any origin when empty
+    #[serde(default)]
+    blocked_user_agents: Vec<String>,
+    #[serde(default)]
+    banned_ips: Vec<IpNetwork>, // Client IPs behind the trusted proxies
+}
+
+impl ConnectionPolicy {
+    pub(crate) fn load(path: &Path) -> AnyResult<Self> {
+        Ok(serde_json::from_slice(&read(path)?)?)
+    }
+
+    pub(crate) fn banned_ips(&self) -> &[IpNetwork] {
+        &self.banned_ips
+    }
+
+    pub(crate) fn check_ip(&self, client_ip: IpAddr) -> Result<(), String> {
+        if self.banned_ips.iter().any(|banned_ip| banned_ip.contains(client_ip)) {
+            return Err(format!("{} is banned!", client_ip));
+        }
+
+        Ok(())
+    }
+
+    // Refusals are the reason of a 403
+    pub(crate) fn check(
+        &self,
+        origin: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<(), String> {
+        if let Some(user_agent) = user_agent {
+            if self.blocked_user_agents.iter().any(|pattern| matches_pattern(pattern, user_agent)) {
+                return Err(format!("User agent {} is blocked!", user_agent));
+            }
+        }
+
+        let is_browser = origin.is_some()
+            || user_agent.is_some_and(|user_agent| {
+                user_agent.to_ascii_lowercase().contains(BROWSER_USER_AGENT)
+            });
+
+        if !is_browser || self.browser_origins.is_empty() {
+            return Ok(());
+        }
+
+        match origin {
+            Some(origin)
+                if self.browser_origins.iter().any(|pattern| matches_pattern(pattern, origin)) =>
+            {
+                Ok(())
+            }
+            Some(origin) => Err(format!("Origin {} is not allowed!", origin)),
+            None => Err("Browser clients must send their Origin!".into()),
+        }
+    }
+}
+
+// Case insensitive, backtracking to the last `*` on a mismatch
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase().into_bytes();
+    let value = value.to_ascii_lowercase().into_bytes();
+    let (mut pattern_index, mut value_index) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None; // Pattern index after it, value index
+
+    while value_index < value.len() {
+        match pattern.get(pattern_index) {
+            Some(b'*') => {
+                pattern_index += 1;
+                last_star = Some((pattern_index, value_index));
+            }
+            Some(pattern_byte) if *pattern_byte == value[value_index] => {
+                pattern_index += 1;
+                value_index += 1;
+            }
+            _ => match last_star {
+                Some((star_pattern_index, star_value_index)) => {
+                    pattern_index = star_pattern_index;
+                    value_index = star_value_index + 1;
+                    last_star = Some((star_pattern_index, value_index));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[pattern_index..].iter().all(|pattern_byte| *pattern_byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_policy_is_as_expected() {
+        let connection_policy: ConnectionPolicy = serde_json::from_str(
+            r#"{
+                "browser_origins": ["https://play.example.com", "https://*.example.com"],
+                "blocked_user_agents": ["*bot*", "python-requests/*"],
+                "banned_ips": ["203.0.113.7", "198.51.100.0/24"]
+            }"#,
+        )
+        .unwrap();
+        let firefox =
+            Some("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0");
+
+        assert!(connection_policy.check(Some("https://play.example.com"), firefox).is_ok());
+        assert!(connection_policy.check(Some("https://eu.example.com"), firefox).is_ok());
+        assert!(connection_policy.check(Some("https://example.org"), firefox).is_err());
+        assert!(connection_policy.check(None, firefox).is_err());
+        // Native clients send neither
+        assert!(connection_policy.check(None, Some("game-client/1.2")).is_ok());
+        assert!(connection_policy.check(None, None).is_ok());
+        assert!(connection_policy.check(None, Some("Python-Requests/2.31")).is_err());
+        assert!(connection_policy.check(None, Some("SomeBot/0.1")).is_err());
+        assert!(connection_policy.check_ip("203.0.113.7".parse().unwrap()).is_err());
+        assert!(connection_policy.check_ip("198.51.100.42".parse().unwrap()).is_err());
+        assert!(connection_policy.check_ip("203.0.113.8".parse().unwrap()).is_ok());
+
+        assert!(ConnectionPolicy::default().check(Some("https://example.org"), firefox).is_ok());
+        assert!(serde_json::from_str::<ConnectionPolicy>(r#"{"origins": []}"#).is_err());
+        assert!(serde_json::from_str::<ConnectionPolicy>(r#"{"banned_ips": ["x"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_matches_pattern_is_as_expected() {
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("a*c", "abbbc"));
+        assert!(matches_pattern("a*b*c", "aXbYbZc"));
+        assert!(!matches_pattern("a*c", "abcd"));
+        assert!(!matches_pattern("abc", "ab"));
+        assert!(matches_pattern("https://*.example.com", "https://EU.example.com"));
+        assert!(!matches_pattern("https://*.example.com", "https://example.com.evil.org"));
+    }
+}
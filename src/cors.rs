@@ -0,0 +1,204 @@
+//! CORS for the browser frontends calling the REST endpoints from another origin, e.g. a lobby
+//! listing the rooms with `GET /`. Requests from an origin listed with `--cors-origin` are answered
+//! the `Access-Control-*` headers, preflights included, other origins get none and the browser
+//! blocks them. With `--ws-check-origin`, WebSocket upgrades carrying an Origin outside the list
+//! are refused with `403`, native clients sending no Origin are not concerned.
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, RETRY_AFTER, UPGRADE, VARY,
+};
+use actix_web::http::Method;
+use actix_web::{Error as ActixError, HttpResponse};
+use futures::future::{ready, FutureExt, LocalBoxFuture};
+
+const ANY_ORIGIN: &str = "*";
+const ALLOWED_METHODS: &str = "GET, POST, DELETE";
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CorsPolicy {
+    allowed_origins: Vec<String>, // CORS is off when empty, * allows any origin
+    allowed_headers: String,      // Comma separated, answered to the preflights
+    max_age: u64,                 // Seconds the browsers cache a preflight for
+    check_ws_origin: bool,
+}
+
+impl CorsPolicy {
+    pub(crate) fn new(
+        allowed_origins: Vec<String>,
+        allowed_headers: &[String],
+        max_age: u64,
+    ) -> Self {
+        Self {
+            allowed_origins,
+            allowed_headers: allowed_headers.join(", "),
+            max_age,
+            check_ws_origin: false,
+        }
+    }
+
+    pub(crate) fn with_ws_origin_check(mut self, check_ws_origin: bool) -> Self {
+        self.check_ws_origin = check_ws_origin;
+        self
+    }
+
+    // Origins are compared without their trailing slash, some clients append one
+    fn is_allowed(&self, origin: &str) -> bool {
+        let origin = origin.trim_end_matches('/');
+
+        self.allowed_origins.iter().any(|allowed_origin| {
+            allowed_origin == ANY_ORIGIN
+                || allowed_origin.trim_end_matches('/').eq_ignore_ascii_case(origin)
+        })
+    }
+
+    // Wraps the routes, preflights and refused upgrades never reach them
+    pub(crate) fn handle<S>(
+        &self,
+        request: ServiceRequest,
+        service: &mut S,
+    ) -> LocalBoxFuture<'static, Result<ServiceResponse, ActixError>>
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = ActixError>,
+        S::Future: 'static,
+    {
+        let headers = request.headers();
+        let origin = headers.get(ORIGIN).and_then(|header_value| header_value.to_str().ok());
+        let allowed_origin = origin
+            .filter(|origin| self.is_allowed(origin))
+            .and_then(|origin| HeaderValue::from_str(origin).ok());
+        let is_ws_upgrade = headers
+            .get(UPGRADE)
+            .and_then(|header_value| header_value.to_str().ok())
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+
+        if let (true, Some(origin), None) = (is_ws_upgrade, origin, allowed_origin.as_ref()) {
+            if self.check_ws_origin {
+                let refusal =
+                    HttpResponse::Forbidden().body(format!("Origin {} is not allowed!", origin));
+
+                return ready(Ok(request.into_response(refusal))).boxed_local();
+            }
+        }
+
+        let allowed_origin = match allowed_origin {
+            Some(allowed_origin) => allowed_origin,
+            None => return service.call(request).boxed_local(),
+        };
+
+        if request.method() == Method::OPTIONS
+            && request.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            let preflight = HttpResponse::NoContent()
+                .header(ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin)
+                .header(ACCESS_CONTROL_ALLOW_METHODS, ALLOWED_METHODS)
+                .header(ACCESS_CONTROL_ALLOW_HEADERS, self.allowed_headers.as_str())
+                .header(ACCESS_CONTROL_MAX_AGE, self.max_age.to_string())
+                .header(VARY, "Origin")
+                .finish();
+
+            return ready(Ok(request.into_response(preflight))).boxed_local();
+        }
+
+        service
+            .call(request)
+            .map(move |response| {
+                let mut response = response?;
+                let headers = response.headers_mut();
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin);
+                // Refused connections tell when to come back
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from(RETRY_AFTER));
+                headers.append(VARY, HeaderValue::from_static("Origin"));
+
+                Ok(response)
+            })
+            .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::web::{get, resource};
+    use actix_web::App;
+
+    #[actix_rt::test]
+    async fn test_cors_policy_is_as_expected() {
+        let cors_policy = CorsPolicy::new(
+            vec!["https://lobby.example.com".into()],
+            &["authorization".into(), "content-type".into()],
+            600,
+        )
+        .with_ws_origin_check(true);
+        let mut service = init_service(
+            App::new()
+                .wrap_fn(move |request, service| cors_policy.handle(request, service))
+                .service(resource("/").route(get().to(HttpResponse::Ok))),
+        )
+        .await;
+
+        let response = call_service(
+            &mut service,
+            TestRequest::get().uri("/").header(ORIGIN, "https://lobby.example.com/").to_request(),
+        )
+        .await;
+
+        assert_eq!(
+            response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://lobby.example.com/"
+        );
+        assert_eq!(response.headers().get(VARY).unwrap(), "Origin");
+
+        let preflight = call_service(
+            &mut service,
+            TestRequest::default()
+                .method(Method::OPTIONS)
+                .uri("/")
+                .header(ORIGIN, "https://lobby.example.com")
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(preflight.status().as_u16(), 204);
+        assert_eq!(
+            preflight.headers().get(ACCESS_CONTROL_ALLOW_HEADERS).unwrap(),
+            "authorization, content-type"
+        );
+        assert_eq!(preflight.headers().get(ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+
+        // Other origins get no CORS header, and no upgrade
+        let response = call_service(
+            &mut service,
+            TestRequest::get().uri("/").header(ORIGIN, "https://evil.example.com").to_request(),
+        )
+        .await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let upgrade = call_service(
+            &mut service,
+            TestRequest::get()
+                .uri("/")
+                .header(ORIGIN, "https://evil.example.com")
+                .header(UPGRADE, "websocket")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(upgrade.status().as_u16(), 403);
+
+        // Native clients send no Origin
+        let upgrade = call_service(
+            &mut service,
+            TestRequest::get().uri("/").header(UPGRADE, "websocket").to_request(),
+        )
+        .await;
+
+        assert_eq!(upgrade.status().as_u16(), 200);
+    }
+}
@@ -0,0 +1,180 @@
+//! Cron expressions of the room schedules, the five usual fields in UTC: minute, hour, day of the
+//! month, month and day of the week (0 or 7 being Sunday). Each field is `*`, a value, a range
+//! `a-b` or a list of them separated by commas, any of them followed by a step such as `*/15`.
+//! As with the classic cron, a day matches either day field when both are restricted.
+
+use crate::{anyerror, AnyError, AnyResult};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Next occurrences are looked for that far ahead, e.g. for the 29th of February
+const SEARCH_DAYS: u64 = 366 * 8;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct CronSchedule {
+    minutes: BTreeSet<u32>,
+    hours: BTreeSet<u32>,
+    days: BTreeSet<u32>,
+    months: BTreeSet<u32>,
+    weekdays: BTreeSet<u32>, // Sunday as 0
+    is_any_day: bool,        // Day of the month left as *
+    is_any_weekday: bool,    // Day of the week left as *
+}
+
+fn parse_field(source: &str, min: u32, max: u32) -> AnyResult<BTreeSet<u32>> {
+    let mut values = BTreeSet::new();
+
+    for part in source.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (first.parse()?, last.parse()?),
+            // A single value with a step runs to the end of the field
+            None if part.contains('/') => (range.parse()?, max),
+            None => (range.parse()?, range.parse()?),
+        };
+
+        if step == 0 || first < min || last > max || first > last {
+            return Err(anyerror!("Invalid cron field {}", source));
+        }
+
+        values.extend((first..=last).step_by(step as usize));
+    }
+
+    Ok(values)
+}
+
+// Year, month and day of the days since the epoch, after Howard Hinnant's civil_from_days
+fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+impl CronSchedule {
+    fn matches_day(&self, days: u64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        let weekday = ((days + 4) % 7) as u32; // The epoch was a Thursday
+        let matches_day = self.days.contains(&day);
+        let matches_weekday = self.weekdays.contains(&weekday);
+
+        self.months.contains(&month)
+            && match (self.is_any_day, self.is_any_weekday) {
+                (false, false) => matches_day || matches_weekday,
+                _ => matches_day && matches_weekday,
+            }
+    }
+
+    // First matching minute strictly after the time
+    pub(crate) fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let minutes = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 60 + 1;
+        let first_day = minutes / 1440;
+
+        for days in first_day..first_day + SEARCH_DAYS {
+            if !self.matches_day(days) {
+                continue;
+            }
+
+            let first_minute = if days == first_day { (minutes % 1440) as u32 } else { 0 };
+            let minute_of_day = self.hours.iter().find_map(|hour| {
+                self.minutes
+                    .iter()
+                    .map(|minute| hour * 60 + minute)
+                    .find(|minute_of_day| *minute_of_day >= first_minute)
+            });
+
+            if let Some(minute_of_day) = minute_of_day {
+                let seconds = (days * 1440 + u64::from(minute_of_day)) * 60;
+                return Some(UNIX_EPOCH + Duration::from_secs(seconds));
+            }
+        }
+
+        None
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let fields: Vec<&str> = source.split_whitespace().collect();
+
+        if fields.len() != 5 {
+            return Err(anyerror!("Expected 5 cron fields in {}", source));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+
+        if weekdays.remove(&7) {
+            weekdays.insert(0);
+        }
+
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            is_any_day: fields[2] == "*",
+            is_any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(source: &str) -> SystemTime {
+        humantime::parse_rfc3339(source).unwrap()
+    }
+
+    #[test]
+    fn test_cron_schedule_is_as_expected() {
+        let next_after = |cron: &str, time: &str| {
+            let next = cron.parse::<CronSchedule>().unwrap().next_after(at(time));
+            next.map(|next| humantime::format_rfc3339(next).to_string())
+        };
+
+        assert_eq!(
+            next_after("*/15 * * * *", "2024-05-01T13:07:30Z").unwrap(),
+            "2024-05-01T13:15:00Z"
+        );
+        // Strictly after, Fridays at 18:00
+        assert_eq!(
+            next_after("0 18 * * 5", "2024-05-03T18:00:00Z").unwrap(),
+            "2024-05-10T18:00:00Z"
+        );
+        assert_eq!(
+            next_after("30 9-17/4 1,15 * *", "2024-05-01T14:00:00Z").unwrap(),
+            "2024-05-01T17:30:00Z"
+        );
+        assert_eq!(
+            next_after("0 0 29 2 *", "2024-03-01T00:00:00Z").unwrap(),
+            "2028-02-29T00:00:00Z"
+        );
+        // Either day field when both are set, Sunday as 7
+        assert_eq!(
+            next_after("0 12 13 * 7", "2024-05-06T00:00:00Z").unwrap(),
+            "2024-05-12T12:00:00Z"
+        );
+        assert_eq!(next_after("0 0 31 2 *", "2024-01-01T00:00:00Z"), None);
+
+        for invalid in ["* * * *", "60 * * * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"] {
+            assert!(invalid.parse::<CronSchedule>().is_err());
+        }
+    }
+}
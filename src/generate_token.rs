@@ -0,0 +1,63 @@
+use crate::ws_handlers::SigningKey;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+const LENGTH_AUTH_TOKEN: usize = 32;
+
+/// Generate a random auth token and print the query a client joins with, along with its signing key
+#[derive(StructOpt, Debug)]
+pub(crate) struct GenerateTokenOptions {
+    /// Room the client joins, left out of the query for /client/auto and /client/lobby when unset
+    #[structopt(long)]
+    pub(crate) room: Option<u32>,
+    /// Client ID of the query, random when unset
+    #[structopt(long)]
+    pub(crate) client_id: Option<Uuid>,
+}
+
+fn to_hex(source: &[u8]) -> String {
+    source.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The router does not tie the token to the room or the client, whoever hands it out does
+pub(crate) fn run(options: GenerateTokenOptions) -> String {
+    let auth_token = to_hex(&rand::random::<[u8; LENGTH_AUTH_TOKEN]>());
+    let client_id = options.client_id.unwrap_or_else(Uuid::new_v4);
+    let room_id = options.room.map(|room_id| format!("&room_id={}", room_id)).unwrap_or_default();
+
+    format!(
+        "auth_token: {}\nsigning_key: {}\nquery: client_id={}{}&auth_token={}",
+        auth_token,
+        to_hex(SigningKey::derive(&auth_token).as_bytes()),
+        client_id,
+        room_id,
+        auth_token
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_token_is_as_expected() {
+        let client_id = Uuid::new_v4();
+        let report = run(GenerateTokenOptions { room: Some(3), client_id: Some(client_id) });
+        let lines: Vec<&str> = report.lines().collect();
+        let auth_token = lines[0].strip_prefix("auth_token: ").unwrap();
+
+        assert_eq!(auth_token.len(), LENGTH_AUTH_TOKEN * 2);
+        assert_eq!(
+            lines[1],
+            format!("signing_key: {}", to_hex(SigningKey::derive(auth_token).as_bytes()))
+        );
+        assert_eq!(
+            lines[2],
+            format!("query: client_id={}&room_id=3&auth_token={}", client_id, auth_token)
+        );
+
+        let report = run(GenerateTokenOptions { room: None, client_id: None });
+        assert!(!report.contains("room_id"));
+        assert_ne!(report.lines().next(), Some(lines[0]));
+    }
+}
@@ -0,0 +1,230 @@
+//! gRPC endpoint for backend game servers preferring it over the `/server` websocket, built with
+//! the `grpc` feature. The `Join` call is a bidirectional stream of raw `MessageStream`s, its
+//! request metadata are the query params of a `/server` upgrade, dashes for underscores. Refused
+//! joins end the call with the status matching the refused upgrade. Admitted calls are
+//! `ServerActor`s over a `GrpcTransport`, registered with the router like websocket servers.
+
+mod generated {
+    tonic::include_proto!("game_room");
+}
+
+use crate::proto::{CompressionCodec, FrameFormat, MessageStream, PartyId};
+use crate::ws_handlers::{InterActorMessage, ReleaseServer, ServerActor, ServerTransport};
+use crate::{HttpSharedState, ServerQueryParams};
+use actix::{Actor, Arbiter, AsyncContext, Context, StreamHandler as ReceiveHandler};
+use actix_web::http::StatusCode;
+use actix_web::web::{Data as SharedData, Query as RequestQuery};
+use actix_web_actors::ws::{CloseReason, Message as WsMessage};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use generated::game_room_server::{GameRoom, GameRoomServer};
+use generated::ServerFrame;
+use log::{info, warn};
+use std::io::Result as IOResult;
+use tokio::net::TcpListener;
+use tonic::metadata::MetadataMap;
+use tonic::transport::Server as TonicServer;
+use tonic::{Request, Response, Status, Streaming};
+
+type OutboundFrames = UnboundedReceiver<Result<ServerFrame, Status>>;
+
+// Binds right away so a busy port fails the startup, calls are then served in background
+pub(crate) async fn start(
+    listen_socket: &str,
+    shared_state: SharedData<HttpSharedState>,
+) -> IOResult<()> {
+    let mut tcp_listener = TcpListener::bind(listen_socket).await?;
+    let game_room_service = GameRoomService { shared_state, arbiter: Arbiter::current() };
+    info!("Accepting gRPC game servers on {}", listen_socket);
+
+    actix::spawn(async move {
+        let serving = TonicServer::builder()
+            .add_service(GameRoomServer::new(game_room_service))
+            .serve_with_incoming(tcp_listener.incoming())
+            .await;
+
+        if let Err(error) = serving {
+            warn!("gRPC listener stopped: {}", error);
+        }
+    });
+
+    Ok(())
+}
+
+// Binary frames only, there is no JSON flavour of the stream
+pub(crate) struct GrpcTransport {
+    sender: Option<UnboundedSender<Result<ServerFrame, Status>>>,
+}
+
+impl ServerTransport for GrpcTransport {
+    type Context = Context<ServerActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, _: &mut Self::Context, frame: WsMessage) {
+        if let (Some(sender), WsMessage::Binary(binary)) = (self.sender.as_ref(), frame) {
+            let _ = sender.unbounded_send(Ok(ServerFrame { message_stream: binary.to_vec() }));
+        }
+    }
+
+    fn ping(&mut self, _: &mut Self::Context) {
+        if let Some(sender) = self.sender.as_ref() {
+            let _ = sender.unbounded_send(Ok(ServerFrame { message_stream: Vec::new() }));
+        }
+    }
+
+    // The call ends with the close reason as its status, if any
+    fn close(&mut self, _: &mut Self::Context, reason: Option<CloseReason>) {
+        if let Some(sender) = self.sender.take() {
+            if let Some(description) = reason.and_then(|reason| reason.description) {
+                let _ = sender.unbounded_send(Err(Status::unavailable(description)));
+            }
+        }
+    }
+}
+
+// Empty frames only keep the call alive, they are never answered
+impl ReceiveHandler<Result<ServerFrame, Status>> for ServerActor<GrpcTransport> {
+    fn handle(&mut self, stream_result: Result<ServerFrame, Status>, context: &mut Self::Context) {
+        let _log_span = self.log_span.clone().entered();
+
+        match stream_result {
+            Ok(frame) => {
+                self.update_last_known_activity();
+
+                if frame.message_stream.is_empty() {
+                    return;
+                }
+
+                if let Ok(message_stream) = MessageStream::from_raw(&frame.message_stream) {
+                    self.forward_inbound(message_stream);
+                }
+            }
+            Err(status) => {
+                info!("gRPC server dropped: {}", status);
+                self.close_and_disconnect(context, None);
+            }
+        }
+    }
+
+    // The game server closed its side
+    fn finished(&mut self, context: &mut Self::Context) {
+        self.close_and_disconnect(context, None);
+    }
+}
+
+struct GameRoomService {
+    shared_state: SharedData<HttpSharedState>,
+    arbiter: Arbiter, // Calls are served out of the actix system, actors are started on it
+}
+
+#[tonic::async_trait]
+impl GameRoom for GameRoomService {
+    type JoinStream = OutboundFrames;
+
+    async fn join(
+        &self,
+        request: Request<Streaming<ServerFrame>>,
+    ) -> Result<Response<OutboundFrames>, Status> {
+        let query_params = parse_metadata(request.metadata()).map_err(refusal_status)?;
+        let is_takeover =
+            self.shared_state.admit_server(&query_params).await.map_err(refusal_status)?;
+        let client_id = query_params.client_id;
+        let server_party_id = PartyId::Server(query_params.server_id);
+        let accepted_codecs = query_params
+            .compression
+            .as_deref()
+            .map(CompressionCodec::parse_list)
+            .unwrap_or_default();
+        let router_address = self.shared_state.router_address.clone();
+        let mailbox_capacity = self.shared_state.server_mailbox_capacity;
+        let max_frame_violations = self.shared_state.max_frame_violations;
+        let (sender, receiver) = unbounded();
+        let inbound_frames = request.into_inner();
+
+        let server_address = self.arbiter.exec(move || {
+            ServerActor::create(move |context| {
+                context.add_stream(inbound_frames);
+                ServerActor::new(
+                    server_party_id,
+                    client_id,
+                    router_address,
+                    accepted_codecs,
+                    FrameFormat::Binary,
+                    GrpcTransport { sender: Some(sender) },
+                )
+                .with_mailbox_capacity(mailbox_capacity)
+                .with_max_frame_violations(max_frame_violations)
+            })
+        });
+
+        match server_address.await {
+            Err(_) => {
+                // A failed takeover leaves the joined server in place
+                if !is_takeover && query_params.server_id == 0 {
+                    self.shared_state.router_address.do_send(ReleaseServer);
+                }
+
+                Err(Status::internal("Actix system is gone!"))
+            }
+            Ok(server_address) => {
+                self.shared_state.router_address.do_send(InterActorMessage::ServerConnect(
+                    server_party_id,
+                    server_address.recipient(),
+                ));
+                info!("Server with client id {} just joined over gRPC...", client_id);
+
+                Ok(Response::new(receiver))
+            }
+        }
+    }
+}
+
+// The metadata are the /server query params, e.g. client-id for client_id
+fn parse_metadata(metadata: &MetadataMap) -> Result<ServerQueryParams, (StatusCode, String)> {
+    let query_string = ["client-id", "server-id", "takeover", "compression"]
+        .iter()
+        .filter_map(|key| {
+            let value = metadata.get(*key)?.to_str().ok()?;
+
+            Some(format!("{}={}", key.replace('-', "_"), value))
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    RequestQuery::<ServerQueryParams>::from_query(&query_string)
+        .map(RequestQuery::into_inner)
+        .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))
+}
+
+fn refusal_status((status, description): (StatusCode, String)) -> Status {
+    match status {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(description),
+        StatusCode::FORBIDDEN => Status::permission_denied(description),
+        _ => Status::internal(description),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata_is_as_expected() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("client-id", "00000000-0000-0000-0000-000000000000".parse().unwrap());
+        metadata.insert("server-id", "2".parse().unwrap());
+        metadata.insert("takeover", "true".parse().unwrap());
+        let query_params = parse_metadata(&metadata).unwrap();
+
+        assert_eq!(query_params.client_id, uuid::Uuid::nil());
+        assert_eq!(query_params.server_id, 2);
+        assert!(query_params.takeover);
+        assert_eq!(query_params.compression, None);
+        assert_eq!(
+            parse_metadata(&MetadataMap::new()).map(|_| ()).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Rolling log file written with `--log-file`, next to the console output and in the same format.
+//! The file is rotated once it would outgrow `--log-file-max-bytes` or once the hour or day of
+//! `--log-file-rotation` is over, being renamed to `<path>.<UTC timestamp>`. With
+//! `--log-file-compress` the rotated files are gzipped away from the logging threads. Only the
+//! `--log-file-keep` most recent rotated files are kept, the older ones being deleted.
+
+use crate::{anyerror, AnyError, AnyResult};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::ffi::OsString;
+use std::fs::{read_dir, remove_file, rename, File, OpenOptions};
+use std::io::{copy, Result as IOResult, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const COMPRESSED_EXTENSION: &str = "gz";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    // Index of the current hour or day since the epoch, the file rotates once it changes
+    fn period(&self, time: SystemTime) -> u64 {
+        let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        match self {
+            Self::Never => 0,
+            Self::Hourly => seconds / 3600,
+            Self::Daily => seconds / 86400,
+        }
+    }
+}
+
+impl FromStr for LogRotation {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        match source.to_lowercase().as_str() {
+            "never" => Ok(Self::Never),
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            _ => Err(anyerror!("Unknown LogRotation {}", source)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LogFileConfig {
+    pub(crate) max_bytes: Option<u64>, // Never rotated by size when None
+    pub(crate) rotation: LogRotation,
+    pub(crate) keep: usize,
+    pub(crate) compress: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct RollingLogFile {
+    path: PathBuf,
+    config: LogFileConfig,
+    file: File,
+    length: u64,
+    period: u64,
+}
+
+impl RollingLogFile {
+    // Appends to the file if it already exists
+    pub(crate) fn open(path: &Path, config: LogFileConfig) -> IOResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let length = file.metadata()?.len();
+        let period = config.rotation.period(SystemTime::now());
+
+        Ok(Self { path: path.into(), config, file, length, period })
+    }
+
+    // e.g. game-room.log.2024-05-01T13-00-00.123Z, colons are not allowed in every file system
+    fn rotated_path(&self, time: SystemTime) -> PathBuf {
+        let timestamp = humantime::format_rfc3339_millis(time).to_string().replace(':', "-");
+        let mut rotated_path = OsString::from(self.path.as_os_str());
+        rotated_path.push(".");
+        rotated_path.push(timestamp);
+
+        rotated_path.into()
+    }
+
+    fn rotate(&mut self, time: SystemTime) -> IOResult<()> {
+        let rotated_path = self.rotated_path(time);
+        self.file.flush()?;
+        rename(&self.path, &rotated_path)?;
+        *self = Self::open(&self.path, self.config.clone())?;
+
+        let path = self.path.clone();
+        let keep = self.config.keep;
+
+        if self.config.compress {
+            thread::Builder::new().name("log-file-compress".into()).spawn(move || {
+                if let Err(error) = compress(&rotated_path) {
+                    eprintln!("Failed to compress {}: {}", rotated_path.display(), error);
+                }
+                let _ = remove_expired(&path, keep);
+            })?;
+        } else {
+            remove_expired(&path, keep)?;
+        }
+
+        Ok(())
+    }
+}
+
+// A failed rotation carries on with the current file, errors are written to stderr since they
+// cannot be logged from within the logger
+impl Write for RollingLogFile {
+    fn write(&mut self, buffer: &[u8]) -> IOResult<usize> {
+        let now = SystemTime::now();
+        let period = self.config.rotation.period(now);
+        let is_full = self
+            .config
+            .max_bytes
+            .is_some_and(|max_bytes| self.length + buffer.len() as u64 > max_bytes);
+
+        // An empty file is never rotated
+        if self.length > 0 && (is_full || period != self.period) {
+            if let Err(error) = self.rotate(now) {
+                eprintln!("Failed to rotate {}: {}", self.path.display(), error);
+            }
+        }
+
+        self.period = period;
+
+        let length = self.file.write(buffer)?;
+        self.length += length as u64;
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.file.flush()
+    }
+}
+
+// Replaces the rotated file by <path>.gz
+fn compress(rotated_path: &Path) -> IOResult<()> {
+    let mut compressed_path = OsString::from(rotated_path.as_os_str());
+    compressed_path.push(".");
+    compressed_path.push(COMPRESSED_EXTENSION);
+
+    let mut encoder = GzEncoder::new(File::create(&compressed_path)?, Compression::default());
+    copy(&mut File::open(rotated_path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+
+    remove_file(rotated_path)
+}
+
+// Rotated files sort by their timestamp, compressed or not
+fn remove_expired(path: &Path, keep: usize) -> IOResult<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let mut prefix = path.file_name().unwrap_or_default().to_os_string();
+    prefix.push(".");
+    let prefix = prefix.to_string_lossy().into_owned();
+
+    let mut rotated_paths: Vec<PathBuf> = read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .collect();
+    rotated_paths.sort();
+
+    let expired_count = rotated_paths.len().saturating_sub(keep);
+
+    for rotated_path in &rotated_paths[..expired_count] {
+        remove_file(rotated_path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all};
+    use std::io::Read;
+    use std::time::Duration;
+
+    fn rotated_names(log_dir: &Path) -> Vec<String> {
+        let mut rotated_names: Vec<String> = read_dir(log_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|file_name| file_name != "game-room.log")
+            .collect();
+        rotated_names.sort();
+
+        rotated_names
+    }
+
+    #[test]
+    fn test_rolling_log_file_is_as_expected() {
+        let log_dir = std::env::temp_dir().join(format!("game-room-log-{}", uuid::Uuid::new_v4()));
+        create_dir_all(&log_dir).unwrap();
+        let log_path = log_dir.join("game-room.log");
+        let config = LogFileConfig {
+            max_bytes: Some(16),
+            rotation: LogRotation::Never,
+            keep: 2,
+            compress: false,
+        };
+        let mut log_file = RollingLogFile::open(&log_path, config).unwrap();
+
+        log_file.write_all(b"first line\n").unwrap();
+        assert!(rotated_names(&log_dir).is_empty());
+
+        // Rotated before each line that would outgrow it, the first rotation expiring
+        for line in ["second line\n", "a line longer than the max\n", "last line\n"] {
+            // Rotated files are named after the millisecond
+            std::thread::sleep(Duration::from_millis(2));
+            log_file.write_all(line.as_bytes()).unwrap();
+        }
+
+        let rotated_names = rotated_names(&log_dir);
+        assert_eq!(rotated_names.len(), 2);
+        assert_eq!(read_to_string(log_dir.join(&rotated_names[0])).unwrap(), "second line\n");
+        assert_eq!(read_to_string(&log_path).unwrap(), "last line\n");
+
+        remove_dir_all(log_dir).unwrap();
+    }
+
+    #[test]
+    fn test_compress_is_as_expected() {
+        let log_dir = std::env::temp_dir().join(format!("game-room-log-{}", uuid::Uuid::new_v4()));
+        create_dir_all(&log_dir).unwrap();
+        let rotated_path = log_dir.join("game-room.log.2024-05-01T13-00-00.000Z");
+        std::fs::write(&rotated_path, "rotated line\n").unwrap();
+
+        compress(&rotated_path).unwrap();
+
+        assert!(!rotated_path.exists());
+        let compressed_file = File::open(log_dir.join("game-room.log.2024-05-01T13-00-00.000Z.gz"));
+        let mut content = String::new();
+        GzDecoder::new(compressed_file.unwrap()).read_to_string(&mut content).unwrap();
+        assert_eq!(content, "rotated line\n");
+
+        remove_dir_all(log_dir).unwrap();
+    }
+
+    #[test]
+    fn test_log_rotation_is_as_expected() {
+        let time = UNIX_EPOCH + Duration::from_secs(86400 + 7200 + 5);
+
+        assert_eq!(LogRotation::Never.period(time), 0);
+        assert_eq!(LogRotation::Hourly.period(time), 26);
+        assert_eq!(LogRotation::Daily.period(time), 1);
+        assert_eq!("Hourly".parse::<LogRotation>().unwrap(), LogRotation::Hourly);
+        assert!("weekly".parse::<LogRotation>().is_err());
+    }
+}
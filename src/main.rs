@@ -1,47 +1,213 @@
+mod admin_api;
+mod admin_events;
+mod audit_log;
+mod bench;
+mod cluster;
+mod config_check;
+mod config_reload;
+mod connection_journal;
+mod connection_policy;
+mod cors;
+mod cron;
+mod generate_token;
+#[cfg(feature = "grpc")]
+mod grpc_listener;
+mod log_file;
+mod metrics;
+mod metrics_sink;
+#[cfg(feature = "mqtt")]
+mod mqtt_bridge;
+mod poll_api;
 mod proto;
+#[path = "../client/src/reconnect.rs"]
+mod reconnect;
+mod relay;
+mod replay;
+mod rest_api;
+mod structured_log;
+mod systemd;
+mod tcp_listener;
+mod telemetry;
+mod trusted_proxies;
+mod upgrade;
 mod utils;
+#[cfg(feature = "plugins")]
+mod wasm_plugin;
+#[cfg(feature = "webtransport")]
+mod webtransport_listener;
 mod ws_handlers;
 
-pub(crate) use anyhow::{anyhow as anyerror, Result as AnyResult};
+pub(crate) use anyhow::{anyhow as anyerror, Error as AnyError, Result as AnyResult};
 
-use crate::proto::{PartyId, ALL_CLIENT_ID};
-use crate::ws_handlers::{ClientActor, GameRoomRouterActor, InterActorMessage, ServerActor};
+use crate::audit_log::{AuditAction, AUDIT_LOG};
+use crate::bench::BenchOptions;
+use crate::cluster::{PeerRing, HEADER_PROXIED_BY};
+use crate::config_reload::{ConfigReloader, ReloadableConfig};
+use crate::connection_journal::CONNECTION_JOURNAL;
+use crate::connection_policy::ConnectionPolicy;
+use crate::cors::CorsPolicy;
+use crate::generate_token::GenerateTokenOptions;
+use crate::log_file::{LogFileConfig, LogRotation, RollingLogFile};
+use crate::metrics::METRICS;
+use crate::metrics_sink::MetricsSink;
+use crate::proto::{
+    CompressionCodec, FrameFormat, InfoCode, PartyId, PayloadKind, ALL_SERVER_ID, LOBBY_ROOM_ID,
+    OFFSET_SERVER_ID,
+};
+use crate::relay::{RelayActor, RelayListenerActor, RelaySequences};
+use crate::replay::ReplayOptions;
+use crate::systemd::InheritedListeners;
+use crate::telemetry::TELEMETRY;
+use crate::trusted_proxies::{IpNetwork, TrustedProxies};
+use crate::upgrade::Drain;
+use crate::ws_handlers::{
+    jittered_retry_after, ws_start, ChaosRule, ClaimServer, ClaimSlot, ClientActor,
+    ConnectionLimits, ConnectionPermit, DeniedPayloadKind, GameRoomRouterActor,
+    GameRoomRouterConfig, GetPresence, GetRoomOccupancy, GetServerJoined, HandshakeLimiter,
+    InterActorMessage, MemoryBudget, MessageMiddleware, PartyRecipient, PeerProxyActor, PickRoom,
+    PollSessions, ReleaseServer, RoomBalancing, RoomClient, ServerActor, ShedPolicy, SigningKey,
+    SlotRefusal, TrafficRecorder, UdpRelay, WsTransport, CONNECTION_RETRY_AFTER,
+};
 use actix::clock::Duration;
 use actix::{Actor, Addr as ActorAddress};
+use actix_web::http::header::{ORIGIN, RETRY_AFTER, USER_AGENT};
+use actix_web::http::StatusCode;
 use actix_web::middleware::Logger as ActixLogger;
 use actix_web::web::{
-    get, resource, route, Bytes, Data as SharedData, Payload, PayloadConfig, Query as RequestQuery,
+    get, resource, route, scope, Bytes, Data as SharedData, Path as RequestPath, Payload,
+    PayloadConfig, Query as RequestQuery, ServiceConfig,
 };
 use actix_web::{
-    get, main as actix_main, App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder,
+    get, main as actix_main, App, Error as ActixError, FromRequest, HttpRequest, HttpResponse,
+    HttpServer, Responder,
 };
-use actix_web_actors::ws::start_with_addr as ws_start;
+use awc::error::WsClientError;
+use awc::Client;
 use log::info;
 use serde::Deserialize;
 use serde_json::to_string_pretty as to_json_pretty;
-use std::io::Result as IOResult;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::env;
+use std::io::{Error as IOError, Result as IOResult};
+use std::net::{IpAddr, TcpListener as StdTcpListener};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
 use structopt::StructOpt;
-use utils::init_logger;
+use utils::{init_logger, initial_log_filters, reload_log_filters, LogFormat};
 use uuid::Uuid;
 
-pub(crate) const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
-
 #[derive(Deserialize)]
 struct ServerQueryParams {
     client_id: Uuid,
+    compression: Option<String>,
+    #[serde(default)]
+    format: FrameFormat,
+    #[serde(default)]
+    takeover: bool, // Replace the joined server instead of being refused, for zero-downtime deploys
+    #[serde(default)]
+    server_id: u32, // Shard servers other than the primary 0 serve the rooms they claim
+    #[serde(default)]
+    app_heartbeat: bool, // Heartbeat frames instead of websocket pings, stripped by some proxies
+}
+
+#[derive(Deserialize)]
+struct RelayQueryParams {
+    node_url: String, // As given to the peer with --node-url
+    epoch: Uuid,      // New for every start of the peer
+}
+
+// Client joins whose room is not picked by the client itself
+#[derive(Deserialize)]
+struct RoomlessClientQueryParams {
+    client_id: Uuid,
+    compression: Option<String>,
+    #[serde(default)]
+    format: FrameFormat,
+    metadata: Option<String>,
+    metadata_hex: Option<String>,
+    auth_token: Option<String>,
+    #[serde(default)]
+    app_heartbeat: bool,
 }
 
 #[derive(Deserialize)]
 struct ClientQueryParams {
     client_id: Uuid,
-    room_id: u8,
+    room_id: u32,
+    compression: Option<String>,
+    #[serde(default)]
+    format: FrameFormat,
+    metadata: Option<String>, // Text such as JSON, sent as its UTF-8 bytes
+    metadata_hex: Option<String>, // Raw bytes, hex encoded
+    auth_token: Option<String>, // Frames must be signed with the key derived from it when set
+    #[serde(default)]
+    app_heartbeat: bool, // Heartbeat frames instead of websocket pings, stripped by some proxies
+}
+
+impl ClientQueryParams {
+    // Empty when the client gave none, both forms at once are refused
+    fn parse_metadata(&self, max_metadata_length: usize) -> AnyResult<Arc<[u8]>> {
+        let metadata = match (self.metadata.as_deref(), self.metadata_hex.as_deref()) {
+            (Some(_), Some(_)) => {
+                return Err(anyerror!("Expected metadata or metadata_hex, not both"))
+            }
+            (Some(metadata), None) => metadata.as_bytes().to_vec(),
+            (None, Some(metadata_hex)) if metadata_hex.len() % 2 != 0 => {
+                return Err(anyerror!("Expected an even number of hex digits in metadata_hex"))
+            }
+            (None, Some(metadata_hex)) => (0..metadata_hex.len())
+                .step_by(2)
+                .map(|index| u8::from_str_radix(&metadata_hex[index..index + 2], 16))
+                .collect::<Result<_, _>>()?,
+            (None, None) => Vec::new(),
+        };
+
+        if metadata.len() > max_metadata_length {
+            return Err(anyerror!(
+                "Metadata of {} bytes exceeds the {} bytes limit",
+                metadata.len(),
+                max_metadata_length
+            ));
+        }
+
+        Ok(metadata.into())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PayloadLengthLimit {
+    payload_kind: PayloadKind,
+    max_payload_length: usize,
+}
+
+impl FromStr for PayloadLengthLimit {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let mut source_split = source.splitn(2, '=');
+
+        match (source_split.next(), source_split.next()) {
+            (Some(payload_kind), Some(max_payload_length)) => Ok(Self {
+                payload_kind: payload_kind.trim().parse()?,
+                max_payload_length: max_payload_length.trim().parse()?,
+            }),
+            _ => Err(anyerror!("Expected <payload-kind>=<bytes>, got {}", source)),
+        }
+    }
 }
 
 /// PoC - Game Room Router
 #[derive(StructOpt, Debug)]
 #[structopt(name = "game-room")]
+pub(crate) struct GameRoomCli {
+    // Without a subcommand the server runs with these, as before the subcommands
+    #[structopt(flatten)]
+    pub(crate) options: GameRoomOptions,
+    #[structopt(subcommand)]
+    pub(crate) command: Option<GameRoomCommand>,
+}
+
+#[derive(StructOpt, Debug)]
 pub(crate) struct GameRoomOptions {
     // Debug Mode to enable INFO message
     #[structopt(short, long)]
@@ -52,27 +218,650 @@ pub(crate) struct GameRoomOptions {
     /// Set listening port
     #[structopt(short, long, default_value = "7575")]
     pub(crate) listen_port: u16,
+    /// Serve every HTTP and WebSocket route under this path, e.g. /gameroom behind a gateway
+    #[structopt(long, default_value = "/", parse(from_str = parse_base_path))]
+    pub(crate) base_path: String,
+    /// Also accept clients over raw TCP with length prefixed frames on this port
+    #[structopt(long)]
+    pub(crate) tcp_port: Option<u16>,
+    /// Also accept MessageStream datagrams of the connected clients on this UDP port
+    #[structopt(long)]
+    pub(crate) udp_port: Option<u16>,
+    /// Send Reliable frames over UDP as well, acknowledged and resent, not only Unreliable ones
+    #[structopt(long)]
+    pub(crate) udp_reliable: bool,
+    /// Also accept game servers over a gRPC bidirectional stream on this port
+    #[cfg(feature = "grpc")]
+    #[structopt(long)]
+    pub(crate) grpc_port: Option<u16>,
+    /// Bridge the rooms to MQTT topics on this broker as <host>[:<port>], for constrained devices
+    #[cfg(feature = "mqtt")]
+    #[structopt(long)]
+    pub(crate) mqtt_broker: Option<String>,
+    /// Also accept WebTransport sessions over HTTP/3 on this UDP port, Unreliable frames going as
+    /// datagrams
+    #[cfg(feature = "webtransport")]
+    #[structopt(long)]
+    pub(crate) webtransport_port: Option<u16>,
+    /// PEM certificate chain presented by the WebTransport listener
+    #[cfg(feature = "webtransport")]
+    #[structopt(long)]
+    pub(crate) webtransport_cert: Option<PathBuf>,
+    /// PEM private key of the WebTransport certificate
+    #[cfg(feature = "webtransport")]
+    #[structopt(long)]
+    pub(crate) webtransport_key: Option<PathBuf>,
+    /// Run the on_message, on_join and on_leave hooks exported by this WASM module
+    #[cfg(feature = "plugins")]
+    #[structopt(long)]
+    pub(crate) plugin: Option<PathBuf>,
+    /// Fuel a plugin hook may spend per call before it is stopped
+    #[cfg(feature = "plugins")]
+    #[structopt(long, default_value = "1000000")]
+    pub(crate) plugin_fuel: u64,
+    /// Coalesce outbound messages per destination and flush them at this rate in Hz (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) batch_tick_rate: u32,
+    /// Set the maximum payload length of a payload kind as <payload-kind>=<bytes> (repeatable),
+    /// payload kinds without one are not limited
+    #[structopt(long = "max-payload-length", number_of_values = 1)]
+    pub(crate) max_payload_lengths: Vec<PayloadLengthLimit>,
+    /// Drop the frames of a payload kind sent by a role as <client|server>:<payload-kind>, e.g.
+    /// client:command, the sender is replied an error and the server told (repeatable)
+    #[structopt(long = "deny-payload-kind", number_of_values = 1)]
+    pub(crate) denied_payload_kinds: Vec<DeniedPayloadKind>,
+    /// Drop, delay or reorder a fraction of the Normal frames of a room to test clients against a
+    /// bad network, as [<room-id>:]drop=<fraction>,delay=<fraction>,reorder=<fraction>,
+    /// max-delay=<millis>, every room without its own when the room is left out (repeatable)
+    #[structopt(long = "chaos", number_of_values = 1)]
+    pub(crate) chaos_rules: Vec<ChaosRule>,
+    /// Refuse client upgrades whose metadata is longer than this many bytes
+    #[structopt(long, default_value = "1024")]
+    pub(crate) max_metadata_length: usize,
+    /// Forget a room once it has been left without clients for this many seconds (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) empty_room_ttl: u64,
+    /// Keep the slot of a dropped client for its UUID to rejoin for this many seconds (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) slot_reservation_ttl: u64,
+    /// Keep the rooms and clients for the server to rejoin for this many seconds (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) server_reconnect_grace: u64,
+    /// Warn then kick the clients sending no Normal frame to a room for this many seconds (0
+    /// disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) idle_timeout: u64,
+    /// Kick warned idle clients after this many more seconds without a Normal frame
+    #[structopt(long, default_value = "30")]
+    pub(crate) idle_grace: u64,
+    /// Refuse joins once a room holds this many clients and reserved slots (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) max_room_clients: usize,
+    /// Refuse client connections with a 429 once this many are open (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) max_connections: usize,
+    /// Refuse client connections with a 429 once this many are open from one IP (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) max_connections_per_ip: usize,
+    /// Refuse client handshakes with a 429 beyond this many per second, bursts of one second
+    /// allowed (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) handshake_rate: f64,
+    /// Refuse client handshakes with a 429 beyond this many per second from one IP, bursts of one
+    /// second allowed (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) handshake_rate_per_ip: f64,
+    /// Believe the Forwarded or X-Forwarded-For header of the requests from this proxy about the
+    /// client IP, as an IP or a CIDR block, e.g. 10.0.0.0/8 (repeatable)
+    #[structopt(long = "trusted-proxy", number_of_values = 1)]
+    pub(crate) trusted_proxies: Vec<IpNetwork>,
+    /// Answer the CORS headers to the browser frontends of this origin, e.g.
+    /// https://lobby.example.com, or * for any origin (repeatable)
+    #[structopt(long = "cors-origin", number_of_values = 1)]
+    pub(crate) cors_origins: Vec<String>,
+    /// Comma separated request headers the CORS preflights allow
+    #[structopt(long, default_value = "authorization,content-type", use_delimiter = true)]
+    pub(crate) cors_allow_headers: Vec<String>,
+    /// Let the browsers cache a CORS preflight for this many seconds
+    #[structopt(long, default_value = "3600")]
+    pub(crate) cors_max_age: u64,
+    /// Refuse WebSocket upgrades with a 403 when their Origin header is not a CORS origin
+    #[structopt(long)]
+    pub(crate) ws_check_origin: bool,
+    /// Require the Origin of the browser clients, block user agents and ban IPs as set in this JSON
+    /// file
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) connection_policy: Option<PathBuf>,
+    /// Override the reloadable options with this JSON file, read again with the connection policy
+    /// on SIGHUP or POST /admin/reload
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) config: Option<PathBuf>,
+    /// Expect a HAProxy PROXY header (v1 or v2) from a trusted proxy on every raw TCP connection
+    #[structopt(long)]
+    pub(crate) proxy_protocol: bool,
+    /// Evict clients leaving frames unread for more than this many seconds (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) slow_client_lag: u64,
+    /// Close connections after this many malformed frames, each told with a MalformedFrame error
+    /// (0 never closes them)
+    #[structopt(long, default_value = "10")]
+    pub(crate) max_frame_violations: u32,
+    /// Close WebSocket connections sending a message fragmented over continuation frames longer
+    /// than this many bytes once reassembled, the longest frame by default
+    #[structopt(long, default_value = "65811")]
+    pub(crate) max_message_length: usize,
+    /// Shed buffered frames once they hold more than this many bytes over every room (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) memory_budget: usize,
+    /// Shed buffered frames once they hold more than this many bytes in one room (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) room_memory_budget: usize,
+    /// Comma separated steps shedding buffered frames over a memory budget, in order, out of
+    /// batches (flush the outbound batches early), bulk and normal (drop frames of the priority)
+    #[structopt(long, default_value = "batches,bulk")]
+    pub(crate) memory_shed_policy: ShedPolicy,
+    /// Fan the client deliveries out on this many worker threads, rooms sharded across them by
+    /// ID, for very high message rates on multi-core machines (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) routing_workers: usize,
+    /// Queue up to this many messages in the mailbox of the router, messages past it are still
+    /// delivered but count as an overflow
+    #[structopt(long, default_value = "256")]
+    pub(crate) router_mailbox_capacity: usize,
+    /// Queue up to this many messages in the mailbox of a server connection, the router warns
+    /// about and counts the frames delivered past it
+    #[structopt(long, default_value = "256")]
+    pub(crate) server_mailbox_capacity: usize,
+    /// Queue up to this many messages in the mailbox of a client connection, the server is sent a
+    /// MailboxOverflow info once frames are delivered past it
+    #[structopt(long, default_value = "256")]
+    pub(crate) client_mailbox_capacity: usize,
+    /// Drop frames whose Sequence option was already seen among this many last sequences of
+    /// their origin, for transports resending frames (0 disables)
+    #[structopt(long, default_value = "0")]
+    pub(crate) dedup_window: u32,
+    /// Drop the cached snapshot of a room once its server sent this many broadcasts since, late
+    /// joiners getting none until the next snapshot (0 keeps no deltas)
+    #[structopt(long, default_value = "1024")]
+    pub(crate) snapshot_max_deltas: usize,
+    /// Bytes of blob chunks a sender may have in flight to a party past its last ack, chunks
+    /// beyond it are refused with a BlobWindowExceeded error (0 disables)
+    #[structopt(long, default_value = "1048576")]
+    pub(crate) blob_window: u32,
+    /// Streams a party may have open to single parties of a room, opens beyond are refused with
+    /// a StreamRefused error (0 lifts the limit)
+    #[structopt(long, default_value = "64")]
+    pub(crate) max_streams: usize,
+    /// Route server frames to a client missing from their room to the room it is in, replying a
+    /// PartyUnlocated error when no room the server controls holds it
+    #[structopt(long)]
+    pub(crate) cross_room_routing: bool,
+    /// Pick the server of created rooms and auto joins, least-loaded, round-robin or weighted
+    #[structopt(long, default_value = "least-loaded")]
+    pub(crate) room_balancing: RoomBalancing,
+    /// URL the peers reach this instance at, e.g. ws://10.0.0.1:7575, required with --peers
+    #[structopt(long)]
+    pub(crate) node_url: Option<String>,
+    /// Comma separated URLs of the static cluster, rooms are owned by consistent hash of their ID
+    #[structopt(long, use_delimiter = true)]
+    pub(crate) peers: Vec<String>,
+    /// Negotiate the permessage-deflate WebSocket extension when offered by the peer
+    #[structopt(long)]
+    pub(crate) permessage_deflate: bool,
+    /// Log line format, plain or json (one object per line with connection context fields)
+    #[structopt(long, default_value = "plain")]
+    pub(crate) log_format: LogFormat,
+    /// Also write the log lines to this file, rotated and kept as the --log-file-* options say
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) log_file: Option<PathBuf>,
+    /// Rotate the log file once it would grow past this many bytes, 0 never rotates it by size
+    #[structopt(long, default_value = "104857600")]
+    pub(crate) log_file_max_bytes: u64,
+    /// Rotate the log file every hour or day (UTC), hourly, daily or never
+    #[structopt(long, default_value = "daily")]
+    pub(crate) log_file_rotation: LogRotation,
+    /// Number of rotated log files kept, the older ones are deleted
+    #[structopt(long, default_value = "7")]
+    pub(crate) log_file_keep: usize,
+    /// Gzip the rotated log files
+    #[structopt(long)]
+    pub(crate) log_file_compress: bool,
+    /// Instance ID carried by every JSON log line, random when unset
+    #[structopt(long)]
+    pub(crate) instance_id: Option<Uuid>,
+    /// Also push the metrics to statsd://host[:port] or dogstatsd://host[:port]
+    #[structopt(long)]
+    pub(crate) metrics_sink: Option<MetricsSink>,
+    /// Export per-hop trace spans to this OTLP/HTTP collector, e.g. http://127.0.0.1:4318
+    #[structopt(long)]
+    pub(crate) otlp_endpoint: Option<String>,
+    /// Fraction of the inbound frames traced when exporting spans
+    #[structopt(long, default_value = "1.0")]
+    pub(crate) otlp_sample_ratio: f64,
+    /// Token expected by the admin endpoints as a bearer token, or as ?token= by the admin events
+    /// WebSocket, admin is off when unset
+    #[structopt(long)]
+    pub(crate) admin_token: Option<String>,
+    /// Append every frame entering the router to this file, see the replay subcommand
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) record_traffic: Option<PathBuf>,
+    /// Append every kick, drain, room close and auth failure to this JSON lines file
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) audit_log: Option<PathBuf>,
+    /// Rotate the audit log once it would grow past this many bytes, 0 never rotates it
+    #[structopt(long, default_value = "10485760")]
+    pub(crate) audit_log_max_bytes: u64,
+    /// Hand the listeners over on this Unix socket to a new process started with --upgrade, then
+    /// drain the client connections and exit
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) upgrade_socket: Option<PathBuf>,
+    /// Take the listeners over from the process serving --upgrade-socket instead of binding them
+    #[structopt(long)]
+    pub(crate) upgrade: bool,
+    /// Wait up to this many seconds for the client connections to close once handed over
+    #[structopt(long, default_value = "300")]
+    pub(crate) upgrade_drain_timeout: u64,
+    /// Journal the client connections to this SQLite database, queried with /admin/history
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) journal: Option<PathBuf>,
+}
+
+impl GameRoomOptions {
+    // Options needing one another, checked before anything is bound or opened
+    pub(crate) fn validate(&self) -> IOResult<()> {
+        if self.upgrade && self.upgrade_socket.is_none() {
+            return Err(IOError::other("--upgrade needs --upgrade-socket"));
+        }
+
+        if !self.peers.is_empty() && self.node_url.is_none() {
+            return Err(IOError::other("--peers needs --node-url"));
+        }
+
+        if self.proxy_protocol && self.trusted_proxies.is_empty() {
+            return Err(IOError::other("--proxy-protocol needs --trusted-proxy"));
+        }
+
+        if self.max_message_length == 0 {
+            return Err(IOError::other("--max-message-length must be greater than 0"));
+        }
+
+        if self.ws_check_origin && self.cors_origins.is_empty() {
+            return Err(IOError::other("--ws-check-origin needs --cors-origin"));
+        }
+
+        #[cfg(feature = "webtransport")]
+        if self.webtransport_port.is_some()
+            && (self.webtransport_cert.is_none() || self.webtransport_key.is_none())
+        {
+            return Err(IOError::other(
+                "--webtransport-port needs --webtransport-cert and --webtransport-key",
+            ));
+        }
+
+        Ok(())
+    }
+
+    // The reloadable options are overridden by the --config file
+    pub(crate) fn config_reloader(&self) -> ConfigReloader {
+        ConfigReloader::new(
+            self.config.clone(),
+            self.connection_policy.clone(),
+            ReloadableConfig {
+                handshake_rate: Some(self.handshake_rate),
+                handshake_rate_per_ip: Some(self.handshake_rate_per_ip),
+                max_connections: Some(self.max_connections),
+                max_connections_per_ip: Some(self.max_connections_per_ip),
+                max_room_clients: Some(self.max_room_clients),
+                log_filters: Some(initial_log_filters()),
+                rooms: None,
+                room_templates: None,
+                provisioned_rooms: None,
+            },
+        )
+    }
 }
 
+// Either empty or a leading slash without a trailing one, ready to prefix the routes
+fn parse_base_path(source: &str) -> String {
+    match source.trim().trim_matches('/') {
+        "" => String::new(),
+        base_path => format!("/{}", base_path),
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub(crate) enum GameRoomCommand {
+    /// Run the router, the same as without a subcommand
+    Run(Box<GameRoomOptions>),
+    /// Validate the options and the files they name, then print the effective config as JSON
+    Check(Box<GameRoomOptions>),
+    GenerateToken(GenerateTokenOptions),
+    /// Print a random UUID, e.g. for --server-uuid or a client ID
+    GenerateUuid,
+    Bench(BenchOptions),
+    Replay(ReplayOptions),
+}
+
+impl GameRoomCommand {
+    // Options given ahead of run or check would be ignored, they only go after it
+    fn check_leading(&self) -> IOResult<()> {
+        let subcommand = match self {
+            Self::Run(_) => "run",
+            Self::Check(_) => "check",
+            _ => return Ok(()),
+        };
+
+        if env::args().nth(1).as_deref() != Some(subcommand) {
+            return Err(IOError::other(format!("Options go after the {} subcommand", subcommand)));
+        }
+
+        Ok(())
+    }
+}
+
+// The router answers its queries right away, one busy for longer refuses the request instead
+const ROUTER_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub(crate) struct HttpSharedState {
-    server_joined: Arc<AtomicBool>,
-    client_counter: Mutex<Vec<u32>>,
     acceptable_server_uuid: Uuid,
-    available_rooms: Arc<Mutex<Vec<u8>>>,
+    permessage_deflate: bool,
+    max_metadata_length: usize,
+    admin_token: Option<String>,
     router_address: ActorAddress<GameRoomRouterActor>,
+    peer_ring: Option<PeerRing>, // Set in static cluster mode
+    relay_sequences: RelaySequences,
+    udp_relay: Option<UdpRelay>, // Set with --udp-port, every client connection gets a session
+    slow_client_lag: Option<Duration>, // Set with --slow-client-lag, for every client connection
+    max_frame_violations: Option<u32>, // Set with --max-frame-violations, for every connection
+    max_message_length: usize,   // Set with --max-message-length, for every WebSocket
+    server_mailbox_capacity: usize,
+    client_mailbox_capacity: usize,
+    poll_sessions: PollSessions,
+    connection_limits: ConnectionLimits,
+    handshake_limiter: HandshakeLimiter,
+    trusted_proxies: TrustedProxies,
+    base_path: String, // Set with --base-path, empty otherwise
+    connection_policy: RwLock<ConnectionPolicy>, // Set with --connection-policy, allowing any otherwise
+    config_reloader: ConfigReloader,
+    #[cfg(feature = "webtransport")]
+    webtransport_certificate: Option<webtransport_listener::WebTransportCertificate>, // Set with --webtransport-port
+}
+
+impl HttpSharedState {
+    // Upgrades a peer already proxied are served here whatever the ring says, ending any loop
+    fn remote_owner_url(&self, room_id: u32, request: &HttpRequest) -> Option<String> {
+        let room_owner = self.peer_ring.as_ref()?.room_owner(room_id);
+
+        if room_owner.is_local || request.headers().contains_key(HEADER_PROXIED_BY) {
+            return None;
+        }
+
+        Some(room_owner.owner_url)
+    }
+
+    // Claims the primary server with the router, refusals are the status of a refused upgrade.
+    // Answers whether the joined server is being taken over
+    async fn admit_server(
+        &self,
+        query_params: &ServerQueryParams,
+    ) -> Result<bool, (StatusCode, String)> {
+        let is_shard = query_params.server_id != 0;
+
+        // Server IDs stop short of the AllServers party IDs
+        if query_params.server_id >= ALL_SERVER_ID - OFFSET_SERVER_ID {
+            return Err((StatusCode::BAD_REQUEST, "Invalid server_id!".into()));
+        }
+
+        if query_params.client_id != self.acceptable_server_uuid {
+            AUDIT_LOG.record(
+                &format!("server {}", query_params.server_id),
+                AuditAction::AuthFailure {
+                    endpoint: "/server".into(),
+                    reason: "Invalid server client_id".into(),
+                },
+            );
+            return Err((StatusCode::FORBIDDEN, "Invalid server client_id!".into()));
+        }
+
+        // Shard servers come and go without the clients noticing
+        if is_shard {
+            return Ok(false);
+        }
+
+        // Deny if already a server in this instance
+        match self
+            .router_address
+            .send(ClaimServer(query_params.takeover))
+            .timeout(ROUTER_QUERY_TIMEOUT)
+            .await
+        {
+            Err(error) => Err((StatusCode::INTERNAL_SERVER_ERROR, error.to_string())),
+            Ok(None) => {
+                Err((StatusCode::FORBIDDEN, "Server already joined in this instance!".into()))
+            }
+            Ok(Some(is_takeover)) => Ok(is_takeover),
+        }
+    }
+
+    // A router not answering in time counts as no server
+    async fn is_server_joined(&self) -> bool {
+        self.router_address
+            .send(GetServerJoined)
+            .timeout(ROUTER_QUERY_TIMEOUT)
+            .await
+            .unwrap_or(false)
+    }
+
+    // Joins not going through a websocket upgrade are not proxied, the client is told where to go
+    async fn check_direct_join(&self, room_id: u32) -> Result<(), (StatusCode, String)> {
+        if !self.is_server_joined().await {
+            return Err((StatusCode::FORBIDDEN, "Server has not joined yet!".into()));
+        }
+
+        if let Some(peer_ring) = self.peer_ring.as_ref() {
+            let room_owner = peer_ring.room_owner(room_id);
+
+            if !room_owner.is_local {
+                return Err((
+                    StatusCode::MISDIRECTED_REQUEST,
+                    format!(
+                        "Room {} is owned by {}!",
+                        room_owner.room_id,
+                        self.public_url(&room_owner.owner_url)
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // URL a client reaches a peer at, the peers are expected to share the base path
+    fn public_url(&self, node_url: &str) -> String {
+        format!("{}{}", node_url, self.base_path)
+    }
+
+    // Checked before the connection limits, refusals are the reason of a 403
+    fn check_connection_policy(&self, request: &HttpRequest) -> Result<(), String> {
+        let headers = request.headers();
+        let header_str = |header_name| {
+            headers.get(header_name).and_then(|header_value| header_value.to_str().ok())
+        };
+
+        if let Some(client_ip) = self.client_ip(request) {
+            self.check_banned(client_ip)?;
+        }
+
+        self.connection_policy
+            .read()
+            .map_err(|_| "Memory poisoning detected!".to_string())?
+            .check(header_str(ORIGIN), header_str(USER_AGENT))
+    }
+
+    pub(crate) fn check_banned(&self, client_ip: IpAddr) -> Result<(), String> {
+        self.connection_policy
+            .read()
+            .map_err(|_| "Memory poisoning detected!".to_string())?
+            .check_ip(client_ip)
+    }
+
+    // IP of the client behind the trusted proxies, or of the peer when it is not one of them
+    pub(crate) fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let peer_ip = request.peer_addr()?.ip();
+
+        Some(self.trusted_proxies.client_ip(peer_ip, request.headers()))
+    }
+
+    // Refused before any actor is created, the permit is then held by the client connection.
+    // Refusals are the reason and the Retry-After seconds
+    fn acquire_connection(
+        &self,
+        client_ip: Option<IpAddr>,
+    ) -> Result<ConnectionPermit, (String, u32)> {
+        let connection_permit = self
+            .connection_limits
+            .acquire(client_ip)
+            .map_err(|description| (description, jittered_retry_after(CONNECTION_RETRY_AFTER)))?;
+
+        self.handshake_limiter
+            .admit(client_ip)
+            .map_err(|(description, wait)| (description, jittered_retry_after(wait)))?;
+
+        Ok(connection_permit)
+    }
+
+    // Checks the room and claims a party ID, refusals are the status of a refused upgrade
+    async fn admit_client(
+        &self,
+        query_params: &ClientQueryParams,
+    ) -> Result<(PartyId, Arc<[u8]>), (StatusCode, String)> {
+        let room_id = query_params.room_id;
+        let metadata = query_params
+            .parse_metadata(self.max_metadata_length)
+            .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))?;
+
+        // The router hands back the reserved slot of a rejoining client before any other party ID
+        match self
+            .router_address
+            .send(ClaimSlot(room_id, query_params.client_id))
+            .timeout(ROUTER_QUERY_TIMEOUT)
+            .await
+        {
+            Err(error) => Err((StatusCode::INTERNAL_SERVER_ERROR, error.to_string())),
+            Ok(Err(SlotRefusal::NoRoom)) => {
+                Err((StatusCode::FORBIDDEN, format!("No room {}!", room_id)))
+            }
+            Ok(Err(SlotRefusal::RoomFull)) => {
+                Err((StatusCode::SERVICE_UNAVAILABLE, format!("Room {} is full!", room_id)))
+            }
+            Ok(Err(SlotRefusal::PartyIdsExhausted)) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Server needs to rejoin for room {} is exhausted!", room_id),
+            )),
+            Ok(Ok(client_party_id)) => Ok((PartyId::Client(client_party_id), metadata)),
+        }
+    }
+
+    // Clients not picking their room are told their room and party IDs with a RoomJoined info
+    fn register_client(
+        &self,
+        query_params: &ClientQueryParams,
+        party_id: PartyId,
+        metadata: Arc<[u8]>,
+        client_address: PartyRecipient,
+        is_room_picked: bool,
+    ) {
+        let client_id = query_params.client_id;
+        let room_id = query_params.room_id;
+        self.router_address.do_send(InterActorMessage::ClientConnect(
+            room_id,
+            party_id,
+            RoomClient::new(client_id, client_address.clone(), metadata)
+                .with_signing_key(query_params.auth_token.as_deref().map(SigningKey::derive)),
+        ));
+
+        if is_room_picked {
+            let _ = client_address.do_send(InterActorMessage::NewMessage(
+                PartyId::Server(0),
+                GameRoomRouterActor::membership_info(
+                    InfoCode::RoomJoined,
+                    room_id,
+                    party_id.get_repr(),
+                ),
+                None,
+            ));
+        }
+
+        info!("Client with client id {} just joined to room {}...", client_id, room_id);
+    }
 }
 
-#[get("/")]
+// Available rooms along with their fullness, for lobby UIs to grey out the full ones
 async fn get_available_rooms(shared_state: SharedData<HttpSharedState>) -> impl Responder {
-    match shared_state.available_rooms.lock() {
-        Err(_) => HttpResponse::InternalServerError().body("Memory poisoning detected!").await,
-        Ok(read_guard) => {
-            let available_rooms_clone = (*read_guard).clone();
-            HttpResponse::Ok().body(to_json_pretty(&available_rooms_clone).unwrap()).await
+    match shared_state.router_address.send(GetRoomOccupancy).timeout(ROUTER_QUERY_TIMEOUT).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok(room_occupancy) => {
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(to_json_pretty(&room_occupancy).unwrap())
+                .await
         }
     }
 }
 
+#[get("/metrics")]
+async fn get_metrics() -> impl Responder {
+    HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(METRICS.render()).await
+}
+
+// Members of a room as known by the router, for lobby UIs not mirroring membership themselves
+#[get("/rooms/{room_id}/presence")]
+async fn get_room_presence(
+    path_params: RequestPath<u32>,
+    shared_state: SharedData<HttpSharedState>,
+) -> impl Responder {
+    let room_id = path_params.into_inner();
+
+    match shared_state.router_address.send(GetPresence(room_id)).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok(None) => HttpResponse::NotFound().body(format!("No room {}!", room_id)).await,
+        Ok(Some(room_members)) => HttpResponse::Ok().json(room_members).await,
+    }
+}
+
+// Peer owning a room in static cluster mode, for servers to only announce the rooms of their node
+#[get("/rooms/{room_id}/owner")]
+async fn get_room_owner(
+    path_params: RequestPath<u32>,
+    shared_state: SharedData<HttpSharedState>,
+) -> impl Responder {
+    match shared_state.peer_ring.as_ref() {
+        None => HttpResponse::NotFound().body("Cluster mode is off!").await,
+        Some(peer_ring) => {
+            let mut room_owner = peer_ring.room_owner(path_params.into_inner());
+            room_owner.owner_url = shared_state.public_url(&room_owner.owner_url);
+
+            HttpResponse::Ok().json(room_owner).await
+        }
+    }
+}
+
+// Every HTTP endpoint, served under /v1 and at its legacy path
+fn configure_routes(config: &mut ServiceConfig) {
+    config
+        .service(resource("/").route(get().to(get_available_rooms)))
+        .service(get_metrics)
+        .service(get_room_presence)
+        .service(get_room_owner)
+        .service(resource("/server").route(get().to(ws_server_upgrade)))
+        .service(resource("/relay").route(get().to(ws_relay_upgrade)))
+        .service(resource("/client").route(get().to(ws_client_upgrade)))
+        .service(resource("/client/auto").route(get().to(ws_client_auto_upgrade)))
+        .service(resource("/client/lobby").route(get().to(ws_client_lobby_upgrade)));
+    admin_api::configure(config);
+    poll_api::configure(config);
+}
+
 async fn reject_unmapped_handler() -> impl Responder {
     HttpResponse::NotFound().body("Nothing to look here...").await
 }
@@ -84,38 +873,75 @@ async fn ws_server_upgrade(
     stream: Payload,
 ) -> impl Responder {
     let client_id = query_params.client_id;
+    let is_takeover = match shared_state.admit_server(&query_params).await {
+        Ok(is_takeover) => is_takeover,
+        Err((status, description)) => return HttpResponse::build(status).body(description).await,
+    };
+    let server_party_id = PartyId::Server(query_params.server_id);
+    let accepted_codecs =
+        query_params.compression.as_deref().map(CompressionCodec::parse_list).unwrap_or_default();
+    let server_actor = ServerActor::new(
+        server_party_id,
+        client_id,
+        shared_state.router_address.clone(),
+        accepted_codecs,
+        query_params.format,
+        WsTransport,
+    )
+    .with_mailbox_capacity(shared_state.server_mailbox_capacity)
+    .with_max_frame_violations(shared_state.max_frame_violations)
+    .with_max_message_length(shared_state.max_message_length)
+    .with_app_heartbeat(query_params.app_heartbeat);
 
-    // Check if the request is server connection and deny if already a server in this instance
-    if client_id == shared_state.acceptable_server_uuid
-        && shared_state.server_joined.load(Ordering::Relaxed)
-    {
-        return HttpResponse::Forbidden().body("Server already joined in this instance!").await;
+    match ws_start(server_actor, &request, stream, shared_state.permessage_deflate) {
+        Err(error) => {
+            // A failed takeover leaves the joined server in place
+            if !is_takeover && query_params.server_id == 0 {
+                shared_state.router_address.do_send(ReleaseServer);
+            }
+
+            HttpResponse::InternalServerError().body(error.to_string()).await
+        }
+        Ok((server_address, response)) => {
+            shared_state.router_address.do_send(InterActorMessage::ServerConnect(
+                server_party_id,
+                server_address.recipient(),
+            ));
+            info!("Server with client id {} just joined...", client_id);
+
+            response.await
+        }
     }
+}
 
-    // Check if the request is server connection
-    if client_id == shared_state.acceptable_server_uuid {
-        shared_state.server_joined.store(true, Ordering::Relaxed);
-        let server_party_id = PartyId::Server(0); // One server connection only
-        let server_actor =
-            ServerActor::new(server_party_id, client_id, shared_state.router_address.clone());
+// Relays of the other peers, only peers listed in the ring are accepted
+async fn ws_relay_upgrade(
+    query_params: RequestQuery<RelayQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+    stream: Payload,
+) -> impl Responder {
+    let is_peer = shared_state
+        .peer_ring
+        .as_ref()
+        .map(|peer_ring| peer_ring.is_peer(&query_params.node_url))
+        .unwrap_or(false);
 
-        match ws_start(server_actor, &request, stream) {
-            Err(error) => {
-                shared_state.server_joined.store(false, Ordering::Relaxed);
+    if !is_peer {
+        return HttpResponse::Forbidden().body("Not a peer of this cluster!").await;
+    }
 
-                HttpResponse::InternalServerError().body(error.to_string()).await
-            }
-            Ok((server_address, response)) => {
-                shared_state
-                    .router_address
-                    .do_send(InterActorMessage::ServerConnect(server_party_id, server_address));
-                info!("Server with client id {} just joined...", client_id);
+    let query_params = query_params.into_inner();
+    let listener_actor = RelayListenerActor::new(
+        query_params.node_url,
+        query_params.epoch,
+        shared_state.router_address.clone(),
+        shared_state.relay_sequences.clone(),
+    );
 
-                response.await
-            }
-        }
-    } else {
-        HttpResponse::Forbidden().body("Invalid server client_id!").await
+    match ws_start(listener_actor, &request, stream, false) {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok((_, response)) => response.await,
     }
 }
 
@@ -125,101 +951,608 @@ async fn ws_client_upgrade(
     request: HttpRequest,
     stream: Payload,
 ) -> impl Responder {
-    if !shared_state.server_joined.load(Ordering::Relaxed) {
+    // The owner checks the server and the room on its side
+    if let Some(owner_url) = shared_state.remote_owner_url(query_params.room_id, &request) {
+        return proxy_client(query_params.into_inner(), owner_url, shared_state, request, stream)
+            .await;
+    }
+
+    if !shared_state.is_server_joined().await {
         return HttpResponse::Forbidden().body("Server has not joined yet!").await;
     }
 
-    let client_id = query_params.client_id;
-    let room_id = query_params.room_id;
-    let room_id_usize = room_id as usize;
-
-    match shared_state.available_rooms.lock() {
-        Err(_) => HttpResponse::InternalServerError().body("Memory poisoning detected!").await,
-        Ok(read_guard) => {
-            if !read_guard.contains(&room_id) {
-                return HttpResponse::Forbidden().body(format!("No room {}!", room_id)).await;
-            }
+    upgrade_client(query_params.into_inner(), false, shared_state, request, stream).await
+}
+
+// The same upgrade is made to the owner first, the client gets its refusal status when refused
+async fn proxy_client(
+    query_params: ClientQueryParams,
+    owner_url: String,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+    stream: Payload,
+) -> Result<HttpResponse, ActixError> {
+    if let Err(description) = shared_state.check_connection_policy(&request) {
+        return HttpResponse::Forbidden().body(description).await;
+    }
 
-            match shared_state.client_counter.lock() {
-                Err(_) => {
-                    HttpResponse::InternalServerError().body("Memory poisoning detected!").await
-                }
-                Ok(mut client_counter_guard) => {
-                    if client_counter_guard[room_id_usize] >= ALL_CLIENT_ID {
-                        return HttpResponse::InternalServerError()
-                            .body(format!(
-                                "Server needs to rejoin for room {} is exhausted!",
-                                room_id
-                            ))
-                            .await;
-                    }
-
-                    let party_id = PartyId::from_u32(client_counter_guard[room_id_usize]);
-                    client_counter_guard[room_id_usize] += 1;
-                    let client_actor =
-                        ClientActor::new(party_id, client_id, shared_state.router_address.clone());
-
-                    match ws_start(client_actor, &request, stream) {
-                        Err(error) => {
-                            HttpResponse::InternalServerError().body(error.to_string()).await
-                        }
-                        Ok((client_address, response)) => {
-                            shared_state.router_address.do_send(InterActorMessage::ClientConnect(
-                                room_id,
-                                party_id,
-                                client_id,
-                                client_address,
-                            ));
-                            info!(
-                                "Client with client id {} just joined to room {}...",
-                                client_id, room_id
-                            );
-
-                            response.await
-                        }
-                    }
-                }
+    let connection_permit = match shared_state.acquire_connection(shared_state.client_ip(&request))
+    {
+        Ok(connection_permit) => connection_permit,
+        Err(refusal) => return refuse_connection(refusal).await,
+    };
+    let peer_url = format!(
+        "{}{}",
+        owner_url,
+        request.uri().path_and_query().map(|path_and_query| path_and_query.as_str()).unwrap_or("")
+    );
+    let node_url = shared_state.peer_ring.as_ref().map(PeerRing::node_url).unwrap_or_default();
+    let peer_framed =
+        match Client::new().ws(peer_url).header(HEADER_PROXIED_BY, node_url).connect().await {
+            Ok((_, peer_framed)) => peer_framed,
+            Err(WsClientError::InvalidResponseStatus(status)) => {
+                return HttpResponse::build(status).body(format!("Refused by {}!", owner_url)).await
             }
+            Err(error) => {
+                return HttpResponse::BadGateway()
+                    .body(format!("{} failed: {}", owner_url, error))
+                    .await
+            }
+        };
+    let proxy_actor =
+        PeerProxyActor::new(query_params.room_id, query_params.client_id, owner_url, peer_framed)
+            .with_connection_permit(connection_permit);
+
+    match ws_start(proxy_actor, &request, stream, shared_state.permessage_deflate) {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok((_, response)) => response.await,
+    }
+}
+
+// The router picks the room, asking the server first, then the client joins it like /client
+async fn ws_client_auto_upgrade(
+    query_params: RequestQuery<RoomlessClientQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+    stream: Payload,
+) -> impl Responder {
+    if !shared_state.is_server_joined().await {
+        return HttpResponse::Forbidden().body("Server has not joined yet!").await;
+    }
+
+    let query_params = query_params.into_inner();
+    let room_id = match shared_state.router_address.send(PickRoom(query_params.client_id)).await {
+        Ok(Some(room_id)) => room_id,
+        Ok(None) => return HttpResponse::ServiceUnavailable().body("No room to join!").await,
+        Err(error) => return HttpResponse::InternalServerError().body(error.to_string()).await,
+    };
+    let client_query_params = ClientQueryParams {
+        client_id: query_params.client_id,
+        room_id,
+        compression: query_params.compression,
+        format: query_params.format,
+        metadata: query_params.metadata,
+        metadata_hex: query_params.metadata_hex,
+        auth_token: query_params.auth_token,
+        app_heartbeat: query_params.app_heartbeat,
+    };
+
+    upgrade_client(client_query_params, true, shared_state, request, stream).await
+}
+
+// The client waits in the lobby, pushed the room list, until it switches or is moved to a room
+async fn ws_client_lobby_upgrade(
+    query_params: RequestQuery<RoomlessClientQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+    stream: Payload,
+) -> impl Responder {
+    if !shared_state.is_server_joined().await {
+        return HttpResponse::Forbidden().body("Server has not joined yet!").await;
+    }
+
+    let query_params = query_params.into_inner();
+    let client_query_params = ClientQueryParams {
+        client_id: query_params.client_id,
+        room_id: LOBBY_ROOM_ID,
+        compression: query_params.compression,
+        format: query_params.format,
+        metadata: query_params.metadata,
+        metadata_hex: query_params.metadata_hex,
+        auth_token: query_params.auth_token,
+        app_heartbeat: query_params.app_heartbeat,
+    };
+
+    upgrade_client(client_query_params, true, shared_state, request, stream).await
+}
+
+// Refusals of a connection limit tell the client when to try again
+pub(crate) fn refuse_connection((description, retry_after): (String, u32)) -> HttpResponse {
+    HttpResponse::TooManyRequests().header(RETRY_AFTER, retry_after.to_string()).body(description)
+}
+
+async fn upgrade_client(
+    query_params: ClientQueryParams,
+    is_room_picked: bool,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+    stream: Payload,
+) -> Result<HttpResponse, ActixError> {
+    if let Err(description) = shared_state.check_connection_policy(&request) {
+        return HttpResponse::Forbidden().body(description).await;
+    }
+
+    // Proxied clients share the IP of the proxying node, they only count toward the total
+    let client_ip = shared_state
+        .client_ip(&request)
+        .filter(|_| !request.headers().contains_key(HEADER_PROXIED_BY));
+    let connection_permit = match shared_state.acquire_connection(client_ip) {
+        Ok(connection_permit) => connection_permit,
+        Err(refusal) => return refuse_connection(refusal).await,
+    };
+    let (party_id, metadata) = match shared_state.admit_client(&query_params).await {
+        Ok(admitted_client) => admitted_client,
+        Err((status, description)) => return HttpResponse::build(status).body(description).await,
+    };
+    let accepted_codecs =
+        query_params.compression.as_deref().map(CompressionCodec::parse_list).unwrap_or_default();
+    let client_actor = ClientActor::new(
+        query_params.room_id,
+        party_id,
+        query_params.client_id,
+        shared_state.router_address.clone(),
+        accepted_codecs,
+        query_params.format,
+        WsTransport,
+    )
+    .with_udp_relay(shared_state.udp_relay.clone())
+    .with_connection_permit(connection_permit)
+    .with_slow_client_lag(shared_state.slow_client_lag)
+    .with_mailbox_capacity(shared_state.client_mailbox_capacity)
+    .with_max_frame_violations(shared_state.max_frame_violations)
+    .with_max_message_length(shared_state.max_message_length)
+    .with_app_heartbeat(query_params.app_heartbeat)
+    .with_signing_key(query_params.auth_token.as_deref().map(SigningKey::derive));
+
+    match ws_start(client_actor, &request, stream, shared_state.permessage_deflate) {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok((client_address, response)) => {
+            shared_state.register_client(
+                &query_params,
+                party_id,
+                metadata,
+                client_address.recipient(),
+                is_room_picked,
+            );
+
+            response.await
         }
     }
 }
 
 #[actix_main]
 async fn main() -> IOResult<()> {
-    let options = GameRoomOptions::from_args();
-    init_logger(options.debug_mode);
+    let GameRoomCli { options, command } = GameRoomCli::from_args();
+
+    if let Some(command) = command.as_ref() {
+        command.check_leading()?;
+    }
+
+    let (options, command) = match command {
+        Some(GameRoomCommand::Run(run_options)) => (*run_options, None),
+        command => (options, command),
+    };
+    let instance_id = options.instance_id.unwrap_or_else(Uuid::new_v4);
+    let log_file = match options.log_file.as_deref() {
+        Some(log_file_path) => Some(RollingLogFile::open(
+            log_file_path,
+            LogFileConfig {
+                max_bytes: Some(options.log_file_max_bytes).filter(|max_bytes| *max_bytes > 0),
+                rotation: options.log_file_rotation,
+                keep: options.log_file_keep,
+                compress: options.log_file_compress,
+            },
+        )?),
+        None => None,
+    };
+    init_logger(options.debug_mode, options.log_format, instance_id, log_file);
+
+    if let Some(command) = command {
+        let report = match command {
+            GameRoomCommand::Run(_) => unreachable!("run is the server itself"),
+            GameRoomCommand::Check(check_options) => config_check::run(&check_options),
+            GameRoomCommand::GenerateToken(token_options) => Ok(generate_token::run(token_options)),
+            GameRoomCommand::GenerateUuid => Ok(Uuid::new_v4().to_string()),
+            GameRoomCommand::Bench(bench_options) => bench::run(bench_options).await,
+            GameRoomCommand::Replay(replay_options) => replay::run(replay_options).await,
+        }
+        .map_err(|error| IOError::other(error.to_string()))?;
+        println!("{}", report);
+
+        return Ok(());
+    }
+
+    options.validate()?;
+
+    if let Some(metrics_sink) = options.metrics_sink.as_ref() {
+        metrics_sink.start(instance_id)?;
+    }
+
+    if let Some(otlp_endpoint) = options.otlp_endpoint.as_deref() {
+        TELEMETRY.init(otlp_endpoint, options.otlp_sample_ratio, instance_id)?;
+    }
+
+    if let Some(audit_log_path) = options.audit_log.as_deref() {
+        let max_bytes = Some(options.audit_log_max_bytes).filter(|max_bytes| *max_bytes > 0);
+        AUDIT_LOG.open(audit_log_path, max_bytes)?;
+    }
+
+    if let Some(journal_path) = options.journal.as_deref() {
+        CONNECTION_JOURNAL.open(journal_path)?;
+    }
+
     let listen_socket = format!("0.0.0.0:{}", options.listen_port);
+    // Sockets inherited from systemd or taken over from the previous process are served instead
+    // of binding the ports
+    let (inherited_listeners, takeover) =
+        match options.upgrade_socket.as_deref().filter(|_| options.upgrade) {
+            Some(upgrade_socket) => {
+                let (inherited_listeners, takeover) = upgrade::take_over(upgrade_socket)?;
+                (inherited_listeners, Some(takeover))
+            }
+            None => (systemd::inherited_listeners(), None),
+        };
+    let tcp_listener = match (inherited_listeners.tcp, options.tcp_port) {
+        (Some(tcp_listener), _) => Some(tcp_listener),
+        (None, Some(tcp_port)) => Some(StdTcpListener::bind(("0.0.0.0", tcp_port))?),
+        (None, None) => None,
+    };
+    let http_listener = match inherited_listeners.http {
+        Some(http_listener) => http_listener,
+        None => StdTcpListener::bind(&listen_socket)?,
+    };
+    // Kept open for the next upgrade
+    let handed_listeners = InheritedListeners {
+        http: Some(http_listener.try_clone()?),
+        tcp: tcp_listener.as_ref().map(StdTcpListener::try_clone).transpose()?,
+    };
+    let udp_relay = match options.udp_port {
+        Some(udp_port) => {
+            let udp_relay = UdpRelay::bind(&format!("0.0.0.0:{}", udp_port), options.udp_reliable)?;
+            udp_relay.start()?;
+            Some(udp_relay)
+        }
+        None => None,
+    };
 
-    let available_rooms = Arc::new(Mutex::new(Vec::new()));
-    let server_joined = Arc::new(AtomicBool::new(false));
+    let config_reloader = options.config_reloader();
+    let config = config_reloader.load().map_err(IOError::other)?;
+
+    if let Some(log_filters) = config.log_filters.as_deref() {
+        if log_filters != initial_log_filters() {
+            reload_log_filters(log_filters).map_err(IOError::other)?;
+        }
+    }
+
+    let router_config = GameRoomRouterConfig {
+        batch_interval: if options.batch_tick_rate > 0 {
+            Some(Duration::from_secs_f64(1.0 / options.batch_tick_rate as f64))
+        } else {
+            None
+        },
+        max_payload_lengths: options
+            .max_payload_lengths
+            .iter()
+            .map(|limit| (limit.payload_kind, limit.max_payload_length))
+            .collect(),
+        empty_room_ttl: if options.empty_room_ttl > 0 {
+            Some(Duration::from_secs(options.empty_room_ttl))
+        } else {
+            None
+        },
+        slot_reservation_ttl: if options.slot_reservation_ttl > 0 {
+            Some(Duration::from_secs(options.slot_reservation_ttl))
+        } else {
+            None
+        },
+        server_reconnect_grace: if options.server_reconnect_grace > 0 {
+            Some(Duration::from_secs(options.server_reconnect_grace))
+        } else {
+            None
+        },
+        max_room_clients: config.max_room_clients(),
+        room_balancing: options.room_balancing,
+        memory_budget: MemoryBudget {
+            max_bytes: if options.memory_budget > 0 { Some(options.memory_budget) } else { None },
+            max_room_bytes: if options.room_memory_budget > 0 {
+                Some(options.room_memory_budget)
+            } else {
+                None
+            },
+            shed_policy: options.memory_shed_policy.clone(),
+        },
+        routing_workers: Some(options.routing_workers)
+            .filter(|routing_workers| *routing_workers > 0),
+        mailbox_capacity: Some(options.router_mailbox_capacity),
+        dedup_window: Some(options.dedup_window).filter(|dedup_window| *dedup_window > 0),
+        cross_room_routing: options.cross_room_routing,
+        idle_timeout: if options.idle_timeout > 0 {
+            Some(Duration::from_secs(options.idle_timeout))
+        } else {
+            None
+        },
+        idle_grace: Duration::from_secs(options.idle_grace),
+        denied_payload_kinds: options.denied_payload_kinds.iter().copied().collect(),
+        chaos: options.chaos_rules.iter().map(|rule| (rule.room_id, rule.spec)).collect(),
+        snapshot_max_deltas: Some(options.snapshot_max_deltas),
+        blob_window: Some(options.blob_window).filter(|blob_window| *blob_window > 0),
+        max_streams: Some(options.max_streams).filter(|max_streams| *max_streams > 0),
+    };
+    let traffic_recorder = match options.record_traffic.as_deref() {
+        Some(record_path) => Some(TrafficRecorder::open(record_path)?),
+        None => None,
+    };
+    // Embedders register their message middlewares here, in the order they run
+    let middlewares: Vec<Box<dyn MessageMiddleware>> = Vec::new();
+    let router = GameRoomRouterActor::new(router_config, traffic_recorder)
+        .with_middlewares(middlewares)
+        .with_room_configs(config.room_configs())
+        .with_provisioned_rooms(config.provisioned_rooms().map_err(IOError::other)?);
+    // The plugin runs after the middlewares of the embedder
+    #[cfg(feature = "plugins")]
+    let router = match options.plugin.as_deref() {
+        Some(plugin_path) => {
+            let plugin = wasm_plugin::WasmPlugin::load(plugin_path, options.plugin_fuel)
+                .map_err(IOError::other)?;
+            router.with_middlewares(vec![Box::new(plugin)])
+        }
+        None => router,
+    };
+    let router_address = router.start();
+    let peer_ring = match options.node_url.as_deref() {
+        Some(node_url) if !options.peers.is_empty() => {
+            Some(PeerRing::new(node_url, &options.peers))
+        }
+        _ => None,
+    };
+
+    let connection_policy = config_reloader.load_connection_policy().map_err(IOError::other)?;
+    let (max_connections, max_connections_per_ip) = config.connection_limits();
+    let (handshake_rate, handshake_rate_per_ip) = config.handshake_rates();
+    let cors_policy = CorsPolicy::new(
+        options.cors_origins.clone(),
+        &options.cors_allow_headers,
+        options.cors_max_age,
+    )
+    .with_ws_origin_check(options.ws_check_origin);
+
+    if let Some(peer_ring) = peer_ring.as_ref() {
+        let epoch = Uuid::new_v4();
+
+        for peer_url in peer_ring.peer_urls() {
+            let node_url = peer_ring.node_url().to_string();
+            RelayActor::new(peer_url, node_url, epoch, router_address.clone())
+                .with_base_path(options.base_path.clone())
+                .start();
+        }
+    }
+
+    #[cfg(feature = "webtransport")]
+    let webtransport_listener = match (
+        options.webtransport_port,
+        options.webtransport_cert.as_deref(),
+        options.webtransport_key.as_deref(),
+    ) {
+        (Some(webtransport_port), Some(webtransport_cert), Some(webtransport_key)) => Some(
+            webtransport_listener::bind(webtransport_port, webtransport_cert, webtransport_key)?,
+        ),
+        _ => None,
+    };
 
-    let router_address =
-        GameRoomRouterActor::new(available_rooms.clone(), server_joined.clone()).start();
     let shared_state = SharedData::new(HttpSharedState {
-        available_rooms: available_rooms.clone(),
-        client_counter: Mutex::new(vec![0; 256]),
         acceptable_server_uuid: options.server_uuid,
+        permessage_deflate: options.permessage_deflate,
+        max_metadata_length: options.max_metadata_length,
+        admin_token: options.admin_token,
         router_address,
-        server_joined,
+        peer_ring,
+        relay_sequences: Default::default(),
+        udp_relay,
+        slow_client_lag: if options.slow_client_lag > 0 {
+            Some(Duration::from_secs(options.slow_client_lag))
+        } else {
+            None
+        },
+        max_frame_violations: Some(options.max_frame_violations)
+            .filter(|max_frame_violations| *max_frame_violations > 0),
+        max_message_length: options.max_message_length,
+        server_mailbox_capacity: options.server_mailbox_capacity,
+        client_mailbox_capacity: options.client_mailbox_capacity,
+        poll_sessions: Default::default(),
+        connection_limits: ConnectionLimits::new(max_connections, max_connections_per_ip),
+        handshake_limiter: HandshakeLimiter::new(handshake_rate, handshake_rate_per_ip),
+        trusted_proxies: TrustedProxies::new(options.trusted_proxies),
+        base_path: options.base_path,
+        connection_policy: RwLock::new(connection_policy),
+        config_reloader,
+        #[cfg(feature = "webtransport")]
+        webtransport_certificate: webtransport_listener
+            .as_ref()
+            .map(webtransport_listener::WebTransportListener::certificate),
     });
 
-    HttpServer::new(move || {
+    config_reload::reload_on_hangup(shared_state.clone());
+
+    let tcp_accept = match tcp_listener {
+        Some(tcp_listener) => {
+            Some(tcp_listener::start(tcp_listener, options.proxy_protocol, shared_state.clone())?)
+        }
+        None => None,
+    };
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = options.grpc_port {
+        grpc_listener::start(&format!("0.0.0.0:{}", grpc_port), shared_state.clone()).await?;
+    }
+
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt_broker) = options.mqtt_broker.as_deref() {
+        let mqtt_client_id = format!("game-room-{}", instance_id);
+        mqtt_bridge::start(mqtt_broker, mqtt_client_id, shared_state.clone())?;
+    }
+
+    #[cfg(feature = "webtransport")]
+    if let Some(webtransport_listener) = webtransport_listener {
+        webtransport_listener::start(webtransport_listener, shared_state.clone())?;
+    }
+
+    let connection_limits = shared_state.connection_limits.clone();
+    let http_server = HttpServer::new(move || {
         let shared_state_clone = shared_state.clone();
+        let cors_policy = cors_policy.clone();
+        let routes = scope(&shared_state.base_path)
+            .service(
+                scope(rest_api::API_PATH)
+                    .service(resource("").route(get().to(get_available_rooms)))
+                    .service(resource("/openapi.json").route(get().to(rest_api::get_openapi)))
+                    .configure(configure_routes)
+                    .wrap_fn(rest_api::negotiate),
+            )
+            .configure(configure_routes)
+            .wrap_fn(move |request, service| cors_policy.handle(request, service));
+
         App::new()
             .app_data(shared_state_clone)
             .app_data(PayloadConfig::new(8 * 1024 * 1024))
             .app_data(Bytes::configure(|cfg| cfg.limit(8 * 1024 * 1024)))
             .wrap(ActixLogger::default())
-            .service(get_available_rooms)
-            .service(resource("/server").route(get().to(ws_server_upgrade)))
-            .service(resource("/client").route(get().to(ws_client_upgrade)))
+            .service(routes)
             .default_service(route().to(reject_unmapped_handler))
     })
     .client_timeout(500)
     .client_shutdown(500)
-    .shutdown_timeout(1)
-    .bind(listen_socket)
-    .unwrap()
-    .run()
-    .await
+    .shutdown_timeout(1);
+    let http_server = http_server.listen(http_listener)?.run();
+
+    systemd::notify("READY=1");
+    systemd::notify_stopping_on_terminate();
+
+    // The previous process stops accepting once told this one serves the listeners
+    if let Some(takeover) = takeover {
+        takeover.confirm()?;
+    }
+
+    if let Some(upgrade_socket) = options.upgrade_socket {
+        let drain = Drain {
+            http_server: http_server.clone(),
+            tcp_accept,
+            connection_limits,
+            timeout: Duration::from_secs(options.upgrade_drain_timeout),
+        };
+        upgrade::serve_handoff(upgrade_socket, handed_listeners, drain)?;
+    }
+
+    http_server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::MessageStream;
+    use actix_web::http::Method;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use serde_json::Value as JsonValue;
+
+    #[actix_rt::test]
+    async fn test_base_path_is_as_expected() {
+        assert_eq!(parse_base_path("/"), "");
+        assert_eq!(parse_base_path(" gameroom/ "), "/gameroom");
+        assert_eq!(parse_base_path("/edge/gameroom"), "/edge/gameroom");
+
+        let mut app = init_service(
+            App::new()
+                .service(
+                    scope(&parse_base_path("/gameroom/"))
+                        .service(scope(rest_api::API_PATH).configure(configure_routes))
+                        .configure(configure_routes),
+                )
+                .default_service(route().to(reject_unmapped_handler)),
+        )
+        .await;
+
+        // Every route moves under the base path, the versioned ones too
+        for (uri, status) in [
+            ("/gameroom/metrics", StatusCode::OK),
+            ("/gameroom/v1/metrics", StatusCode::OK),
+            ("/metrics", StatusCode::NOT_FOUND),
+            ("/v1/metrics", StatusCode::NOT_FOUND),
+        ] {
+            let response = call_service(&mut app, TestRequest::get().uri(uri).to_request()).await;
+
+            assert_eq!(response.status(), status, "GET {}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_openapi_routes_are_as_expected() {
+        let mut app = init_service(
+            App::new()
+                .service(resource("/openapi.json").route(get().to(rest_api::get_openapi)))
+                .configure(configure_routes),
+        )
+        .await;
+        let request = TestRequest::get().uri("/openapi.json").to_request();
+        let response = call_service(&mut app, request).await;
+        let resource_map = response.request().resource_map().clone();
+        let document: JsonValue = serde_json::from_slice(&read_body(response).await).unwrap();
+        let paths = document["paths"].as_object().unwrap();
+
+        // Only the Debug output of actix lists the registered resources
+        for pattern in format!("{:?}", resource_map).split("pattern: \"").skip(1) {
+            let pattern = pattern.split('"').next().unwrap();
+
+            assert!(
+                pattern.is_empty() || paths.contains_key(pattern),
+                "{} is undocumented",
+                pattern
+            );
+        }
+
+        for (path, path_item) in paths {
+            let uri = path
+                .split('/')
+                .map(|segment| if segment.starts_with('{') { "1" } else { segment })
+                .collect::<Vec<_>>()
+                .join("/");
+
+            assert_eq!(resource_map.match_pattern(&uri).as_deref(), Some(path.as_str()));
+
+            for method in path_item.as_object().unwrap().keys().filter(|key| *key != "parameters") {
+                let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+                let request = TestRequest::default().method(method.clone()).uri(&uri).to_request();
+                let response = call_service(&mut app, request).await;
+
+                assert_ne!(
+                    response.status(),
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {}",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_message_length_is_as_expected() {
+        let GameRoomCli { options, .. } = GameRoomCli::from_iter(&["game-room"]);
+
+        assert_eq!(options.max_message_length, MessageStream::MAX_FRAME_LENGTH);
+        assert!(options.validate().is_ok());
+
+        let GameRoomCli { options, .. } =
+            GameRoomCli::from_iter(&["game-room", "--max-message-length", "0"]);
+
+        assert!(options.validate().is_err());
+    }
 }
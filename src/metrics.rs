@@ -0,0 +1,222 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub(crate) static METRICS: Metrics = Metrics::new();
+
+/// Process wide counters and gauges, rendered in the Prometheus text exposition format by
+/// `GET /metrics` and pushed to the `--metrics-sink` when one is set
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    pub(crate) oversized_payloads: AtomicU64,
+    pub(crate) routed_messages: AtomicU64,
+    pub(crate) route_duration_micros: AtomicU64, // Sum over every routed message
+    pub(crate) connected_servers: AtomicU64,
+    pub(crate) connected_clients: AtomicU64,
+    pub(crate) shed_messages: AtomicU64,
+    pub(crate) mailbox_overflows: AtomicU64,
+    pub(crate) undelivered_messages: AtomicU64,
+    pub(crate) duplicate_messages: AtomicU64,
+    pub(crate) expired_messages: AtomicU64,
+    pub(crate) forbidden_payloads: AtomicU64,
+    pub(crate) plugin_failures: AtomicU64,
+    pub(crate) bad_signatures: AtomicU64,
+    pub(crate) dropped_realtime: AtomicU64,
+    pub(crate) malformed_frames: AtomicU64,
+}
+
+/// Values of every metric at one point in time, sinks push the difference between two of them
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct MetricsSnapshot {
+    pub(crate) oversized_payloads: u64,
+    pub(crate) routed_messages: u64,
+    pub(crate) route_duration_micros: u64,
+    pub(crate) connected_servers: u64,
+    pub(crate) connected_clients: u64,
+    pub(crate) shed_messages: u64,
+    pub(crate) mailbox_overflows: u64,
+    pub(crate) undelivered_messages: u64,
+    pub(crate) duplicate_messages: u64,
+    pub(crate) expired_messages: u64,
+    pub(crate) forbidden_payloads: u64,
+    pub(crate) plugin_failures: u64,
+    pub(crate) bad_signatures: u64,
+    pub(crate) dropped_realtime: u64,
+    pub(crate) malformed_frames: u64,
+}
+
+impl Metrics {
+    pub(crate) const fn new() -> Self {
+        Self {
+            oversized_payloads: AtomicU64::new(0),
+            routed_messages: AtomicU64::new(0),
+            route_duration_micros: AtomicU64::new(0),
+            connected_servers: AtomicU64::new(0),
+            connected_clients: AtomicU64::new(0),
+            shed_messages: AtomicU64::new(0),
+            mailbox_overflows: AtomicU64::new(0),
+            undelivered_messages: AtomicU64::new(0),
+            duplicate_messages: AtomicU64::new(0),
+            expired_messages: AtomicU64::new(0),
+            forbidden_payloads: AtomicU64::new(0),
+            plugin_failures: AtomicU64::new(0),
+            bad_signatures: AtomicU64::new(0),
+            dropped_realtime: AtomicU64::new(0),
+            malformed_frames: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add(counter: &AtomicU64, count: u64) {
+        counter.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn decrement(gauge: &AtomicU64) {
+        gauge.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn observe_route(&self, route_duration: Duration) {
+        Self::increment(&self.routed_messages);
+        self.route_duration_micros.fetch_add(route_duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            oversized_payloads: self.oversized_payloads.load(Ordering::Relaxed),
+            routed_messages: self.routed_messages.load(Ordering::Relaxed),
+            route_duration_micros: self.route_duration_micros.load(Ordering::Relaxed),
+            connected_servers: self.connected_servers.load(Ordering::Relaxed),
+            connected_clients: self.connected_clients.load(Ordering::Relaxed),
+            shed_messages: self.shed_messages.load(Ordering::Relaxed),
+            mailbox_overflows: self.mailbox_overflows.load(Ordering::Relaxed),
+            undelivered_messages: self.undelivered_messages.load(Ordering::Relaxed),
+            duplicate_messages: self.duplicate_messages.load(Ordering::Relaxed),
+            expired_messages: self.expired_messages.load(Ordering::Relaxed),
+            forbidden_payloads: self.forbidden_payloads.load(Ordering::Relaxed),
+            plugin_failures: self.plugin_failures.load(Ordering::Relaxed),
+            bad_signatures: self.bad_signatures.load(Ordering::Relaxed),
+            dropped_realtime: self.dropped_realtime.load(Ordering::Relaxed),
+            malformed_frames: self.malformed_frames.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut result = String::new();
+        Self::render_metric(
+            &mut result,
+            "game_room_oversized_payloads_total",
+            "Frames rejected for exceeding the maximum payload size of their kind",
+            "counter",
+            snapshot.oversized_payloads,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_routed_messages_total",
+            "Frames handled by the router",
+            "counter",
+            snapshot.routed_messages,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_connected_servers",
+            "Game servers currently connected",
+            "gauge",
+            snapshot.connected_servers,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_connected_clients",
+            "Clients currently connected across every room",
+            "gauge",
+            snapshot.connected_clients,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_shed_messages_total",
+            "Buffered frames dropped to stay within the memory budgets",
+            "counter",
+            snapshot.shed_messages,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_mailbox_overflows_total",
+            "Frames delivered past the mailbox capacity of a connection",
+            "counter",
+            snapshot.mailbox_overflows,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_undelivered_messages_total",
+            "Frames asking for an acknowledgement the router could not deliver",
+            "counter",
+            snapshot.undelivered_messages,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_duplicate_messages_total",
+            "Frames dropped for a sequence already seen from their origin",
+            "counter",
+            snapshot.duplicate_messages,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_expired_messages_total",
+            "Frames dropped for outliving their TTL while buffered by the router",
+            "counter",
+            snapshot.expired_messages,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_forbidden_payloads_total",
+            "Frames dropped for a payload kind the role of their origin may not send",
+            "counter",
+            snapshot.forbidden_payloads,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_plugin_failures_total",
+            "Plugin hook calls that trapped or ran out of fuel",
+            "counter",
+            snapshot.plugin_failures,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_bad_signatures_total",
+            "Frames dropped for a missing or wrong signature from a signing connection",
+            "counter",
+            snapshot.bad_signatures,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_dropped_realtime_total",
+            "Realtime frames dropped for a congested connection instead of being queued",
+            "counter",
+            snapshot.dropped_realtime,
+        );
+        Self::render_metric(
+            &mut result,
+            "game_room_malformed_frames_total",
+            "Frames received from the connections that could not be parsed",
+            "counter",
+            snapshot.malformed_frames,
+        );
+
+        let name = "game_room_route_duration_seconds";
+        let _ = writeln!(result, "# HELP {} Time spent by the router on a frame", name);
+        let _ = writeln!(result, "# TYPE {} summary", name);
+        let _ = writeln!(result, "{}_sum {}", name, snapshot.route_duration_micros as f64 / 1e6);
+        let _ = writeln!(result, "{}_count {}", name, snapshot.routed_messages);
+
+        result
+    }
+
+    fn render_metric(destination: &mut String, name: &str, help: &str, kind: &str, value: u64) {
+        let _ = writeln!(destination, "# HELP {} {}", name, help);
+        let _ = writeln!(destination, "# TYPE {} {}", name, kind);
+        let _ = writeln!(destination, "{} {}", name, value);
+    }
+}
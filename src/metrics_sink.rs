@@ -0,0 +1,222 @@
+//! Pushes `METRICS` to a StatsD or DogStatsD agent over UDP, for deployments not scraping
+//! `GET /metrics`. Counters are sent as the delta since the previous push, gauges as they are
+//! and the route duration as the average timing of the frames routed in between.
+
+use crate::metrics::{MetricsSnapshot, METRICS};
+use crate::{anyerror, AnyError, AnyResult};
+use log::warn;
+use std::fmt::Write;
+use std::io::Result as IOResult;
+use std::net::UdpSocket;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub(crate) const METRICS_PUSH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_STATSD_PORT: u16 = 8125;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum MetricsSink {
+    Statsd(String),    // Agent host:port
+    DogStatsd(String), // Agent host:port, every metric tagged with the instance ID
+}
+
+// Accepts statsd://host[:port] or dogstatsd://host[:port]
+impl FromStr for MetricsSink {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let mut source_split = source.splitn(2, "://");
+
+        let (scheme, authority) = match (source_split.next(), source_split.next()) {
+            (Some(scheme), Some(authority)) if !authority.is_empty() => (scheme, authority),
+            _ => return Err(anyerror!("Expected <scheme>://<host>[:<port>], got {}", source)),
+        };
+        let agent_address = if authority.contains(':') {
+            authority.trim_end_matches('/').to_string()
+        } else {
+            format!("{}:{}", authority.trim_end_matches('/'), DEFAULT_STATSD_PORT)
+        };
+
+        match scheme.to_lowercase().as_str() {
+            "statsd" => Ok(Self::Statsd(agent_address)),
+            "dogstatsd" => Ok(Self::DogStatsd(agent_address)),
+            _ => Err(anyerror!("Unknown MetricsSink scheme {}", scheme)),
+        }
+    }
+}
+
+impl MetricsSink {
+    // Spawns the thread pushing every METRICS_PUSH_INTERVAL
+    pub(crate) fn start(&self, instance_id: Uuid) -> IOResult<()> {
+        let (agent_address, tags) = match self {
+            Self::Statsd(agent_address) => (agent_address, None),
+            Self::DogStatsd(agent_address) => {
+                (agent_address, Some(format!("instance_id:{}", instance_id)))
+            }
+        };
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(agent_address)?;
+
+        thread::Builder::new().name("metrics-sink".into()).spawn(move || {
+            let mut previous = MetricsSnapshot::default();
+
+            loop {
+                thread::sleep(METRICS_PUSH_INTERVAL);
+
+                let current = METRICS.snapshot();
+                let datagram = Self::render(&previous, &current, tags.as_deref());
+                previous = current;
+
+                if let Err(error) = socket.send(datagram.as_bytes()) {
+                    warn!("Pushing metrics failed: {}", error);
+                }
+            }
+        })?;
+
+        Ok(())
+    }
+
+    // One line per metric, suffixed with the DogStatsD tags when there are any
+    pub(crate) fn render(
+        previous: &MetricsSnapshot,
+        current: &MetricsSnapshot,
+        tags: Option<&str>,
+    ) -> String {
+        let routed_messages = current.routed_messages - previous.routed_messages;
+        let route_duration_micros = current.route_duration_micros - previous.route_duration_micros;
+        let mut lines = vec![
+            format!(
+                "game_room.oversized_payloads:{}|c",
+                current.oversized_payloads - previous.oversized_payloads
+            ),
+            format!("game_room.routed_messages:{}|c", routed_messages),
+            format!("game_room.connected_servers:{}|g", current.connected_servers),
+            format!("game_room.connected_clients:{}|g", current.connected_clients),
+            format!("game_room.shed_messages:{}|c", current.shed_messages - previous.shed_messages),
+            format!(
+                "game_room.mailbox_overflows:{}|c",
+                current.mailbox_overflows - previous.mailbox_overflows
+            ),
+            format!(
+                "game_room.undelivered_messages:{}|c",
+                current.undelivered_messages - previous.undelivered_messages
+            ),
+            format!(
+                "game_room.duplicate_messages:{}|c",
+                current.duplicate_messages - previous.duplicate_messages
+            ),
+            format!(
+                "game_room.expired_messages:{}|c",
+                current.expired_messages - previous.expired_messages
+            ),
+            format!(
+                "game_room.forbidden_payloads:{}|c",
+                current.forbidden_payloads - previous.forbidden_payloads
+            ),
+            format!(
+                "game_room.plugin_failures:{}|c",
+                current.plugin_failures - previous.plugin_failures
+            ),
+            format!(
+                "game_room.bad_signatures:{}|c",
+                current.bad_signatures - previous.bad_signatures
+            ),
+            format!(
+                "game_room.dropped_realtime:{}|c",
+                current.dropped_realtime - previous.dropped_realtime
+            ),
+            format!(
+                "game_room.malformed_frames:{}|c",
+                current.malformed_frames - previous.malformed_frames
+            ),
+        ];
+
+        if routed_messages > 0 {
+            let average_millis = route_duration_micros as f64 / routed_messages as f64 / 1e3;
+            lines.push(format!("game_room.route_duration:{:.3}|ms", average_millis));
+        }
+
+        let mut result = String::new();
+
+        for line in lines {
+            match tags {
+                Some(tags) => {
+                    let _ = writeln!(result, "{}|#{}", line, tags);
+                }
+                None => {
+                    let _ = writeln!(result, "{}", line);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_sink_from_str_is_as_expected() {
+        assert_eq!(
+            "statsd://127.0.0.1".parse::<MetricsSink>().unwrap(),
+            MetricsSink::Statsd("127.0.0.1:8125".into())
+        );
+        assert_eq!(
+            "dogstatsd://agent:9125".parse::<MetricsSink>().unwrap(),
+            MetricsSink::DogStatsd("agent:9125".into())
+        );
+        assert!("udp://agent:8125".parse::<MetricsSink>().is_err());
+        assert!("statsd://".parse::<MetricsSink>().is_err());
+    }
+
+    #[test]
+    fn test_metrics_sink_render_is_as_expected() {
+        let previous = MetricsSnapshot {
+            oversized_payloads: 1,
+            routed_messages: 10,
+            route_duration_micros: 1_000,
+            ..Default::default()
+        };
+        let current = MetricsSnapshot {
+            oversized_payloads: 3,
+            routed_messages: 14,
+            route_duration_micros: 1_200,
+            connected_servers: 1,
+            connected_clients: 5,
+            shed_messages: 0,
+            mailbox_overflows: 2,
+            undelivered_messages: 1,
+            duplicate_messages: 0,
+            expired_messages: 4,
+            forbidden_payloads: 1,
+            plugin_failures: 3,
+            bad_signatures: 2,
+            dropped_realtime: 6,
+            malformed_frames: 1,
+        };
+
+        assert_eq!(
+            MetricsSink::render(&previous, &current, Some("instance_id:a")),
+            "game_room.oversized_payloads:2|c|#instance_id:a\n\
+             game_room.routed_messages:4|c|#instance_id:a\n\
+             game_room.connected_servers:1|g|#instance_id:a\n\
+             game_room.connected_clients:5|g|#instance_id:a\n\
+             game_room.shed_messages:0|c|#instance_id:a\n\
+             game_room.mailbox_overflows:2|c|#instance_id:a\n\
+             game_room.undelivered_messages:1|c|#instance_id:a\n\
+             game_room.duplicate_messages:0|c|#instance_id:a\n\
+             game_room.expired_messages:4|c|#instance_id:a\n\
+             game_room.forbidden_payloads:1|c|#instance_id:a\n\
+             game_room.plugin_failures:3|c|#instance_id:a\n\
+             game_room.bad_signatures:2|c|#instance_id:a\n\
+             game_room.dropped_realtime:6|c|#instance_id:a\n\
+             game_room.malformed_frames:1|c|#instance_id:a\n\
+             game_room.route_duration:0.050|ms|#instance_id:a\n"
+        );
+        assert!(!MetricsSink::render(&current, &current, None).contains("route_duration"));
+    }
+}
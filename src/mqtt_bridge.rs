@@ -0,0 +1,267 @@
+//! MQTT bridge for constrained devices not speaking the websocket protocol, built with the `mqtt`
+//! feature. The bridge connects to the broker given with `--mqtt-broker` and subscribes to
+//! `room/+/to-server`. The first publish on the topic of a room joins that room as one client
+//! party shared by every device of the room, an empty payload only joins. Payloads are sent to the
+//! server as Data payloads, and the Data payloads routed to the party are published on
+//! `room/<id>/from-server`. Each joined room is a `ClientActor` over an `MqttTransport`.
+
+use crate::proto::{FrameFormat, MessageBatch, MessageCode, MessageStream, PartyId, PayloadKind};
+use crate::ws_handlers::{ClientActor, ClientTransport};
+use crate::{ClientQueryParams, HttpSharedState};
+use actix::clock::Duration;
+use actix::{Actor, Addr as ActorAddress, Context, Handler as MessageHandler, Message};
+use actix_web::http::StatusCode;
+use actix_web::web::Data as SharedData;
+use actix_web_actors::ws::{CloseReason, Message as WsMessage};
+use futures::channel::mpsc::unbounded;
+use futures::StreamExt;
+use log::{info, warn};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::collections::BTreeMap;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind, Result as IOResult};
+use std::thread;
+use uuid::Uuid;
+
+const TOPIC_TO_SERVER_FILTER: &str = "room/+/to-server";
+const MQTT_DEFAULT_PORT: u16 = 1883;
+const MQTT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+// Requests of the bridge waiting for the broker connection, publishes beyond are dropped
+const MQTT_REQUEST_CAPACITY: usize = 1024;
+const MQTT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// Payload published by a device on the topic of the room of the receiving party
+#[derive(Message)]
+#[rtype(result = "()")]
+struct MqttPublished(Vec<u8>);
+
+// Parses `room/<id>/to-server`, other topics are not the bridge's business
+fn parse_room_topic(topic: &str) -> Option<u32> {
+    let mut topic_split = topic.split('/');
+
+    match (topic_split.next(), topic_split.next(), topic_split.next(), topic_split.next()) {
+        (Some("room"), Some(room_id), Some("to-server"), None) => room_id.parse().ok(),
+        _ => None,
+    }
+}
+
+fn from_server_topic(room_id: u32) -> String {
+    format!("room/{}/from-server", room_id)
+}
+
+// Connects in background, the broker being down only delays the bridge. `broker_address` is
+// `host[:port]`, the port defaults to 1883
+pub(crate) fn start(
+    broker_address: &str,
+    mqtt_client_id: String,
+    shared_state: SharedData<HttpSharedState>,
+) -> IOResult<()> {
+    let mut broker_split = broker_address.rsplitn(2, ':');
+    let (broker_host, broker_port) = match (broker_split.next(), broker_split.next()) {
+        (Some(port), Some(host)) => (
+            host.to_string(),
+            port.parse().map_err(|_| {
+                IOError::new(IOErrorKind::InvalidInput, format!("Invalid MQTT port {}", port))
+            })?,
+        ),
+        _ => (broker_address.to_string(), MQTT_DEFAULT_PORT),
+    };
+    let broker_address = format!("{}:{}", broker_host, broker_port);
+    let mut mqtt_options = MqttOptions::new(mqtt_client_id, broker_host, broker_port);
+    mqtt_options.set_keep_alive(MQTT_KEEP_ALIVE);
+    let (mqtt_client, mut event_loop) = AsyncClient::new(mqtt_options, MQTT_REQUEST_CAPACITY);
+    let (publish_sender, mut publish_receiver) = unbounded::<(u32, Vec<u8>)>();
+    let subscriber = mqtt_client.clone();
+
+    // rumqttc needs a tokio 1 runtime while actix runs on tokio 0.2, it gets a thread of its own
+    let mqtt_runtime = tokio1::runtime::Builder::new_current_thread().enable_all().build()?;
+    thread::Builder::new().name("mqtt-bridge".into()).spawn(move || {
+        mqtt_runtime.block_on(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("MQTT bridge connected to {}", broker_address);
+                        // Sessions are clean, every connection subscribes again
+                        let _ = subscriber.try_subscribe(TOPIC_TO_SERVER_FILTER, QoS::AtMostOnce);
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if let Some(room_id) = parse_room_topic(&publish.topic) {
+                            if publish_sender
+                                .unbounded_send((room_id, publish.payload.to_vec()))
+                                .is_err()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(error) => {
+                        warn!("MQTT bridge connection failed: {}", error);
+                        tokio1::time::sleep(MQTT_RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        })
+    })?;
+
+    actix::spawn(async move {
+        let mut room_parties: BTreeMap<u32, ActorAddress<ClientActor<MqttTransport>>> =
+            BTreeMap::new();
+
+        while let Some((room_id, payload)) = publish_receiver.next().await {
+            let party_address = match room_parties.get(&room_id) {
+                Some(party_address) if party_address.connected() => party_address.clone(),
+                _ => match join_room(room_id, &mqtt_client, &shared_state).await {
+                    Ok(party_address) => {
+                        room_parties.insert(room_id, party_address.clone());
+                        party_address
+                    }
+                    Err((status, description)) => {
+                        warn!(
+                            "MQTT bridge refused in room {}: {} {}",
+                            room_id, status, description
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            if !payload.is_empty() {
+                party_address.do_send(MqttPublished(payload));
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// Refused like the matching /client upgrade, a kicked party joins again on the next publish
+async fn join_room(
+    room_id: u32,
+    mqtt_client: &AsyncClient,
+    shared_state: &HttpSharedState,
+) -> Result<ActorAddress<ClientActor<MqttTransport>>, (StatusCode, String)> {
+    shared_state.check_direct_join(room_id).await?;
+
+    let query_params = ClientQueryParams {
+        client_id: Uuid::new_v4(),
+        room_id,
+        compression: None,
+        format: FrameFormat::Binary,
+        metadata: Some("mqtt-bridge".into()),
+        metadata_hex: None,
+        auth_token: None,
+        app_heartbeat: false,
+    };
+    let (party_id, metadata) = shared_state.admit_client(&query_params).await?;
+    let party_address = ClientActor::new(
+        room_id,
+        party_id,
+        query_params.client_id,
+        shared_state.router_address.clone(),
+        Vec::new(),
+        FrameFormat::Binary,
+        MqttTransport { room_id, party_id, mqtt_client: mqtt_client.clone() },
+    )
+    .with_mailbox_capacity(shared_state.client_mailbox_capacity)
+    .start();
+    shared_state.register_client(
+        &query_params,
+        party_id,
+        metadata,
+        party_address.clone().recipient(),
+        false,
+    );
+    info!("MQTT bridge joined room {} as Party ID {}", room_id, party_id.get_repr());
+
+    Ok(party_address)
+}
+
+// Devices only exchange Data payloads, the other frames routed to the party are dropped
+pub(crate) struct MqttTransport {
+    room_id: u32,
+    party_id: PartyId,
+    mqtt_client: AsyncClient,
+}
+
+impl ClientTransport for MqttTransport {
+    type Context = Context<ClientActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, _: &mut Self::Context, frame: WsMessage) {
+        let message_stream = match frame {
+            WsMessage::Binary(binary) => match MessageStream::from_raw(&binary) {
+                Ok(message_stream) => message_stream,
+                Err(_) => return,
+            },
+            _ => return,
+        };
+        let messages = match message_stream.payload_kind {
+            PayloadKind::Batch => MessageBatch::unpack(&message_stream).unwrap_or_default(),
+            _ => vec![message_stream],
+        };
+
+        for message in
+            messages.into_iter().filter(|message| message.payload_kind == PayloadKind::Data)
+        {
+            let publishing = self.mqtt_client.try_publish(
+                from_server_topic(self.room_id),
+                QoS::AtMostOnce,
+                false,
+                message.payload,
+            );
+
+            if let Err(error) = publishing {
+                warn!("MQTT bridge dropped a payload of room {}: {}", self.room_id, error);
+            }
+        }
+    }
+
+    // The broker keeps the devices alive, there is nothing to ping
+    fn ping(&mut self, _: &mut Self::Context) {}
+
+    fn close(&mut self, _: &mut Self::Context, reason: Option<CloseReason>) {
+        if let Some(description) = reason.and_then(|reason| reason.description) {
+            info!("MQTT bridge left room {}: {}", self.room_id, description);
+        }
+    }
+
+    // The devices of the room come and go, the party stays as long as the bridge
+    fn is_client_waiting(&self) -> bool {
+        true
+    }
+}
+
+impl MessageHandler<MqttPublished> for ClientActor<MqttTransport> {
+    type Result = ();
+
+    fn handle(&mut self, message: MqttPublished, context: &mut Self::Context) {
+        let _log_span = self.log_span.clone().entered();
+        self.update_last_known_activity();
+        let message_stream = MessageStream::new(
+            MessageCode::Normal,
+            self.transport.room_id,
+            self.transport.party_id,
+            PartyId::Server(0),
+            PayloadKind::Data,
+            Some(&message.0),
+        );
+        self.forward_inbound(message_stream, context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_room_topic_is_as_expected() {
+        assert_eq!(parse_room_topic("room/42/to-server"), Some(42));
+        assert_eq!(parse_room_topic("room/42/from-server"), None);
+        assert_eq!(parse_room_topic("room/x/to-server"), None);
+        assert_eq!(parse_room_topic("room/42/to-server/extra"), None);
+        assert_eq!(from_server_topic(42), "room/42/from-server");
+    }
+}
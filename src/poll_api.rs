@@ -0,0 +1,164 @@
+//! Long-polling fallback for clients behind networks blocking websockets. A session is opened with
+//! the query of a `/client` join, then the client sends with `POST /client/poll` and receives with
+//! a long-polling `GET /client/poll`. Each session is a `ClientActor` over a `PollTransport`,
+//! routed like a websocket client, its frames wait in the transport queue until polled.
+
+use crate::proto::{CompressionCodec, FrameFormat};
+use crate::ws_handlers::{
+    encode_polled, ClientActor, ClosePoll, PollSent, PollSession, PollTransport, SigningKey,
+    TakePolled,
+};
+use crate::{refuse_connection, ClientQueryParams, HttpSharedState};
+use actix::clock::Duration;
+use actix::Actor;
+use actix_web::web::{
+    delete, get, post, resource, Bytes, Data as SharedData, Query as RequestQuery, ServiceConfig,
+};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+// Polls are answered empty after this long without a frame, below the usual proxy timeouts
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Deserialize)]
+struct PollQueryParams {
+    session_id: Uuid,
+}
+
+pub(crate) fn configure(config: &mut ServiceConfig) {
+    config.service(resource("/client/poll/open").route(post().to(open_session))).service(
+        resource("/client/poll")
+            .route(get().to(poll_frames))
+            .route(post().to(send_frames))
+            .route(delete().to(close_session)),
+    );
+}
+
+// Refused like the matching /client upgrade, the session ID is then needed by every request
+async fn open_session(
+    query_params: RequestQuery<ClientQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+    request: HttpRequest,
+) -> impl Responder {
+    let query_params = query_params.into_inner();
+
+    if let Err(description) = shared_state.check_connection_policy(&request) {
+        return HttpResponse::Forbidden().body(description).await;
+    }
+
+    let connection_permit = match shared_state.acquire_connection(shared_state.client_ip(&request))
+    {
+        Ok(connection_permit) => connection_permit,
+        Err(refusal) => return refuse_connection(refusal).await,
+    };
+
+    if let Err((status, description)) = shared_state.check_direct_join(query_params.room_id).await {
+        return HttpResponse::build(status).body(description).await;
+    }
+
+    let (party_id, metadata) = match shared_state.admit_client(&query_params).await {
+        Ok(admitted_client) => admitted_client,
+        Err((status, description)) => return HttpResponse::build(status).body(description).await,
+    };
+    let session_id = Uuid::new_v4();
+    let accepted_codecs =
+        query_params.compression.as_deref().map(CompressionCodec::parse_list).unwrap_or_default();
+    let client_address = ClientActor::new(
+        query_params.room_id,
+        party_id,
+        query_params.client_id,
+        shared_state.router_address.clone(),
+        accepted_codecs,
+        query_params.format,
+        PollTransport::new(session_id, shared_state.poll_sessions.clone()),
+    )
+    .with_udp_relay(shared_state.udp_relay.clone())
+    .with_connection_permit(connection_permit)
+    .with_slow_client_lag(shared_state.slow_client_lag)
+    .with_mailbox_capacity(shared_state.client_mailbox_capacity)
+    .with_max_frame_violations(shared_state.max_frame_violations)
+    .with_signing_key(query_params.auth_token.as_deref().map(SigningKey::derive))
+    .start();
+
+    if let Ok(mut write_guard) = shared_state.poll_sessions.lock() {
+        write_guard.insert(
+            session_id,
+            PollSession { address: client_address.clone(), frame_format: query_params.format },
+        );
+    }
+
+    shared_state.register_client(
+        &query_params,
+        party_id,
+        metadata,
+        client_address.recipient(),
+        false,
+    );
+
+    HttpResponse::Ok().json(json!({ "session_id": session_id })).await
+}
+
+fn find_session(shared_state: &HttpSharedState, session_id: Uuid) -> Option<PollSession> {
+    shared_state.poll_sessions.lock().ok()?.get(&session_id).cloned()
+}
+
+async fn poll_frames(
+    query_params: RequestQuery<PollQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+) -> impl Responder {
+    let poll_session = match find_session(&shared_state, query_params.session_id) {
+        Some(poll_session) => poll_session,
+        None => return HttpResponse::NotFound().body("No poll session!").await,
+    };
+    let polled_frames = match poll_session.address.send(TakePolled).await {
+        Ok(polled_frames) => polled_frames,
+        Err(error) => return HttpResponse::InternalServerError().body(error.to_string()).await,
+    };
+    // A dropped or replaced poll is answered empty, the frames stay queued
+    let frames = match timeout(LONG_POLL_TIMEOUT, polled_frames).await {
+        Ok(Ok(frames)) => frames,
+        Ok(Err(_)) | Err(_) => Vec::new(),
+    };
+    let content_type = match poll_session.frame_format {
+        FrameFormat::Json => "application/json",
+        FrameFormat::Binary => "application/octet-stream",
+    };
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .body(encode_polled(poll_session.frame_format, frames))
+        .await
+}
+
+async fn send_frames(
+    query_params: RequestQuery<PollQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+    body: Bytes,
+) -> impl Responder {
+    let poll_session = match find_session(&shared_state, query_params.session_id) {
+        Some(poll_session) => poll_session,
+        None => return HttpResponse::NotFound().body("No poll session!").await,
+    };
+
+    match poll_session.address.send(PollSent(body)).await {
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()).await,
+        Ok(Err(error)) => HttpResponse::BadRequest().body(error.to_string()).await,
+        Ok(Ok(frame_count)) => HttpResponse::Ok().body(frame_count.to_string()).await,
+    }
+}
+
+async fn close_session(
+    query_params: RequestQuery<PollQueryParams>,
+    shared_state: SharedData<HttpSharedState>,
+) -> impl Responder {
+    match find_session(&shared_state, query_params.session_id) {
+        None => HttpResponse::NotFound().body("No poll session!").await,
+        Some(poll_session) => {
+            poll_session.address.do_send(ClosePoll);
+            HttpResponse::Ok().body("Closed").await
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{anyerror, AnyResult};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum BlobKind {
+    Chunk = 0x01, // Followed by the bytes of the blob from the offset
+    Ack = 0x0A,   // Nothing follows, the offset is the length received from the start
+}
+
+/// Frame of a chunked transfer carried by `PayloadKind::Blob`, e.g. a replay or map data: the
+/// `BlobKind`, then the u32 transfer ID picked by the sender, the u32 total length and the u32
+/// offset (LE), then the bytes of a chunk. The receiver acknowledges the length it holds from the
+/// start, which is also where a sender resumes the transfer after a reconnect.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlobFrame {
+    pub kind: BlobKind,
+    pub transfer_id: u32,
+    pub total_length: u32,
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+impl BlobFrame {
+    pub const LENGTH_BLOB_HEADER: usize = 13;
+    pub const OFFSET_KIND: usize = 0;
+    pub const RANGE_TRANSFER_ID: Range<usize> = 1..5;
+    pub const RANGE_TOTAL_LENGTH: Range<usize> = 5..9;
+    pub const RANGE_OFFSET: Range<usize> = 9..13;
+
+    // Offset past the last byte of the chunk, or the acknowledged length
+    pub fn end(&self) -> u64 {
+        self.offset as u64 + self.data.len() as u64
+    }
+
+    pub fn from_raw(source: &[u8]) -> AnyResult<Self> {
+        if source.len() < BlobFrame::LENGTH_BLOB_HEADER {
+            return Err(anyerror!(
+                "Blob frame length is less than {}",
+                BlobFrame::LENGTH_BLOB_HEADER
+            ));
+        }
+
+        let kind = BlobKind::try_from(source[BlobFrame::OFFSET_KIND]).map_err(|_| {
+            anyerror!("Unknown blob frame kind {:02X}", source[BlobFrame::OFFSET_KIND])
+        })?;
+        let read_u32 = |range: Range<usize>| {
+            let mut u32_bytes = [0u8; 4];
+            u32_bytes.copy_from_slice(&source[range]);
+            u32::from_le_bytes(u32_bytes)
+        };
+        let result = Self {
+            kind,
+            transfer_id: read_u32(BlobFrame::RANGE_TRANSFER_ID),
+            total_length: read_u32(BlobFrame::RANGE_TOTAL_LENGTH),
+            offset: read_u32(BlobFrame::RANGE_OFFSET),
+            data: source[BlobFrame::LENGTH_BLOB_HEADER..].to_vec(),
+        };
+
+        if result.end() > result.total_length as u64 {
+            return Err(anyerror!("Blob frame goes past the {} bytes blob", result.total_length));
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_frame_from_raw_is_as_expected() {
+        let raw = [0x01, 0x07, 0, 0, 0, 0x0A, 0, 0, 0, 0x08, 0, 0, 0, 0xAA, 0xBB];
+        let blob_frame = BlobFrame::from_raw(&raw).unwrap();
+
+        assert_eq!(
+            blob_frame,
+            BlobFrame {
+                kind: BlobKind::Chunk,
+                transfer_id: 7,
+                total_length: 10,
+                offset: 8,
+                data: vec![0xAA, 0xBB],
+            }
+        );
+        assert_eq!(blob_frame.end(), 10);
+
+        // Past the total length, truncated or of an unknown kind
+        assert!(BlobFrame::from_raw(&[&raw[..], &[0xCC]].concat()).is_err());
+        assert!(BlobFrame::from_raw(&raw[..12]).is_err());
+        assert!(BlobFrame::from_raw(&[&[0x02], &raw[1..]].concat()).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use super::MessageStream;
+use crate::{anyerror, AnyError, AnyResult};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::str::FromStr;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, Ord, PartialEq, PartialOrd, TryFromPrimitive)]
+pub enum CompressionCodec {
+    Lz4 = 0x01,
+    Zstd = 0x02,
+}
+
+impl CompressionCodec {
+    // Parses a comma separated list of codec names as negotiated on connect, unknown names are
+    // skipped so newer clients can still connect to an older router
+    pub fn parse_list(source: &str) -> Vec<Self> {
+        let mut result: Vec<Self> =
+            source.split(',').filter_map(|codec_name| codec_name.parse().ok()).collect();
+        result.sort_unstable();
+        result.dedup();
+
+        result
+    }
+
+    // Decompressed payloads still have to fit in a single frame
+    pub fn decompress(&self, source: &[u8]) -> AnyResult<Vec<u8>> {
+        let result = match self {
+            Self::Lz4 => {
+                let mut u32_bytes = [0u8; 4];
+
+                if source.len() < u32_bytes.len() {
+                    return Err(anyerror!("LZ4 payload is missing its size prefix"));
+                }
+
+                u32_bytes.copy_from_slice(&source[..4]);
+
+                if u32::from_le_bytes(u32_bytes) as usize > MessageStream::MAX_PAYLOAD_LENGTH {
+                    return Err(anyerror!("LZ4 payload decompresses beyond a single frame"));
+                }
+
+                lz4_flex::decompress_size_prepended(source)
+                    .map_err(|error| anyerror!("LZ4 decompression failed: {}", error))?
+            }
+            #[cfg(target_arch = "wasm32")]
+            Self::Zstd => return Err(anyerror!("Zstd payloads are not supported on wasm32")),
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Zstd => {
+                use std::io::Read;
+
+                let mut result = Vec::new();
+                zstd::stream::read::Decoder::new(source)?
+                    .take(MessageStream::MAX_PAYLOAD_LENGTH as u64 + 1)
+                    .read_to_end(&mut result)?;
+
+                result
+            }
+        };
+
+        if result.len() > MessageStream::MAX_PAYLOAD_LENGTH {
+            return Err(anyerror!("{:#?} payload decompresses beyond a single frame", self));
+        }
+
+        Ok(result)
+    }
+
+    // LZ4 payloads tell their length in their size prefix, Zstd ones have to be decompressed
+    pub fn decompressed_length(&self, source: &[u8]) -> AnyResult<usize> {
+        match self {
+            Self::Lz4 => {
+                let mut u32_bytes = [0u8; 4];
+
+                if source.len() < u32_bytes.len() {
+                    return Err(anyerror!("LZ4 payload is missing its size prefix"));
+                }
+
+                u32_bytes.copy_from_slice(&source[..4]);
+
+                Ok(u32::from_le_bytes(u32_bytes) as usize)
+            }
+            Self::Zstd => self.decompress(source).map(|result| result.len()),
+        }
+    }
+}
+
+impl FromStr for CompressionCodec {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        match source.trim().to_lowercase().as_str() {
+            "lz4" => Ok(Self::Lz4),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(anyerror!("Unknown CompressionCodec {}", source)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip_is_as_expected() {
+        let payload = b"snapshot snapshot snapshot snapshot snapshot".to_vec();
+
+        let lz4_compressed = lz4_flex::compress_prepend_size(&payload);
+        let zstd_compressed = zstd::stream::encode_all(&payload[..], 3).unwrap();
+
+        assert_eq!(CompressionCodec::Lz4.decompress(&lz4_compressed).unwrap(), payload);
+        assert_eq!(CompressionCodec::Zstd.decompress(&zstd_compressed).unwrap(), payload);
+        assert_eq!(CompressionCodec::Lz4.decompressed_length(&lz4_compressed).unwrap(), 44);
+        assert_eq!(CompressionCodec::Zstd.decompressed_length(&zstd_compressed).unwrap(), 44);
+    }
+
+    #[test]
+    fn test_compression_list_skips_unknown_codecs() {
+        assert_eq!(
+            CompressionCodec::parse_list("zstd,brotli,lz4,zstd"),
+            vec![CompressionCodec::Lz4, CompressionCodec::Zstd]
+        );
+    }
+
+    #[test]
+    fn test_decompression_bomb_is_rejected() {
+        let zstd_compressed = zstd::stream::encode_all(&vec![0u8; 1 << 20][..], 3).unwrap();
+        let lz4_compressed = lz4_flex::compress_prepend_size(&vec![0u8; 1 << 20]);
+
+        assert!(CompressionCodec::Zstd.decompress(&zstd_compressed).is_err());
+        assert!(CompressionCodec::Lz4.decompress(&lz4_compressed).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::ops::Range;
+
+/// Statistics of a client connection the router answers a `ControlCode::QueryStats` with, carried
+/// LE after the `InfoCode::ConnectionStats` byte. Counters run from the opening of the connection,
+/// whatever rooms it joined since.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ConnectionStats {
+    pub client_party_id: u32, // In the header room
+    pub frames_received: u64,
+    pub frames_sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub round_trip_millis: Option<u32>, // Last ping or heartbeat answered, u32::MAX when none was
+    pub queued_frames: u32,             // Waiting in the outbound lanes of the connection
+}
+
+impl ConnectionStats {
+    pub const LENGTH: usize = 44;
+    pub const RANGE_CLIENT_PARTY_ID: Range<usize> = 0..4;
+    pub const RANGE_FRAMES_RECEIVED: Range<usize> = 4..12;
+    pub const RANGE_FRAMES_SENT: Range<usize> = 12..20;
+    pub const RANGE_BYTES_RECEIVED: Range<usize> = 20..28;
+    pub const RANGE_BYTES_SENT: Range<usize> = 28..36;
+    pub const RANGE_ROUND_TRIP_MILLIS: Range<usize> = 36..40;
+    pub const RANGE_QUEUED_FRAMES: Range<usize> = 40..44;
+
+    pub fn into_details(self) -> [u8; ConnectionStats::LENGTH] {
+        let mut result = [0u8; ConnectionStats::LENGTH];
+        result[Self::RANGE_CLIENT_PARTY_ID].copy_from_slice(&self.client_party_id.to_le_bytes());
+        result[Self::RANGE_FRAMES_RECEIVED].copy_from_slice(&self.frames_received.to_le_bytes());
+        result[Self::RANGE_FRAMES_SENT].copy_from_slice(&self.frames_sent.to_le_bytes());
+        result[Self::RANGE_BYTES_RECEIVED].copy_from_slice(&self.bytes_received.to_le_bytes());
+        result[Self::RANGE_BYTES_SENT].copy_from_slice(&self.bytes_sent.to_le_bytes());
+        result[Self::RANGE_ROUND_TRIP_MILLIS]
+            .copy_from_slice(&self.round_trip_millis.unwrap_or(u32::MAX).to_le_bytes());
+        result[Self::RANGE_QUEUED_FRAMES].copy_from_slice(&self.queued_frames.to_le_bytes());
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_stats_is_as_expected() {
+        let connection_stats = ConnectionStats {
+            client_party_id: 3,
+            frames_received: 10,
+            frames_sent: 12,
+            bytes_received: 640,
+            bytes_sent: 768,
+            round_trip_millis: Some(42),
+            queued_frames: 2,
+        };
+        let details = connection_stats.into_details();
+
+        assert_eq!(&details[ConnectionStats::RANGE_CLIENT_PARTY_ID], &[3, 0, 0, 0]);
+        assert_eq!(&details[ConnectionStats::RANGE_FRAMES_SENT], &[12, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&details[ConnectionStats::RANGE_BYTES_SENT], &[0, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&details[36..], &[42, 0, 0, 0, 2, 0, 0, 0]);
+
+        let no_round_trip = ConnectionStats { round_trip_millis: None, ..connection_stats };
+
+        assert_eq!(
+            &no_round_trip.into_details()[ConnectionStats::RANGE_ROUND_TRIP_MILLIS],
+            &[0xFF; 4]
+        );
+    }
+}
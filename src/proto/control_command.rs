@@ -0,0 +1,213 @@
+use super::ControlCode;
+use crate::{anyerror, AnyResult};
+use std::convert::TryFrom;
+
+/// Parsed payload of a Special/Command frame sent to the router, the first byte being the
+/// `ControlCode` and the rest its LE arguments.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ControlCommand {
+    Lockstep(u16),          // Tick duration in milliseconds, 0 disables
+    Subscribe(Vec<u32>),    // Interest keys, trailing bytes are ignored
+    Unsubscribe(Vec<u32>),  // Interest keys, trailing bytes are ignored
+    Pause,                  // Normal frames of the room are held until resumed
+    Resume,                 // Held frames are routed in their arrival order
+    CloseRoom,              // Clients are disconnected and the room is forgotten
+    AddRooms(Vec<u32>),     // Merged into the available rooms
+    RemoveRooms(Vec<u32>),  // No longer available to join, connected clients stay
+    ClaimRooms(Vec<u32>),   // Owned by the sending server, their traffic only goes to it
+    ReleaseRooms(Vec<u32>), // Owned by the sending server, back to the primary server
+    CreateRooms(Vec<u32>),  // Made available on the server the room balancing picks
+    ReportLoad(u32, u32),   // (Hosted Rooms, Max Rooms) of the sending server
+    ConfigureRoom(String),  // JSON settings of the room over the config file ones, {} clears them
+    QueryStats(u32),        // Client Party ID whose ConnectionStats the server is answered
+    SwitchRoom(u32),        // Room the client moves to, keeping its connection
+    JoinRoom(u32),          // Room the client joins on top of the ones it is in
+    LeaveRoom,              // Header room left, leaving the last one closes the connection
+    MoveClient(u32, u32),   // (Client Party ID, Room ID) the server moves a client of the room to
+    AssignRoom(u32, u32),   // (Pick ID, Room ID) answering a PickRoom info
+    SetLogLevel(String),    // Log filters of the router process, e.g. info,game_room=debug
+}
+
+impl ControlCommand {
+    pub fn code(&self) -> ControlCode {
+        match self {
+            Self::Lockstep(_) => ControlCode::Lockstep,
+            Self::Subscribe(_) => ControlCode::Subscribe,
+            Self::Unsubscribe(_) => ControlCode::Unsubscribe,
+            Self::Pause => ControlCode::Pause,
+            Self::Resume => ControlCode::Resume,
+            Self::CloseRoom => ControlCode::CloseRoom,
+            Self::AddRooms(_) => ControlCode::AddRooms,
+            Self::RemoveRooms(_) => ControlCode::RemoveRooms,
+            Self::ClaimRooms(_) => ControlCode::ClaimRooms,
+            Self::ReleaseRooms(_) => ControlCode::ReleaseRooms,
+            Self::CreateRooms(_) => ControlCode::CreateRooms,
+            Self::ReportLoad(_, _) => ControlCode::ReportLoad,
+            Self::ConfigureRoom(_) => ControlCode::ConfigureRoom,
+            Self::QueryStats(_) => ControlCode::QueryStats,
+            Self::SwitchRoom(_) => ControlCode::SwitchRoom,
+            Self::JoinRoom(_) => ControlCode::JoinRoom,
+            Self::LeaveRoom => ControlCode::LeaveRoom,
+            Self::MoveClient(_, _) => ControlCode::MoveClient,
+            Self::AssignRoom(_, _) => ControlCode::AssignRoom,
+            Self::SetLogLevel(_) => ControlCode::SetLogLevel,
+        }
+    }
+
+    pub fn from_payload(payload: &[u8]) -> AnyResult<Self> {
+        let control_code = match payload.first().map(|code| ControlCode::try_from(*code)) {
+            Some(Ok(control_code)) => control_code,
+            _ => return Err(anyerror!("Unknown control command {:02X?}", payload.first())),
+        };
+        let arguments = &payload[1..];
+
+        match control_code {
+            ControlCode::Lockstep => {
+                if arguments.len() < 2 {
+                    return Err(anyerror!("Lockstep control command needs a u16 tick duration"));
+                }
+
+                let mut u16_bytes = [0u8; 2];
+                u16_bytes.copy_from_slice(&arguments[..2]);
+
+                Ok(Self::Lockstep(u16::from_le_bytes(u16_bytes)))
+            }
+            ControlCode::Subscribe => Ok(Self::Subscribe(Self::read_u32_list(arguments))),
+            ControlCode::Unsubscribe => Ok(Self::Unsubscribe(Self::read_u32_list(arguments))),
+            ControlCode::Pause => Ok(Self::Pause),
+            ControlCode::Resume => Ok(Self::Resume),
+            ControlCode::CloseRoom => Ok(Self::CloseRoom),
+            ControlCode::AddRooms => Ok(Self::AddRooms(Self::read_u32_list(arguments))),
+            ControlCode::RemoveRooms => Ok(Self::RemoveRooms(Self::read_u32_list(arguments))),
+            ControlCode::ClaimRooms => Ok(Self::ClaimRooms(Self::read_u32_list(arguments))),
+            ControlCode::ReleaseRooms => Ok(Self::ReleaseRooms(Self::read_u32_list(arguments))),
+            ControlCode::CreateRooms => Ok(Self::CreateRooms(Self::read_u32_list(arguments))),
+            ControlCode::ReportLoad => match Self::read_u32_list(arguments).as_slice() {
+                [hosted_rooms, max_rooms, ..] => Ok(Self::ReportLoad(*hosted_rooms, *max_rooms)),
+                _ => {
+                    Err(anyerror!("ReportLoad control command needs a u32 room count and maximum"))
+                }
+            },
+            ControlCode::ConfigureRoom => match std::str::from_utf8(arguments) {
+                Ok(room_config) if !room_config.trim().is_empty() => {
+                    Ok(Self::ConfigureRoom(room_config.trim().into()))
+                }
+                _ => Err(anyerror!("ConfigureRoom control command needs UTF-8 JSON settings")),
+            },
+            ControlCode::QueryStats => match Self::read_u32_list(arguments).first() {
+                Some(client_party_id) => Ok(Self::QueryStats(*client_party_id)),
+                None => Err(anyerror!("QueryStats control command needs a u32 party ID")),
+            },
+            ControlCode::SwitchRoom | ControlCode::JoinRoom => {
+                let room_id = match Self::read_u32_list(arguments).first() {
+                    Some(room_id) => *room_id,
+                    None => {
+                        return Err(anyerror!(
+                            "{:#?} control command needs a u32 room ID",
+                            control_code
+                        ))
+                    }
+                };
+
+                if control_code == ControlCode::SwitchRoom {
+                    Ok(Self::SwitchRoom(room_id))
+                } else {
+                    Ok(Self::JoinRoom(room_id))
+                }
+            }
+            ControlCode::LeaveRoom => Ok(Self::LeaveRoom),
+            ControlCode::MoveClient => match Self::read_u32_list(arguments).as_slice() {
+                [client_party_id, room_id, ..] => Ok(Self::MoveClient(*client_party_id, *room_id)),
+                _ => Err(anyerror!("MoveClient control command needs a u32 party ID and room ID")),
+            },
+            ControlCode::AssignRoom => match Self::read_u32_list(arguments).as_slice() {
+                [pick_id, room_id, ..] => Ok(Self::AssignRoom(*pick_id, *room_id)),
+                _ => Err(anyerror!("AssignRoom control command needs a u32 pick ID and room ID")),
+            },
+            ControlCode::SetLogLevel => match std::str::from_utf8(arguments) {
+                Ok(log_filters) if !log_filters.trim().is_empty() => {
+                    Ok(Self::SetLogLevel(log_filters.trim().into()))
+                }
+                _ => Err(anyerror!("SetLogLevel control command needs UTF-8 log filters")),
+            },
+        }
+    }
+
+    pub fn read_u32_list(source: &[u8]) -> Vec<u32> {
+        source
+            .chunks_exact(4)
+            .map(|u32_chunk| {
+                let mut u32_bytes = [0u8; 4];
+                u32_bytes.copy_from_slice(u32_chunk);
+                u32::from_le_bytes(u32_bytes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_command_from_payload_is_as_expected() {
+        assert_eq!(
+            ControlCommand::from_payload(&[0x10, 0x32, 0x00]).unwrap(),
+            ControlCommand::Lockstep(50)
+        );
+        assert_eq!(
+            ControlCommand::from_payload(&[0x21, 0x01, 0x00, 0x00, 0x00, 0xFF]).unwrap(),
+            ControlCommand::Unsubscribe(vec![1])
+        );
+        assert_eq!(ControlCommand::from_payload(&[0x30]).unwrap(), ControlCommand::Pause);
+        assert_eq!(
+            ControlCommand::from_payload(&[0x42, 0x03, 0x01, 0x00, 0x00, 0x07]).unwrap(),
+            ControlCommand::RemoveRooms(vec![259])
+        );
+        assert_eq!(
+            ControlCommand::from_payload(&[0x43, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00])
+                .unwrap(),
+            ControlCommand::ClaimRooms(vec![1, 2])
+        );
+        assert_eq!(
+            ControlCommand::from_payload(&[0x44, 0x01, 0x00, 0x00, 0x00]).unwrap(),
+            ControlCommand::ReleaseRooms(vec![1])
+        );
+        assert_eq!(
+            ControlCommand::from_payload(&[0x46, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00])
+                .unwrap(),
+            ControlCommand::ReportLoad(2, 8)
+        );
+        assert!(ControlCommand::from_payload(&[0x46, 0x02, 0x00, 0x00, 0x00]).is_err());
+        assert_eq!(
+            ControlCommand::from_payload(&[0x50, 0x02, 0x00, 0x00, 0x00]).unwrap(),
+            ControlCommand::SwitchRoom(2)
+        );
+        assert!(ControlCommand::from_payload(&[0x10, 0x32]).is_err());
+        assert_eq!(
+            ControlCommand::from_payload(&[0x60, 0x09, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00])
+                .unwrap(),
+            ControlCommand::AssignRoom(9, 2)
+        );
+        assert!(ControlCommand::from_payload(&[0x50, 0x02]).is_err());
+        assert!(ControlCommand::from_payload(&[0x60, 0x09, 0x00, 0x00, 0x00]).is_err());
+        assert_eq!(
+            ControlCommand::from_payload(b"\x70info,game_room=debug").unwrap(),
+            ControlCommand::SetLogLevel("info,game_room=debug".into())
+        );
+        assert!(ControlCommand::from_payload(&[0x70, 0xFF]).is_err());
+        assert!(ControlCommand::from_payload(&[0x70]).is_err());
+        assert_eq!(
+            ControlCommand::from_payload(b"\x47{\"max_clients\": 8}").unwrap(),
+            ControlCommand::ConfigureRoom(r#"{"max_clients": 8}"#.into())
+        );
+        assert!(ControlCommand::from_payload(&[0x47]).is_err());
+        assert_eq!(
+            ControlCommand::from_payload(&[0x48, 0x03, 0x00, 0x00, 0x00]).unwrap(),
+            ControlCommand::QueryStats(3)
+        );
+        assert!(ControlCommand::from_payload(&[0x48, 0x03]).is_err());
+        assert!(ControlCommand::from_payload(&[0x7F]).is_err());
+        assert!(ControlCommand::from_payload(&[]).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::AnyResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Self-describing game event carried inside a `PayloadKind::Data` payload, encoded as a
+/// MessagePack map `{ "event": name, "body": body }`. Frames using it are marked with the
+/// `PayloadEnvelope::MessagePack` header option so routers and JSON clients can inspect them.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EventEnvelope<T> {
+    pub event: String,
+    pub body: T,
+}
+
+impl<T> EventEnvelope<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn from_payload(payload: &[u8]) -> AnyResult<Self> {
+        Ok(rmp_serde::from_slice(payload)?)
+    }
+
+    pub fn to_payload(&self) -> AnyResult<Vec<u8>> {
+        Ok(rmp_serde::to_vec_named(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct PlayerMoved {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn test_event_envelope_round_trip_is_as_expected() {
+        let event_envelope =
+            EventEnvelope { event: "player_moved".to_string(), body: PlayerMoved { x: -3, y: 7 } };
+        let payload = event_envelope.to_payload().unwrap();
+
+        assert_eq!(payload[0], 0x82); // fixmap with 2 entries
+        assert_eq!(EventEnvelope::<PlayerMoved>::from_payload(&payload).unwrap(), event_envelope);
+        assert_eq!(
+            EventEnvelope::<serde_json::Value>::from_payload(&payload).unwrap().body,
+            serde_json::json!({ "x": -3, "y": 7 })
+        );
+    }
+}
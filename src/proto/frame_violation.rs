@@ -0,0 +1,87 @@
+use super::{HeaderOptions, MessageCode, MessageStream, PayloadKind};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::convert::TryFrom;
+
+/// Why a frame received could not be parsed, told to its sender in a `MalformedFrame` error
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum FrameViolation {
+    TooShort = 0x01,         // Shorter than the MessageStream header
+    BadPreamble = 0x02,      // Not starting with MessageStream::PREAMBLE
+    BadMessageCode = 0x03,   // Neither Normal nor Special
+    BadPayloadKind = 0x04,   // Unknown PayloadKind
+    BadLength = 0x05,        // Payload length of the header not matching the frame
+    BadHeaderOptions = 0x06, // Header options that do not parse
+    BadJsonEnvelope = 0x07,  // Text frame, or binary one of a JSON client, that is no envelope
+}
+
+impl FrameViolation {
+    // What MessageStream::from_raw refused the source for, checked in the same order
+    pub fn of_raw(source: &[u8]) -> Self {
+        if source.len() < MessageStream::LENGTH_MESSAGE_STREAM_HEADER {
+            return Self::TooShort;
+        }
+
+        let mut u32_bytes = [0u8; 4];
+        u32_bytes.copy_from_slice(&source[MessageStream::RANGE_PREAMBLE]);
+        let message_code_raw =
+            source[MessageStream::RANGE_MESSAGE_CODE.start] & !HeaderOptions::FLAG_HEADER_OPTIONS;
+
+        if u32::from_le_bytes(u32_bytes) != MessageStream::PREAMBLE {
+            Self::BadPreamble
+        } else if MessageCode::try_from(message_code_raw).is_err() {
+            Self::BadMessageCode
+        } else if PayloadKind::try_from(source[MessageStream::RANGE_PAYLOAD_TYPE.start]).is_err() {
+            Self::BadPayloadKind
+        } else if MessageStream::frame_length(source).ok() != Some(source.len()) {
+            Self::BadLength
+        } else {
+            Self::BadHeaderOptions
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::PartyId;
+
+    #[test]
+    fn test_frame_violation_of_raw_is_as_expected() {
+        let mut message = MessageStream::new(
+            MessageCode::Normal,
+            1,
+            PartyId::Client(0),
+            PartyId::Server(0),
+            PayloadKind::Data,
+            Some(&[0xAA, 0xBB]),
+        );
+        message.header_options.ack_sequence = Some(7);
+        let raw = message.into_raw();
+        let corrupted = |offset: usize, byte: u8| {
+            let mut corrupted = raw.clone();
+            corrupted[offset] = byte;
+            corrupted
+        };
+
+        assert_eq!(FrameViolation::of_raw(&raw[..4]), FrameViolation::TooShort);
+        assert_eq!(FrameViolation::of_raw(&corrupted(0, 0x00)), FrameViolation::BadPreamble);
+        assert_eq!(
+            FrameViolation::of_raw(&corrupted(MessageStream::RANGE_MESSAGE_CODE.start, 0x01)),
+            FrameViolation::BadMessageCode
+        );
+        assert_eq!(
+            FrameViolation::of_raw(&corrupted(MessageStream::RANGE_PAYLOAD_TYPE.start, 0x00)),
+            FrameViolation::BadPayloadKind
+        );
+        assert_eq!(FrameViolation::of_raw(&raw[..raw.len() - 1]), FrameViolation::BadLength);
+        // The Ack option claiming a single byte
+        let options_offset =
+            MessageStream::LENGTH_MESSAGE_STREAM_HEADER + HeaderOptions::LENGTH_OPTIONS_LENGTH;
+        assert!(MessageStream::from_raw(&corrupted(options_offset + 1, 0x01)).is_err());
+        assert_eq!(
+            FrameViolation::of_raw(&corrupted(options_offset + 1, 0x01)),
+            FrameViolation::BadHeaderOptions
+        );
+    }
+}
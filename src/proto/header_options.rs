@@ -0,0 +1,327 @@
+use super::{CompressionCodec, MessagePriority, MessageReliability, PayloadEnvelope};
+use crate::{anyerror, AnyResult};
+use std::convert::TryFrom;
+
+/// Optional header fields, only present on the wire when the `FLAG_HEADER_OPTIONS` bit of the
+/// message code is set. They follow the fixed header as a `u8` total length and a list of
+/// `[tag, length, value..]` entries, unknown tags are skipped so older routers stay compatible.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeaderOptions {
+    pub interest_key: Option<u32>,
+    pub priority: Option<MessagePriority>,
+    pub compression: Option<CompressionCodec>,
+    pub envelope: Option<PayloadEnvelope>,
+    pub reliability: Option<MessageReliability>,
+    pub ack_sequence: Option<u32>, // Sequence number of the sender, the destination acknowledges
+    pub correlation_id: Option<u32>, // Matches a Response to its Request
+    pub sequence: Option<u32>,     // Numbers the frames of the sender, resent ones are dropped
+    pub ttl_millis: Option<u16>,   // Lifetime left, the router drops frames waiting longer
+    pub signature: Option<[u8; 16]>, // HMAC-SHA256 of the signed content, truncated to 128 bits
+    pub key_epoch: Option<u32>, // Room key the payload is encrypted with, the router never reads it
+    pub room_sequence: Option<u32>, // Stamped by the router on the server broadcasts of its room
+}
+
+impl HeaderOptions {
+    pub const FLAG_HEADER_OPTIONS: u8 = 0x80;
+    pub const LENGTH_OPTIONS_LENGTH: usize = 1;
+    pub const TAG_INTEREST_KEY: u8 = 0x01;
+    pub const TAG_PRIORITY: u8 = 0x02;
+    pub const TAG_COMPRESSION: u8 = 0x03;
+    pub const TAG_ENVELOPE: u8 = 0x04;
+    pub const TAG_RELIABILITY: u8 = 0x05;
+    pub const TAG_ACK: u8 = 0x06;
+    pub const TAG_CORRELATION_ID: u8 = 0x07;
+    pub const TAG_SEQUENCE: u8 = 0x08;
+    pub const TAG_TTL: u8 = 0x09;
+    pub const TAG_SIGNATURE: u8 = 0x0A;
+    pub const TAG_KEY_EPOCH: u8 = 0x0B;
+    pub const TAG_ROOM_SEQUENCE: u8 = 0x0C;
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Length on the wire including the leading options length byte, 0 when there is no option
+    pub fn raw_length(&self) -> usize {
+        self.to_raw().len()
+    }
+
+    pub fn from_raw(source: &[u8]) -> AnyResult<Self> {
+        let mut result = Self::default();
+        let mut remaining = source;
+
+        while !remaining.is_empty() {
+            if remaining.len() < 2 {
+                return Err(anyerror!("Truncated header option"));
+            }
+
+            let tag = remaining[0];
+            let value_length = remaining[1] as usize;
+
+            if remaining.len() < 2 + value_length {
+                return Err(anyerror!("Truncated header option value for tag {:#04X}", tag));
+            }
+
+            let value = &remaining[2..2 + value_length];
+
+            match tag {
+                HeaderOptions::TAG_INTEREST_KEY => {
+                    result.interest_key = Some(Self::read_u32(tag, value)?);
+                }
+                HeaderOptions::TAG_PRIORITY => {
+                    result.priority = Some(
+                        MessagePriority::try_from(Self::read_u8(tag, value)?)
+                            .map_err(|_| anyerror!("Invalid MessagePriority {:02X?}", value))?,
+                    );
+                }
+                HeaderOptions::TAG_COMPRESSION => {
+                    result.compression = Some(
+                        CompressionCodec::try_from(Self::read_u8(tag, value)?)
+                            .map_err(|_| anyerror!("Invalid CompressionCodec {:02X?}", value))?,
+                    );
+                }
+                HeaderOptions::TAG_ENVELOPE => {
+                    result.envelope = Some(
+                        PayloadEnvelope::try_from(Self::read_u8(tag, value)?)
+                            .map_err(|_| anyerror!("Invalid PayloadEnvelope {:02X?}", value))?,
+                    );
+                }
+                HeaderOptions::TAG_RELIABILITY => {
+                    result.reliability = Some(
+                        MessageReliability::try_from(Self::read_u8(tag, value)?)
+                            .map_err(|_| anyerror!("Invalid MessageReliability {:02X?}", value))?,
+                    );
+                }
+                HeaderOptions::TAG_ACK => {
+                    result.ack_sequence = Some(Self::read_u32(tag, value)?);
+                }
+                HeaderOptions::TAG_CORRELATION_ID => {
+                    result.correlation_id = Some(Self::read_u32(tag, value)?);
+                }
+                HeaderOptions::TAG_SEQUENCE => {
+                    result.sequence = Some(Self::read_u32(tag, value)?);
+                }
+                HeaderOptions::TAG_TTL => {
+                    result.ttl_millis = Some(Self::read_u16(tag, value)?);
+                }
+                HeaderOptions::TAG_SIGNATURE => {
+                    result.signature = Some(<[u8; 16]>::try_from(value).map_err(|_| {
+                        anyerror!("Header option {:#04X} should be 16 bytes long", tag)
+                    })?);
+                }
+                HeaderOptions::TAG_KEY_EPOCH => {
+                    result.key_epoch = Some(Self::read_u32(tag, value)?);
+                }
+                HeaderOptions::TAG_ROOM_SEQUENCE => {
+                    result.room_sequence = Some(Self::read_u32(tag, value)?);
+                }
+                _ => (),
+            }
+
+            remaining = &remaining[2 + value_length..];
+        }
+
+        Ok(result)
+    }
+
+    // Options length byte followed by the entries, empty when there is no option
+    pub fn to_raw(self) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        if self.is_empty() {
+            return result;
+        }
+
+        result.push(0);
+
+        if let Some(interest_key) = self.interest_key {
+            Self::push_entry(
+                &mut result,
+                HeaderOptions::TAG_INTEREST_KEY,
+                &interest_key.to_le_bytes(),
+            );
+        }
+
+        if let Some(priority) = self.priority {
+            Self::push_entry(&mut result, HeaderOptions::TAG_PRIORITY, &[priority.into()]);
+        }
+
+        if let Some(compression) = self.compression {
+            Self::push_entry(&mut result, HeaderOptions::TAG_COMPRESSION, &[compression.into()]);
+        }
+
+        if let Some(envelope) = self.envelope {
+            Self::push_entry(&mut result, HeaderOptions::TAG_ENVELOPE, &[envelope.into()]);
+        }
+
+        if let Some(reliability) = self.reliability {
+            Self::push_entry(&mut result, HeaderOptions::TAG_RELIABILITY, &[reliability.into()]);
+        }
+
+        if let Some(ack_sequence) = self.ack_sequence {
+            Self::push_entry(&mut result, HeaderOptions::TAG_ACK, &ack_sequence.to_le_bytes());
+        }
+
+        if let Some(correlation_id) = self.correlation_id {
+            Self::push_entry(
+                &mut result,
+                HeaderOptions::TAG_CORRELATION_ID,
+                &correlation_id.to_le_bytes(),
+            );
+        }
+
+        if let Some(sequence) = self.sequence {
+            Self::push_entry(&mut result, HeaderOptions::TAG_SEQUENCE, &sequence.to_le_bytes());
+        }
+
+        if let Some(ttl_millis) = self.ttl_millis {
+            Self::push_entry(&mut result, HeaderOptions::TAG_TTL, &ttl_millis.to_le_bytes());
+        }
+
+        if let Some(signature) = self.signature {
+            Self::push_entry(&mut result, HeaderOptions::TAG_SIGNATURE, &signature);
+        }
+
+        if let Some(key_epoch) = self.key_epoch {
+            Self::push_entry(&mut result, HeaderOptions::TAG_KEY_EPOCH, &key_epoch.to_le_bytes());
+        }
+
+        if let Some(room_sequence) = self.room_sequence {
+            Self::push_entry(
+                &mut result,
+                HeaderOptions::TAG_ROOM_SEQUENCE,
+                &room_sequence.to_le_bytes(),
+            );
+        }
+
+        result[0] = (result.len() - HeaderOptions::LENGTH_OPTIONS_LENGTH) as u8;
+
+        result
+    }
+
+    fn push_entry(destination: &mut Vec<u8>, tag: u8, value: &[u8]) {
+        destination.push(tag);
+        destination.push(value.len() as u8);
+        destination.extend_from_slice(value);
+    }
+
+    fn read_u8(tag: u8, value: &[u8]) -> AnyResult<u8> {
+        match value {
+            [byte] => Ok(*byte),
+            _ => Err(anyerror!("Header option {:#04X} should be 1 byte long", tag)),
+        }
+    }
+
+    fn read_u16(tag: u8, value: &[u8]) -> AnyResult<u16> {
+        match value {
+            [low, high] => Ok(u16::from_le_bytes([*low, *high])),
+            _ => Err(anyerror!("Header option {:#04X} should be 2 bytes long", tag)),
+        }
+    }
+
+    fn read_u32(tag: u8, value: &[u8]) -> AnyResult<u32> {
+        if value.len() != 4 {
+            return Err(anyerror!("Header option {:#04X} should be 4 bytes long", tag));
+        }
+
+        let mut u32_bytes = [0u8; 4];
+        u32_bytes.copy_from_slice(value);
+
+        Ok(u32::from_le_bytes(u32_bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_options_round_trip_is_as_expected() {
+        let header_options = HeaderOptions {
+            interest_key: Some(0x0102_0304),
+            priority: Some(MessagePriority::Bulk),
+            ..Default::default()
+        };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw, vec![0x09, 0x01, 0x04, 0x04, 0x03, 0x02, 0x01, 0x02, 0x01, 0x02]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+    }
+
+    #[test]
+    fn test_header_options_reliability_is_as_expected() {
+        let header_options = HeaderOptions {
+            reliability: Some(MessageReliability::Unreliable),
+            ..Default::default()
+        };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw, vec![0x03, 0x05, 0x01, 0x01]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+        assert_eq!(
+            HeaderOptions::from_raw(&[0x05, 0x01, 0x02]).unwrap().reliability,
+            Some(MessageReliability::Realtime)
+        );
+        assert!(HeaderOptions::from_raw(&[0x05, 0x01, 0x03]).is_err());
+    }
+
+    #[test]
+    fn test_header_options_ack_is_as_expected() {
+        let header_options = HeaderOptions { ack_sequence: Some(0x0102), ..Default::default() };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw, vec![0x06, 0x06, 0x04, 0x02, 0x01, 0x00, 0x00]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+        assert!(HeaderOptions::from_raw(&[0x06, 0x02, 0x02, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_header_options_ttl_is_as_expected() {
+        let header_options = HeaderOptions { ttl_millis: Some(100), ..Default::default() };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw, vec![0x04, 0x09, 0x02, 0x64, 0x00]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+        assert!(HeaderOptions::from_raw(&[0x09, 0x01, 0x64]).is_err());
+    }
+
+    #[test]
+    fn test_header_options_signature_is_as_expected() {
+        let header_options = HeaderOptions { signature: Some([0xAB; 16]), ..Default::default() };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw.len(), 19);
+        assert_eq!(raw[..3], [0x12, 0x0A, 0x10]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+        assert!(HeaderOptions::from_raw(&[0x0A, 0x02, 0xAB, 0xAB]).is_err());
+    }
+
+    #[test]
+    fn test_header_options_key_epoch_is_as_expected() {
+        let header_options = HeaderOptions { key_epoch: Some(3), ..Default::default() };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw, vec![0x06, 0x0B, 0x04, 0x03, 0x00, 0x00, 0x00]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+    }
+
+    #[test]
+    fn test_header_options_room_sequence_is_as_expected() {
+        let header_options = HeaderOptions { room_sequence: Some(0x0102), ..Default::default() };
+        let raw = header_options.to_raw();
+
+        assert_eq!(raw, vec![0x06, 0x0C, 0x04, 0x02, 0x01, 0x00, 0x00]);
+        assert_eq!(HeaderOptions::from_raw(&raw[1..]).unwrap(), header_options);
+        assert!(HeaderOptions::from_raw(&[0x0C, 0x02, 0x02, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_header_options_unknown_tag_is_skipped() {
+        let raw = vec![0x7F, 0x02, 0xAA, 0xBB, 0x01, 0x04, 0x01, 0x00, 0x00, 0x00];
+
+        assert_eq!(
+            HeaderOptions::from_raw(&raw).unwrap(),
+            HeaderOptions { interest_key: Some(1), ..Default::default() }
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use super::{
+    EventEnvelope, HeaderOptions, MessageCode, MessagePriority, MessageReliability, MessageStream,
+    PartyId, PayloadEnvelope, PayloadKind,
+};
+use crate::AnyResult;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str as from_json, to_string as to_json, Value as JsonValue};
+
+/// Text frame representation of a `MessageStream` for clients that negotiated `format=json`.
+/// The payload is always sent uncompressed as an array of bytes, except MessagePack event
+/// envelopes which are transcoded to a JSON `event` object.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct JsonEnvelope {
+    pub message_code: MessageCode,
+    pub room_id: u32,
+    pub origin_id: PartyId,
+    pub destination_id: PartyId,
+    pub payload_kind: PayloadKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interest_key: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<MessagePriority>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reliability: Option<MessageReliability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack_sequence: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_millis: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<[u8; 16]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_epoch: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room_sequence: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<EventEnvelope<JsonValue>>,
+    #[serde(default)]
+    pub payload: Vec<u8>,
+}
+
+impl JsonEnvelope {
+    pub fn from_text(source: &str) -> AnyResult<MessageStream> {
+        let envelope: Self = from_json(source)?;
+
+        envelope.into_message_stream()
+    }
+
+    // Callers must decompress the message beforehand, the codec is not carried over
+    pub fn to_text(message_stream: MessageStream) -> AnyResult<String> {
+        Ok(to_json(&Self::from(message_stream))?)
+    }
+}
+
+impl JsonEnvelope {
+    fn into_message_stream(self) -> AnyResult<MessageStream> {
+        let mut header_options = HeaderOptions {
+            interest_key: self.interest_key,
+            priority: self.priority,
+            reliability: self.reliability,
+            ack_sequence: self.ack_sequence,
+            correlation_id: self.correlation_id,
+            sequence: self.sequence,
+            ttl_millis: self.ttl_millis,
+            signature: self.signature,
+            key_epoch: self.key_epoch,
+            room_sequence: self.room_sequence,
+            ..Default::default()
+        };
+        let payload = match self.event {
+            Some(event_envelope) => {
+                header_options.envelope = Some(PayloadEnvelope::MessagePack);
+                event_envelope.to_payload()?
+            }
+            None => self.payload,
+        };
+        let mut message_stream = MessageStream::new(
+            self.message_code,
+            self.room_id,
+            self.origin_id,
+            self.destination_id,
+            self.payload_kind,
+            Some(&payload),
+        );
+        message_stream.header_options = header_options;
+
+        Ok(message_stream)
+    }
+}
+
+impl From<MessageStream> for JsonEnvelope {
+    fn from(message_stream: MessageStream) -> Self {
+        // Undecodable envelopes are handed over as raw bytes, opaque ones are never decoded
+        let event = match message_stream.header_options.envelope {
+            Some(PayloadEnvelope::MessagePack) if !message_stream.is_opaque() => {
+                EventEnvelope::from_payload(&message_stream.payload).ok()
+            }
+            _ => None,
+        };
+        let payload = if event.is_some() { Vec::new() } else { message_stream.payload };
+
+        Self {
+            message_code: message_stream.message_code,
+            room_id: message_stream.room_id,
+            origin_id: message_stream.origin_id,
+            destination_id: message_stream.destination_id,
+            payload_kind: message_stream.payload_kind,
+            interest_key: message_stream.header_options.interest_key,
+            priority: message_stream.header_options.priority,
+            reliability: message_stream.header_options.reliability,
+            ack_sequence: message_stream.header_options.ack_sequence,
+            correlation_id: message_stream.header_options.correlation_id,
+            sequence: message_stream.header_options.sequence,
+            ttl_millis: message_stream.header_options.ttl_millis,
+            signature: message_stream.header_options.signature,
+            key_epoch: message_stream.header_options.key_epoch,
+            room_sequence: message_stream.header_options.room_sequence,
+            event,
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_envelope_round_trip_is_as_expected() {
+        let text = r#"{"message_code":"Normal","room_id":1,"origin_id":{"Client":3},"destination_id":"AllClients","payload_kind":"Data","priority":"Bulk","payload":[1,2,3]}"#;
+        let message_stream = JsonEnvelope::from_text(text).unwrap();
+
+        assert_eq!(message_stream.origin_id, PartyId::Client(3));
+        assert_eq!(message_stream.destination_id, PartyId::AllClients);
+        assert_eq!(message_stream.header_options.priority, Some(MessagePriority::Bulk));
+        assert_eq!(message_stream.payload, vec![1, 2, 3]);
+        assert_eq!(JsonEnvelope::to_text(message_stream).unwrap(), text);
+    }
+
+    #[test]
+    fn test_json_envelope_with_event_is_as_expected() {
+        let text = r#"{"message_code":"Normal","room_id":1,"origin_id":{"Client":3},"destination_id":{"Server":0},"payload_kind":"Data","event":{"event":"chat","body":{"text":"hi"}},"payload":[]}"#;
+        let message_stream = JsonEnvelope::from_text(text).unwrap();
+
+        assert_eq!(message_stream.header_options.envelope, Some(PayloadEnvelope::MessagePack));
+        assert_eq!(
+            EventEnvelope::<JsonValue>::from_payload(&message_stream.payload).unwrap().event,
+            "chat"
+        );
+        assert_eq!(JsonEnvelope::to_text(message_stream).unwrap(), text);
+    }
+}
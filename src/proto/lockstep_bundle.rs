@@ -0,0 +1,73 @@
+use super::{MessageBatch, MessageCode, MessageStream, PartyId, PayloadKind};
+
+/// One lockstep tick worth of client inputs, ordered by client party id. On the wire it is a
+/// `PayloadKind::Lockstep` frame whose payload is the tick number followed by the raw input frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockstepBundle {
+    pub tick: u32,
+    pub inputs: Vec<MessageStream>,
+}
+
+impl LockstepBundle {
+    pub const LENGTH_BUNDLE_HEADER: usize = 4;
+    pub const MAX_INPUTS_LENGTH: usize =
+        MessageBatch::MAX_PAYLOAD_LENGTH - LockstepBundle::LENGTH_BUNDLE_HEADER;
+
+    pub fn into_message_stream(self, room_id: u32) -> MessageStream {
+        let mut payload = Vec::with_capacity(LockstepBundle::LENGTH_BUNDLE_HEADER);
+        payload.extend_from_slice(&self.tick.to_le_bytes());
+
+        for input in self.inputs {
+            payload.extend_from_slice(&input.into_raw());
+        }
+
+        MessageStream::new(
+            MessageCode::Special,
+            room_id,
+            PartyId::Server(0),
+            PartyId::AllClients,
+            PayloadKind::Lockstep,
+            Some(&payload),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockstep_bundle_into_message_stream_is_as_expected() {
+        let bundle = LockstepBundle {
+            tick: 42,
+            inputs: vec![
+                MessageStream::new(
+                    MessageCode::Normal,
+                    1,
+                    PartyId::Client(0),
+                    PartyId::AllClients,
+                    PayloadKind::Data,
+                    Some(&[0x01, 0x02]),
+                ),
+                MessageStream::new(
+                    MessageCode::Normal,
+                    1,
+                    PartyId::Client(1),
+                    PartyId::AllClients,
+                    PayloadKind::Data,
+                    None,
+                ),
+            ],
+        };
+        let message_stream = bundle.clone().into_message_stream(1);
+
+        let inputs = MessageBatch::split_frames(
+            &message_stream.payload[LockstepBundle::LENGTH_BUNDLE_HEADER..],
+        )
+        .unwrap();
+
+        assert_eq!(message_stream.payload_kind, PayloadKind::Lockstep);
+        assert_eq!(&message_stream.payload[..LockstepBundle::LENGTH_BUNDLE_HEADER], &[42, 0, 0, 0]);
+        assert_eq!(inputs, bundle.inputs);
+    }
+}
@@ -0,0 +1,144 @@
+use super::{MessageCode, MessageStream, PartyId, PayloadKind};
+use crate::{anyerror, AnyResult};
+
+/// Container for coalescing several raw `MessageStream` frames into a single `PayloadKind::Batch`
+/// frame. The batch payload is the plain concatenation of the inner raw frames, each one
+/// delimited by the payload length found in its own header.
+pub struct MessageBatch;
+
+impl MessageBatch {
+    pub const MAX_PAYLOAD_LENGTH: usize = MessageStream::MAX_PAYLOAD_LENGTH;
+
+    pub fn pack(
+        room_id: u32,
+        origin_id: PartyId,
+        destination_id: PartyId,
+        messages: Vec<MessageStream>,
+    ) -> Vec<MessageStream> {
+        let mut result = Vec::new();
+        let mut pending = Vec::new();
+        let mut pending_length = 0;
+
+        for message in messages {
+            let message_length = message.raw_length();
+
+            if pending_length + message_length > MessageBatch::MAX_PAYLOAD_LENGTH {
+                result.extend(Self::seal(room_id, origin_id, destination_id, &mut pending));
+                pending_length = 0;
+            }
+
+            // Too big to be batched, let it through as is
+            if message_length > MessageBatch::MAX_PAYLOAD_LENGTH {
+                result.push(message);
+                continue;
+            }
+
+            pending_length += message_length;
+            pending.push(message);
+        }
+
+        result.extend(Self::seal(room_id, origin_id, destination_id, &mut pending));
+
+        result
+    }
+
+    pub fn unpack(batch: &MessageStream) -> AnyResult<Vec<MessageStream>> {
+        if batch.payload_kind != PayloadKind::Batch {
+            return Err(anyerror!("Not a batch, PayloadKind is {:#?}", batch.payload_kind));
+        }
+
+        Self::split_frames(&batch.payload)
+    }
+
+    pub fn split_frames(source: &[u8]) -> AnyResult<Vec<MessageStream>> {
+        let mut result = Vec::new();
+        let mut remaining = source;
+
+        while !remaining.is_empty() {
+            let frame_length = MessageStream::frame_length(remaining)?;
+
+            if remaining.len() < frame_length {
+                return Err(anyerror!("Truncated frame payload inside batch"));
+            }
+
+            result.push(MessageStream::from_raw(&remaining[..frame_length])?);
+            remaining = &remaining[frame_length..];
+        }
+
+        Ok(result)
+    }
+
+    fn seal(
+        room_id: u32,
+        origin_id: PartyId,
+        destination_id: PartyId,
+        pending: &mut Vec<MessageStream>,
+    ) -> Option<MessageStream> {
+        // A batch of one is just overhead, send the original frame
+        if pending.len() <= 1 {
+            return pending.pop();
+        }
+
+        let mut batch_payload = Vec::new();
+
+        for message in pending.drain(..) {
+            batch_payload.extend_from_slice(&message.into_raw());
+        }
+
+        Some(MessageStream::new(
+            MessageCode::Special,
+            room_id,
+            origin_id,
+            destination_id,
+            PayloadKind::Batch,
+            Some(&batch_payload),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_message(payload: &[u8]) -> MessageStream {
+        MessageStream::new(
+            MessageCode::Normal,
+            3,
+            PartyId::Server(0),
+            PartyId::Client(7),
+            PayloadKind::Data,
+            Some(payload),
+        )
+    }
+
+    #[test]
+    fn test_single_message_is_not_batched() {
+        let message = sample_message(&[0x01]);
+        let packed =
+            MessageBatch::pack(3, PartyId::Server(0), PartyId::Client(7), vec![message.clone()]);
+
+        assert_eq!(packed, vec![message]);
+    }
+
+    #[test]
+    fn test_pack_and_unpack_is_as_expected() {
+        let messages =
+            vec![sample_message(&[0x01]), sample_message(&[]), sample_message(&[0xFF; 9])];
+        let packed =
+            MessageBatch::pack(3, PartyId::Server(0), PartyId::Client(7), messages.clone());
+
+        assert_eq!(packed.len(), 1);
+        assert_eq!(packed[0].payload_kind, PayloadKind::Batch);
+        assert_eq!(MessageBatch::unpack(&packed[0]).unwrap(), messages);
+    }
+
+    #[test]
+    fn test_pack_splits_at_max_payload_length() {
+        let big_payload = vec![0xAB; 40_000];
+        let messages = vec![sample_message(&big_payload), sample_message(&big_payload)];
+        let packed =
+            MessageBatch::pack(3, PartyId::Server(0), PartyId::Client(7), messages.clone());
+
+        assert_eq!(packed, messages);
+    }
+}
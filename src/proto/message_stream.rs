@@ -1,31 +1,40 @@
-use super::{MessageCode, PartyId, PayloadKind};
+use super::{
+    CompressionCodec, ErrorCode, HeaderOptions, InfoCode, MessageCode, MessagePriority,
+    MessageReliability, PartyId, PayloadKind,
+};
 use crate::{anyerror, AnyResult};
-use actix::Message;
 use std::ops::Range;
-
-#[rtype(result = "()")]
-#[derive(Clone, Debug, Message, PartialEq, Eq)]
-pub(crate) struct MessageStream {
-    pub(crate) message_code: MessageCode,
-    pub(crate) room_id: u32,
-    pub(crate) origin_id: PartyId,
-    pub(crate) destination_id: PartyId,
-    pub(crate) payload_kind: PayloadKind,
-    pub(crate) payload: Vec<u8>,
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageStream {
+    pub message_code: MessageCode,
+    pub room_id: u32,
+    pub origin_id: PartyId,
+    pub destination_id: PartyId,
+    pub payload_kind: PayloadKind,
+    pub header_options: HeaderOptions,
+    pub payload: Vec<u8>,
 }
 
 impl MessageStream {
-    pub(crate) const PREAMBLE: u32 = 0xFEED_BEEF;
-    pub(crate) const LENGTH_MESSAGE_STREAM_HEADER: usize = 20;
-    pub(crate) const RANGE_PREAMBLE: Range<usize> = 0..4;
-    pub(crate) const RANGE_MESSAGE_CODE: Range<usize> = 4..5;
-    pub(crate) const RANGE_ROOM_ID: Range<usize> = 5..9;
-    pub(crate) const RANGE_ORIGIN_ID: Range<usize> = 9..13;
-    pub(crate) const RANGE_DESTINATION_ID: Range<usize> = 13..17;
-    pub(crate) const RANGE_PAYLOAD_TYPE: Range<usize> = 17..18;
-    pub(crate) const RANGE_PAYLOAD_LENGTH: Range<usize> = 18..20;
-
-    pub(crate) fn new(
+    pub const PREAMBLE: u32 = 0xFEED_BEEF;
+    pub const LENGTH_MESSAGE_STREAM_HEADER: usize = 20;
+    pub const MAX_PAYLOAD_LENGTH: usize = u16::MAX as usize;
+    // Header, the longest header options and the longest payload
+    pub const MAX_FRAME_LENGTH: usize = Self::LENGTH_MESSAGE_STREAM_HEADER
+        + HeaderOptions::LENGTH_OPTIONS_LENGTH
+        + u8::MAX as usize
+        + Self::MAX_PAYLOAD_LENGTH;
+    pub const RANGE_PREAMBLE: Range<usize> = 0..4;
+    pub const RANGE_MESSAGE_CODE: Range<usize> = 4..5;
+    pub const RANGE_ROOM_ID: Range<usize> = 5..9;
+    pub const RANGE_ORIGIN_ID: Range<usize> = 9..13;
+    pub const RANGE_DESTINATION_ID: Range<usize> = 13..17;
+    pub const RANGE_PAYLOAD_TYPE: Range<usize> = 17..18;
+    pub const RANGE_PAYLOAD_LENGTH: Range<usize> = 18..20;
+
+    pub fn new(
         message_code: MessageCode,
         room_id: u32,
         origin_id: PartyId,
@@ -39,10 +48,62 @@ impl MessageStream {
             Vec::new()
         };
 
-        Self { message_code, room_id, origin_id, destination_id, payload_kind, payload }
+        Self {
+            message_code,
+            room_id,
+            origin_id,
+            destination_id,
+            payload_kind,
+            header_options: Default::default(),
+            payload,
+        }
+    }
+
+    // Info sent by the router, on behalf of the server, to a single party
+    pub fn new_info(
+        room_id: u32,
+        destination_id: PartyId,
+        info_code: InfoCode,
+        details: &[u8],
+    ) -> Self {
+        let mut payload = vec![info_code.into()];
+        payload.extend_from_slice(details);
+
+        Self::new(
+            MessageCode::Special,
+            room_id,
+            PartyId::Server(0),
+            destination_id,
+            PayloadKind::Info,
+            Some(&payload),
+        )
+    }
+
+    // Error reply sent by the router, on behalf of the server, to the party at fault
+    pub fn new_error(
+        room_id: u32,
+        destination_id: PartyId,
+        error_code: ErrorCode,
+        details: &[u8],
+    ) -> Self {
+        let mut error_details = vec![error_code.into()];
+        error_details.extend_from_slice(details);
+
+        Self::new_info(room_id, destination_id, InfoCode::Error, &error_details)
+    }
+
+    // Keeps the connection alive where proxies strip the websocket pings, from either side
+    pub fn new_heartbeat(room_id: u32, destination_id: PartyId) -> Self {
+        Self::new_info(room_id, destination_id, InfoCode::Heartbeat, &[])
+    }
+
+    pub fn is_heartbeat(&self) -> bool {
+        self.message_code == MessageCode::Special
+            && self.payload_kind == PayloadKind::Info
+            && self.payload == [u8::from(InfoCode::Heartbeat)]
     }
 
-    pub(crate) fn from_raw(source: &[u8]) -> AnyResult<Self> {
+    pub fn from_raw(source: &[u8]) -> AnyResult<Self> {
         // Length check
         if source.len() < MessageStream::LENGTH_MESSAGE_STREAM_HEADER {
             return Err(anyerror!(
@@ -61,8 +122,10 @@ impl MessageStream {
 
         // MessageCode
         let message_code;
+        let message_code_raw = source[MessageStream::RANGE_MESSAGE_CODE.start];
+        let has_header_options = message_code_raw & HeaderOptions::FLAG_HEADER_OPTIONS != 0;
 
-        match source[MessageStream::RANGE_MESSAGE_CODE] {
+        match [message_code_raw & !HeaderOptions::FLAG_HEADER_OPTIONS] {
             [0x00] => message_code = MessageCode::Normal,
             [0x5E] => message_code = MessageCode::Special,
             _ => {
@@ -92,6 +155,15 @@ impl MessageStream {
             [0xC0] => payload_kind = PayloadKind::Command,
             [0xDA] => payload_kind = PayloadKind::Data,
             [0x1F] => payload_kind = PayloadKind::Info,
+            [0xBA] => payload_kind = PayloadKind::Batch,
+            [0x15] => payload_kind = PayloadKind::Lockstep,
+            [0x5C] => payload_kind = PayloadKind::TimeSync,
+            [0xA0] => payload_kind = PayloadKind::Request,
+            [0xA1] => payload_kind = PayloadKind::Response,
+            [0xEC] => payload_kind = PayloadKind::KeyExchange,
+            [0x55] => payload_kind = PayloadKind::Snapshot,
+            [0xB0] => payload_kind = PayloadKind::Blob,
+            [0x57] => payload_kind = PayloadKind::Stream,
             _ => {
                 return Err(anyerror!(
                     "Invalid PayloadKind {:#?}",
@@ -100,40 +172,155 @@ impl MessageStream {
             }
         }
 
-        // Payload
-        let mut u16_bytes = [0u8; 2];
-        u16_bytes.copy_from_slice(&source[MessageStream::RANGE_PAYLOAD_LENGTH]);
-        let payload_length = u16::from_le_bytes(u16_bytes);
+        // Header Options
+        let frame_length = MessageStream::frame_length(source)?;
 
-        if payload_length as usize + MessageStream::LENGTH_MESSAGE_STREAM_HEADER != source.len() {
+        if frame_length != source.len() {
             return Err(anyerror!(
                 "Source raw bytes length is less than the length {}",
-                payload_length as usize + MessageStream::LENGTH_MESSAGE_STREAM_HEADER
+                frame_length
             ));
         }
 
-        let payload = if payload_length == 0 {
-            None
-        } else {
-            let range_payload = MessageStream::LENGTH_MESSAGE_STREAM_HEADER
-                ..(MessageStream::LENGTH_MESSAGE_STREAM_HEADER + payload_length as usize);
-            Some(&source[range_payload])
+        let mut header_options = HeaderOptions::default();
+        let mut payload_offset = MessageStream::LENGTH_MESSAGE_STREAM_HEADER;
+
+        if has_header_options {
+            let options_length = source[MessageStream::LENGTH_MESSAGE_STREAM_HEADER] as usize;
+            let range_options = (MessageStream::LENGTH_MESSAGE_STREAM_HEADER
+                + HeaderOptions::LENGTH_OPTIONS_LENGTH)
+                ..(MessageStream::LENGTH_MESSAGE_STREAM_HEADER
+                    + HeaderOptions::LENGTH_OPTIONS_LENGTH
+                    + options_length);
+            payload_offset = range_options.end;
+            header_options = HeaderOptions::from_raw(&source[range_options])?;
+        }
+
+        // Payload
+        let payload =
+            if payload_offset == source.len() { None } else { Some(&source[payload_offset..]) };
+
+        let mut result =
+            Self::new(message_code, room_id, origin_id, destination_id, payload_kind, payload);
+        result.header_options = header_options;
+
+        Ok(result)
+    }
+
+    // Total length of the frame starting at source, only the headers need to be present
+    pub fn frame_length(source: &[u8]) -> AnyResult<usize> {
+        if source.len() < MessageStream::LENGTH_MESSAGE_STREAM_HEADER {
+            return Err(anyerror!(
+                "Source raw bytes length is less than the header length {}",
+                MessageStream::LENGTH_MESSAGE_STREAM_HEADER
+            ));
+        }
+
+        let mut u16_bytes = [0u8; 2];
+        u16_bytes.copy_from_slice(&source[MessageStream::RANGE_PAYLOAD_LENGTH]);
+        let payload_length = u16::from_le_bytes(u16_bytes) as usize;
+        let message_code_raw = source[MessageStream::RANGE_MESSAGE_CODE.start];
+
+        if message_code_raw & HeaderOptions::FLAG_HEADER_OPTIONS == 0 {
+            return Ok(MessageStream::LENGTH_MESSAGE_STREAM_HEADER + payload_length);
+        }
+
+        match source.get(MessageStream::LENGTH_MESSAGE_STREAM_HEADER) {
+            None => Err(anyerror!("Header options flag is set but the options length is missing")),
+            Some(options_length) => Ok(MessageStream::LENGTH_MESSAGE_STREAM_HEADER
+                + HeaderOptions::LENGTH_OPTIONS_LENGTH
+                + *options_length as usize
+                + payload_length),
+        }
+    }
+
+    pub fn priority(&self) -> MessagePriority {
+        self.header_options.priority.unwrap_or(MessagePriority::Normal)
+    }
+
+    // Encrypted payloads and key material, the router never reads, logs nor records them
+    pub fn is_opaque(&self) -> bool {
+        self.header_options.key_epoch.is_some() || self.payload_kind == PayloadKind::KeyExchange
+    }
+
+    pub fn reliability(&self) -> MessageReliability {
+        self.header_options.reliability.unwrap_or(MessageReliability::Reliable)
+    }
+
+    // Takes the time spent waiting off the TTL, false once the frame expired. A TTL of 0 expires
+    // as soon as the frame has to wait
+    pub fn spend_ttl(&mut self, waited: Duration) -> bool {
+        let ttl_millis = match self.header_options.ttl_millis {
+            Some(ttl_millis) => ttl_millis,
+            None => return true,
         };
+        let waited_millis = waited.as_millis().min(u16::MAX as u128) as u16;
+
+        if waited_millis >= ttl_millis {
+            return false;
+        }
+
+        self.header_options.ttl_millis = Some(ttl_millis - waited_millis);
+
+        true
+    }
+
+    // Bytes covered by the Signature header option: the room ID and destination ID (LE), the
+    // payload kind and the payload as sent. The origin ID is stamped by the router and the other
+    // header options may be rewritten on the way
+    pub fn signed_content(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(9 + self.payload.len());
+        result.extend_from_slice(&self.room_id.to_le_bytes());
+        result.extend_from_slice(&self.destination_id.to_le_bytes());
+        result.push(self.payload_kind.into());
+        result.extend_from_slice(&self.payload);
+
+        result
+    }
+
+    pub fn decompress(&mut self) -> AnyResult<()> {
+        if let Some(compression) = self.header_options.compression {
+            self.payload = compression.decompress(&self.payload)?;
+            self.header_options.compression = None;
+        }
+
+        Ok(())
+    }
+
+    // The payload stays compressed as long as the receiving end negotiated its codec
+    pub fn decompress_unless_accepted(
+        &mut self,
+        accepted_codecs: &[CompressionCodec],
+    ) -> AnyResult<()> {
+        match self.header_options.compression {
+            Some(compression) if !accepted_codecs.contains(&compression) => self.decompress(),
+            _ => Ok(()),
+        }
+    }
 
-        Ok(Self::new(message_code, room_id, origin_id, destination_id, payload_kind, payload))
+    pub fn raw_length(&self) -> usize {
+        MessageStream::LENGTH_MESSAGE_STREAM_HEADER
+            + self.header_options.raw_length()
+            + self.payload.len()
     }
 
-    pub(crate) fn into_raw(self) -> Vec<u8> {
+    pub fn into_raw(self) -> Vec<u8> {
         let payload_length = self.payload.len() as u16;
-        let mut result =
-            vec![0u8; MessageStream::LENGTH_MESSAGE_STREAM_HEADER + payload_length as usize];
+        let header_options_raw = self.header_options.to_raw();
+        let options_length = header_options_raw.len();
+        let mut result = vec![0u8; self.raw_length()];
+        let mut message_code: u8 = self.message_code.into();
+
+        if options_length > 0 {
+            message_code |= HeaderOptions::FLAG_HEADER_OPTIONS;
+        }
 
         // Unique Code => Offset 0, Length 4
         result[MessageStream::RANGE_PREAMBLE]
             .copy_from_slice(&MessageStream::PREAMBLE.to_le_bytes());
 
         // Message Code => Offset 4, Length 1
-        result[MessageStream::RANGE_MESSAGE_CODE].copy_from_slice(&[self.message_code.into()]);
+        result[MessageStream::RANGE_MESSAGE_CODE].copy_from_slice(&[message_code]);
 
         // Room ID => Offset 5, Length 4
         result[MessageStream::RANGE_ROOM_ID].copy_from_slice(&self.room_id.to_le_bytes());
@@ -151,11 +338,14 @@ impl MessageStream {
         // Payload Length => Offset 18, Length 2
         result[MessageStream::RANGE_PAYLOAD_LENGTH].copy_from_slice(&payload_length.to_le_bytes());
 
+        // Header Options => Offset 20, Length m (only with the header options flag)
+        let payload_offset = MessageStream::LENGTH_MESSAGE_STREAM_HEADER + options_length;
+        result[MessageStream::LENGTH_MESSAGE_STREAM_HEADER..payload_offset]
+            .copy_from_slice(&header_options_raw);
+
         if payload_length > 0 {
-            // Payload => Offset 20, Length n
-            let range_payload = MessageStream::LENGTH_MESSAGE_STREAM_HEADER
-                ..(MessageStream::LENGTH_MESSAGE_STREAM_HEADER + payload_length as usize);
-            result[range_payload].copy_from_slice(&self.payload[..]);
+            // Payload => Offset 20 + m, Length n
+            result[payload_offset..].copy_from_slice(&self.payload[..]);
         }
 
         result
@@ -164,7 +354,23 @@ impl MessageStream {
 
 #[cfg(test)]
 mod tests {
+    use super::super::proptest_strategies::arb_message_stream;
     use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_message_stream_raw_round_trip_is_as_expected(
+            message_stream in arb_message_stream()
+        ) {
+            let message_stream_raw = message_stream.clone().into_raw();
+            let frame_length = MessageStream::frame_length(&message_stream_raw).unwrap();
+
+            prop_assert_eq!(message_stream_raw.len(), message_stream.raw_length());
+            prop_assert_eq!(frame_length, message_stream_raw.len());
+            prop_assert_eq!(MessageStream::from_raw(&message_stream_raw).unwrap(), message_stream);
+        }
+    }
 
     #[test]
     fn test_message_stream_into_bytes_is_as_expected() {
@@ -203,4 +409,56 @@ mod tests {
 
         assert_eq!(message_stream, expected_result);
     }
+
+    #[test]
+    fn test_message_stream_heartbeat_is_as_expected() {
+        let heartbeat = MessageStream::new_heartbeat(3, PartyId::Client(1));
+        let heartbeat_raw = heartbeat.clone().into_raw();
+
+        assert!(MessageStream::from_raw(&heartbeat_raw).unwrap().is_heartbeat());
+        assert_eq!(heartbeat_raw.len(), MessageStream::LENGTH_MESSAGE_STREAM_HEADER + 1);
+        assert!(
+            !MessageStream::new_info(3, PartyId::Client(1), InfoCode::RoomLeft, &[]).is_heartbeat()
+        );
+    }
+
+    #[test]
+    fn test_message_stream_spend_ttl_is_as_expected() {
+        let mut message_stream = MessageStream::new(
+            MessageCode::Normal,
+            1,
+            PartyId::Client(0),
+            PartyId::AllClients,
+            PayloadKind::Data,
+            None,
+        );
+
+        assert!(message_stream.spend_ttl(Duration::from_secs(60)));
+
+        message_stream.header_options.ttl_millis = Some(100);
+
+        assert!(message_stream.spend_ttl(Duration::from_millis(30)));
+        assert_eq!(message_stream.header_options.ttl_millis, Some(70));
+        assert!(!message_stream.spend_ttl(Duration::from_millis(70)));
+    }
+
+    #[test]
+    fn test_message_stream_with_header_options_round_trip_is_as_expected() {
+        let expected_raw = vec![
+            0xEF, 0xBE, 0xED, 0xFE, 0x80, 0x0A, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0xFE,
+            0xFF, 0xFF, 0x7F, 0xDA, 0x01, 0x00, 0x06, 0x01, 0x04, 0x2A, 0x00, 0x00, 0x00, 0xFF,
+        ];
+        let mut message_stream = MessageStream::new(
+            MessageCode::Normal,
+            10,
+            PartyId::Client(12),
+            PartyId::AllClients,
+            PayloadKind::Data,
+            Some(&[0xFF]),
+        );
+        message_stream.header_options.interest_key = Some(42);
+
+        assert_eq!(message_stream.clone().into_raw(), expected_raw);
+        assert_eq!(MessageStream::from_raw(&expected_raw).unwrap(), message_stream);
+    }
 }
@@ -1,33 +1,263 @@
+mod blob_frame;
+mod compression;
+mod connection_stats;
+mod control_command;
+mod event_envelope;
+mod frame_violation;
+mod header_options;
+mod json_envelope;
+mod lockstep_bundle;
+mod message_batch;
 mod message_stream;
+#[cfg(test)]
+mod proptest_strategies;
+mod relay_frame;
+mod stream_frame;
+mod time_sync;
+mod traffic_record;
+mod udp_datagram;
 
-pub(crate) use message_stream::MessageStream;
+pub use blob_frame::{BlobFrame, BlobKind};
+pub use compression::CompressionCodec;
+pub use connection_stats::ConnectionStats;
+pub use control_command::ControlCommand;
+pub use event_envelope::EventEnvelope;
+pub use frame_violation::FrameViolation;
+pub use header_options::HeaderOptions;
+pub use json_envelope::JsonEnvelope;
+pub use lockstep_bundle::LockstepBundle;
+pub use message_batch::MessageBatch;
+pub use message_stream::MessageStream;
+pub use relay_frame::{RelayFrame, RelayPayload};
+pub use stream_frame::{StreamFrame, StreamKind};
+pub use time_sync::TimeSync;
+pub use traffic_record::TrafficRecord;
+pub use udp_datagram::{UdpDatagram, UdpPayload};
 
-use num_enum::IntoPrimitive;
+use crate::{anyerror, AnyError, AnyResult};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-pub(crate) const ALL_SERVER_ID_WITH_ECHO: u32 = 0xFFFF_FFFF;
-pub(crate) const ALL_CLIENT_ID_WITH_ECHO: u32 = 0x7FFF_FFFF;
-pub(crate) const ALL_SERVER_ID: u32 = 0xFFFF_FFFE;
-pub(crate) const ALL_CLIENT_ID: u32 = 0x7FFF_FFFE;
-pub(crate) const OFFSET_SERVER_ID: u32 = 0x8000_0000;
+pub const ALL_SERVER_ID_WITH_ECHO: u32 = 0xFFFF_FFFF;
+pub const ALL_CLIENT_ID_WITH_ECHO: u32 = 0x7FFF_FFFF;
+pub const ALL_SERVER_ID: u32 = 0xFFFF_FFFE;
+pub const ALL_CLIENT_ID: u32 = 0x7FFF_FFFE;
+pub const OFFSET_SERVER_ID: u32 = 0x8000_0000;
+pub const LOBBY_ROOM_ID: u32 = 0xFFFF_FFFF; // Never announced, clients wait there for a room
+pub const ALL_ROOMS_ID: u32 = 0xFFFF_FFFE; // Never announced, server broadcasts to its every room
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Deserialize, Eq, IntoPrimitive, PartialEq, Serialize)]
-pub(crate) enum MessageCode {
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, IntoPrimitive, PartialEq, Serialize, TryFromPrimitive,
+)]
+pub enum MessageCode {
     Special = 0x5E,
     Normal = 0x00,
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Deserialize, Eq, IntoPrimitive, PartialEq, Serialize)]
-pub(crate) enum PayloadKind {
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    IntoPrimitive,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    TryFromPrimitive,
+)]
+pub enum PayloadKind {
     Command = 0xC0,
     Data = 0xDA,
     Info = 0x1F,
+    Batch = 0xBA,
+    Lockstep = 0x15,
+    TimeSync = 0x5C,
+    Request = 0xA0,
+    Response = 0xA1,
+    KeyExchange = 0xEC, // Key material between the server and a client, never logged
+    Snapshot = 0x55,    // Full state of a room from its server, kept by the router for joiners
+    Blob = 0xB0,        // Chunks of a large transfer and their acks, see BlobFrame
+    Stream = 0x57,      // Open-ended byte stream with credit flow control, see StreamFrame
+}
+
+impl FromStr for PayloadKind {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        match source.to_lowercase().as_str() {
+            "command" => Ok(Self::Command),
+            "data" => Ok(Self::Data),
+            "info" => Ok(Self::Info),
+            "batch" => Ok(Self::Batch),
+            "lockstep" => Ok(Self::Lockstep),
+            "timesync" => Ok(Self::TimeSync),
+            "request" => Ok(Self::Request),
+            "response" => Ok(Self::Response),
+            "keyexchange" => Ok(Self::KeyExchange),
+            "snapshot" => Ok(Self::Snapshot),
+            "blob" => Ok(Self::Blob),
+            "stream" => Ok(Self::Stream),
+            _ => Err(anyerror!("Unknown PayloadKind {}", source)),
+        }
+    }
+}
+
+// First payload byte of a Special/Info frame sent by the router
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum InfoCode {
+    Join = 0xF0,             // Followed by the 16 bytes client UUID
+    Leave = 0x0F,            // Followed by the 16 bytes client UUID
+    Error = 0xEE,            // Followed by an ErrorCode and its details
+    RoomExpired = 0xE0,      // Nothing follows, the expired room is the header room ID
+    RoomSwitched = 0xE1,     // Followed by the u32 new room ID and the u32 new client party ID
+    RoomJoined = 0xE2,       // Followed by the u32 joined room ID and the u32 client party ID there
+    RoomLeft = 0xE3,         // Nothing follows, the left room is the header room ID
+    PickRoom = 0xE4, // Followed by u32 pick ID, 16 bytes client UUID and u32 suggested room ID
+    RoomList = 0xE5, // Followed by any number of u32 available room IDs
+    RoomCreated = 0xE6, // Nothing follows, the room created on the server is the header room ID
+    UdpSession = 0xE7, // Followed by the 16 bytes UDP session token and the u16 UDP port
+    SlowClient = 0xE8, // Followed by the 16 bytes client UUID and the u32 lag in milliseconds
+    MailboxOverflow = 0xE9, // Followed by the 16 bytes client UUID
+    Delivered = 0xEA, // Followed by the u32 ack sequence and the u32 acknowledging party ID
+    Undelivered = 0xEB, // Followed by the u32 ack sequence and the u32 undelivered party ID
+    Forbidden = 0xEC, // Followed by the u32 client party ID and the PayloadKind it may not send
+    RoomProvisioned = 0xED, // Followed by the u8 template name length, the name and the metadata
+    RoomWindowClosed = 0xEF, // Nothing follows, the scheduled room closing is the header room ID
+    IdleWarning = 0xDF, // Followed by the u32 milliseconds left before the idle client is kicked
+    Heartbeat = 0xDE, // Nothing follows, sent instead of the websocket pings when negotiated
+    ConnectionStats = 0xDD, // Followed by the ConnectionStats of the queried client
+    Throttled = 0xDC, // Followed by the u32 client party ID, u32 frames and u32 bytes it sent
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum ErrorCode {
+    PayloadTooLarge = 0x01, // Followed by the offending PayloadKind and the u32 maximum length
+    UndecodablePayload = 0x02, // Followed by the offending PayloadKind
+    RoomPaused = 0x03,      // Nothing follows, the paused room is the header room ID
+    RoomUnavailable = 0x04, // Followed by the u32 requested room ID
+    ServerUnavailable = 0x05, // Nothing follows, the server may rejoin within the grace period
+    RoomOwned = 0x06,       // Followed by the u32 claimed room ID and the u32 owning server ID
+    PartyUnlocated = 0x07,  // Followed by the u32 client party ID, in no room or in several
+    PayloadForbidden = 0x08, // Followed by the PayloadKind the role of the sender may not send
+    BadSignature = 0x09,    // Followed by the PayloadKind of the unsigned or tampered frame
+    BlobWindowExceeded = 0x0A, // Followed by the u32 transfer ID and the u32 acknowledged length
+    StreamCreditExhausted = 0x0B, // Followed by the u32 stream ID and the u32 credit left
+    StreamRefused = 0x0C,   // Followed by the u32 stream ID, not open or past --max-streams
+    RoomThrottled = 0x0D,   // Followed by the u32 milliseconds until the room caps reset
+    MalformedFrame = 0x0E,  // Followed by the FrameViolation and the u32 strikes left
+}
+
+// First payload byte of a Special/Command frame sent to the router
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum ControlCode {
+    Lockstep = 0x10,      // Followed by u16 tick duration in milliseconds, 0 disables
+    Subscribe = 0x20,     // Followed by any number of u32 interest keys
+    Unsubscribe = 0x21,   // Followed by any number of u32 interest keys
+    Pause = 0x30,         // Nothing follows
+    Resume = 0x31,        // Nothing follows
+    CloseRoom = 0x40,     // Nothing follows
+    AddRooms = 0x41,      // Followed by any number of u32 room IDs
+    RemoveRooms = 0x42,   // Followed by any number of u32 room IDs
+    ClaimRooms = 0x43,    // Followed by any number of u32 room IDs
+    ReleaseRooms = 0x44,  // Followed by any number of u32 room IDs
+    CreateRooms = 0x45,   // Followed by any number of u32 room IDs
+    ReportLoad = 0x46,    // Followed by the u32 hosted room count and the u32 maximum room count
+    ConfigureRoom = 0x47, // Followed by the UTF-8 JSON settings of the header room
+    QueryStats = 0x48,    // Followed by the u32 client party ID in the header room
+    SwitchRoom = 0x50,    // Followed by the u32 room ID to move to
+    JoinRoom = 0x51,      // Followed by the u32 room ID to join as well
+    LeaveRoom = 0x52,     // Nothing follows
+    MoveClient = 0x53,    // Followed by the u32 client party ID and the u32 room ID to move it to
+    AssignRoom = 0x60,    // Followed by the u32 pick ID and the u32 room ID picked
+    SetLogLevel = 0x70,   // Followed by the UTF-8 log filters in the RUST_LOG syntax
+}
+
+impl ControlCode {
+    pub fn is_server_only(&self) -> bool {
+        !matches!(
+            self,
+            Self::Subscribe
+                | Self::Unsubscribe
+                | Self::SwitchRoom
+                | Self::JoinRoom
+                | Self::LeaveRoom
+        )
+    }
+}
+
+#[repr(u8)]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    IntoPrimitive,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    TryFromPrimitive,
+)]
+pub enum MessagePriority {
+    Critical = 0x00,
+    Normal = 0x01,
+    Bulk = 0x02,
+}
+
+impl FromStr for MessagePriority {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        match source.to_lowercase().as_str() {
+            "critical" => Ok(Self::Critical),
+            "normal" => Ok(Self::Normal),
+            "bulk" => Ok(Self::Bulk),
+            _ => Err(anyerror!("Unknown MessagePriority {}", source)),
+        }
+    }
+}
+
+// Delivery guarantee asked by the sender, transports without datagrams deliver every frame
+// reliably and in order whatever the flag
+#[repr(u8)]
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, IntoPrimitive, PartialEq, Serialize, TryFromPrimitive,
+)]
+pub enum MessageReliability {
+    Reliable = 0x00,   // Default, ordered and retransmitted
+    Unreliable = 0x01, // May be sent as a datagram, lost or reordered, e.g. state snapshots
+    Realtime = 0x02,   // Unreliable, relayed at once and dropped on congestion, e.g. voice
+}
+
+// Structure of the payload, announced with a header option
+#[repr(u8)]
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, IntoPrimitive, PartialEq, Serialize, TryFromPrimitive,
+)]
+pub enum PayloadEnvelope {
+    MessagePack = 0x01, // EventEnvelope encoded as a MessagePack map
+}
+
+// Websocket frame flavour negotiated on upgrade with the `format` query param
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    #[default]
+    Binary,
+    Json,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
-pub(crate) enum PartyId {
+pub enum PartyId {
     AllClients,
     AllServers,
     AllClientsWithEcho,
@@ -37,7 +267,7 @@ pub(crate) enum PartyId {
 }
 
 impl PartyId {
-    pub(crate) fn from_u32(party_id_value: u32) -> Self {
+    pub fn from_u32(party_id_value: u32) -> Self {
         if party_id_value == ALL_CLIENT_ID {
             return Self::AllClients;
         }
@@ -61,7 +291,7 @@ impl PartyId {
         Self::Client(party_id_value)
     }
 
-    pub(crate) fn get_repr(&self) -> u32 {
+    pub fn get_repr(&self) -> u32 {
         match self {
             Self::AllClientsWithEcho => ALL_CLIENT_ID_WITH_ECHO,
             Self::AllClients => ALL_CLIENT_ID,
@@ -72,15 +302,84 @@ impl PartyId {
         }
     }
 
-    pub(crate) fn to_le_bytes(self) -> [u8; 4] {
+    pub fn to_le_bytes(self) -> [u8; 4] {
         self.get_repr().to_le_bytes()
     }
 
-    pub(crate) fn is_single_client_id(&self) -> bool {
+    pub fn is_single_client_id(&self) -> bool {
         matches!(self, Self::Client(_))
     }
 
-    pub(crate) fn is_single_server_id(&self) -> bool {
+    pub fn is_single_server_id(&self) -> bool {
         matches!(self, Self::Server(_))
     }
+
+    // Broadcasts delivered back to their origin as well
+    pub fn is_echo_broadcast(&self) -> bool {
+        matches!(self, Self::AllClientsWithEcho | Self::AllServersWithEcho)
+    }
+}
+
+// Accepts server[:id], client:id, all-clients[-echo], all-servers[-echo] or the raw u32 value
+impl FromStr for PartyId {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let lowercase_source = source.to_lowercase();
+        let mut source_split = lowercase_source.splitn(2, ':');
+
+        match (source_split.next(), source_split.next()) {
+            (Some("all-clients"), None) => Ok(Self::AllClients),
+            (Some("all-clients-echo"), None) => Ok(Self::AllClientsWithEcho),
+            (Some("all-servers"), None) => Ok(Self::AllServers),
+            (Some("all-servers-echo"), None) => Ok(Self::AllServersWithEcho),
+            (Some("server"), None) => Ok(Self::Server(0)),
+            (Some("server"), Some(server_id)) => Ok(Self::Server(server_id.parse()?)),
+            (Some("client"), Some(client_id)) => Ok(Self::Client(client_id.parse()?)),
+            (Some(party_id_value), None) => match party_id_value.parse() {
+                Ok(party_id_value) => Ok(Self::from_u32(party_id_value)),
+                Err(_) => Err(anyerror!("Unknown PartyId {}", source)),
+            },
+            _ => Err(anyerror!("Unknown PartyId {}", source)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proptest_strategies::arb_party_id;
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_party_id_repr_round_trip_is_as_expected(party_id in arb_party_id()) {
+            prop_assert_eq!(PartyId::from_u32(party_id.get_repr()), party_id);
+        }
+
+        // Every u32 on the wire maps to exactly one PartyId, sentinels included
+        #[test]
+        fn test_party_id_from_u32_round_trip_is_as_expected(
+            party_id_value in prop_oneof![
+                any::<u32>(),
+                (OFFSET_SERVER_ID - 4)..=(OFFSET_SERVER_ID + 4),
+                (ALL_CLIENT_ID - 4)..=ALL_CLIENT_ID_WITH_ECHO,
+                (ALL_SERVER_ID - 4)..=ALL_SERVER_ID_WITH_ECHO,
+            ]
+        ) {
+            prop_assert_eq!(PartyId::from_u32(party_id_value).get_repr(), party_id_value);
+        }
+    }
+
+    #[test]
+    fn test_party_id_near_offset_server_id_is_as_expected() {
+        assert_eq!(PartyId::from_u32(OFFSET_SERVER_ID - 1), PartyId::AllClientsWithEcho);
+        assert_eq!(PartyId::from_u32(OFFSET_SERVER_ID), PartyId::Server(0));
+        assert_eq!(PartyId::from_u32(OFFSET_SERVER_ID + 1), PartyId::Server(1));
+        assert_eq!(PartyId::from_u32(ALL_CLIENT_ID - 1), PartyId::Client(ALL_CLIENT_ID - 1));
+        assert_eq!(
+            PartyId::from_u32(ALL_SERVER_ID - 1),
+            PartyId::Server(ALL_SERVER_ID - 1 - OFFSET_SERVER_ID)
+        );
+    }
 }
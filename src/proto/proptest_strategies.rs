@@ -0,0 +1,129 @@
+use super::{
+    CompressionCodec, HeaderOptions, MessageCode, MessagePriority, MessageReliability,
+    MessageStream, PartyId, PayloadEnvelope, PayloadKind, ALL_CLIENT_ID, ALL_SERVER_ID,
+    OFFSET_SERVER_ID,
+};
+use proptest::prelude::*;
+
+// Highest single client/server ID that does not collide with a broadcast sentinel
+const MAX_CLIENT_ID: u32 = ALL_CLIENT_ID - 1;
+const MAX_SERVER_ID: u32 = ALL_SERVER_ID - 1 - OFFSET_SERVER_ID;
+
+pub(crate) fn arb_party_id() -> impl Strategy<Value = PartyId> {
+    prop_oneof![
+        Just(PartyId::AllClients),
+        Just(PartyId::AllServers),
+        Just(PartyId::AllClientsWithEcho),
+        Just(PartyId::AllServersWithEcho),
+        (0..=MAX_CLIENT_ID).prop_map(PartyId::Client),
+        (0..=MAX_SERVER_ID).prop_map(PartyId::Server),
+        // Both sides of OFFSET_SERVER_ID and the last IDs before the sentinels
+        (MAX_CLIENT_ID - 4..=MAX_CLIENT_ID).prop_map(PartyId::Client),
+        (0..=4u32).prop_map(PartyId::Server),
+        (MAX_SERVER_ID - 4..=MAX_SERVER_ID).prop_map(PartyId::Server),
+    ]
+}
+
+pub(crate) fn arb_header_options() -> impl Strategy<Value = HeaderOptions> {
+    (
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(prop_oneof![
+            Just(MessagePriority::Critical),
+            Just(MessagePriority::Normal),
+            Just(MessagePriority::Bulk),
+        ]),
+        proptest::option::of(prop_oneof![
+            Just(CompressionCodec::Lz4),
+            Just(CompressionCodec::Zstd),
+        ]),
+        proptest::option::of(Just(PayloadEnvelope::MessagePack)),
+        proptest::option::of(prop_oneof![
+            Just(MessageReliability::Reliable),
+            Just(MessageReliability::Unreliable),
+            Just(MessageReliability::Realtime),
+        ]),
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(any::<u16>()),
+        proptest::option::of(any::<[u8; 16]>()),
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(any::<u32>()),
+    )
+        .prop_map(
+            |(
+                interest_key,
+                priority,
+                compression,
+                envelope,
+                reliability,
+                ack_sequence,
+                correlation_id,
+                sequence,
+                ttl_millis,
+                signature,
+                key_epoch,
+                room_sequence,
+            )| HeaderOptions {
+                interest_key,
+                priority,
+                compression,
+                envelope,
+                reliability,
+                ack_sequence,
+                correlation_id,
+                sequence,
+                ttl_millis,
+                signature,
+                key_epoch,
+                room_sequence,
+            },
+        )
+}
+
+pub(crate) fn arb_message_stream() -> impl Strategy<Value = MessageStream> {
+    (
+        prop_oneof![Just(MessageCode::Normal), Just(MessageCode::Special)],
+        any::<u32>(),
+        arb_party_id(),
+        arb_party_id(),
+        prop_oneof![
+            Just(PayloadKind::Command),
+            Just(PayloadKind::Data),
+            Just(PayloadKind::Info),
+            Just(PayloadKind::Batch),
+            Just(PayloadKind::Lockstep),
+            Just(PayloadKind::TimeSync),
+            Just(PayloadKind::Request),
+            Just(PayloadKind::Response),
+            Just(PayloadKind::KeyExchange),
+            Just(PayloadKind::Snapshot),
+            Just(PayloadKind::Blob),
+            Just(PayloadKind::Stream),
+        ],
+        arb_header_options(),
+        prop_oneof![
+            proptest::collection::vec(any::<u8>(), 0..64),
+            proptest::collection::vec(any::<u8>(), 0..=MessageStream::MAX_PAYLOAD_LENGTH),
+        ],
+    )
+        .prop_map(
+            |(
+                message_code,
+                room_id,
+                origin_id,
+                destination_id,
+                payload_kind,
+                header_options,
+                payload,
+            )| MessageStream {
+                message_code,
+                room_id,
+                origin_id,
+                destination_id,
+                payload_kind,
+                header_options,
+                payload,
+            },
+        )
+}
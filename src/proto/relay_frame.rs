@@ -0,0 +1,147 @@
+use super::{ControlCommand, MessageStream};
+use crate::{anyerror, AnyResult};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum RelayKind {
+    Message = 0x01,      // Followed by a raw MessageStream
+    MemberJoined = 0x02, // Followed by u32 room ID, u32 client party ID and 16 bytes client UUID
+    MemberLeft = 0x03,   // Followed by the u32 room ID and the u32 client party ID
+    Ack = 0x0A,          // Nothing follows, the sequence is the highest one received
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RelayPayload {
+    Message(MessageStream),
+    MemberJoined(u32, u32, [u8; 16]), // (Room ID, Client Party ID, Client UUID)
+    MemberLeft(u32, u32),             // (Room ID, Client Party ID)
+    Ack,
+}
+
+/// Frame exchanged between game-room instances over `/relay`, the `RelayKind`, the u64 sequence
+/// (LE) and the arguments of the kind. Senders number their frames from 1 for every epoch they
+/// announce on connect and resend the ones not acknowledged yet after a reconnect, receivers drop
+/// the sequences they already handled and answer with the highest one as an `Ack`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RelayFrame {
+    pub sequence: u64,
+    pub payload: RelayPayload,
+}
+
+impl RelayFrame {
+    pub const LENGTH_RELAY_HEADER: usize = 9;
+    pub const RANGE_SEQUENCE: Range<usize> = 1..9;
+
+    pub fn kind(&self) -> RelayKind {
+        match self.payload {
+            RelayPayload::Message(_) => RelayKind::Message,
+            RelayPayload::MemberJoined(_, _, _) => RelayKind::MemberJoined,
+            RelayPayload::MemberLeft(_, _) => RelayKind::MemberLeft,
+            RelayPayload::Ack => RelayKind::Ack,
+        }
+    }
+
+    pub fn into_raw(self) -> Vec<u8> {
+        let mut result = vec![self.kind().into()];
+        result.extend_from_slice(&self.sequence.to_le_bytes());
+
+        match self.payload {
+            RelayPayload::Message(message_stream) => {
+                result.extend_from_slice(&message_stream.into_raw())
+            }
+            RelayPayload::MemberJoined(room_id, client_party_id, client_id) => {
+                result.extend_from_slice(&room_id.to_le_bytes());
+                result.extend_from_slice(&client_party_id.to_le_bytes());
+                result.extend_from_slice(&client_id);
+            }
+            RelayPayload::MemberLeft(room_id, client_party_id) => {
+                result.extend_from_slice(&room_id.to_le_bytes());
+                result.extend_from_slice(&client_party_id.to_le_bytes());
+            }
+            RelayPayload::Ack => (),
+        }
+
+        result
+    }
+
+    pub fn from_raw(source: &[u8]) -> AnyResult<Self> {
+        if source.len() < RelayFrame::LENGTH_RELAY_HEADER {
+            return Err(anyerror!(
+                "Relay frame length is less than {}",
+                RelayFrame::LENGTH_RELAY_HEADER
+            ));
+        }
+
+        let relay_kind = RelayKind::try_from(source[0])
+            .map_err(|_| anyerror!("Unknown relay frame kind {:02X}", source[0]))?;
+        let mut u64_bytes = [0u8; 8];
+        u64_bytes.copy_from_slice(&source[RelayFrame::RANGE_SEQUENCE]);
+        let sequence = u64::from_le_bytes(u64_bytes);
+        let arguments = &source[RelayFrame::LENGTH_RELAY_HEADER..];
+
+        let payload = match relay_kind {
+            RelayKind::Message => RelayPayload::Message(MessageStream::from_raw(arguments)?),
+            RelayKind::MemberJoined => match ControlCommand::read_u32_list(arguments).as_slice() {
+                [room_id, client_party_id, ..] if arguments.len() >= 24 => {
+                    let mut client_id = [0u8; 16];
+                    client_id.copy_from_slice(&arguments[8..24]);
+
+                    RelayPayload::MemberJoined(*room_id, *client_party_id, client_id)
+                }
+                _ => return Err(anyerror!("MemberJoined needs a room ID, party ID and UUID")),
+            },
+            RelayKind::MemberLeft => match ControlCommand::read_u32_list(arguments).as_slice() {
+                [room_id, client_party_id, ..] => {
+                    RelayPayload::MemberLeft(*room_id, *client_party_id)
+                }
+                _ => return Err(anyerror!("MemberLeft needs a room ID and party ID")),
+            },
+            RelayKind::Ack => RelayPayload::Ack,
+        };
+
+        Ok(Self { sequence, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PartyId, PayloadKind};
+
+    #[test]
+    fn test_relay_frame_raw_is_as_expected() {
+        let relay_frames = vec![
+            RelayFrame {
+                sequence: 1,
+                payload: RelayPayload::Message(MessageStream::new(
+                    MessageCode::Normal,
+                    7,
+                    PartyId::Client(2),
+                    PartyId::AllClients,
+                    PayloadKind::Data,
+                    Some(&[0xAA, 0xBB]),
+                )),
+            },
+            RelayFrame { sequence: 2, payload: RelayPayload::MemberJoined(7, 2, [0x11; 16]) },
+            RelayFrame { sequence: 3, payload: RelayPayload::MemberLeft(7, 2) },
+            RelayFrame { sequence: 3, payload: RelayPayload::Ack },
+        ];
+
+        for relay_frame in relay_frames {
+            assert_eq!(RelayFrame::from_raw(&relay_frame.clone().into_raw()).unwrap(), relay_frame);
+        }
+
+        assert_eq!(
+            RelayFrame { sequence: 258, payload: RelayPayload::MemberLeft(1, 2) }.into_raw(),
+            vec![0x03, 0x02, 0x01, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]
+        );
+        assert!(RelayFrame::from_raw(&[0x0A, 0x01]).is_err());
+        assert!(RelayFrame::from_raw(&[0x7F, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(
+            RelayFrame::from_raw(&[0x02, 1, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 2, 0, 0, 0]).is_err()
+        );
+    }
+}
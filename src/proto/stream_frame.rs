@@ -0,0 +1,93 @@
+use crate::{anyerror, AnyResult};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum StreamKind {
+    Open = 0x01, // Nothing follows, the stream has no credit until its destination grants some
+    Chunk = 0x02, // Followed by the next bytes of the stream
+    Close = 0x03, // Nothing follows, from either side
+    Credit = 0x0C, // Followed by the u32 bytes the destination grants the sender on top (LE)
+}
+
+/// Frame of an open-ended byte stream carried by `PayloadKind::Stream`, e.g. voice or telemetry:
+/// the `StreamKind` and the u32 stream ID picked by the sender (LE), then what the kind says.
+/// Streams to a single party are flow controlled by credits, the sender only sending as many
+/// bytes as its destination granted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamFrame {
+    pub kind: StreamKind,
+    pub stream_id: u32,
+    pub data: Vec<u8>,
+}
+
+impl StreamFrame {
+    pub const LENGTH_STREAM_HEADER: usize = 5;
+    pub const LENGTH_CREDIT: usize = 4;
+    pub const OFFSET_KIND: usize = 0;
+    pub const RANGE_STREAM_ID: Range<usize> = 1..5;
+
+    // Bytes granted by a Credit frame, 0 for the other kinds
+    pub fn credit(&self) -> u32 {
+        match (self.kind, self.data.as_slice()) {
+            (StreamKind::Credit, [first, second, third, fourth]) => {
+                u32::from_le_bytes([*first, *second, *third, *fourth])
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn from_raw(source: &[u8]) -> AnyResult<Self> {
+        if source.len() < StreamFrame::LENGTH_STREAM_HEADER {
+            return Err(anyerror!(
+                "Stream frame length is less than {}",
+                StreamFrame::LENGTH_STREAM_HEADER
+            ));
+        }
+
+        let kind = StreamKind::try_from(source[StreamFrame::OFFSET_KIND]).map_err(|_| {
+            anyerror!("Unknown stream frame kind {:02X}", source[StreamFrame::OFFSET_KIND])
+        })?;
+        let mut u32_bytes = [0u8; 4];
+        u32_bytes.copy_from_slice(&source[StreamFrame::RANGE_STREAM_ID]);
+        let data = source[StreamFrame::LENGTH_STREAM_HEADER..].to_vec();
+        let expected_length = match kind {
+            StreamKind::Chunk => None,
+            StreamKind::Credit => Some(StreamFrame::LENGTH_CREDIT),
+            StreamKind::Open | StreamKind::Close => Some(0),
+        };
+
+        if expected_length.is_some_and(|expected_length| data.len() != expected_length) {
+            return Err(anyerror!("Stream {:?} frame has {} bytes after its ID", kind, data.len()));
+        }
+
+        Ok(Self { kind, stream_id: u32::from_le_bytes(u32_bytes), data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_frame_from_raw_is_as_expected() {
+        let chunk = StreamFrame::from_raw(&[0x02, 0x05, 0, 0, 0, 0xAA, 0xBB]).unwrap();
+        let credit = StreamFrame::from_raw(&[0x0C, 0x05, 0, 0, 0, 0x00, 0x01, 0, 0]).unwrap();
+
+        assert_eq!(
+            chunk,
+            StreamFrame { kind: StreamKind::Chunk, stream_id: 5, data: vec![0xAA, 0xBB] }
+        );
+        assert_eq!(chunk.credit(), 0);
+        assert_eq!(credit.kind, StreamKind::Credit);
+        assert_eq!(credit.credit(), 256);
+
+        // Truncated, of an unknown kind, or with bytes its kind does not take
+        assert!(StreamFrame::from_raw(&[0x02, 0x05, 0, 0]).is_err());
+        assert!(StreamFrame::from_raw(&[0x04, 0x05, 0, 0, 0]).is_err());
+        assert!(StreamFrame::from_raw(&[0x01, 0x05, 0, 0, 0, 0xAA]).is_err());
+        assert!(StreamFrame::from_raw(&[0x0C, 0x05, 0, 0, 0, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{anyerror, AnyResult};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// NTP-like exchange carried by `PayloadKind::TimeSync` frames. The requester fills
+/// `origin_timestamp` with its own clock, the router echoes it back along with its receive and
+/// transmit timestamps (microseconds since UNIX epoch) so the requester can derive both the
+/// round trip time and its offset to the router clock.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TimeSync {
+    pub origin_timestamp: u64,
+    pub receive_timestamp: u64,
+    pub transmit_timestamp: u64,
+}
+
+impl TimeSync {
+    pub const LENGTH_REQUEST: usize = 8;
+    pub const LENGTH_RESPONSE: usize = 24;
+    pub const RANGE_ORIGIN_TIMESTAMP: Range<usize> = 0..8;
+    pub const RANGE_RECEIVE_TIMESTAMP: Range<usize> = 8..16;
+    pub const RANGE_TRANSMIT_TIMESTAMP: Range<usize> = 16..24;
+
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or(0)
+    }
+
+    pub fn from_request(payload: &[u8]) -> AnyResult<Self> {
+        if payload.len() < TimeSync::LENGTH_REQUEST {
+            return Err(anyerror!(
+                "TimeSync request payload length is less than {}",
+                TimeSync::LENGTH_REQUEST
+            ));
+        }
+
+        let mut u64_bytes = [0u8; 8];
+        u64_bytes.copy_from_slice(&payload[TimeSync::RANGE_ORIGIN_TIMESTAMP]);
+
+        Ok(Self { origin_timestamp: u64::from_le_bytes(u64_bytes), ..Default::default() })
+    }
+
+    pub fn into_response(self) -> [u8; TimeSync::LENGTH_RESPONSE] {
+        let mut result = [0u8; TimeSync::LENGTH_RESPONSE];
+        result[TimeSync::RANGE_ORIGIN_TIMESTAMP]
+            .copy_from_slice(&self.origin_timestamp.to_le_bytes());
+        result[TimeSync::RANGE_RECEIVE_TIMESTAMP]
+            .copy_from_slice(&self.receive_timestamp.to_le_bytes());
+        result[TimeSync::RANGE_TRANSMIT_TIMESTAMP]
+            .copy_from_slice(&self.transmit_timestamp.to_le_bytes());
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_sync_response_is_as_expected() {
+        let mut time_sync = TimeSync::from_request(&[0x01, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        time_sync.receive_timestamp = 2;
+        time_sync.transmit_timestamp = 3;
+        let expected_result =
+            [0x01, 0, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0, 0x03, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(time_sync.into_response(), expected_result);
+    }
+
+    #[test]
+    fn test_time_sync_short_request_is_rejected() {
+        assert!(TimeSync::from_request(&[0x01, 0x02]).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use super::{MessageStream, PartyId};
+use crate::{anyerror, AnyResult};
+use std::ops::Range;
+
+/// One routed frame of a traffic recording, laid out as a `u64` timestamp (microseconds since
+/// UNIX epoch), the `u32` origin party ID, the `u32` frame length and the raw frame, all LE.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TrafficRecord {
+    pub timestamp: u64,
+    pub origin_id: PartyId,
+    pub message_stream: MessageStream,
+}
+
+impl TrafficRecord {
+    pub const LENGTH_RECORD_HEADER: usize = 16;
+    pub const RANGE_TIMESTAMP: Range<usize> = 0..8;
+    pub const RANGE_ORIGIN_ID: Range<usize> = 8..12;
+    pub const RANGE_FRAME_LENGTH: Range<usize> = 12..16;
+
+    // Parses the record at the start of source, returns it with the number of bytes consumed
+    pub fn from_raw(source: &[u8]) -> AnyResult<(Self, usize)> {
+        if source.len() < TrafficRecord::LENGTH_RECORD_HEADER {
+            return Err(anyerror!("Truncated traffic record header"));
+        }
+
+        let mut u64_bytes = [0u8; 8];
+        u64_bytes.copy_from_slice(&source[TrafficRecord::RANGE_TIMESTAMP]);
+        let mut u32_bytes = [0u8; 4];
+        u32_bytes.copy_from_slice(&source[TrafficRecord::RANGE_ORIGIN_ID]);
+        let origin_id = PartyId::from_u32(u32::from_le_bytes(u32_bytes));
+        u32_bytes.copy_from_slice(&source[TrafficRecord::RANGE_FRAME_LENGTH]);
+        let record_length =
+            TrafficRecord::LENGTH_RECORD_HEADER + u32::from_le_bytes(u32_bytes) as usize;
+
+        if source.len() < record_length {
+            return Err(anyerror!("Truncated traffic record frame"));
+        }
+
+        let message_stream =
+            MessageStream::from_raw(&source[TrafficRecord::LENGTH_RECORD_HEADER..record_length])?;
+
+        Ok((
+            Self { timestamp: u64::from_le_bytes(u64_bytes), origin_id, message_stream },
+            record_length,
+        ))
+    }
+
+    pub fn into_raw(self) -> Vec<u8> {
+        let frame = self.message_stream.into_raw();
+        let mut result = Vec::with_capacity(TrafficRecord::LENGTH_RECORD_HEADER + frame.len());
+        result.extend_from_slice(&self.timestamp.to_le_bytes());
+        result.extend_from_slice(&self.origin_id.to_le_bytes());
+        result.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        result.extend_from_slice(&frame);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PayloadKind};
+
+    #[test]
+    fn test_traffic_record_round_trip_is_as_expected() {
+        let traffic_record = TrafficRecord {
+            timestamp: 1_600_000_000_000_000,
+            origin_id: PartyId::Client(4),
+            message_stream: MessageStream::new(
+                MessageCode::Normal,
+                2,
+                PartyId::Client(4),
+                PartyId::Server(0),
+                PayloadKind::Data,
+                Some(&[0x01, 0x02, 0x03]),
+            ),
+        };
+        let mut raw = traffic_record.clone().into_raw();
+        let record_length = raw.len();
+        raw.extend_from_slice(&[0xFF; 4]); // Start of the next record
+
+        assert_eq!(record_length, TrafficRecord::LENGTH_RECORD_HEADER + 23);
+        assert_eq!(TrafficRecord::from_raw(&raw).unwrap(), (traffic_record, record_length));
+        assert!(TrafficRecord::from_raw(&raw[..record_length - 1]).is_err());
+    }
+}
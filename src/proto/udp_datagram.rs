@@ -0,0 +1,133 @@
+use super::MessageStream;
+use crate::{anyerror, AnyResult};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+pub enum UdpKind {
+    Unreliable = 0x01, // Followed by a raw MessageStream, never acknowledged
+    Reliable = 0x02,   // Followed by a raw MessageStream, acknowledged and resent until then
+    Ack = 0x0A,        // Nothing follows, the sequence is the acknowledged one
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UdpPayload {
+    Unreliable(MessageStream),
+    Reliable(MessageStream),
+    Ack,
+}
+
+/// Datagram of the UDP relay, the 16 bytes session token told over the client connection, the
+/// `UdpKind`, the u32 sequence (LE) and the raw `MessageStream` if any. Sequences only matter to
+/// reliable datagrams and their acks, each side numbers its reliable datagrams from 1.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UdpDatagram {
+    pub token: [u8; 16],
+    pub sequence: u32,
+    pub payload: UdpPayload,
+}
+
+impl UdpDatagram {
+    pub const LENGTH_UDP_HEADER: usize = 21;
+    pub const RANGE_TOKEN: Range<usize> = 0..16;
+    pub const RANGE_SEQUENCE: Range<usize> = 17..21;
+    pub const OFFSET_KIND: usize = 16;
+
+    pub fn kind(&self) -> UdpKind {
+        match self.payload {
+            UdpPayload::Unreliable(_) => UdpKind::Unreliable,
+            UdpPayload::Reliable(_) => UdpKind::Reliable,
+            UdpPayload::Ack => UdpKind::Ack,
+        }
+    }
+
+    pub fn into_raw(self) -> Vec<u8> {
+        let mut result = self.token.to_vec();
+        result.push(self.kind().into());
+        result.extend_from_slice(&self.sequence.to_le_bytes());
+
+        match self.payload {
+            UdpPayload::Unreliable(message_stream) | UdpPayload::Reliable(message_stream) => {
+                result.extend_from_slice(&message_stream.into_raw())
+            }
+            UdpPayload::Ack => (),
+        }
+
+        result
+    }
+
+    pub fn from_raw(source: &[u8]) -> AnyResult<Self> {
+        if source.len() < UdpDatagram::LENGTH_UDP_HEADER {
+            return Err(anyerror!(
+                "UDP datagram length is less than {}",
+                UdpDatagram::LENGTH_UDP_HEADER
+            ));
+        }
+
+        let mut token = [0u8; 16];
+        token.copy_from_slice(&source[UdpDatagram::RANGE_TOKEN]);
+        let udp_kind = UdpKind::try_from(source[UdpDatagram::OFFSET_KIND]).map_err(|_| {
+            anyerror!("Unknown UDP datagram kind {:02X}", source[UdpDatagram::OFFSET_KIND])
+        })?;
+        let mut u32_bytes = [0u8; 4];
+        u32_bytes.copy_from_slice(&source[UdpDatagram::RANGE_SEQUENCE]);
+        let sequence = u32::from_le_bytes(u32_bytes);
+        let message_raw = &source[UdpDatagram::LENGTH_UDP_HEADER..];
+
+        let payload = match udp_kind {
+            UdpKind::Unreliable => UdpPayload::Unreliable(MessageStream::from_raw(message_raw)?),
+            UdpKind::Reliable => UdpPayload::Reliable(MessageStream::from_raw(message_raw)?),
+            UdpKind::Ack => UdpPayload::Ack,
+        };
+
+        Ok(Self { token, sequence, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PartyId, PayloadKind};
+
+    #[test]
+    fn test_udp_datagram_raw_is_as_expected() {
+        let message_stream = MessageStream::new(
+            MessageCode::Normal,
+            7,
+            PartyId::Client(2),
+            PartyId::AllClients,
+            PayloadKind::Data,
+            Some(&[0xAA, 0xBB]),
+        );
+        let udp_datagrams = vec![
+            UdpDatagram {
+                token: [0x11; 16],
+                sequence: 0,
+                payload: UdpPayload::Unreliable(message_stream.clone()),
+            },
+            UdpDatagram {
+                token: [0x11; 16],
+                sequence: 1,
+                payload: UdpPayload::Reliable(message_stream),
+            },
+            UdpDatagram { token: [0x11; 16], sequence: 1, payload: UdpPayload::Ack },
+        ];
+
+        for udp_datagram in udp_datagrams {
+            assert_eq!(
+                UdpDatagram::from_raw(&udp_datagram.clone().into_raw()).unwrap(),
+                udp_datagram
+            );
+        }
+
+        let ack_raw = UdpDatagram { token: [0x22; 16], sequence: 258, payload: UdpPayload::Ack };
+        let mut expected_raw = vec![0x22; 16];
+        expected_raw.extend_from_slice(&[0x0A, 0x02, 0x01, 0, 0]);
+
+        assert_eq!(ack_raw.into_raw(), expected_raw);
+        assert!(UdpDatagram::from_raw(&[0x22; 20]).is_err());
+        assert!(UdpDatagram::from_raw(&[0x7F; 21]).is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! Node to node relay of the static cluster. Every instance keeps one outbound `RelayActor` per
+//! peer, reconnecting with backoff, and accepts the relays of its peers on `/relay` with a
+//! `RelayListenerActor`. Frames are numbered per sender epoch and kept by the sender until the
+//! receiver acknowledges them, so the frames of a dropped connection are resent on the next one
+//! and the receiver drops the ones it already handled.
+
+use crate::proto::{RelayFrame, RelayPayload};
+use crate::reconnect::{ReconnectPolicy, ReconnectState};
+use crate::ws_handlers::{
+    GameRoomRouterActor, RelayConnected, RelayOut, RelayReset, Relayed, CLIENT_TIMEOUT,
+    HEARTBEAT_INTERVAL, MAILBOX_CAPACITY,
+};
+use actix::clock::{Duration, Instant};
+use actix::fut::wrap_future;
+use actix::io::{SinkWrite, WriteHandler};
+use actix::{
+    Actor as ActixActor, ActorContext, ActorFuture, Addr as ActorAddress, AsyncContext, Context,
+    Handler as MessageHandler, Running, StreamHandler as ReceiveHandler,
+};
+use actix_codec::Framed;
+use actix_web_actors::ws::{
+    Message as WsMessage, ProtocolError as WsProtocolError, WebsocketContext,
+};
+use awc::ws::{Codec as WsCodec, Frame};
+use awc::{BoxedSocket, Client};
+use futures::stream::{SplitSink, StreamExt};
+use log::{info, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+// How often a listener acknowledges what it received
+pub(crate) const RELAY_ACK_INTERVAL: Duration = Duration::from_millis(100);
+// Frames kept for a peer not acknowledging them, the oldest ones are dropped beyond
+const RELAY_BUFFER_CAPACITY: usize = 4096;
+
+type PeerSink = SplitSink<Framed<BoxedSocket, WsCodec>, WsMessage>;
+
+// Peer URL -> (Epoch, Last sequence handled), shared by the listeners so a reconnecting peer is
+// deduplicated against what its previous connection delivered
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RelaySequences(Arc<Mutex<BTreeMap<String, (Uuid, u64)>>>);
+
+impl RelaySequences {
+    // Whether the epoch is new for the peer, its sequences start over then, and the last sequence
+    // handled for it
+    pub(crate) fn begin(&self, peer_url: &str, epoch: Uuid) -> (bool, u64) {
+        let mut write_guard = match self.0.lock() {
+            Ok(write_guard) => write_guard,
+            Err(_) => return (false, 0),
+        };
+
+        match write_guard.get(peer_url) {
+            Some((known_epoch, last_sequence)) if *known_epoch == epoch => (false, *last_sequence),
+            _ => {
+                write_guard.insert(peer_url.into(), (epoch, 0));
+                (true, 0)
+            }
+        }
+    }
+
+    // A sequence is only handled once per epoch, a connection of a former epoch is ignored
+    pub(crate) fn accept(&self, peer_url: &str, epoch: Uuid, sequence: u64) -> bool {
+        let mut write_guard = match self.0.lock() {
+            Ok(write_guard) => write_guard,
+            Err(_) => return false,
+        };
+
+        match write_guard.get_mut(peer_url) {
+            Some((known_epoch, last_sequence))
+                if *known_epoch == epoch && sequence > *last_sequence =>
+            {
+                *last_sequence = sequence;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub(crate) struct RelayActor {
+    peer_url: String,
+    node_url: String,
+    base_path: String, // The peers serve their routes under the same --base-path
+    epoch: Uuid,       // New for every process, the peer forgets what it knew of the previous one
+    router_address: ActorAddress<GameRoomRouterActor>,
+    reconnect_state: ReconnectState,
+    next_sequence: u64,
+    unacked_frames: VecDeque<RelayFrame>,
+    peer_sink: Option<SinkWrite<WsMessage, PeerSink>>,
+    last_known_activity: Instant,
+}
+
+impl RelayActor {
+    pub(crate) fn new(
+        peer_url: String,
+        node_url: String,
+        epoch: Uuid,
+        router_address: ActorAddress<GameRoomRouterActor>,
+    ) -> Self {
+        Self {
+            peer_url,
+            node_url,
+            base_path: String::new(),
+            epoch,
+            router_address,
+            reconnect_state: ReconnectState::new(ReconnectPolicy::default()),
+            next_sequence: 1,
+            unacked_frames: VecDeque::new(),
+            peer_sink: None,
+            last_known_activity: Instant::now(),
+        }
+    }
+
+    pub(crate) fn with_base_path(mut self, base_path: String) -> Self {
+        self.base_path = base_path;
+        self
+    }
+
+    fn relay_url(&self) -> String {
+        format!(
+            "{}{}/relay?node_url={}&epoch={}",
+            self.peer_url, self.base_path, self.node_url, self.epoch
+        )
+    }
+
+    fn connect(&mut self, context: &mut Context<Self>) {
+        let connect_future = wrap_future::<_, Self>(Client::new().ws(self.relay_url()).connect());
+
+        context.spawn(connect_future.map(|connect_result, actor, context| match connect_result {
+            Ok((_, peer_framed)) => actor.on_connected(peer_framed, context),
+            Err(error) => {
+                warn!("Relay to peer {} failed: {}", actor.peer_url, error);
+                actor.schedule_reconnect(context);
+            }
+        }));
+    }
+
+    // Frames not acknowledged yet are resent in order, the peer drops the ones it handled
+    fn on_connected(
+        &mut self,
+        peer_framed: Framed<BoxedSocket, WsCodec>,
+        context: &mut Context<Self>,
+    ) {
+        let (peer_sink, peer_stream) = peer_framed.split();
+        let mut peer_sink = SinkWrite::new(peer_sink, context);
+        context.add_stream(peer_stream);
+
+        for relay_frame in self.unacked_frames.iter() {
+            let _ = peer_sink.write(WsMessage::Binary(relay_frame.clone().into_raw().into()));
+        }
+
+        info!(
+            "Relay to peer {} joined, resending {} frames",
+            self.peer_url,
+            self.unacked_frames.len()
+        );
+        self.peer_sink = Some(peer_sink);
+        self.last_known_activity = Instant::now();
+        self.reconnect_state.on_connected();
+        self.router_address
+            .do_send(RelayConnected(self.peer_url.clone(), context.address().recipient()));
+    }
+
+    fn on_disconnected(&mut self, context: &mut Context<Self>) {
+        if let Some(mut peer_sink) = self.peer_sink.take() {
+            peer_sink.close();
+            warn!("Relay to peer {} lost", self.peer_url);
+            self.schedule_reconnect(context);
+        }
+    }
+
+    fn schedule_reconnect(&mut self, context: &mut Context<Self>) {
+        let reconnect_delay = self.reconnect_state.on_failure().unwrap_or(HEARTBEAT_INTERVAL);
+        context.run_later(reconnect_delay, |actor, context| actor.connect(context));
+    }
+
+    // The listener pings every heartbeat, a silent peer is reconnected to
+    fn heartbeat(&self, context: &mut Context<Self>) {
+        context.run_interval(HEARTBEAT_INTERVAL, |actor, context| {
+            if actor.peer_sink.is_some()
+                && Instant::now().duration_since(actor.last_known_activity) > CLIENT_TIMEOUT
+            {
+                actor.on_disconnected(context);
+            }
+        });
+    }
+}
+
+impl ActixActor for RelayActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+        self.heartbeat(context);
+        self.connect(context);
+    }
+}
+
+impl WriteHandler<WsProtocolError> for RelayActor {}
+
+impl MessageHandler<RelayOut> for RelayActor {
+    type Result = ();
+
+    fn handle(&mut self, message: RelayOut, _: &mut Self::Context) {
+        let relay_frame = RelayFrame { sequence: self.next_sequence, payload: message.0 };
+        self.next_sequence += 1;
+
+        if self.unacked_frames.len() >= RELAY_BUFFER_CAPACITY {
+            self.unacked_frames.pop_front();
+            warn!("Relay to peer {} dropped its oldest unacknowledged frame", self.peer_url);
+        }
+
+        if let Some(peer_sink) = self.peer_sink.as_mut() {
+            let _ = peer_sink.write(WsMessage::Binary(relay_frame.clone().into_raw().into()));
+        }
+
+        self.unacked_frames.push_back(relay_frame);
+    }
+}
+
+impl ReceiveHandler<Result<Frame, WsProtocolError>> for RelayActor {
+    fn handle(
+        &mut self,
+        stream_result: Result<Frame, WsProtocolError>,
+        context: &mut Self::Context,
+    ) {
+        self.last_known_activity = Instant::now();
+
+        match stream_result {
+            Ok(Frame::Binary(binary_payload)) => match RelayFrame::from_raw(&binary_payload) {
+                Ok(RelayFrame { sequence, payload: RelayPayload::Ack }) => {
+                    while self
+                        .unacked_frames
+                        .front()
+                        .map(|relay_frame| relay_frame.sequence <= sequence)
+                        == Some(true)
+                    {
+                        self.unacked_frames.pop_front();
+                    }
+                }
+                Ok(_) => (),
+                Err(error) => warn!("Relay peer {} sent {}", self.peer_url, error),
+            },
+            Ok(Frame::Ping(ping_payload)) => {
+                if let Some(peer_sink) = self.peer_sink.as_mut() {
+                    let _ = peer_sink.write(WsMessage::Pong(ping_payload));
+                }
+            }
+            Ok(Frame::Close(_)) | Err(_) => self.on_disconnected(context),
+            Ok(_) => (),
+        }
+    }
+
+    // The actor outlives its connections
+    fn finished(&mut self, context: &mut Self::Context) {
+        self.on_disconnected(context);
+    }
+}
+
+pub(crate) struct RelayListenerActor {
+    peer_url: String,
+    epoch: Uuid,
+    router_address: ActorAddress<GameRoomRouterActor>,
+    relay_sequences: RelaySequences,
+    received_sequence: u64,
+    acked_sequence: u64,
+    last_known_activity: Instant,
+}
+
+impl RelayListenerActor {
+    pub(crate) fn new(
+        peer_url: String,
+        epoch: Uuid,
+        router_address: ActorAddress<GameRoomRouterActor>,
+        relay_sequences: RelaySequences,
+    ) -> Self {
+        Self {
+            peer_url,
+            epoch,
+            router_address,
+            relay_sequences,
+            received_sequence: 0,
+            acked_sequence: 0,
+            last_known_activity: Instant::now(),
+        }
+    }
+
+    fn heartbeat(&self, context: &mut WebsocketContext<Self>) {
+        context.run_interval(HEARTBEAT_INTERVAL, |actor, context| {
+            if Instant::now().duration_since(actor.last_known_activity) > CLIENT_TIMEOUT {
+                info!(
+                    "Relay from peer {} dropped because of {:#?} inactivity!",
+                    actor.peer_url, CLIENT_TIMEOUT
+                );
+                context.close(None);
+                context.stop();
+            } else {
+                context.ping(b"");
+            }
+        });
+    }
+
+    fn acknowledge(&self, context: &mut WebsocketContext<Self>) {
+        context.run_interval(RELAY_ACK_INTERVAL, |actor, context| {
+            if actor.received_sequence > actor.acked_sequence {
+                let ack_frame =
+                    RelayFrame { sequence: actor.received_sequence, payload: RelayPayload::Ack };
+                context.binary(ack_frame.into_raw());
+                actor.acked_sequence = actor.received_sequence;
+            }
+        });
+    }
+}
+
+impl ActixActor for RelayListenerActor {
+    type Context = WebsocketContext<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+
+        let (is_new_epoch, last_sequence) = self.relay_sequences.begin(&self.peer_url, self.epoch);
+
+        if is_new_epoch {
+            self.router_address.do_send(RelayReset(self.peer_url.clone()));
+        }
+
+        // Whatever an earlier connection handled is acknowledged right away
+        self.received_sequence = last_sequence;
+        info!("Relay from peer {} joined", self.peer_url);
+        self.heartbeat(context);
+        self.acknowledge(context);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        info!("Relay from peer {} left", self.peer_url);
+
+        Running::Stop
+    }
+}
+
+impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for RelayListenerActor {
+    fn handle(
+        &mut self,
+        stream_result: Result<WsMessage, WsProtocolError>,
+        context: &mut Self::Context,
+    ) {
+        self.last_known_activity = Instant::now();
+
+        match stream_result {
+            Ok(WsMessage::Binary(binary_payload)) => match RelayFrame::from_raw(&binary_payload) {
+                Ok(RelayFrame { payload: RelayPayload::Ack, .. }) => (),
+                Ok(RelayFrame { sequence, payload }) => {
+                    if self.relay_sequences.accept(&self.peer_url, self.epoch, sequence) {
+                        self.received_sequence = sequence;
+                        self.router_address.do_send(Relayed(self.peer_url.clone(), payload));
+                    }
+                }
+                Err(error) => warn!("Relay peer {} sent {}", self.peer_url, error),
+            },
+            Ok(WsMessage::Ping(ping_payload)) => context.pong(&ping_payload),
+            Ok(WsMessage::Close(reason)) => {
+                context.close(reason);
+                context.stop();
+            }
+            Ok(_) => (),
+            Err(_) => context.stop(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws_handlers::RouterHarness;
+
+    #[test]
+    fn test_relay_sequences_is_as_expected() {
+        let relay_sequences = RelaySequences::default();
+        let peer_url = "ws://10.0.0.2:7575";
+        let (first_epoch, second_epoch) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert_eq!(relay_sequences.begin(peer_url, first_epoch), (true, 0));
+        assert!(relay_sequences.accept(peer_url, first_epoch, 1));
+        assert!(relay_sequences.accept(peer_url, first_epoch, 2));
+
+        // A reconnect of the same epoch resends what was not acknowledged yet
+        assert_eq!(relay_sequences.begin(peer_url, first_epoch), (false, 2));
+        assert!(!relay_sequences.accept(peer_url, first_epoch, 2));
+        assert!(relay_sequences.accept(peer_url, first_epoch, 3));
+
+        // A restarted peer numbers its frames from 1 again, its former connection is ignored
+        assert_eq!(relay_sequences.begin(peer_url, second_epoch), (true, 0));
+        assert!(relay_sequences.accept(peer_url, second_epoch, 1));
+        assert!(!relay_sequences.accept(peer_url, first_epoch, 4));
+    }
+
+    #[actix_rt::test]
+    async fn test_relay_url_is_as_expected() {
+        let harness = RouterHarness::start(Default::default(), &[0]).await;
+        let epoch = Uuid::new_v4();
+        let relay_actor = |base_path: &str| {
+            RelayActor::new(
+                "ws://10.0.0.2:7575".into(),
+                "ws://10.0.0.1:7575".into(),
+                epoch,
+                harness.router.clone(),
+            )
+            .with_base_path(base_path.into())
+        };
+
+        assert_eq!(
+            relay_actor("").relay_url(),
+            format!("ws://10.0.0.2:7575/relay?node_url=ws://10.0.0.1:7575&epoch={}", epoch)
+        );
+
+        // The peer serves its routes under the same --base-path
+        assert_eq!(
+            relay_actor("/gameroom").relay_url(),
+            format!(
+                "ws://10.0.0.2:7575/gameroom/relay?node_url=ws://10.0.0.1:7575&epoch={}",
+                epoch
+            )
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::proto::{MessageCode, MessageStream, PartyId, PayloadKind, TimeSync, TrafficRecord};
+use crate::{anyerror, AnyResult};
+use actix::clock::{delay_until, Duration, Instant};
+use awc::ws::{Frame, Message as WsMessage};
+use awc::Client;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::{SinkExt, StreamExt};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+const GRACE_DURATION: Duration = Duration::from_millis(500);
+
+/// Feed the client frames of a traffic recording to the game server through a running instance
+#[derive(StructOpt, Debug)]
+pub(crate) struct ReplayOptions {
+    /// Base URL of the instance the game server is connected to
+    #[structopt(short, long, default_value = "ws://127.0.0.1:7575")]
+    pub(crate) url: String,
+    /// Playback speed factor, 2.0 replays twice as fast
+    #[structopt(long, default_value = "1.0")]
+    pub(crate) speed: f64,
+    /// Recording written with --record-traffic
+    #[structopt(parse(from_os_str))]
+    pub(crate) recording: PathBuf,
+}
+
+// Stand-in for a recorded client, joined with the party ID the router assigns this time
+struct ReplayConnection {
+    party_id: PartyId,
+    outbound_sender: UnboundedSender<MessageStream>,
+}
+
+pub(crate) async fn run(options: ReplayOptions) -> AnyResult<String> {
+    if options.speed <= 0.0 {
+        return Err(anyerror!("Speed should be greater than 0"));
+    }
+
+    let base_url = options.url.trim_end_matches('/');
+    let recording = std::fs::read(&options.recording)?;
+    let mut offset = 0;
+    let mut first_timestamp = None;
+    let mut connections: BTreeMap<(u32, u32), ReplayConnection> = BTreeMap::new(); // (Room ID, Recorded Client ID)
+    let mut replayed_count = 0;
+    let mut skipped_count = 0;
+    let started_at = Instant::now();
+
+    while offset < recording.len() {
+        let (traffic_record, consumed) = TrafficRecord::from_raw(&recording[offset..])?;
+        offset += consumed;
+
+        // Server frames are produced again by the live game server
+        let recorded_client_id = match traffic_record.origin_id {
+            PartyId::Client(recorded_client_id) => recorded_client_id,
+            _ => {
+                skipped_count += 1;
+                continue;
+            }
+        };
+
+        let first_timestamp = *first_timestamp.get_or_insert(traffic_record.timestamp);
+        let elapsed_us = traffic_record.timestamp.saturating_sub(first_timestamp) as f64;
+        delay_until(started_at + Duration::from_micros((elapsed_us / options.speed) as u64)).await;
+
+        let mut message_stream = traffic_record.message_stream;
+        let room_id = message_stream.room_id;
+
+        if let Entry::Vacant(vacant_entry) = connections.entry((room_id, recorded_client_id)) {
+            vacant_entry.insert(open_connection(base_url, room_id).await?);
+        }
+
+        if let PartyId::Client(recorded_destination_id) = message_stream.destination_id {
+            if let Some(connection) = connections.get(&(room_id, recorded_destination_id)) {
+                message_stream.destination_id = connection.party_id;
+            }
+        }
+
+        if let Some(connection) = connections.get(&(room_id, recorded_client_id)) {
+            message_stream.origin_id = connection.party_id;
+
+            if connection.outbound_sender.unbounded_send(message_stream).is_ok() {
+                replayed_count += 1;
+            }
+        }
+    }
+
+    delay_until(Instant::now() + GRACE_DURATION).await;
+    let connection_count = connections.len();
+    drop(connections);
+    delay_until(Instant::now() + GRACE_DURATION).await;
+
+    Ok(format!(
+        "replayed {} client frames through {} connections, skipped {} server frames",
+        replayed_count, connection_count, skipped_count
+    ))
+}
+
+async fn open_connection(base_url: &str, room_id: u32) -> AnyResult<ReplayConnection> {
+    let client_url =
+        format!("{}/client?client_id={}&room_id={}", base_url, Uuid::new_v4(), room_id);
+    let mut framed = Client::new()
+        .ws(client_url)
+        .connect()
+        .await
+        .map_err(|error| anyerror!("Failed to join room {}: {}", room_id, error))?
+        .1;
+
+    // The TimeSync reply is addressed to us, it tells the party ID assigned by the router
+    let time_sync = MessageStream::new(
+        MessageCode::Special,
+        room_id,
+        PartyId::Client(0),
+        PartyId::Server(0),
+        PayloadKind::TimeSync,
+        Some(&TimeSync::now().to_le_bytes()),
+    );
+    framed.send(WsMessage::Binary(time_sync.into_raw().into())).await?;
+
+    let party_id = loop {
+        match framed.next().await {
+            Some(Ok(Frame::Binary(binary_payload))) => {
+                match MessageStream::from_raw(&binary_payload) {
+                    Ok(message_stream) if message_stream.payload_kind == PayloadKind::TimeSync => {
+                        break message_stream.destination_id;
+                    }
+                    _ => (),
+                }
+            }
+            Some(Ok(Frame::Ping(ping_payload))) => {
+                framed.send(WsMessage::Pong(ping_payload)).await?;
+            }
+            Some(Ok(_)) => (),
+            _ => return Err(anyerror!("Room {} connection closed before TimeSync", room_id)),
+        }
+    };
+
+    let (outbound_sender, mut outbound_receiver) = unbounded::<MessageStream>();
+
+    // Inbound frames are discarded, only the heartbeat is answered
+    actix::spawn(async move {
+        loop {
+            tokio::select! {
+                message_stream = outbound_receiver.next() => match message_stream {
+                    Some(message_stream) => {
+                        let raw = message_stream.into_raw();
+
+                        if framed.send(WsMessage::Binary(raw.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => {
+                        let _ = framed.send(WsMessage::Close(None)).await;
+                        break;
+                    }
+                },
+                frame = framed.next() => match frame {
+                    Some(Ok(Frame::Ping(ping_payload))) => {
+                        let _ = framed.send(WsMessage::Pong(ping_payload)).await;
+                    }
+                    Some(Ok(_)) => (),
+                    _ => break,
+                },
+            }
+        }
+    });
+
+    Ok(ReplayConnection { party_id, outbound_sender })
+}
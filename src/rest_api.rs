@@ -0,0 +1,248 @@
+//! Version 1 of the REST API: every HTTP endpoint is served under `/v1` as well as at its legacy
+//! path, e.g. `/v1/rooms/{room_id}/presence` and `/rooms/{room_id}/presence`. Under `/v1`, errors
+//! are answered with a JSON envelope `{"error": {"status": 404, "message": "No room 7!"}}`, as
+//! plain text when the `Accept` header prefers `text/plain`, and a response of a media type the
+//! `Accept` header excludes is replaced with a `406` error. The legacy paths are left as they were.
+//!
+//! The routes are described by the OpenAPI document at `/v1/openapi.json`, kept by hand in
+//! `openapi.json` next to this file, for the client teams to generate their SDKs from. A test
+//! checks it against the registered routes, both ways.
+
+use actix_web::dev::{Body, ResponseBody, Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, ACCEPT, CONTENT_TYPE, UPGRADE, VARY};
+use actix_web::http::StatusCode;
+use actix_web::{Error as ActixError, HttpRequest, HttpResponse};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde_json::{json, Value as JsonValue};
+
+pub(crate) const API_PATH: &str = "/v1";
+const JSON_MEDIA_TYPE: &str = "application/json";
+const TEXT_MEDIA_TYPE: &str = "text/plain";
+const OPENAPI_DOCUMENT: &str = include_str!("openapi.json");
+
+// Quality the Accept header gives the media type, 1 when absent. The most specific range wins,
+// e.g. "text/*;q=0.5, */*;q=0" accepts text/plain at 0.5 and refuses application/json
+fn accept_quality(accept: Option<&str>, media_type: &str) -> f32 {
+    let accept = match accept {
+        Some(accept) if !accept.trim().is_empty() => accept,
+        _ => return 1.0,
+    };
+    let media_type = media_type.split(';').next().unwrap_or_default().trim();
+    let media_group = media_type.split('/').next().unwrap_or_default();
+    let mut best_match: Option<(u8, f32)> = None; // (Specificity, Quality)
+
+    for media_range in accept.split(',') {
+        let mut parameters = media_range.split(';');
+        let range = parameters.next().unwrap_or_default().trim();
+        let quality = parameters
+            .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+            .find_map(|quality| quality.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let specificity = match range.split_once('/') {
+            _ if range.eq_ignore_ascii_case(media_type) => 2,
+            Some((group, "*")) if group.eq_ignore_ascii_case(media_group) => 1,
+            Some(("*", "*")) => 0,
+            _ => continue,
+        };
+
+        if best_match.is_none_or(|(best_specificity, _)| specificity > best_specificity) {
+            best_match = Some((specificity, quality));
+        }
+    }
+
+    best_match.map_or(0.0, |(_, quality)| quality)
+}
+
+fn error_response(status: StatusCode, message: &str, is_text: bool) -> HttpResponse {
+    if is_text {
+        return HttpResponse::build(status).content_type(TEXT_MEDIA_TYPE).body(message.to_string());
+    }
+
+    HttpResponse::build(status)
+        .json(json!({ "error": { "status": status.as_u16(), "message": message } }))
+}
+
+// Text of an error body, the reason of its status when empty
+fn error_message(response: &ServiceResponse) -> String {
+    let body_bytes = match response.response().body() {
+        ResponseBody::Body(Body::Bytes(body_bytes))
+        | ResponseBody::Other(Body::Bytes(body_bytes)) => body_bytes.clone(),
+        _ => Default::default(),
+    };
+
+    match String::from_utf8_lossy(&body_bytes).trim() {
+        "" => response.status().canonical_reason().unwrap_or_default().to_string(),
+        message => message.to_string(),
+    }
+}
+
+// The document with the version of the build, its server being the /v1 scope it is served from
+// so that the --base-path is accounted for
+fn openapi_document(openapi_path: &str) -> JsonValue {
+    let mut document: JsonValue = serde_json::from_str(OPENAPI_DOCUMENT).unwrap();
+    let server_url = openapi_path.strip_suffix("/openapi.json").unwrap_or(API_PATH);
+    document["info"]["version"] = json!(env!("CARGO_PKG_VERSION"));
+    document["servers"] = json!([{ "url": server_url }]);
+
+    document
+}
+
+pub(crate) async fn get_openapi(request: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok().json(openapi_document(request.path()))
+}
+
+// Wraps the /v1 routes, WebSocket upgrades are left alone
+pub(crate) fn negotiate<S>(
+    request: ServiceRequest,
+    service: &mut S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse, ActixError>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = ActixError>,
+    S::Future: 'static,
+{
+    let headers = request.headers();
+
+    if headers.contains_key(UPGRADE) {
+        return service.call(request).boxed_local();
+    }
+
+    let accept =
+        headers.get(ACCEPT).and_then(|header_value| header_value.to_str().ok()).map(str::to_string);
+
+    service
+        .call(request)
+        .map(move |response| {
+            let response = response?;
+            let accept = accept.as_deref();
+            let is_text =
+                accept_quality(accept, TEXT_MEDIA_TYPE) > accept_quality(accept, JSON_MEDIA_TYPE);
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|header_value| header_value.to_str().ok())
+                .map(str::to_string);
+            let is_json = content_type
+                .as_deref()
+                .is_some_and(|content_type| content_type.starts_with(JSON_MEDIA_TYPE));
+
+            let status = response.status();
+            let is_error = status.is_client_error() || status.is_server_error();
+
+            // Errors already in JSON, e.g. from a JSON extractor, are kept
+            let mut response = match content_type {
+                _ if is_error && is_json && !is_text => response,
+                _ if is_error => {
+                    let message = error_message(&response);
+                    response.into_response(error_response(status, &message, is_text))
+                }
+                Some(content_type) if accept_quality(accept, &content_type) <= 0.0 => {
+                    let message = format!("{} is not acceptable", content_type);
+                    let refusal = error_response(StatusCode::NOT_ACCEPTABLE, &message, is_text);
+
+                    response.into_response(refusal)
+                }
+                _ => response,
+            };
+            response.headers_mut().append(VARY, HeaderValue::from_static("Accept"));
+
+            Ok(response)
+        })
+        .boxed_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::web::{get, resource, scope};
+    use actix_web::App;
+
+    #[test]
+    fn test_accept_quality_is_as_expected() {
+        assert_eq!(accept_quality(None, JSON_MEDIA_TYPE), 1.0);
+        assert_eq!(accept_quality(Some("application/json"), JSON_MEDIA_TYPE), 1.0);
+        assert_eq!(accept_quality(Some("text/html"), JSON_MEDIA_TYPE), 0.0);
+        assert_eq!(accept_quality(Some("text/*;q=0.5, */*;q=0"), TEXT_MEDIA_TYPE), 0.5);
+        assert_eq!(accept_quality(Some("text/*;q=0.5, */*;q=0"), JSON_MEDIA_TYPE), 0.0);
+        assert_eq!(accept_quality(Some("text/html, */*;q=0.8"), "text/plain; version=0.0.4"), 0.8);
+    }
+
+    #[actix_rt::test]
+    async fn test_negotiate_is_as_expected() {
+        let mut app = init_service(
+            App::new().service(
+                scope(API_PATH)
+                    .wrap_fn(negotiate)
+                    .service(resource("/rooms").route(get().to(|| HttpResponse::Ok().json([1]))))
+                    .service(
+                        resource("/missing")
+                            .route(get().to(|| HttpResponse::NotFound().body("No room 7!"))),
+                    ),
+            ),
+        )
+        .await;
+
+        let response =
+            call_service(&mut app, TestRequest::get().uri("/v1/missing").to_request()).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+
+        assert_eq!(body, serde_json::json!({"error": {"message": "No room 7!", "status": 404}}));
+
+        let request =
+            TestRequest::get().uri("/v1/missing").header(ACCEPT, "text/plain").to_request();
+        let response = call_service(&mut app, request).await;
+
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), TEXT_MEDIA_TYPE);
+        assert_eq!(read_body(response).await, "No room 7!");
+
+        let request = TestRequest::get().uri("/v1/rooms").header(ACCEPT, "text/html").to_request();
+        let response = call_service(&mut app, request).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(response.headers().get(VARY).unwrap(), "Accept");
+
+        let request = TestRequest::get()
+            .uri("/v1/rooms")
+            .header(ACCEPT, "text/html, application/*;q=0.9")
+            .to_request();
+        let response = call_service(&mut app, request).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(read_body(response).await, "[1]");
+    }
+
+    #[test]
+    fn test_openapi_document_is_as_expected() {
+        let document = openapi_document("/game/v1/openapi.json");
+
+        assert_eq!(document["openapi"], "3.0.3");
+        assert_eq!(document["info"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(document["servers"], json!([{ "url": "/game/v1" }]));
+
+        let mut operation_ids = std::collections::BTreeSet::new();
+
+        for (path, path_item) in document["paths"].as_object().unwrap() {
+            for (method, operation) in path_item.as_object().unwrap() {
+                if method == "parameters" {
+                    continue;
+                }
+
+                let operation_id = operation["operationId"].as_str().unwrap();
+                assert!(operation_ids.insert(operation_id), "{} {} reuses an ID", method, path);
+                assert!(!operation["responses"].as_object().unwrap().is_empty());
+            }
+        }
+
+        // Every reference resolves to a component
+        for reference in OPENAPI_DOCUMENT.split("\"$ref\": \"").skip(1) {
+            let pointer = reference.split('"').next().unwrap().trim_start_matches('#');
+            assert!(document.pointer(pointer).is_some(), "{} is unresolved", pointer);
+        }
+
+        for path in ["/", "/metrics", "/rooms/{room_id}/presence", "/admin/rooms", "/client/poll"] {
+            assert!(document["paths"].get(path).is_some(), "{} is undocumented", path);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! JSON log lines for log aggregation. Each event is one object carrying the instance ID and the
+//! fields of every entered span, so the lines of a connection can be filtered by room ID, party
+//! ID or client UUID. Events from the `log` macros are bridged with their original target. Plain
+//! lines keep the `[timestamp LEVEL target] message` layout of the console.
+
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use std::fmt::{Debug, Result as FmtResult};
+use std::time::SystemTime;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+use uuid::Uuid;
+
+pub(crate) struct JsonLineFormat {
+    instance_id: String,
+}
+
+impl JsonLineFormat {
+    pub(crate) fn new(instance_id: Uuid) -> Self {
+        Self { instance_id: instance_id.to_string() }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonLineFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        context: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let normalized_metadata = event.normalized_metadata();
+        let metadata = normalized_metadata.as_ref().unwrap_or_else(|| event.metadata());
+        let mut line = JsonMap::new();
+        line.insert(
+            "timestamp".into(),
+            humantime::format_rfc3339_micros(SystemTime::now()).to_string().into(),
+        );
+        line.insert("level".into(), metadata.level().to_string().into());
+        line.insert("target".into(), metadata.target().into());
+        line.insert("instance_id".into(), self.instance_id.clone().into());
+
+        // Outermost span first, an inner span overrides a field of the same name
+        if let Some(scope) = context.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+
+                if let Some(span_fields) = extensions.get::<FormattedFields<N>>() {
+                    if let Ok(JsonValue::Object(span_fields)) = serde_json::from_str(span_fields) {
+                        line.extend(span_fields);
+                    }
+                }
+            }
+        }
+
+        event.record(&mut JsonFieldVisitor(&mut line));
+
+        writeln!(writer, "{}", JsonValue::Object(line))
+    }
+}
+
+// Continuation lines of a message are indented under its first one
+const PLAIN_INDENT: &str = "\n    ";
+
+pub(crate) struct PlainLineFormat;
+
+impl<S, N> FormatEvent<S, N> for PlainLineFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        let normalized_metadata = event.normalized_metadata();
+        let metadata = normalized_metadata.as_ref().unwrap_or_else(|| event.metadata());
+        let mut message = String::new();
+        event.record(&mut PlainMessageVisitor(&mut message));
+
+        writeln!(
+            writer,
+            "[{} {:<5} {}] {}",
+            humantime::format_rfc3339_nanos(SystemTime::now()),
+            metadata.level(),
+            metadata.target(),
+            message.replace('\n', PLAIN_INDENT)
+        )
+    }
+}
+
+// Only the message is printed, span and event fields are left to the JSON lines
+struct PlainMessageVisitor<'a>(&'a mut String);
+
+impl Visit for PlainMessageVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            *self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+struct JsonFieldVisitor<'a>(&'a mut JsonMap<String, JsonValue>);
+
+impl JsonFieldVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: JsonValue) {
+        // Bridged `log` records repeat their metadata as log.* fields
+        if !field.name().starts_with("log.") {
+            self.0.insert(field.name().into(), value);
+        }
+    }
+}
+
+impl Visit for JsonFieldVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
@@ -0,0 +1,155 @@
+//! Integration with systemd for bare-metal deployments. With socket activation (`LISTEN_FDS`), the
+//! listening sockets are inherited instead of bound, so a restart never closes them and the
+//! connections arriving meanwhile wait in their backlog. The socket named `tcp` with
+//! `FileDescriptorName=` serves the raw TCP clients, the first other one the HTTP routes. Under a
+//! `Type=notify` unit, `READY=1` is reported once the routes are served and `STOPPING=1` once
+//! SIGTERM starts the graceful shutdown.
+
+use log::warn;
+use std::env;
+use std::net::TcpListener as StdTcpListener;
+
+const TCP_SOCKET_NAME: &str = "tcp";
+
+#[derive(Debug, Default)]
+pub(crate) struct InheritedListeners {
+    pub(crate) http: Option<StdTcpListener>,
+    pub(crate) tcp: Option<StdTcpListener>,
+}
+
+// Role of each inherited socket in order, true for the raw TCP one
+fn is_tcp_socket(listen_fds: usize, listen_fd_names: Option<&str>) -> Vec<bool> {
+    let mut listen_fd_names = listen_fd_names.unwrap_or_default().split(':');
+
+    (0..listen_fds).map(|_| listen_fd_names.next() == Some(TCP_SOCKET_NAME)).collect()
+}
+
+// Empty unless started by systemd for this very process
+#[cfg(unix)]
+pub(crate) fn inherited_listeners() -> InheritedListeners {
+    use std::os::unix::io::{FromRawFd, RawFd};
+
+    const LISTEN_FDS_START: RawFd = 3;
+
+    let mut result = InheritedListeners::default();
+    let listen_pid = env::var("LISTEN_PID").ok().and_then(|listen_pid| listen_pid.parse().ok());
+
+    if listen_pid != Some(std::process::id()) {
+        return result;
+    }
+
+    let listen_fds =
+        env::var("LISTEN_FDS").ok().and_then(|listen_fds| listen_fds.parse().ok()).unwrap_or(0);
+    let listen_fd_names = env::var("LISTEN_FDNAMES").ok();
+
+    for (offset, is_tcp_socket) in
+        is_tcp_socket(listen_fds, listen_fd_names.as_deref()).into_iter().enumerate()
+    {
+        let listen_fd = LISTEN_FDS_START + offset as RawFd;
+        // systemd hands the descriptors from 3 on over to this process, nothing else owns them
+        let listener = unsafe { StdTcpListener::from_raw_fd(listen_fd) };
+
+        match (is_tcp_socket, result.http.is_some()) {
+            (true, _) => result.tcp = Some(listener),
+            (false, false) => result.http = Some(listener),
+            (false, true) => warn!("Ignoring the inherited socket {}", listen_fd),
+        }
+    }
+
+    result
+}
+
+#[cfg(not(unix))]
+pub(crate) fn inherited_listeners() -> InheritedListeners {
+    InheritedListeners::default()
+}
+
+// Does nothing outside of a Type=notify unit, a failure is logged and the server carries on
+pub(crate) fn notify(state: &str) {
+    if let Some(notify_socket) = env::var_os("NOTIFY_SOCKET") {
+        if let Err(error) = notify_to(&notify_socket, state) {
+            warn!("Failed to notify systemd of {}: {}", state, error);
+        }
+    }
+}
+
+// Paths starting with @ are in the abstract namespace
+#[cfg(unix)]
+fn notify_to(notify_socket: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+
+    match notify_socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(abstract_name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr as UnixSocketAddr;
+
+            let address = UnixSocketAddr::from_abstract_name(abstract_name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), notify_socket)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify_to(_: &std::ffi::OsStr, _: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Reports STOPPING=1 as soon as systemd asks to stop, while actix drains the connections
+pub(crate) fn notify_stopping_on_terminate() {
+    #[cfg(unix)]
+    if env::var_os("NOTIFY_SOCKET").is_some() {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        actix::spawn(async {
+            match signal(SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    if terminate.recv().await.is_some() {
+                        notify("STOPPING=1");
+                    }
+                }
+                Err(error) => warn!("Failed to watch SIGTERM: {}", error),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_tcp_socket_is_as_expected() {
+        assert_eq!(is_tcp_socket(0, None), Vec::<bool>::new());
+        assert_eq!(is_tcp_socket(2, None), vec![false, false]);
+        assert_eq!(is_tcp_socket(2, Some("tcp:http")), vec![true, false]);
+        assert_eq!(is_tcp_socket(3, Some("http:tcp")), vec![false, true, false]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_to_is_as_expected() {
+        use std::os::unix::net::UnixDatagram;
+
+        let socket_path =
+            env::temp_dir().join(format!("game-room-notify-{}.sock", uuid::Uuid::new_v4()));
+        let notify_socket = UnixDatagram::bind(&socket_path).unwrap();
+
+        notify_to(socket_path.as_os_str(), "READY=1").unwrap();
+
+        let mut buffer = [0u8; 64];
+        let length = notify_socket.recv(&mut buffer).unwrap();
+
+        assert_eq!(&buffer[..length], b"READY=1");
+
+        std::fs::remove_file(socket_path).unwrap();
+    }
+}
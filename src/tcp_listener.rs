@@ -0,0 +1,189 @@
+//! Raw TCP listener for dedicated game clients not needing the websocket framing. Frames are the
+//! same `MessageStream`s prefixed with their u32 length (LE). The first frame of a connection is
+//! the query string of a `/client` upgrade, refused joins are answered with one frame holding the
+//! status and reason of the refused upgrade, e.g. `403 No room 5!`, before the connection closes.
+//! Connections beyond the connection or handshake limits get their `429` frame, ending with the
+//! `Retry after <seconds>s` hint, before any handshake.
+//! Admitted connections are `ClientActor`s over a `TcpTransport`, routed like websocket clients.
+//! With `--proxy-protocol` every connection starts with the PROXY header of a trusted proxy, the
+//! client it carries then counts toward the limits instead of the proxy.
+
+use crate::proto::{CompressionCodec, PartyId};
+use crate::trusted_proxies::read_proxy_header;
+use crate::ws_handlers::{ClientActor, SigningKey, TcpFrameCodec, TcpTransport};
+use crate::{ClientQueryParams, HttpSharedState};
+use actix::clock::Duration;
+use actix::io::SinkWrite;
+use actix::{Actor, AsyncContext};
+use actix_codec::Framed;
+use actix_web::http::StatusCode;
+use actix_web::web::{Bytes, Data as SharedData, Query as RequestQuery};
+use futures::future::{abortable, AbortHandle};
+use futures::{SinkExt, StreamExt};
+use log::{info, warn};
+use std::io::Result as IOResult;
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+// Connections not sending their PROXY header, then their query string, within this delay are
+// dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Bound beforehand so a busy port fails the startup, or inherited. Connections are then accepted
+// in background until aborted, e.g. once a new process took the listener over
+pub(crate) fn start(
+    std_listener: StdTcpListener,
+    proxy_protocol: bool,
+    shared_state: SharedData<HttpSharedState>,
+) -> IOResult<AbortHandle> {
+    std_listener.set_nonblocking(true)?;
+    let mut tcp_listener = TcpListener::from_std(std_listener)?;
+    info!("Accepting TCP clients on {}", tcp_listener.local_addr()?);
+
+    let (accept_loop, abort_handle) = abortable(async move {
+        loop {
+            match tcp_listener.accept().await {
+                Ok((tcp_stream, peer_address)) => actix::spawn(accept_client(
+                    tcp_stream,
+                    peer_address,
+                    proxy_protocol,
+                    shared_state.clone(),
+                )),
+                Err(error) => warn!("TCP accept failed: {}", error),
+            }
+        }
+    });
+    actix::spawn(async move {
+        let _ = accept_loop.await;
+    });
+
+    Ok(abort_handle)
+}
+
+// Health checks of the proxy come with a LOCAL header, their connection is the proxy itself
+async fn accept_proxied(
+    tcp_stream: &mut TcpStream,
+    peer_address: SocketAddr,
+    shared_state: &HttpSharedState,
+) -> Result<SocketAddr, String> {
+    if !shared_state.trusted_proxies.is_trusted(peer_address.ip()) {
+        return Err("not a trusted proxy".into());
+    }
+
+    match timeout(HANDSHAKE_TIMEOUT, read_proxy_header(tcp_stream)).await {
+        Ok(Ok(client_address)) => Ok(client_address.unwrap_or(peer_address)),
+        Ok(Err(error)) => Err(error.to_string()),
+        Err(_) => Err("no PROXY header".into()),
+    }
+}
+
+async fn accept_client(
+    mut tcp_stream: TcpStream,
+    peer_address: SocketAddr,
+    proxy_protocol: bool,
+    shared_state: SharedData<HttpSharedState>,
+) {
+    let peer_address = if proxy_protocol {
+        match accept_proxied(&mut tcp_stream, peer_address, &shared_state).await {
+            Ok(client_address) => client_address,
+            Err(error) => return warn!("TCP proxy {} refused: {}", peer_address, error),
+        }
+    } else {
+        peer_address
+    };
+    let _ = tcp_stream.set_nodelay(true);
+    let mut framed = Framed::new(tcp_stream, TcpFrameCodec);
+    if let Err(description) = shared_state.check_banned(peer_address.ip()) {
+        info!("TCP client {} refused: {}", peer_address, description);
+        let _ = framed.send(Bytes::from(format!("403 {}", description))).await;
+
+        return;
+    }
+
+    // Counted from the accept, the handshake of a refused connection is never awaited
+    let connection_permit = match shared_state.acquire_connection(Some(peer_address.ip())) {
+        Ok(connection_permit) => connection_permit,
+        Err((description, retry_after)) => {
+            info!("TCP client {} refused: {}", peer_address, description);
+            let refusal = format!("429 {} Retry after {}s", description, retry_after);
+            let _ = framed.send(Bytes::from(refusal)).await;
+
+            return;
+        }
+    };
+    let handshake = match timeout(HANDSHAKE_TIMEOUT, framed.next()).await {
+        Ok(Some(Ok(handshake))) => handshake,
+        Ok(Some(Err(error))) => return warn!("TCP client {} failed: {}", peer_address, error),
+        Ok(None) | Err(_) => return warn!("TCP client {} sent no handshake", peer_address),
+    };
+
+    let admission = match parse_handshake(&handshake) {
+        Ok(query_params) => admit_client(query_params, &shared_state).await,
+        Err(refusal) => Err(refusal),
+    };
+    let (query_params, party_id, metadata) = match admission {
+        Ok(admitted_client) => admitted_client,
+        Err((status, description)) => {
+            info!("TCP client {} refused: {}", peer_address, description);
+            let refusal = format!("{} {}", status.as_u16(), description);
+            let _ = framed.send(Bytes::from(refusal)).await;
+
+            return;
+        }
+    };
+
+    let accepted_codecs =
+        query_params.compression.as_deref().map(CompressionCodec::parse_list).unwrap_or_default();
+    let (tcp_sink, tcp_stream) = framed.split();
+    let client_address = ClientActor::create(|context| {
+        context.add_stream(tcp_stream);
+
+        ClientActor::new(
+            query_params.room_id,
+            party_id,
+            query_params.client_id,
+            shared_state.router_address.clone(),
+            accepted_codecs,
+            query_params.format,
+            TcpTransport::new(SinkWrite::new(tcp_sink, context)),
+        )
+        .with_udp_relay(shared_state.udp_relay.clone())
+        .with_connection_permit(connection_permit)
+        .with_mailbox_capacity(shared_state.client_mailbox_capacity)
+        .with_max_frame_violations(shared_state.max_frame_violations)
+        .with_signing_key(query_params.auth_token.as_deref().map(SigningKey::derive))
+    });
+
+    shared_state.register_client(
+        &query_params,
+        party_id,
+        metadata,
+        client_address.recipient(),
+        false,
+    );
+}
+
+// The handshake is the query string of a /client upgrade, the leading `?` is optional, the
+// WebTransport listener takes the query of its session request the same way
+pub(crate) fn parse_handshake(handshake: &[u8]) -> Result<ClientQueryParams, (StatusCode, String)> {
+    let query_string = std::str::from_utf8(handshake)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Handshake is not UTF-8!".to_string()))?;
+
+    RequestQuery::<ClientQueryParams>::from_query(query_string.trim().trim_start_matches('?'))
+        .map(RequestQuery::into_inner)
+        .map_err(|error| (StatusCode::BAD_REQUEST, error.to_string()))
+}
+
+// Rooms owned by another peer are not proxied, the client is told where to go instead
+pub(crate) async fn admit_client(
+    query_params: ClientQueryParams,
+    shared_state: &HttpSharedState,
+) -> Result<(ClientQueryParams, PartyId, Arc<[u8]>), (StatusCode, String)> {
+    shared_state.check_direct_join(query_params.room_id).await?;
+
+    let (party_id, metadata) = shared_state.admit_client(&query_params).await?;
+
+    Ok((query_params, party_id, metadata))
+}
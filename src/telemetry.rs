@@ -0,0 +1,260 @@
+//! Per-frame trace spans exported to an OpenTelemetry collector with OTLP/HTTP JSON. A sampled
+//! frame gets a trace ID when it enters a websocket actor, then one span per actor hop: `ingress`
+//! in the receiving actor, `route` in the router and `deliver` in every destination actor. A hop
+//! starts when the previous one handed the frame over, so mailbox and lane waits are included.
+//! Deliveries leaving through the outbound batches or on a lockstep deadline are not traced.
+
+use log::warn;
+use serde_json::{json, Value as JsonValue};
+use std::io::Result as IOResult;
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub(crate) static TELEMETRY: Telemetry = Telemetry::new();
+
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_EXPORT_BATCH: usize = 1024;
+const MAX_PENDING_SPANS: usize = 8192; // Spans are dropped while the exporter lags this far behind
+
+#[derive(Debug)]
+pub(crate) struct Telemetry {
+    exporter: OnceLock<SpanExporter>,
+}
+
+#[derive(Debug)]
+struct SpanExporter {
+    sender: SyncSender<(HopSpan, u64)>, // Ended span and its end time
+    sample_threshold: u64,
+}
+
+impl Telemetry {
+    pub(crate) const fn new() -> Self {
+        Self { exporter: OnceLock::new() }
+    }
+
+    // Spawns the exporter thread posting to <otlp_endpoint>/v1/traces, sample_ratio in 0.0..=1.0
+    pub(crate) fn init(
+        &self,
+        otlp_endpoint: &str,
+        sample_ratio: f64,
+        instance_id: Uuid,
+    ) -> IOResult<()> {
+        let (sender, receiver) = sync_channel(MAX_PENDING_SPANS);
+        let traces_url = format!("{}/v1/traces", otlp_endpoint.trim_end_matches('/'));
+        let resource = json!({
+            "attributes": [
+                string_attribute("service.name", env!("CARGO_PKG_NAME")),
+                string_attribute("service.instance.id", &instance_id.to_string()),
+            ]
+        });
+
+        thread::Builder::new()
+            .name("telemetry-exporter".into())
+            .spawn(move || export_spans(receiver, traces_url, resource))?;
+
+        let sample_threshold = (sample_ratio.clamp(0.0, 1.0) * u64::MAX as f64) as u64;
+        let _ = self.exporter.set(SpanExporter { sender, sample_threshold });
+
+        Ok(())
+    }
+
+    fn sample(&self) -> Option<[u8; 16]> {
+        let exporter = self.exporter.get()?;
+        let trace_id = *Uuid::new_v4().as_bytes();
+        let mut u64_bytes = [0u8; 8];
+        u64_bytes.copy_from_slice(&trace_id[..8]);
+
+        if u64::from_le_bytes(u64_bytes) <= exporter.sample_threshold {
+            Some(trace_id)
+        } else {
+            None
+        }
+    }
+
+    fn export(&self, span: HopSpan, end_time: u64) {
+        if let Some(exporter) = self.exporter.get() {
+            let _ = exporter.sender.try_send((span, end_time));
+        }
+    }
+}
+
+/// Carried with a frame between actors, the span of the previous hop and when it handed over
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct TraceContext {
+    trace_id: [u8; 16],
+    parent_span_id: [u8; 8],
+    handed_over_at: u64,
+}
+
+impl TraceContext {
+    // Same parent span, handed over now
+    pub(crate) fn stamp(self) -> Self {
+        Self { handed_over_at: unix_nanos(), ..self }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct HopSpan {
+    name: &'static str,
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    start_time: u64,
+    attributes: Vec<(&'static str, i64)>,
+}
+
+impl HopSpan {
+    // First hop of a new trace starting now, None when tracing is off or the frame is not sampled
+    pub(crate) fn ingress() -> Option<Self> {
+        let trace_id = TELEMETRY.sample()?;
+
+        Some(Self::new("ingress", trace_id, None, unix_nanos()))
+    }
+
+    // Next hop of the trace, starting when the previous hop handed the frame over
+    pub(crate) fn follow(name: &'static str, trace_context: TraceContext) -> Self {
+        Self::new(
+            name,
+            trace_context.trace_id,
+            Some(trace_context.parent_span_id),
+            trace_context.handed_over_at,
+        )
+    }
+
+    fn new(
+        name: &'static str,
+        trace_id: [u8; 16],
+        parent_span_id: Option<[u8; 8]>,
+        start_time: u64,
+    ) -> Self {
+        let mut span_id = [0u8; 8];
+        span_id.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+
+        Self { name, trace_id, span_id, parent_span_id, start_time, attributes: Vec::new() }
+    }
+
+    pub(crate) fn with_attribute(mut self, key: &'static str, value: impl Into<i64>) -> Self {
+        self.attributes.push((key, value.into()));
+        self
+    }
+
+    // Context for the next hop, handed over now
+    pub(crate) fn hand_over(&self) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id,
+            parent_span_id: self.span_id,
+            handed_over_at: unix_nanos(),
+        }
+    }
+
+    pub(crate) fn end(self) {
+        TELEMETRY.export(self, unix_nanos());
+    }
+
+    fn to_otlp(&self, end_time: u64) -> JsonValue {
+        let attributes: Vec<JsonValue> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": { "intValue": value.to_string() } }))
+            .collect();
+        let mut result = json!({
+            "traceId": to_hex(&self.trace_id),
+            "spanId": to_hex(&self.span_id),
+            "name": self.name,
+            "kind": 1, // SPAN_KIND_INTERNAL
+            "startTimeUnixNano": self.start_time.to_string(),
+            "endTimeUnixNano": end_time.to_string(),
+            "attributes": attributes,
+        });
+
+        if let Some(parent_span_id) = self.parent_span_id {
+            result["parentSpanId"] = to_hex(&parent_span_id).into();
+        }
+
+        result
+    }
+}
+
+fn export_spans(receiver: Receiver<(HopSpan, u64)>, traces_url: String, resource: JsonValue) {
+    let mut system = actix::System::new("telemetry-exporter");
+    let client = system.block_on(async { awc::Client::default() });
+
+    loop {
+        let deadline = Instant::now() + EXPORT_INTERVAL;
+        let mut spans = Vec::new();
+
+        while spans.len() < MAX_EXPORT_BATCH {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((span, end_time)) => spans.push(span.to_otlp(end_time)),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        if spans.is_empty() {
+            continue;
+        }
+
+        let request_body = json!({
+            "resourceSpans": [{
+                "resource": resource,
+                "scopeSpans": [{ "scope": { "name": env!("CARGO_PKG_NAME") }, "spans": spans }],
+            }]
+        });
+
+        // Sending arms the request timeout, which needs the runtime
+        let request = client.post(&traces_url);
+        let response = system.block_on(async move { request.send_json(&request_body).await });
+
+        match response {
+            Ok(response) if response.status().is_success() => (),
+            Ok(response) => warn!("OTLP collector answered {}", response.status()),
+            Err(error) => warn!("OTLP export to {} failed: {}", traces_url, error),
+        }
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> JsonValue {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn to_hex(source: &[u8]) -> String {
+    source.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hop_span_to_otlp_is_as_expected() {
+        let ingress_span =
+            HopSpan::new("ingress", [0xAB; 16], None, 1_000).with_attribute("room_id", 7u8);
+        let route_span = HopSpan::follow("route", ingress_span.hand_over());
+        let ingress_otlp = ingress_span.to_otlp(2_000);
+        let route_otlp = route_span.to_otlp(3_000);
+
+        assert_eq!(ingress_otlp["traceId"], "abababababababababababababababab");
+        assert_eq!(ingress_otlp["startTimeUnixNano"], "1000");
+        assert_eq!(ingress_otlp["endTimeUnixNano"], "2000");
+        assert_eq!(ingress_otlp["attributes"][0]["value"]["intValue"], "7");
+        assert!(ingress_otlp.get("parentSpanId").is_none());
+        assert_eq!(route_otlp["traceId"], ingress_otlp["traceId"]);
+        assert_eq!(route_otlp["parentSpanId"], ingress_otlp["spanId"]);
+    }
+
+    #[test]
+    fn test_ingress_is_not_sampled_without_exporter() {
+        assert!(HopSpan::ingress().is_none());
+    }
+}
@@ -0,0 +1,361 @@
+//! Real IPs of the clients connecting through load balancers or reverse proxies. Peers listed with
+//! `--trusted-proxy` are believed about the address they forward for: the `Forwarded` or
+//! `X-Forwarded-For` header of the HTTP requests and, with `--proxy-protocol`, the HAProxy PROXY
+//! header (v1 or v2) every raw TCP connection then starts with. The connection limits, the audit
+//! log and the listener logs see the client instead of the proxy.
+
+use crate::{anyerror, AnyError, AnyResult};
+use actix_web::http::header::{HeaderMap, FORWARDED};
+use serde::de::Error as DeserializeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const HEADER_X_FORWARDED_FOR: &str = "X-Forwarded-For";
+
+const PROXY_V1_PREFIX: &[u8] = b"PROXY ";
+const PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const MAX_PROXY_V1_LENGTH: usize = 107; // CRLF included, as per the specification
+
+// An IP or a CIDR block, e.g. 10.0.0.0/8, for the trusted proxies and the banned clients
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct IpNetwork {
+    network: IpAddr,
+    prefix_length: u32,
+}
+
+impl FromStr for IpNetwork {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let (network, prefix_length) = match source.split_once('/') {
+            Some((network, prefix_length)) => {
+                (network.trim().parse::<IpAddr>()?, Some(prefix_length.trim().parse()?))
+            }
+            None => (source.trim().parse::<IpAddr>()?, None),
+        };
+        let address_length = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_length = prefix_length.unwrap_or(address_length);
+
+        if prefix_length > address_length {
+            return Err(anyerror!("Prefix length {} of {} is too long", prefix_length, source));
+        }
+
+        Ok(Self { network, prefix_length })
+    }
+}
+
+// Read from the strings of a JSON file, e.g. the banned IPs of the connection policy
+impl<'de> Deserialize<'de> for IpNetwork {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(DeserializeError::custom)
+    }
+}
+
+// Written back as it is read, e.g. when the check subcommand prints the connection policy
+impl Serialize for IpNetwork {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Display for IpNetwork {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        write!(formatter, "{}/{}", self.network, self.prefix_length)
+    }
+}
+
+impl IpNetwork {
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, address_length) = match (self.network, unmapped(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                (u32::from(network) as u128, u32::from(ip) as u128, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+
+        (network ^ ip).checked_shr(address_length - self.prefix_length).unwrap_or(0) == 0
+    }
+}
+
+// IPv4 peers of a dual stack socket show up as IPv4-mapped IPv6 addresses
+fn unmapped(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrustedProxies(Vec<IpNetwork>);
+
+impl TrustedProxies {
+    pub(crate) fn new(trusted_proxies: Vec<IpNetwork>) -> Self {
+        Self(trusted_proxies)
+    }
+
+    pub(crate) fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|trusted_proxy| trusted_proxy.contains(ip))
+    }
+
+    // Every proxy appends the address it got the request from, so the client is the rightmost
+    // address not itself a trusted proxy. Forwarded wins over X-Forwarded-For, and an address
+    // that cannot be read stops the walk at the proxy that wrote it
+    pub(crate) fn client_ip(&self, peer_ip: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client_ip = peer_ip;
+
+        if !self.is_trusted(peer_ip) {
+            return client_ip;
+        }
+
+        for forwarded_ip in forwarded_ips(headers).into_iter().rev() {
+            match forwarded_ip {
+                Some(forwarded_ip) => client_ip = forwarded_ip,
+                None => break,
+            }
+
+            if !self.is_trusted(client_ip) {
+                break;
+            }
+        }
+
+        client_ip
+    }
+}
+
+// Elements of every line of the header, e.g. `for=192.0.2.60;proto=http` for Forwarded
+fn header_elements<'a>(headers: &'a HeaderMap, header_name: &str) -> Vec<&'a str> {
+    headers
+        .get_all(header_name)
+        .filter_map(|header_value| header_value.to_str().ok())
+        .flat_map(|header_value| header_value.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+// In the order they were appended, None for the unknown and obfuscated ones
+fn forwarded_ips(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded_elements = header_elements(headers, FORWARDED.as_str());
+
+    if forwarded_elements.is_empty() {
+        return header_elements(headers, HEADER_X_FORWARDED_FOR)
+            .into_iter()
+            .map(parse_forwarded_node)
+            .collect();
+    }
+
+    forwarded_elements
+        .into_iter()
+        .map(|forwarded_element| {
+            forwarded_element
+                .split(';')
+                .filter_map(|forwarded_pair| forwarded_pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .and_then(|(_, node)| parse_forwarded_node(node))
+        })
+        .collect()
+}
+
+// Nodes are an IP, optionally with a port, and IPv6 ones bracketed when quoted in Forwarded
+fn parse_forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|socket_address| socket_address.ip()))
+        .or_else(|| node.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+// Reads the PROXY header a proxy starts the connection with, and nothing past it. Answers the
+// source address it carries, None for the LOCAL and UNKNOWN connections of health checks
+pub(crate) async fn read_proxy_header<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> AnyResult<Option<SocketAddr>> {
+    // As long as the v2 signature, and shorter than the shortest v1 header
+    let mut signature = [0u8; 12];
+    reader.read_exact(&mut signature).await?;
+
+    if signature == PROXY_V2_SIGNATURE {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).await?;
+        let mut addresses = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+        reader.read_exact(&mut addresses).await?;
+
+        return parse_proxy_v2(header[0], header[1], &addresses);
+    }
+
+    if !signature.starts_with(PROXY_V1_PREFIX) {
+        return Err(anyerror!("Expected a PROXY header"));
+    }
+
+    let mut line = signature.to_vec();
+
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= MAX_PROXY_V1_LENGTH {
+            return Err(anyerror!("PROXY header is too long"));
+        }
+
+        line.push(reader.read_u8().await?);
+    }
+
+    parse_proxy_v1(&line)
+}
+
+// e.g. `PROXY TCP4 192.0.2.60 10.0.0.1 56324 7576\r\n`
+fn parse_proxy_v1(line: &[u8]) -> AnyResult<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)?.trim_end();
+
+    match line.split(' ').collect::<Vec<_>>().as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source_ip, _, source_port, _] => {
+            Ok(Some(SocketAddr::new(source_ip.parse()?, source_port.parse()?)))
+        }
+        _ => Err(anyerror!("Invalid PROXY header {}", line)),
+    }
+}
+
+// The addresses are the source then the destination IPs, then the source and destination ports
+fn parse_proxy_v2(
+    version_command: u8,
+    family: u8,
+    addresses: &[u8],
+) -> AnyResult<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        return Err(anyerror!("Unsupported PROXY version {}", version_command >> 4));
+    }
+
+    match version_command & 0x0F {
+        0x00 => return Ok(None), // LOCAL
+        0x01 => (),              // PROXY
+        command => return Err(anyerror!("Unsupported PROXY command {}", command)),
+    }
+
+    let source_port =
+        |offset: usize| u16::from_be_bytes([addresses[offset], addresses[offset + 1]]);
+
+    match family >> 4 {
+        0x1 if addresses.len() >= 12 => {
+            let mut source_ip = [0u8; 4];
+            source_ip.copy_from_slice(&addresses[..4]);
+
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(source_ip).into(), source_port(8))))
+        }
+        0x2 if addresses.len() >= 36 => {
+            let mut source_ip = [0u8; 16];
+            source_ip.copy_from_slice(&addresses[..16]);
+
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(source_ip).into(), source_port(32))))
+        }
+        0x1 | 0x2 => Err(anyerror!("PROXY addresses are too short")),
+        _ => Ok(None), // UNSPEC or UNIX, no IP to go by
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{HeaderName, HeaderValue};
+
+    fn trusted_proxies(trusted_proxies: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(trusted_proxies.iter().map(|source| source.parse().unwrap()).collect())
+    }
+
+    fn headers(header_lines: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for (header_name, header_value) in header_lines {
+            headers.append(
+                HeaderName::from_static(header_name),
+                HeaderValue::from_static(header_value),
+            );
+        }
+
+        headers
+    }
+
+    #[test]
+    fn test_trusted_proxy_is_as_expected() {
+        let trusted_proxies = trusted_proxies(&["10.0.0.0/8", "192.0.2.1", "2001:db8::/32"]);
+
+        assert!(trusted_proxies.is_trusted("10.1.2.3".parse().unwrap()));
+        assert!(trusted_proxies.is_trusted("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(trusted_proxies.is_trusted("192.0.2.1".parse().unwrap()));
+        assert!(!trusted_proxies.is_trusted("192.0.2.2".parse().unwrap()));
+        assert!(trusted_proxies.is_trusted("2001:db8:cafe::17".parse().unwrap()));
+        assert!(!trusted_proxies.is_trusted("2001:db9::1".parse().unwrap()));
+        assert!("0.0.0.0/0".parse::<IpNetwork>().unwrap().contains("203.0.113.9".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("10.0.0/8".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn test_trusted_proxies_client_ip_is_as_expected() {
+        let trusted_proxies = trusted_proxies(&["10.0.0.0/8"]);
+        let proxy_ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let client_ip: IpAddr = "203.0.113.9".parse().unwrap();
+        let forwarded_for = headers(&[("x-forwarded-for", "198.51.100.7, 203.0.113.9, 10.0.0.2")]);
+
+        // The rightmost untrusted address, anything left of it may be spoofed by the client
+        assert_eq!(trusted_proxies.client_ip(proxy_ip, &forwarded_for), client_ip);
+        // Untrusted peers are not believed
+        assert_eq!(trusted_proxies.client_ip(client_ip, &forwarded_for), client_ip);
+        assert_eq!(trusted_proxies.client_ip(proxy_ip, &HeaderMap::new()), proxy_ip);
+
+        let forwarded = headers(&[
+            ("forwarded", r#"for="[2001:db8:cafe::17]:4711";proto=https"#),
+            ("forwarded", "for=10.0.0.2;by=10.0.0.1"),
+            ("x-forwarded-for", "198.51.100.7"),
+        ]);
+
+        assert_eq!(
+            trusted_proxies.client_ip(proxy_ip, &forwarded),
+            "2001:db8:cafe::17".parse::<IpAddr>().unwrap()
+        );
+
+        let obfuscated = headers(&[("forwarded", "for=_hidden, for=10.0.0.2")]);
+
+        assert_eq!(
+            trusted_proxies.client_ip(proxy_ip, &obfuscated),
+            "10.0.0.2".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_read_proxy_header_is_as_expected() {
+        let mut proxied: &[u8] = b"PROXY TCP4 192.0.2.60 10.0.0.1 56324 7576\r\nroom_id=1";
+
+        assert_eq!(
+            read_proxy_header(&mut proxied).await.unwrap(),
+            Some("192.0.2.60:56324".parse().unwrap())
+        );
+        // Nothing past the header is read
+        assert_eq!(proxied, b"room_id=1");
+
+        let mut unknown: &[u8] = b"PROXY UNKNOWN\r\n";
+
+        assert_eq!(read_proxy_header(&mut unknown).await.unwrap(), None);
+
+        let mut proxied_v2 = PROXY_V2_SIGNATURE.to_vec();
+        proxied_v2.extend_from_slice(&[0x21, 0x11, 0, 12, 192, 0, 2, 60, 10, 0, 0, 1]);
+        proxied_v2.extend_from_slice(&[0xDC, 0x04, 0x1D, 0x98]);
+
+        assert_eq!(
+            read_proxy_header(&mut proxied_v2.as_slice()).await.unwrap(),
+            Some("192.0.2.60:56324".parse().unwrap())
+        );
+
+        let mut local_v2 = PROXY_V2_SIGNATURE.to_vec();
+        local_v2.extend_from_slice(&[0x20, 0x00, 0, 0]);
+
+        assert_eq!(read_proxy_header(&mut local_v2.as_slice()).await.unwrap(), None);
+        assert!(read_proxy_header(&mut &b"room_id=1&client_id=0"[..]).await.is_err());
+
+        let mut too_long = PROXY_V1_PREFIX.to_vec();
+        too_long.extend_from_slice(&[b'X'; 200]);
+
+        assert!(read_proxy_header(&mut too_long.as_slice()).await.is_err());
+    }
+}
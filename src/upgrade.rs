@@ -0,0 +1,205 @@
+//! Zero-downtime binary upgrades. A process started with `--upgrade-socket` serves its listeners
+//! on that Unix socket: a new process started with `--upgrade` connects to it and receives them
+//! (SCM_RIGHTS) instead of binding the ports, then confirms once it serves them. Only then does
+//! the old process stop accepting, leaving the new connections to the new process, and drain its
+//! client connections for up to `--upgrade-drain-timeout` seconds before exiting. The new process
+//! serves the socket in turn for the next upgrade.
+
+use crate::systemd::InheritedListeners;
+use crate::ws_handlers::ConnectionLimits;
+use actix::clock::Duration;
+use actix_web::dev::Server;
+use futures::future::AbortHandle;
+use std::io::Result as IOResult;
+use std::path::{Path, PathBuf};
+
+const HTTP_LISTENER_NAME: &str = "http";
+const TCP_LISTENER_NAME: &str = "tcp";
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30); // Left to the new process to start
+
+// What the old process stops once the new one took over
+pub(crate) struct Drain {
+    pub(crate) http_server: Server,
+    pub(crate) tcp_accept: Option<AbortHandle>,
+    pub(crate) connection_limits: ConnectionLimits,
+    pub(crate) timeout: Duration,
+}
+
+// Connection to the old process, kept until the new one serves the listeners
+#[derive(Debug)]
+pub(crate) struct Takeover {
+    #[cfg(unix)]
+    old_process: std::os::unix::net::UnixStream,
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use log::{info, warn};
+    use sendfd::{RecvWithFd, SendWithFd};
+    use std::fs::remove_file;
+    use std::io::{Read, Write};
+    use std::net::TcpListener as StdTcpListener;
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::thread;
+    use std::time::Instant;
+
+    // The listeners come with their names, e.g. `http:tcp`
+    pub(crate) fn take_over(upgrade_socket: &Path) -> IOResult<(InheritedListeners, Takeover)> {
+        let old_process = UnixStream::connect(upgrade_socket)?;
+        let mut listener_names = [0u8; 64];
+        let mut listen_fds: [RawFd; 2] = [-1; 2];
+        let (names_length, fds_count) =
+            old_process.recv_with_fd(&mut listener_names, &mut listen_fds)?;
+        let listener_names = String::from_utf8_lossy(&listener_names[..names_length]);
+        let mut result = InheritedListeners::default();
+
+        for (listener_name, listen_fd) in listener_names.split(':').zip(&listen_fds[..fds_count]) {
+            // The old process sent these descriptors to this one, nothing else owns them here
+            let listener = unsafe { StdTcpListener::from_raw_fd(*listen_fd) };
+
+            match listener_name {
+                HTTP_LISTENER_NAME => result.http = Some(listener),
+                TCP_LISTENER_NAME => result.tcp = Some(listener),
+                _ => warn!("Ignoring the handed over listener {}", listener_name),
+            }
+        }
+
+        info!("Took the listeners {} over from {}", listener_names, upgrade_socket.display());
+
+        Ok((result, Takeover { old_process }))
+    }
+
+    impl Takeover {
+        pub(crate) fn confirm(mut self) -> IOResult<()> {
+            self.old_process.write_all(b"1")
+        }
+    }
+
+    // The socket file of the previous process is replaced, it already handed its listeners over
+    pub(crate) fn serve_handoff(
+        upgrade_socket: PathBuf,
+        listeners: InheritedListeners,
+        drain: Drain,
+    ) -> IOResult<()> {
+        let _ = remove_file(&upgrade_socket);
+        let upgrade_listener = UnixListener::bind(&upgrade_socket)?;
+
+        thread::Builder::new().name("upgrade-handoff".into()).spawn(move || {
+            for new_process in upgrade_listener.incoming() {
+                let handoff = new_process.and_then(|new_process| hand_off(new_process, &listeners));
+
+                match handoff {
+                    Ok(()) => return drain_and_stop(drain),
+                    Err(error) => warn!("Upgrade handoff failed, still serving: {}", error),
+                }
+            }
+        })?;
+
+        Ok(())
+    }
+
+    pub(super) fn hand_off(
+        mut new_process: UnixStream,
+        listeners: &InheritedListeners,
+    ) -> IOResult<()> {
+        let mut listener_names = Vec::new();
+        let mut listen_fds = Vec::new();
+        let named_listeners = [
+            (HTTP_LISTENER_NAME, listeners.http.as_ref()),
+            (TCP_LISTENER_NAME, listeners.tcp.as_ref()),
+        ];
+
+        for (listener_name, listener) in named_listeners {
+            if let Some(listener) = listener {
+                listener_names.push(listener_name);
+                listen_fds.push(listener.as_raw_fd());
+            }
+        }
+
+        new_process.send_with_fd(listener_names.join(":").as_bytes(), &listen_fds)?;
+        new_process.set_read_timeout(Some(CONFIRM_TIMEOUT))?;
+
+        // Closed without confirming when the new process failed to start
+        let mut confirmation = [0u8; 1];
+        new_process.read_exact(&mut confirmation)?;
+
+        Ok(())
+    }
+
+    fn drain_and_stop(drain: Drain) {
+        let Drain { http_server, tcp_accept, connection_limits, timeout } = drain;
+        futures::executor::block_on(http_server.pause());
+
+        if let Some(tcp_accept) = tcp_accept {
+            tcp_accept.abort();
+        }
+
+        info!(
+            "Handed the listeners over, draining {} client connections",
+            connection_limits.total_open()
+        );
+        let deadline = Instant::now() + timeout;
+
+        while connection_limits.total_open() > 0 && Instant::now() < deadline {
+            thread::sleep(DRAIN_CHECK_INTERVAL);
+        }
+
+        info!("Drained, {} client connections left", connection_limits.total_open());
+        futures::executor::block_on(http_server.stop(true));
+    }
+}
+
+#[cfg(unix)]
+pub(crate) use unix::{serve_handoff, take_over};
+
+#[cfg(not(unix))]
+pub(crate) fn take_over(_: &Path) -> IOResult<(InheritedListeners, Takeover)> {
+    Err(std::io::Error::other("--upgrade needs a Unix platform"))
+}
+
+#[cfg(not(unix))]
+impl Takeover {
+    pub(crate) fn confirm(self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn serve_handoff(_: PathBuf, _: InheritedListeners, _: Drain) -> IOResult<()> {
+    Err(std::io::Error::other("--upgrade-socket needs a Unix platform"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::net::TcpListener as StdTcpListener;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn test_take_over_is_as_expected() {
+        let upgrade_socket =
+            std::env::temp_dir().join(format!("game-room-upgrade-{}.sock", uuid::Uuid::new_v4()));
+        let upgrade_listener = UnixListener::bind(&upgrade_socket).unwrap();
+        let http_listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let http_address = http_listener.local_addr().unwrap();
+        let listeners = InheritedListeners { http: Some(http_listener), tcp: None };
+
+        let old_process = std::thread::spawn(move || {
+            let (new_process, _) = upgrade_listener.accept().unwrap();
+            unix::hand_off(new_process, &listeners)
+        });
+        let (taken_over, takeover) = take_over(&upgrade_socket).unwrap();
+
+        assert_eq!(taken_over.http.unwrap().local_addr().unwrap(), http_address);
+        assert!(taken_over.tcp.is_none());
+
+        takeover.confirm().unwrap();
+
+        assert!(old_process.join().unwrap().is_ok());
+
+        std::fs::remove_file(upgrade_socket).unwrap();
+    }
+}
@@ -1,12 +1,49 @@
-use env_logger::builder as log_builder;
+use crate::structured_log::{JsonLineFormat, PlainLineFormat};
+use crate::{anyerror, AnyError, AnyResult};
 use std::env;
+use std::io::{stderr, Write};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing_log::AsLog;
+use tracing_subscriber::fmt::format::JsonFields;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload::{Handle as ReloadHandle, Layer as ReloadLayer};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
 
 pub use log::{debug, error, info, log, warn};
 pub use uuid::Uuid;
 
 const RUST_LOG: &str = "RUST_LOG";
 
-pub fn init_logger(debug_mode: bool) {
+// Set once the logger is initialized, whatever its format
+static LOG_FILTER: OnceLock<ReloadHandle<EnvFilter, Registry>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogFormat {
+    Plain,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        match source.to_lowercase().as_str() {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            _ => Err(anyerror!("Unknown LogFormat {}", source)),
+        }
+    }
+}
+
+// The log file gets the same lines as the console, without being filtered on its own
+pub fn init_logger<W: Write + Send + 'static>(
+    debug_mode: bool,
+    log_format: LogFormat,
+    instance_id: Uuid,
+    log_file: Option<W>,
+) {
     if env::var(RUST_LOG).is_err() {
         #[cfg(debug_assertions)]
         {
@@ -26,5 +63,49 @@ pub fn init_logger(debug_mode: bool) {
         }
     }
 
-    log_builder().default_format().format_timestamp_nanos().format_indent(Some(4)).init();
+    // Both formats filter through the same reloadable layer, log records are bridged to it
+    let (log_filter, filter_handle) = ReloadLayer::new(EnvFilter::from_default_env());
+    let log_layer = match log_format {
+        LogFormat::Plain => fmt::layer().event_format(PlainLineFormat).with_writer(stderr).boxed(),
+        LogFormat::Json => fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonLineFormat::new(instance_id))
+            .boxed(),
+    };
+    let log_file_layer = log_file.map(|log_file| match log_format {
+        LogFormat::Plain => {
+            fmt::layer().event_format(PlainLineFormat).with_writer(Mutex::new(log_file)).boxed()
+        }
+        LogFormat::Json => fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonLineFormat::new(instance_id))
+            .with_writer(Mutex::new(log_file))
+            .boxed(),
+    });
+    tracing_subscriber::registry().with(log_filter).with(log_layer).with(log_file_layer).init();
+
+    let _ = LOG_FILTER.set(filter_handle);
+}
+
+// Filters in the RUST_LOG syntax, e.g. info,game_room::ws_handlers=debug. Invalid ones leave the
+// filters as they were
+pub fn reload_log_filters(log_filters: &str) -> AnyResult<()> {
+    let filter_handle = LOG_FILTER.get().ok_or_else(|| anyerror!("Logger is not initialized"))?;
+    let env_filter = EnvFilter::try_new(log_filters)?;
+    // The log macros are checked against their own maximum level before being bridged
+    let max_level = env_filter.max_level_hint().map(|max_level| max_level.as_log());
+    filter_handle.reload(env_filter)?;
+    log::set_max_level(max_level.unwrap_or(log::LevelFilter::Trace));
+
+    Ok(())
+}
+
+// The filters in effect, as they would be given to reload_log_filters
+pub fn current_log_filters() -> Option<String> {
+    LOG_FILTER.get()?.with_current(|env_filter| env_filter.to_string()).ok()
+}
+
+// What the logger was initialized with, to come back to once a reload no longer sets any
+pub fn initial_log_filters() -> String {
+    env::var(RUST_LOG).unwrap_or_default()
 }
@@ -0,0 +1,214 @@
+//! WASM plugin host for deployments that cannot recompile the router, built with the `plugins`
+//! feature. The module given with `--plugin` runs as a message middleware after the ones of the
+//! embedder. It imports nothing and may export any of these hooks, every integer being an `i32`:
+//!
+//! - `on_message(pointer, length) -> verdict` with the raw frame copied at `pointer`, allocated by
+//!   its exported `alloc(length) -> pointer` in its exported `memory`. A zero verdict routes the
+//!   frame, any other drops it
+//! - `on_join(room_id, client_party_id)` once a client joined a room
+//! - `on_leave(room_id, client_party_id)` once a client left a room
+//!
+//! Each hook call may spend up to `--plugin-fuel` units of fuel. A call trapping or running out of
+//! fuel is logged and counted, and its frame routed as if the plugin were not there.
+
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{MessageStream, PartyId};
+use crate::ws_handlers::{MessageMiddleware, MiddlewareContext};
+use crate::{anyerror, AnyResult};
+use log::warn;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::path::Path;
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, TypedFunc};
+
+// Exports of the module, every hook is optional
+struct PluginHooks {
+    memory: Option<Memory>,
+    alloc: Option<TypedFunc<i32, i32>>,
+    on_message: Option<TypedFunc<(i32, i32), i32>>,
+    on_join: Option<TypedFunc<(i32, i32), ()>>,
+    on_leave: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+pub(crate) struct WasmPlugin {
+    fuel: u64, // Per hook call
+    store: Store<()>,
+    hooks: PluginHooks,
+}
+
+impl Debug for WasmPlugin {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.debug_struct("WasmPlugin").field("fuel", &self.fuel).finish_non_exhaustive()
+    }
+}
+
+impl WasmPlugin {
+    // Binary or text module
+    pub(crate) fn load(path: &Path, fuel: u64) -> AnyResult<Self> {
+        Self::new(&std::fs::read(path)?, fuel)
+    }
+
+    pub(crate) fn new(module_bytes: &[u8], fuel: u64) -> AnyResult<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, module_bytes)?;
+        let mut store = Store::new(&engine, ());
+
+        // The start function of the module is bounded as well
+        store.set_fuel(fuel)?;
+
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let hooks = PluginHooks {
+            memory: instance.get_memory(&mut store, "memory"),
+            alloc: instance.get_typed_func(&mut store, "alloc").ok(),
+            on_message: instance.get_typed_func(&mut store, "on_message").ok(),
+            on_join: instance.get_typed_func(&mut store, "on_join").ok(),
+            on_leave: instance.get_typed_func(&mut store, "on_leave").ok(),
+        };
+
+        if hooks.on_message.is_some() && (hooks.memory.is_none() || hooks.alloc.is_none()) {
+            return Err(anyerror!("Plugin exports on_message without memory and alloc"));
+        }
+
+        Ok(Self { fuel, store, hooks })
+    }
+
+    // Refuels the store for one hook call, None when the call trapped or ran out of fuel
+    fn run<R>(
+        &mut self,
+        hook: &str,
+        call: impl FnOnce(&mut Store<()>) -> AnyResult<R>,
+    ) -> Option<R> {
+        let result = self.store.set_fuel(self.fuel).and_then(|_| call(&mut self.store));
+
+        match result {
+            Ok(result) => Some(result),
+            Err(error) => {
+                Metrics::increment(&METRICS.plugin_failures);
+                warn!("Plugin hook {} failed: {:?}", hook, error);
+                None
+            }
+        }
+    }
+
+    fn run_membership_hook(
+        &mut self,
+        hook: &str,
+        hook_func: Option<TypedFunc<(i32, i32), ()>>,
+        room_id: u32,
+        context: &MiddlewareContext,
+    ) {
+        if let (Some(hook_func), PartyId::Client(client_party_id)) =
+            (hook_func, context.origin_party_id)
+        {
+            self.run(hook, |store| hook_func.call(store, (room_id as i32, client_party_id as i32)));
+        }
+    }
+}
+
+impl MessageMiddleware for WasmPlugin {
+    fn on_message(
+        &mut self,
+        _: &MiddlewareContext,
+        message: MessageStream,
+    ) -> Option<MessageStream> {
+        let hooks = &self.hooks;
+        let (memory, alloc, on_message) =
+            match (hooks.memory, hooks.alloc.clone(), hooks.on_message.clone()) {
+                (Some(memory), Some(alloc), Some(on_message)) => (memory, alloc, on_message),
+                _ => return Some(message),
+            };
+        let raw_message = message.clone().into_raw();
+        let verdict = self.run("on_message", |store| {
+            let length = raw_message.len() as i32;
+            let pointer = alloc.call(&mut *store, length)?;
+
+            memory.write(&mut *store, pointer as u32 as usize, &raw_message)?;
+
+            on_message.call(store, (pointer, length))
+        });
+
+        match verdict {
+            Some(0) | None => Some(message),
+            Some(_) => None,
+        }
+    }
+
+    fn on_join(&mut self, room_id: u32, context: &MiddlewareContext) {
+        let on_join = self.hooks.on_join.clone();
+        self.run_membership_hook("on_join", on_join, room_id, context);
+    }
+
+    fn on_leave(&mut self, room_id: u32, context: &MiddlewareContext) {
+        let on_leave = self.hooks.on_leave.clone();
+        self.run_membership_hook("on_leave", on_leave, room_id, context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PayloadKind};
+    use std::sync::atomic::Ordering;
+
+    // Drops the frames of room 1 and spins on the frames of room 2, counts joins at address 0 and
+    // spins on leave
+    const TEST_PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32)
+                (i32.const 1024))
+            (func (export "on_message") (param $pointer i32) (param $length i32) (result i32)
+                (if (i32.eq (i32.load8_u offset=5 (local.get $pointer)) (i32.const 2))
+                    (then (loop $spin (br $spin))))
+                (i32.eq (i32.load8_u offset=5 (local.get $pointer)) (i32.const 1)))
+            (func (export "on_join") (param i32 i32)
+                (i32.store (i32.const 0) (i32.add (i32.load (i32.const 0)) (i32.const 1))))
+            (func (export "on_leave") (param i32 i32)
+                (loop $spin (br $spin))))
+    "#;
+
+    fn room_message(room_id: u32) -> MessageStream {
+        MessageStream::new(
+            MessageCode::Normal,
+            room_id,
+            PartyId::Client(0),
+            PartyId::Server(0),
+            PayloadKind::Data,
+            Some(&[0x01, 0x02]),
+        )
+    }
+
+    #[test]
+    fn test_wasm_plugin_is_as_expected() {
+        let mut plugin = WasmPlugin::new(TEST_PLUGIN.as_bytes(), 10_000).unwrap();
+        let context = MiddlewareContext { origin_party_id: PartyId::Client(0), client_id: None };
+
+        assert_eq!(plugin.on_message(&context, room_message(0)), Some(room_message(0)));
+        assert_eq!(plugin.on_message(&context, room_message(1)), None);
+
+        plugin.on_join(0, &context);
+        plugin.on_join(0, &context);
+
+        let memory = plugin.hooks.memory.as_ref().unwrap();
+
+        assert_eq!(memory.data(&plugin.store)[0], 2);
+
+        // Running out of fuel routes the frame and is counted, hooks are refueled per call
+        let plugin_failures = METRICS.plugin_failures.load(Ordering::Relaxed);
+
+        assert_eq!(plugin.on_message(&context, room_message(2)), Some(room_message(2)));
+        plugin.on_leave(0, &context);
+        assert_eq!(plugin.on_message(&context, room_message(1)), None);
+        assert!(METRICS.plugin_failures.load(Ordering::Relaxed) >= plugin_failures + 2);
+
+        let without_memory = r#"
+            (module
+                (func (export "on_message") (param i32 i32) (result i32)
+                    (i32.const 0)))
+        "#;
+
+        assert!(WasmPlugin::new(without_memory.as_bytes(), 10_000).is_err());
+    }
+}
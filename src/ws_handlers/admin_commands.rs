@@ -0,0 +1,146 @@
+use super::{GameRoomRouterActor, InterActorMessage};
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::proto::PartyId;
+use actix::{Handler as MessageHandler, Message, MessageResult};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct RoomStatus {
+    pub(crate) room_id: u32,
+    pub(crate) client_party_ids: Vec<u32>,
+    pub(crate) client_metadata: BTreeMap<u32, String>, // Only clients connected with metadata
+    pub(crate) routed_messages: u64, // Since the router started, rates are left to the reader
+    pub(crate) is_paused: bool,
+    pub(crate) owner_server_id: u32, // 0 unless claimed by a shard server
+    pub(crate) template: Option<String>, // Only rooms provisioned at startup
+    pub(crate) metadata: Option<String>,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<RoomStatus>")]
+pub(crate) struct ListRooms;
+
+// Both reply with the number of clients told to disconnect
+#[derive(Clone, Copy, Debug, Message)]
+#[rtype(result = "usize")]
+pub(crate) enum AdminCommand {
+    Kick(u32, u32), // (Room ID, Client Party ID)
+    Drain(u32),     // Every client of the room
+}
+
+// UTF-8 metadata such as JSON is shown as is, any other bytes as hex
+pub(super) fn display_metadata(metadata: &[u8]) -> String {
+    match std::str::from_utf8(metadata) {
+        Ok(metadata_text) => metadata_text.into(),
+        Err(_) => metadata.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    }
+}
+
+impl GameRoomRouterActor {
+    // Announced rooms first, then the ones only left with clients of a previous announcement
+    pub(crate) fn list_rooms(&self) -> Vec<RoomStatus> {
+        let mut room_ids = self.available_rooms.clone();
+
+        for room_id in self.game_rooms.keys() {
+            if !room_ids.contains(room_id) {
+                room_ids.push(*room_id);
+            }
+        }
+
+        room_ids
+            .into_iter()
+            .map(|room_id| RoomStatus {
+                room_id,
+                client_party_ids: self
+                    .game_rooms
+                    .get(&room_id)
+                    .map(|room_clients| room_clients.keys().copied().collect())
+                    .unwrap_or_default(),
+                client_metadata: self
+                    .game_rooms
+                    .get(&room_id)
+                    .map(|room_clients| {
+                        room_clients
+                            .iter()
+                            .filter(|(_, room_client)| !room_client.metadata.is_empty())
+                            .map(|(client_party_id, room_client)| {
+                                (*client_party_id, display_metadata(&room_client.metadata))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                routed_messages: self.room_routed_messages.get(&room_id).copied().unwrap_or(0),
+                is_paused: self.paused_rooms.contains_key(&room_id),
+                owner_server_id: self.room_server_id(room_id),
+                template: self
+                    .provisioned_rooms
+                    .get(&room_id)
+                    .map(|provisioned_room| provisioned_room.template.clone()),
+                metadata: self
+                    .provisioned_rooms
+                    .get(&room_id)
+                    .filter(|provisioned_room| !provisioned_room.metadata.is_empty())
+                    .map(|provisioned_room| display_metadata(&provisioned_room.metadata)),
+            })
+            .collect()
+    }
+
+    // The client forgets itself from the router once its connection is closed
+    pub(crate) fn kick_client(&self, room_id: u32, client_party_id: u32, reason: &str) -> bool {
+        let room_client = match self
+            .game_rooms
+            .get(&room_id)
+            .and_then(|room_clients| room_clients.get(&client_party_id))
+        {
+            Some(room_client) => room_client,
+            None => return false,
+        };
+        let party_id = PartyId::Client(client_party_id);
+        let _ = room_client
+            .address
+            .do_send(InterActorMessage::Disconnect(party_id, Some(room_client.client_id)));
+
+        ADMIN_EVENTS.publish(AdminEvent::Kicked {
+            room_id: Some(room_id),
+            party_id: party_id.get_repr(),
+            reason: reason.into(),
+        });
+
+        true
+    }
+}
+
+impl MessageHandler<ListRooms> for GameRoomRouterActor {
+    type Result = MessageResult<ListRooms>;
+
+    fn handle(&mut self, _: ListRooms, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.list_rooms())
+    }
+}
+
+impl MessageHandler<AdminCommand> for GameRoomRouterActor {
+    type Result = usize;
+
+    fn handle(&mut self, command: AdminCommand, _: &mut Self::Context) -> Self::Result {
+        match command {
+            AdminCommand::Kick(room_id, client_party_id) => {
+                self.kick_client(room_id, client_party_id, "admin") as usize
+            }
+            AdminCommand::Drain(room_id) => {
+                let room_party_ids: Vec<u32> = self
+                    .game_rooms
+                    .get(&room_id)
+                    .map(|room_clients| room_clients.keys().copied().collect())
+                    .unwrap_or_default();
+
+                room_party_ids
+                    .into_iter()
+                    .filter(|client_party_id| {
+                        self.kick_client(room_id, *client_party_id, "admin drain")
+                    })
+                    .count()
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::admin_events::{AdminEventRecord, ADMIN_EVENTS};
+use crate::ws_handlers::{CLIENT_TIMEOUT, HEARTBEAT_INTERVAL, MAILBOX_CAPACITY};
+use actix::clock::Instant;
+use actix::{
+    Actor as ActixActor, ActorContext, AsyncContext, Handler, Running,
+    StreamHandler as ReceiveHandler,
+};
+use actix_web_actors::ws::{
+    CloseReason, Message as WsMessage, ProtocolError as WsProtocolError, WebsocketContext,
+};
+use log::{info, warn};
+
+/// Read only connection streaming the `ADMIN_EVENTS` as JSON text messages
+#[derive(Debug)]
+pub(crate) struct AdminActor {
+    subscriber_id: Option<u64>,
+    last_known_activity: Instant,
+}
+
+impl AdminActor {
+    pub(crate) fn new() -> Self {
+        Self { subscriber_id: None, last_known_activity: Instant::now() }
+    }
+
+    pub(crate) fn heartbeat(&self, context: &mut WebsocketContext<Self>) {
+        context.run_interval(HEARTBEAT_INTERVAL, |actor, context| {
+            if Instant::now().duration_since(actor.last_known_activity) > CLIENT_TIMEOUT {
+                info!("Admin kicked because of {:#?} inactivity!", CLIENT_TIMEOUT);
+                Self::close_and_disconnect(context, None);
+            } else {
+                context.ping(b"");
+            }
+        });
+    }
+
+    pub(crate) fn close_and_disconnect(
+        context: &mut WebsocketContext<Self>,
+        reason: Option<CloseReason>,
+    ) {
+        context.close(reason);
+        context.stop();
+    }
+}
+
+impl ActixActor for AdminActor {
+    type Context = WebsocketContext<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+        self.subscriber_id = Some(ADMIN_EVENTS.subscribe(context.address().recipient()));
+        self.heartbeat(context);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if let Some(subscriber_id) = self.subscriber_id.take() {
+            ADMIN_EVENTS.unsubscribe(subscriber_id);
+        }
+
+        Running::Stop
+    }
+}
+
+impl Handler<AdminEventRecord> for AdminActor {
+    type Result = ();
+
+    fn handle(&mut self, record: AdminEventRecord, context: &mut Self::Context) {
+        match serde_json::to_string(&record) {
+            Ok(text_payload) => context.text(text_payload),
+            Err(error) => warn!("Admin event could not be serialized: {}", error),
+        }
+    }
+}
+
+impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for AdminActor {
+    fn handle(
+        &mut self,
+        stream_result: Result<WsMessage, WsProtocolError>,
+        context: &mut Self::Context,
+    ) {
+        if let Ok(payload) = stream_result {
+            match payload {
+                WsMessage::Close(reason) => {
+                    Self::close_and_disconnect(context, reason);
+                }
+                WsMessage::Pong(_) => self.last_known_activity = Instant::now(),
+                WsMessage::Ping(ping_payload) => {
+                    self.last_known_activity = Instant::now();
+                    context.pong(&ping_payload);
+                }
+                _ => (),
+            }
+        } else {
+            Self::close_and_disconnect(context, None);
+        }
+    }
+}
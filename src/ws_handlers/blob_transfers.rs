@@ -0,0 +1,83 @@
+use super::GameRoomRouterActor;
+use crate::proto::{BlobFrame, BlobKind, ErrorCode, MessagePriority, MessageStream, PartyId};
+use log::warn;
+
+impl GameRoomRouterActor {
+    // False when the Blob frame is dropped, the origin being replied an error. Chunks always take
+    // the Bulk lane so that a transfer never delays gameplay frames. Chunks to a single party may
+    // only run --blob-window bytes past the length the destination acknowledged, the first chunk
+    // seen setting where a transfer starts so that a resumed one goes on from its last ack
+    pub(crate) fn admit_blob_frame(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &mut MessageStream,
+    ) -> bool {
+        let room_id = message.room_id;
+        let blob_frame = match BlobFrame::from_raw(&message.payload) {
+            Ok(blob_frame) => blob_frame,
+            Err(error) => {
+                warn!("Party ID {} sent a bad blob frame: {}", origin_party_id.get_repr(), error);
+                self.reply_error(
+                    room_id,
+                    origin_party_id,
+                    ErrorCode::UndecodablePayload,
+                    &[message.payload_kind.into()],
+                );
+                return false;
+            }
+        };
+        let is_single_destination = message.destination_id.is_single_client_id()
+            || message.destination_id.is_single_server_id();
+
+        if blob_frame.kind == BlobKind::Chunk {
+            message.header_options.priority = Some(MessagePriority::Bulk);
+        }
+
+        let blob_window = match self.config.blob_window {
+            Some(blob_window) if is_single_destination => blob_window,
+            _ => return true,
+        };
+
+        match blob_frame.kind {
+            BlobKind::Chunk => {
+                let transfer_key = (room_id, origin_party_id.get_repr(), blob_frame.transfer_id);
+                let acknowledged_length =
+                    *self.blob_transfers.entry(transfer_key).or_insert(blob_frame.offset);
+
+                if blob_frame.end() <= acknowledged_length as u64 + blob_window as u64 {
+                    return true;
+                }
+
+                let mut details = blob_frame.transfer_id.to_le_bytes().to_vec();
+                details.extend_from_slice(&acknowledged_length.to_le_bytes());
+                self.reply_error(room_id, origin_party_id, ErrorCode::BlobWindowExceeded, &details);
+
+                false
+            }
+            // Acks go to the sender, completed transfers are forgotten
+            BlobKind::Ack => {
+                let transfer_key =
+                    (room_id, message.destination_id.get_repr(), blob_frame.transfer_id);
+
+                if blob_frame.offset >= blob_frame.total_length {
+                    self.blob_transfers.remove(&transfer_key);
+                } else if let Some(acknowledged_length) = self.blob_transfers.get_mut(&transfer_key)
+                {
+                    *acknowledged_length = (*acknowledged_length).max(blob_frame.offset);
+                }
+
+                true
+            }
+        }
+    }
+
+    // Transfers of a party leaving the room, or of every party once the room is gone
+    pub(crate) fn forget_blob_transfers(&mut self, room_id: u32, party_id: Option<PartyId>) {
+        let party_id = party_id.map(|party_id| party_id.get_repr());
+
+        self.blob_transfers.retain(|(transfer_room_id, sender_party_id, _), _| {
+            *transfer_room_id != room_id
+                || party_id.is_some_and(|party_id| party_id != *sender_party_id)
+        });
+    }
+}
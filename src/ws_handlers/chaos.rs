@@ -0,0 +1,153 @@
+use super::GameRoomRouterActor;
+use crate::proto::{MessageStream, PartyId};
+use crate::{anyerror, AnyError, AnyResult};
+use actix::clock::Duration;
+use actix::{AsyncContext, Context};
+use log::debug;
+use std::str::FromStr;
+
+const DEFAULT_MAX_CHAOS_DELAY: Duration = Duration::from_millis(200);
+
+// Fractions of the Normal frames of a room the router drops, delays or reorders, their sum at
+// most 1.0. Delayed frames wait up to max_delay, and so do reordered frames no other overtakes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ChaosSpec {
+    pub(crate) drop: f64,
+    pub(crate) delay: f64,
+    pub(crate) reorder: f64,
+    pub(crate) max_delay: Duration,
+}
+
+impl Default for ChaosSpec {
+    fn default() -> Self {
+        Self { drop: 0.0, delay: 0.0, reorder: 0.0, max_delay: DEFAULT_MAX_CHAOS_DELAY }
+    }
+}
+
+// Spec of a room, or of every room without its own, as
+// [<room-id>:]drop=<fraction>,delay=<fraction>,reorder=<fraction>,max-delay=<millis>
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ChaosRule {
+    pub(crate) room_id: Option<u32>,
+    pub(crate) spec: ChaosSpec,
+}
+
+impl FromStr for ChaosRule {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let (room_id, settings) = match source.split_once(':') {
+            Some((room_id, settings)) => (Some(room_id.trim().parse()?), settings),
+            None => (None, source),
+        };
+        let mut spec = ChaosSpec::default();
+
+        for setting in settings.split(',') {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| anyerror!("Expected <setting>=<value>, got {}", setting))?;
+            let value = value.trim();
+
+            match key.trim() {
+                "drop" => spec.drop = value.parse()?,
+                "delay" => spec.delay = value.parse()?,
+                "reorder" => spec.reorder = value.parse()?,
+                "max-delay" => spec.max_delay = Duration::from_millis(value.parse()?),
+                key => {
+                    return Err(anyerror!(
+                        "Unknown chaos setting {}, expected drop, delay, reorder or max-delay",
+                        key
+                    ))
+                }
+            }
+        }
+
+        let fractions = [spec.drop, spec.delay, spec.reorder];
+
+        if fractions.iter().any(|fraction| !(0.0..=1.0).contains(fraction))
+            || fractions.iter().sum::<f64>() > 1.0
+        {
+            return Err(anyerror!("Chaos fractions must be within 0.0 and 1.0 in total"));
+        }
+
+        Ok(Self { room_id, spec })
+    }
+}
+
+impl GameRoomRouterActor {
+    fn chaos_spec(&self, room_id: u32) -> Option<ChaosSpec> {
+        let chaos = &self.config.chaos;
+
+        chaos.get(&Some(room_id)).or_else(|| chaos.get(&None)).copied()
+    }
+
+    // Normal frames go through here once, right before being routed. A reordered frame is held
+    // until the next frame of its room overtakes it
+    pub(crate) fn route_chaotic(
+        &mut self,
+        origin_party_id: PartyId,
+        message_stream: MessageStream,
+        context: &mut Context<Self>,
+    ) {
+        let room_id = message_stream.room_id;
+        let chaos_spec = match self.chaos_spec(room_id) {
+            Some(chaos_spec) => chaos_spec,
+            None => return self.route_normal(origin_party_id, message_stream, context),
+        };
+        let roll = rand::random::<f64>();
+
+        if roll < chaos_spec.drop {
+            debug!("Chaos dropped a frame of room {}", room_id);
+        } else if roll < chaos_spec.drop + chaos_spec.delay {
+            let delay = chaos_spec.max_delay.mul_f64(rand::random::<f64>());
+            context.run_later(delay, move |actor, context| {
+                actor.route_normal(origin_party_id, message_stream, context);
+            });
+        } else if roll < chaos_spec.drop + chaos_spec.delay + chaos_spec.reorder
+            && !self.chaos_held.contains_key(&room_id)
+        {
+            self.chaos_held.insert(room_id, (origin_party_id, message_stream));
+            context.run_later(chaos_spec.max_delay, move |actor, context| {
+                actor.release_chaos_held(room_id, context);
+            });
+        } else {
+            self.route_normal(origin_party_id, message_stream, context);
+            self.release_chaos_held(room_id, context);
+        }
+    }
+
+    fn release_chaos_held(&mut self, room_id: u32, context: &mut Context<Self>) {
+        if let Some((origin_party_id, message_stream)) = self.chaos_held.remove(&room_id) {
+            self.route_normal(origin_party_id, message_stream, context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chaos_rule_from_str_is_as_expected() {
+        assert_eq!(
+            "3:drop=0.1, delay=0.2,max-delay=50".parse::<ChaosRule>().unwrap(),
+            ChaosRule {
+                room_id: Some(3),
+                spec: ChaosSpec {
+                    drop: 0.1,
+                    delay: 0.2,
+                    reorder: 0.0,
+                    max_delay: Duration::from_millis(50),
+                },
+            }
+        );
+        assert_eq!(
+            "reorder=0.5".parse::<ChaosRule>().unwrap(),
+            ChaosRule { room_id: None, spec: ChaosSpec { reorder: 0.5, ..Default::default() } }
+        );
+        assert!("drop=0.6,delay=0.6".parse::<ChaosRule>().is_err());
+        assert!("drop=-0.1".parse::<ChaosRule>().is_err());
+        assert!("jitter=0.1".parse::<ChaosRule>().is_err());
+        assert!("x:drop=0.1".parse::<ChaosRule>().is_err());
+    }
+}
@@ -1,120 +1,940 @@
-use crate::proto::{MessageStream, PartyId};
+use crate::admin_events::{unix_millis, AdminEvent, ADMIN_EVENTS};
+use crate::audit_log::{AuditAction, AUDIT_LOG, ROUTER_PRINCIPAL};
+use crate::connection_journal::{ConnectionEvent, ConnectionEventKind, CONNECTION_JOURNAL};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{
+    CompressionCodec, ConnectionStats, FrameFormat, FrameViolation, InfoCode, JsonEnvelope,
+    MessageBatch, MessageReliability, MessageStream, PartyId, PayloadKind,
+};
+use crate::telemetry::{HopSpan, TraceContext};
 use crate::ws_handlers::{
-    GameRoomRouterActor, InterActorMessage, HEARTBEAT_INTERVAL, MAILBOX_CAPACITY,
+    connection_span, deliver_to_router, ConnectionPermit, FrameStrikes, GameRoomRouterActor,
+    InterActorMessage, MessageReassembly, OutboundLanes, SigningKey, SimulatedLink, UdpReceived,
+    UdpRelay, UdpSession, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL, MAILBOX_CAPACITY,
+    MALFORMED_FRAMES_REASON, UDP_RETRANSMIT_INTERVAL,
 };
-use crate::CLIENT_TIMEOUT;
-use actix::clock::Instant;
+use crate::AnyResult;
+use actix::clock::{delay_for, Duration, Instant};
+use actix::dev::ToEnvelope;
 use actix::{
-    Actor as ActixActor, ActorContext, Addr as ActorAddress, AsyncContext, Handler, Running,
-    StreamHandler as ReceiveHandler,
+    Actor as ActixActor, ActorContext, Addr as ActorAddress, AsyncContext, Handler, Message,
+    Running, StreamHandler as ReceiveHandler,
 };
 use actix_web_actors::ws::{
-    CloseReason, Message as WsMessage, ProtocolError as WsProtocolError, WebsocketContext,
+    CloseCode, CloseReason, Message as WsMessage, ProtocolError as WsProtocolError,
+    WebsocketContext,
 };
 use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use tracing::Span;
 use uuid::Uuid;
 
+pub(crate) const LAST_ROOM_LEFT_REASON: &str = "Last room left";
+pub(crate) const NOT_A_MEMBER_REASON: &str = "Not a member of the room";
+// Close code of the clients evicted for lagging, in the range left to applications
+const SLOW_CLIENT_CLOSE_CODE: u16 = 4008;
+const SLOW_CLIENT_REASON: &str = "Too slow";
+const BANNED_REASON: &str = "Banned";
+// Close reasons journaled for connections closed without a description
+const CLIENT_CLOSED_REASON: &str = "Closed by the client";
+// Realtime frames are dropped past this many frames queued, or this long waited by the oldest
+const REALTIME_MAX_QUEUED: usize = 16;
+const REALTIME_MAX_LAG: Duration = Duration::from_millis(100);
+const ROUTER_DISCONNECTED_REASON: &str = "Disconnected by the router";
+const CONNECTION_DROPPED_REASON: &str = "Connection dropped";
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub(crate) struct SlowClientEvicted;
+
+// Socket a client connection is served over, the membership, heartbeat and routing logic of
+// `ClientActor` is the same whatever the transport
+pub(crate) trait ClientTransport: Sized + Unpin + 'static {
+    type Context: ActorContext
+        + AsyncContext<ClientActor<Self>>
+        + ToEnvelope<ClientActor<Self>, InterActorMessage>
+        + ToEnvelope<ClientActor<Self>, UdpReceived>
+        + ToEnvelope<ClientActor<Self>, SlowClientEvicted>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize);
+    fn send(&mut self, context: &mut Self::Context, frame: WsMessage); // Binary or text frames
+
+    // Frames flagged Unreliable, transports with datagrams may send them as one
+    fn send_unreliable(&mut self, context: &mut Self::Context, frame: WsMessage) {
+        self.send(context, frame);
+    }
+
+    fn ping(&mut self, context: &mut Self::Context);
+    fn close(&mut self, context: &mut Self::Context, reason: Option<CloseReason>);
+
+    // Transports holding a pending request of the client count it as activity
+    fn is_client_waiting(&self) -> bool {
+        false
+    }
+
+    // How long the oldest frame sent has waited for the client, for transports queuing the frames
+    // while the actor keeps running. A websocket not read stops its actor instead
+    fn outbound_lag(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct WsTransport;
+
+impl ClientTransport for WsTransport {
+    type Context = WebsocketContext<ClientActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, context: &mut Self::Context, frame: WsMessage) {
+        match frame {
+            WsMessage::Text(text) => context.text(text),
+            WsMessage::Binary(binary) => context.binary(binary),
+            _ => (),
+        }
+    }
+
+    fn ping(&mut self, context: &mut Self::Context) {
+        context.ping(b"");
+    }
+
+    fn close(&mut self, context: &mut Self::Context, reason: Option<CloseReason>) {
+        context.close(reason);
+    }
+}
+
 #[derive(Debug)]
-pub(crate) struct ClientActor {
-    party_id: PartyId,
+pub(crate) struct ClientActor<T: ClientTransport> {
+    memberships: BTreeMap<u32, PartyId>, // Room ID -> Party ID in that room
+    left_room_ids: BTreeSet<u32>,        // Frames still in flight for these rooms are dropped
     client_id: Uuid,
     last_known_activity: Instant,
+    client_timeout: Duration, // Set by the router from the config of the rooms
     router_actor: ActorAddress<GameRoomRouterActor>,
+    outbound_lanes: OutboundLanes,
+    accepted_codecs: Vec<CompressionCodec>,
+    pub(crate) frame_format: FrameFormat,
+    pub(crate) log_span: Span,
+    pub(crate) transport: T,
+    udp_relay: Option<UdpRelay>, // The session is opened once started
+    udp_session: Option<UdpSession>,
+    connection_permit: Option<ConnectionPermit>, // Held until the actor is dropped
+    slow_client_lag: Option<Duration>, // Evicted once its outbound lag exceeds it when set
+    outbound_pulse: Arc<Mutex<Instant>>, // Last time the outbound path was seen within the lag
+    mailbox_capacity: usize,
+    connected_room: (u32, PartyId), // Room the connection was opened for, as journaled
+    connected_at: Instant,
+    bytes_received: u64,
+    bytes_sent: u64,
+    frames_received: u64,
+    frames_sent: u64,
+    pinged_at: Option<Instant>, // Last ping or heartbeat sent, until answered
+    round_trip: Option<Duration>, // Of the last ping or heartbeat answered
+    close_reason: Option<String>, // The first one given, journaled once stopped
+    simulated_link: SimulatedLink, // Set by an admin, delays the outbound frames
+    app_heartbeat: bool,        // Heartbeat frames instead of pings, negotiated on the upgrade
+    frame_strikes: FrameStrikes,
+    message_reassembly: MessageReassembly,
+    signing_key: Option<SigningKey>, // Outbound frames are signed with it when joined with a token
 }
 
-impl ClientActor {
+impl<T: ClientTransport> ClientActor<T> {
     pub(crate) fn new(
+        room_id: u32,
         party_id: PartyId,
         client_id: Uuid,
         router_actor: ActorAddress<GameRoomRouterActor>,
+        accepted_codecs: Vec<CompressionCodec>,
+        frame_format: FrameFormat,
+        transport: T,
     ) -> Self {
-        Self { party_id, client_id, last_known_activity: Instant::now(), router_actor }
+        Self {
+            memberships: vec![(room_id, party_id)].into_iter().collect(),
+            left_room_ids: Default::default(),
+            client_id,
+            last_known_activity: Instant::now(),
+            client_timeout: CLIENT_TIMEOUT,
+            router_actor,
+            outbound_lanes: Default::default(),
+            accepted_codecs,
+            frame_format,
+            log_span: connection_span(Some(room_id), party_id, client_id),
+            transport,
+            udp_relay: None,
+            udp_session: None,
+            connection_permit: None,
+            slow_client_lag: None,
+            outbound_pulse: Arc::new(Mutex::new(Instant::now())),
+            mailbox_capacity: MAILBOX_CAPACITY,
+            connected_room: (room_id, party_id),
+            connected_at: Instant::now(),
+            bytes_received: 0,
+            bytes_sent: 0,
+            frames_received: 0,
+            frames_sent: 0,
+            pinged_at: None,
+            round_trip: None,
+            close_reason: None,
+            simulated_link: Default::default(),
+            app_heartbeat: false,
+            frame_strikes: Default::default(),
+            message_reassembly: Default::default(),
+            signing_key: None,
+        }
     }
 
-    pub(crate) fn heartbeat(&self, context: &mut WebsocketContext<Self>) {
+    pub(crate) fn with_max_frame_violations(mut self, max_frame_violations: Option<u32>) -> Self {
+        self.frame_strikes = FrameStrikes::new(max_frame_violations);
+        self
+    }
+
+    pub(crate) fn with_max_message_length(mut self, max_message_length: usize) -> Self {
+        self.message_reassembly = MessageReassembly::new(Some(max_message_length));
+        self
+    }
+
+    pub(crate) fn with_udp_relay(mut self, udp_relay: Option<UdpRelay>) -> Self {
+        self.udp_relay = udp_relay;
+        self
+    }
+
+    pub(crate) fn with_connection_permit(mut self, connection_permit: ConnectionPermit) -> Self {
+        self.connection_permit = Some(connection_permit);
+        self
+    }
+
+    pub(crate) fn with_slow_client_lag(mut self, slow_client_lag: Option<Duration>) -> Self {
+        self.slow_client_lag = slow_client_lag;
+        self
+    }
+
+    pub(crate) fn with_mailbox_capacity(mut self, mailbox_capacity: usize) -> Self {
+        self.mailbox_capacity = mailbox_capacity;
+        self
+    }
+
+    pub(crate) fn with_app_heartbeat(mut self, app_heartbeat: bool) -> Self {
+        self.app_heartbeat = app_heartbeat;
+        self
+    }
+
+    pub(crate) fn with_signing_key(mut self, signing_key: Option<SigningKey>) -> Self {
+        self.signing_key = signing_key;
+        self
+    }
+
+    // The client is told its session token over the connection before any datagram
+    fn open_udp_session(&mut self, context: &mut T::Context) {
+        let udp_relay = match self.udp_relay.take() {
+            Some(udp_relay) => udp_relay,
+            None => return,
+        };
+        let udp_session = udp_relay.open_session(context.address().recipient());
+
+        if let Some((room_id, party_id)) = self.memberships.iter().next() {
+            let session_info = MessageStream::new_info(
+                *room_id,
+                *party_id,
+                InfoCode::UdpSession,
+                &udp_session.session_details(),
+            );
+            self.outbound_lanes.push(session_info, None);
+            self.schedule_outbound_drain(context);
+        }
+
+        if udp_session.is_reliable() {
+            context.run_interval(UDP_RETRANSMIT_INTERVAL, |actor, _| {
+                if let Some(udp_session) = actor.udp_session.as_mut() {
+                    udp_session.retransmit();
+                }
+            });
+        }
+
+        self.udp_session = Some(udp_session);
+    }
+
+    // Frames the UDP session does not take are handed back for the connection
+    fn send_over_udp(&mut self, mut message: MessageStream) -> Result<(), Box<MessageStream>> {
+        let udp_session = match self.udp_session.as_mut() {
+            Some(udp_session) => udp_session,
+            None => return Err(Box::new(message)),
+        };
+
+        if message.decompress_unless_accepted(&self.accepted_codecs).is_err() {
+            return Err(Box::new(message));
+        }
+
+        if let Some(signing_key) = self.signing_key {
+            signing_key.sign(&mut message);
+        }
+
+        udp_session.send(message)
+    }
+
+    // Logs are attached to the first room of the connection
+    pub(crate) fn update_log_span(&mut self) {
+        if let Some((room_id, party_id)) = self.memberships.iter().next() {
+            self.log_span = connection_span(Some(*room_id), *party_id, self.client_id);
+        }
+    }
+
+    pub(crate) fn is_member(&self, party_id: PartyId) -> bool {
+        self.memberships.values().any(|member_party_id| *member_party_id == party_id)
+    }
+
+    pub(crate) fn heartbeat(&self, context: &mut T::Context) {
         context.run_interval(HEARTBEAT_INTERVAL, |actor, context| {
-            if Instant::now().duration_since(actor.last_known_activity) > CLIENT_TIMEOUT {
+            let _log_span = actor.log_span.clone().entered();
+
+            if actor.transport.is_client_waiting() {
+                actor.update_last_known_activity();
+            }
+
+            if Instant::now().duration_since(actor.last_known_activity) > actor.client_timeout {
                 info!(
-                    "Party ID {} kicked because of {:#?} inactivity!",
-                    actor.party_id.get_repr(),
-                    CLIENT_TIMEOUT,
+                    "Client {} kicked because of {:#?} inactivity!",
+                    actor.client_id, actor.client_timeout
                 );
-                Self::close_and_disconnect(context, None);
+
+                let reason = format!("{:#?} inactivity", actor.client_timeout);
+                actor.close_reason = Some(reason.clone());
+
+                for (room_id, party_id) in actor.memberships.iter() {
+                    AUDIT_LOG.record(
+                        ROUTER_PRINCIPAL,
+                        AuditAction::Kick {
+                            room_id: Some(*room_id),
+                            party_id: party_id.get_repr(),
+                            reason: reason.clone(),
+                        },
+                    );
+                    ADMIN_EVENTS.publish(AdminEvent::Kicked {
+                        room_id: Some(*room_id),
+                        party_id: party_id.get_repr(),
+                        reason: reason.clone(),
+                    });
+                }
+
+                actor.close_and_disconnect(context, None);
+            } else if actor.app_heartbeat {
+                actor.send_heartbeat(context);
+                actor.pinged_at = Some(Instant::now());
             } else {
-                context.ping(b"");
+                actor.transport.ping(context);
+                actor.pinged_at = Some(Instant::now());
+            }
+
+            actor.update_outbound_pulse();
+        });
+    }
+
+    // Straight to the transport like a ping, ahead of the queued frames
+    fn send_heartbeat(&mut self, context: &mut T::Context) {
+        let heartbeat = match self.memberships.iter().next() {
+            Some((room_id, party_id)) => MessageStream::new_heartbeat(*room_id, *party_id),
+            None => return,
+        };
+
+        if let Ok(frame) = self.encode_outbound(heartbeat) {
+            self.transport.send(context, frame);
+        }
+    }
+
+    // Pongs and heartbeats answer the last ping or heartbeat sent, any older one being lost
+    pub(crate) fn update_round_trip(&mut self) {
+        if let Some(pinged_at) = self.pinged_at.take() {
+            self.round_trip = Some(pinged_at.elapsed());
+        }
+    }
+
+    fn connection_stats(&self, client_party_id: u32) -> ConnectionStats {
+        ConnectionStats {
+            client_party_id,
+            frames_received: self.frames_received,
+            frames_sent: self.frames_sent,
+            bytes_received: self.bytes_received,
+            bytes_sent: self.bytes_sent,
+            round_trip_millis: self
+                .round_trip
+                .map(|round_trip| round_trip.as_millis().min(u32::MAX as u128 - 1) as u32),
+            queued_frames: self.outbound_lanes.len() as u32,
+        }
+    }
+
+    // Voice packets waiting behind others are better lost than late
+    fn is_congested(&self) -> bool {
+        self.outbound_lanes.len() >= REALTIME_MAX_QUEUED
+            || self
+                .transport
+                .outbound_lag()
+                .is_some_and(|outbound_lag| outbound_lag > REALTIME_MAX_LAG)
+    }
+
+    fn update_outbound_pulse(&self) {
+        let is_lagging = match (self.slow_client_lag, self.transport.outbound_lag()) {
+            (Some(slow_client_lag), Some(outbound_lag)) => outbound_lag > slow_client_lag,
+            _ => false,
+        };
+
+        if !is_lagging {
+            if let Ok(mut write_guard) = self.outbound_pulse.lock() {
+                *write_guard = Instant::now();
+            }
+        }
+    }
+
+    // Watched out of the actor, which stops running while the client does not read its websocket.
+    // The router forgets a lagging client right away so frames stop piling up in its mailbox, the
+    // connection is closed once the actor runs again
+    fn watch_outbound_lag(&self, context: &mut T::Context) {
+        let slow_client_lag = match self.slow_client_lag {
+            Some(slow_client_lag) => slow_client_lag,
+            None => return,
+        };
+        let outbound_pulse = self.outbound_pulse.clone();
+        let router_actor = self.router_actor.clone();
+        let client_id = self.client_id;
+        let weak_address = context.address().downgrade();
+
+        actix::spawn(async move {
+            loop {
+                delay_for(HEARTBEAT_INTERVAL).await;
+
+                let address = match weak_address.upgrade() {
+                    Some(address) if address.connected() => address,
+                    _ => break,
+                };
+                let outbound_lag = match outbound_pulse.lock() {
+                    Ok(read_guard) => Instant::now().duration_since(*read_guard),
+                    Err(_) => break,
+                };
+
+                // The pulse is only refreshed every heartbeat
+                if outbound_lag > slow_client_lag + HEARTBEAT_INTERVAL {
+                    info!(
+                        "Client {} evicted because of {:#?} outbound lag!",
+                        client_id, outbound_lag
+                    );
+                    router_actor.do_send(InterActorMessage::SlowClient(
+                        client_id,
+                        address.clone().recipient(),
+                        outbound_lag,
+                    ));
+                    address.do_send(SlowClientEvicted);
+                    break;
+                }
             }
         });
     }
 
+    pub(crate) fn schedule_outbound_drain(&mut self, context: &mut T::Context) {
+        if self.outbound_lanes.is_drain_scheduled {
+            return;
+        }
+
+        self.outbound_lanes.is_drain_scheduled = true;
+        context.run_later(self.simulated_link.next_drain_delay(), |actor, context| {
+            let _log_span = actor.log_span.clone().entered();
+            actor.outbound_lanes.is_drain_scheduled = false;
+            let drain_budget = actor.simulated_link.drain_budget();
+
+            for (message, trace_context) in actor.outbound_lanes.pop_budgeted(drain_budget) {
+                let destination_party_id = message.destination_id.get_repr();
+                let reliability = message.reliability();
+                let ack = message
+                    .header_options
+                    .ack_sequence
+                    .map(|ack_sequence| (message.room_id, message.origin_id, ack_sequence));
+
+                let raw_length = message.raw_length();
+                let sent_length = match actor
+                    .send_over_udp(message)
+                    .map_err(|message| actor.encode_outbound(*message))
+                {
+                    Ok(()) => Some(raw_length),
+                    Err(Ok(frame)) if reliability != MessageReliability::Reliable => {
+                        let frame_length = frame_length(&frame);
+                        actor.transport.send_unreliable(context, frame);
+                        Some(frame_length)
+                    }
+                    Err(Ok(frame)) => {
+                        let frame_length = frame_length(&frame);
+                        actor.transport.send(context, frame);
+                        Some(frame_length)
+                    }
+                    Err(Err(error)) => {
+                        warn!(
+                            "Dropping undecodable message for Party ID {}: {}",
+                            destination_party_id, error
+                        );
+                        None
+                    }
+                };
+                let is_delivered = sent_length.is_some();
+
+                if let Some(sent_length) = sent_length {
+                    actor.frames_sent += 1;
+                    actor.bytes_sent += sent_length as u64;
+                    actor.simulated_link.occupy(sent_length);
+                }
+
+                if let Some((room_id, origin_party_id, ack_sequence)) = ack {
+                    actor.report_delivery(room_id, origin_party_id, ack_sequence, is_delivered);
+                }
+
+                if let Some(trace_context) = trace_context {
+                    HopSpan::follow("deliver", trace_context)
+                        .with_attribute("party_id", destination_party_id)
+                        .end();
+                }
+            }
+
+            if !actor.outbound_lanes.is_empty() {
+                actor.schedule_outbound_drain(context);
+            }
+        });
+    }
+
+    // Acknowledges a frame handed to the transport, or tells it undecodable, as the party of its
+    // room
+    fn report_delivery(
+        &self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        ack_sequence: u32,
+        is_delivered: bool,
+    ) {
+        if let Some(party_id) = self.memberships.get(&room_id) {
+            deliver_to_router(
+                &self.router_actor,
+                InterActorMessage::DeliveryReport(
+                    room_id,
+                    origin_party_id,
+                    *party_id,
+                    ack_sequence,
+                    is_delivered,
+                ),
+            );
+        }
+    }
+
+    // Decompressed for JSON frames and unaccepted codecs, then signed with the token of the client
+    pub(crate) fn encode_outbound(&self, mut message: MessageStream) -> AnyResult<WsMessage> {
+        match self.frame_format {
+            FrameFormat::Binary => message.decompress_unless_accepted(&self.accepted_codecs)?,
+            FrameFormat::Json => message.decompress()?,
+        }
+
+        if let Some(signing_key) = self.signing_key {
+            signing_key.sign(&mut message);
+        }
+
+        match self.frame_format {
+            FrameFormat::Binary => Ok(WsMessage::Binary(message.into_raw().into())),
+            FrameFormat::Json => Ok(WsMessage::Text(JsonEnvelope::to_text(message)?)),
+        }
+    }
+
+    // Frames are told apart by their room ID and stamped with the party of that room, the origin
+    // they carry is not trusted. A room never joined is a protocol error closing the connection,
+    // false once closed
+    pub(crate) fn forward_to_router(
+        &mut self,
+        mut message_stream: MessageStream,
+        trace_context: Option<TraceContext>,
+        context: &mut T::Context,
+    ) -> bool {
+        let room_id = message_stream.room_id;
+
+        match self.memberships.get(&room_id) {
+            Some(party_id) => {
+                message_stream.origin_id = *party_id;
+                deliver_to_router(
+                    &self.router_actor,
+                    InterActorMessage::NewMessage(*party_id, message_stream, trace_context),
+                );
+            }
+            None if self.left_room_ids.contains(&room_id) => {
+                info!("Client {} sent a frame for room {} it left", self.client_id, room_id);
+            }
+            None => {
+                warn!("Client {} sent a frame for room {} it is not in", self.client_id, room_id);
+                let reason = CloseReason {
+                    code: CloseCode::Protocol,
+                    description: Some(NOT_A_MEMBER_REASON.into()),
+                };
+                self.close_and_disconnect(context, Some(reason));
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub(crate) fn forward_inbound(
+        &mut self,
+        mut message_stream: MessageStream,
+        context: &mut T::Context,
+    ) {
+        self.frames_received += 1;
+
+        // Counted as activity on receipt already, heartbeats go no further
+        if message_stream.is_heartbeat() {
+            self.update_round_trip();
+            return;
+        }
+
+        let origin_party_id = self.memberships.get(&message_stream.room_id).copied();
+        let ingress_span = HopSpan::ingress().map(|ingress_span| {
+            ingress_span
+                .with_attribute("room_id", message_stream.room_id)
+                .with_attribute(
+                    "party_id",
+                    origin_party_id.map(|party_id| party_id.get_repr()).unwrap_or_default(),
+                )
+                .with_attribute("payload_kind", u8::from(message_stream.payload_kind))
+                .with_attribute("payload_length", message_stream.payload.len() as i64)
+        });
+        let trace_context = ingress_span.as_ref().map(HopSpan::hand_over);
+
+        // Encrypted batches are not unpacked, the router refuses them
+        if message_stream.payload_kind == PayloadKind::Batch
+            && !message_stream.is_opaque()
+            && message_stream.decompress().is_ok()
+        {
+            // Inbound batches are routed frame by frame, all of them in the same trace
+            if let Ok(batched_messages) = MessageBatch::unpack(&message_stream) {
+                for batched_message in batched_messages {
+                    if !self.forward_to_router(batched_message, trace_context, context) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            self.forward_to_router(message_stream, trace_context, context);
+        }
+
+        if let Some(ingress_span) = ingress_span {
+            ingress_span.end();
+        }
+    }
+
+    // Binary frames are a raw MessageStream, or a JSON envelope for JSON clients on transports
+    // without text frames
+    pub(crate) fn handle_inbound_binary(
+        &mut self,
+        binary_payload: &[u8],
+        context: &mut T::Context,
+    ) {
+        self.update_last_known_activity();
+        self.bytes_received += binary_payload.len() as u64;
+
+        if self.frame_format == FrameFormat::Json {
+            match std::str::from_utf8(binary_payload) {
+                Ok(text_payload) => self.forward_inbound_text(text_payload, context),
+                Err(_) => {
+                    warn!("Client {} sent a non UTF-8 JSON envelope", self.client_id);
+                    self.reject_malformed(FrameViolation::BadJsonEnvelope, context);
+                }
+            }
+        } else {
+            self.forward_inbound_raw(binary_payload, context);
+        }
+    }
+
+    // Datagrams always carry binary frames whatever the format of the connection, like over UDP
+    // a malformed one is dropped without counting against the client
+    #[cfg(feature = "webtransport")]
+    pub(crate) fn handle_inbound_datagram(&mut self, datagram: &[u8], context: &mut T::Context) {
+        self.update_last_known_activity();
+        self.bytes_received += datagram.len() as u64;
+
+        match MessageStream::from_raw(datagram) {
+            Ok(message_stream) => self.forward_inbound(message_stream, context),
+            Err(error) => warn!("Client {} sent a malformed datagram: {}", self.client_id, error),
+        }
+    }
+
+    fn forward_inbound_raw(&mut self, binary_payload: &[u8], context: &mut T::Context) {
+        match MessageStream::from_raw(binary_payload) {
+            Ok(message_stream) => self.forward_inbound(message_stream, context),
+            Err(error) => {
+                warn!("Client {} sent a malformed frame: {}", self.client_id, error);
+                self.reject_malformed(FrameViolation::of_raw(binary_payload), context);
+            }
+        }
+    }
+
+    // Told with a MalformedFrame error as the party of its first room, and closed once out of
+    // strikes
+    fn reject_malformed(&mut self, violation: FrameViolation, context: &mut T::Context) {
+        let (room_id, party_id) = self
+            .memberships
+            .iter()
+            .next()
+            .map_or(self.connected_room, |(room_id, party_id)| (*room_id, *party_id));
+        let (error, is_out) = self.frame_strikes.strike(room_id, party_id, violation);
+
+        if let Ok(frame) = self.encode_outbound(error) {
+            self.transport.send(context, frame);
+        }
+
+        if is_out {
+            info!("Client {} closed for its malformed frames", self.client_id);
+            let reason = CloseReason {
+                code: CloseCode::Protocol,
+                description: Some(MALFORMED_FRAMES_REASON.into()),
+            };
+            self.close_and_disconnect(context, Some(reason));
+        }
+    }
+
+    pub(crate) fn handle_inbound_text(&mut self, text_payload: &str, context: &mut T::Context) {
+        self.update_last_known_activity();
+        self.bytes_received += text_payload.len() as u64;
+        self.forward_inbound_text(text_payload, context);
+    }
+
+    fn forward_inbound_text(&mut self, text_payload: &str, context: &mut T::Context) {
+        match JsonEnvelope::from_text(text_payload) {
+            Ok(message_stream) => self.forward_inbound(message_stream, context),
+            Err(error) => {
+                warn!("Client {} sent an invalid JSON envelope: {}", self.client_id, error);
+                self.reject_malformed(FrameViolation::BadJsonEnvelope, context);
+            }
+        }
+    }
+
     pub(crate) fn update_last_known_activity(&mut self) {
         self.last_known_activity = Instant::now();
     }
 
     pub(crate) fn close_and_disconnect(
-        context: &mut WebsocketContext<Self>,
+        &mut self,
+        context: &mut T::Context,
         reason: Option<CloseReason>,
     ) {
-        context.close(reason);
+        self.close_reason.get_or_insert_with(|| match reason.as_ref() {
+            Some(reason) => {
+                reason.description.clone().unwrap_or_else(|| format!("{:?}", reason.code))
+            }
+            None => CONNECTION_DROPPED_REASON.into(),
+        });
+        self.transport.close(context, reason);
         context.stop();
     }
+
+    fn journal_connection(&self, event: ConnectionEventKind) {
+        let (room_id, party_id) = self.connected_room;
+        let is_disconnected = event == ConnectionEventKind::Disconnected;
+
+        CONNECTION_JOURNAL.record(ConnectionEvent {
+            timestamp_millis: unix_millis(),
+            event,
+            room_id,
+            party_id: party_id.get_repr(),
+            client_id: self.client_id,
+            duration_millis: Some(self.connected_at.elapsed().as_millis() as u64)
+                .filter(|_| is_disconnected),
+            bytes_received: Some(self.bytes_received).filter(|_| is_disconnected),
+            bytes_sent: Some(self.bytes_sent).filter(|_| is_disconnected),
+            close_reason: self.close_reason.clone().filter(|_| is_disconnected),
+        });
+    }
 }
 
-impl ActixActor for ClientActor {
-    type Context = WebsocketContext<Self>;
+fn frame_length(frame: &WsMessage) -> usize {
+    match frame {
+        WsMessage::Text(text) => text.len(),
+        WsMessage::Binary(binary) => binary.len(),
+        _ => 0,
+    }
+}
+
+impl<T: ClientTransport> ActixActor for ClientActor<T> {
+    type Context = T::Context;
 
     fn started(&mut self, context: &mut Self::Context) {
-        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+        T::set_mailbox_capacity(context, self.mailbox_capacity);
         self.heartbeat(context);
+        self.watch_outbound_lag(context);
+        self.open_udp_session(context);
+        self.journal_connection(ConnectionEventKind::Connected);
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        self.router_actor
-            .do_send(InterActorMessage::Disconnect(self.party_id, Some(self.client_id)));
+        for party_id in self.memberships.values() {
+            self.router_actor
+                .do_send(InterActorMessage::Disconnect(*party_id, Some(self.client_id)));
+        }
+
+        self.close_reason.get_or_insert_with(|| CONNECTION_DROPPED_REASON.into());
+        self.journal_connection(ConnectionEventKind::Disconnected);
+
         Running::Stop
     }
 }
 
-impl Handler<InterActorMessage> for ClientActor {
+impl<T: ClientTransport> Handler<InterActorMessage> for ClientActor<T> {
     type Result = ();
 
     fn handle(&mut self, message: InterActorMessage, context: &mut Self::Context) {
         match message {
-            InterActorMessage::Disconnect(party_id, _) => {
-                if party_id == self.party_id {
-                    Self::close_and_disconnect(context, None);
+            InterActorMessage::Disconnect(party_id, _) if self.is_member(party_id) => {
+                self.close_reason.get_or_insert_with(|| ROUTER_DISCONNECTED_REASON.into());
+                self.close_and_disconnect(context, None);
+            }
+            InterActorMessage::CloseConnection(party_id, description)
+                if self.is_member(party_id) =>
+            {
+                info!("Party ID {} closed: {}", party_id.get_repr(), description);
+                let reason =
+                    CloseReason { code: CloseCode::Normal, description: Some(description) };
+                self.close_and_disconnect(context, Some(reason));
+            }
+            InterActorMessage::RoomSwitched(
+                room_id,
+                party_id,
+                switched_room_id,
+                switched_party_id,
+            ) if self.memberships.get(&room_id) == Some(&party_id) => {
+                self.memberships.remove(&room_id);
+                self.left_room_ids.insert(room_id);
+                self.memberships.insert(switched_room_id, switched_party_id);
+                self.update_log_span();
+            }
+            InterActorMessage::RoomJoined(room_id, party_id) => {
+                self.memberships.insert(room_id, party_id);
+                self.update_log_span();
+            }
+            InterActorMessage::RoomLeft(room_id, party_id) => {
+                if self.memberships.get(&room_id) == Some(&party_id) {
+                    self.memberships.remove(&room_id);
+                    self.left_room_ids.insert(room_id);
+                    self.update_log_span();
+                }
+
+                if self.memberships.is_empty() {
+                    info!("Client {} left its last room", self.client_id);
+                    let reason = CloseReason {
+                        code: CloseCode::Normal,
+                        description: Some(LAST_ROOM_LEFT_REASON.into()),
+                    };
+                    self.close_and_disconnect(context, Some(reason));
                 }
             }
-            InterActorMessage::NewMessage(_, binary_message) => {
-                context.binary(binary_message.into_raw());
+            InterActorMessage::NewMessage(_, binary_message, _)
+                if binary_message.reliability() == MessageReliability::Realtime
+                    && self.is_congested() =>
+            {
+                Metrics::increment(&METRICS.dropped_realtime);
+            }
+            InterActorMessage::NewMessage(_, binary_message, trace_context) => {
+                self.outbound_lanes.push(binary_message, trace_context);
+                self.schedule_outbound_drain(context);
+            }
+            InterActorMessage::SimulateLink(party_id, conditions) if self.is_member(party_id) => {
+                info!("Client {} link simulated as {:?}", self.client_id, conditions);
+                self.simulated_link.set(conditions);
+            }
+            InterActorMessage::ConfigUpdate(config_update) => {
+                let peer_ip = self.connection_permit.as_ref().and_then(ConnectionPermit::peer_ip);
+
+                if let Some(peer_ip) = peer_ip.filter(|peer_ip| config_update.is_banned(*peer_ip)) {
+                    info!("Client {} closed, {} is banned", self.client_id, peer_ip);
+                    let reason = CloseReason {
+                        code: CloseCode::Policy,
+                        description: Some(BANNED_REASON.into()),
+                    };
+                    self.close_and_disconnect(context, Some(reason));
+                }
+            }
+            InterActorMessage::ClientTimeout(client_timeout) => {
+                self.client_timeout = client_timeout;
+            }
+            InterActorMessage::QueryStats(room_id, party_id, server_party_id)
+                if self.memberships.get(&room_id) == Some(&party_id) =>
+            {
+                let connection_stats = self.connection_stats(party_id.get_repr());
+                deliver_to_router(
+                    &self.router_actor,
+                    InterActorMessage::ConnectionStats(room_id, server_party_id, connection_stats),
+                );
             }
             _ => (),
         }
     }
 }
 
-impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ClientActor {
+// The router forgot the client already and told the server
+impl<T: ClientTransport> Handler<SlowClientEvicted> for ClientActor<T> {
+    type Result = ();
+
+    fn handle(&mut self, _: SlowClientEvicted, context: &mut Self::Context) {
+        let _log_span = self.log_span.clone().entered();
+        let reason = CloseReason {
+            code: CloseCode::Other(SLOW_CLIENT_CLOSE_CODE),
+            description: Some(SLOW_CLIENT_REASON.into()),
+        };
+        self.close_and_disconnect(context, Some(reason));
+    }
+}
+
+// Datagrams are routed like the frames of the connection, which alone keeps the client alive
+impl<T: ClientTransport> Handler<UdpReceived> for ClientActor<T> {
+    type Result = ();
+
+    fn handle(&mut self, message: UdpReceived, context: &mut Self::Context) {
+        let _log_span = self.log_span.clone().entered();
+        let UdpReceived(peer_address, udp_datagram) = message;
+        let message_stream = match self.udp_session.as_mut() {
+            Some(udp_session) => udp_session.receive(peer_address, udp_datagram),
+            None => None,
+        };
+
+        if let Some(message_stream) = message_stream {
+            self.bytes_received += message_stream.raw_length() as u64;
+            self.forward_inbound(message_stream, context);
+        }
+    }
+}
+
+impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ClientActor<WsTransport> {
     fn handle(
         &mut self,
         stream_result: Result<WsMessage, WsProtocolError>,
         context: &mut Self::Context,
     ) {
+        let _log_span = self.log_span.clone().entered();
+
         if let Ok(payload) = stream_result {
             match payload {
                 WsMessage::Close(reason) => {
-                    Self::close_and_disconnect(context, reason);
+                    if reason.is_none() {
+                        self.close_reason = Some(CLIENT_CLOSED_REASON.into());
+                    }
+
+                    self.close_and_disconnect(context, reason);
+                }
+                WsMessage::Pong(_) => {
+                    self.update_last_known_activity();
+                    self.update_round_trip();
                 }
-                WsMessage::Pong(_) => self.update_last_known_activity(),
                 WsMessage::Ping(ping_payload) => {
                     self.update_last_known_activity();
                     context.pong(&ping_payload);
                 }
                 WsMessage::Binary(binary_payload) => {
                     self.update_last_known_activity();
-
-                    if let Ok(message_stream) = MessageStream::from_raw(&binary_payload) {
-                        self.router_actor
-                            .do_send(InterActorMessage::NewMessage(self.party_id, message_stream));
-                    }
+                    self.bytes_received += binary_payload.len() as u64;
+                    self.forward_inbound_raw(&binary_payload, context);
+                }
+                WsMessage::Text(text_payload) if self.frame_format == FrameFormat::Json => {
+                    self.handle_inbound_text(&text_payload, context);
                 }
                 WsMessage::Text(text_payload) => {
                     let text_payload = text_payload.trim();
@@ -124,10 +944,21 @@ impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ClientActor {
                         text_payload
                     ));
                 }
+                WsMessage::Continuation(item) => match self.message_reassembly.push(item) {
+                    Ok(Some(message)) => ReceiveHandler::handle(self, Ok(message), context),
+                    Ok(None) => self.update_last_known_activity(),
+                    Err(reason) => {
+                        warn!(
+                            "Client {} sent a fragmented message refused: {:?}",
+                            self.client_id, reason
+                        );
+                        self.close_and_disconnect(context, Some(reason));
+                    }
+                },
                 _ => (),
             }
         } else {
-            Self::close_and_disconnect(context, None);
+            self.close_and_disconnect(context, None);
         }
     }
 }
@@ -0,0 +1,46 @@
+use super::{GameRoomRouterActor, InterActorMessage, PartyRecipient, RoomConfig};
+use crate::trusted_proxies::IpNetwork;
+use actix::{Context, Handler as MessageHandler, Message};
+use log::info;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Settings reloaded on SIGHUP or by an admin, the router applies its own and passes it on to every
+// client connection
+#[derive(Clone, Debug, Default, Message)]
+#[rtype(result = "()")]
+pub(crate) struct ConfigUpdate {
+    pub(crate) max_room_clients: Option<usize>,
+    pub(crate) banned_ips: Arc<[IpNetwork]>, // Connected clients from these are closed
+    pub(crate) room_configs: Arc<BTreeMap<u32, RoomConfig>>, // Room ID -> From the config file
+}
+
+impl ConfigUpdate {
+    pub(crate) fn is_banned(&self, client_ip: IpAddr) -> bool {
+        self.banned_ips.iter().any(|banned_ip| banned_ip.contains(client_ip))
+    }
+}
+
+impl MessageHandler<ConfigUpdate> for GameRoomRouterActor {
+    type Result = ();
+
+    fn handle(&mut self, config_update: ConfigUpdate, context: &mut Context<Self>) {
+        info!("Config updated, {:?} room clients at most", config_update.max_room_clients);
+        self.config.max_room_clients = config_update.max_room_clients;
+        self.set_configured_rooms(config_update.room_configs.as_ref().clone(), context);
+
+        // A client in several rooms is told once
+        let client_addresses: BTreeMap<Uuid, &PartyRecipient> = self
+            .game_rooms
+            .values()
+            .flat_map(|room_clients| room_clients.values())
+            .map(|room_client| (room_client.client_id, &room_client.address))
+            .collect();
+
+        for client_address in client_addresses.values() {
+            let _ = client_address.do_send(InterActorMessage::ConfigUpdate(config_update.clone()));
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Wait a refused client is told before connecting again, jittered
+pub(crate) const CONNECTION_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+// The maximums are kept along the counts, a reload changes them under the same lock
+#[derive(Debug, Default)]
+struct OpenConnections {
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    total: usize,
+    per_ip: BTreeMap<IpAddr, usize>, // Peer IP -> Open client connections
+}
+
+// Caps the open client connections, checked before any connection actor is created
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConnectionLimits {
+    open_connections: Arc<Mutex<OpenConnections>>,
+}
+
+impl ConnectionLimits {
+    pub(crate) fn new(
+        max_connections: Option<usize>,
+        max_connections_per_ip: Option<usize>,
+    ) -> Self {
+        let open_connections =
+            OpenConnections { max_connections, max_connections_per_ip, ..Default::default() };
+
+        Self { open_connections: Arc::new(Mutex::new(open_connections)) }
+    }
+
+    // Connections already open over a lowered maximum are kept, only new ones are refused
+    pub(crate) fn set_limits(
+        &self,
+        max_connections: Option<usize>,
+        max_connections_per_ip: Option<usize>,
+    ) {
+        if let Ok(mut write_guard) = self.open_connections.lock() {
+            write_guard.max_connections = max_connections;
+            write_guard.max_connections_per_ip = max_connections_per_ip;
+        }
+    }
+
+    pub(crate) fn total_open(&self) -> usize {
+        self.open_connections.lock().map(|read_guard| read_guard.total).unwrap_or(0)
+    }
+
+    // Connections without a known peer IP only count toward the total, refusals are the reason
+    pub(crate) fn acquire(&self, peer_ip: Option<IpAddr>) -> Result<ConnectionPermit, String> {
+        let mut write_guard =
+            self.open_connections.lock().map_err(|_| "Memory poisoning detected!".to_string())?;
+
+        if let Some(max_connections) = write_guard.max_connections {
+            if write_guard.total >= max_connections {
+                return Err(format!(
+                    "Server holds its maximum of {} connections!",
+                    max_connections
+                ));
+            }
+        }
+
+        if let (Some(max_connections_per_ip), Some(peer_ip)) =
+            (write_guard.max_connections_per_ip, peer_ip)
+        {
+            if write_guard.per_ip.get(&peer_ip).copied().unwrap_or(0) >= max_connections_per_ip {
+                return Err(format!(
+                    "{} holds its maximum of {} connections!",
+                    peer_ip, max_connections_per_ip
+                ));
+            }
+        }
+
+        write_guard.total += 1;
+
+        if let Some(peer_ip) = peer_ip {
+            *write_guard.per_ip.entry(peer_ip).or_default() += 1;
+        }
+
+        Ok(ConnectionPermit { open_connections: self.open_connections.clone(), peer_ip })
+    }
+}
+
+// Held by the connection actor, the connection is released once it is dropped
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    open_connections: Arc<Mutex<OpenConnections>>,
+    peer_ip: Option<IpAddr>,
+}
+
+impl ConnectionPermit {
+    pub(crate) fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_ip
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if let Ok(mut write_guard) = self.open_connections.lock() {
+            write_guard.total = write_guard.total.saturating_sub(1);
+
+            if let Some(peer_ip) = self.peer_ip {
+                if let Some(open_count) = write_guard.per_ip.get_mut(&peer_ip) {
+                    *open_count -= 1;
+
+                    if *open_count == 0 {
+                        write_guard.per_ip.remove(&peer_ip);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_limits_is_as_expected() {
+        let connection_limits = ConnectionLimits::new(Some(3), Some(2));
+        let first_ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let second_ip: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first_permit = connection_limits.acquire(Some(first_ip)).unwrap();
+        let _second_permit = connection_limits.acquire(Some(first_ip)).unwrap();
+
+        assert!(connection_limits.acquire(Some(first_ip)).is_err());
+
+        let _third_permit = connection_limits.acquire(Some(second_ip)).unwrap();
+
+        assert!(connection_limits.acquire(Some(second_ip)).is_err());
+        assert!(connection_limits.acquire(None).is_err());
+
+        // Dropped permits free their slot, both per IP and in total
+        drop(first_permit);
+
+        assert_eq!(connection_limits.open_connections.lock().unwrap().total, 2);
+
+        let _fourth_permit = connection_limits.acquire(Some(first_ip)).unwrap();
+
+        assert!(connection_limits.acquire(None).is_err());
+        assert_eq!(
+            connection_limits.open_connections.lock().unwrap().per_ip.get(&first_ip),
+            Some(&2)
+        );
+
+        // Raised on reload, the open connections are kept
+        connection_limits.set_limits(Some(4), Some(2));
+
+        assert!(connection_limits.acquire(None).is_ok());
+    }
+}
@@ -0,0 +1,50 @@
+use super::{GameRoomRouterActor, InterActorMessage};
+use crate::proto::{ConnectionStats, ErrorCode, InfoCode, MessageStream, PartyId};
+
+impl GameRoomRouterActor {
+    // The connection of the client is asked for its statistics, answered to the server once it
+    // reports them. A party ID missing from the room gets a PartyUnlocated error instead
+    pub(crate) fn query_stats(
+        &mut self,
+        room_id: u32,
+        server_party_id: PartyId,
+        client_party_id: u32,
+    ) {
+        let client_address = self
+            .game_rooms
+            .get(&room_id)
+            .and_then(|room_clients| room_clients.get(&client_party_id))
+            .map(|room_client| room_client.address.clone());
+
+        match client_address {
+            Some(client_address) => {
+                let _ = client_address.do_send(InterActorMessage::QueryStats(
+                    room_id,
+                    PartyId::Client(client_party_id),
+                    server_party_id,
+                ));
+            }
+            None => self.reply_error(
+                room_id,
+                server_party_id,
+                ErrorCode::PartyUnlocated,
+                &PartyId::Client(client_party_id).to_le_bytes(),
+            ),
+        }
+    }
+
+    pub(crate) fn report_stats(
+        &mut self,
+        room_id: u32,
+        server_party_id: PartyId,
+        connection_stats: ConnectionStats,
+    ) {
+        let stats_info = MessageStream::new_info(
+            room_id,
+            server_party_id,
+            InfoCode::ConnectionStats,
+            &connection_stats.into_details(),
+        );
+        self.send_to_party(room_id, server_party_id, PartyId::Server(0), stats_info);
+    }
+}
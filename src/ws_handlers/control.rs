@@ -0,0 +1,212 @@
+use super::{GameRoomRouterActor, RoomConfig};
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::audit_log::{party_principal, AuditAction, AUDIT_LOG};
+use crate::proto::{ControlCode, ControlCommand, MessageStream, PartyId};
+use crate::utils::reload_log_filters;
+use actix::clock::Duration;
+use actix::Context;
+use log::{info, warn};
+
+// Interest keys a client may subscribe to per room, the next ones are ignored
+pub(crate) const MAX_INTEREST_KEYS: usize = 256;
+
+impl GameRoomRouterActor {
+    pub(crate) fn handle_control_command(
+        &mut self,
+        origin_party_id: PartyId,
+        message_stream: MessageStream,
+        context: &mut Context<Self>,
+    ) {
+        let room_id = message_stream.room_id;
+
+        let control_command = match ControlCommand::from_payload(&message_stream.payload) {
+            Ok(control_command) => control_command,
+            Err(error) => {
+                warn!("Party ID {} sent {}", origin_party_id.get_repr(), error);
+                ADMIN_EVENTS.publish(AdminEvent::RoutingError {
+                    room_id,
+                    party_id: origin_party_id.get_repr(),
+                    reason: error.to_string(),
+                });
+                return;
+            }
+        };
+
+        let control_code = control_command.code();
+
+        let is_allowed = match control_code {
+            ControlCode::ClaimRooms | ControlCode::ReleaseRooms | ControlCode::ReportLoad => {
+                origin_party_id.is_single_server_id()
+            }
+            ControlCode::AddRooms
+            | ControlCode::RemoveRooms
+            | ControlCode::CreateRooms
+            | ControlCode::SetLogLevel => origin_party_id == PartyId::Server(0),
+            _ => !control_code.is_server_only() || self.is_in_control(origin_party_id, room_id),
+        };
+
+        if !is_allowed {
+            warn!(
+                "Party ID {} is not allowed to send control command {:#?}",
+                origin_party_id.get_repr(),
+                control_code
+            );
+            ADMIN_EVENTS.publish(AdminEvent::RoutingError {
+                room_id,
+                party_id: origin_party_id.get_repr(),
+                reason: if origin_party_id.is_single_server_id() {
+                    format!("{:#?} is for the primary or owning server only", control_code)
+                } else {
+                    format!("{:#?} is server only", control_code)
+                },
+            });
+            return;
+        }
+
+        match control_command {
+            ControlCommand::Lockstep(tick_millis) => {
+                let tick_duration =
+                    Some(Duration::from_millis(tick_millis as u64)).filter(|_| tick_millis > 0);
+                self.set_lockstep(room_id, tick_duration, context);
+            }
+            ControlCommand::Subscribe(interest_keys)
+            | ControlCommand::Unsubscribe(interest_keys) => {
+                // Clients subscribe for themselves, the server on behalf of the destination client
+                let client_party_id = match (origin_party_id, message_stream.destination_id) {
+                    (PartyId::Client(client_party_id), _) => client_party_id,
+                    (PartyId::Server(_), PartyId::Client(client_party_id))
+                        if self.is_in_control(origin_party_id, room_id) =>
+                    {
+                        client_party_id
+                    }
+                    _ => return,
+                };
+                let is_member = self
+                    .game_rooms
+                    .get(&room_id)
+                    .map(|room_clients| room_clients.contains_key(&client_party_id))
+                    .unwrap_or(false);
+
+                if !is_member {
+                    warn!(
+                        "Party ID {} subscribed for client {} out of room {}",
+                        origin_party_id.get_repr(),
+                        client_party_id,
+                        room_id
+                    );
+                    return;
+                }
+
+                let subscriptions =
+                    self.interest_subscriptions.entry((room_id, client_party_id)).or_default();
+
+                if control_code == ControlCode::Subscribe {
+                    for interest_key in interest_keys {
+                        if subscriptions.len() >= MAX_INTEREST_KEYS
+                            && !subscriptions.contains(&interest_key)
+                        {
+                            warn!(
+                                "Client {} of room {} is past {} interest keys, dropping the rest",
+                                client_party_id, room_id, MAX_INTEREST_KEYS
+                            );
+                            break;
+                        }
+
+                        subscriptions.insert(interest_key);
+                    }
+                } else {
+                    for interest_key in interest_keys {
+                        subscriptions.remove(&interest_key);
+                    }
+                }
+
+                if subscriptions.is_empty() {
+                    self.interest_subscriptions.remove(&(room_id, client_party_id));
+                }
+            }
+            ControlCommand::Pause => self.pause_room(room_id),
+            ControlCommand::Resume => {
+                self.resume_room(room_id, context);
+            }
+            ControlCommand::CloseRoom => {
+                let disconnected_clients = self.close_room(room_id, context);
+                AUDIT_LOG.record(
+                    &party_principal(origin_party_id),
+                    AuditAction::RoomClosed { room_id, disconnected_clients },
+                );
+            }
+            ControlCommand::AddRooms(room_ids) => self.add_available_rooms(&room_ids),
+            ControlCommand::RemoveRooms(room_ids) => self.remove_available_rooms(&room_ids),
+            ControlCommand::ClaimRooms(room_ids) => {
+                if let PartyId::Server(server_id) = origin_party_id {
+                    self.claim_rooms(room_id, server_id, &room_ids);
+                }
+            }
+            ControlCommand::ReleaseRooms(room_ids) => {
+                if let PartyId::Server(server_id) = origin_party_id {
+                    self.release_rooms(server_id, &room_ids);
+                }
+            }
+            ControlCommand::CreateRooms(room_ids) => self.create_rooms(&room_ids),
+            ControlCommand::ReportLoad(hosted_rooms, max_rooms) => {
+                if let PartyId::Server(server_id) = origin_party_id {
+                    self.report_load(server_id, hosted_rooms, max_rooms);
+                }
+            }
+            ControlCommand::SwitchRoom(target_room_id) => {
+                self.switch_room(room_id, origin_party_id, target_room_id, context);
+            }
+            ControlCommand::JoinRoom(target_room_id) => {
+                self.join_room(room_id, origin_party_id, target_room_id, context);
+            }
+            ControlCommand::LeaveRoom => self.leave_room(room_id, origin_party_id, context),
+            ControlCommand::MoveClient(client_party_id, target_room_id) => {
+                let party_id = PartyId::Client(client_party_id);
+                self.switch_room(room_id, party_id, target_room_id, context);
+            }
+            ControlCommand::AssignRoom(pick_id, picked_room_id) => {
+                self.assign_room(pick_id, picked_room_id, context);
+            }
+            ControlCommand::ConfigureRoom(room_config) => match RoomConfig::parse(&room_config) {
+                Ok(room_config) => self.override_room_config(room_id, room_config, context),
+                Err(error) => {
+                    warn!(
+                        "Party ID {} sent invalid settings for room {}: {}",
+                        origin_party_id.get_repr(),
+                        room_id,
+                        error
+                    );
+                    ADMIN_EVENTS.publish(AdminEvent::RoutingError {
+                        room_id,
+                        party_id: origin_party_id.get_repr(),
+                        reason: error.to_string(),
+                    });
+                }
+            },
+            ControlCommand::QueryStats(client_party_id) => {
+                self.query_stats(room_id, origin_party_id, client_party_id);
+            }
+            ControlCommand::SetLogLevel(log_filters) => match reload_log_filters(&log_filters) {
+                Ok(()) => {
+                    info!("Log filters set to {} by the server", log_filters);
+                    AUDIT_LOG.record(
+                        &party_principal(origin_party_id),
+                        AuditAction::LogLevelChanged { log_filters },
+                    );
+                }
+                Err(error) => {
+                    warn!(
+                        "Party ID {} sent invalid log filters: {}",
+                        origin_party_id.get_repr(),
+                        error
+                    );
+                    ADMIN_EVENTS.publish(AdminEvent::RoutingError {
+                        room_id,
+                        party_id: origin_party_id.get_repr(),
+                        reason: error.to_string(),
+                    });
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use super::GameRoomRouterActor;
+use crate::proto::{ErrorCode, MessageStream, PartyId};
+
+impl GameRoomRouterActor {
+    // A server frame to a client missing from its room, e.g. one that just switched rooms, goes to
+    // the room holding the party ID among the rooms the server controls. Party IDs are per room, a
+    // party ID held by several of them is not routed either
+    pub(crate) fn send_across_rooms(
+        &mut self,
+        origin_party_id: PartyId,
+        client_party_id: u32,
+        mut message_stream: MessageStream,
+    ) {
+        let located_room_ids: Vec<u32> = self
+            .game_rooms
+            .iter()
+            .filter(|(room_id, room_clients)| {
+                room_clients.contains_key(&client_party_id)
+                    && self.is_in_control(origin_party_id, **room_id)
+            })
+            .map(|(room_id, _)| *room_id)
+            .collect();
+
+        match located_room_ids.as_slice() {
+            [located_room_id] => {
+                message_stream.room_id = *located_room_id;
+                self.send_to_client(
+                    *located_room_id,
+                    client_party_id,
+                    origin_party_id,
+                    message_stream,
+                );
+            }
+            _ => {
+                let destination_party_id = PartyId::Client(client_party_id);
+                self.reply_error(
+                    message_stream.room_id,
+                    origin_party_id,
+                    ErrorCode::PartyUnlocated,
+                    &destination_party_id.to_le_bytes(),
+                );
+                self.report_undelivered(origin_party_id, &message_stream, destination_party_id);
+            }
+        }
+    }
+}
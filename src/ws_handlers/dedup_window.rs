@@ -0,0 +1,93 @@
+use super::GameRoomRouterActor;
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{MessageStream, PartyId};
+use std::collections::BTreeSet;
+
+// Sequences seen from one origin within the window behind the highest one, older sequences can
+// no longer be told apart and count as duplicates
+#[derive(Debug, Default)]
+pub(crate) struct DedupWindow {
+    highest: Option<u32>,
+    seen: BTreeSet<u32>,
+}
+
+impl DedupWindow {
+    // False for a duplicate, the window is at least 1 sequence wide
+    pub(crate) fn admit(&mut self, sequence: u32, window: u32) -> bool {
+        let window = window.max(1);
+
+        if self.highest.is_some_and(|highest| sequence < highest.saturating_sub(window - 1)) {
+            return false;
+        }
+
+        if !self.seen.insert(sequence) {
+            return false;
+        }
+
+        let highest = self.highest.unwrap_or(sequence).max(sequence);
+        self.highest = Some(highest);
+        self.seen = self.seen.split_off(&highest.saturating_sub(window - 1));
+
+        true
+    }
+}
+
+impl GameRoomRouterActor {
+    // Frames carrying the Sequence option are dropped once seen from the same origin, others
+    // always go through
+    pub(crate) fn admit_sequence(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> bool {
+        let (window, sequence) = match (self.config.dedup_window, message.header_options.sequence) {
+            (Some(window), Some(sequence)) => (window, sequence),
+            _ => return true,
+        };
+        let is_admitted = self
+            .dedup_windows
+            .entry((message.room_id, origin_party_id.get_repr()))
+            .or_default()
+            .admit(sequence, window);
+
+        if !is_admitted {
+            Metrics::increment(&METRICS.duplicate_messages);
+        }
+
+        is_admitted
+    }
+
+    // A new connection of the party numbers its frames from scratch, servers send to any room
+    pub(crate) fn forget_sequences(&mut self, origin_party_id: PartyId, room_id: Option<u32>) {
+        if self.dedup_windows.is_empty() {
+            return;
+        }
+
+        let origin_party_id = origin_party_id.get_repr();
+        self.dedup_windows.retain(|(window_room_id, window_party_id), _| {
+            *window_party_id != origin_party_id
+                || room_id.is_some_and(|room_id| room_id != *window_room_id)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_window_is_as_expected() {
+        let mut dedup_window = DedupWindow::default();
+
+        assert!(dedup_window.admit(5, 4));
+        assert!(!dedup_window.admit(5, 4));
+        assert!(dedup_window.admit(3, 4));
+        assert!(dedup_window.admit(9, 4));
+
+        // 6 is still in the window behind 9, 5 is too old to tell
+        assert!(!dedup_window.admit(5, 4));
+        assert!(dedup_window.admit(6, 4));
+        assert!(!dedup_window.admit(6, 4));
+        assert_eq!(dedup_window.seen.iter().copied().collect::<Vec<_>>(), vec![6, 9]);
+    }
+}
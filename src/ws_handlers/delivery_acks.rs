@@ -0,0 +1,41 @@
+use super::GameRoomRouterActor;
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{InfoCode, MessageStream, PartyId};
+
+impl GameRoomRouterActor {
+    // Told to the origin of a frame asking for an acknowledgement, once per destination party
+    pub(crate) fn report_delivery(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        destination_party_id: PartyId,
+        ack_sequence: u32,
+        is_delivered: bool,
+    ) {
+        let info_code = if is_delivered { InfoCode::Delivered } else { InfoCode::Undelivered };
+        let mut details = ack_sequence.to_le_bytes().to_vec();
+        details.extend_from_slice(&destination_party_id.to_le_bytes());
+
+        let delivery_info = MessageStream::new_info(room_id, origin_party_id, info_code, &details);
+        self.send_to_party(room_id, origin_party_id, PartyId::Server(0), delivery_info);
+    }
+
+    // NACK of a frame dropped by the router, only when its origin asked for an acknowledgement
+    pub(crate) fn report_undelivered(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+        destination_party_id: PartyId,
+    ) {
+        if let Some(ack_sequence) = message.header_options.ack_sequence {
+            Metrics::increment(&METRICS.undelivered_messages);
+            self.report_delivery(
+                message.room_id,
+                origin_party_id,
+                destination_party_id,
+                ack_sequence,
+                false,
+            );
+        }
+    }
+}
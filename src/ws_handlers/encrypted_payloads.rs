@@ -0,0 +1,40 @@
+use super::GameRoomRouterActor;
+use crate::proto::{ErrorCode, MessageCode, MessageStream, PartyId, PayloadKind};
+use log::warn;
+
+impl GameRoomRouterActor {
+    // False when the frame is encrypted but its payload is for the router to read: frames to the
+    // router, time syncs, batches, blob and stream frames. The origin is replied an error
+    pub(crate) fn check_encrypted_payload(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> bool {
+        let is_read_by_router = message.message_code == MessageCode::Special
+            || matches!(
+                message.payload_kind,
+                PayloadKind::TimeSync
+                    | PayloadKind::Batch
+                    | PayloadKind::Blob
+                    | PayloadKind::Stream
+            );
+
+        if message.header_options.key_epoch.is_none() || !is_read_by_router {
+            return true;
+        }
+
+        warn!(
+            "Party ID {} sent an encrypted {:#?} payload the router has to read",
+            origin_party_id.get_repr(),
+            message.payload_kind
+        );
+        self.reply_error(
+            message.room_id,
+            origin_party_id,
+            ErrorCode::UndecodablePayload,
+            &[message.payload_kind.into()],
+        );
+
+        false
+    }
+}
@@ -0,0 +1,136 @@
+use super::GameRoomRouterActor;
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{ErrorCode, MessageStream, PartyId};
+use hmac::{Hmac, Mac};
+use log::warn;
+use sha2::Sha256;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Signing keys are the HMAC-SHA256 of this context keyed by the auth token
+const SIGNING_KEY_CONTEXT: &[u8] = b"game-room frame signing";
+
+// Per connection key derived from the auth token of the client, never logged
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) struct SigningKey([u8; 32]);
+
+impl Debug for SigningKey {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.write_str("SigningKey(..)")
+    }
+}
+
+impl SigningKey {
+    pub(crate) fn derive(auth_token: &str) -> Self {
+        let mut mac = Self::new_mac(auth_token.as_bytes());
+        mac.update(SIGNING_KEY_CONTEXT);
+
+        Self(mac.finalize().into_bytes().into())
+    }
+
+    // Printed by generate-token for the clients signing with it, nothing else exposes it
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    // The signature is the first 128 bits of the HMAC, compared in constant time
+    pub(crate) fn verify(&self, message: &MessageStream) -> bool {
+        let signature = match message.header_options.signature {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let mut mac = Self::new_mac(&self.0);
+        mac.update(&message.signed_content());
+
+        mac.verify_truncated_left(&signature).is_ok()
+    }
+
+    // Clients sign their frames, the router signs the ones it delivers to them with the same key
+    pub(crate) fn sign(&self, message: &mut MessageStream) {
+        let mut mac = Self::new_mac(&self.0);
+        mac.update(&message.signed_content());
+
+        let mut signature = [0u8; 16];
+        signature.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+        message.header_options.signature = Some(signature);
+    }
+
+    fn new_mac(key: &[u8]) -> HmacSha256 {
+        HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length")
+    }
+}
+
+impl GameRoomRouterActor {
+    // False when the origin connected with an auth token and the frame is unsigned or tampered
+    // with, the origin is replied an error. The signature is kept so the server can verify it too
+    pub(crate) fn check_signature(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> bool {
+        let signing_key = match origin_party_id {
+            PartyId::Client(client_party_id) => self
+                .game_rooms
+                .get(&message.room_id)
+                .and_then(|room_clients| room_clients.get(&client_party_id))
+                .and_then(|room_client| room_client.signing_key),
+            _ => None,
+        };
+
+        if signing_key.is_none_or(|signing_key| signing_key.verify(message)) {
+            return true;
+        }
+
+        warn!(
+            "Party ID {} sent a {:#?} payload with a bad signature",
+            origin_party_id.get_repr(),
+            message.payload_kind
+        );
+        Metrics::increment(&METRICS.bad_signatures);
+        self.reply_error(
+            message.room_id,
+            origin_party_id,
+            ErrorCode::BadSignature,
+            &[message.payload_kind.into()],
+        );
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PayloadKind};
+
+    #[test]
+    fn test_signing_key_is_as_expected() {
+        let signing_key = SigningKey::derive("token");
+        let mut message = MessageStream::new(
+            MessageCode::Normal,
+            1,
+            PartyId::Client(2),
+            PartyId::Server(0),
+            PayloadKind::Data,
+            Some(&[0x01, 0x02]),
+        );
+
+        assert!(!signing_key.verify(&message));
+
+        signing_key.sign(&mut message);
+
+        assert!(signing_key.verify(&message));
+        assert!(!SigningKey::derive("other token").verify(&message));
+
+        // The origin is stamped by the router and left out, the payload is not
+        message.origin_id = PartyId::Client(5);
+
+        assert!(signing_key.verify(&message));
+
+        message.payload[0] = 0xFF;
+
+        assert!(!signing_key.verify(&message));
+        assert_eq!(format!("{:?}", signing_key), "SigningKey(..)");
+    }
+}
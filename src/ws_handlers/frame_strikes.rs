@@ -0,0 +1,68 @@
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{ErrorCode, FrameViolation, MessageStream, PartyId};
+
+pub(crate) const MALFORMED_FRAMES_REASON: &str = "Malformed frames";
+
+// Frames a connection sent that could not be parsed, closed once they reach the limit when set
+#[derive(Debug, Default)]
+pub(crate) struct FrameStrikes {
+    strikes: u32,
+    max_strikes: Option<u32>,
+}
+
+impl FrameStrikes {
+    pub(crate) fn new(max_strikes: Option<u32>) -> Self {
+        Self { strikes: 0, max_strikes }
+    }
+
+    // The MalformedFrame error reply for the party, along with whether it is now out of strikes.
+    // Strikes left are u32::MAX without a limit
+    pub(crate) fn strike(
+        &mut self,
+        room_id: u32,
+        party_id: PartyId,
+        violation: FrameViolation,
+    ) -> (MessageStream, bool) {
+        Metrics::increment(&METRICS.malformed_frames);
+        self.strikes = self.strikes.saturating_add(1);
+        let strikes_left = self
+            .max_strikes
+            .map_or(u32::MAX, |max_strikes| max_strikes.saturating_sub(self.strikes));
+        let mut details = vec![violation.into()];
+        details.extend_from_slice(&strikes_left.to_le_bytes());
+        let error =
+            MessageStream::new_error(room_id, party_id, ErrorCode::MalformedFrame, &details);
+
+        (error, self.max_strikes.is_some() && strikes_left == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_strikes_is_as_expected() {
+        let mut frame_strikes = FrameStrikes::new(Some(2));
+        let (error, is_out) =
+            frame_strikes.strike(3, PartyId::Client(1), FrameViolation::BadLength);
+
+        assert_eq!(
+            error,
+            MessageStream::new_error(
+                3,
+                PartyId::Client(1),
+                ErrorCode::MalformedFrame,
+                &[0x05, 1, 0, 0, 0]
+            )
+        );
+        assert!(!is_out);
+        assert!(frame_strikes.strike(3, PartyId::Client(1), FrameViolation::TooShort).1);
+
+        let (error, is_out) =
+            FrameStrikes::new(None).strike(0, PartyId::Server(0), FrameViolation::BadPreamble);
+
+        assert_eq!(error.payload[3..], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(!is_out);
+    }
+}
@@ -0,0 +1,169 @@
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Seconds at most added to every Retry-After hint, so refused clients do not come back together
+pub(crate) const RETRY_AFTER_JITTER: u32 = 5;
+// The idle per-IP buckets are pruned once there are this many
+const MAX_IP_BUCKETS: usize = 4096;
+
+// Leaky bucket holding at most one second of handshakes, it leaks at the rate
+#[derive(Debug)]
+struct LeakyBucket {
+    level: f64,
+    last_leak: Instant,
+}
+
+impl LeakyBucket {
+    fn new(now: Instant) -> Self {
+        Self { level: 0.0, last_leak: now }
+    }
+
+    fn leak(&mut self, rate: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_leak).as_secs_f64();
+        self.level = (self.level - elapsed * rate).max(0.0);
+        self.last_leak = now;
+    }
+
+    // The wait until the handshake fits, nothing is added when it does not
+    fn overflow(&self, rate: f64) -> Option<Duration> {
+        let excess = self.level + 1.0 - rate.max(1.0);
+
+        if excess > 0.0 {
+            Some(Duration::from_secs_f64(excess / rate))
+        } else {
+            None
+        }
+    }
+}
+
+// The rates are kept along the buckets, a reload changes them under the same lock
+#[derive(Debug, Default)]
+struct HandshakeBuckets {
+    rate: Option<f64>,
+    rate_per_ip: Option<f64>,
+    total: Option<LeakyBucket>,
+    per_ip: BTreeMap<IpAddr, LeakyBucket>,
+}
+
+// Smooths the client handshakes of a reconnect storm, checked before any connection actor is
+// created. Rates are handshakes per second
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HandshakeLimiter {
+    buckets: Arc<Mutex<HandshakeBuckets>>,
+}
+
+impl HandshakeLimiter {
+    pub(crate) fn new(rate: Option<f64>, rate_per_ip: Option<f64>) -> Self {
+        let buckets = HandshakeBuckets { rate, rate_per_ip, ..Default::default() };
+
+        Self { buckets: Arc::new(Mutex::new(buckets)) }
+    }
+
+    // The buckets keep their level, a lowered rate leaks them slower from now on
+    pub(crate) fn set_rates(&self, rate: Option<f64>, rate_per_ip: Option<f64>) {
+        if let Ok(mut write_guard) = self.buckets.lock() {
+            write_guard.rate = rate;
+            write_guard.rate_per_ip = rate_per_ip;
+        }
+    }
+
+    // Handshakes without a known peer IP only count toward the total, refusals are the reason and
+    // the wait until the handshake would have fitted
+    pub(crate) fn admit(&self, peer_ip: Option<IpAddr>) -> Result<(), (String, Duration)> {
+        self.admit_at(peer_ip, Instant::now())
+    }
+
+    fn admit_at(&self, peer_ip: Option<IpAddr>, now: Instant) -> Result<(), (String, Duration)> {
+        let mut write_guard = self
+            .buckets
+            .lock()
+            .map_err(|_| ("Memory poisoning detected!".to_string(), Duration::from_secs(0)))?;
+        let HandshakeBuckets { rate, rate_per_ip, total, per_ip } = &mut *write_guard;
+
+        let total_bucket = match *rate {
+            Some(rate) => {
+                let total_bucket = total.get_or_insert_with(|| LeakyBucket::new(now));
+                total_bucket.leak(rate, now);
+
+                if let Some(wait) = total_bucket.overflow(rate) {
+                    return Err(("Server is flooded with handshakes!".to_string(), wait));
+                }
+
+                Some(total_bucket)
+            }
+            None => None,
+        };
+
+        if let (Some(rate_per_ip), Some(peer_ip)) = (*rate_per_ip, peer_ip) {
+            if per_ip.len() >= MAX_IP_BUCKETS {
+                per_ip.retain(|_, ip_bucket| {
+                    ip_bucket.leak(rate_per_ip, now);
+                    ip_bucket.level > 0.0
+                });
+            }
+
+            let ip_bucket = per_ip.entry(peer_ip).or_insert_with(|| LeakyBucket::new(now));
+            ip_bucket.leak(rate_per_ip, now);
+
+            if let Some(wait) = ip_bucket.overflow(rate_per_ip) {
+                return Err((format!("{} is flooding with handshakes!", peer_ip), wait));
+            }
+
+            ip_bucket.level += 1.0;
+        }
+
+        if let Some(total_bucket) = total_bucket {
+            total_bucket.level += 1.0;
+        }
+
+        Ok(())
+    }
+}
+
+// Whole seconds for the Retry-After header, rounded up and jittered
+pub(crate) fn jittered_retry_after(wait: Duration) -> u32 {
+    let wait_secs = wait.as_secs() as u32 + u32::from(wait.subsec_nanos() > 0);
+
+    wait_secs.max(1) + rand::thread_rng().gen_range(0, RETRY_AFTER_JITTER + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_limiter_is_as_expected() {
+        let handshake_limiter = HandshakeLimiter::new(Some(3.0), Some(2.0));
+        let first_ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let second_ip: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(handshake_limiter.admit_at(Some(first_ip), now).is_ok());
+        assert!(handshake_limiter.admit_at(Some(first_ip), now).is_ok());
+
+        // The refused handshake of the IP does not count toward the total
+        let (_, wait) = handshake_limiter.admit_at(Some(first_ip), now).unwrap_err();
+
+        assert_eq!(wait, Duration::from_millis(500));
+        assert!(handshake_limiter.admit_at(Some(second_ip), now).is_ok());
+        assert!(handshake_limiter.admit_at(None, now).is_err());
+
+        // Both buckets leak at their rate
+        let later = now + Duration::from_millis(500);
+
+        assert!(handshake_limiter.admit_at(Some(first_ip), later).is_ok());
+        assert!(handshake_limiter.admit_at(None, later).is_err());
+
+        // Lifting the total rate on reload
+        handshake_limiter.set_rates(None, Some(2.0));
+
+        assert!(handshake_limiter.admit_at(None, later).is_ok());
+
+        let retry_after = jittered_retry_after(Duration::from_millis(1500));
+
+        assert!((2..=2 + RETRY_AFTER_JITTER).contains(&retry_after));
+    }
+}
@@ -0,0 +1,106 @@
+use super::GameRoomRouterActor;
+use crate::admin_events::unix_millis;
+use crate::proto::{InfoCode, MessageStream, PartyId, LOBBY_ROOM_ID};
+use actix::clock::Duration;
+use actix::{AsyncContext, Context};
+use log::info;
+
+// Idle clients are looked for this often, or four times per --idle-timeout when shorter
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const IDLE_REASON: &str = "idle";
+
+impl GameRoomRouterActor {
+    // Always running since the servers may set an idle timeout to their rooms at any time
+    pub(crate) fn start_idle_checks(&mut self, context: &mut Context<Self>) {
+        let check_interval = match self.config.idle_timeout {
+            Some(idle_timeout) => IDLE_CHECK_INTERVAL.min(idle_timeout / 4),
+            None => IDLE_CHECK_INTERVAL,
+        };
+
+        context.run_interval(check_interval, |actor, _| actor.check_idle_clients());
+    }
+
+    // The room setting over --idle-timeout, None when the clients of the room may idle
+    fn room_idle_timeout(&self, room_id: u32) -> Option<Duration> {
+        match self.room_configs.idle_timeout(room_id) {
+            Some(room_idle_timeout) => room_idle_timeout,
+            None => self.config.idle_timeout,
+        }
+    }
+
+    // Clients sending no Normal frame to a room for its idle timeout are warned with an
+    // IdleWarning info, then kicked unless they send one within --idle-grace. The lobby and the
+    // paused rooms are left alone
+    fn check_idle_clients(&mut self) {
+        if self.config.idle_timeout.is_none() && self.room_configs.is_empty() {
+            return;
+        }
+
+        let now_millis = unix_millis();
+        let idle_grace_millis = self.config.idle_grace.as_millis() as u64;
+        let mut warned_clients: Vec<(u32, u32)> = Vec::new(); // (Room ID, Client Party ID)
+        let mut idle_clients: Vec<(u32, u32)> = Vec::new();
+
+        for (room_id, room_clients) in self.game_rooms.iter() {
+            if *room_id == LOBBY_ROOM_ID || self.paused_rooms.contains_key(room_id) {
+                continue;
+            }
+
+            let idle_timeout_millis = match self.room_idle_timeout(*room_id) {
+                Some(idle_timeout) => idle_timeout.as_millis() as u64,
+                None => continue,
+            };
+
+            for (client_party_id, room_client) in room_clients.iter() {
+                match room_client.idle_warned_at_millis {
+                    Some(warned_at_millis)
+                        if now_millis.saturating_sub(warned_at_millis) >= idle_grace_millis =>
+                    {
+                        idle_clients.push((*room_id, *client_party_id))
+                    }
+                    Some(_) => (),
+                    None if now_millis.saturating_sub(room_client.last_played_at_millis)
+                        >= idle_timeout_millis =>
+                    {
+                        warned_clients.push((*room_id, *client_party_id))
+                    }
+                    None => (),
+                }
+            }
+        }
+
+        for (room_id, client_party_id) in warned_clients {
+            if let Some(room_client) = self
+                .game_rooms
+                .get_mut(&room_id)
+                .and_then(|room_clients| room_clients.get_mut(&client_party_id))
+            {
+                room_client.idle_warned_at_millis = Some(now_millis);
+            }
+
+            let party_id = PartyId::Client(client_party_id);
+            let idle_warning = MessageStream::new_info(
+                room_id,
+                party_id,
+                InfoCode::IdleWarning,
+                &(idle_grace_millis as u32).to_le_bytes(),
+            );
+            self.send_to_client(room_id, client_party_id, PartyId::Server(0), idle_warning);
+        }
+
+        // Counted from the kick again, should the client still be there once its grace is over
+        for (room_id, client_party_id) in idle_clients {
+            info!("Kicking client {} idle in room {}", client_party_id, room_id);
+            self.kick_client(room_id, client_party_id, IDLE_REASON);
+
+            if let Some(room_client) = self
+                .game_rooms
+                .get_mut(&room_id)
+                .and_then(|room_clients| room_clients.get_mut(&client_party_id))
+            {
+                room_client.last_played_at_millis = now_millis;
+                room_client.idle_warned_at_millis = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::{GameRoomRouterActor, InterActorMessage, OUTBOUND_DRAIN_BUDGET};
+use crate::proto::PartyId;
+use actix::clock::{Duration, Instant};
+use actix::{Handler as MessageHandler, Message};
+use serde::{Deserialize, Serialize};
+
+// Conditions of the link to a client simulated for QA, e.g. 3G-like, both off when zero
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct LinkConditions {
+    #[serde(default)]
+    pub(crate) bytes_per_second: u64,
+    #[serde(default)]
+    pub(crate) jitter_millis: u64, // Up to this much is added before every frame
+}
+
+// Simulates the link of a client on behalf of an admin, replies whether the client was found.
+// (Room ID, Client Party ID, Conditions)
+#[derive(Debug, Message)]
+#[rtype(result = "bool")]
+pub(crate) struct SimulateLink(pub(crate) u32, pub(crate) u32, pub(crate) LinkConditions);
+
+// Outbound side of a connection, frames leave one by one once the previous one went through the
+// capped link
+#[derive(Debug, Default)]
+pub(crate) struct SimulatedLink {
+    conditions: LinkConditions,
+    busy_until: Option<Instant>,
+}
+
+impl SimulatedLink {
+    pub(crate) fn set(&mut self, conditions: LinkConditions) {
+        self.conditions = conditions;
+        self.busy_until = None;
+    }
+
+    pub(crate) fn drain_budget(&self) -> usize {
+        if self.conditions.bytes_per_second > 0 {
+            1 // One frame
+        } else {
+            OUTBOUND_DRAIN_BUDGET
+        }
+    }
+
+    pub(crate) fn next_drain_delay(&self) -> Duration {
+        let busy_delay = self
+            .busy_until
+            .map(|busy_until| busy_until.saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+        let jitter_delay = match self.conditions.jitter_millis {
+            0 => Duration::default(),
+            jitter_millis => Duration::from_millis(rand::random::<u64>() % (jitter_millis + 1)),
+        };
+
+        busy_delay + jitter_delay
+    }
+
+    // Frames sent back to back queue up behind each other on the link
+    pub(crate) fn occupy(&mut self, bytes: usize) {
+        if self.conditions.bytes_per_second == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let busy_from = self.busy_until.filter(|busy_until| *busy_until > now).unwrap_or(now);
+        let transfer_time =
+            Duration::from_secs_f64(bytes as f64 / self.conditions.bytes_per_second as f64);
+        self.busy_until = Some(busy_from + transfer_time);
+    }
+}
+
+impl MessageHandler<SimulateLink> for GameRoomRouterActor {
+    type Result = bool;
+
+    fn handle(&mut self, message: SimulateLink, _: &mut Self::Context) -> Self::Result {
+        let SimulateLink(room_id, client_party_id, conditions) = message;
+        let room_client = match self
+            .game_rooms
+            .get(&room_id)
+            .and_then(|room_clients| room_clients.get(&client_party_id))
+        {
+            Some(room_client) => room_client,
+            None => return false,
+        };
+        let party_id = PartyId::Client(client_party_id);
+
+        room_client.address.do_send(InterActorMessage::SimulateLink(party_id, conditions)).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_link_is_as_expected() {
+        let mut simulated_link = SimulatedLink::default();
+
+        assert_eq!(simulated_link.drain_budget(), OUTBOUND_DRAIN_BUDGET);
+
+        simulated_link.set(LinkConditions { bytes_per_second: 1_000, jitter_millis: 0 });
+
+        assert_eq!(simulated_link.drain_budget(), 1);
+        assert_eq!(simulated_link.next_drain_delay(), Duration::default());
+
+        // 500 bytes twice at 1000 bytes per second keep the link busy for a second
+        simulated_link.occupy(500);
+        simulated_link.occupy(500);
+        let next_drain_delay = simulated_link.next_drain_delay();
+
+        assert!(next_drain_delay > Duration::from_millis(900));
+        assert!(next_drain_delay <= Duration::from_secs(1));
+
+        simulated_link.set(LinkConditions { bytes_per_second: 0, jitter_millis: 50 });
+
+        assert!(simulated_link.next_drain_delay() <= Duration::from_millis(50));
+        assert_eq!(simulated_link.drain_budget(), OUTBOUND_DRAIN_BUDGET);
+    }
+}
@@ -0,0 +1,34 @@
+use super::GameRoomRouterActor;
+use crate::proto::{InfoCode, MessageStream, PartyId, LOBBY_ROOM_ID};
+
+impl GameRoomRouterActor {
+    fn room_list_info(&self, client_party_id: u32) -> MessageStream {
+        let room_list: Vec<u8> =
+            self.available_rooms.iter().flat_map(|room_id| room_id.to_le_bytes()).collect();
+
+        MessageStream::new_info(
+            LOBBY_ROOM_ID,
+            PartyId::Client(client_party_id),
+            InfoCode::RoomList,
+            &room_list,
+        )
+    }
+
+    pub(crate) fn push_room_list_to(&mut self, client_party_id: u32) {
+        let room_list_info = self.room_list_info(client_party_id);
+        self.send_to_client(LOBBY_ROOM_ID, client_party_id, PartyId::Server(0), room_list_info);
+    }
+
+    // Lobby clients are told the whole room list every time it changes
+    pub(crate) fn push_room_list(&mut self) {
+        let lobby_party_ids: Vec<u32> = self
+            .game_rooms
+            .get(&LOBBY_ROOM_ID)
+            .map(|lobby_clients| lobby_clients.keys().copied().collect())
+            .unwrap_or_default();
+
+        for client_party_id in lobby_party_ids {
+            self.push_room_list_to(client_party_id);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use super::GameRoomRouterActor;
+use crate::proto::{LockstepBundle, MessageStream, PartyId};
+use actix::clock::Duration;
+use actix::{AsyncContext, Context, SpawnHandle};
+use log::warn;
+use std::collections::{BTreeMap, VecDeque};
+
+#[derive(Debug)]
+pub(crate) struct LockstepRoom {
+    pub(crate) tick_duration: Duration,
+    pub(crate) tick: u32,
+    pub(crate) deadline_handle: Option<SpawnHandle>,
+    pending_inputs: BTreeMap<u32, VecDeque<MessageStream>>, // Client Party ID -> Inputs
+}
+
+impl LockstepRoom {
+    // Inputs a client may have waiting for the coming ticks, the next ones are dropped
+    pub(crate) const MAX_PENDING_INPUTS: usize = 16;
+
+    pub(crate) fn new(tick_duration: Duration) -> Self {
+        Self { tick_duration, tick: 0, deadline_handle: None, pending_inputs: Default::default() }
+    }
+
+    pub(crate) fn cancel_deadline(&self, context: &mut Context<GameRoomRouterActor>) {
+        if let Some(deadline_handle) = self.deadline_handle {
+            context.cancel_future(deadline_handle);
+        }
+    }
+
+    // Extra inputs submitted within the same tick roll over to the following ticks, false when the
+    // client already has too many waiting
+    pub(crate) fn submit(&mut self, client_party_id: u32, input: MessageStream) -> bool {
+        let queued_inputs = self.pending_inputs.entry(client_party_id).or_default();
+
+        if queued_inputs.len() >= Self::MAX_PENDING_INPUTS {
+            return false;
+        }
+
+        queued_inputs.push_back(input);
+
+        true
+    }
+
+    pub(crate) fn is_complete<'a>(&self, mut participants: impl Iterator<Item = &'a u32>) -> bool {
+        participants.all(|party_id| {
+            self.pending_inputs.get(party_id).map(|inputs| !inputs.is_empty()).unwrap_or(false)
+        })
+    }
+
+    pub(crate) fn forget(&mut self, client_party_id: u32) {
+        self.pending_inputs.remove(&client_party_id);
+    }
+
+    pub(crate) fn take_bundle(&mut self) -> LockstepBundle {
+        let mut inputs = Vec::new();
+        let mut inputs_length = 0;
+
+        for queued_inputs in self.pending_inputs.values_mut() {
+            if let Some(input) = queued_inputs.front() {
+                if inputs_length + input.raw_length() > LockstepBundle::MAX_INPUTS_LENGTH {
+                    // Does not fit anymore, it will be part of the next tick
+                    continue;
+                }
+
+                inputs_length += input.raw_length();
+                inputs.extend(queued_inputs.pop_front());
+            }
+        }
+
+        self.pending_inputs.retain(|_, queued_inputs| !queued_inputs.is_empty());
+        let bundle = LockstepBundle { tick: self.tick, inputs };
+        self.tick = self.tick.wrapping_add(1);
+
+        bundle
+    }
+}
+
+impl GameRoomRouterActor {
+    // Restarts the ticks of the room from 0, None leaves lockstep
+    pub(crate) fn set_lockstep(
+        &mut self,
+        room_id: u32,
+        tick_duration: Option<Duration>,
+        context: &mut Context<Self>,
+    ) {
+        if let Some(lockstep_room) = self.lockstep_rooms.remove(&room_id) {
+            lockstep_room.cancel_deadline(context);
+        }
+
+        if let Some(tick_duration) = tick_duration {
+            self.lockstep_rooms.insert(room_id, LockstepRoom::new(tick_duration));
+            self.schedule_lockstep_deadline(room_id, context);
+        }
+    }
+
+    pub(crate) fn submit_lockstep_input(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        input: MessageStream,
+        context: &mut Context<Self>,
+    ) {
+        let is_complete = match self.lockstep_rooms.get_mut(&room_id) {
+            None => return,
+            Some(lockstep_room) => {
+                if !lockstep_room.submit(client_party_id, input) {
+                    warn!(
+                        "Party ID {} is {} inputs ahead in room {}, dropping its input",
+                        client_party_id,
+                        LockstepRoom::MAX_PENDING_INPUTS,
+                        room_id
+                    );
+                    return;
+                }
+
+                match self.game_rooms.get(&room_id) {
+                    Some(room_clients) => lockstep_room.is_complete(room_clients.keys()),
+                    None => false,
+                }
+            }
+        };
+
+        if is_complete {
+            self.flush_lockstep_tick(room_id, context);
+        }
+    }
+
+    // The tick may only have been waiting on the input of the client gone from the room
+    pub(crate) fn forget_lockstep_inputs(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        context: &mut Context<Self>,
+    ) {
+        let is_complete = match self.lockstep_rooms.get_mut(&room_id) {
+            None => return,
+            Some(lockstep_room) => {
+                lockstep_room.forget(client_party_id);
+
+                match self.game_rooms.get(&room_id) {
+                    Some(room_clients) if !room_clients.is_empty() => {
+                        lockstep_room.is_complete(room_clients.keys())
+                    }
+                    _ => false,
+                }
+            }
+        };
+
+        if is_complete {
+            self.flush_lockstep_tick(room_id, context);
+        }
+    }
+
+    pub(crate) fn flush_lockstep_tick(&mut self, room_id: u32, context: &mut Context<Self>) {
+        let bundle = match self.lockstep_rooms.get_mut(&room_id) {
+            None => return,
+            Some(lockstep_room) => {
+                lockstep_room.cancel_deadline(context);
+                lockstep_room.take_bundle()
+            }
+        };
+
+        // Sent to the owning server as well, it never saw the inputs on their own
+        let bundle = bundle.into_message_stream(room_id);
+        let server_party_id = PartyId::Server(self.room_server_id(room_id));
+        self.broadcast_to_room(room_id, server_party_id, bundle, true);
+        self.schedule_lockstep_deadline(room_id, context);
+    }
+
+    pub(crate) fn schedule_lockstep_deadline(&mut self, room_id: u32, context: &mut Context<Self>) {
+        if let Some(lockstep_room) = self.lockstep_rooms.get_mut(&room_id) {
+            let deadline_handle =
+                context.run_later(lockstep_room.tick_duration, move |actor, context| {
+                    actor.flush_lockstep_tick(room_id, context);
+                });
+            lockstep_room.deadline_handle = Some(deadline_handle);
+        }
+    }
+}
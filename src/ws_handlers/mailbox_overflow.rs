@@ -0,0 +1,73 @@
+use super::{GameRoomRouterActor, InterActorMessage, OutboundDestination, PartyRecipient};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{InfoCode, PartyId};
+use actix::prelude::SendError;
+use actix::Addr as ActorAddress;
+use log::warn;
+
+// Frames are never dropped for a full mailbox, they go past the capacity and count as an
+// overflow. Answers whether the mailbox was full
+pub(crate) fn deliver(address: &PartyRecipient, message: InterActorMessage) -> bool {
+    match address.try_send(message) {
+        Err(SendError::Full(message)) => {
+            let _ = address.do_send(message);
+            Metrics::increment(&METRICS.mailbox_overflows);
+            true
+        }
+        _ => false,
+    }
+}
+
+// Same for the frames forwarded to the router by the connections, only counted
+pub(crate) fn deliver_to_router(
+    router_address: &ActorAddress<GameRoomRouterActor>,
+    message: InterActorMessage,
+) {
+    if let Err(SendError::Full(message)) = router_address.try_send(message) {
+        router_address.do_send(message);
+        Metrics::increment(&METRICS.mailbox_overflows);
+    }
+}
+
+impl GameRoomRouterActor {
+    // The server is warned once a client mailbox starts overflowing, again only after it drained
+    pub(crate) fn track_overflow(&mut self, destination: OutboundDestination, is_full: bool) {
+        if !is_full {
+            if !self.overflowing_mailboxes.is_empty() {
+                self.overflowing_mailboxes.remove(&destination);
+            }
+
+            return;
+        }
+
+        if !self.overflowing_mailboxes.insert(destination) {
+            return;
+        }
+
+        match destination {
+            OutboundDestination::Server(server_id) => {
+                warn!("Mailbox of server {} is overflowing", server_id);
+            }
+            OutboundDestination::Client(room_id, client_party_id) => {
+                let client_id = match self
+                    .game_rooms
+                    .get(&room_id)
+                    .and_then(|room_clients| room_clients.get(&client_party_id))
+                {
+                    Some(room_client) => room_client.client_id,
+                    None => return,
+                };
+                warn!("Mailbox of client {} in room {} is overflowing", client_id, room_id);
+
+                let overflow_info = Self::presence_info(
+                    InfoCode::MailboxOverflow,
+                    room_id,
+                    PartyId::Client(client_party_id),
+                    client_id,
+                    &[],
+                );
+                self.send_to_server(PartyId::Client(client_party_id), overflow_info);
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use super::GameRoomRouterActor;
+use crate::proto::{InfoCode, MessageCode, MessageStream, PartyId, PayloadKind};
+use actix::clock::Duration;
+use actix::{
+    AsyncContext, Context, Handler as MessageHandler, Message, ResponseFuture, SpawnHandle,
+};
+use futures::channel::oneshot::{channel as oneshot_channel, Sender as OneshotSender};
+use log::{info, warn};
+use uuid::Uuid;
+
+// How long the server has to answer a PickRoom before the router suggestion is used
+pub(crate) const ROOM_PICK_TIMEOUT: Duration = Duration::from_millis(250);
+
+// Picks the room of an auto join, replies None when no room is available
+#[derive(Debug, Message)]
+#[rtype(result = "Option<u32>")]
+pub(crate) struct PickRoom(pub(crate) Uuid);
+
+#[derive(Debug)]
+pub(crate) struct RoomPick {
+    suggested_room_id: u32,
+    reply_sender: OneshotSender<Option<u32>>,
+    timeout_handle: SpawnHandle,
+}
+
+impl GameRoomRouterActor {
+    // The room balancing picks among the servers owning a room not full yet, then the least
+    // occupied of their rooms is suggested, the lowest room ID first on a tie
+    pub(crate) fn suggest_room(&mut self) -> Option<u32> {
+        let open_room_ids: Vec<u32> = self
+            .available_rooms
+            .iter()
+            .copied()
+            .filter(|room_id| !self.is_room_full(*room_id))
+            .collect();
+        let mut server_ids: Vec<u32> =
+            open_room_ids.iter().map(|room_id| self.room_server_id(*room_id)).collect();
+        server_ids.sort_unstable();
+        server_ids.dedup();
+
+        let server_id = match server_ids.as_slice() {
+            [server_id] => *server_id,
+            _ => self.pick_server(&server_ids).or_else(|| server_ids.first().copied())?,
+        };
+
+        open_room_ids
+            .into_iter()
+            .filter(|room_id| self.room_server_id(*room_id) == server_id)
+            .min_by_key(|room_id| {
+                self.game_rooms.get(room_id).map(|room_clients| room_clients.len()).unwrap_or(0)
+            })
+    }
+
+    // The server answer is only taken if the room it picked is available
+    pub(crate) fn assign_room(
+        &mut self,
+        pick_id: u32,
+        picked_room_id: u32,
+        context: &mut Context<Self>,
+    ) {
+        let room_pick = match self.room_picks.remove(&pick_id) {
+            Some(room_pick) => room_pick,
+            None => return warn!("Server assigned a room to unknown or expired pick {}", pick_id),
+        };
+        context.cancel_future(room_pick.timeout_handle);
+
+        let room_id = if self.is_room_available(picked_room_id) {
+            picked_room_id
+        } else {
+            room_pick.suggested_room_id
+        };
+
+        info!("Pick {} assigned to room {}", pick_id, room_id);
+        let _ = room_pick.reply_sender.send(Some(room_id));
+    }
+}
+
+impl MessageHandler<PickRoom> for GameRoomRouterActor {
+    type Result = ResponseFuture<Option<u32>>;
+
+    fn handle(&mut self, message: PickRoom, context: &mut Self::Context) -> Self::Result {
+        let PickRoom(client_id) = message;
+        let suggested_room_id = match self.suggest_room() {
+            Some(suggested_room_id) => suggested_room_id,
+            None => return Box::pin(async { None }),
+        };
+
+        if self.server_handle.is_none() {
+            return Box::pin(async move { Some(suggested_room_id) });
+        }
+
+        let pick_id = self.next_room_pick_id;
+        self.next_room_pick_id = self.next_room_pick_id.wrapping_add(1);
+
+        let (reply_sender, reply_receiver) = oneshot_channel();
+        let timeout_handle = context.run_later(ROOM_PICK_TIMEOUT, move |actor, _| {
+            if let Some(room_pick) = actor.room_picks.remove(&pick_id) {
+                let _ = room_pick.reply_sender.send(Some(room_pick.suggested_room_id));
+            }
+        });
+        self.room_picks
+            .insert(pick_id, RoomPick { suggested_room_id, reply_sender, timeout_handle });
+
+        let mut pick_payload = vec![InfoCode::PickRoom.into()];
+        pick_payload.extend_from_slice(&pick_id.to_le_bytes());
+        pick_payload.extend_from_slice(client_id.as_bytes());
+        pick_payload.extend_from_slice(&suggested_room_id.to_le_bytes());
+
+        let pick_info = MessageStream::new(
+            MessageCode::Special,
+            suggested_room_id,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&pick_payload),
+        );
+        self.send_to_server(PartyId::Server(0), pick_info);
+
+        Box::pin(async move { reply_receiver.await.ok().flatten() })
+    }
+}
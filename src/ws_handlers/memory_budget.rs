@@ -0,0 +1,204 @@
+use super::GameRoomRouterActor;
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{MessagePriority, MessageStream};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// Step of the shedding policy, applied in order until the buffered frames fit the budget again
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ShedStep {
+    Batches, // Outbound batches are flushed ahead of their interval, nothing is lost
+    Bulk,    // Buffered Bulk priority frames are dropped
+    Normal,  // Buffered Normal priority frames are dropped
+}
+
+impl FromStr for ShedStep {
+    type Err = String;
+
+    fn from_str(step: &str) -> Result<Self, Self::Err> {
+        match step.trim() {
+            "batches" => Ok(Self::Batches),
+            "bulk" => Ok(Self::Bulk),
+            "normal" => Ok(Self::Normal),
+            _ => Err(format!("Unknown shed step {}, expected batches, bulk or normal", step)),
+        }
+    }
+}
+
+// Comma separated shed steps, e.g. `batches,bulk`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ShedPolicy(pub(crate) Vec<ShedStep>);
+
+impl Default for ShedPolicy {
+    fn default() -> Self {
+        Self(vec![ShedStep::Batches, ShedStep::Bulk])
+    }
+}
+
+impl FromStr for ShedPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        policy.split(',').map(ShedStep::from_str).collect::<Result<_, _>>().map(Self)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MemoryBudget {
+    pub(crate) max_bytes: Option<usize>,      // Over every room
+    pub(crate) max_room_bytes: Option<usize>, // Per room
+    pub(crate) shed_policy: ShedPolicy,
+}
+
+impl MemoryBudget {
+    pub(crate) fn is_set(&self) -> bool {
+        self.max_bytes.is_some() || self.max_room_bytes.is_some()
+    }
+}
+
+// Bytes of the frames buffered by the router, the outbound batches and the frames held for paused
+// rooms. Frames count toward the room of their header
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(crate) struct MemoryUsage {
+    total: usize,
+    per_room: BTreeMap<u32, usize>, // Room ID -> Buffered bytes
+}
+
+impl MemoryUsage {
+    pub(crate) fn charge(&mut self, room_id: u32, bytes: usize) {
+        self.total += bytes;
+        *self.per_room.entry(room_id).or_default() += bytes;
+    }
+
+    fn room(&self, room_id: u32) -> usize {
+        self.per_room.get(&room_id).copied().unwrap_or(0)
+    }
+
+    fn is_room_over(&self, memory_budget: &MemoryBudget, room_id: u32, bytes: usize) -> bool {
+        memory_budget
+            .max_room_bytes
+            .is_some_and(|max_room_bytes| self.room(room_id) + bytes > max_room_bytes)
+    }
+
+    fn is_total_over(&self, memory_budget: &MemoryBudget, bytes: usize) -> bool {
+        memory_budget.max_bytes.is_some_and(|max_bytes| self.total + bytes > max_bytes)
+    }
+}
+
+impl GameRoomRouterActor {
+    // Sheds buffered frames by the policy until the frame fits, false when it still does not and
+    // must not be buffered
+    pub(crate) fn make_memory_room(&mut self, message: &MessageStream) -> bool {
+        if !self.config.memory_budget.is_set() {
+            return true;
+        }
+
+        for shed_step in self.config.memory_budget.shed_policy.0.clone() {
+            // Only the room is shed when only the room is over
+            let shed_room_id = match self.exceeded_scope(message) {
+                None => return true,
+                Some(shed_room_id) => shed_room_id,
+            };
+
+            match shed_step {
+                ShedStep::Batches => self.flush_outbound_batches(),
+                ShedStep::Bulk => self.drop_buffered(MessagePriority::Bulk, shed_room_id),
+                ShedStep::Normal => self.drop_buffered(MessagePriority::Normal, shed_room_id),
+            }
+        }
+
+        self.exceeded_scope(message).is_none()
+    }
+
+    // Charged once the frame is buffered, `make_memory_room` said it fits
+    pub(crate) fn charge_memory(&mut self, message: &MessageStream) {
+        if self.config.memory_budget.is_set() {
+            self.memory_usage.charge(message.room_id, message.raw_length());
+        }
+    }
+
+    // Some(None) when the total is over, Some(Some(room ID)) when only the room is
+    fn exceeded_scope(&self, message: &MessageStream) -> Option<Option<u32>> {
+        let memory_budget = &self.config.memory_budget;
+        let bytes = message.raw_length();
+
+        if self.memory_usage.is_total_over(memory_budget, bytes) {
+            Some(None)
+        } else if self.memory_usage.is_room_over(memory_budget, message.room_id, bytes) {
+            Some(Some(message.room_id))
+        } else {
+            None
+        }
+    }
+
+    fn drop_buffered(&mut self, priority: MessagePriority, room_id: Option<u32>) {
+        let is_shed = |message: &MessageStream| {
+            message.priority() == priority
+                && room_id.is_none_or(|room_id| message.room_id == room_id)
+        };
+        let mut shed_messages = 0;
+        let mut undelivered_messages = Vec::new();
+
+        for messages in self.outbound_batches.values_mut() {
+            let buffered_messages = messages.len();
+            messages.retain(|(message, _)| !is_shed(message));
+            shed_messages += buffered_messages - messages.len();
+        }
+
+        for held_messages in self.paused_rooms.values_mut() {
+            let buffered_messages = held_messages.len();
+            held_messages.retain(|(origin_party_id, message, _)| {
+                let is_dropped = is_shed(message);
+
+                if is_dropped && message.header_options.ack_sequence.is_some() {
+                    undelivered_messages.push((*origin_party_id, message.clone()));
+                }
+
+                !is_dropped
+            });
+            shed_messages += buffered_messages - held_messages.len();
+        }
+
+        self.outbound_batches.retain(|_, messages| !messages.is_empty());
+
+        Metrics::add(&METRICS.shed_messages, shed_messages as u64);
+
+        self.measure_memory();
+
+        for (origin_party_id, message) in undelivered_messages {
+            self.report_undelivered(origin_party_id, &message, message.destination_id);
+        }
+    }
+
+    // Taken again from the buffers after frames left them other than one by one
+    pub(crate) fn measure_memory(&mut self) {
+        if !self.config.memory_budget.is_set() {
+            return;
+        }
+
+        let mut memory_usage = MemoryUsage::default();
+        let batched_messages = self.outbound_batches.values().flatten().map(|(message, _)| message);
+        let held_messages = self.paused_rooms.values().flatten().map(|(_, message, _)| message);
+
+        for message in batched_messages.chain(held_messages) {
+            memory_usage.charge(message.room_id, message.raw_length());
+        }
+
+        self.memory_usage = memory_usage;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shed_policy_from_str_is_as_expected() {
+        assert_eq!(
+            "bulk, normal".parse::<ShedPolicy>().unwrap(),
+            ShedPolicy(vec![ShedStep::Bulk, ShedStep::Normal])
+        );
+        assert_eq!("batches,bulk".parse::<ShedPolicy>().unwrap(), ShedPolicy::default());
+        assert!("bulk,history".parse::<ShedPolicy>().is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use actix_http::ws::Item as WsItem;
+use actix_web::web::BytesMut;
+use actix_web_actors::ws::{CloseCode, CloseReason, Message as WsMessage};
+
+// Data messages some proxies or peers fragment over continuation frames, put back together up to
+// the maximum length when set
+#[derive(Debug, Default)]
+pub(crate) struct MessageReassembly {
+    fragments: Option<(bool, BytesMut)>, // (Is text, Payload so far)
+    max_length: Option<usize>,
+}
+
+impl MessageReassembly {
+    pub(crate) fn new(max_length: Option<usize>) -> Self {
+        Self { fragments: None, max_length }
+    }
+
+    // The whole message once its last fragment arrived, or the reason to close the connection
+    // with when it grows past the maximum length or is text that is not UTF-8. The actix codec
+    // already refuses a message starting before the previous one is over
+    pub(crate) fn push(&mut self, item: WsItem) -> Result<Option<WsMessage>, CloseReason> {
+        let is_last = matches!(item, WsItem::Last(_));
+
+        match item {
+            WsItem::FirstText(fragment) => self.fragments = Some((true, fragment[..].into())),
+            WsItem::FirstBinary(fragment) => self.fragments = Some((false, fragment[..].into())),
+            WsItem::Continue(fragment) | WsItem::Last(fragment) => match self.fragments.as_mut() {
+                Some((_, payload)) => payload.extend_from_slice(&fragment),
+                None => return Err(CloseCode::Protocol.into()),
+            },
+        }
+
+        let length = self.fragments.as_ref().map_or(0, |(_, payload)| payload.len());
+
+        if self.max_length.is_some_and(|max_length| length > max_length) {
+            self.fragments = None;
+            return Err(CloseReason {
+                code: CloseCode::Size,
+                description: Some(format!("Message exceeds {} bytes", length)),
+            });
+        }
+
+        match self.fragments.take() {
+            Some((true, payload)) if is_last => String::from_utf8(payload.to_vec())
+                .map(|text| Some(WsMessage::Text(text)))
+                .map_err(|_| CloseCode::Invalid.into()),
+            Some((false, payload)) if is_last => Ok(Some(WsMessage::Binary(payload.freeze()))),
+            fragments => {
+                self.fragments = fragments;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+
+    #[test]
+    fn test_message_reassembly_is_as_expected() {
+        let mut message_reassembly = MessageReassembly::new(Some(5));
+
+        assert_eq!(
+            message_reassembly.push(WsItem::FirstBinary(Bytes::from_static(&[1, 2]))),
+            Ok(None)
+        );
+        assert_eq!(message_reassembly.push(WsItem::Continue(Bytes::from_static(&[3]))), Ok(None));
+        assert_eq!(
+            message_reassembly.push(WsItem::Last(Bytes::from_static(&[4, 5]))),
+            Ok(Some(WsMessage::Binary(Bytes::from_static(&[1, 2, 3, 4, 5]))))
+        );
+        assert_eq!(message_reassembly.push(WsItem::FirstText(Bytes::from_static(b"ab"))), Ok(None));
+        assert_eq!(
+            message_reassembly.push(WsItem::Last(Bytes::from_static(b"c"))),
+            Ok(Some(WsMessage::Text("abc".into())))
+        );
+
+        // Over the maximum length, then a continuation of the dropped message
+        assert_eq!(
+            message_reassembly.push(WsItem::FirstBinary(Bytes::from_static(&[0; 4]))),
+            Ok(None)
+        );
+        assert_eq!(
+            message_reassembly
+                .push(WsItem::Continue(Bytes::from_static(&[0; 2])))
+                .unwrap_err()
+                .code,
+            CloseCode::Size
+        );
+        assert_eq!(
+            message_reassembly.push(WsItem::Last(Bytes::new())),
+            Err(CloseCode::Protocol.into())
+        );
+
+        assert_eq!(
+            message_reassembly.push(WsItem::FirstText(Bytes::from_static(&[0xFF]))),
+            Ok(None)
+        );
+        assert_eq!(
+            message_reassembly.push(WsItem::Last(Bytes::new())),
+            Err(CloseCode::Invalid.into())
+        );
+
+        let mut message_reassembly = MessageReassembly::default();
+
+        assert_eq!(
+            message_reassembly.push(WsItem::FirstBinary(Bytes::from(vec![0; 64]))),
+            Ok(None)
+        );
+        assert_eq!(
+            message_reassembly.push(WsItem::Last(Bytes::from(vec![0; 64]))),
+            Ok(Some(WsMessage::Binary(Bytes::from(vec![0; 128]))))
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use super::GameRoomRouterActor;
+use crate::proto::{MessageStream, PartyId};
+use std::fmt::Debug;
+use uuid::Uuid;
+
+// What the router knows of the origin of a frame, handed to the middlewares along with it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct MiddlewareContext {
+    pub(crate) origin_party_id: PartyId,
+    pub(crate) client_id: Option<Uuid>, // Clients only
+}
+
+// Sees every frame received once it passed the router checks, e.g. a profanity filter, a schema
+// validation or an anti-cheat check. Compressed payloads are handed as they are, and a frame whose
+// origin ID no longer matches its sender is not routed
+pub(crate) trait MessageMiddleware: Debug {
+    // The frame to route, modified or not, None drops it
+    fn on_message(
+        &mut self,
+        context: &MiddlewareContext,
+        message: MessageStream,
+    ) -> Option<MessageStream>;
+
+    // Called once the client joined the room, switches included
+    fn on_join(&mut self, _room_id: u32, _context: &MiddlewareContext) {}
+
+    // Called once the client left the room, disconnected or dropped with it
+    fn on_leave(&mut self, _room_id: u32, _context: &MiddlewareContext) {}
+}
+
+impl GameRoomRouterActor {
+    // Run in order after the ones registered before, the first one dropping a frame ends the chain
+    pub(crate) fn with_middlewares(mut self, middlewares: Vec<Box<dyn MessageMiddleware>>) -> Self {
+        self.middlewares.extend(middlewares);
+        self
+    }
+
+    pub(crate) fn notify_join(&mut self, room_id: u32, client_party_id: u32, client_id: Uuid) {
+        let context = MiddlewareContext {
+            origin_party_id: PartyId::Client(client_party_id),
+            client_id: Some(client_id),
+        };
+
+        for middleware in self.middlewares.iter_mut() {
+            middleware.on_join(room_id, &context);
+        }
+    }
+
+    pub(crate) fn notify_leave(&mut self, room_id: u32, client_party_id: u32, client_id: Uuid) {
+        let context = MiddlewareContext {
+            origin_party_id: PartyId::Client(client_party_id),
+            client_id: Some(client_id),
+        };
+
+        for middleware in self.middlewares.iter_mut() {
+            middleware.on_leave(room_id, &context);
+        }
+    }
+
+    // Dropped frames asking for an acknowledgement are reported undelivered, opaque frames are
+    // kept from the middlewares
+    pub(crate) fn apply_middlewares(
+        &mut self,
+        origin_party_id: PartyId,
+        message_stream: MessageStream,
+    ) -> Option<MessageStream> {
+        if self.middlewares.is_empty() || message_stream.is_opaque() {
+            return Some(message_stream);
+        }
+
+        let client_id = match origin_party_id {
+            PartyId::Client(client_party_id) => self
+                .game_rooms
+                .get(&message_stream.room_id)
+                .and_then(|room_clients| room_clients.get(&client_party_id))
+                .map(|room_client| room_client.client_id),
+            _ => None,
+        };
+        let context = MiddlewareContext { origin_party_id, client_id };
+        let acknowledged =
+            message_stream.header_options.ack_sequence.map(|_| message_stream.clone());
+        let result = self.middlewares.iter_mut().try_fold(message_stream, |message, middleware| {
+            middleware.on_message(&context, message)
+        });
+
+        if let (None, Some(message)) = (&result, acknowledged) {
+            self.report_undelivered(origin_party_id, &message, message.destination_id);
+        }
+
+        result
+    }
+}
@@ -1,45 +1,965 @@
+mod admin_commands;
+mod admin_handler;
+mod blob_transfers;
+mod chaos;
 mod client_handler;
+mod config_update;
+mod connection_limits;
+mod connection_stats;
+mod control;
+mod cross_room_routing;
+mod dedup_window;
+mod delivery_acks;
+mod encrypted_payloads;
+mod frame_signing;
+mod frame_strikes;
+mod handshake_limiter;
+mod idle_clients;
+mod link_simulation;
+mod lobby;
+mod lockstep;
+mod mailbox_overflow;
+mod matchmaking;
+mod memory_budget;
+mod message_reassembly;
+mod middleware;
+mod outbound_lanes;
+mod payload_permissions;
+mod peer_proxy;
+mod permessage_deflate;
+mod poll_transport;
+mod presence;
+#[cfg(feature = "webtransport")]
+mod quic_transport;
+mod realtime_relay;
+mod relay;
+mod room_balancing;
+mod room_config;
+mod room_lifecycle;
+mod room_membership;
+mod room_occupancy;
+mod room_ownership;
+mod room_pause;
+mod room_sequences;
+mod room_templates;
+mod room_throughput;
+mod room_wildcard;
+mod room_windows;
+mod router_queries;
+mod routing_pool;
 mod server_handler;
+mod server_reconnect;
+mod slot_reservation;
+mod slow_clients;
+mod state_sync;
+mod stream_credits;
+mod tcp_transport;
+#[cfg(test)]
+mod test_harness;
+mod traffic_recorder;
+mod udp_relay;
 
-use crate::proto::{MessageCode, MessageStream, PartyId, PayloadKind};
-use actix::clock::Duration;
+use crate::admin_events::{unix_millis, AdminEvent, ADMIN_EVENTS};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{
+    CompressionCodec, ConnectionStats, ControlCommand, ErrorCode, InfoCode, MessageBatch,
+    MessageCode, MessagePriority, MessageStream, PartyId, PayloadKind, RelayPayload, TimeSync,
+    ALL_ROOMS_ID, LOBBY_ROOM_ID,
+};
+use crate::telemetry::{HopSpan, TraceContext};
+use actix::clock::{Duration, Instant};
 use actix::{
-    Actor as ActixActor, Addr as ActorAddress, Context, Handler as MessageHandler, Message, Running,
+    Actor as ActixActor, AsyncContext, Context, Handler as MessageHandler, Message, Recipient,
+    Running, SpawnHandle,
 };
-use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use chaos::ChaosSpec;
+use dedup_window::DedupWindow;
+use lockstep::LockstepRoom;
+use log::warn;
+use mailbox_overflow::deliver;
+pub(crate) use mailbox_overflow::deliver_to_router;
+use matchmaking::RoomPick;
+use memory_budget::MemoryUsage;
+use room_balancing::ServerLoad;
+use room_config::RoomConfigs;
+use room_throughput::RoomThroughput;
+use routing_pool::RoutingPool;
+use slot_reservation::ReservedSlot;
+use state_sync::RoomSnapshot;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use stream_credits::StreamCredit;
+use tracing::{info_span, Span};
 use uuid::Uuid;
 
 pub(crate) const MAILBOX_CAPACITY: usize = 256;
 pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+pub(crate) const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+pub(crate) const OUTBOUND_DRAIN_BUDGET: usize = 64 * 1024;
+pub(crate) const RECORDING_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
-pub(crate) use client_handler::ClientActor;
+pub(crate) use admin_commands::{AdminCommand, ListRooms};
+pub(crate) use admin_handler::AdminActor;
+pub(crate) use chaos::ChaosRule;
+pub(crate) use client_handler::{ClientActor, ClientTransport, WsTransport};
+pub(crate) use config_update::ConfigUpdate;
+pub(crate) use connection_limits::{ConnectionLimits, ConnectionPermit, CONNECTION_RETRY_AFTER};
+pub(crate) use frame_signing::SigningKey;
+pub(crate) use frame_strikes::{FrameStrikes, MALFORMED_FRAMES_REASON};
+pub(crate) use handshake_limiter::{jittered_retry_after, HandshakeLimiter};
+pub(crate) use link_simulation::{LinkConditions, SimulateLink, SimulatedLink};
+pub(crate) use matchmaking::PickRoom;
+pub(crate) use memory_budget::{MemoryBudget, ShedPolicy};
+pub(crate) use message_reassembly::MessageReassembly;
+pub(crate) use middleware::MessageMiddleware;
+#[cfg(feature = "plugins")]
+pub(crate) use middleware::MiddlewareContext;
+pub(crate) use outbound_lanes::OutboundLanes;
+pub(crate) use payload_permissions::DeniedPayloadKind;
+pub(crate) use peer_proxy::PeerProxyActor;
+pub(crate) use permessage_deflate::start_with_addr as ws_start;
+pub(crate) use poll_transport::{
+    encode_polled, ClosePoll, PollSent, PollSession, PollSessions, PollTransport, TakePolled,
+};
+pub(crate) use presence::GetPresence;
+#[cfg(feature = "webtransport")]
+pub(crate) use quic_transport::{QuicInbound, QuicOutbound, QuicTransport};
+pub(crate) use relay::{RelayConnected, RelayOut, RelayReset, Relayed};
+pub(crate) use room_balancing::RoomBalancing;
+pub(crate) use room_config::RoomConfig;
+pub(crate) use room_occupancy::GetRoomOccupancy;
+pub(crate) use room_pause::SetRoomPaused;
+pub(crate) use room_templates::{ProvisionedRoom, RoomTemplate};
+pub(crate) use room_windows::RoomSchedule;
+pub(crate) use router_queries::{ClaimServer, GetServerJoined, ReleaseServer};
 pub(crate) use server_handler::ServerActor;
+#[cfg(feature = "grpc")]
+pub(crate) use server_handler::ServerTransport;
+pub(crate) use slot_reservation::{ClaimSlot, SlotRefusal};
+pub(crate) use tcp_transport::{TcpFrameCodec, TcpTransport};
+#[cfg(test)]
+pub(crate) use test_harness::RouterHarness;
+pub(crate) use traffic_recorder::TrafficRecorder;
+pub(crate) use udp_relay::{UdpReceived, UdpRelay, UdpSession, UDP_RETRANSMIT_INTERVAL};
+
+// Any endpoint the router delivers to, a websocket actor or a fake one in tests
+pub(crate) type PartyRecipient = Recipient<InterActorMessage>;
+
+// Log context entered by a websocket actor, clients are the only ones bound to a room
+pub(crate) fn connection_span(room_id: Option<u32>, party_id: PartyId, client_id: Uuid) -> Span {
+    info_span!("connection", room_id, party_id = party_id.get_repr(), client_id = %client_id)
+}
+
+// One membership of a client connection, the same connection is in as many rooms as it joined
+#[derive(Clone, Debug)]
+pub(crate) struct RoomClient {
+    pub(crate) client_id: Uuid,
+    pub(crate) address: PartyRecipient,
+    pub(crate) metadata: Arc<[u8]>, // Given on connect, sent along every Join info
+    pub(crate) joined_at_millis: u64,
+    pub(crate) last_active_at_millis: u64,
+    pub(crate) last_played_at_millis: u64, // Last Normal frame, what the idle timeout goes by
+    pub(crate) idle_warned_at_millis: Option<u64>, // Until the next Normal frame
+    pub(crate) signing_key: Option<SigningKey>, // Frames must be signed with it when set
+}
+
+impl RoomClient {
+    pub(crate) fn new(client_id: Uuid, address: PartyRecipient, metadata: Arc<[u8]>) -> Self {
+        let joined_at_millis = unix_millis();
+
+        Self {
+            client_id,
+            address,
+            metadata,
+            joined_at_millis,
+            last_active_at_millis: joined_at_millis,
+            last_played_at_millis: joined_at_millis,
+            idle_warned_at_millis: None,
+            signing_key: None,
+        }
+    }
+
+    pub(crate) fn with_signing_key(mut self, signing_key: Option<SigningKey>) -> Self {
+        self.signing_key = signing_key;
+        self
+    }
+}
 
 #[derive(Debug, Message)]
 #[rtype(result = "()")]
 pub(crate) enum InterActorMessage {
-    ServerConnect(PartyId, ActorAddress<ServerActor>),
-    ClientConnect(u8, PartyId, Uuid, ActorAddress<ClientActor>),
-    Disconnect(PartyId, Option<Uuid>),  // u32 -> Origin Party ID
-    NewMessage(PartyId, MessageStream), // u32 -> Origin Party ID
+    ServerConnect(PartyId, PartyRecipient),
+    ClientConnect(u32, PartyId, RoomClient),
+    Disconnect(PartyId, Option<Uuid>), // u32 -> Origin Party ID
+    CloseConnection(PartyId, String), // Disconnect with a close reason, the router forgot it already
+    RoomSwitched(u32, PartyId, u32, PartyId), // (Old Room ID, Old Party ID, New Room ID, New Party ID)
+    RoomJoined(u32, PartyId), // One more (Room ID, Party ID) for the same connection
+    RoomLeft(u32, PartyId),   // (Room ID, Party ID) no longer routed to the connection
+    NewMessage(PartyId, MessageStream, Option<TraceContext>), // u32 -> Origin Party ID
+    SlowClient(Uuid, PartyRecipient, Duration), // Connection of a client evicted for this lag
+    DeliveryReport(u32, PartyId, PartyId, u32, bool), // (Room ID, Origin, Destination, Ack, Delivered)
+    SimulateLink(PartyId, LinkConditions),            // Client link conditions set by an admin
+    ConfigUpdate(ConfigUpdate),                       // Reloaded settings, passed on by the router
+    ClientTimeout(Duration), // Inactivity allowed to a client connection by the config of its rooms
+    QueryStats(u32, PartyId, PartyId), // (Room ID, Client Party ID, Asking Server Party ID)
+    ConnectionStats(u32, PartyId, ConnectionStats), // (Room ID, Asking Server Party ID, Stats)
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GameRoomRouterConfig {
+    // Outbound messages are coalesced per destination and flushed every interval when set
+    pub(crate) batch_interval: Option<Duration>,
+    pub(crate) max_payload_lengths: BTreeMap<PayloadKind, usize>,
+    // Rooms left without clients for this long are forgotten when set
+    pub(crate) empty_room_ttl: Option<Duration>,
+    // Slots of dropped clients are kept for them this long when set
+    pub(crate) slot_reservation_ttl: Option<Duration>,
+    // Rooms and clients outlive a server disconnect for this long when set
+    pub(crate) server_reconnect_grace: Option<Duration>,
+    // Joins beyond this many members and reserved slots per room are refused when set
+    pub(crate) max_room_clients: Option<usize>,
+    // Picks the server of created rooms and the rooms of auto joins across servers
+    pub(crate) room_balancing: RoomBalancing,
+    // Caps the bytes of the buffered frames, shedding them by its policy when exceeded
+    pub(crate) memory_budget: MemoryBudget,
+    // Client deliveries are fanned out by this many worker threads, sharded by room, when set
+    pub(crate) routing_workers: Option<usize>,
+    // Mailbox capacity of the router, MAILBOX_CAPACITY when unset
+    pub(crate) mailbox_capacity: Option<usize>,
+    // Frames with a sequence already seen among this many last ones of their origin are dropped
+    // when set
+    pub(crate) dedup_window: Option<u32>,
+    // Server frames to a client missing from their room are routed to the room it is in when set
+    pub(crate) cross_room_routing: bool,
+    // Clients sending no Normal frame to a room for this long are warned, then kicked, when set
+    pub(crate) idle_timeout: Option<Duration>,
+    // Time idle clients are given after their warning
+    pub(crate) idle_grace: Duration,
+    // Payload kinds dropped when sent by the role
+    pub(crate) denied_payload_kinds: BTreeSet<DeniedPayloadKind>,
+    // Room ID -> Fractions of the Normal frames dropped, delayed or reordered, None for every room
+    pub(crate) chaos: BTreeMap<Option<u32>, ChaosSpec>,
+    // Rooms whose server sent this many broadcasts since its last snapshot drop it when set
+    pub(crate) snapshot_max_deltas: Option<usize>,
+    // Blob chunks to a single party may run this many bytes past its last ack when set
+    pub(crate) blob_window: Option<u32>,
+    // Streams a party may have open to single parties of a room when set
+    pub(crate) max_streams: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum OutboundDestination {
+    Server(u32),      // Server ID
+    Client(u32, u32), // (Room ID, Client Party ID)
 }
 
 #[derive(Debug)]
 pub(crate) struct GameRoomRouterActor {
-    pub(crate) available_rooms: Arc<Mutex<Vec<u8>>>,
-    pub(crate) server_handle: Option<(u32, ActorAddress<ServerActor>)>,
-    pub(crate) server_joined: Arc<AtomicBool>,
-    pub(crate) game_rooms: BTreeMap<u8, BTreeMap<u32, (Uuid, ActorAddress<ClientActor>)>>,
+    pub(crate) config: GameRoomRouterConfig,
+    pub(crate) available_rooms: Vec<u32>,           // Sorted
+    pub(crate) client_counters: BTreeMap<u32, u32>, // Room ID -> Next Client Party ID
+    pub(crate) server_handle: Option<(u32, PartyRecipient)>,
+    pub(crate) server_joined: bool, // Claimed by the primary server upgrade, until its disconnect
+    pub(crate) game_rooms: BTreeMap<u32, BTreeMap<u32, RoomClient>>,
+    pub(crate) outbound_batches: BTreeMap<OutboundDestination, Vec<(MessageStream, Instant)>>, // Buffered at
+    pub(crate) lockstep_rooms: BTreeMap<u32, LockstepRoom>,
+    pub(crate) interest_subscriptions: BTreeMap<(u32, u32), BTreeSet<u32>>, // (Room ID, Client Party ID) -> Keys
+    pub(crate) traffic_recorder: Option<TrafficRecorder>,
+    pub(crate) route_trace: Option<TraceContext>, // Frame being routed, handed to direct deliveries
+    pub(crate) room_routed_messages: BTreeMap<u32, u64>, // Normal frames only
+    pub(crate) paused_rooms: BTreeMap<u32, Vec<(PartyId, MessageStream, Instant)>>, // Held Normal frames
+    pub(crate) empty_room_expiries: BTreeMap<u32, SpawnHandle>,
+    pub(crate) room_picks: BTreeMap<u32, RoomPick>, // Pick ID -> Auto joins waiting for the server
+    pub(crate) next_room_pick_id: u32,
+    pub(crate) reserved_slots: BTreeMap<u32, BTreeMap<Uuid, ReservedSlot>>, // Room ID -> Slots
+    pub(crate) server_grace_handle: Option<SpawnHandle>, // Set while waiting for the server back
+    pub(crate) shard_servers: BTreeMap<u32, PartyRecipient>, // Server ID -> Servers other than 0
+    pub(crate) room_owners: BTreeMap<u32, u32>,          // Room ID -> Server ID
+    pub(crate) server_loads: BTreeMap<u32, ServerLoad>,  // Server ID -> Last capacity report
+    pub(crate) last_picked_server_id: Option<u32>,       // Round robin position
+    pub(crate) relay_peers: BTreeMap<String, Recipient<RelayOut>>, // Peer URL -> Relay
+    pub(crate) remote_members: BTreeMap<u32, BTreeSet<(String, u32)>>, // Room ID -> Peer members
+    pub(crate) memory_usage: MemoryUsage,                // Only kept with a memory budget
+    pub(crate) routing_pool: Option<RoutingPool>,        // Started with the router
+    pub(crate) overflowing_mailboxes: BTreeSet<OutboundDestination>, // Warned about already
+    pub(crate) dedup_windows: BTreeMap<(u32, u32), DedupWindow>, // (Room ID, Origin Party ID) -> Sequences
+    pub(crate) middlewares: Vec<Box<dyn MessageMiddleware>>,     // Registered by the embedder
+    pub(crate) chaos_held: BTreeMap<u32, (PartyId, MessageStream)>, // Room ID -> Reordered frame
+    pub(crate) room_configs: RoomConfigs,
+    pub(crate) provisioned_rooms: BTreeMap<u32, ProvisionedRoom>, // Room ID -> From a template
+    pub(crate) closed_windows: BTreeSet<u32>, // Scheduled rooms outside their window
+    pub(crate) room_sequences: BTreeMap<u32, u32>, // Room ID -> Next room sequence
+    pub(crate) room_snapshots: BTreeMap<u32, RoomSnapshot>,
+    pub(crate) blob_transfers: BTreeMap<(u32, u32, u32), u32>, // (Room ID, Sender, Transfer ID) -> Acked
+    pub(crate) stream_credits: BTreeMap<(u32, u32, u32), StreamCredit>, // (Room ID, Sender, Stream ID)
+    pub(crate) room_throughputs: BTreeMap<u32, RoomThroughput>,
 }
 
 impl GameRoomRouterActor {
     pub(crate) fn new(
-        available_rooms: Arc<Mutex<Vec<u8>>>,
-        server_joined: Arc<AtomicBool>,
+        config: GameRoomRouterConfig,
+        traffic_recorder: Option<TrafficRecorder>,
     ) -> Self {
-        Self { available_rooms, server_joined, server_handle: None, game_rooms: Default::default() }
+        Self {
+            config,
+            traffic_recorder,
+            available_rooms: Vec::new(),
+            client_counters: Default::default(),
+            server_joined: false,
+            server_handle: None,
+            game_rooms: Default::default(),
+            outbound_batches: Default::default(),
+            lockstep_rooms: Default::default(),
+            interest_subscriptions: Default::default(),
+            route_trace: None,
+            room_routed_messages: Default::default(),
+            paused_rooms: Default::default(),
+            empty_room_expiries: Default::default(),
+            room_picks: Default::default(),
+            next_room_pick_id: 0,
+            reserved_slots: Default::default(),
+            server_grace_handle: None,
+            shard_servers: Default::default(),
+            room_owners: Default::default(),
+            server_loads: Default::default(),
+            last_picked_server_id: None,
+            relay_peers: Default::default(),
+            remote_members: Default::default(),
+            memory_usage: Default::default(),
+            routing_pool: None,
+            overflowing_mailboxes: Default::default(),
+            dedup_windows: Default::default(),
+            middlewares: Vec::new(),
+            chaos_held: Default::default(),
+            room_configs: Default::default(),
+            provisioned_rooms: Default::default(),
+            closed_windows: Default::default(),
+            room_sequences: Default::default(),
+            room_snapshots: Default::default(),
+            blob_transfers: Default::default(),
+            stream_credits: Default::default(),
+            room_throughputs: Default::default(),
+        }
+    }
+
+    // Critical messages and the ones asking for an acknowledgement are never held back by the
+    // outbound batches
+    pub(crate) fn is_batched(&self, message: &MessageStream) -> bool {
+        self.config.batch_interval.is_some()
+            && message.priority() != MessagePriority::Critical
+            && message.header_options.ack_sequence.is_none()
+    }
+
+    // Goes to the server owning the header room
+    pub(crate) fn send_to_server(&mut self, origin_party_id: PartyId, message: MessageStream) {
+        let server_id = self.room_server_id(message.room_id);
+        self.send_to_server_id(server_id, origin_party_id, message);
+    }
+
+    pub(crate) fn send_to_server_id(
+        &mut self,
+        server_id: u32,
+        origin_party_id: PartyId,
+        message: MessageStream,
+    ) {
+        if self.is_batched(&message) {
+            self.push_outbound_batch(OutboundDestination::Server(server_id), message);
+        } else if let Some(server_address) = self.server_address(server_id) {
+            let is_full = deliver(
+                server_address,
+                InterActorMessage::NewMessage(
+                    origin_party_id,
+                    message,
+                    self.route_trace.map(TraceContext::stamp),
+                ),
+            );
+            self.track_overflow(OutboundDestination::Server(server_id), is_full);
+        } else {
+            self.report_undelivered(origin_party_id, &message, PartyId::Server(server_id));
+        }
+    }
+
+    pub(crate) fn send_to_client(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        origin_party_id: PartyId,
+        message: MessageStream,
+    ) {
+        if self.is_batched(&message) {
+            self.push_outbound_batch(
+                OutboundDestination::Client(room_id, client_party_id),
+                message,
+            );
+        } else if let Some(room_client) = self
+            .game_rooms
+            .get(&room_id)
+            .and_then(|room_clients| room_clients.get(&client_party_id))
+        {
+            match self.routing_pool.as_ref() {
+                Some(routing_pool) => routing_pool.fan_out(
+                    room_id,
+                    vec![room_client.address.clone()],
+                    origin_party_id,
+                    message,
+                    self.route_trace,
+                ),
+                None => {
+                    let is_full = deliver(
+                        &room_client.address,
+                        InterActorMessage::NewMessage(
+                            origin_party_id,
+                            message,
+                            self.route_trace.map(TraceContext::stamp),
+                        ),
+                    );
+                    self.track_overflow(
+                        OutboundDestination::Client(room_id, client_party_id),
+                        is_full,
+                    );
+                }
+            }
+        } else {
+            self.report_undelivered(origin_party_id, &message, PartyId::Client(client_party_id));
+        }
+    }
+
+    pub(crate) fn send_to_party(
+        &mut self,
+        room_id: u32,
+        party_id: PartyId,
+        origin_party_id: PartyId,
+        message: MessageStream,
+    ) {
+        match party_id {
+            PartyId::Server(server_id) => {
+                self.send_to_server_id(server_id, origin_party_id, message)
+            }
+            PartyId::Client(client_party_id) => {
+                self.send_to_client(room_id, client_party_id, origin_party_id, message)
+            }
+            _ => (),
+        }
+    }
+
+    pub(crate) fn reply_error(
+        &mut self,
+        room_id: u32,
+        party_id: PartyId,
+        error_code: ErrorCode,
+        details: &[u8],
+    ) {
+        ADMIN_EVENTS.publish(AdminEvent::RoutingError {
+            room_id,
+            party_id: party_id.get_repr(),
+            reason: format!("{:#?}", error_code),
+        });
+
+        let error = MessageStream::new_error(room_id, party_id, error_code, details);
+        self.send_to_party(room_id, party_id, PartyId::Server(0), error);
+    }
+
+    // Rejects the message with an error reply if its payload is over the limit of its kind.
+    // Compressed payloads are measured decompressed, LZ4 ones by their size prefix. Zstd ones are
+    // decompressed once here and routed on decompressed
+    pub(crate) fn check_payload_length(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &mut MessageStream,
+    ) -> bool {
+        let max_payload_length = match self.config.max_payload_lengths.get(&message.payload_kind) {
+            Some(max_payload_length) => *max_payload_length,
+            None => return true,
+        };
+        let payload_length = match message.header_options.compression {
+            None => Ok(message.payload.len()),
+            Some(CompressionCodec::Zstd) => message.decompress().map(|_| message.payload.len()),
+            Some(compression) => compression.decompressed_length(&message.payload),
+        };
+        let payload_length = match payload_length {
+            Ok(payload_length) => payload_length,
+            Err(error) => {
+                warn!(
+                    "Party ID {} sent an undecodable payload: {}",
+                    origin_party_id.get_repr(),
+                    error
+                );
+                self.reply_error(
+                    message.room_id,
+                    origin_party_id,
+                    ErrorCode::UndecodablePayload,
+                    &[message.payload_kind.into()],
+                );
+                return false;
+            }
+        };
+
+        if payload_length <= max_payload_length {
+            return true;
+        }
+
+        warn!(
+            "Party ID {} sent a {:#?} payload of {} bytes, the limit is {}",
+            origin_party_id.get_repr(),
+            message.payload_kind,
+            payload_length,
+            max_payload_length
+        );
+        Metrics::increment(&METRICS.oversized_payloads);
+
+        let mut details = vec![message.payload_kind.into()];
+        details.extend_from_slice(&(max_payload_length as u32).to_le_bytes());
+        self.reply_error(message.room_id, origin_party_id, ErrorCode::PayloadTooLarge, &details);
+
+        false
+    }
+
+    // AllServers reaches the server of the room and AllClients its clients, peers included. The
+    // origin is skipped unless the broadcast asks for the echo
+    pub(crate) fn broadcast_to_room(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        mut message: MessageStream,
+        with_echo: bool,
+    ) {
+        let is_to_servers =
+            matches!(message.destination_id, PartyId::AllServers | PartyId::AllServersWithEcho);
+        let is_origin_server = origin_party_id == PartyId::Server(self.room_server_id(room_id));
+
+        // The echo to the server carries the same room sequence as the clients get
+        if !is_to_servers && origin_party_id.is_single_server_id() {
+            self.stamp_room_sequence(room_id, &mut message);
+            self.record_delta(room_id, &message);
+        }
+
+        if (is_to_servers && !is_origin_server) || (with_echo && is_origin_server) {
+            self.send_to_server(origin_party_id, message.clone());
+        }
+
+        if !is_to_servers {
+            let skipped_party_id = match origin_party_id {
+                PartyId::Client(client_party_id) if !with_echo => Some(client_party_id),
+                _ => None,
+            };
+            self.relay_broadcast(room_id, &message);
+            self.broadcast_to_room_clients(room_id, origin_party_id, message, skipped_party_id);
+        } else if let (PartyId::Client(client_party_id), true) = (origin_party_id, with_echo) {
+            self.send_to_client(room_id, client_party_id, origin_party_id, message);
+        }
+    }
+
+    pub(crate) fn broadcast_to_room_clients(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        message: MessageStream,
+        skipped_party_id: Option<u32>,
+    ) {
+        let interest_key = message.header_options.interest_key;
+        let room_party_ids: Vec<u32> = self
+            .game_rooms
+            .get(&room_id)
+            .map(|room_clients| room_clients.keys().copied().collect())
+            .unwrap_or_default();
+
+        // Broadcasts tagged with an interest key only reach the subscribed clients
+        let recipient_party_ids: Vec<u32> = room_party_ids
+            .into_iter()
+            .filter(|client_party_id| Some(*client_party_id) != skipped_party_id)
+            .filter(|client_party_id| {
+                interest_key.is_none_or(|interest_key| {
+                    self.interest_subscriptions
+                        .get(&(room_id, *client_party_id))
+                        .is_some_and(|subscriptions| subscriptions.contains(&interest_key))
+                })
+            })
+            .collect();
+
+        // The whole broadcast is a single hand over to the routing pool
+        if let Some(routing_pool) =
+            self.routing_pool.as_ref().filter(|_| !self.is_batched(&message))
+        {
+            let recipients = recipient_party_ids
+                .iter()
+                .filter_map(|client_party_id| self.game_rooms.get(&room_id)?.get(client_party_id))
+                .map(|room_client| room_client.address.clone())
+                .collect();
+            routing_pool.fan_out(room_id, recipients, origin_party_id, message, self.route_trace);
+            return;
+        }
+
+        for client_party_id in recipient_party_ids {
+            self.send_to_client(room_id, client_party_id, origin_party_id, message.clone());
+        }
+    }
+
+    pub(crate) fn reply_time_sync(&self, origin_party_id: PartyId, request: MessageStream) {
+        let receive_timestamp = TimeSync::now();
+        let mut time_sync = match TimeSync::from_request(&request.payload) {
+            Ok(time_sync) => time_sync,
+            Err(error) => {
+                warn!("Party ID {} sent a bad TimeSync: {}", origin_party_id.get_repr(), error);
+                return;
+            }
+        };
+        time_sync.receive_timestamp = receive_timestamp;
+        time_sync.transmit_timestamp = TimeSync::now();
+
+        let response = InterActorMessage::NewMessage(
+            PartyId::Server(0),
+            MessageStream::new(
+                MessageCode::Special,
+                request.room_id,
+                PartyId::Server(0),
+                origin_party_id,
+                PayloadKind::TimeSync,
+                Some(&time_sync.into_response()),
+            ),
+            self.route_trace.map(TraceContext::stamp),
+        );
+
+        // Bypass the outbound batches, a delayed response would skew the measurement
+        match origin_party_id {
+            PartyId::Server(server_id) => {
+                if let Some(server_address) = self.server_address(server_id) {
+                    let _ = server_address.do_send(response);
+                }
+            }
+            PartyId::Client(client_party_id) => {
+                if let Some(room_client) = self
+                    .game_rooms
+                    .get(&(request.room_id))
+                    .and_then(|room_clients| room_clients.get(&client_party_id))
+                {
+                    let _ = room_client.address.do_send(response);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // The room list is a sequence of u32 LE room IDs. Other lengths are rejected whole, a server
+    // still sending u8 room IDs would otherwise replace the rooms with garbage
+    pub(crate) fn update_available_rooms(&mut self, room_list: &[u8]) {
+        if !room_list.len().is_multiple_of(4) {
+            warn!("The server sent a room list of {} bytes, not u32 room IDs", room_list.len());
+            self.reply_error(
+                0,
+                PartyId::Server(0),
+                ErrorCode::UndecodablePayload,
+                &[PayloadKind::Info.into()],
+            );
+            return;
+        }
+
+        let mut room_ids = ControlCommand::read_u32_list(room_list);
+        room_ids.retain(|room_id| ![LOBBY_ROOM_ID, ALL_ROOMS_ID].contains(room_id));
+        room_ids.extend(self.provisioned_rooms.keys());
+        room_ids.retain(|room_id| !self.closed_windows.contains(room_id));
+        room_ids.sort_unstable();
+        room_ids.dedup();
+
+        self.available_rooms = room_ids;
+        self.push_room_list();
+    }
+
+    pub(crate) fn add_available_rooms(&mut self, room_ids: &[u32]) {
+        let room_ids: Vec<u32> = room_ids
+            .iter()
+            .filter(|room_id| {
+                ![LOBBY_ROOM_ID, ALL_ROOMS_ID].contains(room_id)
+                    && !self.closed_windows.contains(room_id)
+            })
+            .copied()
+            .collect();
+        self.available_rooms.extend(room_ids);
+        self.available_rooms.sort_unstable();
+        self.available_rooms.dedup();
+        self.push_room_list();
+    }
+
+    pub(crate) fn remove_available_rooms(&mut self, room_ids: &[u32]) {
+        self.available_rooms.retain(|room_id| !room_ids.contains(room_id));
+        self.push_room_list();
+    }
+
+    // Frames not fitting the memory budget even after shedding are dropped
+    fn push_outbound_batch(&mut self, destination: OutboundDestination, message: MessageStream) {
+        if !self.make_memory_room(&message) {
+            Metrics::increment(&METRICS.shed_messages);
+            return;
+        }
+
+        self.charge_memory(&message);
+        self.outbound_batches.entry(destination).or_default().push((message, Instant::now()));
+    }
+
+    // Frames outliving their TTL while buffered are dropped, the others carry the TTL left
+    pub(crate) fn take_unexpired(
+        buffered_messages: Vec<(MessageStream, Instant)>,
+    ) -> Vec<MessageStream> {
+        let buffered_count = buffered_messages.len();
+        let messages: Vec<MessageStream> = buffered_messages
+            .into_iter()
+            .filter_map(|(mut message, buffered_at)| {
+                message.spend_ttl(buffered_at.elapsed()).then_some(message)
+            })
+            .collect();
+        Metrics::add(&METRICS.expired_messages, (buffered_count - messages.len()) as u64);
+
+        messages
+    }
+
+    pub(crate) fn flush_outbound_batches(&mut self) {
+        let outbound_batches = std::mem::take(&mut self.outbound_batches);
+
+        // Batches are emitted by the router on behalf of the server, inner frames keep their
+        // own addressing
+        for (destination, buffered_messages) in outbound_batches {
+            let messages = Self::take_unexpired(buffered_messages);
+            let mut is_full = false;
+
+            if messages.is_empty() {
+                continue;
+            }
+
+            match destination {
+                OutboundDestination::Server(server_id) => {
+                    if let Some(server_address) = self.server_address(server_id) {
+                        let server_party_id = PartyId::Server(server_id);
+                        let batches =
+                            MessageBatch::pack(0, PartyId::Server(0), server_party_id, messages);
+
+                        for batch in batches {
+                            is_full |= deliver(
+                                server_address,
+                                InterActorMessage::NewMessage(PartyId::Server(0), batch, None),
+                            );
+                        }
+                    }
+                }
+                OutboundDestination::Client(room_id, client_party_id) => {
+                    if let Some(room_client) = self
+                        .game_rooms
+                        .get(&room_id)
+                        .and_then(|room_clients| room_clients.get(&client_party_id))
+                    {
+                        let batches = MessageBatch::pack(
+                            room_id,
+                            PartyId::Server(0),
+                            PartyId::Client(client_party_id),
+                            messages,
+                        );
+
+                        for batch in batches {
+                            is_full |= deliver(
+                                &room_client.address,
+                                InterActorMessage::NewMessage(PartyId::Server(0), batch, None),
+                            );
+                        }
+                    }
+                }
+            }
+
+            self.track_overflow(destination, is_full);
+        }
+
+        self.measure_memory();
+    }
+
+    pub(crate) fn route_message(
+        &mut self,
+        origin_party_id: PartyId,
+        mut message_stream: MessageStream,
+        context: &mut Context<Self>,
+    ) {
+        let _log_span = info_span!(
+            "routing",
+            room_id = message_stream.room_id,
+            party_id = origin_party_id.get_repr()
+        )
+        .entered();
+
+        // Key material and realtime frames never reach the recording
+        let is_recorded = message_stream.payload_kind != PayloadKind::KeyExchange
+            && !self.is_realtime(&message_stream);
+
+        if let Some(traffic_recorder) = self.traffic_recorder.as_mut() {
+            if is_recorded {
+                traffic_recorder.record(origin_party_id, &message_stream);
+            }
+        }
+
+        if !self.admit_sequence(origin_party_id, &message_stream) {
+            return;
+        }
+
+        // The signature covers the payload as sent, before the length check decompresses it
+        if !self.check_signature(origin_party_id, &message_stream) {
+            return;
+        }
+
+        if !self.check_payload_length(origin_party_id, &mut message_stream) {
+            return;
+        }
+
+        if !self.check_payload_permission(origin_party_id, &message_stream) {
+            return;
+        }
+
+        if !self.check_encrypted_payload(origin_party_id, &message_stream) {
+            return;
+        }
+
+        message_stream = match self.apply_middlewares(origin_party_id, message_stream) {
+            Some(message_stream) => message_stream,
+            None => return,
+        };
+
+        // Payloads the router has to inspect are decompressed, others go through as is
+        if message_stream.message_code == MessageCode::Special
+            || matches!(
+                message_stream.payload_kind,
+                PayloadKind::TimeSync | PayloadKind::Blob | PayloadKind::Stream
+            )
+        {
+            if let Err(error) = message_stream.decompress() {
+                warn!(
+                    "Party ID {} sent an undecodable payload: {}",
+                    origin_party_id.get_repr(),
+                    error
+                );
+                self.reply_error(
+                    message_stream.room_id,
+                    origin_party_id,
+                    ErrorCode::UndecodablePayload,
+                    &[message_stream.payload_kind.into()],
+                );
+                return;
+            }
+        }
+
+        if message_stream.payload_kind == PayloadKind::TimeSync {
+            self.reply_time_sync(origin_party_id, message_stream);
+            return;
+        }
+
+        match message_stream.message_code {
+            MessageCode::Special => match message_stream.payload_kind {
+                PayloadKind::Info if origin_party_id == PartyId::Server(0) => {
+                    self.update_available_rooms(&message_stream.payload);
+                }
+                PayloadKind::Command => {
+                    self.handle_control_command(origin_party_id, message_stream, context);
+                }
+                _ => (),
+            },
+            MessageCode::Normal => {
+                if origin_party_id != message_stream.origin_id
+                    || !self.admit_room_throughput(origin_party_id, &message_stream)
+                {
+                    return;
+                }
+
+                self.route_chaotic(origin_party_id, message_stream, context);
+            }
+        }
+    }
+
+    // Normal frames of a paused room are held, and routed from here again once resumed
+    pub(crate) fn route_normal(
+        &mut self,
+        origin_party_id: PartyId,
+        mut message_stream: MessageStream,
+        context: &mut Context<Self>,
+    ) {
+        let room_id = message_stream.room_id;
+
+        if room_id == ALL_ROOMS_ID {
+            self.broadcast_to_owned_rooms(origin_party_id, message_stream, context);
+            return;
+        }
+
+        message_stream = match self.relay_realtime(origin_party_id, message_stream) {
+            Some(message_stream) => message_stream,
+            None => return,
+        };
+
+        if self.paused_rooms.contains_key(&room_id) {
+            self.hold_paused(room_id, origin_party_id, message_stream);
+            return;
+        }
+
+        let destination_party_id = message_stream.destination_id;
+        let origin_is_server = origin_party_id.is_single_server_id();
+        let origin_is_client = origin_party_id.is_single_client_id();
+
+        if message_stream.payload_kind == PayloadKind::Blob
+            && !self.admit_blob_frame(origin_party_id, &mut message_stream)
+        {
+            return;
+        }
+
+        if message_stream.payload_kind == PayloadKind::Stream
+            && !self.admit_stream_frame(origin_party_id, &message_stream)
+        {
+            return;
+        }
+
+        // Only the server in control of the room replaces its snapshot
+        if message_stream.payload_kind == PayloadKind::Snapshot {
+            if self.is_in_control(origin_party_id, room_id) {
+                self.cache_snapshot(room_id, message_stream);
+            }
+
+            return;
+        }
+
+        // Client inputs of a lockstep room are only delivered as part of a bundle
+        if let PartyId::Client(client_party_id) = origin_party_id {
+            if message_stream.payload_kind == PayloadKind::Data
+                && self.lockstep_rooms.contains_key(&room_id)
+            {
+                self.submit_lockstep_input(room_id, client_party_id, message_stream, context);
+                return;
+            }
+        }
+
+        match (origin_is_server, origin_is_client) {
+            (true, true) => (),
+            (false, false) => (),
+            (_, _) => match destination_party_id {
+                PartyId::AllClients
+                | PartyId::AllServers
+                | PartyId::AllClientsWithEcho
+                | PartyId::AllServersWithEcho => {
+                    let with_echo = destination_party_id.is_echo_broadcast();
+                    self.broadcast_to_room(room_id, origin_party_id, message_stream, with_echo);
+                }
+                PartyId::Server(_)
+                    if self.server_grace_handle.is_some() && self.room_server_id(room_id) == 0 =>
+                {
+                    self.reply_error(room_id, origin_party_id, ErrorCode::ServerUnavailable, &[]);
+                    self.report_undelivered(origin_party_id, &message_stream, destination_party_id);
+                }
+                PartyId::Server(_) => {
+                    self.send_to_server(origin_party_id, message_stream);
+                }
+                PartyId::Client(client_party_id)
+                    if origin_is_server
+                        && self.config.cross_room_routing
+                        && !self.game_rooms.get(&room_id).is_some_and(|room_clients| {
+                            room_clients.contains_key(&client_party_id)
+                        }) =>
+                {
+                    self.send_across_rooms(origin_party_id, client_party_id, message_stream);
+                }
+                PartyId::Client(client_party_id) => {
+                    self.send_to_client(room_id, client_party_id, origin_party_id, message_stream);
+                }
+            },
+        }
     }
 }
 
@@ -47,10 +967,31 @@ impl ActixActor for GameRoomRouterActor {
     type Context = Context<Self>;
 
     fn started(&mut self, context: &mut Self::Context) {
-        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+        context.set_mailbox_capacity(self.config.mailbox_capacity.unwrap_or(MAILBOX_CAPACITY));
+        self.routing_pool = self.config.routing_workers.map(RoutingPool::start);
+
+        if let Some(batch_interval) = self.config.batch_interval {
+            context.run_interval(batch_interval, |actor, _| actor.flush_outbound_batches());
+        }
+
+        self.start_configured_lockstep(context);
+        self.start_idle_checks(context);
+        self.publish_provisioned_rooms(context);
+
+        if self.traffic_recorder.is_some() {
+            context.run_interval(RECORDING_FLUSH_INTERVAL, |actor, _| {
+                if let Some(traffic_recorder) = actor.traffic_recorder.as_mut() {
+                    traffic_recorder.flush();
+                }
+            });
+        }
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if let Some(traffic_recorder) = self.traffic_recorder.as_mut() {
+            traffic_recorder.flush();
+        }
+
         Running::Stop
     }
 }
@@ -58,170 +999,2646 @@ impl ActixActor for GameRoomRouterActor {
 impl MessageHandler<InterActorMessage> for GameRoomRouterActor {
     type Result = ();
 
-    fn handle(&mut self, message: InterActorMessage, _: &mut Self::Context) {
+    fn handle(&mut self, message: InterActorMessage, context: &mut Self::Context) {
         match message {
+            InterActorMessage::ServerConnect(PartyId::Server(server_id), server_address)
+                if server_id != 0 =>
+            {
+                self.connect_shard_server(server_id, server_address);
+            }
             InterActorMessage::ServerConnect(party_id, server_address) => {
-                self.server_handle = Some((party_id.get_repr(), server_address));
+                self.forget_sequences(party_id, None);
+
+                match self.server_handle.replace((party_id.get_repr(), server_address)) {
+                    Some((_, previous_server_address)) => {
+                        self.hand_over_server(previous_server_address)
+                    }
+                    None => Metrics::increment(&METRICS.connected_servers),
+                }
+
+                self.server_joined = true;
+                self.end_server_grace(context);
+                self.announce_provisioned_rooms();
+
+                ADMIN_EVENTS.publish(AdminEvent::Connected {
+                    room_id: None,
+                    party_id: party_id.get_repr(),
+                    client_id: None,
+                });
             }
-            InterActorMessage::ClientConnect(room_id, party_id, client_id, client_address) => {
+            InterActorMessage::ClientConnect(room_id, party_id, room_client) => {
+                self.cancel_room_expiry(room_id, context);
+                let client_id = room_client.client_id;
+                let metadata = room_client.metadata.clone();
                 let room_entry = self.game_rooms.entry(room_id).or_default();
-                let _ = room_entry.insert(party_id.get_repr(), (client_id, client_address));
-                let mut hello_payload = [0; 17];
-                hello_payload[0] = 0xF0;
-                hello_payload[1..=16].copy_from_slice(&client_id.as_bytes()[..]);
-
-                let join_info = MessageStream::new(
-                    MessageCode::Special,
-                    room_id as u32,
-                    party_id,
-                    PartyId::Server(0),
-                    PayloadKind::Info,
-                    Some(&hello_payload),
-                );
 
-                if let Some((_, server_actor)) = self.server_handle.as_ref() {
-                    server_actor.do_send(InterActorMessage::NewMessage(party_id, join_info));
+                if room_entry.insert(party_id.get_repr(), room_client).is_none() {
+                    Metrics::increment(&METRICS.connected_clients);
                 }
-            }
-            InterActorMessage::Disconnect(party_id, _) => {
-                if party_id == PartyId::Server(0) {
-                    self.server_joined.store(false, Ordering::Relaxed);
 
-                    if let Ok(mut write_guard) = self.available_rooms.lock() {
-                        write_guard.clear();
-                    }
+                ADMIN_EVENTS.publish(AdminEvent::Connected {
+                    room_id: Some(room_id),
+                    party_id: party_id.get_repr(),
+                    client_id: Some(client_id),
+                });
 
-                    // This will be a recursive call to this branch
-                    let game_room_iter = self.game_rooms.iter();
+                let join_info =
+                    Self::presence_info(InfoCode::Join, room_id, party_id, client_id, &metadata);
+                self.send_to_server(party_id, join_info);
+                self.relay_membership(RelayPayload::MemberJoined(
+                    room_id,
+                    party_id.get_repr(),
+                    *client_id.as_bytes(),
+                ));
+                self.notify_join(room_id, party_id.get_repr(), client_id);
+                self.push_client_timeout(client_id, false);
+                self.sync_room_state(room_id, party_id.get_repr());
 
-                    for (_, rooms) in game_room_iter {
-                        let room_iter = rooms.iter();
+                if room_id == LOBBY_ROOM_ID {
+                    self.push_room_list_to(party_id.get_repr());
+                }
+            }
+            InterActorMessage::Disconnect(party_id, client_id) => {
+                if party_id == PartyId::Server(0) {
+                    self.server_joined = false;
+                    self.forget_sequences(party_id, None);
 
-                        for (party_id_raw, room_client) in room_iter {
-                            room_client.1.do_send(InterActorMessage::Disconnect(
-                                PartyId::from_u32(*party_id_raw),
-                                Some(room_client.0),
-                            ));
+                    match self.config.server_reconnect_grace {
+                        Some(server_reconnect_grace) => {
+                            self.begin_server_grace(server_reconnect_grace, context)
                         }
+                        None => self.drop_rooms(context),
                     }
 
-                    self.server_handle = None;
+                    if self.server_handle.take().is_some() {
+                        Metrics::decrement(&METRICS.connected_servers);
+                        ADMIN_EVENTS.publish(AdminEvent::Disconnected {
+                            room_id: None,
+                            party_id: party_id.get_repr(),
+                            client_id: None,
+                        });
+                    }
+                } else if let PartyId::Server(server_id) = party_id {
+                    self.disconnect_shard_server(server_id);
                 } else {
-                    let game_room_iter = self.game_rooms.iter_mut();
-
-                    for (room_id, rooms) in game_room_iter {
-                        let removed_client = rooms.remove(&party_id.get_repr());
-
-                        if let Some((client_id, _)) = removed_client {
-                            if let Some((_, server_handle)) = self.server_handle.as_ref() {
-                                let mut goodbye_payload = [0; 17];
-                                goodbye_payload[0] = 0x0F;
-                                goodbye_payload[1..=16].copy_from_slice(&client_id.as_bytes()[..]);
-
-                                let exit_info = MessageStream::new(
-                                    MessageCode::Special,
-                                    *room_id as u32,
-                                    party_id,
-                                    PartyId::Server(0),
-                                    PayloadKind::Info,
-                                    Some(&goodbye_payload),
-                                );
-
-                                server_handle
-                                    .do_send(InterActorMessage::NewMessage(party_id, exit_info));
+                    // Party IDs are per room, namesakes in other rooms are other clients
+                    let client_room_ids: Vec<u32> = self
+                        .game_rooms
+                        .iter()
+                        .filter(|(_, room_clients)| {
+                            match (room_clients.get(&party_id.get_repr()), client_id) {
+                                (Some(room_client), Some(client_id)) => {
+                                    room_client.client_id == client_id
+                                }
+                                (room_client, _) => room_client.is_some(),
                             }
-                        }
+                        })
+                        .map(|(room_id, _)| *room_id)
+                        .collect();
+
+                    for room_id in client_room_ids {
+                        self.disconnect_room_client(room_id, party_id.get_repr(), context);
                     }
                 }
             }
-            InterActorMessage::NewMessage(origin_party_id, message_stream) => {
-                match message_stream.message_code {
-                    MessageCode::Special => {
-                        if message_stream.payload_kind != PayloadKind::Info {
-                            return;
-                        }
-
-                        if origin_party_id != PartyId::Server(0) {
-                            return;
-                        }
-
-                        let mut room_list = message_stream.payload;
-                        room_list.sort_unstable();
-                        room_list.dedup();
-                        let mut room_count = room_list.len();
+            InterActorMessage::SlowClient(client_id, address, outbound_lag) => {
+                self.evict_slow_client(client_id, &address, outbound_lag, context);
+            }
+            InterActorMessage::DeliveryReport(
+                room_id,
+                origin_party_id,
+                destination_party_id,
+                ack_sequence,
+                is_delivered,
+            ) => {
+                self.report_delivery(
+                    room_id,
+                    origin_party_id,
+                    destination_party_id,
+                    ack_sequence,
+                    is_delivered,
+                );
+            }
+            InterActorMessage::ConnectionStats(room_id, server_party_id, connection_stats) => {
+                self.report_stats(room_id, server_party_id, connection_stats);
+            }
+            InterActorMessage::CloseConnection(_, _)
+            | InterActorMessage::RoomSwitched(_, _, _, _)
+            | InterActorMessage::RoomJoined(_, _)
+            | InterActorMessage::RoomLeft(_, _)
+            | InterActorMessage::SimulateLink(_, _)
+            | InterActorMessage::ConfigUpdate(_)
+            | InterActorMessage::ClientTimeout(_)
+            | InterActorMessage::QueryStats(_, _, _) => (),
+            InterActorMessage::NewMessage(origin_party_id, message_stream, trace_context) => {
+                let route_span = trace_context.map(|trace_context| {
+                    HopSpan::follow("route", trace_context)
+                        .with_attribute("room_id", message_stream.room_id)
+                        .with_attribute("party_id", origin_party_id.get_repr())
+                });
+                let route_started = Instant::now();
 
-                        if room_count > 256 {
-                            room_count = 256;
-                        }
+                if message_stream.message_code == MessageCode::Normal {
+                    let room_id = message_stream.room_id;
+                    *self.room_routed_messages.entry(room_id).or_default() += 1;
+                }
 
-                        if let Ok(mut write_guard) = self.available_rooms.lock() {
-                            write_guard.resize(room_count, 0);
-                            let room_list_iter = room_list.iter();
+                if let PartyId::Client(client_party_id) = origin_party_id {
+                    if let Some(room_client) = self
+                        .game_rooms
+                        .get_mut(&message_stream.room_id)
+                        .and_then(|room_clients| room_clients.get_mut(&client_party_id))
+                    {
+                        room_client.last_active_at_millis = unix_millis();
 
-                            for (i, room_id) in room_list_iter.enumerate().take(room_count) {
-                                *write_guard.get_mut(i).unwrap() = *room_id;
-                            }
+                        if message_stream.message_code == MessageCode::Normal {
+                            room_client.last_played_at_millis = room_client.last_active_at_millis;
+                            room_client.idle_warned_at_millis = None;
                         }
                     }
-                    MessageCode::Normal => {
-                        if origin_party_id != message_stream.origin_id {
-                            return;
-                        }
+                }
 
-                        let room_id = message_stream.room_id as u8;
-                        let destination_party_id = message_stream.destination_id;
-                        let origin_is_server = origin_party_id.is_single_server_id();
-                        let origin_is_client = origin_party_id.is_single_client_id();
-
-                        match (origin_is_server, origin_is_client) {
-                            (true, true) => (),
-                            (false, false) => (),
-                            (_, _) => match destination_party_id {
-                                PartyId::AllClients
-                                | PartyId::AllServers
-                                | PartyId::AllClientsWithEcho
-                                | PartyId::AllServersWithEcho => {
-                                    let (_, server_address) = self.server_handle.as_ref().unwrap();
-                                    server_address.do_send(InterActorMessage::NewMessage(
-                                        origin_party_id,
-                                        message_stream.clone(),
-                                    ));
-
-                                    if let Some(room_clients) = self.game_rooms.get(&room_id) {
-                                        let room_iter = room_clients.iter();
-
-                                        for (_, (_, client_address)) in room_iter {
-                                            client_address.do_send(InterActorMessage::NewMessage(
-                                                origin_party_id,
-                                                message_stream.clone(),
-                                            ));
-                                        }
-                                    }
-                                }
-                                PartyId::Server(_) => {
-                                    let (_, server_address) = self.server_handle.as_ref().unwrap();
-                                    server_address.do_send(InterActorMessage::NewMessage(
-                                        origin_party_id,
-                                        message_stream,
-                                    ));
-                                }
-                                PartyId::Client(client_party_id) => {
-                                    if let Some(room_clients) = self.game_rooms.get(&room_id) {
-                                        if let Some((_, client_address)) =
-                                            room_clients.get(&client_party_id)
-                                        {
-                                            client_address.do_send(InterActorMessage::NewMessage(
-                                                origin_party_id,
-                                                message_stream,
-                                            ));
-                                        }
-                                    }
-                                }
-                            },
-                        }
-                    }
+                self.route_trace = route_span.as_ref().map(HopSpan::hand_over);
+                self.route_message(origin_party_id, message_stream, context);
+                self.route_trace = None;
+                METRICS.observe_route(route_started.elapsed());
+
+                if let Some(route_span) = route_span {
+                    route_span.end();
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::admin_commands::RoomStatus;
+    use super::control::MAX_INTEREST_KEYS;
+    use super::room_occupancy::RoomOccupancy;
+    use super::test_harness::{
+        FakeEndpoint, RouterHarness, TakeAdminEvents, TakeDelivered, TakeRelayed,
+    };
+    use super::*;
+    use crate::proto::{CompressionCodec, ControlCode, LockstepBundle, MessageReliability};
+    use crate::structured_log::JsonLineFormat;
+    use tracing_subscriber::fmt::format::JsonFields;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    fn data_message(room_id: u32, origin_id: PartyId, destination_id: PartyId) -> MessageStream {
+        MessageStream::new(
+            MessageCode::Normal,
+            room_id,
+            origin_id,
+            destination_id,
+            PayloadKind::Data,
+            Some(&[0x01, 0x02]),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn test_router_broadcast_to_room_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+
+        assert_eq!(harness.available_rooms().await, vec![0, 1]);
+        assert_eq!(harness.take_server_delivered().await.len(), 3); // Join infos
+
+        let broadcast = data_message(0, PartyId::Client(1), PartyId::AllClients);
+        harness.send_from(PartyId::Client(1), broadcast.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![]);
+        assert_eq!(harness.take_client_delivered(0, 0).await, (vec![broadcast.clone()], false));
+        assert_eq!(harness.take_client_delivered(0, 1).await, (vec![], false)); // The origin
+        assert_eq!(harness.take_client_delivered(1, 0).await, (vec![], false));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_broadcast_echo_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.take_server_delivered().await;
+
+        // Client originated, the echo reaches the origin client too
+        let client_echo = data_message(0, PartyId::Client(1), PartyId::AllClientsWithEcho);
+        let to_server = data_message(0, PartyId::Client(1), PartyId::AllServers);
+        let to_server_echo = data_message(0, PartyId::Client(1), PartyId::AllServersWithEcho);
+        harness.send_from(PartyId::Client(1), client_echo.clone()).await;
+        harness.send_from(PartyId::Client(1), to_server.clone()).await;
+        harness.send_from(PartyId::Client(1), to_server_echo.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![to_server, to_server_echo.clone()]);
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![client_echo.clone()]);
+        assert_eq!(harness.take_client_delivered(0, 1).await.0, vec![client_echo, to_server_echo]);
+
+        // Server originated, the server only gets its broadcast back with the echo
+        let to_clients = data_message(0, PartyId::Server(0), PartyId::AllClients);
+        let to_clients_echo = data_message(0, PartyId::Server(0), PartyId::AllClientsWithEcho);
+        let server_echo = data_message(0, PartyId::Server(0), PartyId::AllServersWithEcho);
+        harness.send_from(PartyId::Server(0), to_clients.clone()).await;
+        harness.send_from(PartyId::Server(0), to_clients_echo.clone()).await;
+        harness
+            .send_from(PartyId::Server(0), data_message(0, PartyId::Server(0), PartyId::AllServers))
+            .await;
+        harness.send_from(PartyId::Server(0), server_echo.clone()).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![to_clients_echo.clone(), server_echo]
+        );
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![to_clients.clone(), to_clients_echo.clone()]
+        );
+        assert_eq!(harness.take_client_delivered(0, 1).await.0, vec![to_clients, to_clients_echo]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_wildcard_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1, 2]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(1, 0).await;
+        harness.connect_client(2, 0).await;
+        harness.connect_shard_server(1).await;
+        let claim_rooms = MessageStream::new(
+            MessageCode::Special,
+            2,
+            PartyId::Server(1),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&[ControlCode::ClaimRooms.into(), 2, 0, 0, 0]),
+        );
+        harness.send_from(PartyId::Server(1), claim_rooms).await;
+        harness.take_server_delivered().await;
+
+        // Expanded to the rooms of the primary server, the room of the shard server is left out
+        let announcement = data_message(ALL_ROOMS_ID, PartyId::Server(0), PartyId::AllClients);
+        harness.send_from(PartyId::Server(0), announcement.clone()).await;
+        let in_room = |room_id: u32| MessageStream { room_id, ..announcement.clone() };
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![in_room(0)]);
+        assert_eq!(harness.take_client_delivered(1, 0).await.0, vec![in_room(1)]);
+        assert_eq!(harness.take_client_delivered(2, 0).await.0, vec![]);
+
+        // Clients may not use the wildcard room
+        harness
+            .send_from(
+                PartyId::Client(0),
+                data_message(ALL_ROOMS_ID, PartyId::Client(0), PartyId::AllClients),
+            )
+            .await;
+
+        assert_eq!(harness.take_client_delivered(1, 0).await.0, vec![]);
+        assert_eq!(harness.take_server_delivered().await, vec![]);
+        assert!(!harness.available_rooms().await.contains(&ALL_ROOMS_ID));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_cross_room_routing_is_as_expected() {
+        let config = GameRoomRouterConfig { cross_room_routing: true, ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0, 1, 2]).await;
+        harness.connect_client(1, 0).await;
+        harness.connect_client(1, 1).await;
+        harness.connect_client(2, 1).await;
+        harness.take_server_delivered().await;
+
+        // Found in a single room, the frame is routed there with its room ID
+        let moved = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        harness.send_from(PartyId::Server(0), moved.clone()).await;
+
+        assert_eq!(
+            harness.take_client_delivered(1, 0).await.0,
+            vec![MessageStream { room_id: 1, ..moved }]
+        );
+
+        // Found in several rooms or nowhere, the server is told
+        for client_party_id in [1, 7].iter().copied() {
+            let destination_party_id = PartyId::Client(client_party_id);
+            harness
+                .send_from(
+                    PartyId::Server(0),
+                    data_message(0, PartyId::Server(0), destination_party_id),
+                )
+                .await;
+
+            assert_eq!(
+                harness.take_server_delivered().await,
+                vec![MessageStream::new_error(
+                    0,
+                    PartyId::Server(0),
+                    ErrorCode::PartyUnlocated,
+                    &destination_party_id.to_le_bytes(),
+                )]
+            );
+        }
+
+        assert_eq!(harness.take_client_delivered(1, 1).await.0, vec![]);
+        assert_eq!(harness.take_client_delivered(2, 1).await.0, vec![]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_payload_permissions_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            denied_payload_kinds: vec!["client:data".parse().unwrap()].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // The client is replied an error and the server told, the frame goes nowhere
+        harness
+            .send_from(PartyId::Client(0), data_message(0, PartyId::Client(0), PartyId::Server(0)))
+            .await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![MessageStream::new_info(
+                0,
+                PartyId::Server(0),
+                InfoCode::Forbidden,
+                &[0, 0, 0, 0, PayloadKind::Data.into()]
+            )]
+        );
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Client(0),
+                ErrorCode::PayloadForbidden,
+                &[PayloadKind::Data.into()]
+            )]
+        );
+
+        // Other roles are left alone
+        let from_server = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        harness.send_from(PartyId::Server(0), from_server.clone()).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![from_server]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_chaos_is_as_expected() {
+        let rules: Vec<ChaosRule> =
+            vec!["reorder=1.0,max-delay=50".parse().unwrap(), "1:drop=1.0".parse().unwrap()];
+        let config = GameRoomRouterConfig {
+            chaos: rules.iter().map(|rule| (rule.room_id, rule.spec)).collect(),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+
+        // Every frame is held until the next one overtakes it
+        let mut messages = Vec::new();
+
+        for payload in 0..3u8 {
+            let mut message = data_message(0, PartyId::Server(0), PartyId::Client(0));
+            message.payload = vec![payload];
+            harness.send_from(PartyId::Server(0), message.clone()).await;
+            messages.push(message);
+        }
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![messages[1].clone(), messages[0].clone()]
+        );
+
+        // Nothing overtakes the last one, it is released after the max delay
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![messages[2].clone()]);
+
+        // The room has its own rule
+        harness
+            .send_from(PartyId::Server(0), data_message(1, PartyId::Server(0), PartyId::Client(0)))
+            .await;
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        assert!(harness.take_client_delivered(1, 0).await.0.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_router_frame_signing_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_signing_client(0, 0, "token").await;
+        harness.connect_client(0, 1).await;
+        harness.take_server_delivered().await;
+
+        // Unsigned and tampered frames of a signing client are refused
+        let mut signed = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        SigningKey::derive("token").sign(&mut signed);
+        let mut tampered = signed.clone();
+        tampered.payload[0] = 0xFF;
+
+        harness
+            .send_from(PartyId::Client(0), data_message(0, PartyId::Client(0), PartyId::Server(0)))
+            .await;
+        harness.send_from(PartyId::Client(0), tampered).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![
+                MessageStream::new_error(
+                    0,
+                    PartyId::Client(0),
+                    ErrorCode::BadSignature,
+                    &[PayloadKind::Data.into()]
+                );
+                2
+            ]
+        );
+
+        // Signed frames reach the server with their signature, other clients need none
+        let unsigned = data_message(0, PartyId::Client(1), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), signed.clone()).await;
+        harness.send_from(PartyId::Client(1), unsigned.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![signed, unsigned]);
+    }
+
+    // Drops the frames of client 1 and masks the payload of the others
+    #[derive(Debug)]
+    struct MaskingMiddleware;
+
+    impl MessageMiddleware for MaskingMiddleware {
+        fn on_message(
+            &mut self,
+            context: &middleware::MiddlewareContext,
+            mut message: MessageStream,
+        ) -> Option<MessageStream> {
+            if context.origin_party_id == PartyId::Client(1) {
+                return None;
+            }
+
+            message.payload = vec![0x00; message.payload.len()];
+
+            Some(message)
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_router_middlewares_is_as_expected() {
+        let middlewares: Vec<Box<dyn MessageMiddleware>> = vec![Box::new(MaskingMiddleware)];
+        let mut harness =
+            RouterHarness::start_with_middlewares(Default::default(), &[0], middlewares).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.take_server_delivered().await;
+
+        harness
+            .send_from(PartyId::Client(0), data_message(0, PartyId::Client(0), PartyId::Server(0)))
+            .await;
+        harness
+            .send_from(PartyId::Client(1), data_message(0, PartyId::Client(1), PartyId::Server(0)))
+            .await;
+
+        let mut masked = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        masked.payload = vec![0x00, 0x00];
+
+        assert_eq!(harness.take_server_delivered().await, vec![masked]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_encrypted_payloads_is_as_expected() {
+        let middlewares: Vec<Box<dyn MessageMiddleware>> = vec![Box::new(MaskingMiddleware)];
+        let mut harness =
+            RouterHarness::start_with_middlewares(Default::default(), &[0], middlewares).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // Encrypted payloads and key material go through untouched by the middlewares
+        let mut encrypted = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        encrypted.header_options.key_epoch = Some(1);
+        let mut key_exchange = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        key_exchange.payload_kind = PayloadKind::KeyExchange;
+
+        harness.send_from(PartyId::Server(0), encrypted.clone()).await;
+        harness.send_from(PartyId::Server(0), key_exchange.clone()).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![encrypted, key_exchange]);
+
+        // Payloads the router has to read cannot be encrypted
+        let mut time_sync = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        time_sync.payload_kind = PayloadKind::TimeSync;
+        time_sync.header_options.key_epoch = Some(1);
+        harness.send_from(PartyId::Client(0), time_sync).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Client(0),
+                ErrorCode::UndecodablePayload,
+                &[PayloadKind::TimeSync.into()]
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_routing_pool_is_as_expected() {
+        let config = GameRoomRouterConfig { routing_workers: Some(2), ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+
+        let broadcast = data_message(0, PartyId::Server(0), PartyId::AllClients);
+        let direct = data_message(0, PartyId::Server(0), PartyId::Client(1));
+        let other_room = data_message(1, PartyId::Server(0), PartyId::Client(0));
+        harness.send_from(PartyId::Server(0), broadcast.clone()).await;
+        harness.send_from(PartyId::Server(0), direct.clone()).await;
+        harness.send_from(PartyId::Server(0), other_room.clone()).await;
+
+        // The workers deliver on their own threads
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![broadcast.clone()]);
+        assert_eq!(harness.take_client_delivered(0, 1).await.0, vec![broadcast, direct]);
+        assert_eq!(harness.take_client_delivered(1, 0).await.0, vec![other_room]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_mailbox_overflow_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // Routed in a single run of the router, the client has no chance to read in between
+        let direct = data_message(0, PartyId::Server(0), PartyId::Client(0));
+
+        for _ in 0..40 {
+            harness.router.do_send(InterActorMessage::NewMessage(
+                PartyId::Server(0),
+                direct.clone(),
+                None,
+            ));
+        }
+
+        harness.router.send(GetServerJoined).await.unwrap();
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0.len(), 40);
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![GameRoomRouterActor::presence_info(
+                InfoCode::MailboxOverflow,
+                0,
+                PartyId::Client(0),
+                client_id,
+                &[]
+            )]
+        );
+
+        // Nothing more to tell once the client read its frames
+        harness.send_from(PartyId::Server(0), direct).await;
+
+        assert!(harness.take_server_delivered().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_router_drops_spoofed_origin_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.take_server_delivered().await;
+
+        let spoofed = data_message(0, PartyId::Client(0), PartyId::Client(1));
+        harness.send_from(PartyId::Client(1), spoofed).await;
+
+        assert_eq!(harness.take_client_delivered(0, 1).await, (vec![], false));
+        assert_eq!(harness.take_server_delivered().await, vec![]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_server_disconnect_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.inject(InterActorMessage::Disconnect(PartyId::Server(0), None)).await;
+
+        assert!(harness.available_rooms().await.is_empty());
+        assert!(harness.take_client_delivered(0, 0).await.1);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_admin_commands_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+        harness
+            .send_from(PartyId::Client(1), data_message(0, PartyId::Client(1), PartyId::Server(0)))
+            .await;
+
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+
+        assert_eq!(
+            rooms[0],
+            RoomStatus {
+                room_id: 0,
+                client_party_ids: vec![0, 1],
+                client_metadata: BTreeMap::new(),
+                routed_messages: 1,
+                is_paused: false,
+                owner_server_id: 0,
+                template: None,
+                metadata: None,
+            }
+        );
+        assert_eq!(
+            rooms[1],
+            RoomStatus {
+                room_id: 1,
+                client_party_ids: vec![0],
+                client_metadata: BTreeMap::new(),
+                routed_messages: 0,
+                is_paused: false,
+                owner_server_id: 0,
+                template: None,
+                metadata: None,
+            }
+        );
+        assert_eq!(harness.router.send(AdminCommand::Kick(1, 0)).await.unwrap(), 1);
+        assert_eq!(harness.router.send(AdminCommand::Kick(1, 9)).await.unwrap(), 0);
+        assert_eq!(harness.router.send(AdminCommand::Drain(0)).await.unwrap(), 2);
+        assert!(harness.take_client_delivered(0, 0).await.1);
+        assert!(harness.take_client_delivered(0, 1).await.1);
+        assert!(harness.take_client_delivered(1, 0).await.1);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_pause_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        let pause = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&[ControlCode::Pause.into()]),
+        );
+        harness.send_from(PartyId::Server(0), pause).await;
+
+        let first = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        let second = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        harness.send_from(PartyId::Client(0), first.clone()).await;
+        harness.send_from(PartyId::Server(0), second.clone()).await;
+
+        assert!(harness.take_server_delivered().await.is_empty());
+        assert!(harness.take_client_delivered(0, 0).await.0.is_empty());
+        assert_eq!(harness.router.send(SetRoomPaused(0, false)).await.unwrap(), 2);
+        assert_eq!(harness.take_server_delivered().await, vec![first]);
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![second]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_close_room_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let closed_client_id = harness.connect_client(0, 0).await;
+        harness.connect_client(1, 0).await;
+
+        let close_room = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&[ControlCode::CloseRoom.into()]),
+        );
+        harness.send_from(PartyId::Server(0), close_room).await;
+
+        assert_eq!(harness.available_rooms().await, vec![1]);
+        assert!(harness.take_client_delivered(0, 0).await.1);
+        assert!(!harness.take_client_delivered(1, 0).await.1);
+
+        // The closed client leaving afterwards does not take its namesake of room 1 along
+        harness
+            .inject(InterActorMessage::Disconnect(PartyId::Client(0), Some(closed_client_id)))
+            .await;
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].client_party_ids, vec![0]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_server_reconnect_grace_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            server_reconnect_grace: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        let client_id = harness.connect_client_with_metadata(0, 0, b"Ana").await;
+        harness.take_server_delivered().await;
+        harness.inject(InterActorMessage::Disconnect(PartyId::Server(0), None)).await;
+
+        // Clients stay while the server is away, frames for it are answered with an error
+        let to_server = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), to_server.clone()).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await,
+            (
+                vec![MessageStream::new_error(
+                    0,
+                    PartyId::Client(0),
+                    ErrorCode::ServerUnavailable,
+                    &[]
+                )],
+                false
+            )
+        );
+
+        harness.connect_server().await;
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![GameRoomRouterActor::presence_info(
+                InfoCode::Join,
+                0,
+                PartyId::Client(0),
+                client_id,
+                b"Ana"
+            )]
+        );
+
+        harness.send_from(PartyId::Client(0), to_server.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![to_server]);
+        assert!(!harness.take_client_delivered(0, 0).await.1);
+
+        // Past the grace period the rooms are dropped like without one
+        harness.inject(InterActorMessage::Disconnect(PartyId::Server(0), None)).await;
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        assert!(harness.take_client_delivered(0, 0).await.1);
+        assert!(harness.available_rooms().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_router_server_takeover_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let client_id = harness.connect_client_with_metadata(0, 0, b"Ana").await;
+        let other_client_id = harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+
+        assert_eq!(harness.take_over_server().await, (Vec::new(), true));
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Join,
+                    0,
+                    PartyId::Client(0),
+                    client_id,
+                    b"Ana"
+                ),
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Join,
+                    1,
+                    PartyId::Client(0),
+                    other_client_id,
+                    &[]
+                ),
+            ]
+        );
+
+        // The new server gets the client frames, the clients never noticed the handover
+        let to_server = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), to_server.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![to_server]);
+        assert_eq!(harness.take_client_delivered(0, 0).await, (Vec::new(), false));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_ownership_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(1, 0).await;
+        let shard_server = harness.connect_shard_server(1).await;
+        harness.take_server_delivered().await;
+
+        let room_command = |origin_party_id: PartyId, payload: &[u8]| {
+            MessageStream::new(
+                MessageCode::Special,
+                1,
+                origin_party_id,
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(payload),
+            )
+        };
+        let claim_rooms = [ControlCode::ClaimRooms.into(), 1, 0, 0, 0, 5, 0, 0, 0];
+        harness.send_from(PartyId::Server(1), room_command(PartyId::Server(1), &claim_rooms)).await;
+        harness.send_from(PartyId::Server(0), room_command(PartyId::Server(0), &claim_rooms)).await;
+
+        // The primary server is refused the rooms the shard server already owns
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                MessageStream::new_error(
+                    1,
+                    PartyId::Server(0),
+                    ErrorCode::RoomOwned,
+                    &[1, 0, 0, 0, 1, 0, 0, 0]
+                ),
+                MessageStream::new_error(
+                    1,
+                    PartyId::Server(0),
+                    ErrorCode::RoomOwned,
+                    &[5, 0, 0, 0, 1, 0, 0, 0]
+                ),
+            ]
+        );
+        assert_eq!(harness.available_rooms().await, vec![0, 1, 5]);
+
+        // Each room only reaches its owner
+        let to_primary = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        let to_shard = data_message(1, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), to_primary.clone()).await;
+        harness.send_from(PartyId::Client(0), to_shard.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![to_primary]);
+        assert_eq!(
+            shard_server.send(TakeDelivered).await.unwrap(),
+            (vec![to_shard.clone()], false)
+        );
+
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+
+        assert_eq!((rooms[0].owner_server_id, rooms[1].owner_server_id), (0, 1));
+
+        // Rooms of a leaving shard server go back to the primary one
+        harness.inject(InterActorMessage::Disconnect(PartyId::Server(1), None)).await;
+        harness.send_from(PartyId::Client(0), to_shard.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![to_shard]);
+        assert!(!harness.take_client_delivered(1, 0).await.1);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_relay_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let first_client_id = harness.connect_client(1, 0).await;
+        let peer_url = "ws://10.0.0.2:7575".to_string();
+        let relay = harness.connect_relay(&peer_url).await;
+
+        // Members already there are announced on connect
+        assert_eq!(
+            relay.send(TakeRelayed).await.unwrap(),
+            vec![RelayPayload::MemberJoined(1, 0, *first_client_id.as_bytes())]
+        );
+
+        let second_client_id = harness.connect_client(1, 1).await;
+
+        assert_eq!(
+            relay.send(TakeRelayed).await.unwrap(),
+            vec![RelayPayload::MemberJoined(1, 1, *second_client_id.as_bytes())]
+        );
+
+        // Broadcasts only go to the peers with members in the room
+        let local_broadcast = data_message(1, PartyId::Client(0), PartyId::AllClients);
+        harness.send_from(PartyId::Client(0), local_broadcast.clone()).await;
+
+        assert_eq!(relay.send(TakeRelayed).await.unwrap(), Vec::new());
+
+        let remote_joined = RelayPayload::MemberJoined(1, 0, [0x11; 16]);
+        harness.router.send(Relayed(peer_url.clone(), remote_joined)).await.unwrap();
+        harness.send_from(PartyId::Client(0), local_broadcast.clone()).await;
+
+        assert_eq!(
+            relay.send(TakeRelayed).await.unwrap(),
+            vec![RelayPayload::Message(local_broadcast)]
+        );
+
+        // Relayed broadcasts reach the local clients only
+        harness.take_server_delivered().await;
+        harness.take_client_delivered(1, 0).await;
+        harness.take_client_delivered(1, 1).await;
+        let remote_broadcast = data_message(1, PartyId::Client(0), PartyId::AllClients);
+        let relayed_message = RelayPayload::Message(remote_broadcast.clone());
+        harness.router.send(Relayed(peer_url.clone(), relayed_message)).await.unwrap();
+
+        assert_eq!(harness.take_client_delivered(1, 0).await.0, vec![remote_broadcast.clone()]);
+        assert_eq!(harness.take_client_delivered(1, 1).await.0, vec![remote_broadcast]);
+        assert_eq!(harness.take_server_delivered().await, Vec::new());
+        assert_eq!(relay.send(TakeRelayed).await.unwrap(), Vec::new());
+
+        // A restarted peer has no members left
+        harness.router.send(RelayReset(peer_url)).await.unwrap();
+        harness
+            .send_from(PartyId::Client(0), data_message(1, PartyId::Client(0), PartyId::AllClients))
+            .await;
+        harness.inject(InterActorMessage::Disconnect(PartyId::Client(1), None)).await;
+
+        assert_eq!(relay.send(TakeRelayed).await.unwrap(), vec![RelayPayload::MemberLeft(1, 1)]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_balancing_is_as_expected() {
+        let server_command = |origin_party_id: PartyId, payload: &[u8]| {
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                origin_party_id,
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(payload),
+            )
+        };
+        let report_load = |hosted_rooms: u32, max_rooms: u32| {
+            let mut payload = vec![ControlCode::ReportLoad.into()];
+            payload.extend_from_slice(&hosted_rooms.to_le_bytes());
+            payload.extend_from_slice(&max_rooms.to_le_bytes());
+            payload
+        };
+        let created_info = |room_id: u32, server_id: u32| {
+            MessageStream::new_info(room_id, PartyId::Server(server_id), InfoCode::RoomCreated, &[])
+        };
+        let create_rooms = [ControlCode::CreateRooms.into(), 7, 0, 0, 0, 8, 0, 0, 0];
+
+        // Least loaded, the full primary server is skipped and created rooms count as hosted
+        let harness = RouterHarness::start(Default::default(), &[0]).await;
+        let first_shard = harness.connect_shard_server(1).await;
+        let second_shard = harness.connect_shard_server(2).await;
+        harness
+            .send_from(PartyId::Server(0), server_command(PartyId::Server(0), &report_load(2, 2)))
+            .await;
+        harness
+            .send_from(PartyId::Server(1), server_command(PartyId::Server(1), &report_load(2, 8)))
+            .await;
+        harness
+            .send_from(PartyId::Server(2), server_command(PartyId::Server(2), &report_load(1, 2)))
+            .await;
+        harness
+            .send_from(PartyId::Server(0), server_command(PartyId::Server(0), &create_rooms))
+            .await;
+
+        assert_eq!(harness.take_server_delivered().await, Vec::new());
+        assert_eq!(
+            second_shard.send(TakeDelivered).await.unwrap(),
+            (vec![created_info(7, 2)], false)
+        );
+        assert_eq!(
+            first_shard.send(TakeDelivered).await.unwrap(),
+            (vec![created_info(8, 1)], false)
+        );
+        assert_eq!(harness.available_rooms().await, vec![0, 7, 8]);
+
+        // Auto joins go to the least loaded server with an open room
+        harness
+            .send_from(PartyId::Server(2), server_command(PartyId::Server(2), &report_load(0, 2)))
+            .await;
+
+        assert_eq!(harness.router.send(PickRoom(Uuid::new_v4())).await.unwrap(), Some(7));
+
+        // Round robin ignores the reports
+        let config = GameRoomRouterConfig {
+            room_balancing: RoomBalancing::RoundRobin,
+            ..Default::default()
+        };
+        let harness = RouterHarness::start(config, &[0]).await;
+        let first_shard = harness.connect_shard_server(1).await;
+        harness
+            .send_from(PartyId::Server(0), server_command(PartyId::Server(0), &report_load(5, 10)))
+            .await;
+        let create_rooms = [ControlCode::CreateRooms.into(), 7, 0, 0, 0, 8, 0, 0, 0, 9, 0, 0, 0];
+        harness
+            .send_from(PartyId::Server(0), server_command(PartyId::Server(0), &create_rooms))
+            .await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![created_info(7, 0), created_info(9, 0)]
+        );
+        assert_eq!(
+            first_shard.send(TakeDelivered).await.unwrap(),
+            (vec![created_info(8, 1)], false)
+        );
+
+        // Clients cannot create rooms
+        harness
+            .send_from(PartyId::Client(0), server_command(PartyId::Client(0), &[0x45, 5, 0, 0, 0]))
+            .await;
+
+        assert_eq!(harness.available_rooms().await, vec![0, 7, 8, 9]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_slot_reservation_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            slot_reservation_ttl: Some(Duration::from_millis(50)),
+            max_room_clients: Some(2),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        let other_client_id = harness.connect_client(0, 1).await;
+        harness.set_client_counter(0, 2).await;
+        harness.inject(InterActorMessage::Disconnect(PartyId::Client(0), Some(client_id))).await;
+
+        // The reserved slot still counts toward the capacity, only its client can claim it back
+        let stranger_claim = ClaimSlot(0, Uuid::new_v4());
+        assert_eq!(harness.router.send(stranger_claim).await.unwrap(), Err(SlotRefusal::RoomFull));
+        assert_eq!(harness.router.send(ClaimSlot(0, client_id)).await.unwrap(), Ok(0));
+
+        harness
+            .inject(InterActorMessage::Disconnect(PartyId::Client(1), Some(other_client_id)))
+            .await;
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        // An expired reservation frees the slot, its client then joins like any other
+        assert_eq!(harness.router.send(ClaimSlot(0, other_client_id)).await.unwrap(), Ok(2));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_occupancy_is_as_expected() {
+        let config = GameRoomRouterConfig { max_room_clients: Some(2), ..Default::default() };
+        let room_configs =
+            vec![(2, RoomConfig::parse(r#"{"max_clients": 0}"#).unwrap())].into_iter().collect();
+        let router = GameRoomRouterActor::new(config, None).with_room_configs(room_configs).start();
+        let mut harness = RouterHarness::start_with_router(router, &[0, 1, 2]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+        let occupancy = |room_id: u32, players: usize, capacity: Option<usize>, joinable: bool| {
+            RoomOccupancy { room_id, players, capacity, joinable }
+        };
+
+        // A room lifting the limit is never full
+        assert_eq!(
+            harness.router.send(GetRoomOccupancy).await.unwrap(),
+            vec![
+                occupancy(0, 2, Some(2), false),
+                occupancy(1, 1, Some(2), true),
+                occupancy(2, 0, None, true),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_sequences_is_as_expected() {
+        let room_configs =
+            vec![(0, RoomConfig::parse(r#"{"sequenced": true}"#).unwrap())].into_iter().collect();
+        let router = GameRoomRouterActor::new(Default::default(), None)
+            .with_room_configs(room_configs)
+            .start();
+        let mut harness = RouterHarness::start_with_router(router, &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+
+        let sequenced = |room_sequence: u32, destination_party_id: PartyId| {
+            let mut message = data_message(0, PartyId::Server(0), destination_party_id);
+            message.header_options.room_sequence = Some(room_sequence);
+            message
+        };
+
+        // Only server broadcasts are numbered, the echo along with the clients
+        for destination_party_id in [PartyId::AllClients, PartyId::AllClientsWithEcho] {
+            let broadcast = data_message(0, PartyId::Server(0), destination_party_id);
+            harness.send_from(PartyId::Server(0), broadcast).await;
+        }
+        let unicast = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        harness.send_from(PartyId::Server(0), unicast.clone()).await;
+        let client_broadcast = data_message(0, PartyId::Client(1), PartyId::AllClients);
+        harness.send_from(PartyId::Client(1), client_broadcast.clone()).await;
+        let unsequenced = data_message(1, PartyId::Server(0), PartyId::AllClients);
+        harness.send_from(PartyId::Server(0), unsequenced.clone()).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![
+                sequenced(0, PartyId::AllClients),
+                sequenced(1, PartyId::AllClientsWithEcho),
+                unicast,
+                client_broadcast
+            ]
+        );
+        assert_eq!(
+            harness.take_client_delivered(0, 1).await.0,
+            vec![sequenced(0, PartyId::AllClients), sequenced(1, PartyId::AllClientsWithEcho)]
+        );
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![sequenced(1, PartyId::AllClientsWithEcho)]
+        );
+        assert_eq!(harness.take_client_delivered(1, 0).await.0, vec![unsequenced]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_realtime_relay_is_as_expected() {
+        let room_configs =
+            vec![(0, RoomConfig::parse(r#"{"realtime": true}"#).unwrap())].into_iter().collect();
+        let router = GameRoomRouterActor::new(Default::default(), None)
+            .with_room_configs(room_configs)
+            .start();
+        let mut harness = RouterHarness::start_with_router(router, &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+        harness.router.send(SetRoomPaused(0, true)).await.unwrap();
+        let realtime_message = |room_id: u32, origin_party_id, destination_party_id| {
+            let mut message = data_message(room_id, origin_party_id, destination_party_id);
+            message.header_options.reliability = Some(MessageReliability::Realtime);
+            message
+        };
+
+        // Relayed at once even though the room is paused, the other frames being held
+        let voice = realtime_message(0, PartyId::Client(0), PartyId::AllClients);
+        harness.send_from(PartyId::Client(0), voice.clone()).await;
+        let echoed_voice = realtime_message(0, PartyId::Server(0), PartyId::AllClientsWithEcho);
+        harness.send_from(PartyId::Server(0), echoed_voice.clone()).await;
+        harness
+            .send_from(PartyId::Client(1), data_message(0, PartyId::Client(1), PartyId::Server(0)))
+            .await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![echoed_voice.clone()]);
+        assert_eq!(harness.take_client_delivered(0, 1).await.0, vec![voice, echoed_voice.clone()]);
+        assert_eq!(harness.take_server_delivered().await, vec![echoed_voice]);
+
+        // Out of a realtime room they are routed as Unreliable frames
+        let voice = realtime_message(1, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), voice.clone()).await;
+        let mut unreliable_voice = voice;
+        unreliable_voice.header_options.reliability = Some(MessageReliability::Unreliable);
+
+        assert_eq!(harness.take_server_delivered().await, vec![unreliable_voice]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_throughput_is_as_expected() {
+        let room_configs =
+            vec![(0, RoomConfig::parse(r#"{"max_messages_per_second": 2}"#).unwrap())]
+                .into_iter()
+                .collect();
+        let router = GameRoomRouterActor::new(Default::default(), None)
+            .with_room_configs(room_configs)
+            .start();
+        let mut harness = RouterHarness::start_with_router(router, &[0, 1]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+        harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+        let message = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        let length = message.raw_length() as u32;
+
+        // The cap is shared by the members, the server being told of the top sender once
+        for _ in 0..2 {
+            harness.send_from(PartyId::Client(0), message.clone()).await;
+        }
+        for _ in 0..2 {
+            let message = data_message(0, PartyId::Client(1), PartyId::Server(0));
+            harness.send_from(PartyId::Client(1), message).await;
+        }
+        let other_room_message = data_message(1, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), other_room_message.clone()).await;
+
+        let mut details = 0u32.to_le_bytes().to_vec();
+        details.extend_from_slice(&2u32.to_le_bytes());
+        details.extend_from_slice(&(2 * length).to_le_bytes());
+        let throttled_info =
+            MessageStream::new_info(0, PartyId::Server(0), InfoCode::Throttled, &details);
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![message.clone(), message, throttled_info, other_room_message]
+        );
+
+        let throttled = harness.take_client_delivered(0, 1).await.0;
+        let error_prefix: [u8; 2] = [InfoCode::Error.into(), ErrorCode::RoomThrottled.into()];
+
+        assert_eq!(throttled.len(), 2);
+        assert!(throttled.iter().all(|throttled| throttled.payload[..2] == error_prefix));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_state_sync_is_as_expected() {
+        let config = GameRoomRouterConfig { snapshot_max_deltas: Some(2), ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        let delta = |payload: u8| {
+            let mut message = data_message(0, PartyId::Server(0), PartyId::AllClients);
+            message.payload = vec![payload];
+            message
+        };
+        let mut snapshot = delta(0xFF);
+        snapshot.payload_kind = PayloadKind::Snapshot;
+
+        // Broadcasts before the first snapshot are not kept, the snapshot itself is not routed
+        harness.send_from(PartyId::Server(0), delta(0)).await;
+        harness.send_from(PartyId::Server(0), snapshot.clone()).await;
+        harness.send_from(PartyId::Server(0), delta(1)).await;
+        harness.send_from(PartyId::Client(0), snapshot.clone()).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![delta(0), delta(1)]);
+
+        harness.connect_client(0, 1).await;
+        harness.send_from(PartyId::Server(0), delta(2)).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 1).await.0,
+            vec![snapshot.clone(), delta(1), delta(2)]
+        );
+
+        // Past the deltas limit joiners wait for the next snapshot
+        harness.send_from(PartyId::Server(0), delta(3)).await;
+        harness.connect_client(0, 2).await;
+
+        assert_eq!(harness.take_client_delivered(0, 2).await.0, vec![]);
+
+        harness.send_from(PartyId::Server(0), snapshot.clone()).await;
+        harness.connect_client(0, 3).await;
+
+        assert_eq!(harness.take_client_delivered(0, 3).await.0, vec![snapshot]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_blob_transfers_is_as_expected() {
+        let config = GameRoomRouterConfig { blob_window: Some(100), ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        let blob_message = |origin_party_id: PartyId, kind: u8, offset: u32, length: usize| {
+            let destination_party_id = match origin_party_id {
+                PartyId::Server(_) => PartyId::Client(0),
+                _ => PartyId::Server(0),
+            };
+            let mut message = data_message(0, origin_party_id, destination_party_id);
+            message.payload_kind = PayloadKind::Blob;
+            message.payload = [&[kind][..], &7u32.to_le_bytes(), &200u32.to_le_bytes()].concat();
+            message.payload.extend_from_slice(&offset.to_le_bytes());
+            message.payload.extend_from_slice(&vec![0xBB; length]);
+            message
+        };
+        let bulk = |mut message: MessageStream| {
+            message.header_options.priority = Some(MessagePriority::Bulk);
+            message
+        };
+
+        // Chunks are moved to the Bulk lane and may run the window past the last ack
+        harness.send_from(PartyId::Server(0), blob_message(PartyId::Server(0), 0x01, 0, 60)).await;
+        harness.send_from(PartyId::Server(0), blob_message(PartyId::Server(0), 0x01, 60, 60)).await;
+        let ack = blob_message(PartyId::Client(0), 0x0A, 60, 0);
+        harness.send_from(PartyId::Client(0), ack.clone()).await;
+        harness.send_from(PartyId::Server(0), blob_message(PartyId::Server(0), 0x01, 60, 60)).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![
+                bulk(blob_message(PartyId::Server(0), 0x01, 0, 60)),
+                bulk(blob_message(PartyId::Server(0), 0x01, 60, 60))
+            ]
+        );
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                MessageStream::new_error(
+                    0,
+                    PartyId::Server(0),
+                    ErrorCode::BlobWindowExceeded,
+                    &[7, 0, 0, 0, 0, 0, 0, 0]
+                ),
+                ack
+            ]
+        );
+
+        // A resumed transfer goes on from where its first chunk starts
+        let resumed_chunk = blob_message(PartyId::Client(0), 0x01, 150, 50);
+        harness.send_from(PartyId::Client(0), resumed_chunk.clone()).await;
+        let mut undecodable = blob_message(PartyId::Client(0), 0x01, 190, 50);
+        undecodable.payload.truncate(10);
+        harness.send_from(PartyId::Client(0), undecodable).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![bulk(resumed_chunk)]);
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Client(0),
+                ErrorCode::UndecodablePayload,
+                &[PayloadKind::Blob.into()]
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_stream_credits_is_as_expected() {
+        let config = GameRoomRouterConfig { max_streams: Some(1), ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        let stream_message = |origin_party_id: PartyId, kind: u8, stream_id: u32, data: &[u8]| {
+            let destination_party_id = match origin_party_id {
+                PartyId::Server(_) => PartyId::Client(0),
+                _ => PartyId::Server(0),
+            };
+            let mut message = data_message(0, origin_party_id, destination_party_id);
+            message.payload_kind = PayloadKind::Stream;
+            message.payload = [&[kind][..], &stream_id.to_le_bytes(), data].concat();
+            message
+        };
+        let stream_error = |party_id: PartyId, error_code: ErrorCode, details: &[u8]| {
+            MessageStream::new_error(0, party_id, error_code, details)
+        };
+        let open = stream_message(PartyId::Server(0), 0x01, 5, &[]);
+        let chunk = stream_message(PartyId::Server(0), 0x02, 5, &[0xCC; 10]);
+        let credit = stream_message(PartyId::Client(0), 0x0C, 5, &20u32.to_le_bytes());
+
+        // Chunks only go through once the destination granted credit for them
+        harness.send_from(PartyId::Server(0), open.clone()).await;
+        harness.send_from(PartyId::Server(0), chunk.clone()).await;
+        harness.send_from(PartyId::Client(0), credit.clone()).await;
+        harness.send_from(PartyId::Server(0), chunk.clone()).await;
+        harness
+            .send_from(PartyId::Server(0), stream_message(PartyId::Server(0), 0x01, 6, &[]))
+            .await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![open, chunk.clone()]);
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                stream_error(
+                    PartyId::Server(0),
+                    ErrorCode::StreamCreditExhausted,
+                    &[5, 0, 0, 0, 0, 0, 0, 0]
+                ),
+                credit,
+                stream_error(PartyId::Server(0), ErrorCode::StreamRefused, &[6, 0, 0, 0])
+            ]
+        );
+
+        // Closed by the destination, then chunks of the stream are refused as never opened
+        let close = stream_message(PartyId::Client(0), 0x03, 5, &[]);
+        harness.send_from(PartyId::Client(0), close.clone()).await;
+        harness.send_from(PartyId::Server(0), chunk).await;
+        harness
+            .send_from(PartyId::Client(0), stream_message(PartyId::Client(0), 0x02, 9, &[1]))
+            .await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![close, stream_error(PartyId::Server(0), ErrorCode::StreamRefused, &[5, 0, 0, 0])]
+        );
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![stream_error(PartyId::Client(0), ErrorCode::StreamRefused, &[9, 0, 0, 0])]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_config_update_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.set_client_counter(0, 1).await;
+
+        assert_eq!(harness.router.send(ClaimSlot(0, Uuid::new_v4())).await.unwrap(), Ok(1));
+
+        let config_update = ConfigUpdate {
+            max_room_clients: Some(1),
+            banned_ips: vec!["203.0.113.0/24".parse().unwrap()].into(),
+            room_configs: Default::default(),
+        };
+
+        assert!(config_update.is_banned("203.0.113.7".parse().unwrap()));
+        assert!(!config_update.is_banned("198.51.100.7".parse().unwrap()));
+
+        harness.router.send(config_update).await.unwrap();
+
+        // Reloaded capacity applies to the next joins
+        let claim = ClaimSlot(0, Uuid::new_v4());
+        assert_eq!(harness.router.send(claim).await.unwrap(), Err(SlotRefusal::RoomFull));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_provisioned_rooms_is_as_expected() {
+        let templates = serde_json::from_str(
+            r#"{
+                "ranked-2v2": {"config": {"max_clients": 4}, "metadata": {"mode": "ranked"}},
+                "new-year": {"schedule": {"open": "0 0 1 1 *", "close": "1 0 1 1 *"}}
+            }"#,
+        )
+        .unwrap();
+        let provisioned_rooms =
+            vec![(5, "ranked-2v2".to_string()), (9, "new-year".to_string())].into_iter().collect();
+        let router = GameRoomRouterActor::new(Default::default(), None)
+            .with_provisioned_rooms(
+                ProvisionedRoom::provision(&templates, &provisioned_rooms).unwrap(),
+            )
+            .start();
+        // Outside its window, the scheduled room is left out even when the server announces it
+        let harness = RouterHarness::start_with_router(router, &[1, 9]).await;
+
+        // Kept over the room list of the server
+        assert_eq!(harness.available_rooms().await, vec![1, 5]);
+        assert_eq!(
+            harness.take_server_delivered().await[0],
+            MessageStream::new_info(
+                5,
+                PartyId::Server(0),
+                InfoCode::RoomProvisioned,
+                b"\x0aranked-2v2{\"mode\":\"ranked\"}"
+            )
+        );
+
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+        assert_eq!(rooms[0].template, None);
+        assert_eq!(rooms[1].template.as_deref(), Some("ranked-2v2"));
+        assert_eq!(rooms[1].metadata.as_deref(), Some(r#"{"mode":"ranked"}"#));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_config_is_as_expected() {
+        let room_configs =
+            vec![(0, RoomConfig::parse(r#"{"max_clients": 1}"#).unwrap())].into_iter().collect();
+        let router = GameRoomRouterActor::new(Default::default(), None)
+            .with_room_configs(room_configs)
+            .start();
+        let mut harness = RouterHarness::start_with_router(router, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.set_client_counter(0, 1).await;
+        harness.take_server_delivered().await;
+
+        assert_eq!(
+            harness.router.send(ClaimSlot(0, Uuid::new_v4())).await.unwrap(),
+            Err(SlotRefusal::RoomFull)
+        );
+
+        // The server of the room overrides the config file
+        let configure_room = |room_config: &str| {
+            let mut payload = vec![ControlCode::ConfigureRoom.into()];
+            payload.extend_from_slice(room_config.as_bytes());
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                PartyId::Server(0),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(&payload),
+            )
+        };
+        harness
+            .send_from(
+                PartyId::Server(0),
+                configure_room(r#"{"max_clients": 2, "allowed_payload_kinds": ["Command"]}"#),
+            )
+            .await;
+
+        assert_eq!(harness.router.send(ClaimSlot(0, Uuid::new_v4())).await.unwrap(), Ok(1));
+
+        harness
+            .send_from(PartyId::Client(0), data_message(0, PartyId::Client(0), PartyId::Server(0)))
+            .await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Client(0),
+                ErrorCode::PayloadForbidden,
+                &[PayloadKind::Data.into()]
+            )]
+        );
+
+        // Cleared, the room is back to the config file
+        harness.send_from(PartyId::Server(0), configure_room("{}")).await;
+        harness.take_server_delivered().await;
+        let data = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), data.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![data]);
+        assert_eq!(
+            harness.router.send(ClaimSlot(0, Uuid::new_v4())).await.unwrap(),
+            Err(SlotRefusal::RoomFull)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_empty_room_expiry_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            empty_room_ttl: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0, 1]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        let rejoined_client_id = harness.connect_client(1, 0).await;
+        harness.inject(InterActorMessage::Disconnect(PartyId::Client(0), Some(client_id))).await;
+        harness
+            .inject(InterActorMessage::Disconnect(PartyId::Client(0), Some(rejoined_client_id)))
+            .await;
+        harness.connect_client(1, 1).await;
+        harness.take_server_delivered().await;
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+
+        let expired_info = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&[InfoCode::RoomExpired.into()]),
+        );
+
+        assert_eq!(harness.available_rooms().await, vec![1]);
+        assert_eq!(harness.take_server_delivered().await, vec![expired_info]);
+        assert_eq!(harness.router.send(ListRooms).await.unwrap()[0].client_party_ids, vec![1]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_idle_clients_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            idle_timeout: Some(Duration::from_millis(100)),
+            idle_grace: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+
+        // Only the Normal frames of the second client keep it from idling
+        for _ in 0..12 {
+            actix::clock::delay_for(Duration::from_millis(25)).await;
+            harness
+                .send_from(
+                    PartyId::Client(1),
+                    data_message(0, PartyId::Client(1), PartyId::Server(0)),
+                )
+                .await;
+        }
+
+        // Warned, then kicked once its grace is over
+        let (delivered, is_disconnected) = harness.take_client_delivered(0, 0).await;
+        assert_eq!(
+            delivered[0],
+            MessageStream::new_info(
+                0,
+                PartyId::Client(0),
+                InfoCode::IdleWarning,
+                &100u32.to_le_bytes()
+            )
+        );
+        assert!(is_disconnected);
+        assert_eq!(harness.take_client_delivered(0, 1).await, (vec![], false));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_switch_room_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        harness.set_client_counter(1, 3).await;
+
+        let switch_room = |target_room_id: u32| {
+            let mut payload = vec![ControlCode::SwitchRoom.into()];
+            payload.extend_from_slice(&target_room_id.to_le_bytes());
+
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                PartyId::Client(0),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(&payload),
+            )
+        };
+
+        harness.send_from(PartyId::Client(0), switch_room(7)).await;
+        let unavailable = MessageStream::new_error(
+            0,
+            PartyId::Client(0),
+            ErrorCode::RoomUnavailable,
+            &7u32.to_le_bytes(),
+        );
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![unavailable]);
+
+        harness.send_from(PartyId::Client(0), switch_room(1)).await;
+        let switched_info = MessageStream::new(
+            MessageCode::Special,
+            1,
+            PartyId::Server(0),
+            PartyId::Client(3),
+            PayloadKind::Info,
+            Some(&[InfoCode::RoomSwitched.into(), 1, 0, 0, 0, 3, 0, 0, 0]),
+        );
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Leave,
+                    0,
+                    PartyId::Client(0),
+                    client_id,
+                    &[]
+                ),
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Join,
+                    1,
+                    PartyId::Client(3),
+                    client_id,
+                    &[]
+                ),
+            ]
+        );
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![switched_info]);
+
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+
+        assert!(rooms[0].client_party_ids.is_empty());
+        assert_eq!((rooms[1].room_id, rooms[1].client_party_ids.clone()), (1, vec![3]));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_presence_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let first_client_id = harness.connect_client_with_metadata(0, 0, b"Ana").await;
+        let second_client_id = harness.connect_client(0, 1).await;
+        harness
+            .send_from(PartyId::Client(1), data_message(0, PartyId::Client(1), PartyId::Server(0)))
+            .await;
+
+        let room_members = harness.router.send(GetPresence(0)).await.unwrap().unwrap();
+
+        assert_eq!(
+            room_members
+                .iter()
+                .map(|room_member| (
+                    room_member.party_id,
+                    room_member.client_id,
+                    room_member.metadata.clone()
+                ))
+                .collect::<Vec<_>>(),
+            vec![(0, first_client_id, Some("Ana".into())), (1, second_client_id, None)]
+        );
+        assert!(room_members.iter().all(|room_member| room_member.joined_at_millis > 0));
+        assert!(room_members[1].last_active_at_millis >= room_members[1].joined_at_millis);
+        assert_eq!(harness.router.send(GetPresence(1)).await.unwrap(), Some(Vec::new()));
+        assert_eq!(harness.router.send(GetPresence(9)).await.unwrap(), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_client_metadata_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let json_metadata = br#"{"name":"Ana"}"#;
+        let client_id = harness.connect_client_with_metadata(0, 0, json_metadata).await;
+        harness.connect_client_with_metadata(0, 1, &[0xFF, 0x01]).await;
+        harness.connect_client(0, 2).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await[0],
+            GameRoomRouterActor::presence_info(
+                InfoCode::Join,
+                0,
+                PartyId::Client(0),
+                client_id,
+                json_metadata
+            )
+        );
+
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+
+        assert_eq!(
+            rooms[0].client_metadata,
+            vec![(0, r#"{"name":"Ana"}"#.to_string()), (1, "ff01".to_string())]
+                .into_iter()
+                .collect()
+        );
+
+        // The metadata follows the client into the other rooms it joins
+        let join_room = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Client(0),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&[ControlCode::JoinRoom.into(), 1, 0, 0, 0]),
+        );
+        harness.send_from(PartyId::Client(0), join_room).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![GameRoomRouterActor::presence_info(
+                InfoCode::Join,
+                1,
+                PartyId::Client(0),
+                client_id,
+                json_metadata
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_multi_room_membership_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        harness.connect_client(1, 0).await;
+        harness.take_server_delivered().await;
+        harness.set_client_counter(1, 1).await;
+
+        let membership_command = |room_id: u32, party_id: u32, payload: &[u8]| {
+            MessageStream::new(
+                MessageCode::Special,
+                room_id,
+                PartyId::Client(party_id),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(payload),
+            )
+        };
+        let membership_info = |room_id: u32, party_id: u32, payload: &[u8]| {
+            MessageStream::new(
+                MessageCode::Special,
+                room_id,
+                PartyId::Server(0),
+                PartyId::Client(party_id),
+                PayloadKind::Info,
+                Some(payload),
+            )
+        };
+
+        let join_room = membership_command(0, 0, &[ControlCode::JoinRoom.into(), 1, 0, 0, 0]);
+        harness.send_from(PartyId::Client(0), join_room).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![GameRoomRouterActor::presence_info(
+                InfoCode::Join,
+                1,
+                PartyId::Client(1),
+                client_id,
+                &[]
+            )]
+        );
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![membership_info(1, 1, &[InfoCode::RoomJoined.into(), 1, 0, 0, 0, 1, 0, 0, 0])]
+        );
+
+        // Room 1 frames reach the same connection as its party of room 1
+        let direct = data_message(1, PartyId::Server(0), PartyId::Client(1));
+        harness.send_from(PartyId::Server(0), direct.clone()).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![direct]);
+
+        let leave_room = membership_command(0, 0, &[ControlCode::LeaveRoom.into()]);
+        harness.send_from(PartyId::Client(0), leave_room).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![GameRoomRouterActor::presence_info(
+                InfoCode::Leave,
+                0,
+                PartyId::Client(0),
+                client_id,
+                &[]
+            )]
+        );
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await,
+            (vec![membership_info(0, 0, &[InfoCode::RoomLeft.into()])], false)
+        );
+
+        let rooms = harness.router.send(ListRooms).await.unwrap();
+
+        assert!(rooms[0].client_party_ids.is_empty());
+        assert_eq!(rooms[1].client_party_ids, vec![0, 1]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_pick_room_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1, 2]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        let client_id = Uuid::new_v4();
+        let pick = harness.router.send(PickRoom(client_id));
+        harness.router.send(ListRooms).await.unwrap();
+
+        let mut pick_payload = vec![InfoCode::PickRoom.into(), 0, 0, 0, 0];
+        pick_payload.extend_from_slice(client_id.as_bytes());
+        pick_payload.extend_from_slice(&[1, 0, 0, 0]);
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![MessageStream::new(
+                MessageCode::Special,
+                1,
+                PartyId::Server(0),
+                PartyId::Server(0),
+                PayloadKind::Info,
+                Some(&pick_payload),
+            )]
+        );
+
+        let assign_room = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&[ControlCode::AssignRoom.into(), 0, 0, 0, 0, 2, 0, 0, 0]),
+        );
+        harness.send_from(PartyId::Server(0), assign_room).await;
+
+        assert_eq!(pick.await.unwrap(), Some(2));
+
+        // Unanswered picks fall back to the least occupied room
+        assert_eq!(harness.router.send(PickRoom(client_id)).await.unwrap(), Some(1));
+    }
+
+    #[actix_rt::test]
+    async fn test_router_lobby_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        let client_id = harness.connect_client(LOBBY_ROOM_ID, 0).await;
+        harness.take_server_delivered().await;
+
+        let room_list = |room_ids: &[u32]| {
+            let room_list: Vec<u8> =
+                room_ids.iter().flat_map(|room_id| room_id.to_le_bytes()).collect();
+            MessageStream::new_info(
+                LOBBY_ROOM_ID,
+                PartyId::Client(0),
+                InfoCode::RoomList,
+                &room_list,
+            )
+        };
+        let server_command = |room_id: u32, payload: &[u8]| {
+            MessageStream::new(
+                MessageCode::Special,
+                room_id,
+                PartyId::Server(0),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(payload),
+            )
+        };
+
+        assert_eq!(
+            harness.take_client_delivered(LOBBY_ROOM_ID, 0).await.0,
+            vec![room_list(&[0, 1])]
+        );
+
+        let add_rooms = server_command(0, &[ControlCode::AddRooms.into(), 5, 0, 0, 0]);
+        harness.send_from(PartyId::Server(0), add_rooms).await;
+
+        assert_eq!(
+            harness.take_client_delivered(LOBBY_ROOM_ID, 0).await.0,
+            vec![room_list(&[0, 1, 5])]
+        );
+
+        let move_client = server_command(
+            LOBBY_ROOM_ID,
+            &[ControlCode::MoveClient.into(), 0, 0, 0, 0, 5, 0, 0, 0],
+        );
+        harness.send_from(PartyId::Server(0), move_client).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Leave,
+                    LOBBY_ROOM_ID,
+                    PartyId::Client(0),
+                    client_id,
+                    &[]
+                ),
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Join,
+                    5,
+                    PartyId::Client(0),
+                    client_id,
+                    &[]
+                ),
+            ]
+        );
+        assert_eq!(
+            harness.take_client_delivered(LOBBY_ROOM_ID, 0).await.0,
+            vec![GameRoomRouterActor::membership_info(InfoCode::RoomSwitched, 5, 0)]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_room_list_deltas_is_as_expected() {
+        let harness = RouterHarness::start(Default::default(), &[1, 4]).await;
+        let room_list_delta = |control_code: ControlCode, room_ids: &[u32]| {
+            let mut payload = vec![control_code.into()];
+            payload.extend(room_ids.iter().flat_map(|room_id| room_id.to_le_bytes()));
+
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                PartyId::Server(0),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(&payload),
+            )
+        };
+
+        harness
+            .send_from(PartyId::Server(0), room_list_delta(ControlCode::AddRooms, &[70_000, 1]))
+            .await;
+        assert_eq!(harness.available_rooms().await, vec![1, 4, 70_000]);
+
+        harness
+            .send_from(PartyId::Server(0), room_list_delta(ControlCode::RemoveRooms, &[4, 9]))
+            .await;
+        assert_eq!(harness.available_rooms().await, vec![1, 70_000]);
+
+        // Room lists are the server business
+        harness.send_from(PartyId::Client(0), room_list_delta(ControlCode::AddRooms, &[7])).await;
+        assert_eq!(harness.available_rooms().await, vec![1, 70_000]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_slow_client_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // Namesakes over another connection are left alone
+        let outbound_lag = Duration::from_millis(5_001);
+        let other_address = FakeEndpoint::default().start().recipient();
+        harness.inject(InterActorMessage::SlowClient(client_id, other_address, outbound_lag)).await;
+
+        assert!(harness.take_server_delivered().await.is_empty());
+
+        let address = harness.clients[&(0, 0)].clone().recipient();
+        harness.inject(InterActorMessage::SlowClient(client_id, address, outbound_lag)).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![
+                GameRoomRouterActor::presence_info(
+                    InfoCode::SlowClient,
+                    0,
+                    PartyId::Client(0),
+                    client_id,
+                    &5_001u32.to_le_bytes()
+                ),
+                GameRoomRouterActor::presence_info(
+                    InfoCode::Leave,
+                    0,
+                    PartyId::Client(0),
+                    client_id,
+                    &[]
+                ),
+            ]
+        );
+        assert!(harness.router.send(ListRooms).await.unwrap()[0].client_party_ids.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_router_server_claim_is_as_expected() {
+        let harness = RouterHarness::start(Default::default(), &[0]).await;
+
+        assert!(harness.router.send(GetServerJoined).await.unwrap());
+        assert_eq!(harness.router.send(ClaimServer(false)).await.unwrap(), None);
+        assert_eq!(harness.router.send(ClaimServer(true)).await.unwrap(), Some(true));
+        assert_eq!(
+            harness.router.send(ClaimSlot(1, Uuid::new_v4())).await.unwrap(),
+            Err(SlotRefusal::NoRoom)
+        );
+
+        harness.inject(InterActorMessage::Disconnect(PartyId::Server(0), None)).await;
+
+        assert!(!harness.router.send(GetServerJoined).await.unwrap());
+        assert_eq!(harness.router.send(ClaimServer(true)).await.unwrap(), Some(false));
+
+        // A failed upgrade gives its claim back
+        harness.router.send(ReleaseServer).await.unwrap();
+
+        assert!(!harness.router.send(GetServerJoined).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_router_delivery_acks_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            batch_interval: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        let delivery_info = |info_code: InfoCode, destination_party_id: PartyId| {
+            let mut details = 7u32.to_le_bytes().to_vec();
+            details.extend_from_slice(&destination_party_id.to_le_bytes());
+            MessageStream::new_info(0, PartyId::Server(0), info_code, &details)
+        };
+
+        // Frames asking for an acknowledgement skip the outbound batches
+        let mut turn = data_message(0, PartyId::Server(0), PartyId::Client(0));
+        turn.header_options.ack_sequence = Some(7);
+        harness.send_from(PartyId::Server(0), turn.clone()).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![turn]);
+
+        // Acknowledged by the destination connection, routed back to the origin
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        let delivery_report =
+            InterActorMessage::DeliveryReport(0, PartyId::Server(0), PartyId::Client(0), 7, true);
+        harness.inject(delivery_report).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![delivery_info(InfoCode::Delivered, PartyId::Client(0))]
+        );
+
+        // Unknown destinations are told back as undelivered, frames without the flag are not
+        let mut purchase = data_message(0, PartyId::Server(0), PartyId::Client(5));
+        harness.send_from(PartyId::Server(0), purchase.clone()).await;
+        purchase.header_options.ack_sequence = Some(7);
+        harness.send_from(PartyId::Server(0), purchase).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![delivery_info(InfoCode::Undelivered, PartyId::Client(5))]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_connection_stats_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        let query_stats = |origin_party_id: PartyId, client_party_id: u32| {
+            let mut payload = vec![ControlCode::QueryStats.into()];
+            payload.extend_from_slice(&client_party_id.to_le_bytes());
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                origin_party_id,
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(&payload),
+            )
+        };
+
+        // Clients may not query, parties missing from the room are told unlocated
+        harness.send_from(PartyId::Client(0), query_stats(PartyId::Client(0), 0)).await;
+        harness.send_from(PartyId::Server(0), query_stats(PartyId::Server(0), 0)).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![]);
+
+        harness.send_from(PartyId::Server(0), query_stats(PartyId::Server(0), 5)).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Server(0),
+                ErrorCode::PartyUnlocated,
+                &PartyId::Client(5).to_le_bytes(),
+            )]
+        );
+
+        // Reported by the client connection, answered to the asking server
+        let connection_stats = ConnectionStats {
+            frames_received: 3,
+            round_trip_millis: Some(20),
+            ..Default::default()
+        };
+        harness
+            .inject(InterActorMessage::ConnectionStats(0, PartyId::Server(0), connection_stats))
+            .await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![MessageStream::new_info(
+                0,
+                PartyId::Server(0),
+                InfoCode::ConnectionStats,
+                &connection_stats.into_details(),
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_dedup_window_is_as_expected() {
+        let config = GameRoomRouterConfig { dedup_window: Some(8), ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        let sequenced = |sequence: u32| {
+            let mut message = data_message(0, PartyId::Client(0), PartyId::Server(0));
+            message.header_options.sequence = Some(sequence);
+            message
+        };
+
+        // Resent frames are dropped, frames without a sequence always go through
+        for message in [sequenced(1), sequenced(2), sequenced(1)].iter() {
+            harness.send_from(PartyId::Client(0), message.clone()).await;
+        }
+        let unsequenced = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), unsequenced.clone()).await;
+        harness.send_from(PartyId::Client(0), unsequenced.clone()).await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![sequenced(1), sequenced(2), unsequenced.clone(), unsequenced]
+        );
+
+        // A rejoined party numbers its frames from scratch
+        harness.inject(InterActorMessage::Disconnect(PartyId::Client(0), None)).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        harness.send_from(PartyId::Client(0), sequenced(1)).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![sequenced(1)]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_message_ttl_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            batch_interval: Some(Duration::from_millis(30)),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        let with_ttl = |ttl_millis: u16| {
+            let mut message = data_message(0, PartyId::Server(0), PartyId::Client(0));
+            message.header_options.ttl_millis = Some(ttl_millis);
+            message
+        };
+
+        // Expired in the outbound batch, the frame left is sent with the TTL it has left
+        harness.send_from(PartyId::Server(0), with_ttl(1)).await;
+        harness.send_from(PartyId::Server(0), with_ttl(10_000)).await;
+        actix::clock::delay_for(Duration::from_millis(100)).await;
+        let delivered = harness.take_client_delivered(0, 0).await.0;
+
+        assert_eq!(delivered.len(), 1);
+        assert!(delivered[0].header_options.ttl_millis.unwrap() < 10_000);
+
+        // Expired while held in a paused room
+        harness.router.send(SetRoomPaused(0, true)).await.unwrap();
+        let mut critical = with_ttl(1);
+        critical.header_options.priority = Some(MessagePriority::Critical);
+        harness.send_from(PartyId::Server(0), critical).await;
+        harness
+            .send_from(PartyId::Server(0), data_message(0, PartyId::Server(0), PartyId::Client(0)))
+            .await;
+        actix::clock::delay_for(Duration::from_millis(10)).await;
+
+        assert_eq!(harness.router.send(SetRoomPaused(0, false)).await.unwrap(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_memory_budget_is_as_expected() {
+        let mut bulk = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        bulk.header_options.priority = Some(MessagePriority::Bulk);
+        let first = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        let config = GameRoomRouterConfig {
+            memory_budget: MemoryBudget {
+                max_bytes: None,
+                max_room_bytes: Some(bulk.raw_length() + first.raw_length()),
+                shed_policy: "bulk".parse().unwrap(),
+            },
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+        harness.router.send(SetRoomPaused(0, true)).await.unwrap();
+
+        // The held Bulk frame is shed for the second Normal one, the third one no longer fits
+        for message in [bulk, first.clone(), first.clone(), first.clone()].iter() {
+            harness.send_from(PartyId::Client(0), message.clone()).await;
+        }
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0.len(), 1);
+        assert_eq!(harness.router.send(SetRoomPaused(0, false)).await.unwrap(), 2);
+        assert_eq!(harness.take_server_delivered().await, vec![first.clone(), first]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_lockstep_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        let client_id = harness.connect_client(0, 0).await;
+        harness.connect_client(0, 1).await;
+
+        // Long enough for no deadline to pass during the test
+        let lockstep = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&[ControlCode::Lockstep.into(), 0x60, 0xEA]),
+        );
+        harness.send_from(PartyId::Server(0), lockstep).await;
+        harness.take_server_delivered().await;
+
+        let input = |origin_party_id: u32, value: u8| {
+            MessageStream::new(
+                MessageCode::Normal,
+                0,
+                PartyId::Client(origin_party_id),
+                PartyId::Server(0),
+                PayloadKind::Data,
+                Some(&[value]),
+            )
+        };
+
+        // Client 0 runs as far ahead as allowed, its last input is dropped
+        for value in 0..=LockstepRoom::MAX_PENDING_INPUTS as u8 {
+            harness.send_from(PartyId::Client(0), input(0, value)).await;
+        }
+
+        for value in 0..=LockstepRoom::MAX_PENDING_INPUTS as u8 {
+            harness.send_from(PartyId::Client(1), input(1, value)).await;
+        }
+
+        let bundles = harness.take_client_delivered(0, 1).await.0;
+        let last_bundle = LockstepBundle { tick: 15, inputs: vec![input(0, 15), input(1, 15)] };
+
+        assert_eq!(bundles.len(), LockstepRoom::MAX_PENDING_INPUTS);
+        assert_eq!(bundles.last().unwrap().payload, last_bundle.into_message_stream(0).payload);
+
+        // The tick waiting on client 0 is broadcast as soon as it is gone
+        harness.inject(InterActorMessage::Disconnect(PartyId::Client(0), Some(client_id))).await;
+        let bundles = harness.take_client_delivered(0, 1).await.0;
+        let last_bundle = LockstepBundle { tick: 16, inputs: vec![input(1, 16)] };
+
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].payload, last_bundle.into_message_stream(0).payload);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_shard_lockstep_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        let shard_server = harness.connect_shard_server(1).await;
+
+        let shard_command = |payload: &[u8]| {
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                PartyId::Server(1),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(payload),
+            )
+        };
+        harness
+            .send_from(
+                PartyId::Server(1),
+                shard_command(&[ControlCode::ClaimRooms.into(), 0, 0, 0, 0]),
+            )
+            .await;
+        harness
+            .send_from(
+                PartyId::Server(1),
+                shard_command(&[ControlCode::Lockstep.into(), 0x60, 0xEA]),
+            )
+            .await;
+        shard_server.send(TakeDelivered).await.unwrap();
+        harness.take_server_delivered().await;
+
+        let input = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Client(0), input.clone()).await;
+        let bundle = LockstepBundle { tick: 0, inputs: vec![input] }.into_message_stream(0);
+        let payloads = |delivered: Vec<MessageStream>| {
+            delivered.into_iter().map(|message_stream| message_stream.payload).collect::<Vec<_>>()
+        };
+
+        // The bundle reaches the shard server owning the room, not the primary one
+        assert_eq!(
+            payloads(shard_server.send(TakeDelivered).await.unwrap().0),
+            vec![bundle.payload.clone()]
+        );
+        assert_eq!(harness.take_server_delivered().await, Vec::new());
+        assert_eq!(payloads(harness.take_client_delivered(0, 0).await.0), vec![bundle.payload]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_interest_subscriptions_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // One key past the limit, it is ignored
+        let mut subscribe = vec![ControlCode::Subscribe.into()];
+        (0..=MAX_INTEREST_KEYS as u32).for_each(|key| subscribe.extend(&key.to_le_bytes()));
+        let subscribe = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Client(0),
+            PartyId::Server(0),
+            PayloadKind::Command,
+            Some(&subscribe),
+        );
+        harness.send_from(PartyId::Client(0), subscribe).await;
+
+        let mut subscribed = data_message(0, PartyId::Server(0), PartyId::AllClients);
+        subscribed.header_options.interest_key = Some(MAX_INTEREST_KEYS as u32 - 1);
+        let mut ignored = subscribed.clone();
+        ignored.header_options.interest_key = Some(MAX_INTEREST_KEYS as u32);
+        harness.send_from(PartyId::Server(0), subscribed.clone()).await;
+        harness.send_from(PartyId::Server(0), ignored).await;
+
+        assert_eq!(harness.take_client_delivered(0, 0).await.0, vec![subscribed]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_max_payload_length_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            max_payload_lengths: vec![(PayloadKind::Data, 1)].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // Two bytes, one more than the limit
+        harness
+            .send_from(PartyId::Client(0), data_message(0, PartyId::Client(0), PartyId::Server(0)))
+            .await;
+
+        assert!(harness.take_server_delivered().await.is_empty());
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Client(0),
+                ErrorCode::PayloadTooLarge,
+                &[PayloadKind::Data.into(), 1, 0, 0, 0]
+            )]
+        );
+
+        // Other payload kinds are not limited
+        let mut request = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        request.payload_kind = PayloadKind::Request;
+        harness.send_from(PartyId::Client(0), request.clone()).await;
+
+        assert_eq!(harness.take_server_delivered().await, vec![request]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_compressed_payload_length_is_as_expected() {
+        let config = GameRoomRouterConfig {
+            max_payload_lengths: vec![(PayloadKind::Data, 64)].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_client(0, 0).await;
+        harness.take_server_delivered().await;
+
+        // Well under the limit compressed, four times over it decompressed
+        let mut compressed = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        compressed.payload = lz4_flex::compress_prepend_size(&[0u8; 256]);
+        compressed.header_options.compression = Some(CompressionCodec::Lz4);
+        assert!(compressed.payload.len() < 64);
+        harness.send_from(PartyId::Client(0), compressed).await;
+
+        assert!(harness.take_server_delivered().await.is_empty());
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Client(0),
+                ErrorCode::PayloadTooLarge,
+                &[PayloadKind::Data.into(), 64, 0, 0, 0]
+            )]
+        );
+
+        // Zstd payloads are decompressed once to be measured, then routed on decompressed
+        let mut compressed = data_message(0, PartyId::Client(0), PartyId::Server(0));
+        compressed.payload = zstd::stream::encode_all(&[0x5A; 48][..], 3).unwrap();
+        compressed.header_options.compression = Some(CompressionCodec::Zstd);
+        harness.send_from(PartyId::Client(0), compressed.clone()).await;
+        compressed.decompress().unwrap();
+
+        assert_eq!(compressed.payload, vec![0x5A; 48]);
+        assert_eq!(harness.take_server_delivered().await, vec![compressed]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_snapshot_control_is_as_expected() {
+        let config = GameRoomRouterConfig { snapshot_max_deltas: Some(0), ..Default::default() };
+        let mut harness = RouterHarness::start(config, &[0]).await;
+        harness.connect_shard_server(1).await;
+        let snapshot = |origin_party_id: PartyId, payload: u8| {
+            let mut message = data_message(0, origin_party_id, PartyId::AllClients);
+            message.payload = vec![payload];
+            message.payload_kind = PayloadKind::Snapshot;
+            message
+        };
+
+        // The shard server does not own the room, its snapshot is dropped
+        harness.send_from(PartyId::Server(0), snapshot(PartyId::Server(0), 0xFF)).await;
+        harness.send_from(PartyId::Server(1), snapshot(PartyId::Server(1), 0xEE)).await;
+        harness.connect_client(0, 0).await;
+
+        assert_eq!(
+            harness.take_client_delivered(0, 0).await.0,
+            vec![snapshot(PartyId::Server(0), 0xFF)]
+        );
+
+        // Keeping no deltas, the first broadcast drops the snapshot
+        harness
+            .send_from(PartyId::Server(0), data_message(0, PartyId::Server(0), PartyId::AllClients))
+            .await;
+        harness.connect_client(0, 1).await;
+
+        assert_eq!(harness.take_client_delivered(0, 1).await.0, vec![]);
+    }
+
+    #[actix_rt::test]
+    async fn test_router_u32_room_ids_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[300]).await;
+        harness.connect_client(300, 0).await;
+        harness.take_server_delivered().await;
+
+        let to_client = data_message(300, PartyId::Server(0), PartyId::Client(0));
+        let to_server = data_message(300, PartyId::Client(0), PartyId::Server(0));
+        harness.send_from(PartyId::Server(0), to_client.clone()).await;
+        harness.send_from(PartyId::Client(0), to_server.clone()).await;
+
+        assert_eq!(harness.take_client_delivered(300, 0).await.0, vec![to_client]);
+        assert_eq!(harness.take_server_delivered().await, vec![to_server]);
+
+        // Lists of more than 256 rooms are merged and removed whole
+        let room_command = |control_code: ControlCode, room_ids: &[u32]| {
+            let mut payload = vec![control_code.into()];
+            room_ids.iter().for_each(|room_id| payload.extend(&room_id.to_le_bytes()));
+
+            MessageStream::new(
+                MessageCode::Special,
+                0,
+                PartyId::Server(0),
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(&payload),
+            )
+        };
+        let room_ids: Vec<u32> = (1000..1300).collect();
+        harness.send_from(PartyId::Server(0), room_command(ControlCode::AddRooms, &room_ids)).await;
+
+        let mut expected_rooms = vec![300];
+        expected_rooms.extend(&room_ids);
+        assert_eq!(harness.available_rooms().await, expected_rooms);
+
+        harness
+            .send_from(PartyId::Server(0), room_command(ControlCode::RemoveRooms, &room_ids[1..]))
+            .await;
+
+        assert_eq!(harness.available_rooms().await, vec![300, 1000]);
+
+        // A list of u8 room IDs is rejected instead of misread
+        let u8_room_list = MessageStream::new(
+            MessageCode::Special,
+            0,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&[1, 2, 3]),
+        );
+        harness.send_from(PartyId::Server(0), u8_room_list).await;
+
+        assert_eq!(harness.available_rooms().await, vec![300, 1000]);
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![MessageStream::new_error(
+                0,
+                PartyId::Server(0),
+                ErrorCode::UndecodablePayload,
+                &[PayloadKind::Info.into()]
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_router_set_log_level_is_as_expected() {
+        let mut harness = RouterHarness::start(Default::default(), &[34]).await;
+        let shard_server = harness.connect_shard_server(1).await;
+        harness.connect_client(34, 0).await;
+        harness.take_server_delivered().await;
+        let admin = harness.watch_admin_events();
+        let set_log_level = |origin_id: PartyId| {
+            MessageStream::new(
+                MessageCode::Special,
+                34,
+                origin_id,
+                PartyId::Server(0),
+                PayloadKind::Command,
+                Some(b"\x70info,game_room=debug"),
+            )
+        };
+
+        // Only the primary server changes the log filters of the process, the logger of the
+        // test process is not initialized so its filters are refused too
+        for origin_id in [PartyId::Client(0), PartyId::Server(1), PartyId::Server(0)] {
+            harness.send_from(origin_id, set_log_level(origin_id)).await;
+        }
+
+        let routing_error = |party_id: PartyId, reason: &str| AdminEvent::RoutingError {
+            room_id: 34,
+            party_id: party_id.get_repr(),
+            reason: reason.into(),
+        };
+        let admin_events: Vec<AdminEvent> = admin
+            .send(TakeAdminEvents)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|event| matches!(event, AdminEvent::RoutingError { room_id: 34, .. }))
+            .collect();
+
+        assert_eq!(
+            admin_events,
+            vec![
+                routing_error(PartyId::Client(0), "SetLogLevel is server only"),
+                routing_error(
+                    PartyId::Server(1),
+                    "SetLogLevel is for the primary or owning server only"
+                ),
+                routing_error(PartyId::Server(0), "Logger is not initialized"),
+            ]
+        );
+
+        // The command is never routed
+        assert_eq!(harness.take_server_delivered().await, vec![]);
+        assert_eq!(shard_server.send(TakeDelivered).await.unwrap().0, vec![]);
+        assert_eq!(harness.take_client_delivered(34, 0).await.0, vec![]);
+    }
+
+    // Lines written by the JSON log layer of a test, shared with the writers it makes
+    #[derive(Clone, Default)]
+    struct LogLines(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogLines {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_router_json_log_lines_is_as_expected() {
+        let log_lines = LogLines::default();
+        let instance_id = Uuid::new_v4();
+        let log_layer = tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonLineFormat::new(instance_id))
+            .with_writer({
+                let log_lines = log_lines.clone();
+                move || log_lines.clone()
+            });
+        // The router runs on the test thread, the log records are bridged as in init_logger
+        let _ = tracing_log::LogTracer::init();
+        let _log_guard = tracing_subscriber::registry().with(log_layer).set_default();
+
+        let mut harness = RouterHarness::start(Default::default(), &[73]).await;
+        harness.connect_client(73, 2).await;
+        harness
+            .send_from(
+                PartyId::Client(2),
+                MessageStream::new(
+                    MessageCode::Special,
+                    73,
+                    PartyId::Client(2),
+                    PartyId::Server(0),
+                    PayloadKind::Command,
+                    Some(&[ControlCode::Pause.into()]),
+                ),
+            )
+            .await;
+
+        let log_text = String::from_utf8(log_lines.0.lock().unwrap().clone()).unwrap();
+        let refused_line = log_text
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| line["message"].as_str().unwrap_or("").contains("is not allowed"))
+            .expect("Expected the refused control command to be logged");
+
+        // The routing span tells which room and party the line is about
+        assert_eq!(refused_line["level"], "WARN");
+        assert_eq!(refused_line["target"], "game_room::ws_handlers::control");
+        assert_eq!(refused_line["instance_id"], instance_id.to_string());
+        assert_eq!(refused_line["room_id"], 73);
+        assert_eq!(refused_line["party_id"], 2);
+        assert_eq!(
+            refused_line["message"],
+            "Party ID 2 is not allowed to send control command Pause"
+        );
+    }
+}
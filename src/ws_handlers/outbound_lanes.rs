@@ -0,0 +1,106 @@
+use crate::proto::{MessagePriority, MessageStream};
+use crate::telemetry::TraceContext;
+use std::collections::VecDeque;
+
+pub(crate) type OutboundMessage = (MessageStream, Option<TraceContext>);
+
+/// Per-connection outbound queues, one lane per `MessagePriority`. Lanes are drained in priority
+/// order so a large Bulk snapshot never delays Critical or Normal traffic queued after it.
+#[derive(Debug, Default)]
+pub(crate) struct OutboundLanes {
+    critical: VecDeque<OutboundMessage>,
+    normal: VecDeque<OutboundMessage>,
+    bulk: VecDeque<OutboundMessage>,
+    pub(crate) is_drain_scheduled: bool,
+}
+
+impl OutboundLanes {
+    pub(crate) fn push(&mut self, message: MessageStream, trace_context: Option<TraceContext>) {
+        match message.priority() {
+            MessagePriority::Critical => self.critical.push_back((message, trace_context)),
+            MessagePriority::Normal => self.normal.push_back((message, trace_context)),
+            MessagePriority::Bulk => self.bulk.push_back((message, trace_context)),
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<OutboundMessage> {
+        self.critical
+            .pop_front()
+            .or_else(|| self.normal.pop_front())
+            .or_else(|| self.bulk.pop_front())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.critical.len() + self.normal.len() + self.bulk.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.critical.is_empty() && self.normal.is_empty() && self.bulk.is_empty()
+    }
+
+    // Pops messages in priority order until the byte budget is spent, at least one is returned
+    pub(crate) fn pop_budgeted(&mut self, byte_budget: usize) -> Vec<OutboundMessage> {
+        let mut result = Vec::new();
+        let mut spent = 0;
+
+        while spent < byte_budget {
+            match self.pop() {
+                None => break,
+                Some(outbound_message) => {
+                    spent += outbound_message.0.raw_length();
+                    result.push(outbound_message);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PartyId, PayloadKind};
+
+    fn sample_message(priority: MessagePriority, payload: &[u8]) -> MessageStream {
+        let mut message = MessageStream::new(
+            MessageCode::Normal,
+            0,
+            PartyId::Server(0),
+            PartyId::Client(0),
+            PayloadKind::Data,
+            Some(payload),
+        );
+        message.header_options.priority = Some(priority);
+
+        message
+    }
+
+    #[test]
+    fn test_outbound_lanes_pop_in_priority_order() {
+        let mut outbound_lanes = OutboundLanes::default();
+        outbound_lanes.push(sample_message(MessagePriority::Bulk, &[0x03]), None);
+        outbound_lanes.push(sample_message(MessagePriority::Normal, &[0x02]), None);
+        outbound_lanes.push(sample_message(MessagePriority::Critical, &[0x01]), None);
+
+        let popped: Vec<u8> = outbound_lanes
+            .pop_budgeted(usize::MAX)
+            .iter()
+            .map(|(message, _)| message.payload[0])
+            .collect();
+
+        assert_eq!(popped, vec![0x01, 0x02, 0x03]);
+        assert_eq!(outbound_lanes.len(), 0);
+        assert!(outbound_lanes.is_empty());
+    }
+
+    #[test]
+    fn test_outbound_lanes_budget_is_honored() {
+        let mut outbound_lanes = OutboundLanes::default();
+        outbound_lanes.push(sample_message(MessagePriority::Bulk, &[0xBB; 100]), None);
+        outbound_lanes.push(sample_message(MessagePriority::Bulk, &[0xBB; 100]), None);
+
+        assert_eq!(outbound_lanes.pop_budgeted(1).len(), 1);
+        assert!(!outbound_lanes.is_empty());
+    }
+}
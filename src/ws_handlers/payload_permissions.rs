@@ -0,0 +1,116 @@
+use super::GameRoomRouterActor;
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{ErrorCode, InfoCode, MessageStream, PartyId, PayloadKind};
+use crate::{anyerror, AnyError, AnyResult};
+use log::warn;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum PartyRole {
+    Client,
+    Server,
+}
+
+impl PartyRole {
+    fn of(party_id: PartyId) -> Option<Self> {
+        match party_id {
+            PartyId::Client(_) => Some(Self::Client),
+            PartyId::Server(_) => Some(Self::Server),
+            _ => None,
+        }
+    }
+}
+
+// Payload kind a role may not send, as <role>:<payload-kind>, e.g. `client:command`
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) struct DeniedPayloadKind(pub(crate) PartyRole, pub(crate) PayloadKind);
+
+impl FromStr for DeniedPayloadKind {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        let mut source_split = source.splitn(2, ':');
+        let role = match source_split.next().map(|role| role.trim().to_lowercase()).as_deref() {
+            Some("client") => PartyRole::Client,
+            Some("server") => PartyRole::Server,
+            _ => return Err(anyerror!("Expected <client|server>:<payload-kind>, got {}", source)),
+        };
+
+        match source_split.next() {
+            Some(payload_kind) => Ok(Self(role, payload_kind.trim().parse()?)),
+            None => Err(anyerror!("Expected <client|server>:<payload-kind>, got {}", source)),
+        }
+    }
+}
+
+impl GameRoomRouterActor {
+    // False when the role of the origin may not send the payload kind. The origin is replied an
+    // error, and the server of the room is told about the clients
+    pub(crate) fn check_payload_permission(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> bool {
+        let is_denied = PartyRole::of(origin_party_id).is_some_and(|role| {
+            self.config
+                .denied_payload_kinds
+                .contains(&DeniedPayloadKind(role, message.payload_kind))
+                || (role == PartyRole::Client
+                    && !self.room_configs.allows(message.room_id, message.payload_kind))
+        });
+
+        if !is_denied {
+            return true;
+        }
+
+        warn!(
+            "Party ID {} sent a {:#?} payload it may not send",
+            origin_party_id.get_repr(),
+            message.payload_kind
+        );
+        Metrics::increment(&METRICS.forbidden_payloads);
+
+        let payload_kind = message.payload_kind.into();
+        self.reply_error(
+            message.room_id,
+            origin_party_id,
+            ErrorCode::PayloadForbidden,
+            &[payload_kind],
+        );
+
+        if origin_party_id.is_single_client_id() {
+            let mut details = origin_party_id.to_le_bytes().to_vec();
+            details.push(payload_kind);
+            let server_party_id = PartyId::Server(self.room_server_id(message.room_id));
+            let forbidden_info = MessageStream::new_info(
+                message.room_id,
+                server_party_id,
+                InfoCode::Forbidden,
+                &details,
+            );
+            self.send_to_server(PartyId::Server(0), forbidden_info);
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denied_payload_kind_from_str_is_as_expected() {
+        assert_eq!(
+            "client:command".parse::<DeniedPayloadKind>().unwrap(),
+            DeniedPayloadKind(PartyRole::Client, PayloadKind::Command)
+        );
+        assert_eq!(
+            "Server: Lockstep".parse::<DeniedPayloadKind>().unwrap(),
+            DeniedPayloadKind(PartyRole::Server, PayloadKind::Lockstep)
+        );
+        assert!("client".parse::<DeniedPayloadKind>().is_err());
+        assert!("relay:data".parse::<DeniedPayloadKind>().is_err());
+        assert!("client:chat".parse::<DeniedPayloadKind>().is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use crate::ws_handlers::{ConnectionPermit, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL, MAILBOX_CAPACITY};
+use actix::clock::Instant;
+use actix::io::{SinkWrite, WriteHandler};
+use actix::{
+    Actor as ActixActor, ActorContext, AsyncContext, Running, StreamHandler as ReceiveHandler,
+};
+use actix_codec::Framed;
+use actix_web_actors::ws::{
+    CloseReason, Message as WsMessage, ProtocolError as WsProtocolError, WebsocketContext,
+};
+use awc::ws::{Codec as WsCodec, Frame};
+use awc::BoxedSocket;
+use futures::stream::{SplitSink, StreamExt};
+use log::{info, warn};
+use tracing::{info_span, Span};
+use uuid::Uuid;
+
+type PeerSink = SplitSink<Framed<BoxedSocket, WsCodec>, WsMessage>;
+
+// Relays the frames of a client joining a room owned by another peer over its own connection to
+// that peer, which routes the client as if it had joined there
+pub(crate) struct PeerProxyActor {
+    client_id: Uuid,
+    owner_url: String,
+    peer_framed: Option<Framed<BoxedSocket, WsCodec>>, // Split once started
+    peer_sink: Option<SinkWrite<WsMessage, PeerSink>>,
+    last_known_activity: Instant,
+    log_span: Span,
+    _connection_permit: Option<ConnectionPermit>, // Held until the actor is dropped
+}
+
+impl PeerProxyActor {
+    pub(crate) fn new(
+        room_id: u32,
+        client_id: Uuid,
+        owner_url: String,
+        peer_framed: Framed<BoxedSocket, WsCodec>,
+    ) -> Self {
+        Self {
+            client_id,
+            owner_url,
+            peer_framed: Some(peer_framed),
+            peer_sink: None,
+            last_known_activity: Instant::now(),
+            // The party ID is only known by the owner
+            log_span: info_span!("connection", room_id, client_id = %client_id),
+            _connection_permit: None,
+        }
+    }
+
+    pub(crate) fn with_connection_permit(mut self, connection_permit: ConnectionPermit) -> Self {
+        self._connection_permit = Some(connection_permit);
+        self
+    }
+
+    // The peer pings the proxy like any client, the proxy pings the client itself
+    fn heartbeat(&self, context: &mut WebsocketContext<Self>) {
+        context.run_interval(HEARTBEAT_INTERVAL, |actor, context| {
+            let _log_span = actor.log_span.clone().entered();
+
+            if Instant::now().duration_since(actor.last_known_activity) > CLIENT_TIMEOUT {
+                info!(
+                    "Proxied client {} kicked because of {:#?} inactivity!",
+                    actor.client_id, CLIENT_TIMEOUT
+                );
+                Self::close_and_stop(context, None);
+            } else {
+                context.ping(b"");
+            }
+        });
+    }
+
+    fn send_to_peer(&mut self, message: WsMessage) {
+        if let Some(peer_sink) = self.peer_sink.as_mut() {
+            let _ = peer_sink.write(message);
+        }
+    }
+
+    fn close_and_stop(context: &mut WebsocketContext<Self>, reason: Option<CloseReason>) {
+        context.close(reason);
+        context.stop();
+    }
+}
+
+impl ActixActor for PeerProxyActor {
+    type Context = WebsocketContext<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+
+        if let Some(peer_framed) = self.peer_framed.take() {
+            let (peer_sink, peer_stream) = peer_framed.split();
+            self.peer_sink = Some(SinkWrite::new(peer_sink, context));
+            context.add_stream(peer_stream);
+        }
+
+        info!("Client {} proxied to {}", self.client_id, self.owner_url);
+        self.heartbeat(context);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if let Some(mut peer_sink) = self.peer_sink.take() {
+            peer_sink.close();
+        }
+
+        Running::Stop
+    }
+}
+
+impl WriteHandler<WsProtocolError> for PeerProxyActor {}
+
+// Client side
+impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for PeerProxyActor {
+    fn handle(
+        &mut self,
+        stream_result: Result<WsMessage, WsProtocolError>,
+        context: &mut Self::Context,
+    ) {
+        let _log_span = self.log_span.clone().entered();
+
+        match stream_result {
+            Ok(WsMessage::Close(reason)) => {
+                self.send_to_peer(WsMessage::Close(reason.clone()));
+                Self::close_and_stop(context, reason);
+            }
+            Ok(WsMessage::Ping(ping_payload)) => {
+                self.last_known_activity = Instant::now();
+                context.pong(&ping_payload);
+            }
+            Ok(WsMessage::Pong(_)) => self.last_known_activity = Instant::now(),
+            Ok(message @ WsMessage::Binary(_)) | Ok(message @ WsMessage::Text(_)) => {
+                self.last_known_activity = Instant::now();
+                self.send_to_peer(message);
+            }
+            Ok(_) => (),
+            Err(_) => Self::close_and_stop(context, None),
+        }
+    }
+}
+
+// Peer side
+impl ReceiveHandler<Result<Frame, WsProtocolError>> for PeerProxyActor {
+    fn handle(
+        &mut self,
+        stream_result: Result<Frame, WsProtocolError>,
+        context: &mut Self::Context,
+    ) {
+        let _log_span = self.log_span.clone().entered();
+
+        match stream_result {
+            Ok(Frame::Binary(binary_payload)) => context.binary(binary_payload),
+            Ok(Frame::Text(text_payload)) => match String::from_utf8(text_payload.to_vec()) {
+                Ok(text_payload) => context.text(text_payload),
+                Err(_) => warn!("Peer {} sent a non UTF-8 text frame", self.owner_url),
+            },
+            Ok(Frame::Ping(ping_payload)) => self.send_to_peer(WsMessage::Pong(ping_payload)),
+            Ok(Frame::Close(reason)) => Self::close_and_stop(context, reason),
+            Ok(_) => (),
+            Err(error) => {
+                warn!("Peer {} connection failed: {}", self.owner_url, error);
+                Self::close_and_stop(context, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,517 @@
+//! RFC 7692 permessage-deflate on top of actix-web-actors, which does not implement any WebSocket
+//! extension. The raw frames are rewritten on both sides of the `WebsocketContext`: inbound
+//! compressed messages are inflated before the actix codec sees them, and outbound data frames
+//! are deflated with the RSV1 bit set. The server never keeps its compression context between
+//! messages, the client may.
+
+use crate::proto::MessageStream;
+use crate::{anyerror, AnyResult};
+use actix::{Actor, Addr as ActorAddress, StreamHandler};
+use actix_web::error::{Error as ActixError, PayloadError};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web_actors::ws::{
+    handshake, start_with_addr as ws_start, Message as WsMessage, ProtocolError as WsProtocolError,
+    WebsocketContext,
+};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use futures::task::{Context as TaskContext, Poll};
+use futures::Stream;
+use std::pin::Pin;
+
+const EXTENSION_NAME: &str = "permessage-deflate";
+const HEADER_EXTENSIONS: &str = "Sec-WebSocket-Extensions";
+const DEFLATE_TRAILER: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+const MIN_COMPRESSED_LENGTH: usize = 64;
+// Same as the default of --max-message-length
+const MAX_INFLATED_LENGTH: usize = MessageStream::MAX_FRAME_LENGTH;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+
+// Handshake and start the actor, with permessage-deflate when enabled and offered by the peer
+pub(crate) fn start_with_addr<A, S>(
+    actor: A,
+    request: &HttpRequest,
+    stream: S,
+    is_deflate_enabled: bool,
+) -> Result<(ActorAddress<A>, HttpResponse), ActixError>
+where
+    A: Actor<Context = WebsocketContext<A>> + StreamHandler<Result<WsMessage, WsProtocolError>>,
+    S: Stream<Item = Result<Bytes, PayloadError>> + 'static,
+{
+    let response_extension = if is_deflate_enabled { negotiate(request) } else { None };
+
+    match response_extension {
+        None => ws_start(actor, request, stream),
+        Some(response_extension) => {
+            let mut response = handshake(request)?;
+            response.header(HEADER_EXTENSIONS, response_extension);
+
+            let (address, outbound) =
+                WebsocketContext::create_with_addr(actor, InflateStream::new(stream));
+
+            Ok((address, response.streaming(DeflateStream::new(outbound))))
+        }
+    }
+}
+
+// Picks the first acceptable offer and returns the response extension header value
+fn negotiate(request: &HttpRequest) -> Option<String> {
+    let offers = request
+        .headers()
+        .get_all(HEADER_EXTENSIONS)
+        .filter_map(|header_value| header_value.to_str().ok())
+        .flat_map(|header_value| header_value.split(','));
+
+    for offer in offers {
+        let mut offer_params = offer.split(';').map(str::trim);
+
+        if offer_params.next() != Some(EXTENSION_NAME) {
+            continue;
+        }
+
+        let mut response_extension = format!("{}; server_no_context_takeover", EXTENSION_NAME);
+        let mut is_acceptable = true;
+
+        for offer_param in offer_params {
+            let param_name = offer_param.split('=').next().unwrap_or_default().trim();
+
+            match param_name {
+                "server_no_context_takeover" | "client_max_window_bits" => (),
+                "client_no_context_takeover" => {
+                    response_extension.push_str("; client_no_context_takeover")
+                }
+                // Only the default 15 bits window can be produced
+                "server_max_window_bits" => {
+                    is_acceptable = offer_param.ends_with("15");
+                }
+                _ => is_acceptable = false,
+            }
+        }
+
+        if is_acceptable {
+            return Some(response_extension);
+        }
+    }
+
+    None
+}
+
+#[derive(Debug)]
+struct RawFrame {
+    is_final: bool,
+    is_compressed: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+impl RawFrame {
+    // Returns the frame and the number of bytes consumed, None when more bytes are needed
+    fn parse(source: &[u8], max_payload_length: usize) -> AnyResult<Option<(Self, usize)>> {
+        if source.len() < 2 {
+            return Ok(None);
+        }
+
+        let is_final = source[0] & 0x80 != 0;
+        let is_compressed = source[0] & 0x40 != 0;
+        let opcode = source[0] & 0x0F;
+        let is_masked = source[1] & 0x80 != 0;
+        let mut offset = 2;
+
+        let payload_length = match source[1] & 0x7F {
+            126 => {
+                if source.len() < offset + 2 {
+                    return Ok(None);
+                }
+
+                let mut u16_bytes = [0u8; 2];
+                u16_bytes.copy_from_slice(&source[offset..offset + 2]);
+                offset += 2;
+                u16::from_be_bytes(u16_bytes) as usize
+            }
+            127 => {
+                if source.len() < offset + 8 {
+                    return Ok(None);
+                }
+
+                let mut u64_bytes = [0u8; 8];
+                u64_bytes.copy_from_slice(&source[offset..offset + 8]);
+                offset += 8;
+                let payload_length = u64::from_be_bytes(u64_bytes);
+
+                if payload_length > max_payload_length as u64 {
+                    return Err(anyerror!("Frame of {} bytes is too large", payload_length));
+                }
+
+                payload_length as usize
+            }
+            payload_length => payload_length as usize,
+        };
+
+        let mut mask = [0u8; 4];
+
+        if is_masked {
+            if source.len() < offset + 4 {
+                return Ok(None);
+            }
+
+            mask.copy_from_slice(&source[offset..offset + 4]);
+            offset += 4;
+        }
+
+        if source.len() < offset + payload_length {
+            return Ok(None);
+        }
+
+        let mut payload = source[offset..offset + payload_length].to_vec();
+
+        if is_masked {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        Ok(Some((Self { is_final, is_compressed, opcode, payload }, offset + payload_length)))
+    }
+
+    // Frames fed back to the actix codec of a server must be masked, a zero mask keeps it cheap
+    fn write(&self, destination: &mut BytesMut, is_masked: bool) {
+        let mut first = self.opcode;
+
+        if self.is_final {
+            first |= 0x80;
+        }
+
+        if self.is_compressed {
+            first |= 0x40;
+        }
+
+        let mask_bit = if is_masked { 0x80 } else { 0x00 };
+        let payload_length = self.payload.len();
+        destination.extend_from_slice(&[first]);
+
+        if payload_length < 126 {
+            destination.extend_from_slice(&[mask_bit | payload_length as u8]);
+        } else if payload_length <= u16::MAX as usize {
+            destination.extend_from_slice(&[mask_bit | 126]);
+            destination.extend_from_slice(&(payload_length as u16).to_be_bytes());
+        } else {
+            destination.extend_from_slice(&[mask_bit | 127]);
+            destination.extend_from_slice(&(payload_length as u64).to_be_bytes());
+        }
+
+        if is_masked {
+            destination.extend_from_slice(&[0u8; 4]);
+        }
+
+        destination.extend_from_slice(&self.payload);
+    }
+
+    fn is_data(&self) -> bool {
+        matches!(self.opcode, OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY)
+    }
+}
+
+fn inflate(decompress: &mut Decompress, payload: &[u8]) -> AnyResult<Vec<u8>> {
+    let mut source = payload.to_vec();
+    source.extend_from_slice(&DEFLATE_TRAILER);
+    let mut result = Vec::with_capacity(source.len() * 2);
+
+    loop {
+        if result.len() > MAX_INFLATED_LENGTH {
+            return Err(anyerror!("Inflated message exceeds {} bytes", MAX_INFLATED_LENGTH));
+        }
+
+        result.reserve(source.len().max(1024));
+        let total_in = decompress.total_in();
+        let status = decompress.decompress_vec(&source, &mut result, FlushDecompress::Sync)?;
+        source.drain(..(decompress.total_in() - total_in) as usize);
+
+        // A sync flush is complete once it had room to spare
+        if status == Status::StreamEnd || (source.is_empty() && result.len() < result.capacity()) {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+fn deflate(compress: &mut Compress, payload: &[u8]) -> AnyResult<Vec<u8>> {
+    compress.reset();
+    let mut result = Vec::with_capacity(payload.len() + 64);
+
+    loop {
+        let consumed = compress.total_in() as usize;
+        compress.compress_vec(&payload[consumed..], &mut result, FlushCompress::Sync)?;
+
+        // A sync flush is complete once it had room to spare
+        if compress.total_in() as usize == payload.len() && result.len() < result.capacity() {
+            break;
+        }
+
+        result.reserve(payload.len().max(64));
+    }
+
+    if result.ends_with(&DEFLATE_TRAILER) {
+        result.truncate(result.len() - DEFLATE_TRAILER.len());
+    }
+
+    Ok(result)
+}
+
+struct InflateStream<S> {
+    inner: Pin<Box<S>>,
+    buffer: BytesMut,
+    decompress: Decompress,
+    compressed_message: Option<(u8, Vec<u8>)>, // (Opcode, Fragments)
+}
+
+impl<S> InflateStream<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            buffer: BytesMut::new(),
+            decompress: Decompress::new(false),
+            compressed_message: None,
+        }
+    }
+
+    // Turns buffered frames into frames the actix codec understands, inflating when needed
+    fn rewrite_buffered_frames(&mut self) -> AnyResult<BytesMut> {
+        let mut result = BytesMut::new();
+
+        while let Some((mut frame, consumed)) = RawFrame::parse(&self.buffer, MAX_INFLATED_LENGTH)?
+        {
+            let _ = self.buffer.split_to(consumed);
+
+            if frame.is_data() && (frame.is_compressed || self.compressed_message.is_some()) {
+                let (opcode, fragments) =
+                    self.compressed_message.get_or_insert_with(|| (frame.opcode, Vec::new()));
+                fragments.extend_from_slice(&frame.payload);
+
+                if fragments.len() > MAX_INFLATED_LENGTH {
+                    return Err(anyerror!(
+                        "Compressed message exceeds {} bytes",
+                        MAX_INFLATED_LENGTH
+                    ));
+                }
+
+                if !frame.is_final {
+                    continue;
+                }
+
+                frame.opcode = *opcode;
+                frame.payload = inflate(&mut self.decompress, fragments)?;
+                frame.is_compressed = false;
+                self.compressed_message = None;
+            }
+
+            frame.write(&mut result, true);
+        }
+
+        Ok(result)
+    }
+}
+
+impl<S> Stream for InflateStream<S>
+where
+    S: Stream<Item = Result<Bytes, PayloadError>>,
+{
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        task_context: &mut TaskContext,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.as_mut().poll_next(task_context) {
+                Poll::Ready(Some(Ok(bytes))) => {
+                    self.buffer.extend_from_slice(&bytes);
+
+                    match self.rewrite_buffered_frames() {
+                        Err(error) => {
+                            return Poll::Ready(Some(Err(PayloadError::Io(std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                error.to_string(),
+                            )))))
+                        }
+                        Ok(rewritten) if rewritten.is_empty() => continue,
+                        Ok(rewritten) => return Poll::Ready(Some(Ok(rewritten.freeze()))),
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+struct DeflateStream<S> {
+    inner: Pin<Box<S>>,
+    buffer: BytesMut,
+    compress: Compress,
+}
+
+impl<S> DeflateStream<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            buffer: BytesMut::new(),
+            compress: Compress::new(Compression::default(), false),
+        }
+    }
+
+    // Frames of the router are trusted, they are deflated whatever their length
+    fn rewrite_buffered_frames(&mut self) -> AnyResult<BytesMut> {
+        let mut result = BytesMut::new();
+
+        while let Some((mut frame, consumed)) = RawFrame::parse(&self.buffer, usize::MAX)? {
+            let _ = self.buffer.split_to(consumed);
+
+            // The actix context never fragments, so only whole data messages are compressed
+            if frame.is_data()
+                && frame.is_final
+                && frame.opcode != OPCODE_CONTINUATION
+                && frame.payload.len() >= MIN_COMPRESSED_LENGTH
+            {
+                frame.payload = deflate(&mut self.compress, &frame.payload)?;
+                frame.is_compressed = true;
+            }
+
+            frame.write(&mut result, false);
+        }
+
+        Ok(result)
+    }
+}
+
+impl<S> Stream for DeflateStream<S>
+where
+    S: Stream<Item = Result<Bytes, ActixError>>,
+{
+    type Item = Result<Bytes, ActixError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        task_context: &mut TaskContext,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.as_mut().poll_next(task_context) {
+                Poll::Ready(Some(Ok(bytes))) => {
+                    self.buffer.extend_from_slice(&bytes);
+
+                    match self.rewrite_buffered_frames() {
+                        Err(error) => {
+                            return Poll::Ready(Some(Err(
+                                actix_web::error::ErrorInternalServerError(error),
+                            )))
+                        }
+                        Ok(rewritten) if rewritten.is_empty() => continue,
+                        Ok(rewritten) => return Poll::Ready(Some(Ok(rewritten.freeze()))),
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PartyId, PayloadKind};
+    use actix::ActorContext;
+    use actix_web::test::TestRequest;
+    use futures::StreamExt;
+
+    // Sends one frame with the largest payload then stops
+    struct LargestFrameActor;
+
+    impl Actor for LargestFrameActor {
+        type Context = WebsocketContext<Self>;
+
+        fn started(&mut self, context: &mut Self::Context) {
+            let payload = vec![0x5A; MessageStream::MAX_PAYLOAD_LENGTH];
+            let message_stream = MessageStream::new(
+                MessageCode::Normal,
+                1,
+                PartyId::Server(0),
+                PartyId::Client(1),
+                PayloadKind::Data,
+                Some(&payload),
+            );
+            context.binary(message_stream.into_raw());
+            context.stop();
+        }
+    }
+
+    impl StreamHandler<Result<WsMessage, WsProtocolError>> for LargestFrameActor {
+        fn handle(&mut self, _: Result<WsMessage, WsProtocolError>, _: &mut Self::Context) {}
+    }
+
+    #[test]
+    fn test_deflate_then_inflate_is_as_expected() {
+        let payload = b"state state state state state state state state state state".repeat(20);
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut decompress = Decompress::new(false);
+
+        for _ in 0..2 {
+            let deflated = deflate(&mut compress, &payload).unwrap();
+
+            assert!(deflated.len() < payload.len());
+            assert_eq!(inflate(&mut decompress, &deflated).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_raw_frame_round_trip_is_as_expected() {
+        let frame = RawFrame {
+            is_final: true,
+            is_compressed: true,
+            opcode: OPCODE_BINARY,
+            payload: vec![0xAB; 300],
+        };
+        let mut raw = BytesMut::new();
+        frame.write(&mut raw, true);
+        let (parsed, consumed) = RawFrame::parse(&raw, MAX_INFLATED_LENGTH).unwrap().unwrap();
+
+        assert_eq!(consumed, raw.len());
+        assert!(parsed.is_final && parsed.is_compressed);
+        assert_eq!(parsed.opcode, OPCODE_BINARY);
+        assert_eq!(parsed.payload, frame.payload);
+    }
+
+    #[actix_rt::test]
+    async fn test_deflate_largest_frame_is_as_expected() {
+        let request = TestRequest::get()
+            .header("Upgrade", "websocket")
+            .header("Connection", "Upgrade")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header(HEADER_EXTENSIONS, EXTENSION_NAME)
+            .to_http_request();
+        let inbound = futures::stream::pending::<Result<Bytes, PayloadError>>();
+        let (_, mut response) =
+            start_with_addr(LargestFrameActor, &request, inbound, true).unwrap();
+        let mut outbound = BytesMut::new();
+        let mut body = response.take_body();
+
+        while let Some(bytes) = body.next().await {
+            outbound.extend_from_slice(&bytes.unwrap());
+        }
+
+        let (frame, _) = RawFrame::parse(&outbound, usize::MAX).unwrap().unwrap();
+        let mut source = frame.payload.clone();
+        source.extend_from_slice(&DEFLATE_TRAILER);
+        let mut inflated = Vec::with_capacity(MAX_INFLATED_LENGTH * 2);
+        Decompress::new(false)
+            .decompress_vec(&source, &mut inflated, FlushDecompress::Sync)
+            .unwrap();
+        let message_stream = MessageStream::from_raw(&inflated).unwrap();
+
+        assert!(frame.is_final && frame.is_compressed);
+        assert_eq!(frame.opcode, OPCODE_BINARY);
+        assert_eq!(message_stream.payload, vec![0x5A; MessageStream::MAX_PAYLOAD_LENGTH]);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::proto::FrameFormat;
+use crate::ws_handlers::{ClientActor, ClientTransport, TcpFrameCodec};
+use crate::{anyerror, AnyResult};
+use actix::clock::{Duration, Instant};
+use actix::{Addr as ActorAddress, Context, Handler as MessageHandler, Message, MessageResult};
+use actix_codec::{Decoder, Encoder};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web_actors::ws::{CloseReason, Message as WsMessage};
+use futures::channel::oneshot;
+use log::warn;
+use serde_json::{from_slice as from_json_slice, Value as JsonValue};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+// Frames kept for a client not polling them, the oldest ones are dropped beyond
+const POLL_QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Clone, Debug)]
+pub(crate) struct PollSession {
+    pub(crate) address: ActorAddress<ClientActor<PollTransport>>,
+    pub(crate) frame_format: FrameFormat,
+}
+
+// Session ID -> Long-polling client connection
+pub(crate) type PollSessions = Arc<Mutex<BTreeMap<Uuid, PollSession>>>;
+
+// Answered with the queued frames right away, or as soon as one is queued
+#[derive(Message)]
+#[rtype(result = "oneshot::Receiver<Vec<WsMessage>>")]
+pub(crate) struct TakePolled;
+
+// Body of a send request, answered with the number of frames routed
+#[derive(Message)]
+#[rtype(result = "AnyResult<usize>")]
+pub(crate) struct PollSent(pub(crate) Bytes);
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub(crate) struct ClosePoll;
+
+// HTTP clients blocked from websockets, frames wait in the queue until the client polls them
+#[derive(Debug)]
+pub(crate) struct PollTransport {
+    session_id: Uuid,
+    sessions: PollSessions,
+    queued_frames: VecDeque<(Instant, WsMessage)>, // Queued at, frame
+    waiting_poll: Option<oneshot::Sender<Vec<WsMessage>>>,
+}
+
+impl PollTransport {
+    pub(crate) fn new(session_id: Uuid, sessions: PollSessions) -> Self {
+        Self { session_id, sessions, queued_frames: VecDeque::new(), waiting_poll: None }
+    }
+
+    // A poll that timed out on the client side leaves its frames queued for the next one
+    fn flush(&mut self) {
+        if self.queued_frames.is_empty() {
+            return;
+        }
+
+        if let Some(waiting_poll) = self.waiting_poll.take() {
+            let (queued_at, frames): (Vec<Instant>, Vec<WsMessage>) =
+                self.queued_frames.drain(..).unzip();
+
+            if let Err(frames) = waiting_poll.send(frames) {
+                self.queued_frames = queued_at.into_iter().zip(frames).collect();
+            }
+        }
+    }
+}
+
+impl ClientTransport for PollTransport {
+    type Context = Context<ClientActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, _: &mut Self::Context, frame: WsMessage) {
+        if self.queued_frames.len() >= POLL_QUEUE_CAPACITY {
+            self.queued_frames.pop_front();
+            warn!("Poll session {} dropped its oldest frame", self.session_id);
+        }
+
+        self.queued_frames.push_back((Instant::now(), frame));
+        self.flush();
+    }
+
+    // There is nothing to ping, the waiting poll keeps the client alive instead
+    fn ping(&mut self, _: &mut Self::Context) {}
+
+    fn close(&mut self, _: &mut Self::Context, _: Option<CloseReason>) {
+        self.flush();
+        self.waiting_poll = None;
+    }
+
+    fn is_client_waiting(&self) -> bool {
+        self.waiting_poll.as_ref().map(|waiting_poll| !waiting_poll.is_canceled()).unwrap_or(false)
+    }
+
+    // Frames only wait in the queue while the client is not polling
+    fn outbound_lag(&self) -> Option<Duration> {
+        self.queued_frames.front().map(|(queued_at, _)| Instant::now().duration_since(*queued_at))
+    }
+}
+
+impl Drop for PollTransport {
+    fn drop(&mut self) {
+        if let Ok(mut write_guard) = self.sessions.lock() {
+            write_guard.remove(&self.session_id);
+        }
+    }
+}
+
+// JSON clients poll a JSON array of envelopes, the others the frames prefixed with their u32
+// length (LE) like over TCP
+pub(crate) fn encode_polled(frame_format: FrameFormat, frames: Vec<WsMessage>) -> Bytes {
+    let mut body = BytesMut::new();
+
+    if frame_format == FrameFormat::Json {
+        let envelopes: Vec<String> = frames
+            .into_iter()
+            .filter_map(|frame| match frame {
+                WsMessage::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect();
+        body.extend_from_slice(format!("[{}]", envelopes.join(",")).as_bytes());
+    } else {
+        for frame in frames {
+            if let WsMessage::Binary(binary) = frame {
+                let _ = TcpFrameCodec.encode(binary, &mut body);
+            }
+        }
+    }
+
+    body.freeze()
+}
+
+impl MessageHandler<TakePolled> for ClientActor<PollTransport> {
+    type Result = MessageResult<TakePolled>;
+
+    // A newer poll replaces the waiting one, which is answered empty
+    fn handle(&mut self, _: TakePolled, _: &mut Self::Context) -> Self::Result {
+        let (waiting_poll, polled_frames) = oneshot::channel();
+        self.update_last_known_activity();
+        self.transport.waiting_poll = Some(waiting_poll);
+        self.transport.flush();
+
+        MessageResult(polled_frames)
+    }
+}
+
+impl MessageHandler<PollSent> for ClientActor<PollTransport> {
+    type Result = AnyResult<usize>;
+
+    fn handle(&mut self, message: PollSent, context: &mut Self::Context) -> Self::Result {
+        let _log_span = self.log_span.clone().entered();
+        self.update_last_known_activity();
+
+        if self.frame_format == FrameFormat::Json {
+            let envelopes: Vec<JsonValue> = from_json_slice(&message.0)?;
+
+            for envelope in envelopes.iter() {
+                self.handle_inbound_text(&envelope.to_string(), context);
+            }
+
+            return Ok(envelopes.len());
+        }
+
+        let mut body = BytesMut::from(&message.0[..]);
+        let mut frame_count = 0;
+
+        while let Some(frame) = TcpFrameCodec.decode(&mut body)? {
+            self.handle_inbound_binary(&frame, context);
+            frame_count += 1;
+        }
+
+        if !body.is_empty() {
+            return Err(anyerror!("Truncated frame after {} frames", frame_count));
+        }
+
+        Ok(frame_count)
+    }
+}
+
+impl MessageHandler<ClosePoll> for ClientActor<PollTransport> {
+    type Result = ();
+
+    fn handle(&mut self, _: ClosePoll, context: &mut Self::Context) {
+        self.close_and_disconnect(context, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{JsonEnvelope, MessageCode, MessageStream, PartyId, PayloadKind};
+    use crate::ws_handlers::test_harness::RouterHarness;
+    use crate::ws_handlers::SigningKey;
+    use actix::Actor as ActixActor;
+
+    #[test]
+    fn test_encode_polled_is_as_expected() {
+        let json_frames =
+            vec![WsMessage::Text("{\"a\":1}".into()), WsMessage::Text("{\"b\":2}".into())];
+
+        assert_eq!(&encode_polled(FrameFormat::Json, json_frames)[..], b"[{\"a\":1},{\"b\":2}]");
+        assert_eq!(&encode_polled(FrameFormat::Json, Vec::new())[..], b"[]");
+
+        let binary_frames = vec![
+            WsMessage::Binary(Bytes::from_static(&[0xAA, 0xBB])),
+            WsMessage::Binary(Bytes::from_static(&[0xCC])),
+        ];
+
+        assert_eq!(
+            &encode_polled(FrameFormat::Binary, binary_frames)[..],
+            &[2, 0, 0, 0, 0xAA, 0xBB, 1, 0, 0, 0, 0xCC][..]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_poll_sent_membership_is_as_expected() {
+        let harness = RouterHarness::start(Default::default(), &[0, 1]).await;
+        harness.take_server_delivered().await;
+        let client_address = ClientActor::new(
+            0,
+            PartyId::Client(3),
+            Uuid::new_v4(),
+            harness.router.clone(),
+            Vec::new(),
+            FrameFormat::Binary,
+            PollTransport::new(Uuid::new_v4(), PollSessions::default()),
+        )
+        .start();
+        let poll_sent = |room_id: u32| {
+            let message_stream = MessageStream::new(
+                MessageCode::Normal,
+                room_id,
+                PartyId::Client(7),
+                PartyId::Server(0),
+                PayloadKind::Data,
+                Some(&[0x01]),
+            );
+            let mut body = BytesMut::new();
+            TcpFrameCodec.encode(message_stream.clone().into_raw().into(), &mut body).unwrap();
+            (PollSent(body.freeze()), message_stream)
+        };
+
+        // The origin the frame carries is replaced by the party of the client in its room
+        let (joined_room_sent, joined_room_message) = poll_sent(0);
+        client_address.send(joined_room_sent).await.unwrap().unwrap();
+        harness.available_rooms().await;
+
+        assert_eq!(
+            harness.take_server_delivered().await,
+            vec![MessageStream { origin_id: PartyId::Client(3), ..joined_room_message }]
+        );
+
+        // A room never joined closes the connection
+        client_address.send(poll_sent(1).0).await.unwrap().unwrap();
+        harness.available_rooms().await;
+
+        assert!(!client_address.connected());
+        assert_eq!(harness.take_server_delivered().await, vec![]);
+    }
+
+    #[actix_rt::test]
+    async fn test_poll_signed_frames_is_as_expected() {
+        let harness = RouterHarness::start(Default::default(), &[0]).await;
+        let signing_key = SigningKey::derive("token");
+        let signing_client = |frame_format: FrameFormat| {
+            ClientActor::new(
+                0,
+                PartyId::Client(3),
+                Uuid::new_v4(),
+                harness.router.clone(),
+                Vec::new(),
+                frame_format,
+                PollTransport::new(Uuid::new_v4(), PollSessions::default()),
+            )
+            .with_signing_key(Some(signing_key))
+        };
+        // The signature of the origin is replaced by one of the receiving client
+        let mut delivered = MessageStream::new(
+            MessageCode::Normal,
+            0,
+            PartyId::Server(0),
+            PartyId::Client(3),
+            PayloadKind::Data,
+            Some(&[0x01, 0x02]),
+        );
+        SigningKey::derive("server token").sign(&mut delivered);
+
+        let binary_frame =
+            match signing_client(FrameFormat::Binary).encode_outbound(delivered.clone()) {
+                Ok(WsMessage::Binary(frame)) => MessageStream::from_raw(&frame).unwrap(),
+                other => panic!("Expected a binary frame, got {:?}", other),
+            };
+        let json_frame = match signing_client(FrameFormat::Json).encode_outbound(delivered.clone())
+        {
+            Ok(WsMessage::Text(frame)) => JsonEnvelope::from_text(&frame).unwrap(),
+            other => panic!("Expected a text frame, got {:?}", other),
+        };
+
+        assert!(signing_key.verify(&binary_frame));
+        assert!(signing_key.verify(&json_frame));
+        assert_eq!(binary_frame.payload, delivered.payload);
+
+        // Clients joined without a token get the frame as routed
+        let unsigned_client = ClientActor::new(
+            0,
+            PartyId::Client(4),
+            Uuid::new_v4(),
+            harness.router.clone(),
+            Vec::new(),
+            FrameFormat::Binary,
+            PollTransport::new(Uuid::new_v4(), PollSessions::default()),
+        );
+
+        assert_eq!(
+            unsigned_client.encode_outbound(delivered.clone()).unwrap(),
+            WsMessage::Binary(delivered.into_raw().into())
+        );
+    }
+}
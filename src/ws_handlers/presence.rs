@@ -0,0 +1,53 @@
+use super::admin_commands::display_metadata;
+use super::GameRoomRouterActor;
+use actix::{Handler as MessageHandler, Message, MessageResult};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct RoomMember {
+    pub(crate) party_id: u32,
+    pub(crate) client_id: Uuid,
+    pub(crate) metadata: Option<String>,
+    pub(crate) joined_at_millis: u64, // Unix time the client joined this room
+    pub(crate) last_active_at_millis: u64, // Unix time of the last frame the client sent the room
+}
+
+// Current members of a room, replies None for a room neither available nor with clients
+#[derive(Debug, Message)]
+#[rtype(result = "Option<Vec<RoomMember>>")]
+pub(crate) struct GetPresence(pub(crate) u32);
+
+impl GameRoomRouterActor {
+    pub(crate) fn room_presence(&self, room_id: u32) -> Option<Vec<RoomMember>> {
+        let is_available = self.is_room_available(room_id);
+        let room_clients = match self.game_rooms.get(&room_id) {
+            Some(room_clients) => room_clients,
+            None if is_available => return Some(Vec::new()),
+            None => return None,
+        };
+
+        let room_members = room_clients
+            .iter()
+            .map(|(client_party_id, room_client)| RoomMember {
+                party_id: *client_party_id,
+                client_id: room_client.client_id,
+                metadata: Some(&room_client.metadata)
+                    .filter(|metadata| !metadata.is_empty())
+                    .map(|metadata| display_metadata(metadata)),
+                joined_at_millis: room_client.joined_at_millis,
+                last_active_at_millis: room_client.last_active_at_millis,
+            })
+            .collect();
+
+        Some(room_members)
+    }
+}
+
+impl MessageHandler<GetPresence> for GameRoomRouterActor {
+    type Result = MessageResult<GetPresence>;
+
+    fn handle(&mut self, message: GetPresence, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.room_presence(message.0))
+    }
+}
@@ -0,0 +1,98 @@
+use crate::ws_handlers::{ClientActor, ClientTransport};
+use actix::{Context, StreamHandler as ReceiveHandler};
+use actix_web::web::Bytes;
+use actix_web_actors::ws::{CloseReason, Message as WsMessage};
+use futures::channel::mpsc::UnboundedSender;
+use log::info;
+use std::io::Result as IOResult;
+
+// Written by the session task of the WebTransport listener, in the order the actor sent them
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum QuicOutbound {
+    Frame(Bytes),    // On the stream with its u32 length (LE), an empty frame is a heartbeat
+    Datagram(Bytes), // On the stream instead when larger than the path allows
+    Close(String),
+}
+
+// Read by the session task of the WebTransport listener
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum QuicInbound {
+    Frame(Bytes),
+    Datagram(Bytes),
+}
+
+// WebTransport clients get their Unreliable frames as datagrams, every other frame on their stream
+pub(crate) struct QuicTransport {
+    outbound: UnboundedSender<QuicOutbound>,
+}
+
+impl QuicTransport {
+    pub(crate) fn new(outbound: UnboundedSender<QuicOutbound>) -> Self {
+        Self { outbound }
+    }
+}
+
+impl ClientTransport for QuicTransport {
+    type Context = Context<ClientActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, _: &mut Self::Context, frame: WsMessage) {
+        let frame = match frame {
+            WsMessage::Binary(binary) => binary,
+            WsMessage::Text(text) => text.into(),
+            _ => return,
+        };
+
+        let _ = self.outbound.unbounded_send(QuicOutbound::Frame(frame));
+    }
+
+    // JSON envelopes stay on the stream, datagrams only carry binary frames
+    fn send_unreliable(&mut self, context: &mut Self::Context, frame: WsMessage) {
+        match frame {
+            WsMessage::Binary(binary) => {
+                let _ = self.outbound.unbounded_send(QuicOutbound::Datagram(binary));
+            }
+            frame => self.send(context, frame),
+        }
+    }
+
+    fn ping(&mut self, _: &mut Self::Context) {
+        let _ = self.outbound.unbounded_send(QuicOutbound::Frame(Bytes::new()));
+    }
+
+    // The connection is closed with the reason, after the frames already sent
+    fn close(&mut self, _: &mut Self::Context, reason: Option<CloseReason>) {
+        let description = reason.and_then(|reason| reason.description).unwrap_or_default();
+
+        if !description.is_empty() {
+            info!("WebTransport client closed: {}", description);
+        }
+
+        let _ = self.outbound.unbounded_send(QuicOutbound::Close(description));
+    }
+}
+
+// Empty frames only keep the connection alive, they are never answered
+impl ReceiveHandler<IOResult<QuicInbound>> for ClientActor<QuicTransport> {
+    fn handle(&mut self, stream_result: IOResult<QuicInbound>, context: &mut Self::Context) {
+        let _log_span = self.log_span.clone().entered();
+
+        match stream_result {
+            Ok(QuicInbound::Frame(frame)) if frame.is_empty() => self.update_last_known_activity(),
+            Ok(QuicInbound::Frame(frame)) => self.handle_inbound_binary(&frame, context),
+            Ok(QuicInbound::Datagram(datagram)) => self.handle_inbound_datagram(&datagram, context),
+            Err(error) => {
+                info!("WebTransport client dropped: {}", error);
+                self.close_and_disconnect(context, None);
+            }
+        }
+    }
+
+    // The client closed its side
+    fn finished(&mut self, context: &mut Self::Context) {
+        self.close_and_disconnect(context, None);
+    }
+}
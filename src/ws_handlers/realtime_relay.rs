@@ -0,0 +1,98 @@
+use super::{GameRoomRouterActor, InterActorMessage, PartyRecipient};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{MessageReliability, MessageStream, PartyId};
+use crate::telemetry::TraceContext;
+use actix::prelude::SendError;
+
+impl GameRoomRouterActor {
+    pub(crate) fn is_realtime(&self, message: &MessageStream) -> bool {
+        message.reliability() == MessageReliability::Realtime
+            && self.room_configs.is_realtime(message.room_id)
+    }
+
+    // Realtime frames of the rooms in realtime mode are relayed at once, past the paused room
+    // holds, the lockstep, the room sequences, the snapshot deltas and the outbound batches, and
+    // are dropped for the destinations whose mailbox is full. Elsewhere they are routed as
+    // Unreliable frames. The frame is handed back when left to the normal routing
+    pub(crate) fn relay_realtime(
+        &mut self,
+        origin_party_id: PartyId,
+        mut message: MessageStream,
+    ) -> Option<MessageStream> {
+        if message.reliability() != MessageReliability::Realtime {
+            return Some(message);
+        }
+
+        if !self.room_configs.is_realtime(message.room_id) {
+            message.header_options.reliability = Some(MessageReliability::Unreliable);
+            return Some(message);
+        }
+
+        for recipient in self.realtime_recipients(origin_party_id, &message) {
+            let new_message = InterActorMessage::NewMessage(
+                origin_party_id,
+                message.clone(),
+                self.route_trace.map(TraceContext::stamp),
+            );
+
+            if let Err(SendError::Full(_)) = recipient.try_send(new_message) {
+                Metrics::increment(&METRICS.dropped_realtime);
+            }
+        }
+
+        None
+    }
+
+    // Same parties as the normal routing would reach, the echo included
+    fn realtime_recipients(
+        &self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> Vec<PartyRecipient> {
+        let room_id = message.room_id;
+        let destination_party_id = message.destination_id;
+        let interest_key = message.header_options.interest_key;
+        let server_party_id = PartyId::Server(self.room_server_id(room_id));
+        let mut party_ids: Vec<PartyId> = match destination_party_id {
+            PartyId::Client(_) => vec![destination_party_id],
+            PartyId::Server(_) | PartyId::AllServers | PartyId::AllServersWithEcho => {
+                vec![server_party_id]
+            }
+            PartyId::AllClients | PartyId::AllClientsWithEcho => self
+                .game_rooms
+                .get(&room_id)
+                .into_iter()
+                .flat_map(|room_clients| room_clients.keys().copied())
+                .filter(|client_party_id| {
+                    interest_key.is_none_or(|interest_key| {
+                        self.interest_subscriptions
+                            .get(&(room_id, *client_party_id))
+                            .is_some_and(|subscriptions| subscriptions.contains(&interest_key))
+                    })
+                })
+                .map(PartyId::Client)
+                .collect(),
+        };
+
+        if destination_party_id.is_echo_broadcast() {
+            if !party_ids.contains(&origin_party_id) {
+                party_ids.push(origin_party_id);
+            }
+        } else if !matches!(destination_party_id, PartyId::Client(_) | PartyId::Server(_)) {
+            party_ids.retain(|party_id| *party_id != origin_party_id);
+        }
+
+        party_ids
+            .into_iter()
+            .filter_map(|party_id| match party_id {
+                PartyId::Client(client_party_id) => self
+                    .game_rooms
+                    .get(&room_id)?
+                    .get(&client_party_id)
+                    .map(|room_client| room_client.address.clone()),
+                PartyId::Server(server_id) => self.server_address(server_id).cloned(),
+                _ => None,
+            })
+            .collect()
+    }
+}
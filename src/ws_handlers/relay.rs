@@ -0,0 +1,132 @@
+use super::GameRoomRouterActor;
+use crate::proto::{MessageStream, RelayPayload, LOBBY_ROOM_ID};
+use actix::{Handler as MessageHandler, Message, Recipient};
+use log::info;
+
+// Payload for one peer, numbered and kept by its relay until the peer acknowledges it
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub(crate) struct RelayOut(pub(crate) RelayPayload);
+
+// Sent by the relay to a peer every time its connection is established
+#[derive(Message)]
+#[rtype(result = "()")]
+pub(crate) struct RelayConnected(pub(crate) String, pub(crate) Recipient<RelayOut>); // Peer URL
+
+// Payload received from a peer, duplicates were already dropped
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub(crate) struct Relayed(pub(crate) String, pub(crate) RelayPayload); // Peer URL
+
+// The peer came back with a new epoch, the members it announced before are gone
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub(crate) struct RelayReset(pub(crate) String); // Peer URL
+
+impl GameRoomRouterActor {
+    // Local joins and leaves go to every peer, the lobby is left out
+    pub(crate) fn relay_membership(&self, payload: RelayPayload) {
+        let room_id = match payload {
+            RelayPayload::MemberJoined(room_id, _, _) | RelayPayload::MemberLeft(room_id, _) => {
+                room_id
+            }
+            _ => return,
+        };
+
+        if room_id == LOBBY_ROOM_ID {
+            return;
+        }
+
+        for relay_address in self.relay_peers.values() {
+            let _ = relay_address.do_send(RelayOut(payload.clone()));
+        }
+    }
+
+    // Only the peers with members in the room are sent the broadcast
+    pub(crate) fn relay_broadcast(&self, room_id: u32, message: &MessageStream) {
+        let remote_members = match self.remote_members.get(&room_id) {
+            Some(remote_members) => remote_members,
+            None => return,
+        };
+        let mut peer_urls: Vec<&String> =
+            remote_members.iter().map(|(peer_url, _)| peer_url).collect();
+        peer_urls.dedup();
+
+        for peer_url in peer_urls {
+            if let Some(relay_address) = self.relay_peers.get(peer_url) {
+                let _ = relay_address.do_send(RelayOut(RelayPayload::Message(message.clone())));
+            }
+        }
+    }
+}
+
+impl MessageHandler<RelayConnected> for GameRoomRouterActor {
+    type Result = ();
+
+    // The peer may have restarted since, it is told every local member again
+    fn handle(&mut self, message: RelayConnected, _: &mut Self::Context) {
+        let RelayConnected(peer_url, relay_address) = message;
+
+        for (room_id, room_clients) in self.game_rooms.iter() {
+            if *room_id == LOBBY_ROOM_ID {
+                continue;
+            }
+
+            for (client_party_id, room_client) in room_clients.iter() {
+                let _ = relay_address.do_send(RelayOut(RelayPayload::MemberJoined(
+                    *room_id,
+                    *client_party_id,
+                    *room_client.client_id.as_bytes(),
+                )));
+            }
+        }
+
+        info!("Relay to peer {} connected", peer_url);
+        self.relay_peers.insert(peer_url, relay_address);
+    }
+}
+
+impl MessageHandler<Relayed> for GameRoomRouterActor {
+    type Result = ();
+
+    fn handle(&mut self, message: Relayed, _: &mut Self::Context) {
+        let Relayed(peer_url, payload) = message;
+
+        match payload {
+            RelayPayload::Message(mut message_stream) => {
+                // The origin lives on the peer, its acknowledgements would not reach it
+                message_stream.header_options.ack_sequence = None;
+                let room_id = message_stream.room_id;
+                let origin_party_id = message_stream.origin_id;
+                self.broadcast_to_room_clients(room_id, origin_party_id, message_stream, None);
+            }
+            RelayPayload::MemberJoined(room_id, client_party_id, _) => {
+                self.remote_members.entry(room_id).or_default().insert((peer_url, client_party_id));
+            }
+            RelayPayload::MemberLeft(room_id, client_party_id) => {
+                if let Some(remote_members) = self.remote_members.get_mut(&room_id) {
+                    remote_members.remove(&(peer_url, client_party_id));
+
+                    if remote_members.is_empty() {
+                        self.remote_members.remove(&room_id);
+                    }
+                }
+            }
+            RelayPayload::Ack => (),
+        }
+    }
+}
+
+impl MessageHandler<RelayReset> for GameRoomRouterActor {
+    type Result = ();
+
+    fn handle(&mut self, message: RelayReset, _: &mut Self::Context) {
+        let RelayReset(peer_url) = message;
+
+        for remote_members in self.remote_members.values_mut() {
+            remote_members.retain(|(member_peer_url, _)| *member_peer_url != peer_url);
+        }
+
+        self.remote_members.retain(|_, remote_members| !remote_members.is_empty());
+    }
+}
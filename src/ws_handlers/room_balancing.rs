@@ -0,0 +1,143 @@
+use super::GameRoomRouterActor;
+use crate::proto::{InfoCode, MessageStream, PartyId};
+use crate::{anyerror, AnyError, AnyResult};
+use log::{info, warn};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+// How the router picks the server of a created room or of an auto join
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum RoomBalancing {
+    #[default]
+    LeastLoaded, // Fewest hosted rooms, the lowest server ID first on a tie
+    RoundRobin, // Next server ID after the last one picked, reports are ignored
+    Weighted,   // Lowest share of the reported capacity in use
+}
+
+impl FromStr for RoomBalancing {
+    type Err = AnyError;
+
+    fn from_str(source: &str) -> AnyResult<Self> {
+        match source.to_lowercase().as_str() {
+            "least-loaded" => Ok(Self::LeastLoaded),
+            "round-robin" => Ok(Self::RoundRobin),
+            "weighted" => Ok(Self::Weighted),
+            _ => Err(anyerror!("Unknown RoomBalancing {}", source)),
+        }
+    }
+}
+
+// Last capacity report of a server, plus the rooms created on it since
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ServerLoad {
+    pub(crate) hosted_rooms: u32,
+    pub(crate) max_rooms: Option<u32>, // None until the server reports, it is never full then
+}
+
+impl ServerLoad {
+    fn is_full(&self) -> bool {
+        self.max_rooms.map(|max_rooms| self.hosted_rooms >= max_rooms).unwrap_or(false)
+    }
+
+    // Servers that did not report weigh as if they had room for one
+    fn cmp_usage(&self, other: &Self) -> Ordering {
+        let usage = self.hosted_rooms as u64 * other.max_rooms.unwrap_or(1) as u64;
+        let other_usage = other.hosted_rooms as u64 * self.max_rooms.unwrap_or(1) as u64;
+
+        usage.cmp(&other_usage)
+    }
+}
+
+impl GameRoomRouterActor {
+    pub(crate) fn connected_server_ids(&self) -> Vec<u32> {
+        let primary_server_id = self.server_handle.as_ref().map(|_| 0);
+
+        primary_server_id.into_iter().chain(self.shard_servers.keys().copied()).collect()
+    }
+
+    // Servers reported full are skipped, None when every candidate is full
+    pub(crate) fn pick_server(&mut self, candidate_server_ids: &[u32]) -> Option<u32> {
+        let open_server_ids: Vec<(u32, ServerLoad)> = candidate_server_ids
+            .iter()
+            .map(|server_id| {
+                (*server_id, self.server_loads.get(server_id).copied().unwrap_or_default())
+            })
+            .filter(|(_, server_load)| !server_load.is_full())
+            .collect();
+
+        let picked_server_id = match self.config.room_balancing {
+            RoomBalancing::LeastLoaded => open_server_ids
+                .iter()
+                .min_by_key(|(server_id, server_load)| (server_load.hosted_rooms, *server_id))
+                .map(|(server_id, _)| *server_id),
+            RoomBalancing::RoundRobin => {
+                let next_server_id = open_server_ids
+                    .iter()
+                    .map(|(server_id, _)| *server_id)
+                    .filter(|server_id| Some(*server_id) > self.last_picked_server_id)
+                    .min();
+
+                next_server_id
+                    .or_else(|| open_server_ids.iter().map(|(server_id, _)| *server_id).min())
+            }
+            RoomBalancing::Weighted => open_server_ids
+                .iter()
+                .min_by(|(server_id, server_load), (other_server_id, other_server_load)| {
+                    server_load
+                        .cmp_usage(other_server_load)
+                        .then_with(|| server_id.cmp(other_server_id))
+                })
+                .map(|(server_id, _)| *server_id),
+        };
+
+        if picked_server_id.is_some() {
+            self.last_picked_server_id = picked_server_id;
+        }
+
+        picked_server_id
+    }
+
+    // Replaces the previous report, the server sends one whenever its load changes
+    pub(crate) fn report_load(&mut self, server_id: u32, hosted_rooms: u32, max_rooms: u32) {
+        self.server_loads
+            .insert(server_id, ServerLoad { hosted_rooms, max_rooms: Some(max_rooms) });
+    }
+
+    // Rooms already available are skipped, a new room counts as hosted until the next report
+    pub(crate) fn create_rooms(&mut self, created_room_ids: &[u32]) {
+        let candidate_server_ids = self.connected_server_ids();
+        let mut available_room_ids = Vec::new();
+
+        for created_room_id in created_room_ids {
+            if self.is_room_available(*created_room_id) {
+                continue;
+            }
+
+            let server_id = match self.pick_server(&candidate_server_ids) {
+                Some(server_id) => server_id,
+                None => {
+                    warn!("Every server is full, room {} is not created", created_room_id);
+                    continue;
+                }
+            };
+            let server_load = self.server_loads.entry(server_id).or_default();
+            server_load.hosted_rooms = server_load.hosted_rooms.saturating_add(1);
+
+            if server_id != 0 {
+                self.room_owners.insert(*created_room_id, server_id);
+            }
+
+            info!("Room {} created on server {}", created_room_id, server_id);
+            let created_info = MessageStream::new_info(
+                *created_room_id,
+                PartyId::Server(server_id),
+                InfoCode::RoomCreated,
+                &[],
+            );
+            self.send_to_server_id(server_id, PartyId::Server(0), created_info);
+            available_room_ids.push(*created_room_id);
+        }
+
+        self.add_available_rooms(&available_room_ids);
+    }
+}
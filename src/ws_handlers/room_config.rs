@@ -0,0 +1,324 @@
+use super::{GameRoomRouterActor, InterActorMessage, PartyRecipient, CLIENT_TIMEOUT};
+use crate::proto::PayloadKind;
+use crate::AnyResult;
+use actix::clock::Duration;
+use actix::Context;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+// Settings of one room over the router ones, from the rooms of the --config file or from the
+// ConfigureRoom control command of its server
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RoomConfig {
+    // Joins beyond this many members and reserved slots are refused, 0 lifts the router limit
+    pub(crate) max_clients: Option<usize>,
+    // Inactivity before a member is kicked, a connection gets the most lenient of its rooms
+    pub(crate) client_timeout_millis: Option<u64>,
+    // No Normal frame for this long gets a member warned then kicked, 0 lets the members idle
+    pub(crate) idle_timeout_millis: Option<u64>,
+    // Lockstep ticks per second the room runs at as soon as configured, 0 stops it
+    pub(crate) tick_rate: Option<u32>,
+    // Payload kinds the clients may send, any when unset
+    pub(crate) allowed_payload_kinds: Option<BTreeSet<PayloadKind>>,
+    // Server broadcasts carry a room sequence, numbering them in the order every member gets them
+    pub(crate) sequenced: Option<bool>,
+    // Realtime frames skip the holds and the history of the room, relayed at once to their
+    // destination and dropped on congestion, e.g. Opus voice packets
+    pub(crate) realtime: Option<bool>,
+    // Normal frames and bytes the clients of the room may send per second all together, 0 lifts
+    // the cap
+    pub(crate) max_messages_per_second: Option<u32>,
+    pub(crate) max_bytes_per_second: Option<u64>,
+}
+
+impl RoomConfig {
+    // As JSON, e.g. {"max_clients": 8, "allowed_payload_kinds": ["Data", "Command"]}
+    pub(crate) fn parse(source: &str) -> AnyResult<Self> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    // Settings left out are taken from the fallback
+    pub(crate) fn or(self, fallback: &Self) -> Self {
+        Self {
+            max_clients: self.max_clients.or(fallback.max_clients),
+            client_timeout_millis: self.client_timeout_millis.or(fallback.client_timeout_millis),
+            idle_timeout_millis: self.idle_timeout_millis.or(fallback.idle_timeout_millis),
+            tick_rate: self.tick_rate.or(fallback.tick_rate),
+            allowed_payload_kinds: self
+                .allowed_payload_kinds
+                .or_else(|| fallback.allowed_payload_kinds.clone()),
+            sequenced: self.sequenced.or(fallback.sequenced),
+            realtime: self.realtime.or(fallback.realtime),
+            max_messages_per_second: self
+                .max_messages_per_second
+                .or(fallback.max_messages_per_second),
+            max_bytes_per_second: self.max_bytes_per_second.or(fallback.max_bytes_per_second),
+        }
+    }
+
+    fn client_timeout(&self) -> Duration {
+        self.client_timeout_millis.map(Duration::from_millis).unwrap_or(CLIENT_TIMEOUT)
+    }
+
+    fn tick_duration(&self) -> Option<Duration> {
+        let tick_rate = self.tick_rate.filter(|tick_rate| *tick_rate > 0)?;
+
+        Some(Duration::from_secs_f64(1.0 / tick_rate as f64))
+    }
+}
+
+// Registry of the room settings, the ones set by the servers over the ones of the config file
+#[derive(Debug, Default)]
+pub(crate) struct RoomConfigs {
+    configured: BTreeMap<u32, RoomConfig>, // Room ID -> From the config file
+    overridden: BTreeMap<u32, RoomConfig>, // Room ID -> From the server of the room
+}
+
+impl RoomConfigs {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.configured.is_empty() && self.overridden.is_empty()
+    }
+
+    pub(crate) fn get(&self, room_id: u32) -> RoomConfig {
+        let configured = self.configured.get(&room_id).cloned().unwrap_or_default();
+
+        match self.overridden.get(&room_id) {
+            Some(overridden) => overridden.clone().or(&configured),
+            None => configured,
+        }
+    }
+
+    // One setting of a room without cloning the others, checked on the routing path
+    fn setting<'a, V>(
+        &'a self,
+        room_id: u32,
+        field: impl Fn(&'a RoomConfig) -> Option<V>,
+    ) -> Option<V> {
+        self.overridden
+            .get(&room_id)
+            .and_then(&field)
+            .or_else(|| self.configured.get(&room_id).and_then(&field))
+    }
+
+    // None when the room keeps the router limit, Some(None) when it lifts it
+    pub(crate) fn max_clients(&self, room_id: u32) -> Option<Option<usize>> {
+        self.setting(room_id, |room_config| room_config.max_clients)
+            .map(|max_clients| Some(max_clients).filter(|max_clients| *max_clients > 0))
+    }
+
+    // None when the room keeps --idle-timeout, Some(None) when its members may idle
+    pub(crate) fn idle_timeout(&self, room_id: u32) -> Option<Option<Duration>> {
+        self.setting(room_id, |room_config| room_config.idle_timeout_millis).map(|idle_timeout| {
+            Some(Duration::from_millis(idle_timeout)).filter(|idle_timeout| !idle_timeout.is_zero())
+        })
+    }
+
+    pub(crate) fn allows(&self, room_id: u32, payload_kind: PayloadKind) -> bool {
+        self.setting(room_id, |room_config| room_config.allowed_payload_kinds.as_ref())
+            .is_none_or(|allowed_payload_kinds| allowed_payload_kinds.contains(&payload_kind))
+    }
+
+    pub(crate) fn is_sequenced(&self, room_id: u32) -> bool {
+        self.setting(room_id, |room_config| room_config.sequenced).unwrap_or_default()
+    }
+
+    pub(crate) fn is_realtime(&self, room_id: u32) -> bool {
+        self.setting(room_id, |room_config| room_config.realtime).unwrap_or_default()
+    }
+
+    // Frames and bytes per second, None when uncapped
+    pub(crate) fn max_throughput(&self, room_id: u32) -> (Option<u32>, Option<u64>) {
+        let max_frames = self
+            .setting(room_id, |room_config| room_config.max_messages_per_second)
+            .filter(|max_frames| *max_frames > 0);
+        let max_bytes = self
+            .setting(room_id, |room_config| room_config.max_bytes_per_second)
+            .filter(|max_bytes| *max_bytes > 0);
+
+        (max_frames, max_bytes)
+    }
+
+    fn room_ids(&self) -> BTreeSet<u32> {
+        self.configured.keys().chain(self.overridden.keys()).copied().collect()
+    }
+}
+
+impl GameRoomRouterActor {
+    pub(crate) fn with_room_configs(mut self, configured: BTreeMap<u32, RoomConfig>) -> Self {
+        self.room_configs.configured = configured;
+        self
+    }
+
+    // Rooms of the config file, replaced on every reload
+    pub(crate) fn set_configured_rooms(
+        &mut self,
+        configured: BTreeMap<u32, RoomConfig>,
+        context: &mut Context<Self>,
+    ) {
+        self.update_room_configs(|room_configs| room_configs.configured = configured, context);
+    }
+
+    // An empty config gives the room back to the config file
+    pub(crate) fn override_room_config(
+        &mut self,
+        room_id: u32,
+        room_config: RoomConfig,
+        context: &mut Context<Self>,
+    ) {
+        self.update_room_configs(
+            |room_configs| {
+                if room_config == RoomConfig::default() {
+                    room_configs.overridden.remove(&room_id)
+                } else {
+                    room_configs.overridden.insert(room_id, room_config)
+                }
+            },
+            context,
+        );
+    }
+
+    fn update_room_configs<R>(
+        &mut self,
+        update: impl FnOnce(&mut RoomConfigs) -> R,
+        context: &mut Context<Self>,
+    ) {
+        let previous_configs: BTreeMap<u32, RoomConfig> = self
+            .room_configs
+            .room_ids()
+            .into_iter()
+            .map(|room_id| (room_id, self.room_configs.get(room_id)))
+            .collect();
+        update(&mut self.room_configs);
+
+        let room_ids: BTreeSet<u32> =
+            previous_configs.keys().copied().chain(self.room_configs.room_ids()).collect();
+
+        for room_id in room_ids {
+            let previous_config = previous_configs.get(&room_id).cloned().unwrap_or_default();
+            let room_config = self.room_configs.get(room_id);
+
+            if room_config == previous_config {
+                continue;
+            }
+
+            info!("Room {} configured with {:?}", room_id, room_config);
+
+            if room_config.tick_duration() != previous_config.tick_duration() {
+                self.set_lockstep(room_id, room_config.tick_duration(), context);
+            }
+
+            if room_config.client_timeout() != previous_config.client_timeout() {
+                let client_ids: BTreeSet<Uuid> = self
+                    .game_rooms
+                    .get(&room_id)
+                    .map(|room_clients| {
+                        room_clients.values().map(|room_client| room_client.client_id).collect()
+                    })
+                    .unwrap_or_default();
+
+                for client_id in client_ids {
+                    self.push_client_timeout(client_id, true);
+                }
+            }
+        }
+    }
+
+    // Rooms configured with a tick rate run in lockstep from the start of the router
+    pub(crate) fn start_configured_lockstep(&mut self, context: &mut Context<Self>) {
+        for room_id in self.room_configs.room_ids() {
+            if let Some(tick_duration) = self.room_configs.get(room_id).tick_duration() {
+                self.set_lockstep(room_id, Some(tick_duration), context);
+            }
+        }
+    }
+
+    // A connection gets the most lenient timeout of its rooms. Nothing is sent on joins while
+    // none of the configured rooms sets one, the connection keeping CLIENT_TIMEOUT
+    pub(crate) fn push_client_timeout(&self, client_id: Uuid, is_changed: bool) {
+        if self.room_configs.is_empty() && !is_changed {
+            return;
+        }
+
+        let mut client_address: Option<&PartyRecipient> = None;
+        let mut client_timeout: Option<Duration> = None;
+
+        for (room_id, room_clients) in self.game_rooms.iter() {
+            for room_client in room_clients.values() {
+                if room_client.client_id != client_id {
+                    continue;
+                }
+
+                let room_timeout = self
+                    .room_configs
+                    .setting(*room_id, |room_config| room_config.client_timeout_millis)
+                    .map(Duration::from_millis);
+                client_address = Some(&room_client.address);
+                client_timeout = client_timeout.max(room_timeout);
+            }
+        }
+
+        let client_timeout = match (client_timeout, is_changed) {
+            (Some(client_timeout), _) => client_timeout,
+            (None, true) => CLIENT_TIMEOUT,
+            (None, false) => return,
+        };
+
+        if let Some(client_address) = client_address {
+            let _ = client_address.do_send(InterActorMessage::ClientTimeout(client_timeout));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_configs_is_as_expected() {
+        let configured = RoomConfig::parse(
+            r#"{"max_clients": 8, "client_timeout_millis": 5000, "allowed_payload_kinds": ["Data"]}"#,
+        )
+        .unwrap();
+        let mut room_configs = RoomConfigs::default();
+        room_configs.configured.insert(1, configured.clone());
+
+        assert_eq!(room_configs.get(1), configured);
+        assert_eq!(room_configs.get(2), RoomConfig::default());
+        assert_eq!(room_configs.max_clients(1), Some(Some(8)));
+        assert_eq!(room_configs.max_clients(2), None);
+        assert_eq!(room_configs.idle_timeout(1), None);
+        assert!(room_configs.allows(1, PayloadKind::Data));
+        assert!(!room_configs.allows(1, PayloadKind::Command));
+        assert!(room_configs.allows(2, PayloadKind::Command));
+        assert!(!room_configs.is_sequenced(1));
+        assert!(!room_configs.is_realtime(1));
+        assert_eq!(room_configs.max_throughput(1), (None, None));
+
+        // The server overrides some settings, the others still come from the file
+        room_configs.overridden.insert(1, RoomConfig::parse(r#"{"max_clients": 0}"#).unwrap());
+
+        assert_eq!(room_configs.max_clients(1), Some(None));
+        room_configs
+            .overridden
+            .insert(1, RoomConfig::parse(r#"{"idle_timeout_millis": 0}"#).unwrap());
+        assert_eq!(room_configs.idle_timeout(1), Some(None));
+        room_configs.overridden.insert(
+            2,
+            RoomConfig::parse(r#"{"max_messages_per_second": 0, "max_bytes_per_second": 4096}"#)
+                .unwrap(),
+        );
+        assert_eq!(room_configs.max_throughput(2), (None, Some(4096)));
+        assert_eq!(room_configs.get(1).client_timeout(), Duration::from_secs(5));
+        assert_eq!(room_configs.get(2).client_timeout(), CLIENT_TIMEOUT);
+        assert!(!room_configs.allows(1, PayloadKind::Command));
+        assert_eq!(
+            RoomConfig::parse(r#"{"tick_rate": 20}"#).unwrap().tick_duration(),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(RoomConfig::parse(r#"{"tick_rate": 0}"#).unwrap().tick_duration(), None);
+        assert!(RoomConfig::parse(r#"{"history_size": 64}"#).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use super::{GameRoomRouterActor, InterActorMessage, OutboundDestination, RoomClient};
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{
+    InfoCode, MessageCode, MessageStream, PartyId, PayloadKind, RelayPayload, LOBBY_ROOM_ID,
+};
+use actix::{AsyncContext, Context};
+use log::info;
+use std::collections::BTreeMap;
+
+pub(crate) const ROOM_CLOSED_REASON: &str = "Room closed";
+
+impl GameRoomRouterActor {
+    // Disconnects the room clients and forgets the room, returns how many clients there were.
+    // Connections still in other rooms only leave this one
+    pub(crate) fn close_room(&mut self, room_id: u32, context: &mut Context<Self>) -> usize {
+        let room_clients = self.forget_room(room_id, context);
+
+        for (client_party_id, room_client) in room_clients.iter() {
+            let party_id = PartyId::Client(*client_party_id);
+            let client_address = &room_client.address;
+            let is_elsewhere = self.game_rooms.values().any(|other_room_clients| {
+                other_room_clients
+                    .values()
+                    .any(|other_room_client| other_room_client.client_id == room_client.client_id)
+            });
+
+            if is_elsewhere {
+                self.reply_membership_info(
+                    client_address,
+                    MessageStream::new_info(room_id, party_id, InfoCode::RoomLeft, &[]),
+                );
+                let _ = client_address.do_send(InterActorMessage::RoomLeft(room_id, party_id));
+            } else {
+                let _ = client_address.do_send(InterActorMessage::CloseConnection(
+                    party_id,
+                    ROOM_CLOSED_REASON.into(),
+                ));
+            }
+
+            self.outbound_batches.remove(&OutboundDestination::Client(room_id, *client_party_id));
+            Metrics::decrement(&METRICS.connected_clients);
+            ADMIN_EVENTS.publish(AdminEvent::Disconnected {
+                room_id: Some(room_id),
+                party_id: party_id.get_repr(),
+                client_id: Some(room_client.client_id),
+            });
+        }
+
+        info!("Room {} closed, disconnecting {} clients", room_id, room_clients.len());
+        ADMIN_EVENTS
+            .publish(AdminEvent::RoomClosed { room_id, disconnected_clients: room_clients.len() });
+
+        room_clients.len()
+    }
+
+    // Started when the last client of the room left, a client joining in between cancels it
+    pub(crate) fn schedule_room_expiry(&mut self, room_id: u32, context: &mut Context<Self>) {
+        let empty_room_ttl = match self.config.empty_room_ttl {
+            Some(empty_room_ttl) if room_id != LOBBY_ROOM_ID => empty_room_ttl,
+            _ => return,
+        };
+
+        self.cancel_room_expiry(room_id, context);
+        let expiry_handle = context.run_later(empty_room_ttl, move |actor, context| {
+            actor.empty_room_expiries.remove(&room_id);
+            actor.expire_room(room_id, context);
+        });
+        self.empty_room_expiries.insert(room_id, expiry_handle);
+    }
+
+    pub(crate) fn cancel_room_expiry(&mut self, room_id: u32, context: &mut Context<Self>) {
+        if let Some(expiry_handle) = self.empty_room_expiries.remove(&room_id) {
+            context.cancel_future(expiry_handle);
+        }
+    }
+
+    // The server is told with a RoomExpired info, it can announce the room again if needed
+    fn expire_room(&mut self, room_id: u32, context: &mut Context<Self>) {
+        let is_empty = self
+            .game_rooms
+            .get(&room_id)
+            .map(|room_clients| room_clients.is_empty())
+            .unwrap_or(false);
+
+        if !is_empty {
+            return;
+        }
+
+        self.forget_room(room_id, context);
+        info!("Room {} expired after {:#?} without clients", room_id, self.config.empty_room_ttl);
+        ADMIN_EVENTS.publish(AdminEvent::RoomExpired { room_id });
+
+        let expired_info = MessageStream::new(
+            MessageCode::Special,
+            room_id,
+            PartyId::Server(0),
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&[InfoCode::RoomExpired.into()]),
+        );
+        self.send_to_server(PartyId::Server(0), expired_info);
+    }
+
+    fn forget_room(
+        &mut self,
+        room_id: u32,
+        context: &mut Context<Self>,
+    ) -> BTreeMap<u32, RoomClient> {
+        let room_clients = self.game_rooms.remove(&room_id).unwrap_or_default();
+
+        for (client_party_id, room_client) in room_clients.iter() {
+            self.interest_subscriptions.remove(&(room_id, *client_party_id));
+            self.relay_membership(RelayPayload::MemberLeft(room_id, *client_party_id));
+            self.notify_leave(room_id, *client_party_id, room_client.client_id);
+        }
+
+        if let Some(lockstep_room) = self.lockstep_rooms.remove(&room_id) {
+            lockstep_room.cancel_deadline(context);
+        }
+
+        self.cancel_room_expiry(room_id, context);
+        self.forget_reserved_slots(room_id, context);
+        self.paused_rooms.remove(&room_id);
+        self.measure_memory();
+        self.room_owners.remove(&room_id);
+        self.room_routed_messages.remove(&room_id);
+        self.room_sequences.remove(&room_id);
+        self.room_snapshots.remove(&room_id);
+        self.room_throughputs.remove(&room_id);
+        self.forget_blob_transfers(room_id, None);
+        self.forget_streams(room_id, None);
+
+        self.available_rooms.retain(|available_room_id| *available_room_id != room_id);
+        self.push_room_list();
+
+        room_clients
+    }
+}
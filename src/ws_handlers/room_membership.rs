@@ -0,0 +1,357 @@
+use super::{
+    GameRoomRouterActor, InterActorMessage, OutboundDestination, PartyRecipient, RoomClient,
+};
+use crate::admin_events::{unix_millis, AdminEvent, ADMIN_EVENTS};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{
+    ErrorCode, InfoCode, MessageCode, MessageStream, PartyId, PayloadKind, RelayPayload,
+    ALL_CLIENT_ID,
+};
+use crate::telemetry::TraceContext;
+use actix::Context;
+use log::info;
+use uuid::Uuid;
+
+impl GameRoomRouterActor {
+    // Join or Leave info told to the server on behalf of a client, a Join also carries the client
+    // metadata after the client UUID
+    pub(crate) fn presence_info(
+        info_code: InfoCode,
+        room_id: u32,
+        party_id: PartyId,
+        client_id: Uuid,
+        metadata: &[u8],
+    ) -> MessageStream {
+        let mut presence_payload = Vec::with_capacity(17 + metadata.len());
+        presence_payload.push(info_code.into());
+        presence_payload.extend_from_slice(client_id.as_bytes());
+        presence_payload.extend_from_slice(metadata);
+
+        MessageStream::new(
+            MessageCode::Special,
+            room_id,
+            party_id,
+            PartyId::Server(0),
+            PayloadKind::Info,
+            Some(&presence_payload),
+        )
+    }
+
+    // Client upgrades claim their party ID here too, so one is never handed out twice in a room
+    pub(crate) fn next_client_party_id(&mut self, room_id: u32) -> Option<u32> {
+        let client_counter = self.client_counters.entry(room_id).or_default();
+
+        if *client_counter >= ALL_CLIENT_ID {
+            return None;
+        }
+
+        *client_counter += 1;
+
+        Some(*client_counter - 1)
+    }
+
+    // Forgets one membership and tells the server, the connection itself is left alone
+    pub(crate) fn remove_room_client(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        context: &mut Context<Self>,
+    ) -> Option<RoomClient> {
+        let room_clients = self.game_rooms.get_mut(&room_id)?;
+        let room_client = room_clients.remove(&client_party_id)?;
+        let is_room_emptied = room_clients.is_empty();
+
+        self.forget_lockstep_inputs(room_id, client_party_id, context);
+        self.interest_subscriptions.remove(&(room_id, client_party_id));
+        self.overflowing_mailboxes.remove(&OutboundDestination::Client(room_id, client_party_id));
+
+        let party_id = PartyId::Client(client_party_id);
+        self.forget_sequences(party_id, Some(room_id));
+        self.forget_blob_transfers(room_id, Some(party_id));
+        self.forget_streams(room_id, Some(party_id));
+        self.send_to_server(
+            party_id,
+            Self::presence_info(InfoCode::Leave, room_id, party_id, room_client.client_id, &[]),
+        );
+        self.relay_membership(RelayPayload::MemberLeft(room_id, client_party_id));
+        self.notify_leave(room_id, client_party_id, room_client.client_id);
+
+        if is_room_emptied {
+            self.schedule_room_expiry(room_id, context);
+        }
+
+        Some(room_client)
+    }
+
+    // The connection of the member is gone, its slot is reserved when slot reservation is on
+    pub(crate) fn disconnect_room_client(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        context: &mut Context<Self>,
+    ) {
+        if let Some(room_client) = self.remove_room_client(room_id, client_party_id, context) {
+            Metrics::decrement(&METRICS.connected_clients);
+            ADMIN_EVENTS.publish(AdminEvent::Disconnected {
+                room_id: Some(room_id),
+                party_id: client_party_id,
+                client_id: Some(room_client.client_id),
+            });
+            self.reserve_slot(room_id, client_party_id, room_client.client_id, context);
+        }
+    }
+
+    fn insert_room_client(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        mut room_client: RoomClient,
+        context: &mut Context<Self>,
+    ) {
+        room_client.joined_at_millis = unix_millis();
+        room_client.last_active_at_millis = room_client.joined_at_millis;
+        room_client.last_played_at_millis = room_client.joined_at_millis;
+        room_client.idle_warned_at_millis = None;
+
+        let party_id = PartyId::Client(client_party_id);
+        let join_info = Self::presence_info(
+            InfoCode::Join,
+            room_id,
+            party_id,
+            room_client.client_id,
+            &room_client.metadata,
+        );
+
+        let client_id = room_client.client_id;
+
+        self.cancel_room_expiry(room_id, context);
+        self.game_rooms.entry(room_id).or_default().insert(client_party_id, room_client);
+        self.send_to_server(party_id, join_info);
+        self.relay_membership(RelayPayload::MemberJoined(
+            room_id,
+            client_party_id,
+            *client_id.as_bytes(),
+        ));
+        self.notify_join(room_id, client_party_id, client_id);
+        self.push_client_timeout(client_id, false);
+        self.sync_room_state(room_id, client_party_id);
+    }
+
+    // A party ID in the target room for a client that is not in it yet, or an error reply
+    fn claim_client_party_id(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        client_id: Uuid,
+        target_room_id: u32,
+        context: &mut Context<Self>,
+    ) -> Option<u32> {
+        let is_member = self
+            .game_rooms
+            .get(&target_room_id)
+            .map(|room_clients| {
+                room_clients.values().any(|room_client| room_client.client_id == client_id)
+            })
+            .unwrap_or(false);
+        let is_available = !is_member && self.is_room_available(target_room_id);
+        let target_party_id = if is_available {
+            self.claim_slot(target_room_id, client_id, context).ok()
+        } else {
+            None
+        };
+
+        if target_party_id.is_none() {
+            self.reply_error(
+                room_id,
+                origin_party_id,
+                ErrorCode::RoomUnavailable,
+                &target_room_id.to_le_bytes(),
+            );
+        }
+
+        target_party_id
+    }
+
+    fn room_client(&self, room_id: u32, origin_party_id: PartyId) -> Option<RoomClient> {
+        match origin_party_id {
+            PartyId::Client(client_party_id) => {
+                self.game_rooms.get(&room_id)?.get(&client_party_id).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    // Room and party IDs of a client as told by the RoomSwitched and RoomJoined infos
+    pub(crate) fn membership_info(
+        info_code: InfoCode,
+        room_id: u32,
+        client_party_id: u32,
+    ) -> MessageStream {
+        let mut details = room_id.to_le_bytes().to_vec();
+        details.extend_from_slice(&client_party_id.to_le_bytes());
+
+        MessageStream::new_info(room_id, PartyId::Client(client_party_id), info_code, &details)
+    }
+
+    // Sent to the connection itself, a client that just left the room is no longer routed to
+    pub(crate) fn reply_membership_info(
+        &self,
+        client_address: &PartyRecipient,
+        membership_info: MessageStream,
+    ) {
+        let _ = client_address.do_send(InterActorMessage::NewMessage(
+            PartyId::Server(0),
+            membership_info,
+            self.route_trace.map(TraceContext::stamp),
+        ));
+    }
+
+    // The client keeps its connection but leaves with its old party ID and joins with a new one,
+    // the server sees a Leave then a Join and the client is told its new IDs with a RoomSwitched
+    pub(crate) fn switch_room(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        target_room_id: u32,
+        context: &mut Context<Self>,
+    ) {
+        let client_id = match self.room_client(room_id, origin_party_id) {
+            Some(room_client) => room_client.client_id,
+            None => return,
+        };
+        let target_party_id = match self.claim_client_party_id(
+            room_id,
+            origin_party_id,
+            client_id,
+            target_room_id,
+            context,
+        ) {
+            Some(target_party_id) => target_party_id,
+            None => return,
+        };
+        let client_party_id = origin_party_id.get_repr();
+        let room_client = match self.remove_room_client(room_id, client_party_id, context) {
+            Some(room_client) => room_client,
+            None => return,
+        };
+        let client_address = room_client.address.clone();
+
+        // Frames waiting for the next batch flush still belong to this connection
+        if let Some(pending_messages) =
+            self.outbound_batches.remove(&OutboundDestination::Client(room_id, client_party_id))
+        {
+            self.outbound_batches.insert(
+                OutboundDestination::Client(target_room_id, target_party_id),
+                pending_messages,
+            );
+        }
+
+        let _ = client_address.do_send(InterActorMessage::RoomSwitched(
+            room_id,
+            origin_party_id,
+            target_room_id,
+            PartyId::Client(target_party_id),
+        ));
+        self.insert_room_client(target_room_id, target_party_id, room_client, context);
+
+        info!(
+            "Party ID {} of room {} switched to room {} as Party ID {}",
+            client_party_id, room_id, target_room_id, target_party_id
+        );
+        ADMIN_EVENTS.publish(AdminEvent::RoomSwitched {
+            client_id,
+            from_room_id: room_id,
+            from_party_id: client_party_id,
+            room_id: target_room_id,
+            party_id: target_party_id,
+        });
+
+        self.reply_membership_info(
+            &client_address,
+            Self::membership_info(InfoCode::RoomSwitched, target_room_id, target_party_id),
+        );
+    }
+
+    // One more membership for the same connection, frames tell the rooms apart by their room ID
+    pub(crate) fn join_room(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        target_room_id: u32,
+        context: &mut Context<Self>,
+    ) {
+        let room_client = match self.room_client(room_id, origin_party_id) {
+            Some(room_client) => room_client,
+            None => return,
+        };
+        let client_id = room_client.client_id;
+        let client_address = room_client.address.clone();
+        let target_party_id = match self.claim_client_party_id(
+            room_id,
+            origin_party_id,
+            client_id,
+            target_room_id,
+            context,
+        ) {
+            Some(target_party_id) => target_party_id,
+            None => return,
+        };
+
+        let _ = client_address.do_send(InterActorMessage::RoomJoined(
+            target_room_id,
+            PartyId::Client(target_party_id),
+        ));
+        self.insert_room_client(target_room_id, target_party_id, room_client, context);
+
+        info!(
+            "Party ID {} of room {} also joined room {} as Party ID {}",
+            origin_party_id.get_repr(),
+            room_id,
+            target_room_id,
+            target_party_id
+        );
+        Metrics::increment(&METRICS.connected_clients);
+        ADMIN_EVENTS.publish(AdminEvent::Connected {
+            room_id: Some(target_room_id),
+            party_id: target_party_id,
+            client_id: Some(client_id),
+        });
+
+        self.reply_membership_info(
+            &client_address,
+            Self::membership_info(InfoCode::RoomJoined, target_room_id, target_party_id),
+        );
+    }
+
+    // Leaving the last room of a connection closes it
+    pub(crate) fn leave_room(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        context: &mut Context<Self>,
+    ) {
+        let client_party_id = match origin_party_id {
+            PartyId::Client(client_party_id) => client_party_id,
+            _ => return,
+        };
+        let room_client = match self.remove_room_client(room_id, client_party_id, context) {
+            Some(room_client) => room_client,
+            None => return,
+        };
+        let client_address = room_client.address;
+
+        info!("Party ID {} left room {}", client_party_id, room_id);
+        Metrics::decrement(&METRICS.connected_clients);
+        ADMIN_EVENTS.publish(AdminEvent::Disconnected {
+            room_id: Some(room_id),
+            party_id: client_party_id,
+            client_id: Some(room_client.client_id),
+        });
+
+        self.reply_membership_info(
+            &client_address,
+            MessageStream::new_info(room_id, origin_party_id, InfoCode::RoomLeft, &[]),
+        );
+        let _ = client_address.do_send(InterActorMessage::RoomLeft(room_id, origin_party_id));
+    }
+}
@@ -0,0 +1,38 @@
+use super::GameRoomRouterActor;
+use actix::{Handler as MessageHandler, Message, MessageResult};
+use serde::Serialize;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct RoomOccupancy {
+    pub(crate) room_id: u32,
+    pub(crate) players: usize,          // Current members of the room
+    pub(crate) capacity: Option<usize>, // None when the room takes any number of clients
+    pub(crate) joinable: bool,          // False once the members and reserved slots fill it
+}
+
+// Rooms announced by the servers with their fullness, sorted and without the lobby
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<RoomOccupancy>")]
+pub(crate) struct GetRoomOccupancy;
+
+impl GameRoomRouterActor {
+    pub(crate) fn room_occupancy(&self) -> Vec<RoomOccupancy> {
+        self.available_rooms
+            .iter()
+            .map(|room_id| RoomOccupancy {
+                room_id: *room_id,
+                players: self.game_rooms.get(room_id).map_or(0, |room_clients| room_clients.len()),
+                capacity: self.room_capacity(*room_id),
+                joinable: !self.is_room_full(*room_id),
+            })
+            .collect()
+    }
+}
+
+impl MessageHandler<GetRoomOccupancy> for GameRoomRouterActor {
+    type Result = MessageResult<GetRoomOccupancy>;
+
+    fn handle(&mut self, _: GetRoomOccupancy, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.room_occupancy())
+    }
+}
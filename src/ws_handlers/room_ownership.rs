@@ -0,0 +1,111 @@
+use super::{GameRoomRouterActor, InterActorMessage, PartyRecipient};
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{ErrorCode, PartyId};
+use log::info;
+
+pub(crate) const SERVER_ID_TAKEN_REASON: &str = "Server ID taken";
+
+impl GameRoomRouterActor {
+    // Rooms nobody claimed belong to the primary server, server ID 0
+    pub(crate) fn room_server_id(&self, room_id: u32) -> u32 {
+        self.room_owners.get(&room_id).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn server_address(&self, server_id: u32) -> Option<&PartyRecipient> {
+        match server_id {
+            0 => self.server_handle.as_ref().map(|(_, server_address)| server_address),
+            _ => self.shard_servers.get(&server_id),
+        }
+    }
+
+    // The primary server controls every room, a shard server only the rooms it owns
+    pub(crate) fn is_in_control(&self, origin_party_id: PartyId, room_id: u32) -> bool {
+        match origin_party_id {
+            PartyId::Server(0) => true,
+            PartyId::Server(server_id) => self.room_owners.get(&room_id) == Some(&server_id),
+            _ => false,
+        }
+    }
+
+    // A second connection asking for a connected shard server ID is closed right away
+    pub(crate) fn connect_shard_server(&mut self, server_id: u32, server_address: PartyRecipient) {
+        let party_id = PartyId::Server(server_id);
+
+        if self.shard_servers.contains_key(&server_id) {
+            let _ = server_address.do_send(InterActorMessage::CloseConnection(
+                party_id,
+                SERVER_ID_TAKEN_REASON.into(),
+            ));
+            return;
+        }
+
+        self.forget_sequences(party_id, None);
+
+        info!("Shard server {} joined", server_id);
+        self.shard_servers.insert(server_id, server_address);
+        Metrics::increment(&METRICS.connected_servers);
+        ADMIN_EVENTS.publish(AdminEvent::Connected {
+            room_id: None,
+            party_id: party_id.get_repr(),
+            client_id: None,
+        });
+    }
+
+    // The rooms of a leaving shard server go back to the primary server with their clients
+    pub(crate) fn disconnect_shard_server(&mut self, server_id: u32) {
+        if self.shard_servers.remove(&server_id).is_none() {
+            return;
+        }
+
+        self.room_owners.retain(|_, owner_server_id| *owner_server_id != server_id);
+        self.server_loads.remove(&server_id);
+        self.forget_sequences(PartyId::Server(server_id), None);
+
+        info!("Shard server {} left", server_id);
+        Metrics::decrement(&METRICS.connected_servers);
+        ADMIN_EVENTS.publish(AdminEvent::Disconnected {
+            room_id: None,
+            party_id: PartyId::Server(server_id).get_repr(),
+            client_id: None,
+        });
+    }
+
+    // Claimed rooms are made available, a room owned by another server is refused
+    pub(crate) fn claim_rooms(&mut self, room_id: u32, server_id: u32, claimed_room_ids: &[u32]) {
+        let mut owned_room_ids = Vec::new();
+
+        for claimed_room_id in claimed_room_ids {
+            match self.room_owners.get(claimed_room_id) {
+                Some(owner_server_id) if *owner_server_id != server_id => {
+                    let mut details = claimed_room_id.to_le_bytes().to_vec();
+                    details.extend_from_slice(&owner_server_id.to_le_bytes());
+                    self.reply_error(
+                        room_id,
+                        PartyId::Server(server_id),
+                        ErrorCode::RoomOwned,
+                        &details,
+                    );
+                }
+                _ => {
+                    self.room_owners.insert(*claimed_room_id, server_id);
+                    owned_room_ids.push(*claimed_room_id);
+                }
+            }
+        }
+
+        info!("Server {} owns rooms {:?}", server_id, owned_room_ids);
+        self.add_available_rooms(&owned_room_ids);
+    }
+
+    // Only the owner releases a room, it stays available and is served by the primary server
+    pub(crate) fn release_rooms(&mut self, server_id: u32, released_room_ids: &[u32]) {
+        for released_room_id in released_room_ids {
+            if self.room_owners.get(released_room_id) == Some(&server_id) {
+                self.room_owners.remove(released_room_id);
+            }
+        }
+
+        info!("Server {} released rooms {:?}", server_id, released_room_ids);
+    }
+}
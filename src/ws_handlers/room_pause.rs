@@ -0,0 +1,100 @@
+use super::GameRoomRouterActor;
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::metrics::{Metrics, METRICS};
+use crate::proto::{ErrorCode, MessageStream, PartyId};
+use actix::clock::Instant;
+use actix::{Context, Handler as MessageHandler, Message};
+use log::info;
+
+// Normal frames held per paused room, the next ones are rejected with a RoomPaused error
+pub(crate) const MAX_HELD_MESSAGES: usize = 4096;
+
+// Pauses or resumes a room on behalf of an admin, replies with the number of frames released
+#[derive(Debug, Message)]
+#[rtype(result = "usize")]
+pub(crate) struct SetRoomPaused(pub(crate) u32, pub(crate) bool);
+
+impl GameRoomRouterActor {
+    pub(crate) fn pause_room(&mut self, room_id: u32) {
+        if self.paused_rooms.contains_key(&room_id) {
+            return;
+        }
+
+        info!("Room {} paused", room_id);
+        self.paused_rooms.insert(room_id, Vec::new());
+        ADMIN_EVENTS.publish(AdminEvent::RoomPaused { room_id });
+    }
+
+    // Held frames go through the routing again in their arrival order
+    pub(crate) fn resume_room(&mut self, room_id: u32, context: &mut Context<Self>) -> usize {
+        let held_messages = match self.paused_rooms.remove(&room_id) {
+            Some(held_messages) => held_messages,
+            None => return 0,
+        };
+        let mut released_messages = 0;
+        self.measure_memory();
+
+        // Released frames do not belong to the trace of the frame resuming the room
+        let route_trace = self.route_trace.take();
+
+        for (origin_party_id, mut message_stream, held_at) in held_messages {
+            if !message_stream.spend_ttl(held_at.elapsed()) {
+                Metrics::increment(&METRICS.expired_messages);
+                let destination_party_id = message_stream.destination_id;
+                self.report_undelivered(origin_party_id, &message_stream, destination_party_id);
+                continue;
+            }
+
+            released_messages += 1;
+            self.route_normal(origin_party_id, message_stream, context);
+        }
+
+        self.route_trace = route_trace;
+
+        info!("Room {} resumed, releasing {} frames", room_id, released_messages);
+        ADMIN_EVENTS.publish(AdminEvent::RoomResumed { room_id, released_messages });
+
+        released_messages
+    }
+
+    pub(crate) fn hold_paused(
+        &mut self,
+        room_id: u32,
+        origin_party_id: PartyId,
+        message_stream: MessageStream,
+    ) {
+        let is_held = self
+            .paused_rooms
+            .get(&room_id)
+            .is_some_and(|held_messages| held_messages.len() < MAX_HELD_MESSAGES);
+
+        // Shedding may drop held frames but never resumes the room
+        if is_held && self.make_memory_room(&message_stream) {
+            self.charge_memory(&message_stream);
+
+            if let Some(held_messages) = self.paused_rooms.get_mut(&room_id) {
+                held_messages.push((origin_party_id, message_stream, Instant::now()));
+            }
+
+            return;
+        }
+
+        self.reply_error(room_id, origin_party_id, ErrorCode::RoomPaused, &[]);
+        self.report_undelivered(origin_party_id, &message_stream, message_stream.destination_id);
+    }
+}
+
+impl MessageHandler<SetRoomPaused> for GameRoomRouterActor {
+    type Result = usize;
+
+    fn handle(&mut self, message: SetRoomPaused, context: &mut Self::Context) -> Self::Result {
+        let SetRoomPaused(room_id, is_paused) = message;
+
+        if is_paused {
+            self.pause_room(room_id);
+            0
+        } else {
+            self.resume_room(room_id, context)
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use super::GameRoomRouterActor;
+use crate::proto::MessageStream;
+
+impl GameRoomRouterActor {
+    // Server broadcasts to the clients of a sequenced room are numbered from 0 as the router takes
+    // them. A room is only ever routed by this actor, then by the single routing worker of its
+    // shard, so every member gets them in that order and can tell so from the numbers. Gaps are
+    // broadcasts filtered by interest or dropped on the way
+    pub(crate) fn stamp_room_sequence(&mut self, room_id: u32, message: &mut MessageStream) {
+        if !self.room_configs.is_sequenced(room_id) {
+            return;
+        }
+
+        let room_sequence = self.room_sequences.entry(room_id).or_default();
+        message.header_options.room_sequence = Some(*room_sequence);
+        *room_sequence = room_sequence.wrapping_add(1);
+    }
+}
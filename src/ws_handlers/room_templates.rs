@@ -0,0 +1,144 @@
+use super::room_windows::RoomWindow;
+use super::{GameRoomRouterActor, RoomConfig, RoomSchedule};
+use crate::proto::{InfoCode, MessageStream, PartyId, ALL_ROOMS_ID, LOBBY_ROOM_ID};
+use crate::{anyerror, AnyResult};
+use actix::Context;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// A kind of room of the --config file, e.g. "ranked-2v2" or "lobby-64", the rooms provisioned
+// from it sharing its settings and metadata
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RoomTemplate {
+    #[serde(default)]
+    pub(crate) config: RoomConfig, // Under the settings of the rooms entry of the same room
+    pub(crate) metadata: Option<serde_json::Value>, // Handed to the server as JSON
+    pub(crate) schedule: Option<RoomSchedule>,      // Always available when unset
+}
+
+// Room created by the router at startup rather than announced by the server
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ProvisionedRoom {
+    pub(crate) template: String,
+    pub(crate) metadata: Arc<[u8]>, // JSON, empty when the template has none
+    pub(crate) window: Option<RoomWindow>,
+}
+
+impl ProvisionedRoom {
+    // Room ID -> Template name, the names must be known and fit a u8 length
+    pub(crate) fn provision(
+        templates: &BTreeMap<String, RoomTemplate>,
+        provisioned_rooms: &BTreeMap<u32, String>,
+    ) -> AnyResult<BTreeMap<u32, Self>> {
+        let mut result = BTreeMap::new();
+
+        for (room_id, template_name) in provisioned_rooms {
+            if [LOBBY_ROOM_ID, ALL_ROOMS_ID].contains(room_id) {
+                return Err(anyerror!("Room {} cannot be provisioned", room_id));
+            }
+
+            let template = templates
+                .get(template_name)
+                .ok_or_else(|| anyerror!("Unknown room template {}", template_name))?;
+
+            if template_name.len() > u8::MAX as usize {
+                return Err(anyerror!("Room template name too long: {}", template_name));
+            }
+
+            let metadata = match template.metadata.as_ref() {
+                Some(metadata) => serde_json::to_vec(metadata)?,
+                None => Vec::new(),
+            };
+            let window = template.schedule.as_ref().map(RoomWindow::parse).transpose()?;
+            let provisioned_room =
+                Self { template: template_name.clone(), metadata: metadata.into(), window };
+            result.insert(*room_id, provisioned_room);
+        }
+
+        Ok(result)
+    }
+
+    // The u8 length of the template name, the name, then the metadata
+    fn details(&self) -> Vec<u8> {
+        let mut details = vec![self.template.len() as u8];
+        details.extend_from_slice(self.template.as_bytes());
+        details.extend_from_slice(&self.metadata);
+
+        details
+    }
+}
+
+impl GameRoomRouterActor {
+    pub(crate) fn with_provisioned_rooms(
+        mut self,
+        provisioned_rooms: BTreeMap<u32, ProvisionedRoom>,
+    ) -> Self {
+        self.provisioned_rooms = provisioned_rooms;
+        self
+    }
+
+    // Available from the start of the router, before the server announces anything, scheduled
+    // ones only inside their window
+    pub(crate) fn publish_provisioned_rooms(&mut self, context: &mut Context<Self>) {
+        if self.provisioned_rooms.is_empty() {
+            return;
+        }
+
+        info!("Provisioned {} rooms from their template", self.provisioned_rooms.len());
+
+        self.start_room_windows(context);
+        let room_ids: Vec<u32> = self.provisioned_rooms.keys().copied().collect();
+        self.add_available_rooms(&room_ids);
+    }
+
+    // Every time the primary server connects, it learns the template of each provisioned room
+    pub(crate) fn announce_provisioned_rooms(&mut self) {
+        let provisioned_infos: Vec<MessageStream> = self
+            .provisioned_rooms
+            .iter()
+            .map(|(room_id, provisioned_room)| {
+                MessageStream::new_info(
+                    *room_id,
+                    PartyId::Server(0),
+                    InfoCode::RoomProvisioned,
+                    &provisioned_room.details(),
+                )
+            })
+            .collect();
+
+        for provisioned_info in provisioned_infos {
+            self.send_to_server_id(0, PartyId::Server(0), provisioned_info);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provisioned_room_is_as_expected() {
+        let templates: BTreeMap<String, RoomTemplate> = serde_json::from_str(
+            r#"{
+                "ranked-2v2": {"config": {"max_clients": 4}, "metadata": {"mode": "ranked"}},
+                "lobby-64": {"config": {"max_clients": 64}}
+            }"#,
+        )
+        .unwrap();
+        let provisioned_rooms =
+            vec![(1, "ranked-2v2".to_string()), (2, "lobby-64".to_string())].into_iter().collect();
+        let provisioned = ProvisionedRoom::provision(&templates, &provisioned_rooms).unwrap();
+
+        assert_eq!(provisioned[&1].details(), b"\x0aranked-2v2{\"mode\":\"ranked\"}".to_vec());
+        assert_eq!(provisioned[&2].details(), b"\x08lobby-64".to_vec());
+
+        let unknown_template = vec![(3, "casual".to_string())].into_iter().collect();
+        assert!(ProvisionedRoom::provision(&templates, &unknown_template).is_err());
+        let lobby_room = vec![(LOBBY_ROOM_ID, "lobby-64".to_string())].into_iter().collect();
+        assert!(ProvisionedRoom::provision(&templates, &lobby_room).is_err());
+        assert!(serde_json::from_str::<RoomTemplate>(r#"{"max_clients": 4}"#).is_err());
+    }
+}
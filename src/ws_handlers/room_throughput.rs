@@ -0,0 +1,99 @@
+use super::GameRoomRouterActor;
+use crate::proto::{ErrorCode, InfoCode, MessageStream, PartyId};
+use actix::clock::{Duration, Instant};
+use log::warn;
+use std::collections::BTreeMap;
+
+// Throughput caps of a room are counted over windows this long
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+
+// Traffic of the members of a room in the current window
+#[derive(Debug)]
+pub(crate) struct RoomThroughput {
+    window_start: Instant,
+    frames: u32,
+    bytes: u64,
+    senders: BTreeMap<u32, (u32, u64)>, // Client Party ID -> Frames and bytes sent, dropped included
+    is_reported: bool,
+}
+
+impl RoomThroughput {
+    fn new(window_start: Instant) -> Self {
+        Self { window_start, frames: 0, bytes: 0, senders: Default::default(), is_reported: false }
+    }
+}
+
+impl GameRoomRouterActor {
+    // Normal frames of the clients count towards the caps the server set on their room, across
+    // every member. Frames past either cap are dropped until the next window, the sender being
+    // replied a RoomThrottled error, and the server is told once per window with a Throttled info
+    // naming the member that sent the most bytes
+    pub(crate) fn admit_room_throughput(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> bool {
+        let client_party_id = match origin_party_id {
+            PartyId::Client(client_party_id) => client_party_id,
+            _ => return true,
+        };
+        let room_id = message.room_id;
+        let (max_frames, max_bytes) = self.room_configs.max_throughput(room_id);
+
+        if max_frames.is_none() && max_bytes.is_none() {
+            return true;
+        }
+
+        let now = Instant::now();
+        let room_throughput =
+            self.room_throughputs.entry(room_id).or_insert_with(|| RoomThroughput::new(now));
+
+        if now.duration_since(room_throughput.window_start) >= THROUGHPUT_WINDOW {
+            *room_throughput = RoomThroughput::new(now);
+        }
+
+        let length = message.raw_length() as u64;
+        let sender = room_throughput.senders.entry(client_party_id).or_default();
+        sender.0 += 1;
+        sender.1 += length;
+
+        if max_frames.is_none_or(|max_frames| room_throughput.frames < max_frames)
+            && max_bytes.is_none_or(|max_bytes| room_throughput.bytes + length <= max_bytes)
+        {
+            room_throughput.frames += 1;
+            room_throughput.bytes += length;
+            return true;
+        }
+
+        let window_left = THROUGHPUT_WINDOW - now.duration_since(room_throughput.window_start);
+        let offender = match room_throughput.is_reported {
+            true => None,
+            false => room_throughput.senders.iter().max_by_key(|(_, (_, bytes))| *bytes),
+        }
+        .map(|(offender_party_id, (frames, bytes))| (*offender_party_id, *frames, *bytes));
+        room_throughput.is_reported = true;
+        self.reply_error(
+            room_id,
+            origin_party_id,
+            ErrorCode::RoomThrottled,
+            &(window_left.as_millis() as u32).to_le_bytes(),
+        );
+
+        if let Some((offender_party_id, frames, bytes)) = offender {
+            warn!(
+                "Room {} throttled, client {} sent {} frames of {} bytes",
+                room_id, offender_party_id, frames, bytes
+            );
+
+            let mut details = offender_party_id.to_le_bytes().to_vec();
+            details.extend_from_slice(&frames.to_le_bytes());
+            details.extend_from_slice(&(bytes.min(u32::MAX as u64) as u32).to_le_bytes());
+            let server_party_id = PartyId::Server(self.room_server_id(room_id));
+            let throttled_info =
+                MessageStream::new_info(room_id, server_party_id, InfoCode::Throttled, &details);
+            self.send_to_server(PartyId::Server(0), throttled_info);
+        }
+
+        false
+    }
+}
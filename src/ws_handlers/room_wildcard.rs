@@ -0,0 +1,44 @@
+use super::GameRoomRouterActor;
+use crate::proto::{MessageStream, PartyId, LOBBY_ROOM_ID};
+use actix::Context;
+use log::warn;
+
+impl GameRoomRouterActor {
+    // A client broadcast from a server to the wildcard room is routed once per room it owns, with
+    // the room ID of each room. The lobby is left out
+    pub(crate) fn broadcast_to_owned_rooms(
+        &mut self,
+        origin_party_id: PartyId,
+        message_stream: MessageStream,
+        context: &mut Context<Self>,
+    ) {
+        let server_id = match (origin_party_id, message_stream.destination_id) {
+            (PartyId::Server(server_id), PartyId::AllClients | PartyId::AllClientsWithEcho) => {
+                server_id
+            }
+            _ => {
+                warn!(
+                    "Party ID {} sent a wildcard room frame to {:?}, only servers may broadcast \
+                     to every room",
+                    origin_party_id.get_repr(),
+                    message_stream.destination_id
+                );
+                return;
+            }
+        };
+        let owned_room_ids: Vec<u32> = self
+            .game_rooms
+            .keys()
+            .copied()
+            .filter(|room_id| {
+                *room_id != LOBBY_ROOM_ID && self.room_server_id(*room_id) == server_id
+            })
+            .collect();
+
+        for room_id in owned_room_ids {
+            let mut room_message = message_stream.clone();
+            room_message.room_id = room_id;
+            self.route_normal(origin_party_id, room_message, context);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use super::GameRoomRouterActor;
+use crate::cron::CronSchedule;
+use crate::proto::{InfoCode, MessageStream, PartyId};
+use crate::AnyResult;
+use actix::clock::Duration;
+use actix::{AsyncContext, Context};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+// Windows are checked again at least that often, far occurrences outlasting the timers
+const MAX_WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+// Cron expressions of a room template, its rooms being available from open to close, e.g.
+// {"open": "0 18 * * 5", "close": "0 22 * * 5"} on Fridays from 18:00 to 22:00 UTC
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RoomSchedule {
+    pub(crate) open: String,
+    pub(crate) close: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct RoomWindow {
+    open: CronSchedule,
+    close: CronSchedule,
+}
+
+impl RoomWindow {
+    pub(crate) fn parse(room_schedule: &RoomSchedule) -> AnyResult<Self> {
+        Ok(Self { open: room_schedule.open.parse()?, close: room_schedule.close.parse()? })
+    }
+
+    // Open when the window closes before it opens again, along with when that changes
+    fn state_at(&self, time: SystemTime) -> (bool, Option<SystemTime>) {
+        match (self.open.next_after(time), self.close.next_after(time)) {
+            (Some(next_open), Some(next_close)) if next_close < next_open => {
+                (true, Some(next_close))
+            }
+            (None, Some(next_close)) => (true, Some(next_close)),
+            (next_open, _) => (false, next_open),
+        }
+    }
+}
+
+impl GameRoomRouterActor {
+    // Scheduled rooms start out of the available rooms, then follow their window
+    pub(crate) fn start_room_windows(&mut self, context: &mut Context<Self>) {
+        let room_ids: Vec<u32> = self
+            .provisioned_rooms
+            .iter()
+            .filter(|(_, provisioned_room)| provisioned_room.window.is_some())
+            .map(|(room_id, _)| *room_id)
+            .collect();
+        self.closed_windows.extend(&room_ids);
+
+        for room_id in room_ids {
+            self.update_room_window(room_id, context);
+        }
+    }
+
+    fn update_room_window(&mut self, room_id: u32, context: &mut Context<Self>) {
+        let room_window = match self
+            .provisioned_rooms
+            .get(&room_id)
+            .and_then(|provisioned_room| provisioned_room.window.as_ref())
+        {
+            Some(room_window) => room_window,
+            None => return,
+        };
+        let now = SystemTime::now();
+        let (is_open, next_change) = room_window.state_at(now);
+        let was_open = !self.closed_windows.contains(&room_id);
+
+        if is_open && !was_open {
+            info!("Room {} window opened", room_id);
+            self.closed_windows.remove(&room_id);
+            self.add_available_rooms(&[room_id]);
+        } else if !is_open && was_open {
+            self.closed_windows.insert(room_id);
+            self.end_room_window(room_id, context);
+        }
+
+        if let Some(next_change) = next_change {
+            let delay = next_change.duration_since(now).unwrap_or_default();
+            context.run_later(delay.min(MAX_WINDOW_CHECK_INTERVAL), move |actor, context| {
+                actor.update_room_window(room_id, context)
+            });
+        }
+    }
+
+    // The members and the server are told with a RoomWindowClosed info, then the room is closed
+    fn end_room_window(&mut self, room_id: u32, context: &mut Context<Self>) {
+        info!("Room {} window closed", room_id);
+
+        let client_party_ids: Vec<u32> = self
+            .game_rooms
+            .get(&room_id)
+            .map(|room_clients| room_clients.keys().copied().collect())
+            .unwrap_or_default();
+
+        for client_party_id in client_party_ids {
+            let party_id = PartyId::Client(client_party_id);
+            let closed_info =
+                MessageStream::new_info(room_id, party_id, InfoCode::RoomWindowClosed, &[]);
+            self.send_to_client(room_id, client_party_id, PartyId::Server(0), closed_info);
+        }
+
+        let closed_info =
+            MessageStream::new_info(room_id, PartyId::Server(0), InfoCode::RoomWindowClosed, &[]);
+        self.send_to_server(PartyId::Server(0), closed_info);
+        self.close_room(room_id, context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_window_is_as_expected() {
+        let room_window = RoomWindow::parse(&RoomSchedule {
+            open: "0 18 * * 5".into(),
+            close: "0 22 * * 5".into(),
+        })
+        .unwrap();
+        let at = |source: &str| humantime::parse_rfc3339(source).unwrap();
+
+        // Friday 3 May 2024
+        assert_eq!(
+            room_window.state_at(at("2024-05-03T17:59:00Z")),
+            (false, Some(at("2024-05-03T18:00:00Z")))
+        );
+        assert_eq!(
+            room_window.state_at(at("2024-05-03T18:00:00Z")),
+            (true, Some(at("2024-05-03T22:00:00Z")))
+        );
+        assert_eq!(
+            room_window.state_at(at("2024-05-03T22:00:00Z")),
+            (false, Some(at("2024-05-10T18:00:00Z")))
+        );
+        assert!(
+            RoomWindow::parse(&RoomSchedule { open: "0 18 * *".into(), close: "".into() }).is_err()
+        );
+    }
+}
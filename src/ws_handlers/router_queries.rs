@@ -0,0 +1,57 @@
+use super::GameRoomRouterActor;
+use actix::{Handler as MessageHandler, Message};
+
+// Whether the primary server is joined, clients are refused until it is
+#[derive(Debug, Message)]
+#[rtype(result = "bool")]
+pub(crate) struct GetServerJoined;
+
+// Claims the primary server for an upgrade about to start, taking over the joined one when true.
+// Replies whether the joined server is taken over, None when it is joined and not taken over
+#[derive(Debug, Message)]
+#[rtype(result = "Option<bool>")]
+pub(crate) struct ClaimServer(pub(crate) bool);
+
+// Gives back the claim of an upgrade that failed, a failed takeover keeps the joined server
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub(crate) struct ReleaseServer;
+
+impl GameRoomRouterActor {
+    pub(crate) fn is_room_available(&self, room_id: u32) -> bool {
+        self.available_rooms.contains(&room_id)
+    }
+}
+
+impl MessageHandler<GetServerJoined> for GameRoomRouterActor {
+    type Result = bool;
+
+    fn handle(&mut self, _: GetServerJoined, _: &mut Self::Context) -> Self::Result {
+        self.server_joined
+    }
+}
+
+impl MessageHandler<ClaimServer> for GameRoomRouterActor {
+    type Result = Option<bool>;
+
+    fn handle(&mut self, message: ClaimServer, _: &mut Self::Context) -> Self::Result {
+        let ClaimServer(takeover) = message;
+        let is_takeover = takeover && self.server_joined;
+
+        if self.server_joined && !is_takeover {
+            return None;
+        }
+
+        self.server_joined = true;
+
+        Some(is_takeover)
+    }
+}
+
+impl MessageHandler<ReleaseServer> for GameRoomRouterActor {
+    type Result = ();
+
+    fn handle(&mut self, _: ReleaseServer, _: &mut Self::Context) -> Self::Result {
+        self.server_joined = false;
+    }
+}
@@ -0,0 +1,72 @@
+use super::{deliver, InterActorMessage, PartyRecipient};
+use crate::proto::{MessageStream, PartyId};
+use crate::telemetry::TraceContext;
+use actix::{
+    Actor, Addr as ActorAddress, Handler as MessageHandler, Message, SyncArbiter, SyncContext,
+};
+
+// Client deliveries of one routed frame, handed over to the worker of its room
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+struct FanOut {
+    recipients: Vec<PartyRecipient>,
+    origin_party_id: PartyId,
+    message: MessageStream,
+    route_trace: Option<TraceContext>,
+}
+
+// Copies the frame for every recipient on a thread of its own, out of the router
+#[derive(Debug, Default)]
+struct RoutingWorker;
+
+impl Actor for RoutingWorker {
+    type Context = SyncContext<Self>;
+}
+
+impl MessageHandler<FanOut> for RoutingWorker {
+    type Result = ();
+
+    fn handle(&mut self, fan_out: FanOut, _: &mut Self::Context) {
+        let FanOut { recipients, origin_party_id, message, route_trace } = fan_out;
+
+        // Overflows are only counted, the server is not warned from here
+        for recipient in recipients {
+            deliver(
+                &recipient,
+                InterActorMessage::NewMessage(
+                    origin_party_id,
+                    message.clone(),
+                    route_trace.map(TraceContext::stamp),
+                ),
+            );
+        }
+    }
+}
+
+// One single threaded worker per shard, rooms are sharded by ID so the frames of a room keep
+// their order. Connection actors stay on their async arbiters
+#[derive(Clone, Debug)]
+pub(crate) struct RoutingPool {
+    workers: Vec<ActorAddress<RoutingWorker>>,
+}
+
+impl RoutingPool {
+    pub(crate) fn start(worker_count: usize) -> Self {
+        let workers =
+            (0..worker_count.max(1)).map(|_| SyncArbiter::start(1, || RoutingWorker)).collect();
+
+        Self { workers }
+    }
+
+    pub(crate) fn fan_out(
+        &self,
+        room_id: u32,
+        recipients: Vec<PartyRecipient>,
+        origin_party_id: PartyId,
+        message: MessageStream,
+        route_trace: Option<TraceContext>,
+    ) {
+        let fan_out = FanOut { recipients, origin_party_id, message, route_trace };
+        self.workers[room_id as usize % self.workers.len()].do_send(fan_out);
+    }
+}
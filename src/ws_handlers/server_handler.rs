@@ -1,107 +1,380 @@
-use crate::proto::{MessageStream, PartyId};
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::audit_log::{AuditAction, AUDIT_LOG, ROUTER_PRINCIPAL};
+use crate::proto::{
+    CompressionCodec, FrameFormat, FrameViolation, JsonEnvelope, MessageBatch, MessageStream,
+    PartyId, PayloadKind,
+};
+use crate::telemetry::HopSpan;
 use crate::ws_handlers::{
-    GameRoomRouterActor, InterActorMessage, HEARTBEAT_INTERVAL, MAILBOX_CAPACITY,
+    connection_span, deliver_to_router, FrameStrikes, GameRoomRouterActor, InterActorMessage,
+    MessageReassembly, OutboundLanes, WsTransport, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL,
+    MAILBOX_CAPACITY, MALFORMED_FRAMES_REASON, OUTBOUND_DRAIN_BUDGET,
 };
-use crate::CLIENT_TIMEOUT;
-use actix::clock::Instant;
+use crate::AnyResult;
+use actix::clock::{Duration, Instant};
+use actix::dev::ToEnvelope;
 use actix::{
     Actor as ActixActor, ActorContext, Addr as ActorAddress, AsyncContext, Handler, Running,
     StreamHandler as ReceiveHandler,
 };
 use actix_web_actors::ws::{
-    CloseReason, Message as WsMessage, ProtocolError as WsProtocolError, WebsocketContext,
+    CloseCode, CloseReason, Message as WsMessage, ProtocolError as WsProtocolError,
+    WebsocketContext,
 };
 use log::{info, warn};
+use tracing::Span;
 use uuid::Uuid;
 
+// Socket a game server is served over, the routing and heartbeat logic of `ServerActor` is the
+// same whatever the transport
+pub(crate) trait ServerTransport: Sized + Unpin + 'static {
+    type Context: ActorContext
+        + AsyncContext<ServerActor<Self>>
+        + ToEnvelope<ServerActor<Self>, InterActorMessage>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize);
+    fn send(&mut self, context: &mut Self::Context, frame: WsMessage); // Binary or text frames
+    fn ping(&mut self, context: &mut Self::Context);
+    fn close(&mut self, context: &mut Self::Context, reason: Option<CloseReason>);
+}
+
+impl ServerTransport for WsTransport {
+    type Context = WebsocketContext<ServerActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, context: &mut Self::Context, frame: WsMessage) {
+        match frame {
+            WsMessage::Text(text) => context.text(text),
+            WsMessage::Binary(binary) => context.binary(binary),
+            _ => (),
+        }
+    }
+
+    fn ping(&mut self, context: &mut Self::Context) {
+        context.ping(b"");
+    }
+
+    fn close(&mut self, context: &mut Self::Context, reason: Option<CloseReason>) {
+        context.close(reason);
+    }
+}
+
 #[derive(Debug)]
-pub(crate) struct ServerActor {
+pub(crate) struct ServerActor<T: ServerTransport> {
     party_id: PartyId,
     client_id: Uuid,
     last_known_activity: Instant,
     router_actor: ActorAddress<GameRoomRouterActor>,
+    outbound_lanes: OutboundLanes,
+    accepted_codecs: Vec<CompressionCodec>,
+    frame_format: FrameFormat,
+    pub(crate) log_span: Span,
+    is_dismissed: bool, // Closed by the router itself, nothing to tell it on stop
+    transport: T,
+    mailbox_capacity: usize,
+    app_heartbeat: bool, // Heartbeat frames instead of pings, negotiated on the upgrade
+    frame_strikes: FrameStrikes,
+    message_reassembly: MessageReassembly,
 }
 
-impl ServerActor {
+impl<T: ServerTransport> ServerActor<T> {
     pub(crate) fn new(
         party_id: PartyId,
         client_id: Uuid,
         router_actor: ActorAddress<GameRoomRouterActor>,
+        accepted_codecs: Vec<CompressionCodec>,
+        frame_format: FrameFormat,
+        transport: T,
     ) -> Self {
-        Self { party_id, client_id, last_known_activity: Instant::now(), router_actor }
+        Self {
+            party_id,
+            client_id,
+            last_known_activity: Instant::now(),
+            router_actor,
+            outbound_lanes: Default::default(),
+            accepted_codecs,
+            frame_format,
+            log_span: connection_span(None, party_id, client_id),
+            is_dismissed: false,
+            transport,
+            mailbox_capacity: MAILBOX_CAPACITY,
+            app_heartbeat: false,
+            frame_strikes: Default::default(),
+            message_reassembly: Default::default(),
+        }
+    }
+
+    pub(crate) fn with_mailbox_capacity(mut self, mailbox_capacity: usize) -> Self {
+        self.mailbox_capacity = mailbox_capacity;
+        self
+    }
+
+    pub(crate) fn with_app_heartbeat(mut self, app_heartbeat: bool) -> Self {
+        self.app_heartbeat = app_heartbeat;
+        self
+    }
+
+    pub(crate) fn with_max_frame_violations(mut self, max_frame_violations: Option<u32>) -> Self {
+        self.frame_strikes = FrameStrikes::new(max_frame_violations);
+        self
+    }
+
+    pub(crate) fn with_max_message_length(mut self, max_message_length: usize) -> Self {
+        self.message_reassembly = MessageReassembly::new(Some(max_message_length));
+        self
+    }
+
+    // Told with a MalformedFrame error, and closed once out of strikes
+    fn reject_malformed(&mut self, violation: FrameViolation, context: &mut T::Context) {
+        let (error, is_out) = self.frame_strikes.strike(0, self.party_id, violation);
+
+        if let Ok(frame) = self.encode_outbound(error) {
+            self.transport.send(context, frame);
+        }
+
+        if is_out {
+            info!("Party ID {} closed for its malformed frames", self.party_id.get_repr());
+            let reason = CloseReason {
+                code: CloseCode::Protocol,
+                description: Some(MALFORMED_FRAMES_REASON.into()),
+            };
+            self.close_and_disconnect(context, Some(reason));
+        }
     }
 
-    pub(crate) fn heartbeat(&self, context: &mut WebsocketContext<Self>) {
+    pub(crate) fn heartbeat(&self, context: &mut T::Context) {
         context.run_interval(HEARTBEAT_INTERVAL, |actor, context| {
+            let _log_span = actor.log_span.clone().entered();
+
             if Instant::now().duration_since(actor.last_known_activity) > CLIENT_TIMEOUT {
                 info!(
                     "Party ID {} kicked because of {:#?} inactivity!",
                     actor.party_id.get_repr(),
                     CLIENT_TIMEOUT,
                 );
-                Self::close_and_disconnect(context, None);
+                let reason = format!("{:#?} inactivity", CLIENT_TIMEOUT);
+                AUDIT_LOG.record(
+                    ROUTER_PRINCIPAL,
+                    AuditAction::Kick {
+                        room_id: None,
+                        party_id: actor.party_id.get_repr(),
+                        reason: reason.clone(),
+                    },
+                );
+                ADMIN_EVENTS.publish(AdminEvent::Kicked {
+                    room_id: None,
+                    party_id: actor.party_id.get_repr(),
+                    reason,
+                });
+                actor.close_and_disconnect(context, None);
+            } else if actor.app_heartbeat {
+                actor.send_heartbeat(context);
             } else {
-                context.ping(b"");
+                actor.transport.ping(context);
+            }
+        });
+    }
+
+    // Straight to the transport like a ping, ahead of the queued frames
+    fn send_heartbeat(&mut self, context: &mut T::Context) {
+        if let Ok(frame) = self.encode_outbound(MessageStream::new_heartbeat(0, self.party_id)) {
+            self.transport.send(context, frame);
+        }
+    }
+
+    pub(crate) fn schedule_outbound_drain(&mut self, context: &mut T::Context) {
+        if self.outbound_lanes.is_drain_scheduled {
+            return;
+        }
+
+        self.outbound_lanes.is_drain_scheduled = true;
+        context.run_later(Duration::from_millis(0), |actor, context| {
+            let _log_span = actor.log_span.clone().entered();
+            actor.outbound_lanes.is_drain_scheduled = false;
+
+            for (message, trace_context) in actor.outbound_lanes.pop_budgeted(OUTBOUND_DRAIN_BUDGET)
+            {
+                let ack = message
+                    .header_options
+                    .ack_sequence
+                    .map(|ack_sequence| (message.room_id, message.origin_id, ack_sequence));
+
+                let is_delivered = match actor.encode_outbound(message) {
+                    Ok(frame) => {
+                        actor.transport.send(context, frame);
+                        true
+                    }
+                    Err(error) => {
+                        warn!(
+                            "Dropping undecodable message for Party ID {}: {}",
+                            actor.party_id.get_repr(),
+                            error
+                        );
+                        false
+                    }
+                };
+
+                // Acknowledged once handed to the transport, or told undecodable
+                if let Some((room_id, origin_party_id, ack_sequence)) = ack {
+                    deliver_to_router(
+                        &actor.router_actor,
+                        InterActorMessage::DeliveryReport(
+                            room_id,
+                            origin_party_id,
+                            actor.party_id,
+                            ack_sequence,
+                            is_delivered,
+                        ),
+                    );
+                }
+
+                if let Some(trace_context) = trace_context {
+                    HopSpan::follow("deliver", trace_context)
+                        .with_attribute("party_id", actor.party_id.get_repr())
+                        .end();
+                }
+            }
+
+            if !actor.outbound_lanes.is_empty() {
+                actor.schedule_outbound_drain(context);
             }
         });
     }
 
+    // JSON text frames never carry a compressed payload
+    pub(crate) fn encode_outbound(&self, mut message: MessageStream) -> AnyResult<WsMessage> {
+        match self.frame_format {
+            FrameFormat::Binary => {
+                message.decompress_unless_accepted(&self.accepted_codecs)?;
+
+                Ok(WsMessage::Binary(message.into_raw().into()))
+            }
+            FrameFormat::Json => {
+                message.decompress()?;
+
+                Ok(WsMessage::Text(JsonEnvelope::to_text(message)?))
+            }
+        }
+    }
+
+    pub(crate) fn forward_inbound(&self, mut message_stream: MessageStream) {
+        // Counted as activity on receipt already, heartbeats go no further
+        if message_stream.is_heartbeat() {
+            return;
+        }
+
+        let ingress_span = HopSpan::ingress().map(|ingress_span| {
+            ingress_span
+                .with_attribute("room_id", message_stream.room_id)
+                .with_attribute("party_id", self.party_id.get_repr())
+                .with_attribute("payload_kind", u8::from(message_stream.payload_kind))
+                .with_attribute("payload_length", message_stream.payload.len() as i64)
+        });
+        let trace_context = ingress_span.as_ref().map(HopSpan::hand_over);
+
+        // Encrypted batches are not unpacked, the router refuses them
+        if message_stream.payload_kind == PayloadKind::Batch
+            && !message_stream.is_opaque()
+            && message_stream.decompress().is_ok()
+        {
+            // Inbound batches are routed frame by frame, all of them in the same trace
+            if let Ok(batched_messages) = MessageBatch::unpack(&message_stream) {
+                for batched_message in batched_messages {
+                    deliver_to_router(
+                        &self.router_actor,
+                        InterActorMessage::NewMessage(
+                            self.party_id,
+                            batched_message,
+                            trace_context,
+                        ),
+                    );
+                }
+            }
+        } else {
+            deliver_to_router(
+                &self.router_actor,
+                InterActorMessage::NewMessage(self.party_id, message_stream, trace_context),
+            );
+        }
+
+        if let Some(ingress_span) = ingress_span {
+            ingress_span.end();
+        }
+    }
+
     pub(crate) fn update_last_known_activity(&mut self) {
         self.last_known_activity = Instant::now();
     }
 
     pub(crate) fn close_and_disconnect(
-        context: &mut WebsocketContext<Self>,
+        &mut self,
+        context: &mut T::Context,
         reason: Option<CloseReason>,
     ) {
-        context.close(reason);
+        self.transport.close(context, reason);
         context.stop();
     }
 }
 
-impl ActixActor for ServerActor {
-    type Context = WebsocketContext<Self>;
+impl<T: ServerTransport> ActixActor for ServerActor<T> {
+    type Context = T::Context;
 
     fn started(&mut self, context: &mut Self::Context) {
-        context.set_mailbox_capacity(MAILBOX_CAPACITY);
+        T::set_mailbox_capacity(context, self.mailbox_capacity);
         self.heartbeat(context);
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        self.router_actor
-            .do_send(InterActorMessage::Disconnect(self.party_id, Some(self.client_id)));
+        if !self.is_dismissed {
+            self.router_actor
+                .do_send(InterActorMessage::Disconnect(self.party_id, Some(self.client_id)));
+        }
+
         Running::Stop
     }
 }
 
-impl Handler<InterActorMessage> for ServerActor {
+impl<T: ServerTransport> Handler<InterActorMessage> for ServerActor<T> {
     type Result = ();
 
     fn handle(&mut self, message: InterActorMessage, context: &mut Self::Context) {
         match message {
-            InterActorMessage::Disconnect(party_id, _) => {
-                if party_id == self.party_id {
-                    Self::close_and_disconnect(context, None);
-                }
+            InterActorMessage::Disconnect(party_id, _) if party_id == self.party_id => {
+                self.close_and_disconnect(context, None);
             }
-            InterActorMessage::NewMessage(_, binary_message) => {
-                context.binary(binary_message.into_raw());
+            InterActorMessage::CloseConnection(party_id, description)
+                if party_id == self.party_id =>
+            {
+                info!("Party ID {} closed: {}", party_id.get_repr(), description);
+                self.is_dismissed = true;
+                let reason =
+                    CloseReason { code: CloseCode::Normal, description: Some(description) };
+                self.close_and_disconnect(context, Some(reason));
+            }
+            InterActorMessage::NewMessage(_, binary_message, trace_context) => {
+                self.outbound_lanes.push(binary_message, trace_context);
+                self.schedule_outbound_drain(context);
             }
             _ => (),
         }
     }
 }
 
-impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ServerActor {
+impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ServerActor<WsTransport> {
     fn handle(
         &mut self,
         stream_result: Result<WsMessage, WsProtocolError>,
         context: &mut Self::Context,
     ) {
+        let _log_span = self.log_span.clone().entered();
+
         if let Ok(payload) = stream_result {
             match payload {
                 WsMessage::Close(reason) => {
-                    Self::close_and_disconnect(context, reason);
+                    self.close_and_disconnect(context, reason);
                 }
                 WsMessage::Pong(_) => self.update_last_known_activity(),
                 WsMessage::Ping(ping_payload) => {
@@ -111,9 +384,31 @@ impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ServerActor {
                 WsMessage::Binary(binary_payload) => {
                     self.update_last_known_activity();
 
-                    if let Ok(message_stream) = MessageStream::from_raw(&binary_payload) {
-                        self.router_actor
-                            .do_send(InterActorMessage::NewMessage(self.party_id, message_stream));
+                    match MessageStream::from_raw(&binary_payload) {
+                        Ok(message_stream) => self.forward_inbound(message_stream),
+                        Err(error) => {
+                            warn!(
+                                "Party ID {} sent a malformed frame: {}",
+                                self.party_id.get_repr(),
+                                error
+                            );
+                            self.reject_malformed(FrameViolation::of_raw(&binary_payload), context);
+                        }
+                    }
+                }
+                WsMessage::Text(text_payload) if self.frame_format == FrameFormat::Json => {
+                    self.update_last_known_activity();
+
+                    match JsonEnvelope::from_text(&text_payload) {
+                        Ok(message_stream) => self.forward_inbound(message_stream),
+                        Err(error) => {
+                            warn!(
+                                "Party ID {} sent an invalid JSON envelope: {}",
+                                self.party_id.get_repr(),
+                                error
+                            );
+                            self.reject_malformed(FrameViolation::BadJsonEnvelope, context);
+                        }
                     }
                 }
                 WsMessage::Text(text_payload) => {
@@ -124,10 +419,22 @@ impl ReceiveHandler<Result<WsMessage, WsProtocolError>> for ServerActor {
                         text_payload
                     ));
                 }
+                WsMessage::Continuation(item) => match self.message_reassembly.push(item) {
+                    Ok(Some(message)) => ReceiveHandler::handle(self, Ok(message), context),
+                    Ok(None) => self.update_last_known_activity(),
+                    Err(reason) => {
+                        warn!(
+                            "Party ID {} sent a fragmented message refused: {:?}",
+                            self.party_id.get_repr(),
+                            reason
+                        );
+                        self.close_and_disconnect(context, Some(reason));
+                    }
+                },
                 _ => (),
             }
         } else {
-            Self::close_and_disconnect(context, None);
+            self.close_and_disconnect(context, None);
         }
     }
 }
@@ -0,0 +1,118 @@
+use super::{GameRoomRouterActor, InterActorMessage, PartyRecipient};
+use crate::proto::{InfoCode, PartyId};
+use actix::clock::Duration;
+use actix::{AsyncContext, Context};
+use log::info;
+
+pub(crate) const SERVER_TAKEN_OVER_REASON: &str = "Server taken over";
+
+impl GameRoomRouterActor {
+    // Rooms and clients are kept as is, frames sent to the server are answered with an error
+    pub(crate) fn begin_server_grace(
+        &mut self,
+        server_reconnect_grace: Duration,
+        context: &mut Context<Self>,
+    ) {
+        if let Some(grace_handle) = self.server_grace_handle.take() {
+            context.cancel_future(grace_handle);
+        }
+
+        info!("Server left, keeping the rooms for {:#?}", server_reconnect_grace);
+        let grace_handle = context.run_later(server_reconnect_grace, |actor, context| {
+            actor.server_grace_handle = None;
+            info!("Server did not rejoin in time, dropping the rooms");
+            actor.drop_rooms(context);
+        });
+        self.server_grace_handle = Some(grace_handle);
+    }
+
+    // The rejoining server is told every client still in a room with the Join info it missed
+    pub(crate) fn end_server_grace(&mut self, context: &mut Context<Self>) {
+        let grace_handle = match self.server_grace_handle.take() {
+            Some(grace_handle) => grace_handle,
+            None => return,
+        };
+        context.cancel_future(grace_handle);
+
+        info!("Server rejoined");
+        self.send_room_snapshot();
+    }
+
+    // The new server already holds the handle, the previous one closes without dropping the rooms
+    pub(crate) fn hand_over_server(&mut self, previous_server_address: PartyRecipient) {
+        let _ = previous_server_address.do_send(InterActorMessage::CloseConnection(
+            PartyId::Server(0),
+            SERVER_TAKEN_OVER_REASON.into(),
+        ));
+
+        info!("Server taken over by a new connection");
+        self.send_room_snapshot();
+    }
+
+    // Membership and metadata of every room, as the Join infos a server would have seen
+    fn send_room_snapshot(&mut self) {
+        let join_infos: Vec<_> = self
+            .game_rooms
+            .iter()
+            .flat_map(|(room_id, room_clients)| {
+                room_clients.iter().map(move |(client_party_id, room_client)| {
+                    let party_id = PartyId::Client(*client_party_id);
+                    let join_info = Self::presence_info(
+                        InfoCode::Join,
+                        *room_id,
+                        party_id,
+                        room_client.client_id,
+                        &room_client.metadata,
+                    );
+
+                    (party_id, join_info)
+                })
+            })
+            .collect();
+
+        info!("Replaying {} client joins to the server", join_infos.len());
+
+        for (party_id, join_info) in join_infos {
+            self.send_to_server(party_id, join_info);
+        }
+    }
+
+    // Every client is disconnected and the rooms are forgotten until the server announces them,
+    // the provisioned ones inside their window aside
+    pub(crate) fn drop_rooms(&mut self, context: &mut Context<Self>) {
+        self.available_rooms = self
+            .provisioned_rooms
+            .keys()
+            .filter(|room_id| !self.closed_windows.contains(room_id))
+            .copied()
+            .collect();
+
+        // This will be a recursive call to the Disconnect branch
+        for (_, rooms) in self.game_rooms.iter() {
+            for (party_id_raw, room_client) in rooms.iter() {
+                let _ = room_client.address.do_send(InterActorMessage::Disconnect(
+                    PartyId::from_u32(*party_id_raw),
+                    Some(room_client.client_id),
+                ));
+            }
+        }
+
+        for (_, lockstep_room) in std::mem::take(&mut self.lockstep_rooms) {
+            lockstep_room.cancel_deadline(context);
+        }
+
+        self.interest_subscriptions.clear();
+        self.paused_rooms.clear();
+        self.measure_memory();
+        self.room_owners.clear();
+        self.server_loads.remove(&0);
+
+        for (_, expiry_handle) in std::mem::take(&mut self.empty_room_expiries) {
+            context.cancel_future(expiry_handle);
+        }
+
+        for room_id in self.reserved_slots.keys().copied().collect::<Vec<_>>() {
+            self.forget_reserved_slots(room_id, context);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use super::GameRoomRouterActor;
+use crate::proto::LOBBY_ROOM_ID;
+use actix::{AsyncContext, Context, Handler as MessageHandler, Message, SpawnHandle};
+use log::info;
+use uuid::Uuid;
+
+// Claims a party ID for a client about to join a room, a slot reserved for it is given back first.
+// The lobby is always available
+#[derive(Debug, Message)]
+#[rtype(result = "Result<u32, SlotRefusal>")]
+pub(crate) struct ClaimSlot(pub(crate) u32, pub(crate) Uuid); // (Room ID, Client ID)
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum SlotRefusal {
+    NoRoom,            // The room is not available
+    RoomFull,          // Members and reserved slots reached the room capacity
+    PartyIdsExhausted, // The server needs to rejoin to reset the party IDs of the room
+}
+
+// Slot of a client dropped from a room, only the same client UUID can claim it until it expires
+#[derive(Debug)]
+pub(crate) struct ReservedSlot {
+    client_party_id: u32,
+    expiry_handle: SpawnHandle,
+}
+
+impl GameRoomRouterActor {
+    // Room setting over --max-room-clients, None when the room takes any number of clients
+    pub(crate) fn room_capacity(&self, room_id: u32) -> Option<usize> {
+        self.room_configs
+            .max_clients(room_id)
+            .unwrap_or(self.config.max_room_clients)
+            .filter(|_| room_id != LOBBY_ROOM_ID)
+    }
+
+    // Reserved slots count as members, the lobby is never full
+    pub(crate) fn is_room_full(&self, room_id: u32) -> bool {
+        let max_room_clients = match self.room_capacity(room_id) {
+            Some(max_room_clients) => max_room_clients,
+            None => return false,
+        };
+        let member_count = self.game_rooms.get(&room_id).map(|room_clients| room_clients.len());
+        let reserved_count = self.reserved_slots.get(&room_id).map(|room_slots| room_slots.len());
+
+        member_count.unwrap_or(0) + reserved_count.unwrap_or(0) >= max_room_clients
+    }
+
+    pub(crate) fn claim_slot(
+        &mut self,
+        room_id: u32,
+        client_id: Uuid,
+        context: &mut Context<Self>,
+    ) -> Result<u32, SlotRefusal> {
+        if let Some(reserved_slot) = self.take_reserved_slot(room_id, client_id) {
+            context.cancel_future(reserved_slot.expiry_handle);
+            info!(
+                "Client {} reclaimed Party ID {} of room {}",
+                client_id, reserved_slot.client_party_id, room_id
+            );
+
+            return Ok(reserved_slot.client_party_id);
+        }
+
+        if self.is_room_full(room_id) {
+            return Err(SlotRefusal::RoomFull);
+        }
+
+        self.next_client_party_id(room_id).ok_or(SlotRefusal::PartyIdsExhausted)
+    }
+
+    // Kept for clients dropped from a room still available, a later drop replaces the reservation
+    pub(crate) fn reserve_slot(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        client_id: Uuid,
+        context: &mut Context<Self>,
+    ) {
+        let slot_reservation_ttl = match self.config.slot_reservation_ttl {
+            Some(slot_reservation_ttl) if room_id != LOBBY_ROOM_ID => slot_reservation_ttl,
+            _ => return,
+        };
+        if !self.is_room_available(room_id) {
+            return;
+        }
+
+        let expiry_handle = context.run_later(slot_reservation_ttl, move |actor, _| {
+            if actor.take_reserved_slot(room_id, client_id).is_some() {
+                info!("Reserved Party ID {} of room {} released", client_party_id, room_id);
+            }
+        });
+        let reserved_slot = ReservedSlot { client_party_id, expiry_handle };
+
+        if let Some(replaced_slot) =
+            self.reserved_slots.entry(room_id).or_default().insert(client_id, reserved_slot)
+        {
+            context.cancel_future(replaced_slot.expiry_handle);
+        }
+
+        info!("Party ID {} of room {} reserved for client {}", client_party_id, room_id, client_id);
+    }
+
+    fn take_reserved_slot(&mut self, room_id: u32, client_id: Uuid) -> Option<ReservedSlot> {
+        let room_slots = self.reserved_slots.get_mut(&room_id)?;
+        let reserved_slot = room_slots.remove(&client_id);
+
+        if room_slots.is_empty() {
+            self.reserved_slots.remove(&room_id);
+        }
+
+        reserved_slot
+    }
+
+    pub(crate) fn forget_reserved_slots(&mut self, room_id: u32, context: &mut Context<Self>) {
+        for (_, reserved_slot) in self.reserved_slots.remove(&room_id).unwrap_or_default() {
+            context.cancel_future(reserved_slot.expiry_handle);
+        }
+    }
+}
+
+impl MessageHandler<ClaimSlot> for GameRoomRouterActor {
+    type Result = Result<u32, SlotRefusal>;
+
+    fn handle(&mut self, message: ClaimSlot, context: &mut Self::Context) -> Self::Result {
+        let ClaimSlot(room_id, client_id) = message;
+
+        if room_id != LOBBY_ROOM_ID && !self.is_room_available(room_id) {
+            return Err(SlotRefusal::NoRoom);
+        }
+
+        self.claim_slot(room_id, client_id, context)
+    }
+}
@@ -0,0 +1,62 @@
+use super::{GameRoomRouterActor, PartyRecipient};
+use crate::admin_events::{AdminEvent, ADMIN_EVENTS};
+use crate::audit_log::{AuditAction, AUDIT_LOG, ROUTER_PRINCIPAL};
+use crate::proto::{InfoCode, PartyId};
+use actix::clock::Duration;
+use actix::Context;
+use uuid::Uuid;
+
+impl GameRoomRouterActor {
+    // Every membership of the lagging connection is told slow to the server before its Leave, so
+    // frames stop piling up for it. Namesakes over another connection are left alone
+    pub(crate) fn evict_slow_client(
+        &mut self,
+        client_id: Uuid,
+        address: &PartyRecipient,
+        outbound_lag: Duration,
+        context: &mut Context<Self>,
+    ) {
+        let memberships: Vec<(u32, u32)> = self
+            .game_rooms
+            .iter()
+            .flat_map(|(room_id, room_clients)| {
+                room_clients
+                    .iter()
+                    .filter(|(_, room_client)| {
+                        room_client.client_id == client_id && room_client.address == *address
+                    })
+                    .map(move |(client_party_id, _)| (*room_id, *client_party_id))
+            })
+            .collect();
+        let lag_millis = outbound_lag.as_millis().min(u32::MAX as u128) as u32;
+
+        for (room_id, client_party_id) in memberships {
+            let party_id = PartyId::Client(client_party_id);
+            let reason = format!("{:#?} outbound lag", outbound_lag);
+            AUDIT_LOG.record(
+                ROUTER_PRINCIPAL,
+                AuditAction::Kick {
+                    room_id: Some(room_id),
+                    party_id: client_party_id,
+                    reason: reason.clone(),
+                },
+            );
+            ADMIN_EVENTS.publish(AdminEvent::Kicked {
+                room_id: Some(room_id),
+                party_id: client_party_id,
+                reason,
+            });
+            self.send_to_server(
+                party_id,
+                Self::presence_info(
+                    InfoCode::SlowClient,
+                    room_id,
+                    party_id,
+                    client_id,
+                    &lag_millis.to_le_bytes(),
+                ),
+            );
+            self.disconnect_room_client(room_id, client_party_id, context);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use super::GameRoomRouterActor;
+use crate::proto::{MessageStream, PartyId};
+use log::warn;
+use std::iter;
+
+// Latest snapshot of a room along with the server broadcasts routed since, for the late joiners
+#[derive(Debug)]
+pub(crate) struct RoomSnapshot {
+    snapshot: MessageStream,
+    deltas: Vec<MessageStream>,
+}
+
+impl GameRoomRouterActor {
+    // A Snapshot frame of the server replaces the one of its room and is not routed, the members
+    // having that state from the broadcasts already
+    pub(crate) fn cache_snapshot(&mut self, room_id: u32, snapshot: MessageStream) {
+        self.room_snapshots.insert(room_id, RoomSnapshot { snapshot, deltas: Vec::new() });
+    }
+
+    // Server broadcasts to the clients of a room with a snapshot are its deltas, except those
+    // tagged with an interest key no joiner is subscribed to yet. Past --snapshot-max-deltas the
+    // snapshot is dropped, joiners getting none until the server sends the next one
+    pub(crate) fn record_delta(&mut self, room_id: u32, message: &MessageStream) {
+        let room_snapshot = match self.room_snapshots.get_mut(&room_id) {
+            Some(room_snapshot) if message.header_options.interest_key.is_none() => room_snapshot,
+            _ => return,
+        };
+
+        if self
+            .config
+            .snapshot_max_deltas
+            .is_some_and(|snapshot_max_deltas| room_snapshot.deltas.len() >= snapshot_max_deltas)
+        {
+            warn!("Room {} snapshot dropped after {} deltas", room_id, room_snapshot.deltas.len());
+            self.room_snapshots.remove(&room_id);
+            return;
+        }
+
+        room_snapshot.deltas.push(message.clone());
+    }
+
+    // Joiners get the snapshot then its deltas, ahead of the frames routed after their join
+    pub(crate) fn sync_room_state(&mut self, room_id: u32, client_party_id: u32) {
+        let messages: Vec<MessageStream> = match self.room_snapshots.get(&room_id) {
+            Some(room_snapshot) => {
+                iter::once(&room_snapshot.snapshot).chain(&room_snapshot.deltas).cloned().collect()
+            }
+            None => return,
+        };
+
+        for message in messages {
+            self.send_to_client(room_id, client_party_id, PartyId::Server(0), message);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use super::GameRoomRouterActor;
+use crate::proto::{ErrorCode, MessageStream, PartyId, StreamFrame, StreamKind};
+use log::warn;
+
+// Open stream of a sender to a single party, with the bytes it may still send
+#[derive(Debug)]
+pub(crate) struct StreamCredit {
+    destination_party_id: PartyId,
+    credit: u64,
+}
+
+impl GameRoomRouterActor {
+    // False when the Stream frame is dropped, the origin being replied an error. Streams to a
+    // single party are opened by their sender, then only carry the bytes their destination
+    // granted with Credit frames. Streams to a broadcast destination are routed as they come
+    pub(crate) fn admit_stream_frame(
+        &mut self,
+        origin_party_id: PartyId,
+        message: &MessageStream,
+    ) -> bool {
+        let room_id = message.room_id;
+        let stream_frame = match StreamFrame::from_raw(&message.payload) {
+            Ok(stream_frame) => stream_frame,
+            Err(error) => {
+                warn!("Party ID {} sent a bad stream frame: {}", origin_party_id.get_repr(), error);
+                self.reply_error(
+                    room_id,
+                    origin_party_id,
+                    ErrorCode::UndecodablePayload,
+                    &[message.payload_kind.into()],
+                );
+                return false;
+            }
+        };
+        let destination_party_id = message.destination_id;
+
+        if !destination_party_id.is_single_client_id()
+            && !destination_party_id.is_single_server_id()
+        {
+            return true;
+        }
+
+        let stream_id = stream_frame.stream_id;
+        let stream_key = (room_id, origin_party_id.get_repr(), stream_id);
+        // Credits and closes of the destination are keyed by the sender they go back to
+        let reverse_key = (room_id, destination_party_id.get_repr(), stream_id);
+
+        match stream_frame.kind {
+            StreamKind::Open => {
+                let open_streams = self
+                    .stream_credits
+                    .range((room_id, stream_key.1, 0)..=(room_id, stream_key.1, u32::MAX))
+                    .count();
+
+                if !self.stream_credits.contains_key(&stream_key)
+                    && self
+                        .config
+                        .max_streams
+                        .is_some_and(|max_streams| open_streams >= max_streams)
+                {
+                    self.reply_error(
+                        room_id,
+                        origin_party_id,
+                        ErrorCode::StreamRefused,
+                        &stream_id.to_le_bytes(),
+                    );
+                    return false;
+                }
+
+                self.stream_credits
+                    .insert(stream_key, StreamCredit { destination_party_id, credit: 0 });
+
+                true
+            }
+            StreamKind::Chunk => {
+                let chunk_length = stream_frame.data.len() as u64;
+                let error = match self.stream_credits.get_mut(&stream_key) {
+                    Some(stream_credit)
+                        if stream_credit.destination_party_id == destination_party_id
+                            && stream_credit.credit >= chunk_length =>
+                    {
+                        stream_credit.credit -= chunk_length;
+                        return true;
+                    }
+                    Some(stream_credit)
+                        if stream_credit.destination_party_id == destination_party_id =>
+                    {
+                        let mut details = stream_id.to_le_bytes().to_vec();
+                        details.extend_from_slice(
+                            &(stream_credit.credit.min(u32::MAX as u64) as u32).to_le_bytes(),
+                        );
+                        (ErrorCode::StreamCreditExhausted, details)
+                    }
+                    _ => (ErrorCode::StreamRefused, stream_id.to_le_bytes().to_vec()),
+                };
+                self.reply_error(room_id, origin_party_id, error.0, &error.1);
+
+                false
+            }
+            StreamKind::Close => {
+                for (stream_key, sender_party_id) in
+                    [(stream_key, destination_party_id), (reverse_key, origin_party_id)]
+                {
+                    if self.stream_credits.get(&stream_key).is_some_and(|stream_credit| {
+                        stream_credit.destination_party_id == sender_party_id
+                    }) {
+                        self.stream_credits.remove(&stream_key);
+                    }
+                }
+
+                true
+            }
+            // Credits for streams the router does not know of are left to the sender
+            StreamKind::Credit => {
+                if let Some(stream_credit) = self
+                    .stream_credits
+                    .get_mut(&reverse_key)
+                    .filter(|stream_credit| stream_credit.destination_party_id == origin_party_id)
+                {
+                    stream_credit.credit += stream_frame.credit() as u64;
+                }
+
+                true
+            }
+        }
+    }
+
+    // Streams from or to a party leaving the room, or every stream once the room is gone
+    pub(crate) fn forget_streams(&mut self, room_id: u32, party_id: Option<PartyId>) {
+        self.stream_credits.retain(|(stream_room_id, sender_party_id, _), stream_credit| {
+            *stream_room_id != room_id
+                || party_id.is_some_and(|party_id| {
+                    party_id.get_repr() != *sender_party_id
+                        && party_id != stream_credit.destination_party_id
+                })
+        });
+    }
+}
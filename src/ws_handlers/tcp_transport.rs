@@ -0,0 +1,158 @@
+use crate::ws_handlers::{ClientActor, ClientTransport};
+use actix::io::{SinkWrite, WriteHandler};
+use actix::{Context, StreamHandler as ReceiveHandler};
+use actix_codec::{Decoder, Encoder, Framed};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web_actors::ws::{CloseReason, Message as WsMessage};
+use futures::stream::SplitSink;
+use log::info;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+use tokio::net::TcpStream;
+
+const LENGTH_FRAME_PREFIX: usize = 4;
+// Same limit as the HTTP payloads, a larger prefix is a broken or hostile peer
+const MAX_TCP_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+pub(crate) type TcpSink = SplitSink<Framed<TcpStream, TcpFrameCodec>, Bytes>;
+
+// Frames prefixed with their u32 length (LE), an empty frame is a heartbeat
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct TcpFrameCodec;
+
+impl Decoder for TcpFrameCodec {
+    type Item = BytesMut;
+    type Error = IOError;
+
+    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if source.len() < LENGTH_FRAME_PREFIX {
+            return Ok(None);
+        }
+
+        let mut u32_bytes = [0u8; LENGTH_FRAME_PREFIX];
+        u32_bytes.copy_from_slice(&source[..LENGTH_FRAME_PREFIX]);
+        let frame_length = u32::from_le_bytes(u32_bytes) as usize;
+
+        if frame_length > MAX_TCP_FRAME_LENGTH {
+            return Err(IOError::new(
+                IOErrorKind::InvalidData,
+                format!("Frame of {} bytes exceeds {} bytes", frame_length, MAX_TCP_FRAME_LENGTH),
+            ));
+        }
+
+        if source.len() < LENGTH_FRAME_PREFIX + frame_length {
+            source.reserve(LENGTH_FRAME_PREFIX + frame_length - source.len());
+
+            return Ok(None);
+        }
+
+        let _ = source.split_to(LENGTH_FRAME_PREFIX);
+
+        Ok(Some(source.split_to(frame_length)))
+    }
+}
+
+impl Encoder<Bytes> for TcpFrameCodec {
+    type Error = IOError;
+
+    fn encode(&mut self, frame: Bytes, destination: &mut BytesMut) -> Result<(), Self::Error> {
+        destination.reserve(LENGTH_FRAME_PREFIX + frame.len());
+        destination.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        destination.extend_from_slice(&frame);
+
+        Ok(())
+    }
+}
+
+// Raw TCP clients skip the websocket framing, there are no close frames so the reason is logged
+pub(crate) struct TcpTransport {
+    sink: SinkWrite<Bytes, TcpSink>,
+}
+
+impl TcpTransport {
+    pub(crate) fn new(sink: SinkWrite<Bytes, TcpSink>) -> Self {
+        Self { sink }
+    }
+}
+
+impl ClientTransport for TcpTransport {
+    type Context = Context<ClientActor<Self>>;
+
+    fn set_mailbox_capacity(context: &mut Self::Context, capacity: usize) {
+        context.set_mailbox_capacity(capacity);
+    }
+
+    fn send(&mut self, _: &mut Self::Context, frame: WsMessage) {
+        let frame = match frame {
+            WsMessage::Binary(binary) => binary,
+            WsMessage::Text(text) => text.into(),
+            _ => return,
+        };
+
+        let _ = self.sink.write(frame);
+    }
+
+    fn ping(&mut self, _: &mut Self::Context) {
+        let _ = self.sink.write(Bytes::new());
+    }
+
+    fn close(&mut self, _: &mut Self::Context, reason: Option<CloseReason>) {
+        if let Some(description) = reason.and_then(|reason| reason.description) {
+            info!("TCP client closed: {}", description);
+        }
+
+        self.sink.close();
+    }
+}
+
+impl WriteHandler<IOError> for ClientActor<TcpTransport> {}
+
+// Empty frames only keep the connection alive, they are never answered
+impl ReceiveHandler<Result<BytesMut, IOError>> for ClientActor<TcpTransport> {
+    fn handle(&mut self, stream_result: Result<BytesMut, IOError>, context: &mut Self::Context) {
+        let _log_span = self.log_span.clone().entered();
+
+        match stream_result {
+            Ok(frame) if frame.is_empty() => self.update_last_known_activity(),
+            Ok(frame) => self.handle_inbound_binary(&frame, context),
+            Err(error) => {
+                info!("TCP client dropped: {}", error);
+                self.close_and_disconnect(context, None);
+            }
+        }
+    }
+
+    // The peer closed its side
+    fn finished(&mut self, context: &mut Self::Context) {
+        self.close_and_disconnect(context, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_frame_codec_is_as_expected() {
+        let mut codec = TcpFrameCodec;
+        let mut encoded = BytesMut::new();
+        codec.encode(Bytes::from_static(&[0xAA, 0xBB, 0xCC]), &mut encoded).unwrap();
+        codec.encode(Bytes::new(), &mut encoded).unwrap();
+
+        assert_eq!(&encoded[..], &[3, 0, 0, 0, 0xAA, 0xBB, 0xCC, 0, 0, 0, 0][..]);
+
+        // Partial frames wait for the rest
+        let mut partial = BytesMut::from(&encoded[..5]);
+
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+
+        partial.extend_from_slice(&encoded[5..]);
+
+        assert_eq!(codec.decode(&mut partial).unwrap().unwrap(), &[0xAA, 0xBB, 0xCC][..]);
+        assert_eq!(codec.decode(&mut partial).unwrap().unwrap(), &[][..]);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+
+        let mut oversized = BytesMut::from(&(MAX_TCP_FRAME_LENGTH as u32 + 1).to_le_bytes()[..]);
+
+        assert!(codec.decode(&mut oversized).is_err());
+    }
+}
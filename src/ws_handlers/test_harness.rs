@@ -0,0 +1,300 @@
+//! In-process harness for router tests: fake endpoints stand in for the websocket actors, so
+//! routing is asserted on delivered frames without binding any port. Every call waits until the
+//! router handled the message, and mailboxes are FIFO, so no sleep is ever needed.
+
+use crate::admin_events::{AdminEvent, AdminEventRecord, ADMIN_EVENTS};
+use crate::proto::{MessageCode, MessageStream, PartyId, PayloadKind, RelayPayload};
+use crate::ws_handlers::{
+    GameRoomRouterActor, GameRoomRouterConfig, GetRoomOccupancy, InterActorMessage,
+    MessageMiddleware, PartyRecipient, RelayConnected, RelayOut, RoomClient, SigningKey,
+};
+use actix::{
+    Actor as ActixActor, Addr as ActorAddress, Context, Handler as MessageHandler, Message,
+    MessageResult,
+};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Default)]
+pub(crate) struct FakeEndpoint {
+    delivered: Vec<MessageStream>,
+    is_disconnected: bool,
+    relayed: Vec<RelayPayload>, // When standing in for the relay to a peer
+    admin_events: Vec<AdminEvent>, // When standing in for an admin watching the events
+}
+
+impl ActixActor for FakeEndpoint {
+    type Context = Context<Self>;
+}
+
+impl MessageHandler<InterActorMessage> for FakeEndpoint {
+    type Result = ();
+
+    fn handle(&mut self, message: InterActorMessage, _: &mut Self::Context) {
+        match message {
+            InterActorMessage::NewMessage(_, message_stream, _) => {
+                self.delivered.push(message_stream)
+            }
+            InterActorMessage::Disconnect(_, _) | InterActorMessage::CloseConnection(_, _) => {
+                self.is_disconnected = true
+            }
+            _ => (),
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "(Vec<MessageStream>, bool)")]
+pub(crate) struct TakeDelivered;
+
+impl MessageHandler<TakeDelivered> for FakeEndpoint {
+    type Result = MessageResult<TakeDelivered>;
+
+    fn handle(&mut self, _: TakeDelivered, _: &mut Self::Context) -> Self::Result {
+        MessageResult((std::mem::take(&mut self.delivered), self.is_disconnected))
+    }
+}
+
+impl MessageHandler<RelayOut> for FakeEndpoint {
+    type Result = ();
+
+    fn handle(&mut self, message: RelayOut, _: &mut Self::Context) {
+        self.relayed.push(message.0);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<RelayPayload>")]
+pub(crate) struct TakeRelayed;
+
+impl MessageHandler<TakeRelayed> for FakeEndpoint {
+    type Result = MessageResult<TakeRelayed>;
+
+    fn handle(&mut self, _: TakeRelayed, _: &mut Self::Context) -> Self::Result {
+        MessageResult(std::mem::take(&mut self.relayed))
+    }
+}
+
+impl MessageHandler<AdminEventRecord> for FakeEndpoint {
+    type Result = ();
+
+    fn handle(&mut self, record: AdminEventRecord, _: &mut Self::Context) {
+        self.admin_events.push(record.event);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<AdminEvent>")]
+pub(crate) struct TakeAdminEvents;
+
+impl MessageHandler<TakeAdminEvents> for FakeEndpoint {
+    type Result = MessageResult<TakeAdminEvents>;
+
+    fn handle(&mut self, _: TakeAdminEvents, _: &mut Self::Context) -> Self::Result {
+        MessageResult(std::mem::take(&mut self.admin_events))
+    }
+}
+
+// Moves the next client party ID of a room past the clients joined by the harness, which do not
+// claim theirs
+#[derive(Message)]
+#[rtype(result = "()")]
+pub(crate) struct SetClientCounter(pub(crate) u32, pub(crate) u32); // (Room ID, Next Party ID)
+
+impl MessageHandler<SetClientCounter> for GameRoomRouterActor {
+    type Result = ();
+
+    fn handle(&mut self, message: SetClientCounter, _: &mut Self::Context) {
+        let SetClientCounter(room_id, next_client_party_id) = message;
+        self.client_counters.insert(room_id, next_client_party_id);
+    }
+}
+
+pub(crate) struct RouterHarness {
+    pub(crate) router: ActorAddress<GameRoomRouterActor>,
+    server: ActorAddress<FakeEndpoint>,
+    pub(crate) clients: BTreeMap<(u32, u32), ActorAddress<FakeEndpoint>>,
+}
+
+impl RouterHarness {
+    // Starts a router with a fake server already joined and announcing room_ids
+    pub(crate) async fn start(config: GameRoomRouterConfig, room_ids: &[u32]) -> Self {
+        Self::start_with_middlewares(config, room_ids, Vec::new()).await
+    }
+
+    pub(crate) async fn start_with_middlewares(
+        config: GameRoomRouterConfig,
+        room_ids: &[u32],
+        middlewares: Vec<Box<dyn MessageMiddleware>>,
+    ) -> Self {
+        let router = GameRoomRouterActor::new(config, None).with_middlewares(middlewares).start();
+        Self::start_with_router(router, room_ids).await
+    }
+
+    // Same, for a router built by the test
+    pub(crate) async fn start_with_router(
+        router: ActorAddress<GameRoomRouterActor>,
+        room_ids: &[u32],
+    ) -> Self {
+        let server = FakeEndpoint::default().start();
+        let result = Self { router, server, clients: BTreeMap::new() };
+        let room_list: Vec<u8> =
+            room_ids.iter().flat_map(|room_id| room_id.to_le_bytes()).collect();
+
+        result.connect_server().await;
+        result
+            .send_from(
+                PartyId::Server(0),
+                MessageStream::new(
+                    MessageCode::Special,
+                    0,
+                    PartyId::Server(0),
+                    PartyId::Server(0),
+                    PayloadKind::Info,
+                    Some(&room_list),
+                ),
+            )
+            .await;
+
+        result
+    }
+
+    // Joins a fake client and returns its client ID, the Join info it triggers is left for the
+    // server to take
+    pub(crate) async fn connect_client(&mut self, room_id: u32, client_party_id: u32) -> Uuid {
+        self.connect_client_with_metadata(room_id, client_party_id, &[]).await
+    }
+
+    pub(crate) async fn connect_client_with_metadata(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        metadata: &[u8],
+    ) -> Uuid {
+        let client_id = Uuid::new_v4();
+        self.connect_room_client(room_id, client_party_id, |address| {
+            RoomClient::new(client_id, address, metadata.into())
+        })
+        .await;
+
+        client_id
+    }
+
+    // Joins a fake client whose frames must be signed with the key derived from auth_token
+    pub(crate) async fn connect_signing_client(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        auth_token: &str,
+    ) {
+        self.connect_room_client(room_id, client_party_id, |address| {
+            RoomClient::new(Uuid::new_v4(), address, Vec::new().into())
+                .with_signing_key(Some(SigningKey::derive(auth_token)))
+        })
+        .await;
+    }
+
+    async fn connect_room_client(
+        &mut self,
+        room_id: u32,
+        client_party_id: u32,
+        room_client: impl FnOnce(PartyRecipient) -> RoomClient,
+    ) {
+        let client = FakeEndpoint::default().start();
+        self.inject(InterActorMessage::ClientConnect(
+            room_id,
+            PartyId::Client(client_party_id),
+            room_client(client.clone().recipient()),
+        ))
+        .await;
+        self.clients.insert((room_id, client_party_id), client);
+    }
+
+    // Joins the fake server, again after a Disconnect to rejoin
+    pub(crate) async fn connect_server(&self) {
+        self.inject(InterActorMessage::ServerConnect(
+            PartyId::Server(0),
+            self.server.clone().recipient(),
+        ))
+        .await;
+    }
+
+    // Joins a fake shard server next to the primary one
+    pub(crate) async fn connect_shard_server(&self, server_id: u32) -> ActorAddress<FakeEndpoint> {
+        let shard_server = FakeEndpoint::default().start();
+        self.inject(InterActorMessage::ServerConnect(
+            PartyId::Server(server_id),
+            shard_server.clone().recipient(),
+        ))
+        .await;
+
+        shard_server
+    }
+
+    // Connects a fake relay to the peer, it is told the members already there
+    pub(crate) async fn connect_relay(&self, peer_url: &str) -> ActorAddress<FakeEndpoint> {
+        let relay = FakeEndpoint::default().start();
+        self.router
+            .send(RelayConnected(peer_url.into(), relay.clone().recipient()))
+            .await
+            .expect("Router mailbox closed");
+
+        relay
+    }
+
+    // Subscribes a fake admin to the events of every router of the process, tests running at the
+    // same time publish theirs too so they are told apart by room ID
+    pub(crate) fn watch_admin_events(&self) -> ActorAddress<FakeEndpoint> {
+        let admin = FakeEndpoint::default().start();
+        ADMIN_EVENTS.subscribe(admin.clone().recipient());
+
+        admin
+    }
+
+    // Joins a new fake server over the current one, returns what the previous one was delivered
+    pub(crate) async fn take_over_server(&mut self) -> (Vec<MessageStream>, bool) {
+        let previous_server = std::mem::replace(&mut self.server, FakeEndpoint::default().start());
+        self.connect_server().await;
+
+        previous_server.send(TakeDelivered).await.expect("Server mailbox closed")
+    }
+
+    pub(crate) async fn available_rooms(&self) -> Vec<u32> {
+        let room_occupancy =
+            self.router.send(GetRoomOccupancy).await.expect("Router mailbox closed");
+
+        room_occupancy.into_iter().map(|room_occupancy| room_occupancy.room_id).collect()
+    }
+
+    pub(crate) async fn set_client_counter(&self, room_id: u32, next_client_party_id: u32) {
+        self.router
+            .send(SetClientCounter(room_id, next_client_party_id))
+            .await
+            .expect("Router mailbox closed");
+    }
+
+    pub(crate) async fn inject(&self, message: InterActorMessage) {
+        self.router.send(message).await.expect("Router mailbox closed");
+    }
+
+    pub(crate) async fn send_from(&self, origin_party_id: PartyId, message: MessageStream) {
+        self.inject(InterActorMessage::NewMessage(origin_party_id, message, None)).await;
+    }
+
+    // Frames delivered to the server since the last call
+    pub(crate) async fn take_server_delivered(&self) -> Vec<MessageStream> {
+        self.server.send(TakeDelivered).await.expect("Server mailbox closed").0
+    }
+
+    // Frames delivered to the client since the last call, and whether it was disconnected
+    pub(crate) async fn take_client_delivered(
+        &self,
+        room_id: u32,
+        client_party_id: u32,
+    ) -> (Vec<MessageStream>, bool) {
+        self.clients[&(room_id, client_party_id)]
+            .send(TakeDelivered)
+            .await
+            .expect("Client mailbox closed")
+    }
+}
@@ -0,0 +1,37 @@
+use crate::proto::{MessageStream, PartyId, TimeSync, TrafficRecord};
+use log::warn;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Result as IOResult, Write};
+use std::path::Path;
+
+// Append-only log of every frame entering the router, replayed with the replay subcommand
+#[derive(Debug)]
+pub(crate) struct TrafficRecorder {
+    writer: BufWriter<File>,
+}
+
+impl TrafficRecorder {
+    pub(crate) fn open(path: &Path) -> IOResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self { writer: BufWriter::new(file) })
+    }
+
+    pub(crate) fn record(&mut self, origin_id: PartyId, message_stream: &MessageStream) {
+        let traffic_record = TrafficRecord {
+            timestamp: TimeSync::now(),
+            origin_id,
+            message_stream: message_stream.clone(),
+        };
+
+        if let Err(error) = self.writer.write_all(&traffic_record.into_raw()) {
+            warn!("Failed to record traffic: {}", error);
+        }
+    }
+
+    pub(crate) fn flush(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            warn!("Failed to flush the traffic recording: {}", error);
+        }
+    }
+}
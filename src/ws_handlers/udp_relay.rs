@@ -0,0 +1,353 @@
+use crate::proto::{MessageReliability, MessageStream, UdpDatagram, UdpPayload};
+use actix::clock::{Duration, Instant};
+use actix::{Message, Recipient};
+use log::warn;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Result as IOResult;
+use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+use std::sync::{Arc, Mutex};
+use tokio::net::UdpSocket;
+use uuid::Uuid;
+
+// Reliable datagrams not acknowledged within this delay are sent again
+pub(crate) const UDP_RETRANSMIT_INTERVAL: Duration = Duration::from_millis(100);
+// Sends of a reliable datagram before it is given up
+const UDP_MAX_ATTEMPTS: u32 = 10;
+// Reliable datagrams waiting for their ack, the oldest ones are given up beyond
+const UDP_PENDING_CAPACITY: usize = 1024;
+// Received reliable sequences remembered to drop the resent ones, older ones are dropped as well
+const UDP_RECEIVED_WINDOW: u32 = 1024;
+// Frames larger than this go over the client connection, UDP fragmentation would lose them more
+const UDP_MAX_DATAGRAM_LENGTH: usize = 1200;
+const UDP_RECEIVE_BUFFER_LENGTH: usize = 64 * 1024;
+
+// Datagram whose token is the session of the receiving client connection
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub(crate) struct UdpReceived(pub(crate) SocketAddr, pub(crate) UdpDatagram); // Sender address
+
+type UdpSessions = Arc<Mutex<BTreeMap<[u8; 16], Recipient<UdpReceived>>>>;
+
+// Socket shared by every client connection, datagrams are told apart by their session token
+#[derive(Clone, Debug)]
+pub(crate) struct UdpRelay {
+    socket: Arc<StdUdpSocket>,
+    port: u16,
+    is_reliable: bool, // Reliable frames are sent over UDP as well, acknowledged and resent
+    sessions: UdpSessions,
+}
+
+impl UdpRelay {
+    // Sends never wait, a datagram the socket cannot take right away is lost like any other
+    pub(crate) fn bind(listen_socket: &str, is_reliable: bool) -> IOResult<Self> {
+        let socket = StdUdpSocket::bind(listen_socket)?;
+        socket.set_nonblocking(true)?;
+        let port = socket.local_addr()?.port();
+
+        Ok(Self { socket: Arc::new(socket), port, is_reliable, sessions: Default::default() })
+    }
+
+    // Hands the received datagrams over to the client connection of their session
+    pub(crate) fn start(&self) -> IOResult<()> {
+        let mut socket = UdpSocket::from_std(self.socket.try_clone()?)?;
+        let sessions = self.sessions.clone();
+
+        actix::spawn(async move {
+            let mut buffer = vec![0u8; UDP_RECEIVE_BUFFER_LENGTH];
+
+            loop {
+                let (length, peer_address) = match socket.recv_from(&mut buffer).await {
+                    Ok(received) => received,
+                    Err(error) => {
+                        warn!("UDP receive failed: {}", error);
+                        continue;
+                    }
+                };
+                let udp_datagram = match UdpDatagram::from_raw(&buffer[..length]) {
+                    Ok(udp_datagram) => udp_datagram,
+                    Err(_) => continue,
+                };
+                let session_address = match sessions.lock() {
+                    Ok(read_guard) => read_guard.get(&udp_datagram.token).cloned(),
+                    Err(_) => None,
+                };
+
+                if let Some(session_address) = session_address {
+                    let _ = session_address.do_send(UdpReceived(peer_address, udp_datagram));
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn open_session(&self, session_address: Recipient<UdpReceived>) -> UdpSession {
+        let token = *Uuid::new_v4().as_bytes();
+
+        if let Ok(mut write_guard) = self.sessions.lock() {
+            write_guard.insert(token, session_address);
+        }
+
+        UdpSession {
+            relay: self.clone(),
+            token,
+            peer_address: None,
+            next_sequence: 1,
+            pending_datagrams: BTreeMap::new(),
+            received_sequences: BTreeSet::new(),
+        }
+    }
+
+    fn send_to(&self, raw_datagram: &[u8], peer_address: SocketAddr) {
+        let _ = self.socket.send_to(raw_datagram, peer_address);
+    }
+}
+
+#[derive(Debug)]
+struct PendingDatagram {
+    raw_datagram: Vec<u8>,
+    sent_at: Instant,
+    attempts: u32,
+}
+
+// UDP side of one client connection, the session is closed when the connection drops it
+#[derive(Debug)]
+pub(crate) struct UdpSession {
+    relay: UdpRelay,
+    token: [u8; 16],
+    peer_address: Option<SocketAddr>, // Sender of the last datagram, the client may be re-NATed
+    next_sequence: u32,
+    pending_datagrams: BTreeMap<u32, PendingDatagram>, // Sequence -> Reliable datagram
+    received_sequences: BTreeSet<u32>,
+}
+
+impl UdpSession {
+    // Details of the UdpSession info telling the client its token
+    pub(crate) fn session_details(&self) -> Vec<u8> {
+        let mut details = self.token.to_vec();
+        details.extend_from_slice(&self.relay.port.to_le_bytes());
+
+        details
+    }
+
+    pub(crate) fn is_reliable(&self) -> bool {
+        self.relay.is_reliable
+    }
+
+    // Reliable datagrams are acknowledged and only handed over the first time
+    pub(crate) fn receive(
+        &mut self,
+        peer_address: SocketAddr,
+        udp_datagram: UdpDatagram,
+    ) -> Option<MessageStream> {
+        self.peer_address = Some(peer_address);
+
+        match udp_datagram.payload {
+            UdpPayload::Unreliable(message_stream) => Some(message_stream),
+            UdpPayload::Reliable(message_stream) => {
+                let ack = UdpDatagram {
+                    token: self.token,
+                    sequence: udp_datagram.sequence,
+                    payload: UdpPayload::Ack,
+                };
+                self.relay.send_to(&ack.into_raw(), peer_address);
+
+                if self.accept_sequence(udp_datagram.sequence) {
+                    Some(message_stream)
+                } else {
+                    None
+                }
+            }
+            UdpPayload::Ack => {
+                self.pending_datagrams.remove(&udp_datagram.sequence);
+                None
+            }
+        }
+    }
+
+    fn accept_sequence(&mut self, sequence: u32) -> bool {
+        let highest_sequence = self.received_sequences.iter().next_back().copied().unwrap_or(0);
+
+        if sequence.saturating_add(UDP_RECEIVED_WINDOW) <= highest_sequence
+            || !self.received_sequences.insert(sequence)
+        {
+            return false;
+        }
+
+        let oldest_sequence = highest_sequence.max(sequence).saturating_sub(UDP_RECEIVED_WINDOW);
+        self.received_sequences = self.received_sequences.split_off(&oldest_sequence);
+
+        true
+    }
+
+    // Frames are handed back when the client has not sent a datagram yet, when they are too
+    // large, or when they are reliable and reliable frames stay on the connection
+    pub(crate) fn send(&mut self, message: MessageStream) -> Result<(), Box<MessageStream>> {
+        let peer_address = match self.peer_address {
+            Some(peer_address) => peer_address,
+            None => return Err(Box::new(message)),
+        };
+        let is_reliable = message.reliability() == MessageReliability::Reliable;
+
+        if (is_reliable && !self.relay.is_reliable)
+            || message.raw_length() + UdpDatagram::LENGTH_UDP_HEADER > UDP_MAX_DATAGRAM_LENGTH
+        {
+            return Err(Box::new(message));
+        }
+
+        if !is_reliable {
+            let udp_datagram = UdpDatagram {
+                token: self.token,
+                sequence: 0,
+                payload: UdpPayload::Unreliable(message),
+            };
+            self.relay.send_to(&udp_datagram.into_raw(), peer_address);
+
+            return Ok(());
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1).max(1);
+        let raw_datagram =
+            UdpDatagram { token: self.token, sequence, payload: UdpPayload::Reliable(message) }
+                .into_raw();
+        self.relay.send_to(&raw_datagram, peer_address);
+
+        if self.pending_datagrams.len() >= UDP_PENDING_CAPACITY {
+            if let Some(oldest_sequence) = self.pending_datagrams.keys().next().copied() {
+                self.pending_datagrams.remove(&oldest_sequence);
+            }
+
+            warn!("UDP session dropped its oldest unacknowledged datagram");
+        }
+
+        self.pending_datagrams.insert(
+            sequence,
+            PendingDatagram { raw_datagram, sent_at: Instant::now(), attempts: 1 },
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn retransmit(&mut self) {
+        let peer_address = match self.peer_address {
+            Some(peer_address) => peer_address,
+            None => return,
+        };
+        let now = Instant::now();
+        let relay = &self.relay;
+
+        self.pending_datagrams.retain(|sequence, pending_datagram| {
+            if now.duration_since(pending_datagram.sent_at) < UDP_RETRANSMIT_INTERVAL {
+                return true;
+            }
+
+            if pending_datagram.attempts >= UDP_MAX_ATTEMPTS {
+                warn!("UDP datagram {} given up after {} sends", sequence, UDP_MAX_ATTEMPTS);
+                return false;
+            }
+
+            relay.send_to(&pending_datagram.raw_datagram, peer_address);
+            pending_datagram.sent_at = now;
+            pending_datagram.attempts += 1;
+
+            true
+        });
+    }
+}
+
+impl Drop for UdpSession {
+    fn drop(&mut self) {
+        if let Ok(mut write_guard) = self.relay.sessions.lock() {
+            write_guard.remove(&self.token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{MessageCode, PartyId, PayloadKind};
+    use actix::{Actor as ActixActor, Context, Handler as MessageHandler};
+
+    struct NullSession;
+
+    impl ActixActor for NullSession {
+        type Context = Context<Self>;
+    }
+
+    impl MessageHandler<UdpReceived> for NullSession {
+        type Result = ();
+
+        fn handle(&mut self, _: UdpReceived, _: &mut Self::Context) {}
+    }
+
+    fn sample_message(reliability: MessageReliability) -> MessageStream {
+        let mut message = MessageStream::new(
+            MessageCode::Normal,
+            1,
+            PartyId::Server(0),
+            PartyId::Client(0),
+            PayloadKind::Data,
+            Some(&[1, 2, 3]),
+        );
+        message.header_options.reliability = Some(reliability);
+
+        message
+    }
+
+    #[actix_rt::test]
+    async fn test_udp_session_is_as_expected() {
+        let udp_relay = UdpRelay::bind("127.0.0.1:0", true).unwrap();
+        let peer_socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer_address = peer_socket.local_addr().unwrap();
+        let mut udp_session = udp_relay.open_session(NullSession.start().recipient());
+        let token = udp_session.token;
+
+        assert!(udp_relay.sessions.lock().unwrap().contains_key(&token));
+        assert_eq!(&udp_session.session_details()[..16], &token[..]);
+
+        // Nothing goes over UDP before the client sent a datagram
+        let unreliable_message = sample_message(MessageReliability::Unreliable);
+
+        assert_eq!(
+            udp_session.send(unreliable_message.clone()),
+            Err(Box::new(unreliable_message.clone()))
+        );
+
+        // Resent reliable datagrams are acknowledged every time but handed over once
+        let reliable_datagram = UdpDatagram {
+            token,
+            sequence: 1,
+            payload: UdpPayload::Reliable(sample_message(MessageReliability::Reliable)),
+        };
+
+        assert!(udp_session.receive(peer_address, reliable_datagram.clone()).is_some());
+        assert!(udp_session.receive(peer_address, reliable_datagram).is_none());
+
+        let mut buffer = [0u8; 64];
+
+        for _ in 0..2 {
+            let (length, _) = peer_socket.recv_from(&mut buffer).unwrap();
+
+            assert_eq!(
+                UdpDatagram::from_raw(&buffer[..length]).unwrap(),
+                UdpDatagram { token, sequence: 1, payload: UdpPayload::Ack }
+            );
+        }
+
+        // Reliable frames wait for their ack
+        assert_eq!(udp_session.send(unreliable_message), Ok(()));
+        assert_eq!(udp_session.send(sample_message(MessageReliability::Reliable)), Ok(()));
+        assert_eq!(udp_session.pending_datagrams.keys().collect::<Vec<_>>(), vec![&1]);
+
+        let ack = UdpDatagram { token, sequence: 1, payload: UdpPayload::Ack };
+
+        assert!(udp_session.receive(peer_address, ack).is_none());
+        assert!(udp_session.pending_datagrams.is_empty());
+
+        drop(udp_session);
+
+        assert!(udp_relay.sessions.lock().unwrap().is_empty());
+    }
+}
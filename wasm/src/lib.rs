@@ -0,0 +1,250 @@
+//! wasm-bindgen bindings of the router protocol for web games. The `proto` sources and the
+//! client reconnect state machine are compiled as is, so browsers share the exact framing of
+//! the router instead of a TypeScript port.
+//!
+//! Build with `wasm-pack build wasm --target web`.
+
+#[path = "../../src/proto/mod.rs"]
+pub mod proto;
+#[path = "../../client/src/reconnect.rs"]
+pub mod reconnect;
+
+pub(crate) use anyhow::{anyhow as anyerror, Error as AnyError, Result as AnyResult};
+
+use proto::{MessageCode, MessagePriority, MessageStream, PartyId, PayloadKind};
+use reconnect::{ReconnectPolicy, ReconnectState};
+use std::convert::TryFrom;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(error: AnyError) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
+
+#[wasm_bindgen(js_name = MessageStream)]
+pub struct JsMessageStream {
+    inner: MessageStream,
+}
+
+#[wasm_bindgen(js_class = MessageStream)]
+impl JsMessageStream {
+    // Party ids are the u32 wire representation, see the partyId* helpers
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        is_special: bool,
+        room_id: u32,
+        origin_id: u32,
+        destination_id: u32,
+        payload_kind: &str,
+        payload: &[u8],
+    ) -> Result<JsMessageStream, JsValue> {
+        let message_code = if is_special { MessageCode::Special } else { MessageCode::Normal };
+        let payload_kind: PayloadKind = payload_kind.parse().map_err(to_js_error)?;
+
+        Ok(Self {
+            inner: MessageStream::new(
+                message_code,
+                room_id,
+                PartyId::from_u32(origin_id),
+                PartyId::from_u32(destination_id),
+                payload_kind,
+                Some(payload),
+            ),
+        })
+    }
+
+    #[wasm_bindgen(js_name = fromRaw)]
+    pub fn from_raw(source: &[u8]) -> Result<JsMessageStream, JsValue> {
+        MessageStream::from_raw(source).map(|inner| Self { inner }).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = toRaw)]
+    pub fn to_raw(&self) -> Vec<u8> {
+        self.inner.clone().into_raw()
+    }
+
+    // Undoes the compression header option, batches are left packed
+    pub fn decompress(&mut self) -> Result<(), JsValue> {
+        self.inner.decompress().map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(getter, js_name = isSpecial)]
+    pub fn is_special(&self) -> bool {
+        self.inner.message_code == MessageCode::Special
+    }
+
+    #[wasm_bindgen(getter, js_name = roomId)]
+    pub fn room_id(&self) -> u32 {
+        self.inner.room_id
+    }
+
+    #[wasm_bindgen(getter, js_name = originId)]
+    pub fn origin_id(&self) -> u32 {
+        self.inner.origin_id.get_repr()
+    }
+
+    #[wasm_bindgen(getter, js_name = destinationId)]
+    pub fn destination_id(&self) -> u32 {
+        self.inner.destination_id.get_repr()
+    }
+
+    #[wasm_bindgen(getter, js_name = payloadKind)]
+    pub fn payload_kind(&self) -> u8 {
+        self.inner.payload_kind.into()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn payload(&self) -> Vec<u8> {
+        self.inner.payload.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = interestKey)]
+    pub fn interest_key(&self) -> Option<u32> {
+        self.inner.header_options.interest_key
+    }
+
+    #[wasm_bindgen(setter, js_name = interestKey)]
+    pub fn set_interest_key(&mut self, interest_key: Option<u32>) {
+        self.inner.header_options.interest_key = interest_key;
+    }
+
+    #[wasm_bindgen(getter, js_name = ackSequence)]
+    pub fn ack_sequence(&self) -> Option<u32> {
+        self.inner.header_options.ack_sequence
+    }
+
+    #[wasm_bindgen(setter, js_name = ackSequence)]
+    pub fn set_ack_sequence(&mut self, ack_sequence: Option<u32>) {
+        self.inner.header_options.ack_sequence = ack_sequence;
+    }
+
+    #[wasm_bindgen(getter, js_name = correlationId)]
+    pub fn correlation_id(&self) -> Option<u32> {
+        self.inner.header_options.correlation_id
+    }
+
+    #[wasm_bindgen(setter, js_name = correlationId)]
+    pub fn set_correlation_id(&mut self, correlation_id: Option<u32>) {
+        self.inner.header_options.correlation_id = correlation_id;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sequence(&self) -> Option<u32> {
+        self.inner.header_options.sequence
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_sequence(&mut self, sequence: Option<u32>) {
+        self.inner.header_options.sequence = sequence;
+    }
+
+    #[wasm_bindgen(getter, js_name = ttlMillis)]
+    pub fn ttl_millis(&self) -> Option<u16> {
+        self.inner.header_options.ttl_millis
+    }
+
+    #[wasm_bindgen(setter, js_name = ttlMillis)]
+    pub fn set_ttl_millis(&mut self, ttl_millis: Option<u16>) {
+        self.inner.header_options.ttl_millis = ttl_millis;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn priority(&self) -> u8 {
+        self.inner.priority().into()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), JsValue> {
+        let priority = MessagePriority::try_from(priority)
+            .map_err(|_| to_js_error(anyerror!("Invalid MessagePriority {}", priority)))?;
+        self.inner.header_options.priority = Some(priority);
+
+        Ok(())
+    }
+}
+
+#[wasm_bindgen(js_name = partyIdClient)]
+pub fn party_id_client(client_id: u32) -> u32 {
+    PartyId::Client(client_id).get_repr()
+}
+
+#[wasm_bindgen(js_name = partyIdServer)]
+pub fn party_id_server(server_id: u32) -> u32 {
+    PartyId::Server(server_id).get_repr()
+}
+
+#[wasm_bindgen(js_name = partyIdAllClients)]
+pub fn party_id_all_clients(with_echo: bool) -> u32 {
+    if with_echo { PartyId::AllClientsWithEcho } else { PartyId::AllClients }.get_repr()
+}
+
+#[wasm_bindgen(js_name = partyIdAllServers)]
+pub fn party_id_all_servers(with_echo: bool) -> u32 {
+    if with_echo { PartyId::AllServersWithEcho } else { PartyId::AllServers }.get_repr()
+}
+
+// e.g. "Client(3)" or "AllClients"
+#[wasm_bindgen(js_name = partyIdDescribe)]
+pub fn party_id_describe(party_id: u32) -> String {
+    format!("{:?}", PartyId::from_u32(party_id))
+}
+
+#[wasm_bindgen(js_name = ReconnectState)]
+pub struct JsReconnectState {
+    inner: ReconnectState,
+}
+
+#[wasm_bindgen(js_class = ReconnectState)]
+impl JsReconnectState {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        initial_delay_ms: u32,
+        max_delay_ms: u32,
+        max_attempts: Option<u32>,
+    ) -> JsReconnectState {
+        Self {
+            inner: ReconnectState::new(ReconnectPolicy {
+                initial_delay: Duration::from_millis(initial_delay_ms as u64),
+                max_delay: Duration::from_millis(max_delay_ms as u64),
+                max_attempts,
+            }),
+        }
+    }
+
+    #[wasm_bindgen(js_name = onConnected)]
+    pub fn on_connected(&mut self) {
+        self.inner.on_connected();
+    }
+
+    // Milliseconds to wait before the next attempt, undefined once giving up
+    #[wasm_bindgen(js_name = onFailure)]
+    pub fn on_failure(&mut self) -> Option<u32> {
+        self.inner.on_failure().map(|retry_delay| retry_delay.as_millis() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_js_message_stream_round_trip_is_as_expected() {
+        let raw = MessageStream::new(
+            MessageCode::Normal,
+            7,
+            PartyId::Client(1),
+            PartyId::AllClients,
+            PayloadKind::Data,
+            Some(&[0xAA, 0xBB]),
+        )
+        .into_raw();
+        let js_message_stream = JsMessageStream::from_raw(&raw).ok().unwrap();
+
+        assert_eq!(js_message_stream.room_id(), 7);
+        assert_eq!(js_message_stream.origin_id(), party_id_client(1));
+        assert_eq!(js_message_stream.destination_id(), party_id_all_clients(false));
+        assert_eq!(js_message_stream.payload(), vec![0xAA, 0xBB]);
+        assert_eq!(js_message_stream.to_raw(), raw);
+        assert_eq!(party_id_describe(party_id_server(2)), "Server(2)");
+    }
+}